@@ -69,7 +69,7 @@ pub fn criterion_benchmark(c: &mut Criterion) {
                 let pipeline =
                     Pipeline::new(&query_container, NopWriter {}, OutputMode::Legacy).unwrap();
                 let f = File::open(file).unwrap();
-                pipeline.process(black_box(BufReader::new(f)))
+                pipeline.process(black_box(BufReader::new(f))).unwrap()
             })
         });
         group.finish();
@@ -5,13 +5,16 @@
 //! 2. Create a new test config inside `tests/structured_tests/aliases`.
 //! 3. Add the test config to the `test_aliases()` test.
 
+pub mod packs;
+
 use std::borrow::Cow;
 use std::path::{Path, PathBuf};
 
 use lazy_static::lazy_static;
 
-use crate::errors::{QueryContainer, TermErrorReporter};
+use crate::errors::{ErrorBuilder, QueryContainer, TermErrorReporter};
 use crate::lang::{pipeline_template, Operator};
+use crate::pipeline::CompileError;
 use include_dir::Dir;
 use serde::Deserialize;
 
@@ -52,37 +55,62 @@ fn parse_alias(
         keyword: None,
         contents: Some(contents.to_string()),
     })?;
-    let reporter = Box::new(TermErrorReporter {});
+    let reporter = Box::new(TermErrorReporter::default());
     let aliases = AliasCollection {
         aliases: Cow::Borrowed(aliases),
+        prefer_builtin: false,
     };
-    let qc = QueryContainer::new_with_aliases(config.template, reporter, aliases);
     let keyword = config.keyword;
-    let pipeline = pipeline_template(&qc).map_err(|err| InvalidAliasError {
-        path: path.to_owned(),
-        cause: err.into(),
-        keyword: Some(keyword.clone()),
-        contents: Some(contents.to_string()),
-    })?;
+    // A template with parameters is only parsed once the arguments are substituted into it.
+    let pipeline = if config.params.is_empty() {
+        let qc = QueryContainer::new_with_aliases(config.template.clone(), reporter, aliases);
+        // Errors that the parser recovered from are reported without failing the parse.
+        let parsed = pipeline_template(&qc).and_then(|pipeline| match qc.get_error_count() {
+            0 => Ok(pipeline),
+            _ => Err(CompileError::Parse),
+        });
+        parsed.map_err(|err| InvalidAliasError {
+            path: path.to_owned(),
+            cause: err.into(),
+            keyword: Some(keyword.clone()),
+            contents: Some(contents.to_string()),
+        })?
+    } else {
+        vec![]
+    };
 
-    Ok(AliasPipeline { keyword, pipeline })
+    Ok(AliasPipeline {
+        keyword,
+        path: path.to_owned(),
+        params: config.params,
+        template: config.template,
+        pipeline,
+    })
 }
 
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct AliasConfig {
     keyword: String,
     template: String,
+    /// The names of the arguments the alias is invoked with, eg. `errors(warn)`.  They're
+    /// substituted for `{name}`, or `{0}` for the first, in the template.
+    #[serde(default)]
+    params: Vec<String>,
 }
 
 #[derive(Debug, Clone)]
 pub struct AliasPipeline {
     keyword: String,
+    path: PathBuf,
+    params: Vec<String>,
+    template: String,
     pipeline: Vec<Operator>,
 }
 
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct AliasCollection<'a> {
     aliases: Cow<'a, [AliasPipeline]>,
+    prefer_builtin: bool,
 }
 
 #[derive(Default)]
@@ -93,10 +121,46 @@ struct AliasAccum {
 
 impl AliasCollection<'_> {
     pub fn get_alias(&self, name: &str) -> Option<&AliasPipeline> {
+        let local = || self.aliases.iter().find(|alias| alias.keyword == name);
+        let builtin = || AliasPipeline::matching_string(name);
+        if self.prefer_builtin {
+            builtin().or_else(local)
+        } else {
+            local().or_else(builtin)
+        }
+    }
+
+    /// When set, built-in aliases take precedence over local aliases with the same keyword.
+    pub fn prefer_builtin(mut self, prefer_builtin: bool) -> Self {
+        self.prefer_builtin = prefer_builtin;
+        self
+    }
+
+    /// The aliases other than the local alias with the given keyword, for expanding that alias
+    /// without it being able to refer to itself.
+    pub fn excluding(&self, keyword: &str) -> AliasCollection<'static> {
+        AliasCollection {
+            aliases: Cow::Owned(
+                self.aliases
+                    .iter()
+                    .filter(|alias| alias.keyword != keyword)
+                    .cloned()
+                    .collect(),
+            ),
+            prefer_builtin: self.prefer_builtin,
+        }
+    }
+
+    /// Local aliases that share a keyword with a built-in alias.
+    pub fn shadowing_aliases(&self) -> impl Iterator<Item = &AliasPipeline> {
         self.aliases
             .iter()
-            .find(|alias| alias.keyword == name)
-            .or_else(|| AliasPipeline::matching_string(name))
+            .filter(|alias| LOADED_KEYWORDS.contains(&alias.keyword.as_str()))
+    }
+
+    /// The aliases loaded from alias directories, in the order they're searched.
+    pub fn local_aliases(&self) -> impl Iterator<Item = &AliasPipeline> {
+        self.aliases.iter()
     }
 
     pub fn valid_aliases(&self) -> impl Iterator<Item = &str> {
@@ -116,6 +180,7 @@ impl AliasCollection<'static> {
         Ok((
             AliasCollection {
                 aliases: Cow::Owned(valid),
+                prefer_builtin: false,
             },
             invalid,
         ))
@@ -129,50 +194,104 @@ impl AliasCollection<'static> {
         Ok((
             AliasCollection {
                 aliases: Cow::Owned(aliases.valid_aliases),
+                prefer_builtin: false,
             },
             aliases.invalid_aliases,
         ))
     }
 }
 
-fn find_local_aliases(dir: &Path, aliases: &mut AliasAccum) -> anyhow::Result<()> {
-    if let Some(alias_dir) = dir.read_dir()?.find_map(|file| match file {
-        Ok(entry) if entry.file_name() == ".agrind-aliases" => Some(entry),
-        _else => None,
-    }) {
-        aliases_from_dir(&alias_dir.path(), aliases)?;
+/// The `.agrind-aliases` directories in the directory and its ancestors, nearest first.
+pub fn ancestor_alias_dirs(path: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut dirs = vec![];
+    for dir in path.ancestors() {
+        if let Some(alias_dir) = dir.read_dir()?.find_map(|file| match file {
+            Ok(entry) if entry.file_name() == ".agrind-aliases" => Some(entry),
+            _else => None,
+        }) {
+            dirs.push(alias_dir.path());
+        }
     }
-    Ok(())
+    Ok(dirs)
 }
 
+/// Load the aliases in the directory, followed by the aliases in the packs that it lists.
 fn aliases_from_dir(dir: &Path, pipelines: &mut AliasAccum) -> anyhow::Result<()> {
     for entry in dir.read_dir()? {
         let entry = entry?;
-        let path = entry.path();
-        let contents = match std::fs::read_to_string(&path) {
-            Ok(s) => s,
-            Err(e) => {
+        if entry.file_type()?.is_dir() || entry.file_name() == packs::MANIFEST {
+            continue;
+        }
+        alias_from_file(entry.path(), pipelines);
+    }
+
+    let manifest = dir.join(packs::MANIFEST);
+    let packs = match packs::read_manifest(dir) {
+        Ok(packs) => packs,
+        Err(e) => {
+            pipelines.invalid_aliases.push(InvalidAliasError {
+                keyword: None,
+                path: manifest,
+                cause: e,
+                contents: None,
+            });
+            return Ok(());
+        }
+    };
+    for pack in packs {
+        let pack_dir = pack.aliases_dir(dir);
+        let entries = match pack_dir.read_dir() {
+            Ok(entries) => entries,
+            Err(_) => {
                 pipelines.invalid_aliases.push(InvalidAliasError {
                     keyword: None,
-                    path,
-                    cause: e.into(),
+                    path: manifest.clone(),
+                    cause: anyhow::anyhow!(
+                        "alias pack `{}` hasn't been fetched, run `agrind alias sync`",
+                        pack.name
+                    ),
                     contents: None,
                 });
                 continue;
             }
         };
-        match parse_alias(&contents, &path, &pipelines.valid_aliases) {
-            Ok(alias) => pipelines.valid_aliases.push(alias),
-            Err(e) => pipelines.invalid_aliases.push(e),
+        // Packs are often repositories with a README and the like, so only `.toml` files are
+        // taken to be aliases.
+        let mut paths = entries
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?;
+        paths.retain(|path| path.is_file() && path.extension().is_some_and(|ext| ext == "toml"));
+        paths.sort();
+        for path in paths {
+            alias_from_file(path, pipelines);
         }
     }
     Ok(())
 }
 
+fn alias_from_file(path: PathBuf, pipelines: &mut AliasAccum) {
+    let contents = match std::fs::read_to_string(&path) {
+        Ok(s) => s,
+        Err(e) => {
+            pipelines.invalid_aliases.push(InvalidAliasError {
+                keyword: None,
+                path,
+                cause: e.into(),
+                contents: None,
+            });
+            return;
+        }
+    };
+    match parse_alias(&contents, &path, &pipelines.valid_aliases) {
+        Ok(alias) => pipelines.valid_aliases.push(alias),
+        Err(e) => pipelines.invalid_aliases.push(e),
+    }
+}
+
 fn find_all_aliases(path: PathBuf) -> anyhow::Result<(Vec<AliasPipeline>, Vec<InvalidAliasError>)> {
     let mut accum = AliasAccum::default();
-    for path in path.ancestors() {
-        find_local_aliases(path, &mut accum)?;
+    for alias_dir in ancestor_alias_dirs(&path)? {
+        aliases_from_dir(&alias_dir, &mut accum)?;
     }
     Ok((accum.valid_aliases, accum.invalid_aliases))
 }
@@ -182,8 +301,72 @@ impl AliasPipeline {
         LOADED_ALIASES.iter().find(|alias| alias.keyword == s)
     }
 
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+
+    /// The file the alias was loaded from.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The names of the alias's parameters, which are empty if it isn't invoked with arguments.
+    pub fn params(&self) -> &[String] {
+        &self.params
+    }
+
+    /// The template, before any arguments are substituted.
+    pub fn template(&self) -> &str {
+        self.template.trim()
+    }
+
+    /// Parameters that aren't used in the template, which are likely to be typos.
+    pub fn unused_params(&self) -> impl Iterator<Item = &str> {
+        self.params
+            .iter()
+            .enumerate()
+            .filter_map(move |(i, param)| {
+                let used = self.template.contains(&format!("{{{}}}", param))
+                    || self.template.contains(&format!("{{{}}}", i));
+                (!used).then_some(param.as_str())
+            })
+    }
+
     /// Render the alias as a string that should parse into a valid operator.
     pub fn render(&self) -> Vec<Operator> {
         self.pipeline.clone()
     }
+
+    /// Substitute the arguments into the template, replacing `{name}` or `{index}` for each
+    /// parameter.  Braces that don't name a parameter, like a regex's `\d{4}`, are left as-is.
+    pub fn expand(&self, args: &[String]) -> String {
+        let mut expanded = String::with_capacity(self.template.len());
+        let mut rest = self.template.as_str();
+        while let Some(start) = rest.find('{') {
+            expanded.push_str(&rest[..start]);
+            rest = &rest[start..];
+            let arg = rest.find('}').and_then(|end| {
+                let name = &rest[1..end];
+                let index = match name.parse::<usize>() {
+                    Ok(index) => Some(index),
+                    Err(_) => self.params.iter().position(|param| param == name),
+                };
+                index
+                    .and_then(|index| args.get(index))
+                    .map(|arg| (arg, end))
+            });
+            match arg {
+                Some((arg, end)) => {
+                    expanded.push_str(arg);
+                    rest = &rest[end + 1..];
+                }
+                None => {
+                    expanded.push('{');
+                    rest = &rest[1..];
+                }
+            }
+        }
+        expanded.push_str(rest);
+        expanded.trim().to_string()
+    }
 }
@@ -0,0 +1,435 @@
+//! Alias packs are aliases shared from a git repository or a tarball.  An alias directory lists
+//! them in a `packs.toml` manifest:
+//!
+//! ```toml
+//! [[pack]]
+//! name = "team"
+//! git = "https://github.com/example/agrind-aliases.git"
+//! rev = "main"
+//!
+//! [[pack]]
+//! name = "web"
+//! url = "https://example.com/aliases.tar.gz"
+//! path = "aliases"
+//! ```
+//!
+//! `agrind alias sync` fetches each pack into `.packs/<name>` next to the manifest.  Repositories
+//! are cloned with the system's `git`, while tarballs are downloaded over HTTP(S), or read from a
+//! `file://` URL, and extracted in-process.  The aliases are then loaded from the `.toml` files
+//! in the pack, or in `path` inside it.
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, bail, Context};
+use serde::Deserialize;
+
+use crate::decompress;
+
+/// The name of the manifest in an alias directory.
+pub const MANIFEST: &str = "packs.toml";
+
+/// The directory, inside the alias directory, that packs are fetched into.
+pub const CACHE_DIR: &str = ".packs";
+
+/// The URLs that tarballs can be fetched from.
+const URL_SCHEMES: &[&str] = &["https://", "http://", "file://"];
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Manifest {
+    #[serde(default, rename = "pack")]
+    packs: Vec<Pack>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Pack {
+    pub name: String,
+    /// A git repository to clone.
+    git: Option<String>,
+    /// The branch or tag to clone, instead of the default branch.
+    rev: Option<String>,
+    /// A tarball to download and extract.
+    url: Option<String>,
+    /// The directory in the pack that the aliases are in.
+    path: Option<PathBuf>,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Source<'a> {
+    Git { repo: &'a str, rev: Option<&'a str> },
+    Tarball { url: &'a str },
+}
+
+impl Pack {
+    fn source(&self) -> anyhow::Result<Source<'_>> {
+        match (&self.git, &self.url) {
+            (Some(repo), None) => Ok(Source::Git {
+                repo,
+                rev: self.rev.as_deref(),
+            }),
+            (None, Some(url)) if self.rev.is_some() => bail!(
+                "alias pack `{}` has a `rev`, which only applies to `git`",
+                self.name
+            ),
+            (None, Some(url)) if !URL_SCHEMES.iter().any(|scheme| url.starts_with(scheme)) => {
+                bail!(
+                    "alias pack `{}` has a `url` that doesn't start with {}",
+                    self.name,
+                    URL_SCHEMES.join(", ")
+                )
+            }
+            (None, Some(url)) => Ok(Source::Tarball { url }),
+            _ => bail!(
+                "alias pack `{}` needs exactly one of `git` or `url`",
+                self.name
+            ),
+        }
+    }
+
+    /// Where the source is fetched from, for messages.
+    pub fn origin(&self) -> &str {
+        self.git
+            .as_deref()
+            .or(self.url.as_deref())
+            .unwrap_or_default()
+    }
+
+    /// The directory the pack is fetched into.
+    pub fn cache_dir(&self, alias_dir: &Path) -> PathBuf {
+        alias_dir.join(CACHE_DIR).join(&self.name)
+    }
+
+    /// The directory the pack's aliases are loaded from.
+    pub fn aliases_dir(&self, alias_dir: &Path) -> PathBuf {
+        let cache_dir = self.cache_dir(alias_dir);
+        match &self.path {
+            Some(path) => cache_dir.join(path),
+            None => cache_dir,
+        }
+    }
+}
+
+/// Read the packs listed in the alias directory's manifest, which are empty if there isn't one.
+pub fn read_manifest(alias_dir: &Path) -> anyhow::Result<Vec<Pack>> {
+    let path = alias_dir.join(MANIFEST);
+    let contents = match fs::read_to_string(&path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(e) => return Err(e.into()),
+    };
+    let manifest: Manifest = toml::from_str(&contents)?;
+    for pack in &manifest.packs {
+        let valid_name = !pack.name.is_empty()
+            && pack
+                .name
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_');
+        if !valid_name {
+            bail!(
+                "invalid alias pack name `{}`: names may only contain letters, digits, `-` and `_`",
+                pack.name
+            );
+        }
+        pack.source()?;
+        // The path is joined to the pack's directory, so it mustn't lead out of it.
+        if let Some(path) = &pack.path {
+            if !path
+                .components()
+                .all(|component| matches!(component, Component::Normal(_)))
+            {
+                bail!(
+                    "alias pack `{}` has a `path` that isn't a directory inside the pack: {}",
+                    pack.name,
+                    path.display()
+                );
+            }
+        }
+    }
+    Ok(manifest.packs)
+}
+
+/// Fetch the pack, replacing any previous copy.  The pack is fetched next to its final location
+/// first, so that a failed fetch leaves the previous copy in place.
+pub fn sync(alias_dir: &Path, pack: &Pack) -> anyhow::Result<()> {
+    let cache = alias_dir.join(CACHE_DIR);
+    fs::create_dir_all(&cache).with_context(|| format!("could not create {}", cache.display()))?;
+    let staging = cache.join(format!(".{}.partial", pack.name));
+    let archive = cache.join(format!(".{}.archive", pack.name));
+    let _ = fs::remove_dir_all(&staging);
+
+    let fetched = match pack.source()? {
+        Source::Git { repo, rev } => clone(repo, rev, &staging).map(|_| staging.clone()),
+        Source::Tarball { url } => download(url, &archive, &staging),
+    };
+    let _ = fs::remove_file(&archive);
+    let root = match fetched {
+        Ok(root) => root,
+        Err(e) => {
+            let _ = fs::remove_dir_all(&staging);
+            return Err(e);
+        }
+    };
+
+    let dest = pack.cache_dir(alias_dir);
+    if dest.exists() {
+        fs::remove_dir_all(&dest)
+            .with_context(|| format!("could not remove the old copy in {}", dest.display()))?;
+    }
+    fs::rename(&root, &dest)?;
+    let _ = fs::remove_dir_all(&staging);
+    if !pack.aliases_dir(alias_dir).is_dir() {
+        return Err(anyhow!(
+            "alias pack `{}` has no directory {}",
+            pack.name,
+            pack.aliases_dir(alias_dir).display()
+        ));
+    }
+    Ok(())
+}
+
+fn clone(repo: &str, rev: Option<&str>, dest: &Path) -> anyhow::Result<()> {
+    let mut git = Command::new("git");
+    // The manifest may come from someone else's repository, so transports that run commands are
+    // refused, and `--` keeps a repository starting with `-` from being read as an option.
+    git.args([
+        "-c",
+        "protocol.ext.allow=never",
+        "clone",
+        "--quiet",
+        "--depth",
+        "1",
+    ]);
+    if let Some(rev) = rev {
+        git.arg(format!("--branch={}", rev));
+    }
+    run(git.arg("--").arg(repo).arg(dest))
+}
+
+/// Download and extract the tarball, returning the directory it was extracted into.  When the
+/// tarball has a single top-level directory, like GitHub's archives, that directory is returned.
+/// It may be compressed with gzip, zstd or bzip2, and entries that would be extracted outside of
+/// `dest` are skipped.
+fn download(url: &str, archive: &Path, dest: &Path) -> anyhow::Result<PathBuf> {
+    let archive = match url.strip_prefix("file://") {
+        Some(path) => PathBuf::from(path),
+        None => {
+            let response = ureq::get(url)
+                .call()
+                .with_context(|| format!("could not download {}", url))?;
+            let mut file = File::create(archive)
+                .with_context(|| format!("could not create {}", archive.display()))?;
+            io::copy(&mut response.into_reader(), &mut file)
+                .with_context(|| format!("could not download {}", url))?;
+            archive.to_owned()
+        }
+    };
+    fs::create_dir_all(dest)?;
+    let tarball = decompress::open(&archive)
+        .with_context(|| format!("could not read {}", archive.display()))?;
+    tar::Archive::new(tarball)
+        .unpack(dest)
+        .with_context(|| format!("could not extract {}", url))?;
+
+    let entries = fs::read_dir(dest)?.collect::<Result<Vec<_>, _>>()?;
+    match entries.as_slice() {
+        [entry] if entry.file_type()?.is_dir() => Ok(entry.path()),
+        _ => Ok(dest.to_owned()),
+    }
+}
+
+fn run(command: &mut Command) -> anyhow::Result<()> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .with_context(|| format!("could not run `{}`", program))?;
+    if !output.status.success() {
+        bail!(
+            "`{}` failed ({}): {}",
+            program,
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn temp_dir() -> PathBuf {
+        static DIRS: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "agrind-packs-{}-{}",
+            std::process::id(),
+            DIRS.fetch_add(1, Ordering::Relaxed)
+        ));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn parse(manifest: &str) -> anyhow::Result<Vec<Pack>> {
+        let dir = temp_dir();
+        fs::write(dir.join(MANIFEST), manifest).unwrap();
+        let packs = read_manifest(&dir);
+        fs::remove_dir_all(&dir).unwrap();
+        packs
+    }
+
+    #[test]
+    fn reads_manifest() {
+        let packs = parse(
+            r#"
+            [[pack]]
+            name = "team"
+            git = "https://example.com/aliases.git"
+            rev = "v1"
+
+            [[pack]]
+            name = "web"
+            url = "https://example.com/aliases.tar.gz"
+            path = "aliases"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(
+            packs[0].source().unwrap(),
+            Source::Git {
+                repo: "https://example.com/aliases.git",
+                rev: Some("v1")
+            }
+        );
+        assert_eq!(
+            packs[1].aliases_dir(Path::new("a")),
+            Path::new("a/.packs/web/aliases")
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_packs() {
+        let err = |manifest: &str| parse(manifest).unwrap_err().to_string();
+        assert_eq!(
+            err("[[pack]]\nname = \"x\""),
+            "alias pack `x` needs exactly one of `git` or `url`"
+        );
+        assert_eq!(
+            err("[[pack]]\nname = \"x\"\nurl = \"u\"\nrev = \"main\""),
+            "alias pack `x` has a `rev`, which only applies to `git`"
+        );
+        assert!(
+            err("[[pack]]\nname = \"../x\"\ngit = \"g\"").starts_with("invalid alias pack name")
+        );
+        assert_eq!(
+            err("[[pack]]\nname = \"x\"\nurl = \"-o/tmp/x\""),
+            "alias pack `x` has a `url` that doesn't start with https://, http://, file://"
+        );
+        assert_eq!(
+            err("[[pack]]\nname = \"x\"\ngit = \"g\"\npath = \"/etc\""),
+            "alias pack `x` has a `path` that isn't a directory inside the pack: /etc"
+        );
+        assert_eq!(
+            err("[[pack]]\nname = \"x\"\ngit = \"g\"\npath = \"aliases/../../y\""),
+            "alias pack `x` has a `path` that isn't a directory inside the pack: aliases/../../y"
+        );
+    }
+
+    /// Write a gzipped tarball of a GitHub-style archive, with the aliases in `aliases/`.
+    fn write_tarball(path: &Path, alias: &str) {
+        let gz = flate2::write::GzEncoder::new(
+            File::create(path).unwrap(),
+            flate2::Compression::default(),
+        );
+        let mut tarball = tar::Builder::new(gz);
+        let mut header = tar::Header::new_gnu();
+        header.set_size(alias.len() as u64);
+        header.set_mode(0o644);
+        tarball
+            .append_data(&mut header, "repo-main/aliases/a.toml", alias.as_bytes())
+            .unwrap();
+        tarball.into_inner().unwrap().finish().unwrap();
+    }
+
+    fn alias(dir: &Path, pack: &Pack) -> String {
+        fs::read_to_string(pack.aliases_dir(dir).join("a.toml")).unwrap()
+    }
+
+    #[test]
+    fn installs_and_updates_tarballs() {
+        let dir = temp_dir();
+        let tarball = dir.join("pack.tar.gz");
+        let pack = Pack {
+            name: "web".to_string(),
+            git: None,
+            rev: None,
+            url: Some(format!("file://{}", tarball.display())),
+            path: Some(PathBuf::from("aliases")),
+        };
+        write_tarball(&tarball, "keyword = \"v1\"");
+        sync(&dir, &pack).unwrap();
+        assert_eq!(alias(&dir, &pack), "keyword = \"v1\"");
+
+        write_tarball(&tarball, "keyword = \"v2\"");
+        sync(&dir, &pack).unwrap();
+        assert_eq!(alias(&dir, &pack), "keyword = \"v2\"");
+
+        // A failed update leaves the previous copy in place.
+        fs::write(&tarball, b"not a tarball").unwrap();
+        assert!(sync(&dir, &pack).is_err());
+        assert_eq!(alias(&dir, &pack), "keyword = \"v2\"");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn installs_and_updates_repositories() {
+        let dir = temp_dir();
+        let repo = dir.join("repo");
+        let git = |args: &[&str]| {
+            let status = Command::new("git")
+                .args(["-c", "user.name=a", "-c", "user.email=a@example.com"])
+                .args(args)
+                .current_dir(&repo)
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {:?} failed", args);
+        };
+        fs::create_dir_all(&repo).unwrap();
+        git(&["init", "--quiet", "--initial-branch=main"]);
+        let commit = |alias: &str| {
+            fs::write(repo.join("a.toml"), alias).unwrap();
+            git(&["add", "a.toml"]);
+            git(&["commit", "--quiet", "-m", alias]);
+        };
+        let pack = Pack {
+            name: "team".to_string(),
+            git: Some(repo.display().to_string()),
+            rev: Some("main".to_string()),
+            url: None,
+            path: None,
+        };
+        commit("keyword = \"v1\"");
+        sync(&dir, &pack).unwrap();
+        assert_eq!(alias(&dir, &pack), "keyword = \"v1\"");
+
+        commit("keyword = \"v2\"");
+        sync(&dir, &pack).unwrap();
+        assert_eq!(alias(&dir, &pack), "keyword = \"v2\"");
+
+        // A repository that looks like an option is passed to git as a repository.
+        let marker = dir.join("marker");
+        let pack = Pack {
+            git: Some(format!("--upload-pack=touch {}", marker.display())),
+            ..pack
+        };
+        assert!(sync(&dir, &pack).is_err());
+        assert!(!marker.exists());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,62 @@
+/// Remove ANSI escape sequences, like the SGR codes that set colors and the CSI sequences that
+/// move the cursor, from a line captured from a terminal.
+pub fn strip_ansi(inp: &str) -> String {
+    let mut out = String::with_capacity(inp.len());
+    let mut chars = inp.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\x1b' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            // CSI: parameter and intermediate bytes followed by a final byte, e.g. `\x1b[1;31m`.
+            Some('[') => {
+                for c in chars.by_ref() {
+                    if ('\x40'..='\x7e').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            // OSC: a string terminated by BEL or ST, e.g. the window title or a hyperlink.
+            Some(']') => {
+                while let Some(c) = chars.next() {
+                    if c == '\x07' {
+                        break;
+                    }
+                    if c == '\x1b' && chars.peek() == Some(&'\\') {
+                        chars.next();
+                        break;
+                    }
+                }
+            }
+            // Anything else is intermediate bytes followed by a final byte, e.g. `\x1b(B`.
+            Some(c) if ('\x20'..='\x2f').contains(&c) => {
+                for c in chars.by_ref() {
+                    if !('\x20'..='\x2f').contains(&c) {
+                        break;
+                    }
+                }
+            }
+            Some(_) | None => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn strip_ansi_sequences() {
+        assert_eq!(strip_ansi("plain text"), "plain text");
+        assert_eq!(
+            strip_ansi("\x1b[1;31mERROR\x1b[0m status=500"),
+            "ERROR status=500"
+        );
+        assert_eq!(strip_ansi("\x1b[2K\x1b[1Aredrawn"), "redrawn");
+        assert_eq!(strip_ansi("\x1b]8;;http://x\x1b\\link\x1b]8;;\x07"), "link");
+        assert_eq!(strip_ansi("\x1b(Bcharset \x1b7saved"), "charset saved");
+        assert_eq!(strip_ansi("dangling\x1b"), "dangling");
+    }
+}
@@ -0,0 +1,110 @@
+//! Arrow IPC input, from files (`.arrow` or Feather v2) and streams, when agrind is built with
+//! the `arrow` feature.
+//!
+//! Each row of a record batch becomes a record whose fields are its columns, typed from the
+//! schema.  Files and streams are read by the `arrow-ipc` crate, which looks up dictionary
+//! encoded columns in their dictionaries and decompresses buffers compressed with LZ4 or zstd.
+#[cfg(feature = "arrow")]
+use std::fs::File;
+#[cfg(feature = "arrow")]
+use std::io::{BufReader, Read, Seek, SeekFrom};
+
+#[cfg(feature = "arrow")]
+use arrow_array::RecordBatch;
+#[cfg(feature = "arrow")]
+use arrow_ipc::reader::{FileReader, StreamReader};
+#[cfg(feature = "arrow")]
+use arrow_schema::ArrowError;
+
+#[cfg(feature = "arrow")]
+use crate::columnar;
+#[cfg(feature = "arrow")]
+use crate::data::Record;
+
+/// The start of the file format, which is followed by the messages of the stream format.
+const FILE_MAGIC: &[u8] = b"ARROW1";
+/// Marks the start of a message's metadata.
+const CONTINUATION: u32 = 0xffff_ffff;
+
+/// Whether the data starts like an Arrow IPC file or stream.
+pub fn is_arrow(magic: &[u8]) -> bool {
+    magic.starts_with(FILE_MAGIC) || magic.starts_with(&CONTINUATION.to_le_bytes())
+}
+
+/// The rows of an Arrow IPC file or stream, read a record batch at a time.
+#[cfg(feature = "arrow")]
+pub struct RecordBatches {
+    reader: Box<dyn Iterator<Item = Result<RecordBatch, ArrowError>> + Send>,
+}
+
+#[cfg(feature = "arrow")]
+impl RecordBatches {
+    pub fn new(mut file: File) -> Result<Self, String> {
+        let mut magic = Vec::with_capacity(FILE_MAGIC.len());
+        (&mut file)
+            .take(FILE_MAGIC.len() as u64)
+            .read_to_end(&mut magic)
+            .map_err(|e| e.to_string())?;
+        file.seek(SeekFrom::Start(0)).map_err(|e| e.to_string())?;
+        let reader: Box<dyn Iterator<Item = _> + Send> = if magic == FILE_MAGIC {
+            Box::new(FileReader::try_new(BufReader::new(file), None).map_err(|e| e.to_string())?)
+        } else {
+            Box::new(StreamReader::try_new(BufReader::new(file), None).map_err(|e| e.to_string())?)
+        };
+        Ok(RecordBatches { reader })
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl Iterator for RecordBatches {
+    type Item = Result<Vec<Record>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = self.reader.next()?;
+        Some(
+            batch
+                .map_err(|e| e.to_string())
+                .and_then(|batch| columnar::batch_records(&batch)),
+        )
+    }
+}
+
+#[cfg(all(test, feature = "arrow"))]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+
+    fn read(data: &[u8]) -> Result<Vec<Vec<Record>>, String> {
+        let path = std::env::temp_dir().join(format!(
+            "agrind-arrow-{}-{}",
+            data.len(),
+            std::process::id()
+        ));
+        std::fs::write(&path, data).unwrap();
+        let batches = RecordBatches::new(File::open(&path).unwrap())
+            .and_then(|batches| batches.collect::<Result<Vec<_>, _>>());
+        std::fs::remove_file(&path).unwrap();
+        batches
+    }
+
+    #[test]
+    fn reads_a_stream() {
+        let data = include_bytes!("../test_files/requests.arrows");
+        assert!(is_arrow(data));
+        let batches = read(data).unwrap();
+        assert_eq!(batches.len(), 1);
+        let rec = &batches[0][5];
+        assert_eq!(rec.data.get("level"), Some(&Value::None));
+        assert_eq!(
+            rec.data.get("tags"),
+            Some(&Value::Array(vec![Value::Str("b".to_string())]))
+        );
+    }
+
+    #[test]
+    fn rejects_corrupt_files() {
+        assert!(read(b"ARROW1\0\0ARROW").is_err());
+        let data = include_bytes!("../test_files/requests.arrows");
+        assert!(read(&data[..data.len() - 100]).is_err());
+    }
+}
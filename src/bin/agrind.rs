@@ -1,23 +1,43 @@
-use ag::alias::AliasCollection;
-use ag::pipeline::{ErrorReporter, OutputMode, Pipeline, QueryContainer, TermErrorReporter};
+use ag::alias::{self, AliasCollection, InvalidAliasError, LOADED_ALIASES};
+use ag::columnar;
+use ag::grok;
+use ag::operator::geoip;
+use ag::pipeline::{
+    ColorChoice, ErrorReporter, JsonOptions, Leaderboard, MultiPipeline, OutputMode, Pipeline,
+    PipelineOptions, PipelineStats, QueryContainer, TableOptions, TermErrorReporter, Theme,
+};
+use ag::serve::{serve_metrics, Snapshot};
+use ag::split::SplitConfig;
+use ag::sqlite::SqliteConfig;
 use annotate_snippets::display_list::FormatOptions;
 use annotate_snippets::snippet::{Annotation, AnnotationType, Slice, Snippet};
 use human_panic::setup_panic;
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 #[cfg(feature = "self_update")]
 use self_update;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io;
-use std::io::{stdout, BufReader};
-use std::path::PathBuf;
+#[cfg(unix)]
+use std::io::IsTerminal;
+use std::io::Read;
+use std::io::{stdout, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
 use thiserror::Error;
 
 #[cfg(not(target_env = "msvc"))]
 #[global_allocator]
 static GLOBAL: tikv_jemallocator::Jemalloc = tikv_jemallocator::Jemalloc;
 
-use crate::InvalidArgs::{CantSupplyBoth, InvalidFormatString, InvalidOutputMode};
+#[cfg(feature = "otlp")]
+use crate::InvalidArgs::InvalidOtlpEndpoint;
+use crate::InvalidArgs::{
+    CantSupplyBoth, InvalidFormatString, InvalidJsonOption, InvalidOutputMode,
+    InvalidParquetOutput, InvalidSplitOutput, InvalidSqliteOutput, UnsetEnvVar, UnterminatedEnvVar,
+};
+#[cfg(feature = "otlp")]
+use ag::otlp::{Endpoint, OtlpConfig, OtlpExporter};
 
 #[derive(Debug, Parser)]
 #[command(
@@ -25,44 +45,421 @@ use crate::InvalidArgs::{CantSupplyBoth, InvalidFormatString, InvalidOutputMode}
     after_help = "For more details + docs, see https://github.com/rcoh/angle-grinder"
 )]
 struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+
     /// The query
     #[arg(group = "main")]
     query: Option<String>,
 
+    /// The query, given after `--` so that it can start with a dash. Everything after `--` is
+    /// taken as the query, with multiple arguments joined by spaces
+    #[arg(last = true, group = "main", value_name = "QUERY")]
+    verbatim_query: Vec<String>,
+
     #[cfg(feature = "self_update")]
     /// Update agrind to the latest published version Github (https://github.com/rcoh/angle-grinder)
     #[arg(long = "self-update", group = "main")]
     update: bool,
 
-    /// Optionally reads from a file instead of Stdin
+    /// Run several queries over a single pass of the input, instead of reading it once per query.
+    /// The output of each query is written under a heading once the input ends, eg.
+    /// `agrind --multi 'q1' 'q2' --file big.log`
+    #[arg(long = "multi", group = "main", value_name = "QUERY", num_args = 1..)]
+    multi: Vec<String>,
+
+    /// Read the query from Stdin. Requires the data to be read from a file with --file
+    #[arg(long = "query-stdin", group = "main")]
+    query_stdin: bool,
+
+    /// Read the query from a file. The query can span several lines, and `#` starts a comment
+    /// that runs to the end of the line
+    #[arg(long = "query-file", group = "main", value_name = "PATH")]
+    query_file: Option<String>,
+
+    /// Optionally reads from a file instead of Stdin. Can be repeated to read several files in
+    /// turn, in which case each record gets a `__file__` field with the name of its file.
+    /// Parquet and Arrow IPC files are read as rows whose columns are already fields
     #[arg(long = "file", short = 'f')]
-    file: Option<String>,
+    file: Vec<String>,
+
+    /// The file that a `join` without a file of its own runs its operators over, e.g. the error
+    /// log for `join using (json | count by request_id) on request_id`.  Without it, those joins
+    /// run their operators over the same rows as the join
+    #[arg(long = "file2", value_name = "FILE")]
+    file2: Option<PathBuf>,
 
     /// DEPRECATED. Use -o format=... instead. Provide a Rust std::fmt string to format output
     #[arg(long = "format", short = 'm')]
     format: Option<String>,
 
-    /// Set output format. One of (json|legacy|format=<rust fmt str>|logfmt)
+    /// Set output format. One of (json[=array,pretty]|legacy|format=<rust fmt str>|logfmt|prometheus|markdown|html|chart[=<column>]|sparkline[=<column>]|sqlite=<file>[:table]|parquet=<file>|split=<dir>,by=<field>)
     #[arg(
         long = "output",
         short = 'o',
         long_help = "Set output format. Options: \n\
-                     - `json`,\n\
+                     - `json[=array,pretty]` one object per line by default. `array` writes a \
+                       single JSON array once the input ends and `pretty` indents the output,\n\
                      - `logfmt`\n\
                      - `format=<rust format string>` (eg. -o format='{src} => {dst}'\n\
-                     - `legacy` The original output format, auto aligning [k=v]"
+                       supports the escapes \\t, \\n, \\\\, and \\{ / \\} for literal braces)\n\
+                     - `legacy` The original output format, auto aligning [k=v]\n\
+                     - `prometheus` Aggregates as Prometheus metrics, with the `by` columns as \
+                       labels\n\
+                     - `markdown` A GitHub-flavored markdown table, written once the input ends\n\
+                     - `html` A minimal HTML table, written once the input ends\n\
+                     - `chart[=<column>]` Aggregates as a bar chart of the column, or of the \
+                       first numeric column that isn't a `by` column\n\
+                     - `sparkline[=<column>]` Aggregates split into time buckets, like \
+                       `count by _timeslice, status`, as a sparkline of the column for each group\n\
+                     - `sqlite=<file>[:table]` Rows inserted into a table of a SQLite database, \
+                       `results` by default, which is replaced\n\
+                     - `parquet=<file>` The final rows written to a Parquet file, with the column \
+                       types inferred from their values (requires the `parquet` feature)\n\
+                     - `split=<dir>,by=<field>` Rows as JSON lines in a file for each value of the \
+                       field, like `<dir>/<field>=<value>.jsonl`\n\
+                     - `otlp[=http://collector:4318]` OpenTelemetry JSON log records, POSTed to \
+                       the collector when one is given (requires the `otlp` feature)"
     )]
     output: Option<String>,
 
+    #[cfg(feature = "otlp")]
+    #[arg(
+        long = "otlp-severity-field",
+        value_name = "FIELD",
+        default_value = ag::otlp::DEFAULT_SEVERITY_FIELD,
+        long_help = "Field that the severity of a record is read from with `-o otlp`. Level names \
+                     like `INFO` and `warning` are mapped to OpenTelemetry severity numbers"
+    )]
+    otlp_severity_field: String,
+
     #[arg(
         long = "alias-dir",
         short = 'a',
+        global = true,
         long_help = "Specifies an alternative directory to use for aliases. Defaults to `.agrind-aliases` in all parent directories."
     )]
     alias_dir: Option<PathBuf>,
 
-    #[arg(long = "no-alias", long_help = "Disables aliases")]
+    #[arg(
+        long = "quiet",
+        short = 'Q',
+        global = true,
+        long_help = "Suppress warnings, such as invalid aliases and deprecated flags. Errors are still reported"
+    )]
+    quiet: bool,
+
+    #[arg(long = "no-alias", global = true, long_help = "Disables aliases")]
     no_alias: bool,
+
+    #[arg(
+        long = "prefer-builtin",
+        global = true,
+        long_help = "When a local alias has the same keyword as a built-in alias, use the built-in \
+                     alias instead of the local one"
+    )]
+    prefer_builtin: bool,
+
+    #[arg(
+        long = "leaderboard",
+        value_name = "COLUMN",
+        num_args = 0..=1,
+        require_equals = true,
+        long_help = "Keep live aggregate output sorted by COLUMN, largest first, and capped to the \
+                     height of the terminal. Defaults to the first aggregate in the query \
+                     (eg. `_count` for `count by host`)"
+    )]
+    leaderboard: Option<Option<String>>,
+
+    #[arg(
+        long = "columns",
+        value_name = "COLUMNS",
+        value_delimiter = ',',
+        long_help = "Only show these comma separated columns, in this order, in the `legacy` \
+                     table, eg. `--columns status,_count`"
+    )]
+    columns: Option<Vec<String>>,
+
+    #[arg(
+        long = "max-column-width",
+        value_name = "N",
+        value_parser = clap::value_parser!(u64).range(1..),
+        long_help = "Cut values wider than N characters short with an ellipsis in the `legacy` \
+                     table, so a long `message` can't push the other columns out of view"
+    )]
+    max_column_width: Option<u64>,
+
+    #[arg(
+        long = "right-align-numbers",
+        long_help = "Right-align the columns of an aggregate that only hold numbers in the \
+                     `legacy` table"
+    )]
+    right_align_numbers: bool,
+
+    #[arg(
+        long = "color",
+        value_name = "WHEN",
+        default_value = "auto",
+        value_parser = ["auto", "always", "never"],
+        long_help = "Color the `legacy` table: `auto` when the output is a terminal, unless \
+                     NO_COLOR is set, `always`, or `never`"
+    )]
+    color: String,
+
+    #[arg(
+        long = "theme",
+        value_name = "FILE",
+        long_help = "Read how to color the `legacy` table from a TOML file, with the `header` \
+                     style, the `stripe` style of every other row, and `[[threshold]]`s that \
+                     style the values of a `column` that are `above` or `below` a limit. A \
+                     style is space separated words like `bold red` or `black on-yellow`"
+    )]
+    theme: Option<PathBuf>,
+
+    #[arg(
+        long = "render-interval",
+        visible_alias = "refresh-interval",
+        value_name = "DURATION",
+        value_parser = parse_interval,
+        long_help = "Redraw live aggregates at most once every DURATION, eg. `500ms` or `2s`, \
+                     coalescing the updates in between. A plain number is in milliseconds. \
+                     Defaults to 250ms"
+    )]
+    render_interval: Option<Duration>,
+
+    #[arg(
+        long = "no-live",
+        long_help = "Don't redraw aggregates as the input is read; only print the final table once \
+                     the input ends, as when the output isn't a terminal"
+    )]
+    no_live: bool,
+
+    #[arg(
+        long = "expand-env",
+        long_help = "Replace `$VAR` and `${VAR}` in the query with the value of the environment \
+                     variable VAR before parsing it. Unset variables are an error. Use `$$` for a \
+                     literal `$`; a `$` that isn't followed by a variable name is left as is"
+    )]
+    expand_env: bool,
+
+    #[arg(
+        long = "repl",
+        group = "main",
+        long_help = "Read the file given with --file into memory once, then read queries from stdin, \
+                     one per line, and run each over the same data. Mistakes in a query are \
+                     reported and the next query can be entered. At a terminal, the query can be \
+                     edited, Up and Down go through the history (kept in AGRIND_HISTORY or \
+                     ~/.agrind_history), and Tab completes field names, operators and aliases. \
+                     Exit with `exit` or Ctrl-D"
+    )]
+    repl: bool,
+
+    #[cfg(unix)]
+    #[arg(
+        long = "interactive",
+        short = 'i',
+        conflicts_with_all = ["repl", "follow", "explain", "check", "query_stdin"],
+        long_help = "Edit the query in a terminal UI, re-running it after every change over the \
+                     input, which is read into memory first. The query given on the command line, \
+                     if any, is the starting point. Press Enter to exit and print the query, or Esc \
+                     to exit without printing it"
+    )]
+    interactive: bool,
+
+    #[arg(
+        long = "explain",
+        long_help = "Print the query with aliases expanded, one operator per line, and exit \
+                     without reading any input"
+    )]
+    explain: bool,
+
+    #[arg(
+        long = "check",
+        conflicts_with_all = ["explain", "repl", "follow"],
+        long_help = "Parse and type check the query, with aliases expanded, and exit without \
+                     reading any input. Exits with 1 if the query is invalid, and 0 otherwise. \
+                     Warnings are still printed"
+    )]
+    check: bool,
+
+    #[arg(
+        long = "follow",
+        short = 'F',
+        requires = "file",
+        conflicts_with = "repl",
+        long_help = "Keep reading the file as it grows, like `tail -F`, reopening it when it's \
+                     truncated or rotated. In a terminal, aggregates are re-rendered as new lines \
+                     arrive"
+    )]
+    follow: bool,
+
+    #[arg(
+        long = "serve-metrics",
+        requires = "follow",
+        value_name = "ADDR",
+        long_help = "Serve the aggregate as Prometheus metrics at http://ADDR/metrics, e.g. \
+                     `--serve-metrics 127.0.0.1:9100`, instead of writing it to stdout. The \
+                     metrics are updated as new lines arrive. Requires --follow and implies \
+                     `-o prometheus`"
+    )]
+    serve_metrics: Option<String>,
+
+    #[arg(
+        long = "strict-utf8",
+        long_help = "Fail on input that isn't valid UTF-8. By default, invalid bytes are replaced \
+                     with U+FFFD and processing continues"
+    )]
+    strict_utf8: bool,
+
+    #[arg(
+        long = "strict-parse",
+        long_help = "Fail on the first line that `parse`, `json`, `logfmt` or `split` can't parse, \
+                     naming the line number and content. By default, those lines are dropped"
+    )]
+    strict_parse: bool,
+
+    #[arg(
+        long = "strict",
+        value_name = "MAX",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "0",
+        value_parser = parse_failure_threshold,
+        long_help = "Once the input is processed, fail if more than MAX rows couldn't be parsed by \
+                     `parse`, `json`, `logfmt` or `split`, listing how many each one dropped. MAX \
+                     is a number of rows or a percentage of the matched lines, like `1%`, and \
+                     defaults to 0. Unlike `--strict-parse`, the whole input is processed first"
+    )]
+    strict: Option<FailureThreshold>,
+
+    #[arg(
+        long = "max-line-length",
+        value_name = "BYTES",
+        long_help = "Truncate input lines longer than BYTES as they're read, so a single huge line \
+                     can't exhaust memory. `--stats` reports how many lines were affected. \
+                     Unlimited by default"
+    )]
+    max_line_length: Option<usize>,
+
+    #[arg(
+        long = "skip-long-lines",
+        requires = "max_line_length",
+        long_help = "Drop lines longer than `--max-line-length` instead of truncating them"
+    )]
+    skip_long_lines: bool,
+
+    #[arg(
+        long = "strip-ansi",
+        long_help = "Remove ANSI escape sequences, like colors and cursor movement, from each line \
+                     before it's searched or parsed"
+    )]
+    strip_ansi: bool,
+
+    #[arg(
+        long = "multiline-start",
+        value_name = "REGEX",
+        value_parser = regex::Regex::new,
+        long_help = "Treat lines matching REGEX as the start of a record and join the lines that \
+                     follow onto it, so that, e.g., a stack trace is searched and parsed along with \
+                     the message it belongs to. Try `'^\\d{4}-\\d{2}-\\d{2}'` for logs that start \
+                     each message with a date"
+    )]
+    multiline_start: Option<regex::Regex>,
+
+    #[arg(
+        long = "threads",
+        short = 'j',
+        value_name = "N",
+        default_value_t = 1,
+        long_help = "Search and parse the input on N threads, or one per CPU with 0. The output is \
+                     the same as with one thread, but records are handed to the threads in \
+                     batches, so lines from a slow stream show up a batch at a time. `--follow` \
+                     always uses one thread"
+    )]
+    threads: usize,
+
+    #[arg(
+        long = "max-memory",
+        value_name = "SIZE",
+        value_parser = parse_size,
+        long_help = "Let `sort` hold about SIZE of rows, and aggregates about SIZE of groups, in \
+                     memory, eg. `512M` or `2G`. Past that, sorted rows are written to temporary \
+                     files that are merged when the sort is output, and the partial results of new \
+                     groups to temporary files that are merged when the groups are output. \
+                     Unlimited by default"
+    )]
+    max_memory: Option<usize>,
+
+    #[arg(
+        long = "seed",
+        value_name = "N",
+        long_help = "Seed the random choices of `sample` operators that don't have a `seed=` of \
+                     their own, so that the same input gives the same output on every run"
+    )]
+    seed: Option<u64>,
+
+    #[arg(
+        long = "include-meta",
+        long_help = "Include metadata fields, those starting with `_` like `_raw`, when `dedup` \
+                     without any fields compares whole records, and in the fields matched by \
+                     patterns like `fields except *_id`. By default they're left out"
+    )]
+    include_meta: bool,
+
+    #[arg(
+        long = "stats",
+        value_name = "FORMAT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "text",
+        value_parser = ["text", "json"],
+        long_help = "After processing, print the number of lines and bytes read, the rows matched \
+                     and dropped by each operator, the time taken, and the peak memory use to \
+                     stderr. With `--stats=json`, they're written as a single JSON object"
+    )]
+    stats: Option<String>,
+
+    #[arg(
+        long = "geoip-db",
+        value_name = "PATH",
+        long_help = "The MaxMind database, like GeoLite2-City.mmdb, that `geoip` looks addresses up \
+                     in. Can be repeated to use, e.g., both the City and ASN databases. Defaults to \
+                     the paths in the AGRIND_GEOIP_DB environment variable"
+    )]
+    geoip_db: Vec<PathBuf>,
+
+    #[arg(
+        long = "grok-patterns",
+        value_name = "PATH",
+        long_help = "A file of grok patterns, one `NAME regex` per line like Logstash's, or a \
+                     directory of them, for `parse grok` to use along with the standard patterns. \
+                     Can be repeated. Defaults to the paths in the AGRIND_GROK_PATTERNS \
+                     environment variable"
+    )]
+    grok_patterns: Vec<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Inspect the aliases that queries can use
+    #[command(subcommand)]
+    Alias(AliasCommand),
+}
+
+#[derive(Debug, Subcommand)]
+enum AliasCommand {
+    /// List the built-in and local aliases, where each was loaded from, and which are shadowed
+    List,
+    /// Print an alias's template and the operators it expands to. An alias with parameters can be
+    /// given arguments to expand it with, eg. `agrind alias show 'errors(warn)'`
+    Show {
+        /// The alias, optionally with arguments
+        name: String,
+    },
+    /// Load the aliases and report any that are invalid, exiting with an error if there are any
+    Check,
+    /// Fetch the alias packs listed in the `packs.toml` of each alias directory
+    Sync,
 }
 
 #[derive(Debug, Error)]
@@ -73,6 +470,12 @@ pub enum InvalidArgs {
     #[error("Invalid output mode {}. Valid choices: {}", choice, choices)]
     InvalidOutputMode { choice: String, choices: String },
 
+    #[error(
+        "Invalid json option {}. Expected a comma separated list of `array` and `pretty`, eg. `-o json=array,pretty`",
+        option
+    )]
+    InvalidJsonOption { option: String },
+
     #[error("Invalid format string. Expected something like `-o format='{{src}} => {{dst}}'`")]
     InvalidFormatString,
 
@@ -81,66 +484,859 @@ pub enum InvalidArgs {
 
     #[error("Can't disable aliases and also set a directory")]
     CantDisableAndOverride,
+
+    #[error("Can't read the query from stdin without reading data from a file. Usage: `agrind --query-stdin --file data.log`")]
+    QueryStdinWithoutFile,
+
+    #[error("Failed to read the query from {}: {}", path, error)]
+    UnreadableQueryFile { path: String, error: String },
+
+    #[error("The REPL needs the data in a file, so that queries can be read from stdin. Usage: `agrind --repl --file data.log`")]
+    ReplWithoutFile,
+
+    #[error("The interactive mode reads keys from the terminal, so the data must come from --file or a pipe. Usage: `agrind --interactive --file data.log`")]
+    InteractiveWithoutInput,
+
+    #[error(
+        "--serve-metrics serves Prometheus metrics, so it can only be used with `-o prometheus`"
+    )]
+    ServeMetricsOutput,
+
+    #[error("Can't serve metrics on {}: {}", addr, error)]
+    CantServeMetrics { addr: String, error: String },
+
+    #[error("--multi reads the input once, so it can't be used with --follow")]
+    MultiFollow,
+
+    #[error("{} only works with a single --file", flag)]
+    SingleFileOnly { flag: &'static str },
+
+    #[error("Invalid file pattern {}: {}", pattern, reason)]
+    InvalidFilePattern { pattern: String, reason: String },
+
+    #[error("No files match {}", pattern)]
+    NoMatchingFiles { pattern: String },
+
+    #[error("Environment variable `{}` used in the query is not set", name)]
+    UnsetEnvVar { name: String },
+
+    #[error("Unterminated `${{` in the query. Expected something like `${{HOST}}`")]
+    UnterminatedEnvVar,
+
+    #[cfg(feature = "otlp")]
+    #[error(
+        "Invalid OTLP endpoint: {}. Expected something like `-o otlp=http://collector:4318`",
+        reason
+    )]
+    InvalidOtlpEndpoint { reason: String },
+
+    #[error(
+        "Invalid SQLite output: {}. Expected the path of the database and optionally a table, eg. `-o sqlite=results.db:errors`",
+        reason
+    )]
+    InvalidSqliteOutput { reason: String },
+
+    #[error("Invalid theme {}: {}", path, reason)]
+    InvalidTheme { path: String, reason: String },
+
+    #[error("Missing the path of the Parquet file. Expected something like `-o parquet=results.parquet`")]
+    InvalidParquetOutput,
+
+    #[error(
+        "Invalid split output: {}. Expected a directory and the field to split by, eg. `-o split=logs,by=service`",
+        reason
+    )]
+    InvalidSplitOutput { reason: String },
+}
+
+#[derive(Debug, Error)]
+pub enum StrictError {
+    #[error(
+        "{} of {} rows couldn't be parsed, more than the {} allowed by --strict",
+        failures,
+        rows,
+        allowed
+    )]
+    TooManyParseFailures {
+        failures: u64,
+        rows: u64,
+        allowed: String,
+    },
+}
+
+#[derive(Debug, Error)]
+pub enum AliasError {
+    #[error(
+        "No alias named `{}`. Run `agrind alias list` to see the aliases",
+        name
+    )]
+    UnknownAlias { name: String },
+
+    #[error("{} of {} aliases are invalid", invalid, total)]
+    InvalidAliases { invalid: usize, total: usize },
+
+    #[error("{} of {} alias packs couldn't be fetched", failed, total)]
+    SyncFailed { failed: usize, total: usize },
+}
+
+/// How many rows may fail to parse with `--strict`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FailureThreshold {
+    Count(u64),
+    /// A percentage of the lines that matched the search.
+    Percent(f64),
 }
 
+impl FailureThreshold {
+    fn exceeded(self, failures: u64, rows: u64) -> bool {
+        match self {
+            FailureThreshold::Count(max) => failures > max,
+            FailureThreshold::Percent(max) => failures as f64 * 100.0 > max * rows as f64,
+        }
+    }
+}
+
+impl std::fmt::Display for FailureThreshold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailureThreshold::Count(max) => write!(f, "{}", max),
+            FailureThreshold::Percent(max) => write!(f, "{}%", max),
+        }
+    }
+}
+
+fn parse_failure_threshold(max: &str) -> Result<FailureThreshold, String> {
+    match max.strip_suffix('%') {
+        Some(percent) => match percent.parse::<f64>() {
+            Ok(percent) if (0.0..=100.0).contains(&percent) => {
+                Ok(FailureThreshold::Percent(percent))
+            }
+            _ => Err("expected a percentage from 0% to 100%, like `1%`".to_string()),
+        },
+        None => max
+            .parse()
+            .map(FailureThreshold::Count)
+            .map_err(|_| "expected a number of rows, or a percentage like `1%`".to_string()),
+    }
+}
+
+/// Parse a number of bytes with an optional `K`, `M` or `G` suffix, in powers of 1024.
+fn parse_size(size: &str) -> Result<usize, String> {
+    let upper = size.trim().to_ascii_uppercase();
+    let number = upper.strip_suffix('B').unwrap_or(&upper);
+    let (number, multiplier) = match number.char_indices().last() {
+        Some((i, 'K')) => (&number[..i], 1 << 10),
+        Some((i, 'M')) => (&number[..i], 1 << 20),
+        Some((i, 'G')) => (&number[..i], 1 << 30),
+        _ => (number, 1),
+    };
+    number
+        .parse::<usize>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|n| *n > 0)
+        .ok_or_else(|| "expected a size in bytes, like `1048576`, `512M` or `2G`".to_string())
+}
+
+/// Parse a duration like `500ms`, `2s` or `1m`.  A plain number is in milliseconds.
+fn parse_interval(interval: &str) -> Result<Duration, String> {
+    let interval = interval.trim();
+    let split = interval
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(interval.len());
+    let (number, unit) = interval.split_at(split);
+    let millis = match unit {
+        "" | "ms" => 1,
+        "s" => 1000,
+        "m" => 60 * 1000,
+        _ => 0,
+    };
+    number
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(millis))
+        .filter(|_| millis > 0)
+        .map(Duration::from_millis)
+        .ok_or_else(|| "expected a duration, like `500ms`, `2s` or `250`".to_string())
+}
+
+/// Read a `--theme` file.
+fn load_theme(path: &Path) -> Result<Theme, InvalidArgs> {
+    let invalid = |reason: String| InvalidArgs::InvalidTheme {
+        path: path.display().to_string(),
+        reason,
+    };
+    let contents = fs::read_to_string(path).map_err(|err| invalid(err.to_string()))?;
+    toml::from_str(&contents).map_err(|err| invalid(err.to_string()))
+}
+
+/// With `--strict`, fail if too many rows couldn't be parsed, after listing the operators that
+/// dropped them.
+fn check_parse_failures(
+    stats: &PipelineStats,
+    threshold: FailureThreshold,
+) -> Result<(), StrictError> {
+    let failures: u64 = stats.parse_failures().map(|(_, failed)| failed).sum();
+    if !threshold.exceeded(failures, stats.lines_matched) {
+        return Ok(());
+    }
+    eprintln!("error: rows that couldn't be parsed, by operator:");
+    for (operator, failed) in stats.parse_failures() {
+        if failed > 0 {
+            eprintln!("  {}: {}", operator, failed);
+        }
+    }
+    Err(StrictError::TooManyParseFailures {
+        failures,
+        rows: stats.lines_matched,
+        allowed: threshold.to_string(),
+    })
+}
+
+#[cfg(not(feature = "otlp"))]
+const OUTPUT_CHOICES: &str =
+    "legacy, json, logfmt, format, prometheus, markdown, html, chart, sparkline, sqlite, parquet, split";
+#[cfg(feature = "otlp")]
+const OUTPUT_CHOICES: &str =
+    "legacy, json, logfmt, format, prometheus, markdown, html, chart, sparkline, sqlite, parquet, split, otlp";
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     setup_panic!();
     let args = Cli::parse();
+    if !args.geoip_db.is_empty() {
+        // The operator reads the paths from the environment, so the flag overrides it.
+        std::env::set_var(geoip::DATABASE_ENV, std::env::join_paths(&args.geoip_db)?);
+    }
+    if !args.grok_patterns.is_empty() {
+        std::env::set_var(
+            grok::PATTERNS_ENV,
+            std::env::join_paths(&args.grok_patterns)?,
+        );
+    }
     #[cfg(feature = "self_update")]
     if args.update {
         return update();
     }
+    if let Some(Command::Alias(AliasCommand::Sync)) = args.command {
+        let alias_dirs = match args.alias_dir {
+            Some(dir) => vec![dir],
+            None => alias::ancestor_alias_dirs(&std::env::current_dir()?)?,
+        };
+        return sync_alias_packs(&alias_dirs);
+    }
     let (aliases, errors) = match (args.alias_dir, args.no_alias) {
         (Some(dir), false) => AliasCollection::load_aliases_from_dir(&dir)?,
         (None, false) => AliasCollection::load_aliases_ancestors(None)?,
         (Some(_), true) => return Err(InvalidArgs::CantDisableAndOverride.into()),
         (None, true) => (AliasCollection::default(), vec![]),
     };
-    let error_reporter = Box::new(TermErrorReporter {});
+    let aliases = aliases.prefer_builtin(args.prefer_builtin);
+    let error_reporter = Box::new(TermErrorReporter { quiet: args.quiet });
+    if let Some(Command::Alias(AliasCommand::Check)) = args.command {
+        return check_aliases(
+            error_reporter.as_ref(),
+            &aliases,
+            errors,
+            args.prefer_builtin,
+        );
+    }
     for error in errors {
+        report_alias_warning(
+            error_reporter.as_ref(),
+            &format!("invalid alias: {}", error.cause),
+            &error.path,
+        );
+    }
+    for alias in aliases.shadowing_aliases() {
+        let label = if args.prefer_builtin {
+            format!(
+                "alias `{}` is shadowed by a built-in alias of the same name",
+                alias.keyword()
+            )
+        } else {
+            format!(
+                "alias `{}` shadows a built-in alias of the same name (use --prefer-builtin to use the built-in)",
+                alias.keyword()
+            )
+        };
+        report_alias_warning(error_reporter.as_ref(), &label, alias.path());
+    }
+    match args.command {
+        Some(Command::Alias(AliasCommand::List)) => {
+            list_aliases(&aliases);
+            return Ok(());
+        }
+        Some(Command::Alias(AliasCommand::Show { name })) => {
+            return show_alias(&name, error_reporter, aliases)
+        }
+        Some(Command::Alias(AliasCommand::Check | AliasCommand::Sync)) | None => {}
+    }
+    if args.format.is_some() {
         error_reporter.handle_error(Snippet {
             title: Some(Annotation {
                 id: None,
-                label: Some(&format!("invalid alias: {}", error.cause)),
+                label: Some("--format is deprecated, use -o format=... instead"),
                 annotation_type: AnnotationType::Warning,
             }),
             footer: vec![],
-            slices: vec![Slice {
-                source: "",
-                line_start: 0,
-                origin: Some(error.path.to_str().unwrap()),
-                annotations: vec![],
-                fold: true,
-            }],
+            slices: vec![],
             opt: FormatOptions::default(),
         });
     }
-    let query = QueryContainer::new_with_aliases(
-        args.query.ok_or(InvalidArgs::MissingQuery)?,
-        error_reporter,
-        aliases,
-    );
     let output_mode = match (args.output, args.format) {
         (Some(_output), Some(_format)) => Err(CantSupplyBoth),
         (Some(output), None) => parse_output(&output),
         (None, Some(format)) => Ok(OutputMode::Format(format)),
+        (None, None) if args.serve_metrics.is_some() => Ok(OutputMode::Prometheus),
         (None, None) => parse_output("legacy"),
     }?;
-    let pipeline = Pipeline::new(&query, stdout(), output_mode)?;
-    match args.file {
-        Some(file_name) => {
-            let f = File::open(file_name)?;
-            pipeline.process(BufReader::new(f))
+    if args.serve_metrics.is_some() && output_mode != OutputMode::Prometheus {
+        return Err(InvalidArgs::ServeMetricsOutput.into());
+    }
+    #[cfg(feature = "otlp")]
+    let output_mode = match output_mode {
+        OutputMode::Otlp(config) => OutputMode::Otlp(OtlpConfig {
+            severity_field: args.otlp_severity_field,
+            ..config
+        }),
+        other => other,
+    };
+    let snapshot = args.serve_metrics.as_ref().map(|_| Snapshot::default());
+    let files = expand_file_patterns(&args.file)?;
+    let options = PipelineOptions {
+        leaderboard: args.leaderboard.map(|column| match column {
+            Some(column) => Leaderboard::Column(column),
+            None => Leaderboard::PrimaryAggregate,
+        }),
+        strict_utf8: args.strict_utf8,
+        strict_parse: args.strict_parse,
+        max_line_length: args.max_line_length,
+        skip_long_lines: args.skip_long_lines,
+        strip_ansi: args.strip_ansi,
+        multiline_start: args.multiline_start,
+        refresh_interval: args.render_interval,
+        table: TableOptions {
+            columns: args.columns,
+            max_column_width: args.max_column_width.map(|width| width as usize),
+            right_align_numbers: args.right_align_numbers,
+        },
+        color: match args.color.as_str() {
+            "always" => ColorChoice::Always,
+            "never" => ColorChoice::Never,
+            _ => ColorChoice::Auto,
+        },
+        theme: match &args.theme {
+            Some(path) => load_theme(path)?,
+            None => Theme::default(),
+        },
+        // The whole aggregate is written once, rather than redrawn into the database or files.
+        render_once: args.no_live
+            || matches!(
+                output_mode,
+                OutputMode::Sqlite(_) | OutputMode::Parquet(_) | OutputMode::Split(_)
+            ),
+        threads: match args.threads {
+            0 => std::thread::available_parallelism().map_or(1, |n| n.get()),
+            threads => threads,
+        },
+        max_memory: args.max_memory,
+        snapshot: snapshot.clone(),
+        typed_input: files
+            .iter()
+            .any(|file| matches!(columnar::detect(Path::new(file)), Ok(Some(_)))),
+        multiple_files: files.len() > 1,
+        file2: args.file2,
+        seed: args.seed,
+        include_meta: args.include_meta,
+    };
+    if args.repl {
+        let file = match files.as_slice() {
+            [] => return Err(InvalidArgs::ReplWithoutFile.into()),
+            [file] => file,
+            _ => return Err(InvalidArgs::SingleFileOnly { flag: "--repl" }.into()),
+        };
+        return repl(
+            file,
+            &aliases,
+            &output_mode,
+            &options,
+            ReplFlags {
+                quiet: args.quiet,
+                expand_env: args.expand_env,
+                stats: args.stats,
+            },
+        );
+    }
+    if !args.multi.is_empty() {
+        if args.follow {
+            return Err(InvalidArgs::MultiFollow.into());
+        }
+        return multi(
+            &args.multi,
+            &files,
+            &aliases,
+            output_mode,
+            options,
+            ReplFlags {
+                quiet: args.quiet,
+                expand_env: args.expand_env,
+                stats: args.stats,
+            },
+            args.strict,
+        );
+    }
+    // Only a query file can have comments, since `#` can be part of a search on the command line.
+    let comments = args.query.is_none() && args.query_file.is_some();
+    let query =
+        match (args.query, args.query_file) {
+            (Some(query), _) => Some(query),
+            (None, Some(path)) => Some(fs::read_to_string(&path).map_err(|error| {
+                InvalidArgs::UnreadableQueryFile {
+                    path,
+                    error: error.to_string(),
+                }
+            })?),
+            (None, None) if !args.verbatim_query.is_empty() => Some(args.verbatim_query.join(" ")),
+            (None, None) => None,
+        };
+    #[cfg(unix)]
+    if args.interactive {
+        let query = query.unwrap_or_default();
+        return interactive(&query, &files, &aliases, &output_mode, &options);
+    }
+    let query_str = match (query, args.query_stdin) {
+        (Some(query), _) => query,
+        (None, true) if files.is_empty() => return Err(InvalidArgs::QueryStdinWithoutFile.into()),
+        (None, true) => {
+            let mut query = String::new();
+            io::stdin().read_to_string(&mut query)?;
+            query
         }
-        None => {
+        (None, false) => return Err(InvalidArgs::MissingQuery.into()),
+    };
+    let query_str = if args.expand_env {
+        expand_env(&query_str, |name| std::env::var(name).ok())?
+    } else {
+        query_str
+    };
+    let query = QueryContainer::new_with_aliases(query_str, error_reporter, aliases);
+    let query = if comments {
+        query.with_comments()
+    } else {
+        query
+    };
+    if args.explain {
+        println!("{}", Pipeline::explain(&query)?);
+        return Ok(());
+    }
+    if args.check {
+        Pipeline::new_with_options(&query, io::sink(), output_mode, options)?;
+        return Ok(());
+    }
+    let output = output_for(&output_mode);
+    let pipeline = Pipeline::new_with_options(&query, output, output_mode, options)?;
+    let stats = match files.as_slice() {
+        [] => {
             let stdin = io::stdin();
             let locked = stdin.lock();
             pipeline.process(locked)
         }
+        [file_name] if args.follow => {
+            if let (Some(addr), Some(snapshot)) = (&args.serve_metrics, snapshot) {
+                let bound = serve_metrics(addr.as_str(), snapshot.clone()).map_err(|error| {
+                    InvalidArgs::CantServeMetrics {
+                        addr: addr.clone(),
+                        error: error.to_string(),
+                    }
+                })?;
+                eprintln!("serving metrics at http://{}/metrics", bound);
+            }
+            pipeline.follow(file_name)
+        }
+        _ if args.follow => return Err(InvalidArgs::SingleFileOnly { flag: "--follow" }.into()),
+        files => pipeline.process_files(files),
+    }?;
+    if let Some(format) = &args.stats {
+        print_stats(&stats, format);
+    }
+    if let Some(threshold) = args.strict {
+        check_parse_failures(&stats, threshold)?;
+    }
+    Ok(())
+}
+
+/// Write the stats of a run to stderr, as text or as JSON, as given to `--stats`.
+fn print_stats(stats: &PipelineStats, format: &str) {
+    match format {
+        "json" => eprintln!(
+            "{}",
+            serde_json::to_string(stats).expect("stats can be serialized")
+        ),
+        _ => eprintln!("{}", stats),
+    }
+}
+
+/// The command line flags that also apply to each query run by the REPL or `--multi`.
+struct ReplFlags {
+    quiet: bool,
+    expand_env: bool,
+    stats: Option<String>,
+}
+
+/// Run each query read from stdin over the contents of `file`, which is only read once.
+fn repl(
+    file: &str,
+    aliases: &AliasCollection<'static>,
+    output_mode: &OutputMode,
+    options: &PipelineOptions,
+    flags: ReplFlags,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut data = Vec::new();
+    File::open(file)?.read_to_end(&mut data)?;
+    // Queries typed at a terminal get line editing, history and completion, while queries piped
+    // in are read a line at a time.
+    #[cfg(unix)]
+    let mut prompt = io::stdin()
+        .is_terminal()
+        .then(|| ag::interactive::Prompt::new(&data, aliases, history_path()))
+        .transpose()?;
+    let mut queries = io::stdin().lock().lines();
+    let mut next_query = || -> io::Result<Option<String>> {
+        #[cfg(unix)]
+        if let Some(prompt) = prompt.as_mut() {
+            return prompt.read_query();
+        }
+        eprint!("agrind> ");
+        io::stderr().flush()?;
+        queries.next().transpose()
     };
+    while let Some(query_str) = next_query()? {
+        let query_str = query_str.trim();
+        match query_str {
+            "" => continue,
+            "exit" | "quit" => break,
+            _ => {}
+        }
+        let query_str = if flags.expand_env {
+            match expand_env(query_str, |name| std::env::var(name).ok()) {
+                Ok(query_str) => query_str,
+                Err(err) => {
+                    eprintln!("Error: {}", err);
+                    continue;
+                }
+            }
+        } else {
+            query_str.to_owned()
+        };
+        let error_reporter = Box::new(TermErrorReporter { quiet: flags.quiet });
+        let query = QueryContainer::new_with_aliases(query_str, error_reporter, aliases.clone());
+        let output = output_for(output_mode);
+        let result =
+            Pipeline::new_with_options(&query, output, output_mode.clone(), options.clone())
+                .and_then(|pipeline| Ok(pipeline.process(data.as_slice())?));
+        match result {
+            Ok(stats) => {
+                if let Some(format) = &flags.stats {
+                    print_stats(&stats, format);
+                }
+            }
+            Err(err) => eprintln!("Error: {}", err),
+        }
+    }
+    Ok(())
+}
+
+/// Run each of the `--multi` queries over a single pass of `files`, or stdin.
+fn multi(
+    queries: &[String],
+    files: &[String],
+    aliases: &AliasCollection<'static>,
+    output_mode: OutputMode,
+    options: PipelineOptions,
+    flags: ReplFlags,
+    strict: Option<FailureThreshold>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let queries = queries
+        .iter()
+        .map(|query_str| {
+            let query_str = if flags.expand_env {
+                expand_env(query_str, |name| std::env::var(name).ok())?
+            } else {
+                query_str.clone()
+            };
+            let error_reporter = Box::new(TermErrorReporter { quiet: flags.quiet });
+            Ok(QueryContainer::new_with_aliases(
+                query_str,
+                error_reporter,
+                aliases.clone(),
+            ))
+        })
+        .collect::<Result<Vec<_>, InvalidArgs>>()?;
+    let output = output_for(&output_mode);
+    let pipeline = MultiPipeline::new_with_options(&queries, output, output_mode, options)?;
+    let all_stats = match files {
+        [] => pipeline.process(io::stdin().lock()),
+        files => pipeline.process_files(files),
+    }?;
+    for (query, stats) in queries.iter().zip(&all_stats) {
+        if let Some(format) = &flags.stats {
+            // JSON stats are written one query per line, in order, so they stay parseable.
+            if format != "json" {
+                eprintln!("==> {} <==", query.query.trim());
+            }
+            print_stats(stats, format);
+        }
+    }
+    if let Some(threshold) = strict {
+        for stats in &all_stats {
+            check_parse_failures(stats, threshold)?;
+        }
+    }
+    Ok(())
+}
+
+/// The file that the REPL's history is kept in: `AGRIND_HISTORY`, or `.agrind_history` in the
+/// home directory.
+#[cfg(unix)]
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("AGRIND_HISTORY")
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| Path::new(&home).join(".agrind_history")))
+}
+
+/// Read the data from `files` or stdin, then edit the query in a terminal UI over it.  The query
+/// is printed when it's accepted, so that it can be copied or captured by a script.
+#[cfg(unix)]
+fn interactive(
+    query: &str,
+    files: &[String],
+    aliases: &AliasCollection<'static>,
+    output_mode: &OutputMode,
+    options: &PipelineOptions,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut data = Vec::new();
+    match files {
+        [] if io::stdin().is_terminal() => return Err(InvalidArgs::InteractiveWithoutInput.into()),
+        [] => {
+            io::stdin().lock().read_to_end(&mut data)?;
+        }
+        [file] => {
+            File::open(file)?.read_to_end(&mut data)?;
+        }
+        _ => {
+            return Err(InvalidArgs::SingleFileOnly {
+                flag: "--interactive",
+            }
+            .into())
+        }
+    }
+    if let Some(query) = ag::interactive::run(query, &data, aliases, output_mode, options)? {
+        println!("{}", query);
+    }
+    Ok(())
+}
+
+/// Print every alias with where it was loaded from.  An alias is shadowed when a query using
+/// its keyword would get a different alias.
+fn list_aliases(aliases: &AliasCollection) {
+    let all: Vec<_> = LOADED_ALIASES
+        .iter()
+        .map(|alias| (alias, "built-in".to_string()))
+        .chain(
+            aliases
+                .local_aliases()
+                .map(|alias| (alias, alias.path().display().to_string())),
+        )
+        .map(|(alias, source)| {
+            let name = match alias.params() {
+                [] => alias.keyword().to_string(),
+                params => format!("{}({})", alias.keyword(), params.join(", ")),
+            };
+            let shadowed = !aliases
+                .get_alias(alias.keyword())
+                .is_some_and(|active| std::ptr::eq(active, alias));
+            (name, source, shadowed)
+        })
+        .collect();
+    let width = all.iter().map(|(name, _, _)| name.len()).max().unwrap_or(0);
+    for (name, source, shadowed) in all {
+        let note = if shadowed { " (shadowed)" } else { "" };
+        println!("{:width$}  {}{}", name, source, note, width = width);
+    }
+}
+
+/// Print the alias's template, and the operators it expands to when it has no parameters or
+/// when arguments are given.
+fn show_alias(
+    name: &str,
+    error_reporter: Box<dyn ErrorReporter>,
+    aliases: AliasCollection<'static>,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let keyword = name.split('(').next().unwrap_or_default().trim();
+    let alias = aliases
+        .get_alias(keyword)
+        .ok_or_else(|| AliasError::UnknownAlias {
+            name: keyword.to_string(),
+        })?;
+    if LOADED_ALIASES
+        .iter()
+        .any(|builtin| std::ptr::eq(builtin, alias))
+    {
+        println!("source: built-in");
+    } else {
+        println!("source: {}", alias.path().display());
+    }
+    if !alias.params().is_empty() {
+        println!("params: {}", alias.params().join(", "));
+    }
+    println!("template:\n{}", alias.template());
+    if alias.params().is_empty() || name.contains('(') {
+        let query = QueryContainer::new_with_aliases(
+            format!("* | {}", name.trim()),
+            error_reporter,
+            aliases.clone(),
+        );
+        let explained = Pipeline::explain(&query)?;
+        // Drop the search, which is only there to make a complete query, and the plan.
+        let operators = explained
+            .lines()
+            .skip(1)
+            .take_while(|line| !line.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+        println!("expands to:\n{}", operators);
+    }
+    Ok(())
+}
+
+/// Report the aliases that failed to load as errors, and other problems as warnings.
+fn check_aliases(
+    error_reporter: &dyn ErrorReporter,
+    aliases: &AliasCollection,
+    errors: Vec<InvalidAliasError>,
+    prefer_builtin: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    for error in &errors {
+        report_alias(
+            error_reporter,
+            &format!("invalid alias: {}", error.cause),
+            &error.path,
+            AnnotationType::Error,
+        );
+    }
+    for alias in aliases.local_aliases() {
+        for param in alias.unused_params() {
+            report_alias_warning(
+                error_reporter,
+                &format!(
+                    "alias `{}` has a parameter `{}` that isn't used in its template",
+                    alias.keyword(),
+                    param
+                ),
+                alias.path(),
+            );
+        }
+    }
+    for alias in aliases.shadowing_aliases() {
+        let label = if prefer_builtin {
+            format!(
+                "alias `{}` is shadowed by a built-in alias of the same name",
+                alias.keyword()
+            )
+        } else {
+            format!(
+                "alias `{}` shadows a built-in alias of the same name",
+                alias.keyword()
+            )
+        };
+        report_alias_warning(error_reporter, &label, alias.path());
+    }
+    let total = LOADED_ALIASES.len() + aliases.local_aliases().count() + errors.len();
+    if errors.is_empty() {
+        println!("{} aliases are valid", total);
+        Ok(())
+    } else {
+        Err(AliasError::InvalidAliases {
+            invalid: errors.len(),
+            total,
+        }
+        .into())
+    }
+}
+
+/// Fetch the packs listed in each alias directory, reporting the ones that fail and carrying on
+/// with the rest.
+fn sync_alias_packs(alias_dirs: &[PathBuf]) -> Result<(), Box<dyn std::error::Error>> {
+    let (mut total, mut failed) = (0, 0);
+    for alias_dir in alias_dirs {
+        let packs = match alias::packs::read_manifest(alias_dir) {
+            Ok(packs) => packs,
+            Err(e) => {
+                eprintln!(
+                    "error: invalid {}: {}",
+                    alias_dir.join(alias::packs::MANIFEST).display(),
+                    e
+                );
+                failed += 1;
+                total += 1;
+                continue;
+            }
+        };
+        for pack in packs {
+            total += 1;
+            match alias::packs::sync(alias_dir, &pack) {
+                Ok(()) => println!(
+                    "fetched `{}` from {} into {}",
+                    pack.name,
+                    pack.origin(),
+                    pack.cache_dir(alias_dir).display()
+                ),
+                Err(e) => {
+                    eprintln!("error: failed to fetch `{}`: {:#}", pack.name, e);
+                    failed += 1;
+                }
+            }
+        }
+    }
+    match (total, failed) {
+        (0, _) => println!(
+            "no alias packs are listed in a `{}`",
+            alias::packs::MANIFEST
+        ),
+        (_, 0) => {}
+        (total, failed) => return Err(AliasError::SyncFailed { failed, total }.into()),
+    }
     Ok(())
 }
 
+fn report_alias_warning(error_reporter: &dyn ErrorReporter, label: &str, path: &Path) {
+    report_alias(error_reporter, label, path, AnnotationType::Warning)
+}
+
+fn report_alias(
+    error_reporter: &dyn ErrorReporter,
+    label: &str,
+    path: &Path,
+    annotation_type: AnnotationType,
+) {
+    error_reporter.handle_error(Snippet {
+        title: Some(Annotation {
+            id: None,
+            label: Some(label),
+            annotation_type,
+        }),
+        footer: vec![],
+        slices: vec![Slice {
+            source: "",
+            line_start: 0,
+            origin: Some(path.to_str().unwrap()),
+            annotations: vec![],
+            fold: true,
+        }],
+        opt: FormatOptions::default(),
+    });
+}
+
 fn parse_output(output_param: &str) -> Result<OutputMode, InvalidArgs> {
     // for some args, we split on `=` first
     let (arg, val) = match output_param.find('=') {
@@ -151,17 +1347,148 @@ fn parse_output(output_param: &str) -> Result<OutputMode, InvalidArgs> {
 
     match (arg, val) {
         ("legacy", "") => Ok(OutputMode::Legacy),
-        ("json", "") => Ok(OutputMode::Json),
+        ("json", v) => parse_json_options(v).map(OutputMode::Json),
         ("logfmt", "") => Ok(OutputMode::Logfmt),
+        ("prometheus", "") => Ok(OutputMode::Prometheus),
+        ("markdown", "") => Ok(OutputMode::Markdown),
+        ("html", "") => Ok(OutputMode::Html),
+        ("chart", "") => Ok(OutputMode::Chart(None)),
+        ("chart", v) => Ok(OutputMode::Chart(Some(v.to_owned()))),
+        ("sparkline", "") => Ok(OutputMode::Sparkline(None)),
+        ("sparkline", v) => Ok(OutputMode::Sparkline(Some(v.to_owned()))),
+        ("sqlite", v) => SqliteConfig::parse(v)
+            .map(OutputMode::Sqlite)
+            .map_err(|reason| InvalidSqliteOutput { reason }),
+        ("parquet", v) if !v.is_empty() => Ok(OutputMode::Parquet(PathBuf::from(v))),
+        ("parquet", "") => Err(InvalidParquetOutput),
+        ("split", v) => SplitConfig::parse(v)
+            .map(OutputMode::Split)
+            .map_err(|reason| InvalidSplitOutput { reason }),
         ("format", v) if !v.is_empty() => Ok(OutputMode::Format(v.to_owned())),
         ("format", "") => Err(InvalidFormatString),
+        #[cfg(feature = "otlp")]
+        ("otlp", "") => Ok(OutputMode::Otlp(OtlpConfig::default())),
+        #[cfg(feature = "otlp")]
+        ("otlp", v) => match Endpoint::parse(v) {
+            Ok(endpoint) => Ok(OutputMode::Otlp(OtlpConfig {
+                endpoint: Some(endpoint),
+                ..OtlpConfig::default()
+            })),
+            Err(reason) => Err(InvalidOtlpEndpoint { reason }),
+        },
         (other, _v) => Err(InvalidOutputMode {
             choice: other.to_owned(),
-            choices: "legacy, json, logfmt, format".to_owned(),
+            choices: OUTPUT_CHOICES.to_owned(),
         }),
     }
 }
 
+fn parse_json_options(options: &str) -> Result<JsonOptions, InvalidArgs> {
+    let mut json_options = JsonOptions::default();
+    for option in options.split(',').filter(|o| !o.is_empty()) {
+        match option {
+            "array" => json_options.array = true,
+            "pretty" => json_options.pretty = true,
+            other => {
+                return Err(InvalidJsonOption {
+                    option: other.to_owned(),
+                })
+            }
+        }
+    }
+    Ok(json_options)
+}
+
+/// Expand the glob patterns among the `--file` arguments, e.g. `logs/**/*.log`, into the files
+/// they match in lexical order.  Arguments that aren't patterns, or that name an existing file,
+/// are used as-is.
+fn expand_file_patterns(args: &[String]) -> Result<Vec<String>, InvalidArgs> {
+    let mut files = Vec::with_capacity(args.len());
+    for arg in args {
+        if !arg.contains(['*', '?', '[']) || Path::new(arg).exists() {
+            files.push(arg.clone());
+            continue;
+        }
+        let paths = glob::glob(arg).map_err(|e| InvalidArgs::InvalidFilePattern {
+            pattern: arg.clone(),
+            reason: e.msg.to_owned(),
+        })?;
+        let mut matches = Vec::new();
+        for path in paths {
+            let path = path.map_err(|e| InvalidArgs::InvalidFilePattern {
+                pattern: arg.clone(),
+                reason: e.to_string(),
+            })?;
+            if path.is_file() {
+                matches.push(path.to_string_lossy().into_owned());
+            }
+        }
+        if matches.is_empty() {
+            return Err(InvalidArgs::NoMatchingFiles {
+                pattern: arg.clone(),
+            });
+        }
+        matches.sort();
+        files.extend(matches);
+    }
+    Ok(files)
+}
+
+/// Substitute `$VAR` and `${VAR}` in the query with the variables returned by `lookup`. `$$` is
+/// a literal `$`, as is a `$` followed by something other than a variable name, so that
+/// `rename /(.+)_id$/ as $1` reads the same with or without `--expand-env`.
+fn expand_env(query: &str, lookup: impl Fn(&str) -> Option<String>) -> Result<String, InvalidArgs> {
+    let is_name_start = |c: char| c.is_ascii_alphabetic() || c == '_';
+    let mut out = String::with_capacity(query.len());
+    let mut rest = query;
+    while let Some(idx) = rest.find('$') {
+        out.push_str(&rest[..idx]);
+        rest = &rest[idx + 1..];
+        let name = if let Some(braced) = rest.strip_prefix('{') {
+            let end = braced.find('}').ok_or(UnterminatedEnvVar)?;
+            rest = &braced[end + 1..];
+            &braced[..end]
+        } else if let Some(after) = rest.strip_prefix('$') {
+            out.push('$');
+            rest = after;
+            continue;
+        } else if rest.starts_with(is_name_start) {
+            let end = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+                .unwrap_or(rest.len());
+            let (name, after) = rest.split_at(end);
+            rest = after;
+            name
+        } else {
+            out.push('$');
+            continue;
+        };
+        let value = lookup(name).ok_or_else(|| UnsetEnvVar {
+            name: name.to_owned(),
+        })?;
+        out.push_str(&value);
+    }
+    out.push_str(rest);
+    Ok(out)
+}
+
+/// Where the rendered output goes: stdout, unless it's being exported to an OTLP collector.
+#[cfg(feature = "otlp")]
+fn output_for(output_mode: &OutputMode) -> Box<dyn Write + Send> {
+    match output_mode {
+        OutputMode::Otlp(OtlpConfig {
+            endpoint: Some(endpoint),
+            ..
+        }) => Box::new(OtlpExporter::new(endpoint.clone())),
+        _ => Box::new(stdout()),
+    }
+}
+
+#[cfg(not(feature = "otlp"))]
+fn output_for(_output_mode: &OutputMode) -> Box<dyn Write + Send> {
+    Box::new(stdout())
+}
+
 #[cfg(feature = "self_update")]
 fn update() -> Result<(), Box<dyn std::error::Error>> {
     let crate_version = self_update::cargo_crate_version!();
@@ -0,0 +1,301 @@
+//! Running queries from other programs.
+//!
+//! [`PipelineBuilder`] takes a query as a string and runs it over any [`Read`], writing the
+//! output to any [`Write`], or handing the rows to a callback or an iterator.  The errors in the
+//! query are returned, rendered with the part of the query they're about, rather than printed to
+//! the terminal.
+use crate::alias::AliasCollection;
+use crate::data::Row;
+use crate::errors::{ErrorReporter, QueryContainer};
+use crate::pipeline::{InputError, OutputMode, Pipeline, PipelineOptions, PipelineStats};
+use crate::plugin::Plugins;
+use crate::sink::{Callback, RowSink};
+use annotate_snippets::display_list::DisplayList;
+use annotate_snippets::snippet::{Annotation, AnnotationType, Snippet};
+use crossbeam_channel::{bounded, Receiver};
+use std::io::{self, BufReader, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::thread::{self, JoinHandle};
+use thiserror::Error;
+
+/// Why a query couldn't be run.
+#[derive(Debug, Error)]
+pub enum PipelineError {
+    /// The query has errors, each rendered with the part of the query it's about.
+    #[error("invalid query:\n{}", .errors.join("\n"))]
+    Query { errors: Vec<String> },
+
+    #[error(transparent)]
+    Input(#[from] InputError),
+}
+
+/// Collects the errors about the query, dropping the warnings.
+#[derive(Clone, Default)]
+struct CollectingReporter(Arc<Mutex<Vec<String>>>);
+
+impl ErrorReporter for CollectingReporter {
+    fn handle_error(&self, snippet: Snippet) {
+        if let Some(Annotation {
+            annotation_type: AnnotationType::Warning,
+            ..
+        }) = snippet.title
+        {
+            return;
+        }
+        let error = DisplayList::from(snippet).to_string();
+        self.0.lock().expect("not poisoned").push(error);
+    }
+}
+
+/// Builds a pipeline for a query, e.g.
+/// `PipelineBuilder::new("* | json | count by level").run(input, output)`.  The output is
+/// written in the `legacy` format by default, and aggregates are written once, when the input
+/// ends.  No aliases are available unless they're given with [`PipelineBuilder::aliases`].
+pub struct PipelineBuilder {
+    query: String,
+    output_mode: OutputMode,
+    options: PipelineOptions,
+    aliases: AliasCollection<'static>,
+    plugins: Plugins,
+}
+
+impl PipelineBuilder {
+    pub fn new<Q: Into<String>>(query: Q) -> Self {
+        PipelineBuilder {
+            query: query.into(),
+            output_mode: OutputMode::Legacy,
+            options: PipelineOptions::default(),
+            aliases: AliasCollection::default(),
+            plugins: Plugins::default(),
+        }
+    }
+
+    pub fn output_mode(mut self, output_mode: OutputMode) -> Self {
+        self.output_mode = output_mode;
+        self
+    }
+
+    pub fn options(mut self, options: PipelineOptions) -> Self {
+        self.options = options;
+        self
+    }
+
+    pub fn aliases(mut self, aliases: AliasCollection<'static>) -> Self {
+        self.aliases = aliases;
+        self
+    }
+
+    /// Make the operators of the plugins available to the query.
+    pub fn plugins(mut self, plugins: Plugins) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    /// Parse and check the query, returning a pipeline that writes to `output`.
+    pub fn build<W: 'static + Write + Send>(self, output: W) -> Result<Pipeline, PipelineError> {
+        let reporter = CollectingReporter::default();
+        let container =
+            QueryContainer::new_with_aliases(self.query, Box::new(reporter.clone()), self.aliases)
+                .with_plugins(self.plugins);
+        let options = PipelineOptions {
+            render_once: true,
+            ..self.options
+        };
+        Pipeline::new_with_options(&container, output, self.output_mode, options).map_err(|e| {
+            let mut errors = reporter.0.lock().expect("not poisoned").clone();
+            // Errors that aren't about a part of the query, like an output mode that doesn't
+            // work with it, are only in the error that's returned.
+            if errors.is_empty() {
+                errors.push(e.to_string());
+            }
+            PipelineError::Query { errors }
+        })
+    }
+
+    /// Run the query over `input`, writing the output to `output`.
+    pub fn run<R: Read, W: 'static + Write + Send>(
+        self,
+        input: R,
+        output: W,
+    ) -> Result<PipelineStats, PipelineError> {
+        let pipeline = self.build(output)?;
+        Ok(pipeline.process(BufReader::new(input))?)
+    }
+
+    /// Run the query over `input`, handing each output row to `sink` instead of writing it out.
+    pub fn run_with_sink<R: Read, S: 'static + RowSink>(
+        self,
+        input: R,
+        sink: S,
+    ) -> Result<PipelineStats, PipelineError> {
+        self.output_mode(OutputMode::Callback(Callback::new(sink)))
+            .run(input, io::sink())
+    }
+
+    /// Run the query over `input` on another thread, returning the output rows as they're
+    /// emitted.  Errors in the query are returned before anything is read.
+    pub fn rows<R: 'static + Read + Send>(self, input: R) -> Result<Rows, PipelineError> {
+        let (sender, rows) = bounded(ROWS_BUFFER);
+        let pipeline = self
+            .output_mode(OutputMode::Callback(Callback::new(move |row| {
+                // The rows are no longer wanted once the iterator is dropped.
+                let _ = sender.send(row);
+            })))
+            .build(io::sink())?;
+        let handle = thread::spawn(move || Ok(pipeline.process(BufReader::new(input))?));
+        Ok(Rows { rows, handle })
+    }
+}
+
+/// The number of rows that can be waiting to be read from [`Rows`] before the pipeline stops to
+/// wait for them.
+const ROWS_BUFFER: usize = 1024;
+
+/// The output rows of a query run by [`PipelineBuilder::rows`].
+pub struct Rows {
+    rows: Receiver<Row>,
+    handle: JoinHandle<Result<PipelineStats, PipelineError>>,
+}
+
+impl Rows {
+    /// Wait for the input to be read, returning the stats, or why it couldn't be read.  The rows
+    /// that haven't been read from the iterator are dropped.
+    pub fn finish(self) -> Result<PipelineStats, PipelineError> {
+        drop(self.rows);
+        self.handle.join().expect("pipeline thread panicked")
+    }
+}
+
+impl Iterator for Rows {
+    type Item = Row;
+
+    fn next(&mut self) -> Option<Row> {
+        self.rows.recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{Record, Value};
+    use crate::operator::{EvalError, UnaryPreAggFunction};
+    use crate::pipeline::JsonOptions;
+    use crate::plugin::{OperatorPlugin, PluginOperator};
+
+    #[derive(Clone, Default)]
+    struct Output(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Output {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn runs_query() {
+        let output = Output::default();
+        let input = "{\"level\": \"info\"}\n{\"level\": \"error\"}\n{\"level\": \"info\"}\n";
+        let stats = PipelineBuilder::new("* | json | count by level")
+            .output_mode(OutputMode::Json(JsonOptions::default()))
+            .run(input.as_bytes(), output.clone())
+            .unwrap();
+        assert_eq!(stats.lines_read, 3);
+        assert_eq!(
+            String::from_utf8(output.0.lock().unwrap().clone()).unwrap(),
+            "[{\"level\":\"info\",\"_count\":2},{\"level\":\"error\",\"_count\":1}]\n"
+        );
+    }
+
+    #[test]
+    fn iterates_over_rows() {
+        let input = "{\"level\": \"info\"}\n{\"level\": \"error\"}\n";
+        let mut rows = PipelineBuilder::new("* | json")
+            .rows(input.as_bytes())
+            .unwrap();
+        let levels: Vec<_> = rows
+            .by_ref()
+            .map(|row| match row {
+                Row::Record(rec) => rec.data["level"].clone(),
+                Row::Aggregate(_) => panic!("expected records"),
+            })
+            .collect();
+        assert_eq!(
+            levels,
+            vec![
+                Value::Str("info".to_string()),
+                Value::Str("error".to_string())
+            ]
+        );
+        assert_eq!(rows.finish().unwrap().lines_read, 2);
+    }
+
+    /// Adds the field named by its arguments, with the line as its value.
+    struct LinePlugin;
+
+    #[derive(Clone)]
+    struct Line(String);
+
+    impl UnaryPreAggFunction for Line {
+        fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
+            let line = Value::Str(rec.raw.trim_end().to_string());
+            Ok(Some(rec.put(self.0.clone(), line)))
+        }
+    }
+
+    impl OperatorPlugin for LinePlugin {
+        fn keyword(&self) -> &str {
+            "line"
+        }
+
+        fn build(&self, args: &str) -> Result<PluginOperator, String> {
+            match args {
+                "" => Err("expected the name of the field".to_string()),
+                name => Ok(PluginOperator::Inline(Box::new(Line(name.to_string())))),
+            }
+        }
+    }
+
+    #[test]
+    fn runs_plugins() {
+        let mut plugins = Plugins::default();
+        plugins.register(LinePlugin).unwrap();
+        let output = Output::default();
+        PipelineBuilder::new("* | line text | count by text")
+            .plugins(plugins.clone())
+            .output_mode(OutputMode::Json(JsonOptions::default()))
+            .run("a\nb\na\n".as_bytes(), output.clone())
+            .unwrap();
+        assert_eq!(
+            String::from_utf8(output.0.lock().unwrap().clone()).unwrap(),
+            "[{\"text\":\"a\",\"_count\":2},{\"text\":\"b\",\"_count\":1}]\n"
+        );
+        let result = PipelineBuilder::new("* | line")
+            .plugins(plugins)
+            .run("".as_bytes(), std::io::sink());
+        match result {
+            Err(PipelineError::Query { errors }) => assert!(
+                errors[0].contains("Invalid arguments for line: expected the name of the field"),
+                "{}",
+                errors[0]
+            ),
+            _ => panic!("expected a query error"),
+        }
+    }
+
+    #[test]
+    fn returns_query_errors() {
+        let result = PipelineBuilder::new("* | jsn").run("".as_bytes(), std::io::sink());
+        match result {
+            Err(PipelineError::Query { errors }) => {
+                assert_eq!(errors.len(), 1);
+                assert!(errors[0].contains("Expected an operator"), "{}", errors[0]);
+            }
+            _ => panic!("expected a query error"),
+        }
+    }
+}
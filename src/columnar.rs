@@ -0,0 +1,352 @@
+//! Columnar input files, Parquet and Arrow IPC, whose rows are read as records with typed fields
+//! rather than as lines of text to be parsed.
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+#[cfg(any(feature = "parquet", feature = "arrow"))]
+use chrono::{TimeZone, Utc};
+
+use crate::data::Record;
+#[cfg(any(feature = "parquet", feature = "arrow"))]
+use crate::data::Value;
+
+/// The format of a columnar input file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Parquet,
+    Arrow,
+}
+
+impl Format {
+    fn name(self) -> &'static str {
+        match self {
+            Format::Parquet => "Parquet",
+            Format::Arrow => "Arrow IPC",
+        }
+    }
+}
+
+/// Detect whether a file is a Parquet or Arrow IPC file from the magic bytes at its start, like
+/// compressed files are.  Only regular files are checked, since reading the start of a pipe
+/// would lose it.
+pub fn detect(path: &Path) -> io::Result<Option<Format>> {
+    let file = File::open(path)?;
+    if !file.metadata()?.is_file() {
+        return Ok(None);
+    }
+    let mut magic = Vec::with_capacity(6);
+    file.take(6).read_to_end(&mut magic)?;
+    Ok(if magic.starts_with(crate::parquet::MAGIC) {
+        Some(Format::Parquet)
+    } else if crate::arrow::is_arrow(&magic) {
+        Some(Format::Arrow)
+    } else {
+        None
+    })
+}
+
+/// The rows of a columnar file, a batch at a time.
+pub struct Rows {
+    /// The size of the file.
+    pub bytes: u64,
+    pub batches: Box<dyn Iterator<Item = io::Result<Vec<Record>>> + Send>,
+}
+
+/// Read a columnar file.
+pub fn open(path: &Path, format: Format) -> io::Result<Rows> {
+    match format {
+        #[cfg(feature = "parquet")]
+        Format::Parquet => rows(path, format, crate::parquet::RecordBatches::new),
+        #[cfg(not(feature = "parquet"))]
+        Format::Parquet => Err(disabled(path, format, "parquet")),
+        #[cfg(feature = "arrow")]
+        Format::Arrow => rows(path, format, crate::arrow::RecordBatches::new),
+        #[cfg(not(feature = "arrow"))]
+        Format::Arrow => Err(disabled(path, format, "arrow")),
+    }
+}
+
+/// Read the rows of a file with the reader of its format.
+#[cfg(any(feature = "parquet", feature = "arrow"))]
+fn rows<B>(path: &Path, format: Format, read: fn(File) -> Result<B, String>) -> io::Result<Rows>
+where
+    B: Iterator<Item = Result<Vec<Record>, String>> + Send + 'static,
+{
+    let file = File::open(path)?;
+    let bytes = file.metadata()?.len();
+    let name = path.display().to_string();
+    let invalid = move |reason: String| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{} isn't a valid {} file: {}", name, format.name(), reason),
+        )
+    };
+    let batches = read(file).map_err(&invalid)?;
+    Ok(Rows {
+        bytes,
+        batches: Box::new(batches.map(move |batch| batch.map_err(&invalid))),
+    })
+}
+
+/// The error for a file whose format agrind was built without support for.
+#[cfg(not(all(feature = "parquet", feature = "arrow")))]
+fn disabled(path: &Path, format: Format, feature: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::Unsupported,
+        format!(
+            "{} can't be read: agrind was built without {} support. \
+             Rebuild it with `--features {}`",
+            path.display(),
+            format.name(),
+            feature
+        ),
+    )
+}
+
+/// A record made from a row's columns, in order.  Its raw text, which search filters match
+/// against and which is output when no fields are, is the row as a JSON object.
+#[cfg(any(feature = "parquet", feature = "arrow"))]
+pub(crate) fn record(columns: impl IntoIterator<Item = (String, Value)>) -> Record {
+    let mut raw = String::from("{");
+    let mut rec = Record::new(String::new());
+    for (name, value) in columns {
+        if raw.len() > 1 {
+            raw.push(',');
+        }
+        raw.push_str(&serde_json::to_string(&name).expect("strings serialize"));
+        raw.push(':');
+        raw.push_str(&serde_json::to_string(&value).expect("values serialize"));
+        rec.put_mut(name, value);
+    }
+    raw.push('}');
+    rec.raw = raw;
+    rec
+}
+
+/// A point in time that's `count` units since the epoch, with `per_second` units in a second.
+#[cfg(any(feature = "parquet", feature = "arrow"))]
+pub(crate) fn timestamp(count: i64, per_second: i64) -> Value {
+    let nanos = count.rem_euclid(per_second) * (1_000_000_000 / per_second);
+    match Utc
+        .timestamp_opt(count.div_euclid(per_second), nanos as u32)
+        .single()
+    {
+        Some(dt) => Value::DateTime(dt),
+        None => Value::None,
+    }
+}
+
+/// A duration of `count` units, with `per_second` units in a second.
+#[cfg(any(feature = "parquet", feature = "arrow"))]
+pub(crate) fn duration(count: i64, per_second: i64) -> Value {
+    let nanos = count.rem_euclid(per_second) * (1_000_000_000 / per_second);
+    match chrono::Duration::try_seconds(count.div_euclid(per_second)) {
+        Some(secs) => Value::Duration(secs + chrono::Duration::nanoseconds(nanos)),
+        None => Value::None,
+    }
+}
+
+/// The records of a record batch, as the `parquet` and `arrow-ipc` crates read them.
+#[cfg(any(feature = "parquet", feature = "arrow"))]
+pub(crate) fn batch_records(batch: &arrow_array::RecordBatch) -> Result<Vec<Record>, String> {
+    let schema = batch.schema();
+    (0..batch.num_rows())
+        .map(|i| {
+            let columns = schema
+                .fields()
+                .iter()
+                .zip(batch.columns())
+                .map(|(field, column)| {
+                    array_value(column.as_ref(), i)
+                        .map(|value| (field.name().clone(), value))
+                        .map_err(|e| format!("column `{}`: {}", field.name(), e))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(record(columns))
+        })
+        .collect()
+}
+
+/// The value of the `i`th element of an array.  Structs and maps become objects, lists become
+/// arrays, and dictionary encoded values are looked up in their dictionaries.
+#[cfg(any(feature = "parquet", feature = "arrow"))]
+fn array_value(array: &dyn arrow_array::Array, i: usize) -> Result<Value, String> {
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::*;
+    use arrow_array::Array;
+    use arrow_schema::{DataType, TimeUnit};
+    use std::convert::TryFrom;
+
+    if array.is_null(i) {
+        return Ok(Value::None);
+    }
+    let binary = |bytes: &[u8]| Value::Str(String::from_utf8_lossy(bytes).into_owned());
+    let decimal = |unscaled: f64, scale: i8| Value::from_float(unscaled / 10f64.powi(scale as i32));
+    let elements = |values: arrow_array::ArrayRef| {
+        (0..values.len())
+            .map(|j| array_value(values.as_ref(), j))
+            .collect::<Result<Vec<_>, _>>()
+            .map(Value::Array)
+    };
+    Ok(match array.data_type() {
+        DataType::Null => Value::None,
+        DataType::Boolean => Value::Bool(array.as_boolean().value(i)),
+        DataType::Int8 => Value::Int(array.as_primitive::<Int8Type>().value(i) as i64),
+        DataType::Int16 => Value::Int(array.as_primitive::<Int16Type>().value(i) as i64),
+        DataType::Int32 => Value::Int(array.as_primitive::<Int32Type>().value(i) as i64),
+        DataType::Int64 => Value::Int(array.as_primitive::<Int64Type>().value(i)),
+        DataType::UInt8 => Value::Int(array.as_primitive::<UInt8Type>().value(i) as i64),
+        DataType::UInt16 => Value::Int(array.as_primitive::<UInt16Type>().value(i) as i64),
+        DataType::UInt32 => Value::Int(array.as_primitive::<UInt32Type>().value(i) as i64),
+        DataType::UInt64 => {
+            let n = array.as_primitive::<UInt64Type>().value(i);
+            i64::try_from(n).map_or_else(|_| Value::from_float(n as f64), Value::Int)
+        }
+        DataType::Float16 => {
+            Value::from_float(array.as_primitive::<Float16Type>().value(i).to_f64())
+        }
+        DataType::Float32 => Value::from_float(array.as_primitive::<Float32Type>().value(i) as f64),
+        DataType::Float64 => Value::from_float(array.as_primitive::<Float64Type>().value(i)),
+        DataType::Utf8 => Value::Str(array.as_string::<i32>().value(i).to_string()),
+        DataType::LargeUtf8 => Value::Str(array.as_string::<i64>().value(i).to_string()),
+        DataType::Utf8View => Value::Str(array.as_string_view().value(i).to_string()),
+        DataType::Binary => binary(array.as_binary::<i32>().value(i)),
+        DataType::LargeBinary => binary(array.as_binary::<i64>().value(i)),
+        DataType::BinaryView => binary(array.as_binary_view().value(i)),
+        DataType::FixedSizeBinary(_) => binary(array.as_fixed_size_binary().value(i)),
+        DataType::Decimal128(_, scale) => decimal(
+            array.as_primitive::<Decimal128Type>().value(i) as f64,
+            *scale,
+        ),
+        DataType::Decimal256(_, scale) => decimal(
+            array
+                .as_primitive::<Decimal256Type>()
+                .value(i)
+                .to_string()
+                .parse()
+                .unwrap_or(f64::NAN),
+            *scale,
+        ),
+        DataType::Date32 => timestamp(
+            (array.as_primitive::<Date32Type>().value(i) as i64).saturating_mul(86_400),
+            1,
+        ),
+        DataType::Date64 => timestamp(array.as_primitive::<Date64Type>().value(i), 1_000),
+        DataType::Timestamp(TimeUnit::Second, _) => {
+            timestamp(array.as_primitive::<TimestampSecondType>().value(i), 1)
+        }
+        DataType::Timestamp(TimeUnit::Millisecond, _) => timestamp(
+            array.as_primitive::<TimestampMillisecondType>().value(i),
+            1_000,
+        ),
+        DataType::Timestamp(TimeUnit::Microsecond, _) => timestamp(
+            array.as_primitive::<TimestampMicrosecondType>().value(i),
+            1_000_000,
+        ),
+        DataType::Timestamp(TimeUnit::Nanosecond, _) => timestamp(
+            array.as_primitive::<TimestampNanosecondType>().value(i),
+            1_000_000_000,
+        ),
+        DataType::Time32(TimeUnit::Second) => {
+            duration(array.as_primitive::<Time32SecondType>().value(i) as i64, 1)
+        }
+        DataType::Time32(_) => duration(
+            array.as_primitive::<Time32MillisecondType>().value(i) as i64,
+            1_000,
+        ),
+        DataType::Time64(TimeUnit::Microsecond) => duration(
+            array.as_primitive::<Time64MicrosecondType>().value(i),
+            1_000_000,
+        ),
+        DataType::Time64(_) => duration(
+            array.as_primitive::<Time64NanosecondType>().value(i),
+            1_000_000_000,
+        ),
+        DataType::Duration(TimeUnit::Second) => {
+            duration(array.as_primitive::<DurationSecondType>().value(i), 1)
+        }
+        DataType::Duration(TimeUnit::Millisecond) => duration(
+            array.as_primitive::<DurationMillisecondType>().value(i),
+            1_000,
+        ),
+        DataType::Duration(TimeUnit::Microsecond) => duration(
+            array.as_primitive::<DurationMicrosecondType>().value(i),
+            1_000_000,
+        ),
+        DataType::Duration(TimeUnit::Nanosecond) => duration(
+            array.as_primitive::<DurationNanosecondType>().value(i),
+            1_000_000_000,
+        ),
+        DataType::List(_) => elements(array.as_list::<i32>().value(i))?,
+        DataType::LargeList(_) => elements(array.as_list::<i64>().value(i))?,
+        DataType::FixedSizeList(_, _) => elements(array.as_fixed_size_list().value(i))?,
+        DataType::Struct(_) => {
+            let fields = array.as_struct();
+            Value::Obj(
+                fields
+                    .column_names()
+                    .into_iter()
+                    .zip(fields.columns())
+                    .map(|(name, column)| Ok((name.to_string(), array_value(column.as_ref(), i)?)))
+                    .collect::<Result<_, String>>()?,
+            )
+        }
+        // A list of entries, which are structs of a key and a value.
+        DataType::Map(_, _) => {
+            let entries = array.as_map().value(i);
+            let (keys, values) = (entries.column(0), entries.column(1));
+            Value::Obj(
+                (0..entries.len())
+                    .map(|j| {
+                        let key = match array_value(keys.as_ref(), j)? {
+                            Value::Str(key) => key,
+                            key => key.to_string(),
+                        };
+                        Ok((key, array_value(values.as_ref(), j)?))
+                    })
+                    .collect::<Result<_, String>>()?,
+            )
+        }
+        DataType::Dictionary(_, _) => {
+            let dictionary = array.as_any_dictionary();
+            let values = dictionary.values();
+            match array_value(dictionary.keys(), i)? {
+                Value::Int(key) if key >= 0 && (key as usize) < values.len() => {
+                    array_value(values.as_ref(), key as usize)?
+                }
+                _ => return Err("a value isn't in its dictionary".to_string()),
+            }
+        }
+        other => return Err(format!("its type, {}, isn't supported", other)),
+    })
+}
+
+#[cfg(all(test, any(feature = "parquet", feature = "arrow")))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_keep_column_order() {
+        let rec = record(vec![
+            ("b".to_string(), Value::Int(1)),
+            ("a".to_string(), Value::None),
+            ("c".to_string(), Value::Str("x".to_string())),
+        ]);
+        assert_eq!(rec.raw, r#"{"b":1,"a":null,"c":"x"}"#);
+        assert_eq!(rec.data.get("a"), Some(&Value::None));
+    }
+
+    #[test]
+    fn converts_times() {
+        assert_eq!(
+            timestamp(-1_500, 1_000),
+            Value::DateTime(Utc.timestamp_opt(-2, 500_000_000).unwrap())
+        );
+        assert_eq!(
+            duration(90_000_001, 1_000_000),
+            Value::Duration(chrono::Duration::nanoseconds(90_000_001_000))
+        );
+        assert_eq!(timestamp(i64::MAX, 1), Value::None);
+    }
+}
@@ -14,6 +14,7 @@ use std::ops::{Add, Div, Mul, Sub};
 
 pub type VMap = HashMap<String, Value>;
 
+#[derive(Clone)]
 pub enum Row {
     Aggregate(Aggregate),
     Record(Record),
@@ -86,6 +87,32 @@ impl serde::Serialize for Record {
     }
 }
 
+/// The types a value can be explicitly converted to in a query, e.g. `fields status:int`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ValueType {
+    Int,
+    Float,
+    Str,
+    Bool,
+    /// The value encoded as a compact JSON string.
+    Json,
+}
+
+impl ValueType {
+    pub const NAMES: &'static [&'static str] = &["int", "float", "str", "bool", "json"];
+
+    pub fn from_name(name: &str) -> Option<ValueType> {
+        match name {
+            "int" => Some(ValueType::Int),
+            "float" => Some(ValueType::Float),
+            "str" => Some(ValueType::Str),
+            "bool" => Some(ValueType::Bool),
+            "json" => Some(ValueType::Json),
+            _ => None,
+        }
+    }
+}
+
 #[derive(Clone, Debug, PartialEq, Eq, Hash)]
 pub enum Value {
     Str(String),
@@ -113,8 +140,9 @@ impl serde::Serialize for Value {
             Value::DateTime(dt) => serializer.serialize_str(dt.to_rfc3339().as_str()),
             Value::Duration(d) => serializer.serialize_str(d.to_string().as_str()),
             Value::Obj(map) => {
+                // The keys are sorted so the output is deterministic.
                 let mut m = serializer.serialize_map(Some(map.len()))?;
-                for (k, v) in map {
+                for (k, v) in map.iter().sorted() {
                     m.serialize_entry(k, v)?;
                 }
                 m.end()
@@ -141,6 +169,13 @@ impl Ord for Value {
             (Value::Bool(l), Value::Bool(r)) => l.cmp(r),
             (Value::DateTime(l), Value::DateTime(r)) => l.cmp(r),
             (Value::Duration(l), Value::Duration(r)) => l.cmp(r),
+            // Numbers compared with durations are taken to be seconds
+            (Value::Duration(d), Value::Int(_) | Value::Float(_)) => {
+                OrderedFloat::from(duration_seconds(d)).cmp(&OrderedFloat::from(
+                    f64::try_from(other).expect("numbers convert to floats"),
+                ))
+            }
+            (Value::Int(_) | Value::Float(_), Value::Duration(_)) => other.cmp(self).reverse(),
             (Value::Obj(l), Value::Obj(r)) => l.cmp(r),
             // All these remaining cases aren't directly comparable
             (unrelated_l, unrelated_r) => unrelated_l.rank().cmp(&unrelated_r.rank()),
@@ -163,7 +198,14 @@ impl Display for Value {
             Value::Bool(ref s) => write!(f, "{}", s),
             Value::DateTime(ref dt) => write!(f, "{:?}", dt),
             Value::Duration(ref d) => write!(f, "{:?}", d),
-            Value::Obj(ref o) => write!(f, "{:?}", o),
+            Value::Obj(ref o) => write!(
+                f,
+                "{{{}}}",
+                o.iter()
+                    .sorted()
+                    .map(|(k, v)| format!("{:?}: {:?}", k, v))
+                    .join(", ")
+            ),
             Value::Array(ref o) => write!(f, "{:?}", o),
             Value::None => write!(f, "None"),
         }
@@ -180,7 +222,21 @@ impl Add for Value {
             (Value::Duration(ld), Value::Duration(rd)) => Ok(Value::Duration(ld.add(rd))),
             (Value::Float(lf), Value::Float(rf)) => Ok(Value::Float(lf + rf)),
             (Value::Int(li), Value::Int(ri)) => Ok(Value::Int(li + ri)),
-            (left, right) => left.binary_op(&f64::add, "+", &right),
+            // Strings that look like numbers are still added as numbers, anything else is
+            // concatenated.
+            (left, right) => {
+                left.binary_op(&f64::add, "+", &right)
+                    .or_else(|err| match (&left, &right) {
+                        (
+                            Value::Str(_),
+                            Value::Str(_) | Value::Int(_) | Value::Float(_) | Value::Bool(_),
+                        )
+                        | (Value::Int(_) | Value::Float(_) | Value::Bool(_), Value::Str(_)) => {
+                            Ok(Value::Str(format!("{}{}", left, right)))
+                        }
+                        _ => Err(err),
+                    })
+            }
         }
     }
 }
@@ -379,6 +435,44 @@ impl Value {
             .unwrap_or_else(|_| Value::Str(trimmed.into()))
     }
 
+    /// Convert this value to the given type, producing `Value::None` if the conversion isn't
+    /// possible.
+    pub fn coerce(&self, to: ValueType) -> Value {
+        match (to, self) {
+            (_, Value::None) => Value::None,
+            (ValueType::Int, Value::Int(i)) => Value::Int(*i),
+            (ValueType::Int, Value::Float(f)) => Value::Int(f.0.trunc() as i64),
+            (ValueType::Int, Value::Bool(b)) => Value::Int(*b as i64),
+            (ValueType::Int, Value::Str(s)) => s
+                .trim()
+                .parse::<i64>()
+                .map(Value::Int)
+                .unwrap_or(Value::None),
+            (ValueType::Float, Value::Int(i)) => Value::Float(OrderedFloat(*i as f64)),
+            (ValueType::Float, Value::Float(f)) => Value::Float(*f),
+            (ValueType::Float, Value::Str(s)) => s
+                .trim()
+                .parse::<f64>()
+                .map(|f| Value::Float(OrderedFloat(f)))
+                .unwrap_or(Value::None),
+            (ValueType::Str, Value::Str(s)) => Value::Str(s.clone()),
+            (ValueType::Str, Value::Float(f)) => Value::Str(f.to_string()),
+            (ValueType::Str, other) => Value::Str(other.render(&DisplayConfig::default())),
+            (ValueType::Bool, Value::Bool(b)) => Value::Bool(*b),
+            (ValueType::Bool, Value::Int(0)) => Value::Bool(false),
+            (ValueType::Bool, Value::Int(1)) => Value::Bool(true),
+            (ValueType::Bool, Value::Str(s)) => match s.trim().to_lowercase().as_str() {
+                "true" => Value::Bool(true),
+                "false" => Value::Bool(false),
+                _ => Value::None,
+            },
+            (ValueType::Json, other) => serde_json::to_string(other)
+                .map(Value::Str)
+                .unwrap_or(Value::None),
+            _ => Value::None,
+        }
+    }
+
     pub fn binary_op(
         &self,
         op_fn: &dyn Fn(f64, f64) -> f64,
@@ -405,6 +499,14 @@ impl From<f64> for Value {
     }
 }
 
+/// A duration as a number of seconds, which is how it's used as a number, e.g. in aggregates.
+fn duration_seconds(d: &Duration) -> f64 {
+    match d.num_microseconds() {
+        Some(us) => us as f64 / 1_000_000.0,
+        None => d.num_milliseconds() as f64 / 1000.0,
+    }
+}
+
 impl TryFrom<&Value> for f64 {
     type Error = EvalError;
 
@@ -414,6 +516,7 @@ impl TryFrom<&Value> for f64 {
             Value::Float(f) => Ok(f.0),
             Value::Str(s) => Value::aggressively_to_num(s),
             Value::DateTime(dt) => Ok(dt.timestamp_millis() as f64),
+            Value::Duration(d) => Ok(duration_seconds(d)),
             _ => Err(EvalError::ExpectedNumber {
                 found: format!("{}", value),
             }),
@@ -583,12 +686,37 @@ impl Record {
                     found: "function call".to_string(),
                 })
             }
+            #[cfg(feature = "wasm")]
+            Expr::Wasm { .. } => {
+                return Err(EvalError::ExpectedXYZ {
+                    expected: "valid expr".to_string(),
+                    found: "function call".to_string(),
+                })
+            }
             Expr::IfOp { .. } => {
                 return Err(EvalError::ExpectedXYZ {
                     expected: "valid expr".to_string(),
                     found: "if operator".to_string(),
                 })
             }
+            Expr::Coalesce(_) => {
+                return Err(EvalError::ExpectedXYZ {
+                    expected: "valid expr".to_string(),
+                    found: "coalesce".to_string(),
+                })
+            }
+            Expr::In { .. } => {
+                return Err(EvalError::ExpectedXYZ {
+                    expected: "valid expr".to_string(),
+                    found: "in expr".to_string(),
+                })
+            }
+            Expr::Match { .. } => {
+                return Err(EvalError::ExpectedXYZ {
+                    expected: "valid expr".to_string(),
+                    found: "regex match".to_string(),
+                })
+            }
             Expr::Value(_) => {
                 return Err(EvalError::ExpectedXYZ {
                     expected: "valid expr".to_string(),
@@ -646,6 +774,23 @@ mod tests {
     use maplit::hashmap;
     use std::str::FromStr;
 
+    #[test]
+    fn objects_are_deterministic() {
+        let obj = Value::Obj(
+            (0..20)
+                .map(|i| (format!("k{:02}", i), Value::Int(i)))
+                .collect::<HashMap<_, _>>()
+                .into(),
+        );
+        let keys: Vec<_> = (0..20).map(|i| format!("k{:02}", i)).collect();
+        let json = serde_json::to_string(&obj).unwrap();
+        let positions: Vec<_> = keys.iter().map(|k| json.find(k.as_str())).collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]), "{}", json);
+        let display = obj.to_string();
+        let positions: Vec<_> = keys.iter().map(|k| display.find(k.as_str())).collect();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]), "{}", display);
+    }
+
     #[test]
     fn render_duration() {
         let cfg = DisplayConfig { floating_points: 2 };
@@ -727,12 +872,54 @@ mod tests {
         assert_eq!(Value::from_string("abcd "), Value::Str("abcd".to_owned()));
     }
 
+    #[test]
+    fn coerce() {
+        assert_eq!(
+            Value::from_string("42").coerce(ValueType::Int),
+            Value::Int(42)
+        );
+        assert_eq!(Value::from_float(4.7).coerce(ValueType::Int), Value::Int(4));
+        assert_eq!(
+            Value::Str("nope".to_string()).coerce(ValueType::Int),
+            Value::None
+        );
+        assert_eq!(
+            Value::Int(3).coerce(ValueType::Float),
+            Value::Float(OrderedFloat(3.0))
+        );
+        assert_eq!(
+            Value::Int(200).coerce(ValueType::Str),
+            Value::Str("200".to_string())
+        );
+        assert_eq!(
+            Value::Str("TRUE".to_string()).coerce(ValueType::Bool),
+            Value::Bool(true)
+        );
+        assert_eq!(Value::Int(7).coerce(ValueType::Bool), Value::None);
+        assert_eq!(Value::None.coerce(ValueType::Str), Value::None);
+        assert_eq!(
+            Value::Obj(hashmap! { "a".to_string() => Value::Int(1) }.into())
+                .coerce(ValueType::Json),
+            Value::Str(r#"{"a":1}"#.to_string())
+        );
+        assert_eq!(
+            Value::Array(vec![Value::Str("x".to_string()), Value::None]).coerce(ValueType::Json),
+            Value::Str(r#"["x",null]"#.to_string())
+        );
+    }
+
     #[test]
     fn value_ordering() {
         assert_eq!(
             Value::from_string("hello").cmp(&Value::Int(0)),
             Ordering::Greater
         );
+        // Durations are compared with numbers as seconds
+        let minute = Value::Duration(Duration::minutes(1));
+        assert!(minute > Value::Int(59));
+        assert!(minute < Value::from_float(60.5));
+        assert!(Value::Int(61) > minute);
+        assert_eq!(f64::try_from(&minute), Ok(60.0));
     }
 
     #[test]
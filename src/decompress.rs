@@ -0,0 +1,133 @@
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+use bzip2::read::MultiBzDecoder;
+use flate2::read::MultiGzDecoder;
+
+/// A compression format that input files are transparently decompressed from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Compression {
+    Gzip,
+    Zstd,
+    Bzip2,
+}
+
+impl Compression {
+    /// Detect the format from the magic bytes at the start of a file.  These are checked rather
+    /// than the extension so that, e.g., rotated logs like `app.log.2.gz` and files that were
+    /// renamed after being compressed are both handled.
+    fn detect(magic: &[u8]) -> Option<Compression> {
+        if magic.starts_with(&[0x1f, 0x8b]) {
+            Some(Compression::Gzip)
+        } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+            Some(Compression::Zstd)
+        } else if magic.starts_with(b"BZh") {
+            Some(Compression::Bzip2)
+        } else {
+            None
+        }
+    }
+}
+
+/// Open a file for reading, decompressing it on the fly if it's gzip, zstd, or bzip2
+/// compressed.  A file of several compressed streams one after another, like a log that was
+/// appended to with `gzip >>`, is read to the end of the last one.
+pub fn open(path: &Path) -> io::Result<Box<dyn Read + Send>> {
+    let mut file = File::open(path)?;
+    let mut magic = Vec::with_capacity(4);
+    (&mut file).take(4).read_to_end(&mut magic)?;
+    let compression = Compression::detect(&magic);
+    let input = io::Cursor::new(magic).chain(file);
+    let decoder: Box<dyn Read + Send> = match compression {
+        None => return Ok(Box::new(input)),
+        Some(Compression::Gzip) => Box::new(MultiGzDecoder::new(input)),
+        Some(Compression::Zstd) => Box::new(zstd::stream::read::Decoder::new(input)?),
+        Some(Compression::Bzip2) => Box::new(MultiBzDecoder::new(input)),
+    };
+    Ok(Box::new(Decompressor {
+        path: path.display().to_string(),
+        decoder,
+    }))
+}
+
+/// The decompressed contents of a file.  An error, e.g. because the file is corrupt, says which
+/// file it's in.
+struct Decompressor {
+    path: String,
+    decoder: Box<dyn Read + Send>,
+}
+
+impl Read for Decompressor {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.decoder.read(buf).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("could not decompress {}: {}", self.path, e),
+            )
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_compression() {
+        assert_eq!(
+            Compression::detect(&[0x1f, 0x8b, 8, 0]),
+            Some(Compression::Gzip)
+        );
+        assert_eq!(
+            Compression::detect(&[0x28, 0xb5, 0x2f, 0xfd]),
+            Some(Compression::Zstd)
+        );
+        assert_eq!(Compression::detect(b"BZh9"), Some(Compression::Bzip2));
+        assert_eq!(Compression::detect(b"plain"), None);
+        assert_eq!(Compression::detect(b""), None);
+    }
+
+    fn read(path: &str) -> io::Result<Vec<u8>> {
+        let mut out = vec![];
+        open(Path::new(path))?.read_to_end(&mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn decompresses_files() {
+        let plain = read("test_files/test_json.log").unwrap();
+        for path in [
+            "test_files/test_json.log.gz",
+            "test_files/test_json.log.zst",
+            "test_files/test_json.log.bz2",
+        ] {
+            assert_eq!(read(path).unwrap(), plain, "{}", path);
+        }
+    }
+
+    #[test]
+    fn reads_every_stream() {
+        let path = std::env::temp_dir().join(format!("agrind-concat-{}.gz", std::process::id()));
+        let gz = std::fs::read("test_files/test_json.log.gz").unwrap();
+        std::fs::write(&path, [gz.clone(), gz].concat()).unwrap();
+        let read = read(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        let plain = std::fs::read("test_files/test_json.log").unwrap();
+        assert_eq!(read.unwrap(), [plain.clone(), plain].concat());
+    }
+
+    #[test]
+    fn reports_corrupt_files() {
+        let path = std::env::temp_dir().join(format!("agrind-corrupt-{}.gz", std::process::id()));
+        let mut gz = std::fs::read("test_files/test_json.log.gz").unwrap();
+        gz.truncate(gz.len() / 2);
+        std::fs::write(&path, gz).unwrap();
+        let read = read(path.to_str().unwrap());
+        std::fs::remove_file(&path).unwrap();
+        assert!(read
+            .unwrap_err()
+            .to_string()
+            .contains("could not decompress"));
+    }
+}
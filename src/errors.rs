@@ -1,6 +1,7 @@
 use crate::alias::AliasCollection;
 use crate::lang::{query, Positioned, Query};
 use crate::pipeline::CompileError;
+use crate::plugin::Plugins;
 use annotate_snippets::snippet::{Annotation, AnnotationType, Slice, Snippet, SourceAnnotation};
 use std::env;
 use std::io::IsTerminal;
@@ -14,6 +15,9 @@ pub struct QueryContainer<'a> {
     pub reporter: Box<dyn ErrorReporter>,
     pub error_count: AtomicUsize,
     pub aliases: AliasCollection<'a>,
+    pub plugins: Plugins,
+    /// Whether `#` starts a comment, as it does in a query file.
+    pub comments: bool,
 }
 
 /// Trait that can be used to report errors by the parser and other layers.
@@ -21,6 +25,9 @@ pub trait ErrorBuilder {
     /// Create a SnippetBuilder for the given error
     fn report_error_for<E: ToString>(&self, error: E) -> SnippetBuilder;
 
+    /// Create a SnippetBuilder for a warning, which doesn't count as an error
+    fn report_warning_for<W: ToString>(&self, warning: W) -> SnippetBuilder<'_>;
+
     fn get_error_count(&self) -> usize;
 }
 
@@ -35,8 +42,22 @@ impl<'a> QueryContainer<'a> {
             reporter,
             error_count: AtomicUsize::new(0),
             aliases,
+            plugins: Plugins::default(),
+            comments: false,
         }
     }
+
+    /// Make the operators of the plugins available to the query.
+    pub fn with_plugins(mut self, plugins: Plugins) -> Self {
+        self.plugins = plugins;
+        self
+    }
+
+    /// Treat `#` as the start of a comment, for a query read from a file.
+    pub fn with_comments(mut self) -> Self {
+        self.comments = true;
+        self
+    }
 }
 
 impl QueryContainer<'static> {
@@ -79,6 +100,18 @@ impl ErrorBuilder for QueryContainer<'_> {
         }
     }
 
+    fn report_warning_for<W: ToString>(&self, warning: W) -> SnippetBuilder<'_> {
+        SnippetBuilder {
+            query: self,
+            data: SnippetData {
+                error: warning.to_string(),
+                source: self.query.to_string(),
+                is_warning: true,
+                ..Default::default()
+            },
+        }
+    }
+
     fn get_error_count(&self) -> usize {
         self.error_count.load(Ordering::Relaxed)
     }
@@ -87,7 +120,7 @@ impl ErrorBuilder for QueryContainer<'_> {
 pub fn did_you_mean<'a>(input: &str, choices: impl Iterator<Item = &'a str>) -> Option<String> {
     let similarities = choices.map(|choice| (choice, normalized_levenshtein(choice, input)));
     let mut candidates: Vec<_> = similarities.filter(|(_op, score)| *score > 0.6).collect();
-    candidates.sort_by_key(|(_op, score)| (score * 100_f64) as u16);
+    candidates.sort_by_key(|(_op, score)| std::cmp::Reverse((score * 100_f64) as u16));
     candidates.first().map(|(choice, _scoe)| choice.to_string())
 }
 
@@ -97,10 +130,24 @@ pub trait ErrorReporter {
 }
 
 /// An ErrorReporter that writes errors related to the query string to the terminal
-pub struct TermErrorReporter {}
+#[derive(Default)]
+pub struct TermErrorReporter {
+    /// Drop warnings and only write errors.
+    pub quiet: bool,
+}
 
 impl ErrorReporter for TermErrorReporter {
     fn handle_error(&self, mut snippet: Snippet) {
+        let is_warning = matches!(
+            snippet.title,
+            Some(Annotation {
+                annotation_type: AnnotationType::Warning,
+                ..
+            })
+        );
+        if self.quiet && is_warning {
+            return;
+        }
         snippet.opt.color = env::var("NO_COLOR").is_err() && std::io::stderr().is_terminal();
         let dl = annotate_snippets::display_list::DisplayList::from(snippet);
 
@@ -115,6 +162,7 @@ pub struct SnippetData {
     source: String,
     annotations: Vec<((usize, usize), String)>,
     resolution: Vec<String>,
+    is_warning: bool,
 }
 
 #[must_use = "the send_report() method must eventually be called for this builder"]
@@ -150,11 +198,16 @@ impl SnippetBuilder<'_> {
 
     /// Build and send the Snippet to the ErrorReporter in the QueryContainer.
     pub fn send_report(self) {
+        let annotation_type = if self.data.is_warning {
+            AnnotationType::Warning
+        } else {
+            AnnotationType::Error
+        };
         self.query.reporter.handle_error(Snippet {
             title: Some(Annotation {
                 label: Some(self.data.error.as_str()),
                 id: None,
-                annotation_type: AnnotationType::Error,
+                annotation_type,
             }),
             slices: vec![Slice {
                 source: self.data.source.as_str(),
@@ -168,7 +221,7 @@ impl SnippetBuilder<'_> {
                     .map(|anno| SourceAnnotation {
                         range: anno.0,
                         label: anno.1.as_str(),
-                        annotation_type: AnnotationType::Error,
+                        annotation_type,
                     })
                     .collect(),
             }],
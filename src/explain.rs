@@ -0,0 +1,1229 @@
+//! Renders a parsed query back into query syntax, with aliases expanded, for `--explain`, along
+//! with a plan of what each stage of the pipeline reads, writes and waits for.
+use crate::data::{Value, ValueType};
+use crate::lang::*;
+use crate::operator::fields;
+use crate::pipeline::{referenced_fields, KnownFields};
+use itertools::Itertools;
+use std::fmt::{self, Display, Formatter, Write};
+
+/// Render the search and each operator of the query on its own line.  The operators of an alias
+/// are written in its place, as though they had been typed into the query.
+pub fn explain(query: &Query) -> String {
+    let mut out = query.search.to_string();
+    for op in flatten(&query.operators) {
+        write!(out, "\n| {}", op).unwrap();
+    }
+    out
+}
+
+/// Describe each stage of the pipeline: the alias it came from, whether it streams rows through
+/// or waits for the end of the input, the fields it reads, and what it does to the fields.
+pub fn plan(query: &Query) -> String {
+    let mut out = String::new();
+    let mut blocked_by = None;
+    for (i, (op, alias)) in with_aliases(&query.operators, None).into_iter().enumerate() {
+        let stage = i + 1;
+        write!(out, "{}. {}\n   ", stage, op).unwrap();
+        if let Some(alias) = alias {
+            write!(out, "from alias `{}`, ", alias).unwrap();
+        }
+        let blocking = blocking(op);
+        match blocked_by {
+            Some(earlier) => write!(out, "runs on the output of stage {}", earlier).unwrap(),
+            None if blocking => out.push_str("blocking, waits for the end of the input"),
+            None => out.push_str("streaming"),
+        }
+        if blocking && blocked_by.is_none() {
+            blocked_by = Some(stage);
+        }
+        let reads = reads(op);
+        if !reads.is_empty() {
+            write!(out, "\n   reads: {}", reads.join(", ")).unwrap();
+        }
+        if let Some(writes) = writes(op) {
+            write!(out, "\n   {}", writes).unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Whether the operator waits for the end of the input before it outputs anything.  Branches wait
+/// if any of their operators do.
+fn blocking(op: &Operator) -> bool {
+    match op {
+        Operator::MultiAggregate(_) | Operator::Sort(_) | Operator::Top(_) => true,
+        Operator::Inline(Positioned {
+            value: InlineOperator::Limit { count: Some(count) },
+            ..
+        }) => count.value < 0.0,
+        Operator::Branch(branch) => branch.branches.iter().flatten().any(blocking),
+        _ => false,
+    }
+}
+
+/// The operators with the alias that each one was expanded from, if any.  An alias used inside
+/// another alias is reported as the outer one, since that's the one in the query.
+fn with_aliases<'a>(
+    operators: &'a [Operator],
+    alias: Option<&'a str>,
+) -> Vec<(&'a Operator, Option<&'a str>)> {
+    operators
+        .iter()
+        .flat_map(|op| match op {
+            Operator::RenderedAlias { keyword, operators } => {
+                with_aliases(operators, alias.or(Some(keyword)))
+            }
+            op => vec![(op, alias)],
+        })
+        .collect()
+}
+
+/// The fields the operator reads, with `(line)` for the raw line.
+fn reads(op: &Operator) -> Vec<String> {
+    /// Parsers read the raw line unless they're given a field to read `from`.
+    fn line_or(column: Option<&Expr>) -> (Vec<&Expr>, bool) {
+        (column.into_iter().collect(), column.is_none())
+    }
+    let (exprs, reads_line): (Vec<&Expr>, bool) = match op {
+        Operator::Inline(Positioned { value, .. }) => match value {
+            InlineOperator::Json { input_column, .. }
+            | InlineOperator::Logfmt { input_column, .. }
+            | InlineOperator::Csv { input_column, .. }
+            | InlineOperator::Syslog { input_column, .. }
+            | InlineOperator::AccessLog { input_column, .. }
+            | InlineOperator::UserAgent { input_column }
+            | InlineOperator::Url { input_column, .. }
+            | InlineOperator::Split { input_column, .. } => line_or(input_column.as_ref()),
+            InlineOperator::Parse { input_column, .. } => line_or(
+                input_column
+                    .0
+                    .as_ref()
+                    .or(input_column.1.as_ref())
+                    .map(|column| &column.value),
+            ),
+            InlineOperator::Where { expr } => {
+                (expr.iter().map(|expr| &expr.value).collect(), false)
+            }
+            InlineOperator::Explode { input_column, .. }
+            | InlineOperator::Timeslice { input_column, .. }
+            | InlineOperator::Total { input_column, .. }
+            | InlineOperator::GeoIp { input_column } => (vec![input_column], false),
+            InlineOperator::Dedup { key_cols, ttl, .. } => (
+                key_cols
+                    .iter()
+                    .chain(ttl.as_ref().map(|(_, expr)| expr))
+                    .collect(),
+                false,
+            ),
+            InlineOperator::Transaction {
+                key_cols,
+                timestamp,
+                ..
+            } => (key_cols.iter().chain(Some(timestamp)).collect(), false),
+            InlineOperator::FieldExpression { value, .. } => (vec![value], false),
+            InlineOperator::Join { keys, .. } => (keys.iter().collect(), false),
+            InlineOperator::Lookup { key, .. } => (vec![key], false),
+            InlineOperator::Wasm { .. } | InlineOperator::Script { .. } => (vec![], true),
+            InlineOperator::Fields { fields, .. } => {
+                return fields.iter().map(|f| field_name(f)).collect()
+            }
+            InlineOperator::Rename {
+                pattern: RenamePattern::Field(field),
+                ..
+            } => return vec![ident(field)],
+            InlineOperator::Rename { .. }
+            | InlineOperator::Limit { .. }
+            | InlineOperator::Sample { .. } => (vec![], false),
+        },
+        Operator::MultiAggregate(agg) => (
+            agg.key_cols
+                .iter()
+                .chain(
+                    agg.aggregate_functions
+                        .iter()
+                        .flat_map(|(_, func)| aggregate_args(&func.value)),
+                )
+                .collect(),
+            false,
+        ),
+        Operator::Sort(sort) => (sort.sort_cols.iter().collect(), false),
+        Operator::Top(top) => (top.key_cols.iter().collect(), false),
+        Operator::Branch(branch) => {
+            return branch
+                .branches
+                .iter()
+                .flatten()
+                .flat_map(reads)
+                .unique()
+                .collect()
+        }
+        // What a plugin's operator reads is up to the plugin.
+        Operator::RenderedAlias { .. } | Operator::Plugin(_) | Operator::Error => (vec![], false),
+    };
+    let mut fields = vec![];
+    exprs
+        .into_iter()
+        .for_each(|expr| referenced_fields(expr, &mut fields));
+    reads_line
+        .then(|| "(line)".to_string())
+        .into_iter()
+        .chain(fields.into_iter().unique().map(ident))
+        .collect()
+}
+
+fn aggregate_args(func: &AggregateFunction) -> Vec<&Expr> {
+    match func {
+        AggregateFunction::Count { condition } => condition.iter().collect(),
+        AggregateFunction::Sum { column }
+        | AggregateFunction::Min { column }
+        | AggregateFunction::Average { column }
+        | AggregateFunction::Max { column }
+        | AggregateFunction::First { column }
+        | AggregateFunction::Last { column }
+        | AggregateFunction::Percentile { column, .. }
+        | AggregateFunction::StdDev { column }
+        | AggregateFunction::Variance { column }
+        | AggregateFunction::Median { column }
+        | AggregateFunction::Mode { column } => vec![column],
+        AggregateFunction::Earliest { column, by } | AggregateFunction::Latest { column, by } => {
+            vec![column, by]
+        }
+        AggregateFunction::SumIf { args }
+        | AggregateFunction::MinBy { args }
+        | AggregateFunction::MaxBy { args }
+        | AggregateFunction::Values { args }
+        | AggregateFunction::CountDistinct { column: args, .. } => {
+            args.iter().flat_map(|args| args.value.iter()).collect()
+        }
+        AggregateFunction::Error => vec![],
+    }
+}
+
+/// What the operator does to the fields of a row, if anything.
+fn writes(op: &Operator) -> Option<String> {
+    let list = |fields: &[String]| fields.iter().map(|f| field_name(f)).join(", ");
+    if let Operator::Inline(Positioned { value, .. }) = op {
+        match value {
+            InlineOperator::Fields {
+                mode: FieldMode::Only,
+                fields,
+                ..
+            } => return Some(format!("keeps only: {}", list(fields))),
+            InlineOperator::Fields {
+                mode: FieldMode::Except,
+                fields,
+                ..
+            } => return Some(format!("drops: {}", list(fields))),
+            InlineOperator::Rename {
+                pattern: RenamePattern::Field(field),
+                replacement,
+            } => {
+                return Some(format!(
+                    "renames: {} to {}",
+                    ident(field),
+                    ident(replacement)
+                ))
+            }
+            _ => {}
+        }
+    }
+    let mut known = KnownFields::empty();
+    known.apply(op);
+    match known {
+        KnownFields::Any => Some("writes: any fields, depending on the input".to_string()),
+        KnownFields::Only { fields, .. } if fields.is_empty() => None,
+        KnownFields::Only { fields, .. } => {
+            let fields = fields.into_iter().sorted().collect::<Vec<_>>();
+            // Aggregates and transactions output new rows with only their own fields.
+            let replaces = matches!(
+                op,
+                Operator::MultiAggregate(_)
+                    | Operator::Top(_)
+                    | Operator::Inline(Positioned {
+                        value: InlineOperator::Transaction { .. },
+                        ..
+                    })
+            );
+            if replaces {
+                Some(format!("outputs only: {}", list(&fields)))
+            } else {
+                Some(format!("writes: {}", list(&fields)))
+            }
+        }
+    }
+}
+
+fn flatten(operators: &[Operator]) -> Vec<&Operator> {
+    operators
+        .iter()
+        .flat_map(|op| match op {
+            Operator::RenderedAlias { operators, .. } => flatten(operators),
+            op => vec![op],
+        })
+        .collect()
+}
+
+/// Write a string literal that parses back into the same string.
+fn quoted(s: &str) -> String {
+    let mut out = String::from("\"");
+    for ch in s.chars() {
+        match ch {
+            '\\' => out.push_str("\\\\"),
+            '"' => out.push_str("\\\""),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\0' => out.push_str("\\0"),
+            ch => out.push(ch),
+        }
+    }
+    out.push('"');
+    out
+}
+
+/// Write a field name, bracketing it when it isn't a plain identifier.
+fn ident(name: &str) -> String {
+    let mut chars = name.chars();
+    let is_bare = chars
+        .next()
+        .is_some_and(|ch| ch.is_ascii_alphabetic() || ch == '_')
+        && chars.all(|ch| ch.is_ascii_alphanumeric() || ch == '_')
+        && !matches!(name, "true" | "false" | "null");
+    if is_bare {
+        name.to_string()
+    } else {
+        format!("[{}]", quoted(name))
+    }
+}
+
+/// A field given to `fields`, which is left bare if it's a pattern like `request_*`.
+fn field_name(name: &str) -> String {
+    let bare = name
+        .chars()
+        .all(|ch| ch.is_ascii_alphanumeric() || matches!(ch, '_' | '.' | '*'));
+    if fields::is_pattern(name) && bare {
+        name.to_string()
+    } else {
+        ident(name)
+    }
+}
+
+fn duration(d: &chrono::Duration) -> String {
+    const UNITS: &[(&str, i64)] = &[
+        ("w", 7 * 24 * 60 * 60 * 1_000_000_000),
+        ("d", 24 * 60 * 60 * 1_000_000_000),
+        ("h", 60 * 60 * 1_000_000_000),
+        ("m", 60 * 1_000_000_000),
+        ("s", 1_000_000_000),
+        ("ms", 1_000_000),
+        ("us", 1_000),
+        ("ns", 1),
+    ];
+    let mut remaining = match d.num_nanoseconds() {
+        Some(0) => return "0s".to_string(),
+        Some(nanos) => nanos,
+        // Too long to count in nanoseconds, so it's a whole number of seconds at this size.
+        None => return format!("{}s", d.num_seconds()),
+    };
+    let mut out = String::new();
+    for (unit, size) in UNITS {
+        let amount = remaining / size;
+        if amount != 0 {
+            write!(out, "{}{}", amount, unit).unwrap();
+            remaining -= amount * size;
+        }
+    }
+    out
+}
+
+fn value_type(tpe: &ValueType) -> &'static str {
+    match tpe {
+        ValueType::Int => "int",
+        ValueType::Float => "float",
+        ValueType::Str => "str",
+        ValueType::Bool => "bool",
+        ValueType::Json => "json",
+    }
+}
+
+/// Write a list of field names with the types they're converted to, e.g. `a, b:int`.
+fn typed_fields(fields: &[String], casts: &[(String, ValueType)]) -> String {
+    fields
+        .iter()
+        .map(|name| match casts.iter().find(|(cast, _)| cast == name) {
+            Some((_, tpe)) => format!("{}:{}", field_name(name), value_type(tpe)),
+            None => field_name(name),
+        })
+        .join(", ")
+}
+
+/// Write an aggregate whose argument list is optional, e.g. `count_distinct(a)`.
+fn optional_args(name: &str, args: &Option<Positioned<Vec<Expr>>>) -> String {
+    match args {
+        Some(args) => format!("{}({})", name, args.value.iter().join(", ")),
+        None => name.to_string(),
+    }
+}
+
+impl Display for Search {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Search::And(terms) if terms.is_empty() => write!(f, "*"),
+            Search::And(terms) => write!(f, "{}", terms.iter().map(nested_search).join(" ")),
+            Search::Or(terms) => write!(f, "{}", terms.iter().map(nested_search).join(" OR ")),
+            Search::Not(term) => write!(f, "NOT {}", nested_search(term)),
+            Search::Keyword(keyword) if keyword.is_exact() => {
+                write!(f, "{}", quoted(keyword.as_str()))
+            }
+            Search::Keyword(keyword) => write!(f, "{}", keyword.as_str()),
+        }
+    }
+}
+
+fn nested_search(search: &Search) -> String {
+    match search {
+        Search::And(terms) | Search::Or(terms) if terms.len() > 1 => format!("({})", search),
+        search => search.to_string(),
+    }
+}
+
+impl Display for DataAccessAtom {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            DataAccessAtom::Key(key) => write!(f, ".{}", ident(key)),
+            DataAccessAtom::Index(index) => write!(f, "[{}]", index),
+        }
+    }
+}
+
+impl Display for BinaryOp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let op = match self {
+            BinaryOp::Comparison(ComparisonOp::Eq) => "==",
+            BinaryOp::Comparison(ComparisonOp::Neq) => "!=",
+            BinaryOp::Comparison(ComparisonOp::Gt) => ">",
+            BinaryOp::Comparison(ComparisonOp::Lt) => "<",
+            BinaryOp::Comparison(ComparisonOp::Gte) => ">=",
+            BinaryOp::Comparison(ComparisonOp::Lte) => "<=",
+            BinaryOp::Arithmetic(ArithmeticOp::Add) => "+",
+            BinaryOp::Arithmetic(ArithmeticOp::Subtract) => "-",
+            BinaryOp::Arithmetic(ArithmeticOp::Multiply) => "*",
+            BinaryOp::Arithmetic(ArithmeticOp::Divide) => "/",
+            BinaryOp::Logical(LogicalOp::And) => "and",
+            BinaryOp::Logical(LogicalOp::Or) => "or",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+impl BinaryOp {
+    /// How tightly the operator binds, matching the order the parser handles them in.
+    fn precedence(&self) -> u8 {
+        match self {
+            BinaryOp::Logical(LogicalOp::Or) => 1,
+            BinaryOp::Logical(LogicalOp::And) => 2,
+            BinaryOp::Comparison(_) => 3,
+            BinaryOp::Arithmetic(ArithmeticOp::Add | ArithmeticOp::Subtract) => 4,
+            BinaryOp::Arithmetic(ArithmeticOp::Multiply | ArithmeticOp::Divide) => 5,
+        }
+    }
+}
+
+/// Write an operand of a binary operator, adding parentheses when it binds more loosely than
+/// the operator.  All of the operators group to the left and comparisons can't be chained, so
+/// an operand on the right, or either operand of a comparison, needs them at the same level too.
+fn operand(expr: &Expr, parent: &BinaryOp, is_right: bool) -> String {
+    let precedence = match expr {
+        Expr::Binary { op, .. } => op.precedence(),
+        // `in` and `=~` are parsed in place of a comparison.
+        Expr::In { .. } | Expr::Match { .. } => BinaryOp::Comparison(ComparisonOp::Eq).precedence(),
+        expr => return expr.to_string(),
+    };
+    if precedence < parent.precedence()
+        || (precedence == parent.precedence()
+            && (is_right || matches!(parent, BinaryOp::Comparison(_))))
+    {
+        format!("({})", expr)
+    } else {
+        expr.to_string()
+    }
+}
+
+impl Display for Expr {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Expr::Column {
+                head: DataAccessAtom::Key(key),
+                rest,
+            } => write!(f, "{}{}", ident(key), rest.iter().join("")),
+            Expr::Column { head, rest } => write!(f, "{}{}", head, rest.iter().join("")),
+            Expr::Unary {
+                op: UnaryOp::Not,
+                operand,
+            } => match operand.as_ref() {
+                operand @ (Expr::Binary { .. } | Expr::In { .. } | Expr::Match { .. }) => {
+                    write!(f, "!({})", operand)
+                }
+                operand => write!(f, "!{}", operand),
+            },
+            Expr::Binary { op, left, right } => write!(
+                f,
+                "{} {} {}",
+                operand(left, op, false),
+                op,
+                operand(right, op, true)
+            ),
+            Expr::FunctionCall { name, args } => write!(f, "{}({})", name, args.iter().join(", ")),
+            Expr::IfOp {
+                cond,
+                value_if_true,
+                value_if_false,
+            } => write!(f, "if({}, {}, {})", cond, value_if_true, value_if_false),
+            Expr::In { value, list } => write!(
+                f,
+                "{} in ({})",
+                operand(value, &BinaryOp::Comparison(ComparisonOp::Eq), false),
+                list.iter().join(", ")
+            ),
+            Expr::Match { value, regex } => write!(
+                f,
+                "{} =~ /{}/",
+                operand(value, &BinaryOp::Comparison(ComparisonOp::Eq), false),
+                regex.replace('/', "\\/")
+            ),
+            Expr::Value(Value::Str(s)) => write!(f, "{}", quoted(s)),
+            Expr::Value(Value::Float(n)) => write!(f, "{:?}", n.0),
+            Expr::Value(Value::Duration(d)) => write!(f, "{}", duration(d)),
+            Expr::Value(Value::None) => write!(f, "null"),
+            Expr::Value(value) => write!(f, "{}", value),
+            Expr::Error => write!(f, "<error>"),
+        }
+    }
+}
+
+impl Display for AggregateFunction {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            AggregateFunction::Count { condition: None } => write!(f, "count"),
+            AggregateFunction::Count {
+                condition: Some(condition),
+            } => write!(f, "count({})", condition),
+            AggregateFunction::Sum { column } => write!(f, "sum({})", column),
+            AggregateFunction::SumIf { args } => write!(f, "{}", optional_args("sum_if", args)),
+            AggregateFunction::Min { column } => write!(f, "min({})", column),
+            AggregateFunction::Average { column } => write!(f, "average({})", column),
+            AggregateFunction::Max { column } => write!(f, "max({})", column),
+            AggregateFunction::MinBy { args } => write!(f, "{}", optional_args("min_by", args)),
+            AggregateFunction::MaxBy { args } => write!(f, "{}", optional_args("max_by", args)),
+            AggregateFunction::First { column } => write!(f, "first({})", column),
+            AggregateFunction::Last { column } => write!(f, "last({})", column),
+            AggregateFunction::Earliest { column, by } => {
+                write!(f, "earliest({} by {})", column, by)
+            }
+            AggregateFunction::Latest { column, by } => write!(f, "latest({} by {})", column, by),
+            AggregateFunction::Percentile {
+                percentile_str,
+                column,
+                ..
+            } if percentile_str.contains('.') => write!(f, "pct({}, {})", column, percentile_str),
+            AggregateFunction::Percentile {
+                percentile_str,
+                column,
+                ..
+            } => write!(f, "p{}({})", percentile_str, column),
+            AggregateFunction::StdDev { column } => write!(f, "stddev({})", column),
+            AggregateFunction::Variance { column } => write!(f, "variance({})", column),
+            AggregateFunction::Median { column } => write!(f, "median({})", column),
+            AggregateFunction::Mode { column } => write!(f, "mode({})", column),
+            AggregateFunction::Values { args } => write!(f, "{}", optional_args("values", args)),
+            AggregateFunction::CountDistinct {
+                column,
+                approx: false,
+            } => write!(f, "{}", optional_args("count_distinct", column)),
+            AggregateFunction::CountDistinct {
+                column,
+                approx: true,
+            } => write!(f, "{}", optional_args("count_distinct_approx", column)),
+            AggregateFunction::Error => write!(f, "<error>"),
+        }
+    }
+}
+
+impl Display for InlineOperator {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            InlineOperator::Json {
+                input_column,
+                mode,
+                duplicates,
+                warn_duplicates,
+            } => {
+                write!(f, "json")?;
+                match mode {
+                    JsonMode::Fields => {}
+                    JsonMode::Flatten => write!(f, " flatten")?,
+                    JsonMode::Paths(paths) => write!(
+                        f,
+                        " path {}",
+                        paths
+                            .iter()
+                            .map(|path| format!("{} as {}", quoted(&path.path), ident(&path.name)))
+                            .join(", ")
+                    )?,
+                }
+                if let Some(input_column) = input_column {
+                    write!(f, " from {}", input_column)?;
+                }
+                match duplicates {
+                    DuplicateKeys::First => write!(f, " duplicates=first")?,
+                    DuplicateKeys::Last => {}
+                    DuplicateKeys::Array => write!(f, " duplicates=array")?,
+                }
+                if *warn_duplicates {
+                    write!(f, " warn")?;
+                }
+                Ok(())
+            }
+            InlineOperator::Logfmt {
+                input_column,
+                strict,
+                keys,
+                duplicates,
+            } => {
+                write!(f, "logfmt")?;
+                if let Some(input_column) = input_column {
+                    write!(f, " from {}", input_column)?;
+                }
+                if *strict {
+                    write!(f, " strict=true")?;
+                }
+                if !keys.is_empty() {
+                    write!(
+                        f,
+                        " keys=({})",
+                        keys.iter().map(|key| ident(key)).join(", ")
+                    )?;
+                }
+                match duplicates {
+                    DuplicateKeys::First => write!(f, " duplicates=first")?,
+                    DuplicateKeys::Last => {}
+                    DuplicateKeys::Array => write!(f, " duplicates=array")?,
+                }
+                Ok(())
+            }
+            InlineOperator::Parse {
+                pattern,
+                fields,
+                input_column,
+                no_drop,
+                no_convert,
+                casts,
+            } => {
+                if pattern.is_regex() {
+                    write!(f, "parse regex {}", quoted(pattern.as_str()))?;
+                } else if pattern.is_grok() {
+                    write!(f, "parse grok {}", quoted(pattern.as_str()))?;
+                } else {
+                    write!(f, "parse {}", quoted(pattern.as_str()))?;
+                }
+                if let (Some(from), _) | (None, Some(from)) = input_column {
+                    write!(f, " from {}", from.value)?;
+                }
+                if !pattern.is_regex() && !pattern.is_grok() {
+                    write!(f, " as {}", typed_fields(fields, casts))?;
+                }
+                if *no_drop {
+                    write!(f, " nodrop")?;
+                }
+                if *no_convert {
+                    write!(f, " noconvert")?;
+                }
+                Ok(())
+            }
+            InlineOperator::Csv {
+                fields,
+                casts,
+                input_column,
+                headers,
+                delimiter,
+                no_drop,
+            } => {
+                write!(f, "parse csv")?;
+                if let Some(input_column) = input_column {
+                    write!(f, " from {}", input_column)?;
+                }
+                if !fields.is_empty() {
+                    write!(f, " as {}", typed_fields(fields, casts))?;
+                }
+                if *headers {
+                    write!(f, " headers=true")?;
+                }
+                if *delimiter != ',' {
+                    write!(f, " delimiter={}", quoted(&delimiter.to_string()))?;
+                }
+                if *no_drop {
+                    write!(f, " nodrop")?;
+                }
+                Ok(())
+            }
+            InlineOperator::AccessLog {
+                input_column,
+                no_drop,
+            } => {
+                write!(f, "parse accesslog")?;
+                if let Some(input_column) = input_column {
+                    write!(f, " from {}", input_column)?;
+                }
+                if *no_drop {
+                    write!(f, " nodrop")?;
+                }
+                Ok(())
+            }
+            InlineOperator::Url {
+                input_column,
+                no_drop,
+            } => {
+                write!(f, "parse url")?;
+                if let Some(input_column) = input_column {
+                    write!(f, " from {}", input_column)?;
+                }
+                if *no_drop {
+                    write!(f, " nodrop")?;
+                }
+                Ok(())
+            }
+            InlineOperator::UserAgent { input_column } => {
+                write!(f, "parse useragent")?;
+                if let Some(input_column) = input_column {
+                    write!(f, " from {}", input_column)?;
+                }
+                Ok(())
+            }
+            InlineOperator::Syslog {
+                input_column,
+                format,
+                no_drop,
+            } => {
+                write!(f, "parse syslog")?;
+                if let Some(input_column) = input_column {
+                    write!(f, " from {}", input_column)?;
+                }
+                match format {
+                    Some(SyslogFormat::Rfc3164) => write!(f, " format=rfc3164")?,
+                    Some(SyslogFormat::Rfc5424) => write!(f, " format=rfc5424")?,
+                    None => {}
+                }
+                if *no_drop {
+                    write!(f, " nodrop")?;
+                }
+                Ok(())
+            }
+            InlineOperator::Fields {
+                mode,
+                fields,
+                casts,
+            } => {
+                let mode = match mode {
+                    FieldMode::Only => "+",
+                    FieldMode::Except => "-",
+                };
+                write!(f, "fields {} {}", mode, typed_fields(fields, casts))
+            }
+            InlineOperator::Where { expr: None } => write!(f, "where"),
+            InlineOperator::Where { expr: Some(expr) } => write!(f, "where {}", expr.value),
+            InlineOperator::Limit { count: None } => write!(f, "limit"),
+            InlineOperator::Limit { count: Some(count) } => write!(f, "limit {}", count.value),
+            InlineOperator::Sample { rate, seed } => {
+                write!(f, "sample {}", rate.value)?;
+                if let Some(seed) = seed {
+                    write!(f, " seed={}", seed)?;
+                }
+                Ok(())
+            }
+            InlineOperator::Split {
+                separator,
+                max_splits,
+                input_column,
+                output_column,
+            } => {
+                write!(f, "split")?;
+                if let Some(input_column) = input_column {
+                    write!(f, "({})", input_column)?;
+                }
+                write!(f, " on {}", quoted(separator))?;
+                if let Some(max_splits) = max_splits {
+                    write!(f, " maxsplit={}", max_splits)?;
+                }
+                match output_column {
+                    Some(output_column) if input_column.as_ref() != Some(output_column) => {
+                        write!(f, " as {}", output_column)
+                    }
+                    _ => Ok(()),
+                }
+            }
+            InlineOperator::Timeslice {
+                input_column,
+                format,
+                duration: slice,
+                output_column,
+            } => {
+                write!(f, "timeslice({})", input_column)?;
+                match slice {
+                    Some(SliceDuration::Fixed(d)) => write!(f, " {}", duration(d))?,
+                    Some(SliceDuration::Auto { buckets }) => {
+                        write!(f, " auto buckets={}", buckets)?
+                    }
+                    None => {}
+                }
+                if let Some(format) = format {
+                    write!(f, " format={}", quoted(format))?;
+                }
+                if let Some(output_column) = output_column {
+                    write!(f, " as {}", ident(output_column))?;
+                }
+                Ok(())
+            }
+            InlineOperator::Total {
+                input_column,
+                output_column,
+            } => write!(f, "total({}) as {}", input_column, ident(output_column)),
+            InlineOperator::Dedup {
+                key_cols,
+                last,
+                ttl,
+                ..
+            } => {
+                write!(f, "dedup")?;
+                if !key_cols.is_empty() {
+                    write!(f, " {}", key_cols.iter().join(", "))?;
+                }
+                if let Some(last) = last {
+                    write!(f, " last={}", last)?;
+                }
+                if let Some((ttl, timestamp)) = ttl {
+                    write!(f, " ttl={} on {}", duration(ttl), timestamp)?;
+                }
+                Ok(())
+            }
+            InlineOperator::GeoIp { input_column } => write!(f, "geoip {}", input_column),
+            InlineOperator::Explode {
+                input_column,
+                output_column,
+            } => {
+                write!(f, "explode {}", input_column)?;
+                if output_column != input_column {
+                    write!(f, " as {}", output_column)?;
+                }
+                Ok(())
+            }
+            InlineOperator::Transaction {
+                key_col_headers,
+                max_span,
+                max_pause,
+                timestamp,
+                ..
+            } => {
+                write!(f, "transaction by {}", key_col_headers.join(", "))?;
+                if let Some(max_span) = max_span {
+                    write!(f, " maxspan={}", duration(max_span))?;
+                }
+                if let Some(max_pause) = max_pause {
+                    write!(f, " maxpause={}", duration(max_pause))?;
+                }
+                write!(f, " on {}", timestamp)
+            }
+            InlineOperator::FieldExpression { value, name } => {
+                write!(f, "{} as {}", value, ident(name))
+            }
+            InlineOperator::Rename {
+                pattern,
+                replacement,
+            } => {
+                match pattern {
+                    RenamePattern::Field(field) => write!(f, "rename {}", ident(field))?,
+                    RenamePattern::Regex(regex) => {
+                        write!(f, "rename /{}/", regex.replace('/', "\\/"))?
+                    }
+                }
+                write!(f, " as {}", quoted(replacement))
+            }
+            InlineOperator::Join {
+                mode,
+                path,
+                operators,
+                keys,
+            } => {
+                write!(f, "join")?;
+                if *mode == JoinMode::Left {
+                    write!(f, " left")?;
+                }
+                if let Some(path) = path {
+                    write!(f, " {}", quoted(path))?;
+                }
+                write!(
+                    f,
+                    " using ({}) on {}",
+                    operators.iter().join(" | "),
+                    keys.iter().join(", ")
+                )
+            }
+            InlineOperator::Lookup {
+                fields,
+                path,
+                key,
+                column,
+            } => {
+                write!(
+                    f,
+                    "lookup {} from {} on {}",
+                    fields.iter().map(|field| ident(field)).join(", "),
+                    quoted(path),
+                    key
+                )?;
+                match column {
+                    Some(column) => write!(f, " = {}", ident(column)),
+                    None => Ok(()),
+                }
+            }
+            InlineOperator::Wasm { path, function } => {
+                write!(f, "wasm {}", quoted(path))?;
+                match function {
+                    Some(function) => write!(f, " {}", function),
+                    None => Ok(()),
+                }
+            }
+            InlineOperator::Script { source } => write!(f, "script {}", quoted(source)),
+        }
+    }
+}
+
+impl Display for Operator {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Operator::RenderedAlias { operators, .. } => {
+                write!(f, "{}", operators.iter().join(" | "))
+            }
+            Operator::Inline(op) => write!(f, "{}", op.value),
+            Operator::MultiAggregate(agg) => {
+                let functions = agg.aggregate_functions.iter().map(|(name, function)| {
+                    if *name == function.value.default_name() {
+                        function.value.to_string()
+                    } else {
+                        format!("{} as {}", function.value, ident(name))
+                    }
+                });
+                write!(f, "{}", functions.format(", "))?;
+                if !agg.key_col_headers.is_empty() {
+                    write!(f, " by {}", agg.key_col_headers.join(", "))?;
+                }
+                Ok(())
+            }
+            Operator::Sort(sort) => {
+                write!(f, "sort")?;
+                if !sort.sort_cols.is_empty() {
+                    write!(f, " by {}", sort.sort_cols.iter().join(", "))?;
+                }
+                if sort.direction == SortMode::Descending {
+                    write!(f, " desc")?;
+                }
+                Ok(())
+            }
+            Operator::Top(top) => {
+                let name = match top.mode {
+                    TopMode::Most => "top",
+                    TopMode::Least => "rare",
+                };
+                write!(
+                    f,
+                    "{} {} by {}",
+                    name,
+                    top.count,
+                    top.key_col_headers.join(", ")
+                )
+            }
+            Operator::Branch(branch) => write!(f, "{}", branch),
+            Operator::Plugin(plugin) => write!(f, "{}", plugin.value),
+            Operator::Error => write!(f, "<error>"),
+        }
+    }
+}
+
+impl Display for PluginOperator {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}", self.keyword)?;
+        if !self.args.value.is_empty() {
+            write!(f, " {}", self.args.value)?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for BranchOperator {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        let branches = self
+            .branches
+            .iter()
+            .map(|operators| operators.iter().join(" | "));
+        write!(f, "[{}]", branches.format("; "))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alias::AliasCollection;
+    use crate::errors::{QueryContainer, TermErrorReporter};
+    use expect_test::{expect, Expect};
+
+    fn explained(query: &str) -> String {
+        let qc = QueryContainer::new_with_aliases(
+            query.to_string(),
+            Box::<TermErrorReporter>::default(),
+            AliasCollection::default(),
+        );
+        explain(&qc.parse().unwrap())
+    }
+
+    fn check_plan(query: &str, expect: Expect) {
+        let qc = QueryContainer::new_with_aliases(
+            query.to_string(),
+            Box::<TermErrorReporter>::default(),
+            AliasCollection::default(),
+        );
+        expect.assert_eq(&plan(&qc.parse().unwrap()));
+    }
+
+    /// Check the rendered query, and that it parses back into a query that renders the same.
+    fn check(query: &str, expect: Expect) {
+        let actual = explained(query);
+        expect.assert_eq(&actual);
+        assert_eq!(explained(&actual), actual);
+    }
+
+    #[test]
+    fn explain_operators() {
+        check(
+            r#"error "foo bar" NOT (x OR y) | apache | where status >= 500 and (a + b) * 2 > 3 | count, p50(latency) as median by host | sort by _count desc"#,
+            expect![[r#"
+                error "foo bar" NOT (x OR y)
+                | parse "* - * [*] \"* * *\" * *" as ip, name, timestamp, method, url, protocol, status, contentlength
+                | where status >= 500 and (a + b) * 2 > 3
+                | count, p50(latency) as median by host
+                | sort by _count desc"#]],
+        );
+        check(
+            r#"* | parse regex "(?P<x>\d+)" from line nodrop | split(x) on "," as y | timeslice(ts) 1h30m as t | rename /a\/b/ as c | limit 5 | total(n) | fields - a, b:int | json from ["x y"].z[0] duplicates=array warn | !(a == 1) as q | a - (b - c) as d"#,
+            expect![[r#"
+                *
+                | parse regex "(?P<x>\\d+)" from line nodrop
+                | split(x) on "," as y
+                | timeslice(ts) 1h30m as t
+                | rename /a\/b/ as "c"
+                | limit 5
+                | total(n) as _total
+                | fields - a, b:int
+                | json from ["x y"].z[0] duplicates=array warn
+                | !(a == 1) as q
+                | a - (b - c) as d"#]],
+        );
+        check(
+            r#"* | lookup name, ["team id"] from services.csv on toLowerCase(svc) = id | let n = 1 | timeslice 5m on ts format="%H:%M" as t | rare by t, n | count_distinct_approx(n), stddev(n), var(n), median(n) as m, mode(n), max_by(n, t) by t"#,
+            expect![[r#"
+                *
+                | lookup name, ["team id"] from "services.csv" on toLowerCase(svc) = id
+                | 1 as n
+                | timeslice(ts) 5m format="%H:%M" as t
+                | rare 10 by t, n
+                | count_distinct_approx(n), stddev(n), variance(n), median(n) as m, mode(n), max_by(n, t) by t"#]],
+        );
+        check(
+            "* | json | transaction by sid, host maxspan=1h30m maxpause=5m | transaction by sid on _start",
+            expect![[r#"
+                *
+                | json
+                | transaction by sid, host maxspan=1h30m maxpause=5m on timestamp
+                | transaction by sid on _start"#]],
+        );
+        check(
+            "* | dedup host, len(msg) last=100 | dedup msg ttl=5m on ts | dedup msg ttl=1h | dedup last=5 | dedup | count",
+            expect![[r#"
+                *
+                | dedup host, len(msg) last=100
+                | dedup msg ttl=5m on ts
+                | dedup msg ttl=1h on timestamp
+                | dedup last=5
+                | dedup
+                | count"#]],
+        );
+        check(
+            r#"* | fields + req_*, *.ms:int, ["a b*"] | fields except *"#,
+            expect![[r#"
+                *
+                | fields + req_*, *.ms:int, ["a b*"]
+                | fields - *"#]],
+        );
+        check(
+            "* | head | head 5 | tail 3 | sample 0.25 | sample 1e-3 seed=7",
+            expect![[r#"
+                *
+                | limit 10
+                | limit 5
+                | limit -3
+                | sample 0.25
+                | sample 0.001 seed=7"#]],
+        );
+        check(
+            r#"* | parse grok "%{IP:client} %{NUMBER:ms:float}" nodrop | parse useragent from ua | parse url from path nodrop | geoip client"#,
+            expect![[r#"
+                *
+                | parse grok "%{IP:client} %{NUMBER:ms:float}" nodrop
+                | parse useragent from ua
+                | parse url from path nodrop
+                | geoip client"#]],
+        );
+        check(
+            r#"* | where status in (500, 502 + 1) and msg =~ /timeout|a\/b/ | where !(x in ("a")) | (a =~ /b/) == true as c"#,
+            expect![[r#"
+                *
+                | where status in (500, 502 + 1) and msg =~ /timeout|a\/b/
+                | where !(x in ("a"))
+                | (a =~ /b/) == true as c"#]],
+        );
+        check(
+            "* | count_if(a > 1) as n, count() as total, sum_if(b, c == \"x\"), sum_if by d",
+            expect![[r#"
+                *
+                | count(a > 1) as n, count as total, sum_if(b, c == "x"), sum_if by d"#]],
+        );
+        check(
+            "* | first(a), last(b) as l, earliest(c by ts), latest(c by parseDate(d)) by e",
+            expect![[r#"
+                *
+                | first(a), last(b) as l, earliest(c by ts), latest(c by parseDate(d)) by e"#]],
+        );
+        check(
+            "* | values(a), values(b, 10) as bs by c",
+            expect![[r#"
+                *
+                | values(a), values(b, 10) as bs by c"#]],
+        );
+        check(
+            "* | pct(latency, 99.9), percentile(latency, 50) as median, p99(latency)",
+            expect![[r#"
+                *
+                | pct(latency, 99.9), p50(latency) as median, p99(latency)"#]],
+        );
+        check(
+            r#"* | logfmt from raw strict=true keys=(msg, ["request-id"]) duplicates=first | logfmt strict=false duplicates=last"#,
+            expect![[r#"
+                *
+                | logfmt from raw strict=true keys=(msg, ["request-id"]) duplicates=first
+                | logfmt"#]],
+        );
+        check(
+            "* | explode tags | explode spans as span",
+            expect![[r#"
+                *
+                | explode tags
+                | explode spans as span"#]],
+        );
+        check(
+            r#"* | json flatten from body | json path "spans[0].duration", "a.b" as c"#,
+            expect![[r#"
+                *
+                | json flatten from body
+                | json path "spans[0].duration" as duration, "a.b" as c"#]],
+        );
+        check(
+            r#"* | json | [ where level=="error" | count;where level == "warn" | count as n by host ] | ["a b"] as c"#,
+            expect![[r#"
+                *
+                | json
+                | [where level == "error" | count; where level == "warn" | count as n by host]
+                | ["a b"] as c"#]],
+        );
+        check(
+            r#"* | json | join left "users.log" using (json | count by id) on id | join using (where a > 1) on a, ["b c"]"#,
+            expect![[r#"
+                *
+                | json
+                | join left "users.log" using (json | count by id) on id
+                | join using (where a > 1) on a, ["b c"]"#]],
+        );
+    }
+
+    #[test]
+    fn plan_stages() {
+        check_plan(
+            "* | apache | where status >= 500 | count by url | sort by _count desc | head 3",
+            expect![[r#"
+                1. parse "* - * [*] \"* * *\" * *" as ip, name, timestamp, method, url, protocol, status, contentlength
+                   from alias `apache`, streaming
+                   reads: (line)
+                   writes: contentlength, ip, method, name, protocol, status, timestamp, url
+                2. where status >= 500
+                   streaming
+                   reads: status
+                3. count by url
+                   blocking, waits for the end of the input
+                   reads: url
+                   outputs only: _count, url
+                4. sort by _count desc
+                   runs on the output of stage 3
+                   reads: _count
+                5. limit 3
+                   runs on the output of stage 3
+            "#]],
+        );
+        check_plan(
+            "* | json | fields + a, b | x + 1 as y | rename a as c | limit -5 | logfmt",
+            expect![[r#"
+                1. json
+                   streaming
+                   reads: (line)
+                   writes: any fields, depending on the input
+                2. fields + a, b
+                   streaming
+                   reads: a, b
+                   keeps only: a, b
+                3. x + 1 as y
+                   streaming
+                   reads: x
+                   writes: y
+                4. rename a as "c"
+                   streaming
+                   reads: a
+                   renames: a to c
+                5. limit -5
+                   blocking, waits for the end of the input
+                6. logfmt
+                   runs on the output of stage 5
+                   reads: (line)
+                   writes: any fields, depending on the input
+            "#]],
+        );
+        check_plan(
+            "* | logfmt | fields - a | p99(latency), count by host | top 2 by host",
+            expect![[r#"
+                1. logfmt
+                   streaming
+                   reads: (line)
+                   writes: any fields, depending on the input
+                2. fields - a
+                   streaming
+                   reads: a
+                   drops: a
+                3. p99(latency), count by host
+                   blocking, waits for the end of the input
+                   reads: host, latency
+                   outputs only: _count, host, p99
+                4. top 2 by host
+                   runs on the output of stage 3
+                   reads: host
+                   outputs only: _count, _percent, host
+            "#]],
+        );
+    }
+}
@@ -0,0 +1,140 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+/// How long to wait before checking a followed file for new data again.
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Reads a file like `tail -F`: once the end is reached, reads block until more data is
+/// appended instead of returning EOF.  If the file is truncated, reading starts over from the
+/// beginning, and if it's replaced, e.g. by log rotation, the new file is opened once the rest
+/// of the old one has been read.
+pub struct FollowFile {
+    path: PathBuf,
+    file: File,
+    /// The number of bytes read from the current file.
+    pos: u64,
+    id: Option<FileId>,
+}
+
+/// Identifies a file independently of its path, to detect when the path is replaced.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct FileId {
+    dev: u64,
+    ino: u64,
+}
+
+impl FileId {
+    #[cfg(unix)]
+    fn of(metadata: &fs::Metadata) -> Option<FileId> {
+        use std::os::unix::fs::MetadataExt;
+        Some(FileId {
+            dev: metadata.dev(),
+            ino: metadata.ino(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    fn of(_metadata: &fs::Metadata) -> Option<FileId> {
+        None
+    }
+}
+
+impl FollowFile {
+    pub fn open<P: Into<PathBuf>>(path: P) -> io::Result<FollowFile> {
+        let path = path.into();
+        let file = File::open(&path)?;
+        let id = FileId::of(&file.metadata()?);
+        Ok(FollowFile {
+            path,
+            file,
+            pos: 0,
+            id,
+        })
+    }
+
+    /// Called at the end of the current file to switch to a new file at the same path or to
+    /// start over if the file was truncated.  While the path is missing, e.g. in the middle of a
+    /// rotation, the current file is kept.
+    fn reopen_if_changed(&mut self) -> io::Result<()> {
+        let metadata = match fs::metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        let id = FileId::of(&metadata);
+        if id != self.id {
+            self.file = File::open(&self.path)?;
+            self.id = id;
+            self.pos = 0;
+        } else if metadata.len() < self.pos {
+            self.file.seek(SeekFrom::Start(0))?;
+            self.pos = 0;
+        }
+        Ok(())
+    }
+}
+
+impl Read for FollowFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        loop {
+            let ct = self.file.read(buf)?;
+            if ct > 0 {
+                self.pos += ct as u64;
+                return Ok(ct);
+            }
+            let pos = self.pos;
+            self.reopen_if_changed()?;
+            if self.pos == pos {
+                thread::sleep(POLL_INTERVAL);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{BufRead, BufReader, Write};
+    use std::sync::mpsc;
+
+    #[test]
+    fn follows_appends_truncation_and_rotation() {
+        let dir = std::env::temp_dir().join(format!("agrind-follow-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        fs::write(&path, "one\n").unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let follow = FollowFile::open(&path).unwrap();
+        thread::spawn(move || {
+            for line in BufReader::new(follow).lines() {
+                if tx.send(line.unwrap()).is_err() {
+                    break;
+                }
+            }
+        });
+        let next = || rx.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(next(), "one");
+
+        let mut file = fs::OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(b"two\n").unwrap();
+        assert_eq!(next(), "two");
+
+        // Give the reader a chance to reach the end before truncating.
+        thread::sleep(POLL_INTERVAL * 3);
+        fs::write(&path, "3\n").unwrap();
+        assert_eq!(next(), "3");
+
+        fs::rename(&path, dir.join("app.log.1")).unwrap();
+        fs::write(&path, "four\n").unwrap();
+        assert_eq!(next(), "four");
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -3,7 +3,7 @@ use std::convert::{TryFrom, TryInto};
 use std::fmt;
 use std::fmt::{Debug, Formatter};
 
-use chrono::{DateTime, FixedOffset, Utc};
+use chrono::{DateTime, FixedOffset, NaiveDate, NaiveDateTime, Utc};
 use itertools::Itertools;
 use lazy_static::lazy_static;
 
@@ -17,6 +17,7 @@ pub enum FunctionWrapper {
     Float2(fn(f64, f64) -> f64),
     String1(fn(&str) -> Result<data::Value, EvalError>),
     String2(fn(&str, &str) -> Result<data::Value, EvalError>),
+    String3(fn(&str, &str, &str) -> Result<data::Value, EvalError>),
     Generic(fn(&[data::Value]) -> Result<data::Value, EvalError>),
 }
 
@@ -39,6 +40,20 @@ impl FunctionContainer {
         FunctionContainer { name, func }
     }
 
+    /// The number of arguments the function takes, if it's always the same.
+    pub fn arity(&self) -> Option<usize> {
+        match self.func {
+            FunctionWrapper::Float1(_) | FunctionWrapper::String1(_) => Some(1),
+            FunctionWrapper::Float2(_) | FunctionWrapper::String2(_) => Some(2),
+            FunctionWrapper::String3(_) => Some(3),
+            FunctionWrapper::Generic(_) => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
     fn eval1<'v, A: TryFrom<&'v data::Value, Error = EvalError>, O: Into<data::Value>>(
         &self,
         f: fn(A) -> O,
@@ -102,6 +117,21 @@ impl FunctionContainer {
                     })
                 }
             }
+            FunctionWrapper::String3(func) => {
+                if let [arg0, arg1, arg2] = args {
+                    func(
+                        arg0.to_string().as_str(),
+                        arg1.to_string().as_str(),
+                        arg2.to_string().as_str(),
+                    )
+                } else {
+                    Err(EvalError::InvalidFunctionArguments {
+                        name: self.name,
+                        expected: 3,
+                        found: args.len(),
+                    })
+                }
+            }
             FunctionWrapper::Generic(func) => func(args),
         }
     }
@@ -134,7 +164,7 @@ fn length(args: &[data::Value]) -> Result<data::Value, EvalError> {
     }
 }
 
-fn parse_date(date_str: &str) -> Result<data::Value, EvalError> {
+pub(crate) fn parse_date(date_str: &str) -> Result<data::Value, EvalError> {
     dtparse::parse(date_str)
         .map(|pair| {
             data::Value::DateTime(
@@ -151,6 +181,36 @@ fn parse_date(date_str: &str) -> Result<data::Value, EvalError> {
         })
 }
 
+/// Parse a date with a `strftime` format.  Formats without an offset are taken to be in UTC, and
+/// those without a time to be at midnight.
+pub(crate) fn parse_date_format(date_str: &str, format: &str) -> Result<data::Value, EvalError> {
+    DateTime::parse_from_str(date_str, format)
+        .map(|dt| dt.with_timezone(&Utc))
+        .or_else(|_| NaiveDateTime::parse_from_str(date_str, format).map(|dt| dt.and_utc()))
+        .or_else(|_| {
+            NaiveDate::parse_from_str(date_str, format)
+                .map(|date| date.and_hms_opt(0, 0, 0).unwrap().and_utc())
+        })
+        .map(data::Value::DateTime)
+        .map_err(|_| EvalError::ExpectedDateFormat {
+            format: format.to_string(),
+            found: date_str.to_string(),
+        })
+}
+
+fn parse_date_args(args: &[data::Value]) -> Result<data::Value, EvalError> {
+    match args {
+        [data::Value::DateTime(dt)] => Ok(data::Value::DateTime(*dt)),
+        [arg0] => parse_date(&arg0.to_string()),
+        [arg0, arg1] => parse_date_format(&arg0.to_string(), &arg1.to_string()),
+        _ => Err(EvalError::InvalidFunctionArguments {
+            name: "parseDate",
+            expected: 2,
+            found: args.len(),
+        }),
+    }
+}
+
 fn parse_hex(num_str: &str) -> Result<data::Value, EvalError> {
     i64::from_str_radix(num_str.trim().trim_start_matches("0x"), 16)
         .map(data::Value::Int)
@@ -207,6 +267,31 @@ fn to_upper_case(s: &str) -> Result<data::Value, EvalError> {
     Ok(data::Value::from_string(s.to_uppercase()))
 }
 
+fn replace(s: &str, from: &str, to: &str) -> Result<data::Value, EvalError> {
+    if from.is_empty() {
+        return Ok(data::Value::Str(s.to_string()));
+    }
+    Ok(data::Value::Str(s.replace(from, to)))
+}
+
+fn split(s: &str, separator: &str) -> Result<data::Value, EvalError> {
+    if separator.is_empty() {
+        return Err(EvalError::FunctionFailed {
+            name: "split",
+            msg: "the separator is empty".to_string(),
+        });
+    }
+    Ok(data::Value::Array(
+        s.split(separator)
+            .map(|part| data::Value::Str(part.to_string()))
+            .collect(),
+    ))
+}
+
+fn trim(s: &str) -> Result<data::Value, EvalError> {
+    Ok(data::Value::Str(s.trim().to_string()))
+}
+
 fn is_null(args: &[data::Value]) -> Result<data::Value, EvalError> {
     match args {
         [data::Value::None] => Ok(data::Value::Bool(true)),
@@ -258,6 +343,39 @@ fn is_numeric(args: &[data::Value]) -> Result<data::Value, EvalError> {
     }
 }
 
+/// The argument whose numeric value is picked by `pick` over all the others, e.g. the smallest.
+fn extreme(
+    name: &'static str,
+    args: &[data::Value],
+    pick: fn(f64, f64) -> bool,
+) -> Result<data::Value, EvalError> {
+    if args.len() < 2 {
+        return Err(EvalError::InvalidFunctionArguments {
+            name,
+            expected: 2,
+            found: args.len(),
+        });
+    }
+    let mut best: Option<(f64, &data::Value)> = None;
+    for arg in args {
+        let value: f64 = arg.try_into()?;
+        if best.is_none_or(|(b, _)| pick(value, b)) {
+            best = Some((value, arg));
+        }
+    }
+    Ok(best
+        .map(|(_, arg)| arg.clone())
+        .unwrap_or(data::Value::None))
+}
+
+fn min(args: &[data::Value]) -> Result<data::Value, EvalError> {
+    extreme("min", args, |l, r| l < r)
+}
+
+fn max(args: &[data::Value]) -> Result<data::Value, EvalError> {
+    extreme("max", args, |l, r| l > r)
+}
+
 fn num(value: f64) -> f64 {
     value
 }
@@ -293,6 +411,8 @@ lazy_static! {
             FunctionContainer::new("log", FunctionWrapper::Float1(f64::ln)),
             FunctionContainer::new("log10", FunctionWrapper::Float1(f64::log10)),
             FunctionContainer::new("log1p", FunctionWrapper::Float1(f64::ln_1p)),
+            FunctionContainer::new("max", FunctionWrapper::Generic(max)),
+            FunctionContainer::new("min", FunctionWrapper::Generic(min)),
             FunctionContainer::new("round", FunctionWrapper::Float1(f64::round)),
             FunctionContainer::new("sin", FunctionWrapper::Float1(f64::sin)),
             FunctionContainer::new("sinh", FunctionWrapper::Float1(f64::sinh)),
@@ -305,11 +425,17 @@ lazy_static! {
             FunctionContainer::new("concat", FunctionWrapper::Generic(concat)),
             FunctionContainer::new("contains", FunctionWrapper::String2(contains)),
             FunctionContainer::new("length", FunctionWrapper::Generic(length)),
-            FunctionContainer::new("parseDate", FunctionWrapper::String1(parse_date)),
+            FunctionContainer::new("len", FunctionWrapper::Generic(length)),
+            FunctionContainer::new("parseDate", FunctionWrapper::Generic(parse_date_args)),
+            FunctionContainer::new("lower", FunctionWrapper::String1(to_lower_case)),
             FunctionContainer::new("parseHex", FunctionWrapper::String1(parse_hex)),
+            FunctionContainer::new("replace", FunctionWrapper::String3(replace)),
+            FunctionContainer::new("split", FunctionWrapper::String2(split)),
             FunctionContainer::new("substring", FunctionWrapper::Generic(substring)),
             FunctionContainer::new("toLowerCase", FunctionWrapper::String1(to_lower_case)),
             FunctionContainer::new("toUpperCase", FunctionWrapper::String1(to_upper_case)),
+            FunctionContainer::new("trim", FunctionWrapper::String1(trim)),
+            FunctionContainer::new("upper", FunctionWrapper::String1(to_upper_case)),
             FunctionContainer::new("isNull", FunctionWrapper::Generic(is_null)),
             FunctionContainer::new("isEmpty", FunctionWrapper::Generic(is_empty)),
             FunctionContainer::new("isBlank", FunctionWrapper::Generic(is_blank)),
@@ -360,6 +486,56 @@ mod tests {
         assert_eq!(Ok(data::Value::Int(1)), length(&[data::Value::Obj(map)]));
     }
 
+    #[test]
+    fn min_max() {
+        let args = [
+            data::Value::Int(3),
+            data::Value::from_float(-1.5),
+            data::Value::Str("10".to_string()),
+        ];
+        assert_eq!(Ok(data::Value::from_float(-1.5)), min(&args));
+        assert_eq!(Ok(data::Value::Str("10".to_string())), max(&args));
+        assert_eq!(
+            Err(EvalError::InvalidFunctionArguments {
+                name: "max",
+                expected: 2,
+                found: 1,
+            }),
+            max(&args[..1])
+        );
+        assert!(min(&[data::Value::Int(1), data::Value::None]).is_err());
+    }
+
+    #[test]
+    fn parse_date_with_format() {
+        let date = |s: &str| data::Value::DateTime(DateTime::parse_from_rfc3339(s).unwrap().into());
+        let args = |date: &str, format: &str| {
+            [
+                data::Value::Str(date.to_string()),
+                data::Value::Str(format.to_string()),
+            ]
+        };
+        assert_eq!(
+            parse_date_args(&args("10/08/2021 12:30:05", "%d/%m/%Y %H:%M:%S")),
+            Ok(date("2021-08-10T12:30:05Z"))
+        );
+        assert_eq!(
+            parse_date_args(&args("2021-08-10 12:30 +0200", "%Y-%m-%d %H:%M %z")),
+            Ok(date("2021-08-10T10:30:00Z"))
+        );
+        assert_eq!(
+            parse_date_args(&args("2021-08-10", "%Y-%m-%d")),
+            Ok(date("2021-08-10T00:00:00Z"))
+        );
+        assert_eq!(
+            parse_date_args(&args("yesterday", "%Y-%m-%d")),
+            Err(EvalError::ExpectedDateFormat {
+                format: "%Y-%m-%d".to_string(),
+                found: "yesterday".to_string(),
+            })
+        );
+    }
+
     #[test]
     fn parse_hex_str() {
         assert_eq!(Ok(data::Value::Int(123)), parse_hex("0x7b"));
@@ -379,6 +555,25 @@ mod tests {
         assert_eq!(Ok(data::Value::from_string("def")), to_lower_case("DEF"));
     }
 
+    #[test]
+    fn replace_split_trim() {
+        assert_eq!(
+            Ok(data::Value::from_string("/users/:id/orders/:id")),
+            replace("/users/42/orders/42", "42", ":id")
+        );
+        assert_eq!(Ok(data::Value::from_string("abc")), replace("abc", "", "x"));
+        assert_eq!(
+            Ok(data::Value::Array(vec![
+                data::Value::from_string(""),
+                data::Value::from_string("api"),
+                data::Value::from_string("v1"),
+            ])),
+            split("/api/v1", "/")
+        );
+        assert!(split("abc", "").is_err());
+        assert_eq!(Ok(data::Value::from_string("a b")), trim(" \ta b \n"));
+    }
+
     #[test]
     fn does_not_contain() {
         assert_eq!(
@@ -0,0 +1,311 @@
+//! [Grok](https://www.elastic.co/guide/en/logstash/current/plugins-filters-grok.html) patterns,
+//! like `%{IP:client} %{WORD:method}`, which are expanded into a regular expression with a named
+//! capture for each field.
+//!
+//! The standard patterns are in `grok-patterns`, in the same format as Logstash's.  More can be
+//! loaded from the files or directories in `AGRIND_GROK_PATTERNS`, and replace the standard ones
+//! with the same name.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use include_dir::Dir;
+use lazy_static::lazy_static;
+use regex::Regex;
+use thiserror::Error;
+
+use crate::data::ValueType;
+
+const PATTERNS_DIR: Dir = include_dir!("grok-patterns");
+
+/// The environment variable with the paths of the custom pattern files, separated like `PATH`.
+pub const PATTERNS_ENV: &str = "AGRIND_GROK_PATTERNS";
+
+lazy_static! {
+    /// A reference to a pattern: `%{NAME}`, `%{NAME:field}` or `%{NAME:field:type}`.
+    static ref REFERENCE: Regex =
+        Regex::new(r"%\{(\w+)(?::([\w.\[\]]+))?(?::(\w+))?\}").unwrap();
+    static ref DEFINITION: Regex = Regex::new(r"^(\w+)\s+(.*)$").unwrap();
+    static ref STANDARD_PATTERNS: Patterns = {
+        let mut patterns = Patterns::default();
+        for file in PATTERNS_DIR.files() {
+            patterns
+                .add(file.contents_utf8().expect("invalid utf-8"), file.path())
+                .expect("invalid grok pattern");
+        }
+        patterns
+    };
+}
+
+#[derive(Debug, Error)]
+pub enum GrokError {
+    #[error("unknown grok pattern %{{{}}}", name)]
+    UnknownPattern { name: String },
+
+    #[error("the grok pattern %{{{}}} refers to itself", name)]
+    RecursivePattern { name: String },
+
+    #[error("unknown type {} for the {} field", tpe, field)]
+    UnknownType { field: String, tpe: String },
+
+    #[error("line {} of {} isn't a grok pattern, e.g. `NAME regex`", line, path.display())]
+    InvalidDefinition { path: PathBuf, line: usize },
+
+    #[error("failed to read the grok patterns in {}: {}", path.display(), error)]
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+}
+
+/// A grok pattern expanded into a regular expression.
+#[derive(Debug)]
+pub struct Grok {
+    pub regex: String,
+    /// The fields that were given a type, e.g. `%{NUMBER:bytes:int}`.
+    pub casts: Vec<(String, ValueType)>,
+}
+
+/// A library of patterns by name.
+#[derive(Clone, Default)]
+pub struct Patterns(HashMap<String, String>);
+
+impl Patterns {
+    /// The standard patterns along with any custom ones in `AGRIND_GROK_PATTERNS`.
+    pub fn load() -> Result<Patterns, GrokError> {
+        let mut patterns = STANDARD_PATTERNS.clone();
+        if let Some(paths) = std::env::var_os(PATTERNS_ENV) {
+            for path in std::env::split_paths(&paths) {
+                patterns.add_path(&path)?;
+            }
+        }
+        Ok(patterns)
+    }
+
+    /// Add the patterns in a file, or in each file of a directory.
+    fn add_path(&mut self, path: &Path) -> Result<(), GrokError> {
+        let io_error = |error| GrokError::Io {
+            path: path.to_owned(),
+            error,
+        };
+        if path.is_dir() {
+            let mut files = std::fs::read_dir(path)
+                .and_then(|entries| {
+                    entries
+                        .map(|entry| entry.map(|entry| entry.path()))
+                        .collect::<Result<Vec<_>, _>>()
+                })
+                .map_err(io_error)?;
+            files.sort();
+            for file in files.iter().filter(|file| file.is_file()) {
+                self.add_path(file)?;
+            }
+            Ok(())
+        } else {
+            let contents = std::fs::read_to_string(path).map_err(io_error)?;
+            self.add(&contents, path)
+        }
+    }
+
+    /// Add the patterns defined in `contents`, one per line, with blank lines and `#` comments
+    /// ignored.
+    fn add(&mut self, contents: &str, path: &Path) -> Result<(), GrokError> {
+        for (i, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let definition =
+                DEFINITION
+                    .captures(line)
+                    .ok_or_else(|| GrokError::InvalidDefinition {
+                        path: path.to_owned(),
+                        line: i + 1,
+                    })?;
+            self.0
+                .insert(definition[1].to_string(), definition[2].to_string());
+        }
+        Ok(())
+    }
+
+    /// Expand the references to other patterns in `pattern`.
+    pub fn compile(&self, pattern: &str) -> Result<Grok, GrokError> {
+        let mut casts = vec![];
+        let regex = self.expand(pattern, &mut vec![], &mut casts)?;
+        Ok(Grok { regex, casts })
+    }
+
+    fn expand(
+        &self,
+        pattern: &str,
+        stack: &mut Vec<String>,
+        casts: &mut Vec<(String, ValueType)>,
+    ) -> Result<String, GrokError> {
+        let pattern = non_capturing(pattern);
+        let mut regex = String::new();
+        let mut last = 0;
+        for reference in REFERENCE.captures_iter(&pattern) {
+            let whole = reference.get(0).unwrap();
+            regex.push_str(&pattern[last..whole.start()]);
+            last = whole.end();
+
+            let name = &reference[1];
+            let definition = self
+                .0
+                .get(name)
+                .ok_or_else(|| GrokError::UnknownPattern { name: name.into() })?;
+            if stack.iter().any(|outer| outer == name) {
+                return Err(GrokError::RecursivePattern { name: name.into() });
+            }
+            stack.push(name.to_string());
+            let expanded = self.expand(definition, stack, casts)?;
+            stack.pop();
+
+            match reference.get(2) {
+                Some(field) => {
+                    if let Some(tpe) = reference.get(3) {
+                        let tpe = ValueType::from_name(tpe.as_str()).ok_or_else(|| {
+                            GrokError::UnknownType {
+                                field: field.as_str().into(),
+                                tpe: tpe.as_str().into(),
+                            }
+                        })?;
+                        casts.push((field.as_str().into(), tpe));
+                    }
+                    write!(regex, "(?P<{}>{})", field.as_str(), expanded).unwrap();
+                }
+                None => write!(regex, "(?:{})", expanded).unwrap(),
+            }
+        }
+        regex.push_str(&pattern[last..]);
+        Ok(regex)
+    }
+}
+
+/// Turn the unnamed groups in a regular expression, and the atomic groups that the Rust regex
+/// syntax doesn't have, into non-capturing groups, so only the named fields are captured.
+fn non_capturing(regex: &str) -> String {
+    let mut out = String::with_capacity(regex.len());
+    let mut chars = regex.chars().peekable();
+    let mut in_class = false;
+    while let Some(c) = chars.next() {
+        out.push(c);
+        match c {
+            '\\' => out.extend(chars.next()),
+            '[' if !in_class => {
+                in_class = true;
+                // A `]` right at the start of a class, or after a `^`, is a literal.
+                if chars.peek() == Some(&'^') {
+                    out.extend(chars.next());
+                }
+                if chars.peek() == Some(&']') {
+                    out.extend(chars.next());
+                }
+            }
+            ']' if in_class => in_class = false,
+            '(' if !in_class => match chars.peek() {
+                Some('?') => {
+                    out.extend(chars.next());
+                    if chars.peek() == Some(&'>') {
+                        chars.next();
+                        out.push(':');
+                    }
+                }
+                _ => out.push_str("?:"),
+            },
+            _ => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields(pattern: &str, input: &str) -> Vec<(String, String)> {
+        let grok = STANDARD_PATTERNS.compile(pattern).unwrap();
+        let regex = Regex::new(&grok.regex).unwrap();
+        let captures = regex.captures(input).expect("the pattern should match");
+        regex
+            .capture_names()
+            .flatten()
+            .filter_map(|name| Some((name.to_string(), captures.name(name)?.as_str().into())))
+            .collect()
+    }
+
+    #[test]
+    fn standard_patterns_compile() {
+        for name in STANDARD_PATTERNS.0.keys() {
+            let grok = STANDARD_PATTERNS
+                .compile(&format!("%{{{}}}", name))
+                .unwrap();
+            let regex = Regex::new(&grok.regex)
+                .unwrap_or_else(|e| panic!("%{{{}}} is invalid: {}", name, e));
+            assert_eq!(
+                regex.captures_len() - 1,
+                regex.capture_names().flatten().count(),
+                "%{{{}}} has unnamed captures",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn combined_apache_log() {
+        let line = r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326 "http://www.example.com/start.html" "Mozilla/4.08 [en] (Win98; I ;Nav)""#;
+        let fields = fields("%{COMBINEDAPACHELOG}", line);
+        let get = |name: &str| {
+            fields
+                .iter()
+                .find(|(field, _)| field == name)
+                .map(|(_, value)| value.as_str())
+        };
+        assert_eq!(get("clientip"), Some("127.0.0.1"));
+        assert_eq!(get("auth"), Some("frank"));
+        assert_eq!(get("timestamp"), Some("10/Oct/2000:13:55:36 -0700"));
+        assert_eq!(get("verb"), Some("GET"));
+        assert_eq!(get("request"), Some("/apache_pb.gif"));
+        assert_eq!(get("response"), Some("200"));
+        assert_eq!(get("bytes"), Some("2326"));
+        assert_eq!(get("agent"), Some(r#""Mozilla/4.08 [en] (Win98; I ;Nav)""#));
+        assert_eq!(get("rawrequest"), None);
+    }
+
+    #[test]
+    fn types_and_errors() {
+        let grok = STANDARD_PATTERNS
+            .compile("%{IP:client} took %{NUMBER:ms:float}")
+            .unwrap();
+        assert_eq!(grok.casts, vec![("ms".to_string(), ValueType::Float)]);
+        assert!(matches!(
+            STANDARD_PATTERNS.compile("%{NOPE:x}"),
+            Err(GrokError::UnknownPattern { .. })
+        ));
+        assert!(matches!(
+            STANDARD_PATTERNS.compile("%{INT:x:long}"),
+            Err(GrokError::UnknownType { .. })
+        ));
+        let mut patterns = Patterns::default();
+        patterns
+            .add("# a comment\nA %{B}\n\nB x%{A}", Path::new("loop"))
+            .unwrap();
+        assert!(matches!(
+            patterns.compile("%{A}"),
+            Err(GrokError::RecursivePattern { .. })
+        ));
+        assert!(matches!(
+            patterns.add("NOPATTERN", Path::new("bad")),
+            Err(GrokError::InvalidDefinition { line: 1, .. })
+        ));
+    }
+
+    #[test]
+    fn unnamed_groups_are_not_captured() {
+        assert_eq!(
+            non_capturing(r"(a|b)(?>c)(?P<d>e)\(f\)[(]g[]()]"),
+            r"(?:a|b)(?:c)(?P<d>e)\(f\)[(]g[]()]"
+        );
+    }
+}
@@ -0,0 +1,605 @@
+//! A terminal UI for `--interactive`: the query is edited on the top line and re-run over input
+//! held in memory after every change, with its output or errors drawn below it.  The `--repl`
+//! prompt is read with rustyline, with history and completion of the fields in the data.
+use crate::alias::AliasCollection;
+use crate::lang::VALID_OPERATORS;
+use crate::operator::logfmt;
+use crate::pipeline::{ErrorReporter, OutputMode, Pipeline, PipelineOptions, QueryContainer};
+use annotate_snippets::display_list::DisplayList;
+use annotate_snippets::snippet::Snippet;
+use crossterm::cursor::MoveTo;
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{Behavior, CompletionType, Config, Context, Editor, Helper};
+use std::collections::BTreeSet;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use terminal_size::{terminal_size_of, Height, Width};
+
+const PROMPT: &str = "agrind> ";
+const HELP: &str = "Enter: print the query and exit | Esc: exit";
+
+/// The most queries that are kept in the REPL's history.
+const HISTORY_LIMIT: usize = 1000;
+
+/// The number of lines that field names are collected from for completion.
+const SAMPLE_LINES: usize = 1000;
+
+/// A key press read from the terminal.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Key {
+    Char(char),
+    Backspace,
+    Delete,
+    Left,
+    Right,
+    Home,
+    End,
+    /// Ctrl-U
+    ClearLine,
+    /// Ctrl-W
+    DeleteWord,
+    Enter,
+    /// Esc, Ctrl-C or Ctrl-D
+    Exit,
+    Unknown,
+}
+
+/// The key that a key event from the terminal stands for.
+fn key(event: KeyEvent) -> Key {
+    if event.modifiers.contains(KeyModifiers::CONTROL) {
+        return match event.code {
+            KeyCode::Char('a') => Key::Home,
+            KeyCode::Char('e') => Key::End,
+            KeyCode::Char('b') => Key::Left,
+            KeyCode::Char('f') => Key::Right,
+            KeyCode::Char('h') => Key::Backspace,
+            KeyCode::Char('u') => Key::ClearLine,
+            KeyCode::Char('w') => Key::DeleteWord,
+            KeyCode::Char('c' | 'd') => Key::Exit,
+            _ => Key::Unknown,
+        };
+    }
+    match event.code {
+        KeyCode::Char(c) => Key::Char(c),
+        KeyCode::Backspace => Key::Backspace,
+        KeyCode::Delete => Key::Delete,
+        KeyCode::Left => Key::Left,
+        KeyCode::Right => Key::Right,
+        KeyCode::Home => Key::Home,
+        KeyCode::End => Key::End,
+        KeyCode::Enter => Key::Enter,
+        KeyCode::Esc => Key::Exit,
+        _ => Key::Unknown,
+    }
+}
+
+/// Wait for the next key that's pressed.
+fn read_key() -> io::Result<Key> {
+    loop {
+        match event::read()? {
+            Event::Key(event) if event.kind != KeyEventKind::Release => return Ok(key(event)),
+            _ => {}
+        }
+    }
+}
+
+/// The query being edited, with the cursor as an index into its characters.
+#[derive(Debug, Default)]
+struct LineEditor {
+    chars: Vec<char>,
+    cursor: usize,
+}
+
+impl LineEditor {
+    fn new(text: &str) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        LineEditor {
+            cursor: chars.len(),
+            chars,
+        }
+    }
+
+    fn text(&self) -> String {
+        self.chars.iter().collect()
+    }
+
+    /// Apply an editing key, returning whether the text changed.
+    fn edit(&mut self, key: Key) -> bool {
+        match key {
+            Key::Char(c) => {
+                self.chars.insert(self.cursor, c);
+                self.cursor += 1;
+                true
+            }
+            Key::Backspace if self.cursor > 0 => {
+                self.cursor -= 1;
+                self.chars.remove(self.cursor);
+                true
+            }
+            Key::Delete if self.cursor < self.chars.len() => {
+                self.chars.remove(self.cursor);
+                true
+            }
+            Key::Left => {
+                self.cursor = self.cursor.saturating_sub(1);
+                false
+            }
+            Key::Right => {
+                self.cursor = (self.cursor + 1).min(self.chars.len());
+                false
+            }
+            Key::Home => {
+                self.cursor = 0;
+                false
+            }
+            Key::End => {
+                self.cursor = self.chars.len();
+                false
+            }
+            Key::ClearLine => {
+                let changed = self.cursor > 0;
+                self.chars.drain(..self.cursor);
+                self.cursor = 0;
+                changed
+            }
+            Key::DeleteWord => {
+                let mut start = self.cursor;
+                while start > 0 && self.chars[start - 1] == ' ' {
+                    start -= 1;
+                }
+                while start > 0 && self.chars[start - 1] != ' ' {
+                    start -= 1;
+                }
+                self.chars.drain(start..self.cursor);
+                let changed = start != self.cursor;
+                self.cursor = start;
+                changed
+            }
+            _ => false,
+        }
+    }
+}
+
+/// A writer that can be read back once the pipeline that owns it is done.
+#[derive(Clone, Default)]
+struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl SharedBuffer {
+    fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().unwrap()).into_owned()
+    }
+}
+
+/// An ErrorReporter that collects the errors and warnings about the query to be drawn under it.
+#[derive(Clone, Default)]
+struct CollectingReporter(Arc<Mutex<String>>);
+
+impl ErrorReporter for CollectingReporter {
+    fn handle_error(&self, snippet: Snippet) {
+        let mut out = self.0.lock().unwrap();
+        out.push_str(&DisplayList::from(snippet).to_string());
+        out.push('\n');
+    }
+}
+
+/// Run the query over the input, returning what it output, or the errors in the query.
+fn evaluate(
+    query: &str,
+    data: &[u8],
+    aliases: &AliasCollection<'static>,
+    output_mode: &OutputMode,
+    options: &PipelineOptions,
+) -> String {
+    if query.trim().is_empty() {
+        return "Type a query, e.g. `* | json | count by level`".to_string();
+    }
+    let reporter = CollectingReporter::default();
+    let container = QueryContainer::new_with_aliases(
+        query.to_string(),
+        Box::new(reporter.clone()),
+        aliases.clone(),
+    );
+    let output = SharedBuffer::default();
+    let options = PipelineOptions {
+        render_once: true,
+        ..options.clone()
+    };
+    let result =
+        Pipeline::new_with_options(&container, output.clone(), output_mode.clone(), options)
+            .and_then(|pipeline| Ok(pipeline.process(data)?));
+    let errors = reporter.0.lock().unwrap().clone();
+    match result {
+        Ok(_) => errors + output.contents().as_str(),
+        Err(err) => format!("{}Error: {}", errors, err),
+    }
+}
+
+/// Raw mode, in which keys are read as they're pressed without being echoed.  The terminal's
+/// previous mode is restored when it's dropped, including when a query panics.
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        Ok(RawMode)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// The terminal, put in raw mode, with the screen switched to the alternate buffer.  Both are
+/// restored when it's dropped.
+struct Terminal {
+    tty: File,
+    // Dropped after the screen is restored.
+    _raw_mode: RawMode,
+}
+
+impl Terminal {
+    fn open() -> io::Result<Self> {
+        let tty = OpenOptions::new().write(true).open("/dev/tty")?;
+        let mut term = Terminal {
+            tty,
+            _raw_mode: RawMode::enable()?,
+        };
+        execute!(term.tty, EnterAlternateScreen)?;
+        Ok(term)
+    }
+
+    fn size(&self) -> (usize, usize) {
+        terminal_size_of(&self.tty)
+            .map(|(Width(width), Height(height))| (width as usize, height as usize))
+            .unwrap_or((80, 24))
+    }
+
+    /// Draw the query with the cursor in it, a status line, and as much of the output as fits.
+    fn draw(&mut self, editor: &LineEditor, output: &str) -> io::Result<()> {
+        let (width, height) = self.size();
+        // Scroll the query horizontally to keep the cursor on screen.
+        let room = width.saturating_sub(PROMPT.len() + 1).max(1);
+        let scroll = editor.cursor.saturating_sub(room);
+        let visible: String = editor.chars.iter().skip(scroll).take(room).collect();
+        queue!(
+            self.tty,
+            MoveTo(0, 0),
+            Clear(ClearType::All),
+            Print(PROMPT),
+            Print(visible),
+            MoveTo(0, 1),
+            SetAttribute(Attribute::Reverse),
+            Print(truncate(&format!("{:width$}", HELP, width = width), width)),
+            SetAttribute(Attribute::Reset),
+        )?;
+        for (row, line) in output.lines().take(height.saturating_sub(2)).enumerate() {
+            queue!(
+                self.tty,
+                MoveTo(0, row as u16 + 2),
+                Print(truncate(line, width))
+            )?;
+        }
+        let column = PROMPT.len() + editor.cursor - scroll;
+        queue!(self.tty, MoveTo(column as u16, 0))?;
+        self.tty.flush()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(self.tty, LeaveAlternateScreen);
+    }
+}
+
+/// Cut a line down to the given number of characters.
+fn truncate(line: &str, width: usize) -> String {
+    line.chars().take(width).collect()
+}
+
+/// Edit `query` in the terminal, re-running it over `data` as it changes.  Returns the query
+/// when Enter is pressed, or `None` if the user exits without accepting it.
+pub fn run(
+    query: &str,
+    data: &[u8],
+    aliases: &AliasCollection<'static>,
+    output_mode: &OutputMode,
+    options: &PipelineOptions,
+) -> io::Result<Option<String>> {
+    let mut term = Terminal::open()?;
+    let mut editor = LineEditor::new(query);
+    let mut output = evaluate(query, data, aliases, output_mode, options);
+    loop {
+        term.draw(&editor, &output)?;
+        // Apply every key that's waiting before running the query again, so that pasted text
+        // doesn't run it once per character.
+        let mut changed = false;
+        let mut next = Some(read_key()?);
+        while let Some(key) = next {
+            match key {
+                Key::Enter => return Ok(Some(editor.text())),
+                Key::Exit => return Ok(None),
+                key => changed |= editor.edit(key),
+            }
+            next = if event::poll(Duration::ZERO)? {
+                Some(read_key()?)
+            } else {
+                None
+            };
+        }
+        if changed {
+            output = evaluate(&editor.text(), data, aliases, output_mode, options);
+        }
+    }
+}
+
+/// Completes the field names in the data, operators and aliases.
+struct QueryHelper {
+    words: Vec<String>,
+}
+
+impl Completer for QueryHelper {
+    type Candidate = String;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<String>)> {
+        Ok(complete(line, pos, &self.words))
+    }
+}
+
+impl Hinter for QueryHelper {
+    type Hint = String;
+}
+
+impl Highlighter for QueryHelper {}
+
+impl Validator for QueryHelper {}
+
+impl Helper for QueryHelper {}
+
+/// Reads the queries for `--repl` from the terminal, with line editing, a history that's browsed
+/// with Up and Down, and Tab completion of the field names in the data, operators and aliases.
+pub struct Prompt {
+    editor: Editor<QueryHelper, FileHistory>,
+    history_path: Option<PathBuf>,
+}
+
+impl Prompt {
+    /// The history is read from and saved to `history_path`, if it's given.
+    pub fn new(
+        data: &[u8],
+        aliases: &AliasCollection,
+        history_path: Option<PathBuf>,
+    ) -> io::Result<Self> {
+        let config = Config::builder()
+            .max_history_size(HISTORY_LIMIT)
+            .and_then(|config| config.history_ignore_dups(true))
+            .map_err(readline_error)?
+            .completion_type(CompletionType::List)
+            // The prompt is drawn on the terminal even when the output is redirected.
+            .behavior(Behavior::PreferTerm)
+            .build();
+        let mut editor = Editor::with_config(config).map_err(readline_error)?;
+        let mut words = sample_fields(data);
+        words.extend(VALID_OPERATORS.iter().map(|op| op.to_string()));
+        words.extend(aliases.valid_aliases().map(String::from));
+        editor.set_helper(Some(QueryHelper {
+            words: words.into_iter().collect(),
+        }));
+        if let Some(path) = &history_path {
+            // There's no history the first time.
+            let _ = editor.load_history(path);
+        }
+        Ok(Prompt {
+            editor,
+            history_path,
+        })
+    }
+
+    /// Read the next query, or `None` when Ctrl-D is pressed on an empty line.
+    pub fn read_query(&mut self) -> io::Result<Option<String>> {
+        match self.editor.readline(PROMPT) {
+            Ok(query) => {
+                self.add_history(&query);
+                Ok(Some(query))
+            }
+            // Ctrl-C abandons the query that's being typed.
+            Err(ReadlineError::Interrupted) => Ok(Some(String::new())),
+            Err(ReadlineError::Eof) => Ok(None),
+            Err(e) => Err(readline_error(e)),
+        }
+    }
+
+    /// Add the query to the history, and append it to the history file.  Failing to save the
+    /// history isn't worth interrupting the REPL for, so errors are ignored.
+    fn add_history(&mut self, query: &str) {
+        let query = query.trim();
+        if query.is_empty() {
+            return;
+        }
+        let _ = self.editor.add_history_entry(query);
+        if let Some(path) = &self.history_path {
+            let _ = self.editor.append_history(path);
+        }
+    }
+}
+
+fn readline_error(error: ReadlineError) -> io::Error {
+    match error {
+        ReadlineError::Io(e) => e,
+        e => io::Error::other(e),
+    }
+}
+
+/// The field names in the first lines of the data: the keys of JSON objects, with nested keys
+/// joined by dots, and the keys in logfmt lines.
+fn sample_fields(data: &[u8]) -> BTreeSet<String> {
+    fn add_keys(
+        prefix: &str,
+        obj: &serde_json::Map<String, serde_json::Value>,
+        fields: &mut BTreeSet<String>,
+    ) {
+        for (key, value) in obj {
+            let field = format!("{}{}", prefix, key);
+            if let serde_json::Value::Object(nested) = value {
+                add_keys(&format!("{}.", field), nested, fields);
+            }
+            fields.insert(field);
+        }
+    }
+
+    let mut fields = BTreeSet::new();
+    for line in data.split(|b| *b == b'\n').take(SAMPLE_LINES) {
+        let line = String::from_utf8_lossy(line);
+        let line = line.trim();
+        if line.starts_with('{') {
+            if let Ok(serde_json::Value::Object(obj)) = serde_json::from_str(line) {
+                add_keys("", &obj, &mut fields);
+            }
+        } else {
+            // Without a value, a "key" is just a word in the line.
+            let pairs = logfmt::parse(line, false).unwrap_or_default();
+            let pairs = pairs.into_iter().filter(|pair| pair.value.is_some());
+            fields.extend(pairs.map(|pair| pair.key.to_string()));
+        }
+    }
+    fields
+}
+
+/// The start of the word before the cursor, and the words that start with it.
+fn complete(line: &str, pos: usize, words: &[String]) -> (usize, Vec<String>) {
+    let is_word = |c: char| c.is_alphanumeric() || "_.@-".contains(c);
+    let start = line[..pos]
+        .char_indices()
+        .rev()
+        .take_while(|(_, c)| is_word(*c))
+        .last()
+        .map_or(pos, |(i, _)| i);
+    let prefix = &line[start..pos];
+    if prefix.is_empty() {
+        return (pos, vec![]);
+    }
+    let candidates = words.iter().filter(|w| w.starts_with(prefix)).cloned();
+    (start, candidates.collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_keys() {
+        let press = |code, modifiers| key(KeyEvent::new(code, modifiers));
+        assert_eq!(
+            press(KeyCode::Char('é'), KeyModifiers::NONE),
+            Key::Char('é')
+        );
+        assert_eq!(
+            press(KeyCode::Char('A'), KeyModifiers::SHIFT),
+            Key::Char('A')
+        );
+        assert_eq!(press(KeyCode::Left, KeyModifiers::NONE), Key::Left);
+        assert_eq!(press(KeyCode::Esc, KeyModifiers::NONE), Key::Exit);
+        assert_eq!(
+            press(KeyCode::Char('w'), KeyModifiers::CONTROL),
+            Key::DeleteWord
+        );
+        assert_eq!(press(KeyCode::Char('d'), KeyModifiers::CONTROL), Key::Exit);
+        assert_eq!(
+            press(KeyCode::Char('z'), KeyModifiers::CONTROL),
+            Key::Unknown
+        );
+        assert_eq!(press(KeyCode::BackTab, KeyModifiers::SHIFT), Key::Unknown);
+    }
+
+    #[test]
+    fn edits_the_line() {
+        let mut editor = LineEditor::new("* | json");
+        assert!(!editor.edit(Key::Left));
+        assert!(editor.edit(Key::Backspace));
+        assert_eq!(editor.text(), "* | jsn");
+        editor.edit(Key::End);
+        for c in " | count by a".chars() {
+            editor.edit(Key::Char(c));
+        }
+        assert!(editor.edit(Key::DeleteWord));
+        assert_eq!(editor.text(), "* | jsn | count by ");
+        editor.edit(Key::Home);
+        assert!(editor.edit(Key::Delete));
+        assert!(!editor.edit(Key::ClearLine));
+        editor.edit(Key::End);
+        assert!(editor.edit(Key::ClearLine));
+        assert_eq!(editor.text(), "");
+    }
+
+    #[test]
+    fn samples_fields() {
+        let data = b"{\"level\": \"info\", \"http\": {\"status\": 200}}\nts=1 msg=\"a b\" ok\nplain text\n";
+        let fields: Vec<_> = sample_fields(data).into_iter().collect();
+        assert_eq!(fields, vec!["http", "http.status", "level", "msg", "ts"]);
+    }
+
+    #[test]
+    fn completes_words() {
+        let words: Vec<String> = ["level", "latency", "latency_ms", "count"]
+            .iter()
+            .map(|w| w.to_string())
+            .collect();
+        let line = "* | json | count by lat";
+        assert_eq!(
+            complete(line, line.len(), &words),
+            (20, vec!["latency".to_string(), "latency_ms".to_string()])
+        );
+        assert_eq!(
+            complete("* | c | json", 5, &words),
+            (4, vec!["count".to_string()])
+        );
+        assert_eq!(complete("* | é", 6, &words), (4, vec![]));
+        assert_eq!(complete("* | ", 4, &words), (4, vec![]));
+    }
+
+    #[test]
+    fn evaluates_queries() {
+        let data = b"{\"level\": \"info\"}\n{\"level\": \"error\"}\n{\"level\": \"info\"}\n";
+        let aliases = AliasCollection::default();
+        let run = |query| {
+            evaluate(
+                query,
+                data,
+                &aliases,
+                &OutputMode::Legacy,
+                &PipelineOptions::default(),
+            )
+        };
+        assert!(run("* | json | count by level").contains("info         2"));
+        assert!(run("* | jsn").contains("Error: Failed to parse query"));
+    }
+}
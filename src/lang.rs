@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::fmt::Debug;
 use std::ops::Range;
 use std::str;
@@ -7,13 +8,13 @@ use lazy_static::lazy_static;
 use nom::bytes::complete::escaped;
 use nom::combinator::not;
 use nom::multi::{fold_many0, fold_many1};
-use nom::sequence::{delimited, separated_pair};
+use nom::sequence::{delimited, preceded, separated_pair};
 use nom::{
     branch::alt,
     bytes::complete::{take, take_while, take_while1},
     character::complete::{anychar, digit1, multispace0, multispace1, none_of, satisfy},
     character::{is_alphabetic, is_alphanumeric},
-    combinator::{eof, map, map_res, opt, peek, recognize},
+    combinator::{all_consuming, eof, map, map_res, opt, peek, recognize, verify},
     error::ParseError,
     multi::{many0, many_till, separated_list0, separated_list1},
     number::complete::double,
@@ -28,18 +29,37 @@ use nom_supreme::tag::complete::tag;
 
 use crate::alias::{self, AliasCollection};
 use crate::data;
-use crate::errors::{ErrorBuilder, QueryContainer};
+use crate::errors::{ErrorBuilder, QueryContainer, TermErrorReporter};
+use crate::grok;
 use crate::pipeline::CompileError;
+use crate::plugin::Plugins;
 
 pub const VALID_AGGREGATES: &[&str] = &[
     "count",
+    "count_if",
     "min",
     "average",
     "avg",
     "max",
+    "min_by",
+    "max_by",
+    "first",
+    "last",
+    "earliest",
+    "latest",
     "sum",
+    "sum_if",
     "count_distinct",
+    "count_distinct_approx",
+    "values",
+    "stddev",
+    "var",
+    "variance",
+    "median",
+    "mode",
     "sort",
+    "top",
+    "rare",
 ];
 
 pub const VALID_INLINE: &[&str] = &[
@@ -52,6 +72,19 @@ pub const VALID_INLINE: &[&str] = &[
     "where",
     "split",
     "timeslice",
+    "rename",
+    "join",
+    "let",
+    "lookup",
+    "transaction",
+    "dedup",
+    "head",
+    "tail",
+    "sample",
+    "geoip",
+    "explode",
+    "wasm",
+    "script",
 ];
 
 lazy_static! {
@@ -203,6 +236,16 @@ pub enum Expr {
         value_if_true: Box<Expr>,
         value_if_false: Box<Expr>,
     },
+    /// Whether the value equals any in the list, e.g. `status in (500, 502, 503)`.
+    In {
+        value: Box<Expr>,
+        list: Vec<Expr>,
+    },
+    /// Whether the value matches a regular expression, e.g. `msg =~ /timeout|refused/`.
+    Match {
+        value: Box<Expr>,
+        regex: String,
+    },
     Value(data::Value),
     Error,
 }
@@ -354,7 +397,10 @@ fn expect_pipe<'a, M>(
 where
     M: ToString,
 {
-    expect(peek(multispace0.and(tag("|").or(eof))), error_msg)
+    expect(
+        peek(multispace0.and(alt((tag("|"), tag(")"), tag(";"), tag("]"), eof)))),
+        error_msg,
+    )
 }
 
 /// The KeywordType determines how a keyword string should be interpreted.
@@ -365,6 +411,8 @@ enum KeywordType {
     /// The keyword string can contain wildcards.
     Wildcard,
     Regex,
+    /// A grok pattern, along with the regular expression it expands to.
+    Grok(String),
 }
 
 /// Represents a `keyword` search string.
@@ -387,6 +435,31 @@ impl Keyword {
         Keyword(str, KeywordType::Regex)
     }
 
+    /// Create a Keyword for a grok pattern that expands to the given regular expression.
+    pub fn new_grok(str: String, regex: String) -> Keyword {
+        Keyword(str, KeywordType::Grok(regex))
+    }
+
+    /// The keyword as it was written, without any quotes.
+    pub(crate) fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Test if the keyword has to match the input exactly, e.g. it was quoted.
+    pub(crate) fn is_exact(&self) -> bool {
+        self.1 == KeywordType::Exact
+    }
+
+    /// Test if the keyword is a regular expression.
+    pub(crate) fn is_regex(&self) -> bool {
+        self.1 == KeywordType::Regex
+    }
+
+    /// Test if the keyword is a grok pattern.
+    pub(crate) fn is_grok(&self) -> bool {
+        matches!(self.1, KeywordType::Grok(_))
+    }
+
     /// Test if this is an empty keyword string
     pub fn is_empty(&self) -> bool {
         self.0.is_empty()
@@ -394,8 +467,10 @@ impl Keyword {
 
     /// Convert this keyword to a `regex::Regex` object.
     pub fn to_regex(&self) -> regex::Regex {
-        if self.1 == KeywordType::Regex {
-            return regex::Regex::new(&self.0).unwrap();
+        match &self.1 {
+            KeywordType::Regex => return regex::Regex::new(&self.0).unwrap(),
+            KeywordType::Grok(regex) => return regex::Regex::new(regex).unwrap(),
+            _ => {}
         }
 
         let mut regex_str = regex::escape(&self.0.replace("\\\"", "\"")).replace(' ', "\\s");
@@ -403,7 +478,8 @@ impl Keyword {
         // Only apply case-insensitive flag for wildcard keywords
         // Exact keywords (quoted strings) should be case-sensitive
         if self.1 == KeywordType::Wildcard {
-            regex_str.insert_str(0, "(?i)");
+            // `*` also matches the newlines in a record joined from several lines.
+            regex_str.insert_str(0, "(?is)");
             regex_str = regex_str.replace("\\*", "(.*?)");
             // If it ends with a star, we need to ensure we read until the end.
             if self.0.ends_with('*') {
@@ -445,10 +521,17 @@ impl Search {
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Operator {
-    RenderedAlias(Vec<Operator>),
+    /// The operators that an alias expanded to.
+    RenderedAlias {
+        keyword: String,
+        operators: Vec<Operator>,
+    },
     Inline(Positioned<InlineOperator>),
     MultiAggregate(MultiAggregateOperator),
     Sort(SortOperator),
+    Top(TopOperator),
+    Branch(BranchOperator),
+    Plugin(Positioned<PluginOperator>),
     Error,
 }
 
@@ -456,9 +539,18 @@ pub enum Operator {
 pub enum InlineOperator {
     Json {
         input_column: Option<Expr>,
+        mode: JsonMode,
+        duplicates: DuplicateKeys,
+        /// Warn the first time an object with duplicate keys is seen.
+        warn_duplicates: bool,
     },
     Logfmt {
         input_column: Option<Expr>,
+        /// Fail on lines that aren't entirely `key=value` pairs.
+        strict: bool,
+        /// The keys to extract, or all of them if empty.
+        keys: Vec<String>,
+        duplicates: DuplicateKeys,
     },
     Parse {
         pattern: Keyword,
@@ -466,10 +558,41 @@ pub enum InlineOperator {
         input_column: (Option<Positioned<Expr>>, Option<Positioned<Expr>>),
         no_drop: bool,
         no_convert: bool,
+        /// Captures that were given a type, e.g. `duration:float`, in the order they were listed.
+        casts: Vec<(String, data::ValueType)>,
+    },
+    Csv {
+        fields: Vec<String>,
+        /// Fields that were given a type, e.g. `status:int`, in the order they were listed.
+        casts: Vec<(String, data::ValueType)>,
+        input_column: Option<Expr>,
+        /// The first line is a header.  It gives the field names unless they were listed.
+        headers: bool,
+        delimiter: char,
+        no_drop: bool,
+    },
+    Syslog {
+        input_column: Option<Expr>,
+        /// Detect the format of each message if it isn't given.
+        format: Option<SyslogFormat>,
+        no_drop: bool,
+    },
+    AccessLog {
+        input_column: Option<Expr>,
+        no_drop: bool,
+    },
+    UserAgent {
+        input_column: Option<Expr>,
+    },
+    Url {
+        input_column: Option<Expr>,
+        no_drop: bool,
     },
     Fields {
         mode: FieldMode,
         fields: Vec<String>,
+        /// Fields that were given a type, e.g. `status:int`, in the order they were listed.
+        casts: Vec<(String, data::ValueType)>,
     },
     Where {
         expr: Option<Positioned<Expr>>,
@@ -479,24 +602,158 @@ pub enum InlineOperator {
         /// check the value to see if it's sane or provide a default if no number was given.
         count: Option<Positioned<f64>>,
     },
+    /// Keep each row with the probability `rate`, e.g. `sample 0.01`.
+    Sample {
+        rate: Positioned<f64>,
+        /// Pick the same rows every time.
+        seed: Option<u64>,
+    },
     Split {
         separator: String,
+        /// Stop splitting after this many tokens, leaving the rest of the input as the final token.
+        max_splits: Option<usize>,
         input_column: Option<Expr>,
         output_column: Option<Expr>,
     },
+    /// Emit a copy of the record for each element of an array, e.g. `explode tags`.
+    Explode {
+        input_column: Expr,
+        output_column: Expr,
+    },
     Timeslice {
         input_column: Expr,
-        duration: Option<chrono::Duration>,
+        /// The `strftime` format that string timestamps are parsed with.
+        format: Option<String>,
+        duration: Option<SliceDuration>,
         output_column: Option<String>,
     },
     Total {
         input_column: Expr,
         output_column: String,
     },
+    /// Drop records whose key has already been seen, e.g. `dedup host, message`.  Without any
+    /// keys, the whole record is the key.
+    Dedup {
+        key_cols: Vec<Expr>,
+        /// Only remember this many of the most recently seen keys.
+        last: Option<usize>,
+        /// Forget a key this long after the record that was let through with it, by the
+        /// timestamps in the given field.
+        ttl: Option<(chrono::Duration, Expr)>,
+    },
+    /// Add the location and network of an IP address, e.g. `geoip client_ip`.
+    GeoIp {
+        input_column: Expr,
+    },
+    /// Group the events that share a key into one record, e.g. `transaction by session_id`.
+    Transaction {
+        key_cols: Vec<Expr>,
+        key_col_headers: Vec<String>,
+        /// The longest a transaction can last, from its first event to its last.
+        max_span: Option<chrono::Duration>,
+        /// The longest gap there can be between two events of a transaction.
+        max_pause: Option<chrono::Duration>,
+        timestamp: Expr,
+    },
     FieldExpression {
         value: Expr,
         name: String,
     },
+    Rename {
+        pattern: RenamePattern,
+        replacement: String,
+    },
+    Join {
+        mode: JoinMode,
+        /// The file to run the operators over, or `None` for the same rows as the join, unless
+        /// the pipeline is given a file for joins to read.
+        path: Option<String>,
+        /// The operators whose results are joined, e.g. `json` to turn the lines of the other
+        /// file into records.
+        operators: Vec<Operator>,
+        keys: Vec<Expr>,
+    },
+    Lookup {
+        /// The columns of the table that are added to the record.
+        fields: Vec<String>,
+        path: String,
+        key: Expr,
+        /// The column of the table that's matched against the key.  It can be left out when the
+        /// key is a field with the same name.
+        column: Option<String>,
+    },
+    /// Run each record through a function exported by a WebAssembly module.
+    Wasm {
+        path: String,
+        /// The function to call, or `None` for `map`.
+        function: Option<String>,
+    },
+    /// Run a script on each record.
+    Script {
+        source: String,
+    },
+}
+
+/// Which fields `json` adds to the record.
+#[derive(Debug, PartialEq, Clone)]
+pub enum JsonMode {
+    /// Each top-level key of the object.
+    Fields,
+    /// Each value nested in the object under its dotted path, e.g. `spans.0.duration`.
+    Flatten,
+    /// The values at the given paths, e.g. `json path "spans[0].duration"`.
+    Paths(Vec<JsonPath>),
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct JsonPath {
+    /// The path as it was written.
+    pub path: String,
+    /// The path as a column of the object.
+    pub column: Expr,
+    pub name: String,
+}
+
+/// How `json` handles an object that has the same key more than once.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum DuplicateKeys {
+    First,
+    Last,
+    Array,
+}
+
+/// The framing `parse syslog` expects, when it isn't detected automatically.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SyslogFormat {
+    Rfc3164,
+    Rfc5424,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum JoinMode {
+    Inner,
+    Left,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SliceDuration {
+    Fixed(chrono::Duration),
+    /// Pick a duration once the span of the timestamps is known, e.g. `timeslice(ts) auto`.
+    Auto {
+        /// The most slices the span should be divided into.
+        buckets: usize,
+    },
+}
+
+pub const DEFAULT_AUTO_SLICES: usize = 100;
+
+/// The number of rows `limit`, `head`, and `tail` keep when it isn't given.
+pub const DEFAULT_LIMIT: i64 = 10;
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum RenamePattern {
+    Field(String),
+    Regex(String),
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -519,6 +776,10 @@ pub enum AggregateFunction {
     Sum {
         column: Expr,
     },
+    /// The arguments are the value to add up and the condition for adding it.
+    SumIf {
+        args: Option<Positioned<Vec<Expr>>>,
+    },
     Min {
         column: Expr,
     },
@@ -528,29 +789,83 @@ pub enum AggregateFunction {
     Max {
         column: Expr,
     },
+    /// The arguments are the value to minimize and the value to return.
+    MinBy {
+        args: Option<Positioned<Vec<Expr>>>,
+    },
+    MaxBy {
+        args: Option<Positioned<Vec<Expr>>>,
+    },
+    First {
+        column: Expr,
+    },
+    Last {
+        column: Expr,
+    },
+    /// The value to return from the row with the earliest timestamp in `by`.
+    Earliest {
+        column: Expr,
+        by: Expr,
+    },
+    Latest {
+        column: Expr,
+        by: Expr,
+    },
     Percentile {
         percentile: f64,
         percentile_str: String,
         column: Expr,
     },
+    StdDev {
+        column: Expr,
+    },
+    Variance {
+        column: Expr,
+    },
+    Median {
+        column: Expr,
+    },
+    Mode {
+        column: Expr,
+    },
+    /// The arguments are the value to collect and, optionally, the most values to keep.
+    Values {
+        args: Option<Positioned<Vec<Expr>>>,
+    },
     CountDistinct {
         column: Option<Positioned<Vec<Expr>>>,
+        /// Estimate the count with HyperLogLog instead of keeping every value.
+        approx: bool,
     },
     Error,
 }
 
 impl AggregateFunction {
-    fn default_name(&self) -> String {
+    pub(crate) fn default_name(&self) -> String {
         match self {
             AggregateFunction::Count { .. } => "_count".to_string(),
-            AggregateFunction::Sum { .. } => "_sum".to_string(),
+            AggregateFunction::Sum { .. } | AggregateFunction::SumIf { .. } => "_sum".to_string(),
             AggregateFunction::Min { .. } => "_min".to_string(),
             AggregateFunction::Average { .. } => "_average".to_string(),
             AggregateFunction::Max { .. } => "_max".to_string(),
+            AggregateFunction::MinBy { .. } => "_minBy".to_string(),
+            AggregateFunction::MaxBy { .. } => "_maxBy".to_string(),
+            AggregateFunction::First { .. } => "_first".to_string(),
+            AggregateFunction::Last { .. } => "_last".to_string(),
+            AggregateFunction::Earliest { .. } => "_earliest".to_string(),
+            AggregateFunction::Latest { .. } => "_latest".to_string(),
             AggregateFunction::Percentile {
                 ref percentile_str, ..
             } => format!("p{}", percentile_str),
-            AggregateFunction::CountDistinct { .. } => "_countDistinct".to_string(),
+            AggregateFunction::StdDev { .. } => "_stddev".to_string(),
+            AggregateFunction::Variance { .. } => "_variance".to_string(),
+            AggregateFunction::Median { .. } => "_median".to_string(),
+            AggregateFunction::Mode { .. } => "_mode".to_string(),
+            AggregateFunction::Values { .. } => "_values".to_string(),
+            AggregateFunction::CountDistinct { approx: false, .. } => "_countDistinct".to_string(),
+            AggregateFunction::CountDistinct { approx: true, .. } => {
+                "_countDistinctApprox".to_string()
+            }
             AggregateFunction::Error => "_err".to_string(),
         }
     }
@@ -569,6 +884,41 @@ pub struct SortOperator {
     pub direction: SortMode,
 }
 
+/// Pipelines that each see every row, e.g. `[where level == "error" | count; count]`, whose
+/// outputs are combined.
+#[derive(Debug, PartialEq, Clone)]
+pub struct BranchOperator {
+    pub branches: Vec<Vec<Operator>>,
+}
+
+/// A use of an operator registered by a plugin.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct PluginOperator {
+    pub keyword: String,
+    /// Everything after the keyword, up to the end of the operator.
+    pub args: Positioned<String>,
+}
+
+/// The `top` and `rare` operators.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct TopOperator {
+    pub mode: TopMode,
+    /// The number of values to output.
+    pub count: usize,
+    pub key_cols: Vec<Expr>,
+    pub key_col_headers: Vec<String>,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum TopMode {
+    /// `top`, the most frequent values.
+    Most,
+    /// `rare`, the least frequent values.
+    Least,
+}
+
+pub const DEFAULT_TOP_COUNT: usize = 10;
+
 #[derive(Debug, PartialEq, Clone)]
 pub struct Query {
     pub search: Search,
@@ -639,6 +989,35 @@ fn req_single_arg(description: &'static str) -> impl Clone + Fn(Span) -> IResult
     }
 }
 
+/// Parses the `(value by timestamp)` arguments of the `earliest` and `latest` aggregates
+fn by_args(name: &'static str) -> impl Clone + Fn(Span) -> IResult<Span, (Expr, Expr)> {
+    move |input: Span| {
+        expect_delimited(
+            tag("(").and(multispace0),
+            expr.and(
+                expect_fn(
+                    tag("by").delimited_by(multispace1).precedes(expr),
+                    |qc, r| {
+                        qc.report_error_for("expecting the timestamp to order the rows by")
+                            .with_code_range(r, "")
+                            .with_resolution(format!("example: {}(status by timestamp)", name))
+                            .send_report()
+                    },
+                )
+                .map(|by| by.unwrap_or(Expr::Error)),
+            ),
+            multispace0.and(tag(")")),
+            |qc, r| {
+                qc.report_error_for("unterminated function call")
+                    .with_code_range(r, "unterminated function call")
+                    .with_resolution("Insert a right parenthesis to terminate this call")
+                    .send_report()
+            },
+        )
+        .parse(input)
+    }
+}
+
 /// Combinator that checks for an optional keyword that should be followed by an expression
 fn kw_expr(
     keyword: &'static str,
@@ -750,6 +1129,163 @@ fn sort(input: Span) -> IResult<Span, Operator> {
     .parse(input)
 }
 
+fn top(input: Span) -> IResult<Span, Operator> {
+    tuple((
+        alt((
+            oper_0_args("top").map(|_| TopMode::Most),
+            oper_0_args("rare").map(|_| TopMode::Least),
+        )),
+        opt(map_res(digit1, |s: Span| s.fragment().parse::<usize>()).preceded_by(multispace1)),
+        expect_fn(
+            tag("by")
+                .delimited_by(multispace1)
+                .precedes(sourced_expr_list),
+            |qc, r| {
+                qc.report_error_for("expecting the values to count")
+                    .with_code_range(r, "")
+                    .with_resolution("eg. top 10 by url")
+                    .send_report()
+            },
+        ),
+    ))
+    .terminated(expect_pipe(
+        "unrecognized option, only the number of values and 'by' are available",
+    ))
+    .map(|(mode, count, cols)| {
+        let (key_col_headers, key_cols) = cols.unwrap_or_default().into_iter().unzip();
+        Operator::Top(TopOperator {
+            mode,
+            count: count.unwrap_or(DEFAULT_TOP_COUNT),
+            key_cols,
+            key_col_headers,
+        })
+    })
+    .parse(input)
+}
+
+fn dedup(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
+    with_pos(
+        tuple((
+            oper_0_args("dedup").precedes(opt(multispace1
+                .and(not(alt((end_of_query, tag("last="), tag("ttl=")))))
+                .precedes(separated_list1(tag(",").delimited_by(multispace0), expr)))),
+            opt(tag("last=")
+                .preceded_by(multispace1)
+                .precedes(map_res(digit1, |s: Span| s.fragment().parse::<usize>()))),
+            opt(tag("ttl=")
+                .preceded_by(multispace1)
+                .precedes(duration)
+                .and(opt(tag("on").delimited_by(multispace1).precedes(expr)))),
+        ))
+        .terminated(expect_pipe(
+            "unrecognized option, only the 'last', 'ttl', and 'on' options are available",
+        )),
+    )
+    .map(|Positioned { range, value }| {
+        let (key_cols, last, ttl) = value;
+        Positioned {
+            range,
+            value: InlineOperator::Dedup {
+                key_cols: key_cols.unwrap_or_default(),
+                last,
+                ttl: ttl.map(|(ttl, timestamp)| {
+                    (ttl, timestamp.unwrap_or_else(|| Expr::column("timestamp")))
+                }),
+            },
+        }
+    })
+    .parse(input)
+}
+
+fn geoip(input: Span) -> IResult<Span, Operator> {
+    with_pos(
+        oper_0_args("geoip")
+            .precedes(expect_fn(expr.preceded_by(multispace1), |qc, r| {
+                qc.report_error_for("expecting the IP address to look up")
+                    .with_code_range(r, "")
+                    .with_resolution("eg. geoip client_ip")
+                    .send_report()
+            }))
+            .terminated(expect_pipe("unrecognized option, `geoip` has no options")),
+    )
+    .map(|Positioned { range, value }| match value {
+        Some(input_column) => Operator::Inline(Positioned {
+            range,
+            value: InlineOperator::GeoIp { input_column },
+        }),
+        None => Operator::Error,
+    })
+    .parse(input)
+}
+
+fn transaction(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
+    with_pos(
+        tuple((
+            oper_0_args("transaction").precedes(expect_fn(
+                tag("by")
+                    .delimited_by(multispace1)
+                    .precedes(sourced_expr_list),
+                |qc, r| {
+                    qc.report_error_for("expecting the values that identify a transaction")
+                        .with_code_range(r, "")
+                        .with_resolution("eg. transaction by session_id maxpause=30m")
+                        .send_report()
+                },
+            )),
+            opt(tag("maxspan=").preceded_by(multispace1).precedes(duration)),
+            opt(tag("maxpause=").preceded_by(multispace1).precedes(duration)),
+            opt(tag("on").delimited_by(multispace1).precedes(expr)),
+        ))
+        .terminated(expect_pipe(
+            "unrecognized option, only the 'maxspan', 'maxpause', and 'on' options are available",
+        )),
+    )
+    .map(|Positioned { range, value }| {
+        let (cols, max_span, max_pause, timestamp) = value;
+        let (key_col_headers, key_cols) = cols.unwrap_or_default().into_iter().unzip();
+        Positioned {
+            range,
+            value: InlineOperator::Transaction {
+                key_cols,
+                key_col_headers,
+                max_span,
+                max_pause,
+                timestamp: timestamp.unwrap_or_else(|| Expr::column("timestamp")),
+            },
+        }
+    })
+    .parse(input)
+}
+
+fn sample(input: Span) -> IResult<Span, Operator> {
+    with_pos(
+        oper_0_args("sample")
+            .precedes(expect_fn(
+                with_pos(double).preceded_by(multispace1),
+                |qc, r| {
+                    qc.report_error_for("expecting the fraction of rows to keep")
+                        .with_code_range(r, "")
+                        .with_resolution("eg. sample 0.01")
+                        .send_report()
+                },
+            ))
+            .and(opt(tag("seed=").preceded_by(multispace1).precedes(
+                map_res(digit1, |s: Span| s.fragment().parse::<u64>()),
+            )))
+            .terminated(expect_pipe(
+                "unrecognized option, only the 'seed' option is available",
+            )),
+    )
+    .map(|Positioned { range, value }| match value {
+        (Some(rate), seed) => Operator::Inline(Positioned {
+            range,
+            value: InlineOperator::Sample { rate, seed },
+        }),
+        (None, _) => Operator::Error,
+    })
+    .parse(input)
+}
+
 fn filter_explicit_and(input: Span) -> IResult<Span, Option<Search>> {
     separated_pair(low_filter, tag("AND").delimited_by(multispace1), low_filter)
         .map(|p| match p {
@@ -793,8 +1329,16 @@ fn high_filter(input: Span) -> IResult<Span, Option<Search>> {
     alt((filter_explicit_or, mid_filter))(input)
 }
 
+/// Operators end at a vertical bar, the end of the query, the closing parenthesis of the
+/// operators given to a `join`, or the end of a branch.
 fn end_of_query(input: Span) -> IResult<Span, Span> {
-    peek(multispace0.precedes(alt((peek(tag("|")), eof))))(input)
+    peek(multispace0.precedes(alt((
+        peek(tag("|")),
+        peek(tag(")")),
+        peek(tag(";")),
+        peek(tag("]")),
+        eof,
+    ))))(input)
 }
 
 fn parse_search(input: Span) -> IResult<Span, Search> {
@@ -868,6 +1412,47 @@ fn index_access(input: Span) -> IResult<Span, DataAccessAtom> {
         .parse(input)
 }
 
+/// A path into a JSON object for `json path`, like `"spans[0].duration" as duration`.  The field
+/// is named after the last key of the path unless it's given a name.
+fn json_path(input: Span) -> IResult<Span, JsonPath> {
+    tuple((
+        with_pos(quoted_string),
+        opt(tag("as").delimited_by(multispace1).precedes(ident)),
+    ))
+    .map(|(path, name)| {
+        let column = LocatedSpan::new_extra(path.value.as_str(), input.extra);
+        let column = match all_consuming(column_ref).parse(column) {
+            Ok((_, column)) => column,
+            Err(_) => {
+                input
+                    .extra
+                    .report_error_for("invalid JSON path")
+                    .with_code_range(path.range.clone(), "")
+                    .with_resolution("use keys and indexes, eg. \"spans[0].duration\"")
+                    .send_report();
+                Expr::Error
+            }
+        };
+        let name = name.unwrap_or_else(|| match &column {
+            Expr::Column { head, rest } => std::iter::once(head)
+                .chain(rest)
+                .rev()
+                .find_map(|atom| match atom {
+                    DataAccessAtom::Key(key) => Some(key.clone()),
+                    DataAccessAtom::Index(_) => None,
+                })
+                .unwrap_or_default(),
+            _ => String::new(),
+        });
+        JsonPath {
+            path: path.value,
+            column,
+            name,
+        }
+    })
+    .parse(input)
+}
+
 fn column_ref(input: Span) -> IResult<Span, Expr> {
     tuple((ident, many0(alt((dot_property, index_access)))))
         .map(|(head, rest)| Expr::Column {
@@ -899,7 +1484,8 @@ fn escaped_ident(input: Span) -> IResult<Span, String> {
 
 /// Parses the basic unit of an expression
 fn atomic(input: Span) -> IResult<Span, Expr> {
-    let num = digit1.map(|s: Span| data::Value::from_string(*s.fragment()));
+    let num = recognize(digit1.and(opt(tag(".").and(digit1))))
+        .map(|s: Span| data::Value::from_string(*s.fragment()));
     let bool_lit = alt((
         tag("true").map(|_| data::Value::Bool(true)),
         tag("false").map(|_| data::Value::Bool(false)),
@@ -1018,21 +1604,72 @@ fn arith_expr(input: Span) -> IResult<Span, Expr> {
 }
 
 fn cmp_expr(input: Span) -> IResult<Span, Expr> {
+    /// What can follow the left-hand-side of a comparison.
+    enum Rhs {
+        Comparison(ComparisonOp, Option<Expr>),
+        In(Option<Vec<Expr>>),
+        Match(Option<String>),
+    }
+
+    let comparison = pair(
+        delimited(multispace0, comp_op, multispace0),
+        expect(
+            arith_expr,
+            "expecting the right-hand-side of the comparison",
+        ),
+    )
+    .map(|(op, right)| Rhs::Comparison(op, right));
+    let in_list = tag("in")
+        .delimited_by(multispace1)
+        .terminated(tag("("))
+        .precedes(expect(
+            |i| {
+                separated_list1(tag(","), expr)
+                    .terminated(tag(")").preceded_by(multispace0))
+                    .parse(i)
+            },
+            "expecting a list of values, eg. status in (500, 503)",
+        ))
+        .map(Rhs::In);
+    let regex_match = tag("=~")
+        .delimited_by(multispace0)
+        .precedes(expect(
+            with_pos(regex_literal),
+            "expecting a regular expression, eg. msg =~ /timeout|refused/",
+        ))
+        .map(|regex| {
+            Rhs::Match(
+                regex.and_then(|regex| match regex::Regex::new(&regex.value) {
+                    Ok(_) => Some(regex.value),
+                    Err(regex_err) => {
+                        input
+                            .extra
+                            .report_error_for("invalid regular expression")
+                            .with_code_range(regex.range, format!("{}", regex_err))
+                            .send_report();
+                        None
+                    }
+                }),
+            )
+        });
     let cmp = map(
-        arith_expr.and(opt(pair(
-            delimited(multispace0, comp_op, multispace0),
-            expect(
-                arith_expr,
-                "expecting the right-hand-side of the comparison",
-            ),
-        ))),
+        arith_expr.and(opt(alt((regex_match, comparison, in_list)))),
         |(left, opt_right)| match opt_right {
             None => left,
-            Some((op, right)) => Expr::Binary {
+            Some(Rhs::Comparison(op, right)) => Expr::Binary {
                 left: Box::new(left),
                 op: BinaryOp::Comparison(op),
                 right: Box::new(right.unwrap_or(Expr::Error)),
             },
+            Some(Rhs::In(Some(list))) => Expr::In {
+                value: Box::new(left),
+                list,
+            },
+            Some(Rhs::Match(Some(regex))) => Expr::Match {
+                value: Box::new(left),
+                regex,
+            },
+            Some(Rhs::In(None) | Rhs::Match(None)) => Expr::Error,
         },
     );
 
@@ -1243,25 +1880,40 @@ fn req_quoted_string(input: Span) -> IResult<Span, String> {
     }
 }
 
-fn var_list(input: Span) -> IResult<Span, Vec<String>> {
-    separated_list1(tag(","), ident.preceded_by(multispace0))(input)
-}
-
 fn parse(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
     with_pos(
         tuple((
             tag("parse").precedes(multispace1),
-            opt(tag("regex").precedes(multispace1)),
+            opt(alt((tag("regex"), tag("grok"))).terminated(multispace1)),
             with_pos(req_quoted_string),
             opt(multispace1.precedes(with_pos(pair(tag("from"), multispace1).precedes(expr)))),
-            opt(with_pos(tag("as").preceded_by(multispace1).precedes(var_list))),
+            opt(with_pos(tag("as").preceded_by(multispace1).precedes(typed_var_list))),
             opt(multispace1.precedes(with_pos(pair(tag("from"), multispace1).precedes(expr)))),
             opt(tag("nodrop").preceded_by(multispace1)).map(|nd| nd.is_some()),
             opt(tag("noconvert").preceded_by(multispace1)).map(|nd| nd.is_some()),
         ))
-        .map(|(_p, is_regex, s, from_col_before, user_fields_opt, from_col_after, no_drop, no_convert)| {
-            let (pattern, fields) = if is_regex.is_some() {
-                let named_fields: Vec<String> = match regex::Regex::new(&s.value) {
+        .map(|(_p, kind, s, from_col_before, user_fields_opt, from_col_after, no_drop, no_convert)| {
+            let (pattern, fields, casts) = if let Some(kind) = kind {
+                let (pattern, casts) = if *kind.fragment() == "grok" {
+                    match grok::Patterns::load().and_then(|patterns| patterns.compile(&s.value)) {
+                        Ok(grok) => (Keyword::new_grok(s.value.clone(), grok.regex), grok.casts),
+                        Err(grok_err) => {
+                            input
+                                .extra
+                                .report_error_for("invalid grok pattern")
+                                .with_code_range(s.range.clone(), format!("{}", grok_err))
+                                .send_report();
+                            (Keyword::new_grok(s.value.clone(), String::new()), Vec::new())
+                        }
+                    }
+                } else {
+                    (Keyword::new_regex(s.value.clone()), Vec::new())
+                };
+                let regex_str = match &pattern.1 {
+                    KeywordType::Grok(regex) => regex,
+                    _ => &s.value,
+                };
+                let named_fields: Vec<String> = match regex::Regex::new(regex_str) {
                     Err(regex_err) => {
                         input
                             .extra
@@ -1290,7 +1942,7 @@ fn parse(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
                         } else if let Some(user_fields) = user_fields_opt {
                             input
                                 .extra
-                                .report_error_for("the parse regex operator does not support an 'as' clause")
+                                .report_error_for(format!("the parse {} operator does not support an 'as' clause", kind.fragment()))
                                 .with_code_range(user_fields.range, "remove this")
                                 .with_resolution("remove the 'as' clause, the named capture groups in the regular expression determine the output column")
                                 .send_report();
@@ -1301,11 +1953,17 @@ fn parse(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
                     }
                 };
 
-                (Keyword::new_regex(s.value), named_fields)
+                (pattern, named_fields, casts)
             } else {
+                let typed_fields = user_fields_opt.map(|user_fields| user_fields.value).unwrap_or_default();
+                let casts = typed_fields
+                    .iter()
+                    .filter_map(|(name, tpe)| tpe.map(|tpe| (name.clone(), tpe)))
+                    .collect();
                 (
                     Keyword::new_wildcard(s.value),
-                    user_fields_opt.map(|user_fields| user_fields.value).unwrap_or_default(),
+                    typed_fields.into_iter().map(|(name, _)| name).collect(),
+                    casts,
                 )
             };
 
@@ -1314,16 +1972,166 @@ fn parse(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
                 fields,
                 input_column: (from_col_before, from_col_after),
                 no_drop,
-                no_convert
+                no_convert,
+                casts,
+            }
+        }),
+    )
+    .terminated(expect_pipe(
+        "unrecognized option, only the 'from' and 'nodrop' options are available",
+    ))
+    .parse(input)
+}
+
+fn parse_csv(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
+    with_pos(
+        tuple((
+            tag("parse")
+                .precedes(multispace1)
+                .precedes(oper_0_args("csv"))
+                .precedes(kw_expr("from", "a delimited string")),
+            opt(tag("as").preceded_by(multispace1).precedes(typed_var_list)),
+            opt(tag("headers=").preceded_by(multispace1).precedes(expect_fn(
+                alt((tag("true").map(|_| true), tag("false").map(|_| false))),
+                |qc, r| {
+                    qc.report_error_for("expecting whether the first line is a header")
+                        .with_code_range(r, "")
+                        .with_resolution("use one of 'true' or 'false'")
+                        .send_report()
+                },
+            ))),
+            opt(tag("delimiter=")
+                .preceded_by(multispace1)
+                .precedes(with_pos(req_quoted_string))),
+            opt(tag("nodrop").preceded_by(multispace1)).map(|nd| nd.is_some()),
+        ))
+        .map(|(input_column, typed_fields, headers, delimiter, no_drop)| {
+            let delimiter = match delimiter {
+                None => ',',
+                Some(delimiter) => {
+                    let mut chars = delimiter.value.chars();
+                    match (chars.next(), chars.next()) {
+                        (Some(c), None) if c != '"' => c,
+                        _ => {
+                            input
+                                .extra
+                                .report_error_for("the delimiter must be a single character")
+                                .with_code_range(delimiter.range, "")
+                                .with_resolution("use a character other than a double quote, like \",\" or \"\\t\"")
+                                .send_report();
+                            ','
+                        }
+                    }
+                }
+            };
+            let typed_fields = typed_fields.unwrap_or_default();
+            let casts = typed_fields
+                .iter()
+                .filter_map(|(name, tpe)| tpe.map(|tpe| (name.clone(), tpe)))
+                .collect();
+            InlineOperator::Csv {
+                fields: typed_fields.into_iter().map(|(name, _)| name).collect(),
+                casts,
+                input_column,
+                headers: headers.flatten().unwrap_or(false),
+                delimiter,
+                no_drop,
             }
         }),
     )
+    .terminated(expect_pipe(
+        "unrecognized option, only the 'from', 'as', 'headers', 'delimiter' and 'nodrop' options are available",
+    ))
+    .parse(input)
+}
+
+fn parse_syslog(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
+    with_pos(
+        tuple((
+            tag("parse")
+                .precedes(multispace1)
+                .precedes(oper_0_args("syslog"))
+                .precedes(kw_expr("from", "a syslog message")),
+            opt(tag("format=").preceded_by(multispace1).precedes(expect_fn(
+                alt((
+                    tag("rfc3164").map(|_| SyslogFormat::Rfc3164),
+                    tag("rfc5424").map(|_| SyslogFormat::Rfc5424),
+                )),
+                |qc, r| {
+                    qc.report_error_for("expecting the syslog format")
+                        .with_code_range(r, "")
+                        .with_resolution("use one of 'rfc3164' or 'rfc5424'")
+                        .send_report()
+                },
+            ))),
+            opt(tag("nodrop").preceded_by(multispace1)).map(|nd| nd.is_some()),
+        ))
+        .map(|(input_column, format, no_drop)| InlineOperator::Syslog {
+            input_column,
+            format: format.flatten(),
+            no_drop,
+        }),
+    )
+    .terminated(expect_pipe(
+        "unrecognized option, only the 'from', 'format' and 'nodrop' options are available",
+    ))
+    .parse(input)
+}
+
+fn parse_accesslog(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
+    with_pos(
+        tuple((
+            tag("parse")
+                .precedes(multispace1)
+                .precedes(oper_0_args("accesslog"))
+                .precedes(kw_expr("from", "an access log line")),
+            opt(tag("nodrop").preceded_by(multispace1)).map(|nd| nd.is_some()),
+        ))
+        .map(|(input_column, no_drop)| InlineOperator::AccessLog {
+            input_column,
+            no_drop,
+        }),
+    )
+    .terminated(expect_pipe(
+        "unrecognized option, only the 'from' and 'nodrop' options are available",
+    ))
+    .parse(input)
+}
+
+fn parse_url(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
+    with_pos(
+        tuple((
+            tag("parse")
+                .precedes(multispace1)
+                .precedes(oper_0_args("url"))
+                .precedes(kw_expr("from", "a URL")),
+            opt(tag("nodrop").preceded_by(multispace1)).map(|nd| nd.is_some()),
+        ))
+        .map(|(input_column, no_drop)| InlineOperator::Url {
+            input_column,
+            no_drop,
+        }),
+    )
     .terminated(expect_pipe(
         "unrecognized option, only the 'from' and 'nodrop' options are available",
     ))
     .parse(input)
 }
 
+fn parse_useragent(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
+    with_pos(
+        tag("parse")
+            .precedes(multispace1)
+            .precedes(oper_0_args("useragent"))
+            .precedes(kw_expr("from", "a user agent"))
+            .map(|input_column| InlineOperator::UserAgent { input_column }),
+    )
+    .terminated(expect_pipe(
+        "unrecognized option, only the 'from' option is available",
+    ))
+    .parse(input)
+}
+
 fn fields_mode(input: Span) -> IResult<Span, FieldMode> {
     alt((
         alt((tag("+"), tag("only"), tag("include"))).map(|_| FieldMode::Only),
@@ -1331,44 +2139,432 @@ fn fields_mode(input: Span) -> IResult<Span, FieldMode> {
     ))(input)
 }
 
+/// Parses a field name pattern with a `*` wildcard, e.g. `request_*`.
+fn field_pattern(input: Span) -> IResult<Span, String> {
+    verify(
+        take_while1(|c| is_ident(c) || c == '.' || c == '*'),
+        |name: &Span| name.fragment().contains('*'),
+    )
+    .map(|name: Span| name.fragment().to_string())
+    .parse(input)
+}
+
+/// Parses a field name or pattern with an optional `:type` suffix
+fn typed_var(input: Span) -> IResult<Span, (String, Option<data::ValueType>)> {
+    alt((field_pattern, ident))
+        .and(opt(tag(":").precedes(with_pos(bare_ident))))
+        .map(|(name, opt_type)| match opt_type {
+            None => (name, None),
+            Some(type_name) => match data::ValueType::from_name(&type_name.value) {
+                Some(tpe) => (name, Some(tpe)),
+                None => {
+                    input
+                        .extra
+                        .report_error_for(format!("unknown type: {}", type_name.value))
+                        .with_code_pointer(&type_name, "")
+                        .with_resolution(format!(
+                            "valid types are: {}",
+                            data::ValueType::NAMES.join(", ")
+                        ))
+                        .send_report();
+                    (name, None)
+                }
+            },
+        })
+        .parse(input)
+}
+
+fn typed_var_list(input: Span) -> IResult<Span, Vec<(String, Option<data::ValueType>)>> {
+    separated_list1(tag(","), typed_var.preceded_by(multispace0))(input)
+}
+
 fn fields(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
     with_pos(
         tuple((
             tag("fields")
                 .precedes(multispace1)
                 .precedes(opt(fields_mode).map(|m| m.unwrap_or(FieldMode::Only))),
-            var_list,
+            typed_var_list,
         ))
-        .map(|(mode, fields)| InlineOperator::Fields { mode, fields }),
+        .map(|(mode, typed_fields)| {
+            let casts = typed_fields
+                .iter()
+                .filter_map(|(name, tpe)| tpe.map(|tpe| (name.clone(), tpe)))
+                .collect();
+            InlineOperator::Fields {
+                mode,
+                fields: typed_fields.into_iter().map(|(name, _)| name).collect(),
+                casts,
+            }
+        }),
     )
     .parse(input)
 }
 
-fn pct(input: Span) -> IResult<Span, Positioned<AggregateFunction>> {
+/// Parses a regular expression delimited by slashes, e.g. `/^svc_(.+)$/`.  A slash in the
+/// expression can be escaped with a backslash.
+fn regex_literal(input: Span) -> IResult<Span, String> {
+    let re_esc = escaped(none_of("\\/"), '\\', anychar);
+    delimited(tag("/"), alt((re_esc, tag(""))), tag("/"))
+        .map(|re: Span| re.fragment().replace("\\/", "/"))
+        .parse(input)
+}
+
+fn rename(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
+    let bare_replacement = take_while1(|c: char| !c.is_whitespace() && c != '|')
+        .map(|s: Span| s.fragment().to_string());
+    let pattern = alt((
+        with_pos(regex_literal).map(|re| {
+            if let Err(regex_err) = regex::Regex::new(&re.value) {
+                input
+                    .extra
+                    .report_error_for("invalid regular expression")
+                    .with_code_range(re.range, format!("{}", regex_err))
+                    .send_report();
+            }
+            RenamePattern::Regex(re.value)
+        }),
+        ident.map(RenamePattern::Field),
+    ));
+    let new_name = tag("as")
+        .delimited_by(multispace1)
+        .precedes(alt((quoted_string, bare_replacement)));
     with_pos(
-        alt((tag("pct"), tag("percentile"), tag("p")))
-            .precedes(with_pos(digit1))
-            .and(req_single_arg("the value to compute the percentile of"))
-            .map(|(pct_pos, column)| match pct_pos.value.parse::<f64>() {
-                Ok(pct) if pct > 0.0 && pct < 100.0 => AggregateFunction::Percentile {
-                    column,
-                    percentile: pct / 100.0,
-                    percentile_str: pct.to_string(),
-                },
-                _ => {
-                    input
-                        .extra
-                        .report_error_for(format!("invalid percentile number: {}", pct_pos.value))
-                        .with_code_range(pct_pos.range, "expecting a number between (0, 100)")
+        oper_0_args("rename")
+            .precedes(expect_fn(
+                multispace1.precedes(pattern).and(new_name),
+                |qc, r| {
+                    qc.report_error_for("expecting the field to rename and its new name")
+                        .with_code_range(r, "")
+                        .with_resolution("eg. rename old_name as new_name")
+                        .with_resolution("or with a regex, eg. rename /^svc_(.+)$/ as $1")
                         .send_report();
-
-                    AggregateFunction::Error
+                },
+            ))
+            .map(|rename| {
+                let (pattern, replacement) =
+                    rename.unwrap_or((RenamePattern::Field(String::new()), String::new()));
+                InlineOperator::Rename {
+                    pattern,
+                    replacement,
                 }
             }),
     )
+    .terminated(expect_pipe(
+        "unrecognized option, only a single 'as' clause is available",
+    ))
+    .parse(input)
+}
+
+/// Pipelines that each see every row, e.g. `[where level == "error" | count; count]`.  A
+/// bracket that's followed by a quote is an escaped field name instead, like `["user id"]`.
+fn branch<'a>(input: Span<'a>, aliases: &AliasCollection) -> IResult<Span<'a>, Operator> {
+    expect_delimited(
+        tag("[").terminated(not(multispace0.and(alt((tag("\""), tag("'")))))),
+        separated_list1(tag(";"), |input| parse_operators(input, aliases)),
+        tag("]").preceded_by(multispace0),
+        |qc, r| {
+            qc.report_error_for("unterminated branches")
+                .with_code_range(r, "")
+                .with_resolution("Insert a right square bracket after the last branch")
+                .send_report()
+        },
+    )
+    .map(|branches| Operator::Branch(BranchOperator { branches }))
+    .terminated(expect_pipe("unrecognized syntax after the branches"))
     .parse(input)
 }
 
+/// The arguments to a plugin's operator: everything up to the `|` that ends it, or the end of the
+/// branch or `join` it's in.  Separators inside quotes or brackets are part of the arguments.
+fn plugin_args(input: Span) -> IResult<Span, Span> {
+    let mut depth = 0;
+    let mut quote = None;
+    let mut escaped = false;
+    let end = input.fragment().char_indices().find(|&(_, c)| {
+        match quote {
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None => match c {
+                '"' | '\'' => quote = Some(c),
+                '(' | '[' | '{' => depth += 1,
+                ')' | ']' | '}' if depth == 0 => return true,
+                ')' | ']' | '}' => depth -= 1,
+                '|' | ';' if depth == 0 => return true,
+                _ => {}
+            },
+        }
+        false
+    });
+    let end = end.map_or(input.fragment().len(), |(i, _)| i);
+    Ok((input.slice(end..), input.slice(..end)))
+}
+
+/// An operator registered by a plugin, e.g. `acme_log strict`.
+fn plugin(input: Span) -> IResult<Span, Operator> {
+    let plugins = &input.extra.plugins;
+    with_pos(
+        verify(bare_ident, move |keyword: &String| {
+            plugins.get(keyword).is_some()
+        })
+        .and(plugin_args)
+        .map(|(keyword, args)| {
+            let trimmed = args.fragment().trim_start();
+            let start = args.location_offset() + args.fragment().len() - trimmed.len();
+            let trimmed = trimmed.trim_end();
+            PluginOperator {
+                keyword,
+                args: Positioned {
+                    range: start..start + trimmed.len(),
+                    value: trimmed.to_string(),
+                },
+            }
+        }),
+    )
+    .map(Operator::Plugin)
+    .parse(input)
+}
+
+/// A join against the results of other operators, run over another file or, without one, over
+/// the same rows as the join.
+fn join<'a>(
+    input: Span<'a>,
+    aliases: &AliasCollection,
+) -> IResult<Span<'a>, Positioned<InlineOperator>> {
+    let mode = opt(multispace1.precedes(alt((
+        tag("left").map(|_| JoinMode::Left),
+        tag("inner").map(|_| JoinMode::Inner),
+    ))))
+    .map(|mode| mode.unwrap_or(JoinMode::Inner));
+    let path = opt(multispace1.precedes(quoted_string));
+    let operators = expect_delimited(
+        tag("("),
+        |input| parse_operators(input, aliases),
+        tag(")").preceded_by(multispace0),
+        |qc, r| {
+            qc.report_error_for("unterminated operators for the join")
+                .with_code_range(r, "")
+                .with_resolution("Insert a right parenthesis after the operators")
+                .send_report()
+        },
+    );
+    let using = opt(tag("using").delimited_by(multispace1).precedes(operators));
+    let keys = expect_fn(
+        tag("on")
+            .delimited_by(multispace1)
+            .precedes(separated_list1(tag(",").delimited_by(multispace0), expr)),
+        |qc, r| {
+            qc.report_error_for("expecting the keys to join on")
+                .with_code_range(r, "")
+                .with_resolution(r#"eg. join "users.log" using (json) on user_id"#)
+                .send_report()
+        },
+    );
+    with_pos(oper_0_args("join").precedes(tuple((mode, path, using, keys))))
+        .map(|Positioned { range, value }| {
+            let (mode, path, operators, keys) = value;
+            if operators.is_none() {
+                input
+                    .extra
+                    .report_error_for("expecting the operators to run for the join")
+                    .with_code_range(range.clone(), "")
+                    .with_resolution(r#"eg. join "users.log" using (json) on user_id"#)
+                    .send_report();
+            }
+            Positioned {
+                range,
+                value: InlineOperator::Join {
+                    mode,
+                    path,
+                    operators: operators.unwrap_or_default(),
+                    keys: keys.unwrap_or_else(|| vec![Expr::Error]),
+                },
+            }
+        })
+        .terminated(expect_pipe(
+            "unrecognized option, only 'using' and 'on' are available",
+        ))
+        .parse(input)
+}
+
+/// Parses a file name that isn't quoted, e.g. `statuses.csv`.
+fn bare_path(input: Span) -> IResult<Span, String> {
+    take_while1(|c: char| !c.is_whitespace() && !matches!(c, '|' | ')' | '"' | '\''))
+        .map(|span: Span| span.fragment().to_string())
+        .parse(input)
+}
+
+fn lookup(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
+    const EXAMPLE: &str = r#"eg. lookup status_name from "statuses.csv" on status"#;
+    let fields = opt(separated_list1(
+        tag(","),
+        not(tag("from").terminated(multispace1))
+            .precedes(ident)
+            .preceded_by(multispace0),
+    ));
+    let path = expect_fn(
+        tag("from")
+            .preceded_by(multispace0)
+            .terminated(multispace1)
+            .precedes(alt((quoted_string, bare_path))),
+        |qc, r| {
+            qc.report_error_for("expecting the file containing the table")
+                .with_code_range(r, "")
+                .with_resolution(EXAMPLE)
+                .send_report()
+        },
+    );
+    let key = expect_fn(
+        tag("on").delimited_by(multispace1).precedes(expr),
+        |qc, r| {
+            qc.report_error_for("expecting the key to look up")
+                .with_code_range(r, "")
+                .with_resolution(EXAMPLE)
+                .send_report()
+        },
+    );
+    let column = opt(tag("=").delimited_by(multispace0).precedes(req_ident));
+    with_pos(
+        oper_0_args("lookup").precedes(expect_fn(
+            multispace1
+                .terminated(not(alt((tag("|"), eof))))
+                .precedes(tuple((fields, path, key, column))),
+            |qc, r| {
+                qc.report_error_for(
+                    "expecting the columns to add from a table and the key to look up",
+                )
+                .with_code_range(r, "")
+                .with_resolution(EXAMPLE)
+                .send_report()
+            },
+        )),
+    )
+    .map(|Positioned { range, value }| {
+        let (fields, path, key, column) = value.unwrap_or((Some(vec![]), None, None, None));
+        if fields.is_none() {
+            input
+                .extra
+                .report_error_for("expecting the columns to add from the table")
+                .with_code_range(range.clone(), "")
+                .with_resolution(EXAMPLE)
+                .send_report();
+        }
+        Positioned {
+            range,
+            value: InlineOperator::Lookup {
+                fields: fields.unwrap_or_default(),
+                path: path.unwrap_or_default(),
+                key: key.unwrap_or(Expr::Error),
+                column,
+            },
+        }
+    })
+    .terminated(expect_pipe(
+        "unrecognized option, only 'from' and 'on' are available",
+    ))
+    .parse(input)
+}
+
+/// `wasm plugin.wasm [function]`.  Unlike other operators, it can be followed by a parenthesis,
+/// which makes it the `wasm(...)` function of an expression instead.
+fn wasm(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
+    with_pos(
+        tag("wasm")
+            .terminated(alt((peek(multispace1), end_of_query)))
+            .precedes(expect_fn(
+                multispace1
+                    .terminated(not(alt((tag("|"), eof))))
+                    .precedes(alt((quoted_string, bare_path))),
+                |qc, r| {
+                    qc.report_error_for("expecting the WebAssembly module to run")
+                        .with_code_range(r, "")
+                        .with_resolution("eg. wasm my_plugin.wasm")
+                        .send_report()
+                },
+            ))
+            .and(opt(multispace1.precedes(bare_ident)))
+            .terminated(expect_pipe(
+                "unrecognized option, only the function to call can be given",
+            ))
+            .map(|(path, function)| InlineOperator::Wasm {
+                path: path.unwrap_or_default(),
+                function,
+            }),
+    )
+    .parse(input)
+}
+
+/// `script '<source>'`
+fn script(input: Span) -> IResult<Span, Positioned<InlineOperator>> {
+    with_pos(
+        tag("script")
+            .terminated(alt((peek(multispace1), end_of_query)))
+            .precedes(expect_fn(
+                multispace1
+                    .terminated(not(alt((tag("|"), eof))))
+                    .precedes(quoted_string),
+                |qc, r| {
+                    qc.report_error_for("expecting the script to run, as a quoted string")
+                        .with_code_range(r, "")
+                        .with_resolution("eg. script 'rec.slow = rec.ms > 1000'")
+                        .send_report()
+                },
+            ))
+            .terminated(expect_pipe(
+                "unrecognized option, only the script can be given",
+            ))
+            .map(|source| InlineOperator::Script {
+                source: source.unwrap_or_default(),
+            }),
+    )
+    .parse(input)
+}
+
+/// Builds a percentile aggregate, reporting an error if `pct` isn't a number in (0, 100)
+fn percentile(input: Span, pct: &str, range: QueryRange, column: Expr) -> AggregateFunction {
+    match pct.parse::<f64>() {
+        Ok(pct) if pct > 0.0 && pct < 100.0 => AggregateFunction::Percentile {
+            column,
+            percentile: pct / 100.0,
+            percentile_str: pct.to_string(),
+        },
+        _ => {
+            input
+                .extra
+                .report_error_for(format!("invalid percentile number: {}", pct))
+                .with_code_range(range, "expecting a number between (0, 100)")
+                .send_report();
+
+            AggregateFunction::Error
+        }
+    }
+}
+
+fn pct(input: Span) -> IResult<Span, Positioned<AggregateFunction>> {
+    let digits = alt((tag("pct"), tag("percentile"), tag("p")))
+        .precedes(with_pos(digit1))
+        .and(req_single_arg("the value to compute the percentile of"))
+        .map(|(pct_pos, column)| percentile(input, &pct_pos.value, pct_pos.range, column));
+    let args = alt((tag("pct"), tag("percentile")))
+        .precedes(with_pos(arg_list))
+        .map(|args| match args.value.as_slice() {
+            [column, pct] => percentile(input, &pct.to_string(), args.range, column.clone()),
+            _ => {
+                input
+                    .extra
+                    .report_error_for("expecting the value and the percentile to compute")
+                    .with_code_range(args.range, "2 arguments are needed")
+                    .with_resolution("example: pct(latency, 99.9)")
+                    .send_report();
+
+                AggregateFunction::Error
+            }
+        });
+    with_pos(alt((digits, args))).parse(input)
+}
+
 pub fn with_pos<'a, O, E: ParseError<Span<'a>>, F>(
     mut f: F,
 ) -> impl FnMut(Span<'a>) -> IResult<Span<'a>, Positioned<O>, E>
@@ -1425,6 +2621,7 @@ fn skip_to_end_of_query(input: Span) -> IResult<Span, Operator> {
 
 struct ValidOperators<'a> {
     aliases: &'a AliasCollection<'a>,
+    plugins: &'a Plugins,
 }
 
 impl<'a> ValidOperators<'a> {
@@ -1436,6 +2633,7 @@ impl<'a> ValidOperators<'a> {
         self.valid_aggs()
             .chain(VALID_INLINE.iter().copied())
             .chain(self.aliases.valid_aliases())
+            .chain(self.plugins.keywords())
     }
 }
 
@@ -1445,11 +2643,55 @@ fn parse_operators<'a>(
 ) -> IResult<Span<'a>, Vec<Operator>> {
     let json = with_pos(
         oper_0_args("json")
-            .precedes(kw_expr("from", "a JSON-encoded string"))
+            .precedes(tuple((
+                opt(alt((
+                    tag("flatten")
+                        .preceded_by(multispace1)
+                        .map(|_| JsonMode::Flatten),
+                    tag("path")
+                        .preceded_by(multispace1)
+                        .precedes(expect_fn(
+                            separated_list1(
+                                tag(",").delimited_by(multispace0),
+                                json_path.preceded_by(multispace0),
+                            )
+                            .preceded_by(multispace1),
+                            |qc, r| {
+                                qc.report_error_for("expecting the paths of the values to extract")
+                                    .with_code_range(r, "")
+                                    .with_resolution("eg. json path \"spans[0].duration\" as duration")
+                                    .send_report()
+                            },
+                        ))
+                        .map(|paths| JsonMode::Paths(paths.unwrap_or_default())),
+                ))),
+                kw_expr("from", "a JSON-encoded string"),
+                opt(tag("duplicates=").preceded_by(multispace1).precedes(expect_fn(
+                    alt((
+                        tag("first").map(|_| DuplicateKeys::First),
+                        tag("last").map(|_| DuplicateKeys::Last),
+                        tag("array").map(|_| DuplicateKeys::Array),
+                    )),
+                    |qc, r| {
+                        qc.report_error_for("expecting how to handle duplicate keys")
+                            .with_code_range(r, "")
+                            .with_resolution("use one of 'first', 'last' or 'array'")
+                            .send_report()
+                    },
+                ))),
+                opt(tag("warn").preceded_by(multispace1)),
+            )))
             .terminated(expect_pipe(
-                "unrecognized option, only the 'from' option is available",
+                "unrecognized option, only the 'from', 'duplicates' and 'warn' options are available",
             ))
-            .map(|input_column| InlineOperator::Json { input_column }),
+            .map(
+                |(mode, input_column, duplicates, warn)| InlineOperator::Json {
+                    input_column,
+                    mode: mode.unwrap_or(JsonMode::Fields),
+                    duplicates: duplicates.flatten().unwrap_or(DuplicateKeys::Last),
+                    warn_duplicates: warn.is_some(),
+                },
+            ),
     );
     let limit = with_pos(
         oper_0_args("limit")
@@ -1459,44 +2701,167 @@ fn parse_operators<'a>(
             ))
             .map(|count| InlineOperator::Limit { count }),
     );
+    // `head N` and `tail N` are another way to write `limit N` and `limit -N`.
+    let head_tail = with_pos(
+        with_pos(alt((
+            oper_0_args("head").map(|_| 1.0),
+            oper_0_args("tail").map(|_| -1.0),
+        )))
+        .and(opt(with_pos(map_res(digit1, |s: Span| {
+            s.fragment().parse::<u32>()
+        }))
+        .preceded_by(multispace1)))
+        .terminated(expect_pipe(
+            "unrecognized option, only the number of rows can be specified",
+        ))
+        .map(|(sign, count)| {
+            let count = match count {
+                Some(count) => Positioned {
+                    range: count.range,
+                    value: sign.value * f64::from(count.value),
+                },
+                None => Positioned {
+                    range: sign.range,
+                    value: sign.value * DEFAULT_LIMIT as f64,
+                },
+            };
+            InlineOperator::Limit { count: Some(count) }
+        }),
+    );
     let logfmt = with_pos(
         oper_0_args("logfmt")
-            .precedes(kw_expr("from", "a logfmt-serialized string"))
+            .precedes(tuple((
+                kw_expr("from", "a logfmt-serialized string"),
+                opt(tag("strict=").preceded_by(multispace1).precedes(expect_fn(
+                    alt((tag("true").map(|_| true), tag("false").map(|_| false))),
+                    |qc, r| {
+                        qc.report_error_for("expecting whether to be strict")
+                            .with_code_range(r, "")
+                            .with_resolution("use 'true' or 'false'")
+                            .send_report()
+                    },
+                ))),
+                opt(tag("keys=").preceded_by(multispace1).precedes(expect_delimited(
+                    tag("(").and(multispace0),
+                    separated_list1(tag(",").delimited_by(multispace0), ident),
+                    multispace0.and(tag(")")),
+                    |qc, r| {
+                        qc.report_error_for("expecting the keys to extract")
+                            .with_code_range(r, "")
+                            .with_resolution("eg. logfmt keys=(msg, level)")
+                            .send_report()
+                    },
+                ))),
+                opt(tag("duplicates=").preceded_by(multispace1).precedes(expect_fn(
+                    alt((
+                        tag("first").map(|_| DuplicateKeys::First),
+                        tag("last").map(|_| DuplicateKeys::Last),
+                        tag("array").map(|_| DuplicateKeys::Array),
+                    )),
+                    |qc, r| {
+                        qc.report_error_for("expecting how to handle duplicate keys")
+                            .with_code_range(r, "")
+                            .with_resolution("use one of 'first', 'last' or 'array'")
+                            .send_report()
+                    },
+                ))),
+            )))
             .terminated(expect_pipe(
-                "unrecognized option, only the 'from' option is available",
+                "unrecognized option, only the 'from', 'strict', 'keys' and 'duplicates' options are available",
             ))
-            .map(|input_column| InlineOperator::Logfmt { input_column }),
+            .map(
+                |(input_column, strict, keys, duplicates)| InlineOperator::Logfmt {
+                    input_column,
+                    strict: strict.flatten().unwrap_or(false),
+                    keys: keys.unwrap_or_default(),
+                    duplicates: duplicates.flatten().unwrap_or(DuplicateKeys::Last),
+                },
+            ),
     );
+    // `split(field, ",")`, with more than one argument, is the function rather than the operator.
+    let split_function = tuple((tag("("), multispace0, opt_expr, multispace0, tag(",")));
     let split = with_pos(
         tag("split")
+            .terminated(not(peek(split_function)))
             .precedes(tuple((
                 opt(single_arg("the string to split")),
                 opt(tag("on")
                     .delimited_by(multispace1)
                     .precedes(req_quoted_string)),
+                opt(tag("maxsplit=")
+                    .preceded_by(multispace1)
+                    .precedes(map_res(digit1, |s: Span| s.fragment().parse::<usize>()))),
                 opt(tag("as").delimited_by(multispace1).precedes(expr)),
             )))
             .terminated(expect_pipe(
-                "unrecognized option, only the 'on' and 'as' options are available",
+                "unrecognized option, only the 'on', 'maxsplit', and 'as' options are available",
             ))
-            .map(|(e, o, a)| InlineOperator::Split {
+            .map(|(e, o, m, a)| InlineOperator::Split {
                 separator: o.unwrap_or_else(|| ",".to_string()),
+                max_splits: m,
                 input_column: e.clone(),
                 output_column: a.or(e),
             }),
     );
+    let explode = with_pos(
+        oper_0_args("explode")
+            .precedes(expect_fn(expr.preceded_by(multispace1), |qc, r| {
+                qc.report_error_for("expecting the array to explode")
+                    .with_code_range(r, "")
+                    .with_resolution("eg. explode tags")
+                    .send_report()
+            }))
+            .and(opt(tag("as").delimited_by(multispace1).precedes(expr)))
+            .terminated(expect_pipe(
+                "unrecognized option, only the 'as' option is available",
+            )),
+    )
+    .map(|Positioned { range, value }| match value {
+        (Some(input_column), output_column) => Operator::Inline(Positioned {
+            range,
+            value: InlineOperator::Explode {
+                output_column: output_column.unwrap_or_else(|| input_column.clone()),
+                input_column,
+            },
+        }),
+        (None, _) => Operator::Error,
+    });
+    let slice_duration = || {
+        alt((
+            duration.map(SliceDuration::Fixed),
+            tag("auto")
+                .precedes(opt(tag("buckets=").preceded_by(multispace1).precedes(
+                    map_res(digit1, |s: Span| s.fragment().parse::<usize>()),
+                )))
+                .map(|buckets| SliceDuration::Auto {
+                    buckets: buckets.unwrap_or(DEFAULT_AUTO_SLICES),
+                }),
+        ))
+        .preceded_by(multispace1)
+    };
     let timeslice = with_pos(
         tuple((
-            tag("timeslice").precedes(req_single_arg("the date-time value for the log message")),
-            opt(duration.preceded_by(multispace1)),
+            tag("timeslice").precedes(alt((
+                // e.g. `timeslice 5m on ts`
+                slice_duration()
+                    .and(tag("on").delimited_by(multispace1).precedes(expr))
+                    .map(|(duration, input_column)| (input_column, Some(duration))),
+                // e.g. `timeslice(ts) 5m`
+                req_single_arg("the date-time value for the log message")
+                    .and(opt(slice_duration())),
+            ))),
+            opt(tag("format=")
+                .preceded_by(multispace1)
+                .precedes(req_quoted_string)),
             opt(tag("as").delimited_by(multispace1).precedes(ident)),
         ))
         .terminated(expect_pipe(
-            "unrecognized option, only the 'as' option is available",
+            "unrecognized option, only the 'buckets', 'format', and 'as' options are available",
         ))
         .map(
-            |(input_column, duration, output_column)| InlineOperator::Timeslice {
+            |((input_column, duration), format, output_column)| InlineOperator::Timeslice {
                 input_column,
+                format,
                 duration,
                 output_column,
             },
@@ -1528,13 +2893,61 @@ fn parse_operators<'a>(
 
     let count = with_pos(
         tag("count")
-            .precedes(opt(single_arg("the value to count")))
-            .map(|condition| AggregateFunction::Count { condition }),
+            .precedes(opt(alt((
+                tag("(").and(multispace0).and(tag(")")).map(|_| None),
+                single_arg("the value to count").map(Some),
+            ))))
+            .map(|condition| AggregateFunction::Count {
+                condition: condition.flatten(),
+            }),
+    );
+    let count_if = with_pos(
+        tag("count_if")
+            .precedes(req_single_arg("the condition for the rows to count"))
+            .map(|condition| AggregateFunction::Count {
+                condition: Some(condition),
+            }),
     );
     let count_distinct = with_pos(
         tag("count_distinct")
+            .precedes(opt(tag("_approx")).map(|approx| approx.is_some()))
+            .and(opt(with_pos(arg_list)))
+            .map(|(approx, column)| AggregateFunction::CountDistinct { column, approx }),
+    );
+    let values = with_pos(
+        tag("values")
+            .precedes(opt(with_pos(arg_list)))
+            .map(|args| AggregateFunction::Values { args }),
+    );
+    let min_by = with_pos(
+        tag("min_by")
+            .precedes(opt(with_pos(arg_list)))
+            .map(|args| AggregateFunction::MinBy { args }),
+    );
+    let max_by = with_pos(
+        tag("max_by")
             .precedes(opt(with_pos(arg_list)))
-            .map(|column| AggregateFunction::CountDistinct { column }),
+            .map(|args| AggregateFunction::MaxBy { args }),
+    );
+    let first = with_pos(
+        tag("first")
+            .precedes(req_single_arg("the value from the first row"))
+            .map(|column| AggregateFunction::First { column }),
+    );
+    let last = with_pos(
+        tag("last")
+            .precedes(req_single_arg("the value from the last row"))
+            .map(|column| AggregateFunction::Last { column }),
+    );
+    let earliest = with_pos(
+        tag("earliest")
+            .precedes(by_args("earliest"))
+            .map(|(column, by)| AggregateFunction::Earliest { column, by }),
+    );
+    let latest = with_pos(
+        tag("latest")
+            .precedes(by_args("latest"))
+            .map(|(column, by)| AggregateFunction::Latest { column, by }),
     );
     let min = with_pos(
         tag("min")
@@ -1546,6 +2959,11 @@ fn parse_operators<'a>(
             .precedes(req_single_arg("the numeric value to find the maximum of"))
             .map(|column| AggregateFunction::Max { column }),
     );
+    let sum_if = with_pos(
+        tag("sum_if")
+            .precedes(opt(with_pos(arg_list)))
+            .map(|args| AggregateFunction::SumIf { args }),
+    );
     let sum = with_pos(
         tag("sum")
             .precedes(req_single_arg("the numeric value to find the sum of"))
@@ -1557,10 +2975,54 @@ fn parse_operators<'a>(
             .precedes(req_single_arg("the numeric value to find the average of"))
             .map(|column| AggregateFunction::Average { column }),
     );
+    let stddev = with_pos(
+        tag("stddev")
+            .precedes(req_single_arg(
+                "the numeric value to find the standard deviation of",
+            ))
+            .map(|column| AggregateFunction::StdDev { column }),
+    );
+    let variance = with_pos(
+        tag("variance")
+            .or(tag("var"))
+            .precedes(req_single_arg("the numeric value to find the variance of"))
+            .map(|column| AggregateFunction::Variance { column }),
+    );
+    let median = with_pos(
+        tag("median")
+            .precedes(req_single_arg("the numeric value to find the median of"))
+            .map(|column| AggregateFunction::Median { column }),
+    );
+    let mode = with_pos(
+        tag("mode")
+            .precedes(req_single_arg("the value to find the most common of"))
+            .map(|column| AggregateFunction::Mode { column }),
+    );
 
-    let agg_opers = alt((count_distinct, count, min, max, pct, sum, avg))
-        .and(opt(tag("as").delimited_by(multispace1).precedes(req_ident)))
-        .map(|(f, n)| (n.unwrap_or_else(|| f.value.default_name()), f));
+    let agg_opers = alt((
+        count_distinct,
+        count_if,
+        count,
+        min_by,
+        max_by,
+        min,
+        max,
+        first,
+        last,
+        earliest,
+        latest,
+        pct,
+        sum_if,
+        sum,
+        avg,
+        stddev,
+        variance,
+        median,
+        mode,
+        values,
+    ))
+    .and(opt(tag("as").delimited_by(multispace1).precedes(req_ident)))
+    .map(|(f, n)| (n.unwrap_or_else(|| f.value.default_name()), f));
 
     let multi_agg_opers = tuple((
         separated_list1(tag(","), agg_opers.delimited_by(multispace0)),
@@ -1579,8 +3041,29 @@ fn parse_operators<'a>(
         })
     });
 
+    let join = |input| join(input, aliases);
+
     let inline_opers = alt((
-        parse, json, logfmt, fields, limit, split, timeslice, total, wher,
+        parse_csv,
+        parse_syslog,
+        parse_accesslog,
+        parse_useragent,
+        parse_url,
+        parse,
+        json,
+        logfmt,
+        fields,
+        rename,
+        join,
+        lookup,
+        limit,
+        head_tail,
+        split,
+        timeslice,
+        total,
+        transaction,
+        dedup,
+        alt((wher, wasm, script)),
     ))
     .map(Operator::Inline);
 
@@ -1590,13 +3073,38 @@ fn parse_operators<'a>(
     )
     .map(Operator::Inline);
 
-    let alias = recognize(ident).map_res(|span| {
-        aliases
-            .get_alias(span.fragment())
-            .ok_or(())
-            .map(|pipe| Operator::RenderedAlias(pipe.render()))
+    // `let name = expr` is another way to write `expr as name`.  A field named `let` can still be
+    // used in an expression, e.g. `let as x`.
+    let let_expr = with_pos(
+        tag("let")
+            .precedes(multispace1)
+            .precedes(not(tag("as").terminated(multispace1)))
+            .precedes(ident)
+            .and(expect(
+                preceded(
+                    tag("=").delimited_by(multispace0).terminated(not(tag("="))),
+                    expr,
+                ),
+                "expecting `=` and an expression",
+            )),
+    )
+    .map(|Positioned { range, value }| match value {
+        (name, Some(value)) => Operator::Inline(Positioned {
+            range,
+            value: InlineOperator::FieldExpression { value, name },
+        }),
+        (_, None) => Operator::Error,
     });
 
+    let alias = recognize(ident)
+        .and(opt(with_pos(alias_args)))
+        .map_res(|(name, args)| {
+            aliases
+                .get_alias(name.fragment())
+                .ok_or(())
+                .map(|pipe| render_alias(input.extra, name, pipe, args, aliases))
+        });
+
     let unknown_op = recognize(ident).terminated(opt(arg_list));
 
     let did_you_mean = separated_list1(tag(","), unknown_op.delimited_by(multispace0))
@@ -1613,10 +3121,28 @@ fn parse_operators<'a>(
                     if VALID_AGGREGATES.contains(&i) {
                         continue;
                     }
+                } else if VALID_OPERATORS.contains(&i)
+                    && crate::funcs::FUNC_MAP.contains_key(*i.fragment())
+                {
+                    // Only reached when the operator with the same name, like `split`, left the
+                    // function call to the expression parsers and they couldn't parse the rest.
+                    input
+                        .extra
+                        .report_error_for("unrecognized syntax")
+                        .with_code_range(i.to_range(), "")
+                        .with_resolution(format!(
+                            "the {} function can only be used in an expression, eg. {}(...) as name",
+                            i, i
+                        ))
+                        .send_report();
+                    continue;
                 } else if VALID_OPERATORS.contains(&i) {
                     continue;
                 }
-                let valid_operators = ValidOperators { aliases };
+                let valid_operators = ValidOperators {
+                    aliases,
+                    plugins: &input.extra.plugins,
+                };
 
                 let m = if is_agg {
                     crate::errors::did_you_mean(&i, valid_operators.valid_aggs())
@@ -1652,9 +3178,16 @@ fn parse_operators<'a>(
     let garbage = expect(alt((tag("|"), eof)), "unrecognized syntax").map(|_| Operator::Error);
 
     let opers = alt((
+        |input| branch(input, aliases),
+        plugin,
         inline_opers,
         multi_agg_opers,
         sort,
+        top,
+        sample,
+        geoip,
+        explode,
+        let_expr,
         field_expr,
         alias,
         skip_to_end_of_query,
@@ -1665,20 +3198,161 @@ fn parse_operators<'a>(
     separated_list1(tag("|"), opers.delimited_by(multispace0)).parse(input)
 }
 
+/// The arguments to a parameterized alias, e.g. `(warn)` in `errors(warn)`.  An argument is
+/// either a quoted string or the text up to the next comma or closing parenthesis.
+fn alias_args(input: Span) -> IResult<Span, Vec<String>> {
+    let arg = alt((
+        quoted_string.delimited_by(multispace0),
+        take_while1(|c| c != ',' && c != ')').map(|arg: Span| arg.fragment().trim().to_string()),
+    ));
+    expect_delimited(
+        tag("("),
+        separated_list0(tag(","), arg),
+        tag(")"),
+        |qc, r| {
+            qc.report_error_for("unterminated alias arguments")
+                .with_code_range(r, "")
+                .with_resolution("Insert a right parenthesis to terminate the arguments")
+                .send_report()
+        },
+    )
+    .parse(input)
+}
+
+/// Render a use of an alias, substituting the arguments into its template if it has parameters.
+fn render_alias(
+    qc: &QueryContainer,
+    name: Span,
+    pipe: &alias::AliasPipeline,
+    args: Option<Positioned<Vec<String>>>,
+    aliases: &AliasCollection,
+) -> Operator {
+    let params = pipe.params();
+    let args = match args {
+        None if params.is_empty() => {
+            return Operator::RenderedAlias {
+                keyword: pipe.keyword().to_string(),
+                operators: pipe.render(),
+            }
+        }
+        Some(args) if args.value.len() == params.len() => args,
+        args => {
+            let range = args
+                .map(|args| args.range)
+                .unwrap_or_else(|| name.to_range());
+            let expects = match params.len() {
+                0 => "no arguments".to_string(),
+                1 => format!("1 argument: {}", params[0]),
+                n => format!("{} arguments: {}", n, params.join(", ")),
+            };
+            qc.report_error_for(format!("the `{}` alias expects {}", name, expects))
+                .with_code_range(range, "")
+                .with_resolution(format!("the alias is defined in {}", pipe.path().display()))
+                .send_report();
+            return Operator::Error;
+        }
+    };
+
+    let expanded = QueryContainer::new_with_aliases(
+        pipe.expand(&args.value),
+        Box::new(TermErrorReporter::default()),
+        aliases.excluding(name.fragment()),
+    )
+    .with_plugins(qc.plugins.clone());
+    match pipeline_template(&expanded) {
+        Ok(operators) if expanded.get_error_count() == 0 => Operator::RenderedAlias {
+            keyword: pipe.keyword().to_string(),
+            operators,
+        },
+        _ => {
+            qc.report_error_for(format!("the `{}` alias expanded to an invalid query", name))
+                .with_code_pointer(&args, "")
+                .with_resolution(format!("the expanded query was: {}", expanded.query))
+                .send_report();
+            Operator::Error
+        }
+    }
+}
+
+/// Report anything left over after the operators, like a closing parenthesis that doesn't
+/// belong to a `join`.
+fn end_of_operators(input: Span) -> IResult<Span, Span> {
+    let (input, _) = multispace0(input)?;
+    if !input.is_empty() {
+        input
+            .extra
+            .report_error_for("unrecognized syntax")
+            .with_code_range(input.to_range(), "")
+            .send_report();
+    }
+    Ok((input.slice(input.len()..), input))
+}
+
+/// Replace `#` comments, which run to the end of the line, with spaces.  A comment starts at the
+/// beginning of the query or after whitespace, outside of a quoted string.  The length of the
+/// query is kept, so the positions in errors still point into the original query.
+fn blank_comments(query: &str) -> Cow<'_, str> {
+    if !query.contains('#') {
+        return Cow::Borrowed(query);
+    }
+    let mut blanked = String::with_capacity(query.len());
+    let mut in_comment = false;
+    let mut quote = None;
+    let mut escaped = false;
+    let mut prev = ' ';
+    for c in query.chars() {
+        if in_comment && c != '\n' {
+            blanked.extend(std::iter::repeat_n(' ', c.len_utf8()));
+            continue;
+        }
+        match quote {
+            _ if in_comment => in_comment = false,
+            Some(_) if escaped => escaped = false,
+            Some(_) if c == '\\' => escaped = true,
+            Some(q) if c == q => quote = None,
+            Some(_) => {}
+            None if (c == '"' || c == '\'') && !prev.is_alphanumeric() => quote = Some(c),
+            None if c == '#' && prev.is_whitespace() => {
+                in_comment = true;
+                blanked.push(' ');
+                continue;
+            }
+            None => {}
+        }
+        blanked.push(c);
+        prev = c;
+    }
+    Cow::Owned(blanked)
+}
+
+/// The query to parse, with its comments blanked out if it can have them.
+fn source<'a>(container: &'a QueryContainer) -> Cow<'a, str> {
+    if container.comments {
+        blank_comments(&container.query)
+    } else {
+        Cow::Borrowed(&container.query)
+    }
+}
+
 pub fn pipeline_template(input: &QueryContainer) -> Result<Vec<Operator>, CompileError> {
-    let span = Span::new_extra(input.query.as_str(), input);
-    let (_input, operators) =
-        parse_operators(span, &input.aliases).map_err(|_| CompileError::Parse)?;
+    let source = source(input);
+    let span = Span::new_extra(&source, input);
+    let (_input, operators) = (|span| parse_operators(span, &input.aliases))
+        .terminated(end_of_operators)
+        .parse(span)
+        .map_err(|_| CompileError::Parse)?;
 
     Ok(operators)
 }
 
 pub fn query(container: &QueryContainer) -> Result<Query, CompileError> {
-    let span = Span::new_extra(container.query.as_str(), container);
+    let source = source(container);
+    let span = Span::new_extra(&source, container);
     let (input, search) = parse_search(span).map_err(|_| CompileError::Parse)?;
     let (input, operators) =
         opt(tag("|").precedes(|span| parse_operators(span, &container.aliases)))
             .map(|ops| ops.unwrap_or_default())
+            .terminated(end_of_operators)
             .parse(input)
             .map_err(|_| CompileError::Parse)?;
 
@@ -1867,6 +3541,62 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_let() {
+        check_query(
+            "* | let x = 1",
+            expect![[r#"
+            Query {
+                search: And(
+                    [],
+                ),
+                operators: [
+                    Inline(
+                        Positioned {
+                            range: 4..13,
+                            value: FieldExpression {
+                                value: Value(
+                                    Int(
+                                        1,
+                                    ),
+                                ),
+                                name: "x",
+                            },
+                        },
+                    ),
+                ],
+            }
+        "#]],
+        );
+        // A field named `let` is still usable in an expression.
+        check_query(
+            "* | let as x",
+            expect![[r#"
+            Query {
+                search: And(
+                    [],
+                ),
+                operators: [
+                    Inline(
+                        Positioned {
+                            range: 4..12,
+                            value: FieldExpression {
+                                value: Column {
+                                    head: Key(
+                                        "let",
+                                    ),
+                                    rest: [],
+                                },
+                                name: "x",
+                            },
+                        },
+                    ),
+                ],
+            }
+        "#]],
+        );
+    }
+
     #[test]
     fn parse_parses() {
         check_query(
@@ -1894,6 +3624,7 @@ mod tests {
                                     ),
                                     no_drop: false,
                                     no_convert: false,
+                                    casts: [],
                                 },
                             },
                         ),
@@ -1976,6 +3707,7 @@ mod tests {
                                     ),
                                     no_drop: false,
                                     no_convert: false,
+                                    casts: [],
                                 },
                             },
                         ),
@@ -2008,6 +3740,7 @@ mod tests {
                                     ),
                                     no_drop: true,
                                     no_convert: false,
+                                    casts: [],
                                 },
                             },
                         ),
@@ -2041,6 +3774,7 @@ mod tests {
                                     ),
                                     no_drop: true,
                                     no_convert: false,
+                                    casts: [],
                                 },
                             },
                         ),
@@ -2083,6 +3817,7 @@ mod tests {
                                     ),
                                     no_drop: false,
                                     no_convert: false,
+                                    casts: [],
                                 },
                             },
                         ),
@@ -2125,6 +3860,7 @@ mod tests {
                                     ),
                                     no_drop: false,
                                     no_convert: false,
+                                    casts: [],
                                 },
                             },
                         ),
@@ -2602,6 +4338,71 @@ mod tests {
         );
     }
 
+    #[test]
+    fn parse_in_and_match() {
+        check_query(
+            r#"* | where status in (500, 503) && !(msg =~ /a\/b/)"#,
+            expect![[r#"
+                Query {
+                    search: And(
+                        [],
+                    ),
+                    operators: [
+                        Inline(
+                            Positioned {
+                                range: 4..50,
+                                value: Where {
+                                    expr: Some(
+                                        Positioned {
+                                            range: 10..50,
+                                            value: Binary {
+                                                op: Logical(
+                                                    And,
+                                                ),
+                                                left: In {
+                                                    value: Column {
+                                                        head: Key(
+                                                            "status",
+                                                        ),
+                                                        rest: [],
+                                                    },
+                                                    list: [
+                                                        Value(
+                                                            Int(
+                                                                500,
+                                                            ),
+                                                        ),
+                                                        Value(
+                                                            Int(
+                                                                503,
+                                                            ),
+                                                        ),
+                                                    ],
+                                                },
+                                                right: Unary {
+                                                    op: Not,
+                                                    operand: Match {
+                                                        value: Column {
+                                                            head: Key(
+                                                                "msg",
+                                                            ),
+                                                            rest: [],
+                                                        },
+                                                        regex: "a/b",
+                                                    },
+                                                },
+                                            },
+                                        },
+                                    ),
+                                },
+                            },
+                        ),
+                    ],
+                }
+            "#]],
+        );
+    }
+
     #[test]
     fn parse_expr_precedence() {
         check_query(
@@ -2901,14 +4702,27 @@ mod tests {
                     search: And(
                         [],
                     ),
-                    operators: [],
+                    operators: [
+                        MultiAggregate(
+                            MultiAggregateOperator {
+                                key_cols: [],
+                                key_col_headers: [],
+                                aggregate_functions: [
+                                    (
+                                        "_count",
+                                        Positioned {
+                                            range: 4..11,
+                                            value: Count {
+                                                condition: None,
+                                            },
+                                        },
+                                    ),
+                                ],
+                            },
+                        ),
+                    ],
                 }
-                error: this operator takes 1 argument, but 0 were supplied
-                  |
-                1 | * | count()
-                  |            - supplied 0 arguments
-                  |
-                  = help: the argument should supply the value to count"#]],
+            "#]],
         );
         check_query(
             "* | json | count, count_distinct(message) by level",
@@ -2923,6 +4737,9 @@ mod tests {
                                 range: 4..8,
                                 value: Json {
                                     input_column: None,
+                                    mode: Fields,
+                                    duplicates: Last,
+                                    warn_duplicates: false,
                                 },
                             },
                         ),
@@ -2967,6 +4784,7 @@ mod tests {
                                                         ],
                                                     },
                                                 ),
+                                                approx: false,
                                             },
                                         },
                                     ),
@@ -3130,6 +4948,23 @@ mod tests {
         );
     }
 
+    #[test]
+    fn comments() {
+        assert_eq!(
+            blank_comments("# urls\n* | json | # parse\n  count by url # ✓\n  | head 3"),
+            "      \n* | json |        \n  count by url      \n  | head 3"
+        );
+        assert_eq!(
+            blank_comments(r#"* | where x == "a # b" and y == 'it\'s # c' | json # d"#),
+            r#"* | where x == "a # b" and y == 'it\'s # c' | json    "#
+        );
+        assert_eq!(
+            blank_comments("* | where don't == 1 # e"),
+            "* | where don't == 1    "
+        );
+        assert_eq!(blank_comments("a#b | c#d"), "a#b | c#d");
+    }
+
     #[test]
     fn quoted_strings() {
         check_query(
@@ -3203,6 +5038,23 @@ mod tests {
                   = help: Insert a right parenthesis to terminate this expression"#]],
         );
 
+        check_query(
+            "* | [count; count",
+            expect![[r#"
+                Query {
+                    search: And(
+                        [],
+                    ),
+                    operators: [],
+                }
+                error: unterminated branches
+                  |
+                1 | * | [count; count
+                  |     ^^^^^^^^^^^^^
+                  |
+                  = help: Insert a right square bracket after the last branch"#]],
+        );
+
         check_query(
             "* | parseDate(abc) | bar",
             expect![[r#"
@@ -3257,6 +5109,50 @@ mod tests {
         );
     }
 
+    #[test]
+    fn rename_operator() {
+        check_query(
+            r#"* | rename /^svc_(.+)\/$/ as "$1 ms""#,
+            expect![[r#"
+                Query {
+                    search: And(
+                        [],
+                    ),
+                    operators: [
+                        Inline(
+                            Positioned {
+                                range: 4..36,
+                                value: Rename {
+                                    pattern: Regex(
+                                        "^svc_(.+)/$",
+                                    ),
+                                    replacement: "$1 ms",
+                                },
+                            },
+                        ),
+                    ],
+                }
+            "#]],
+        );
+        check_query(
+            "* | rename svc as",
+            expect![[r#"
+                Query {
+                    search: And(
+                        [],
+                    ),
+                    operators: [],
+                }
+                error: expecting the field to rename and its new name
+                  |
+                1 | * | rename svc as
+                  |                  
+                  |
+                  = help: eg. rename old_name as new_name
+                  = help: or with a regex, eg. rename /^svc_(.+)$/ as $1"#]],
+        );
+    }
+
     #[test]
     fn logfmt_operator() {
         check_query(
@@ -3279,6 +5175,9 @@ mod tests {
                                         rest: [],
                                     },
                                 ),
+                                strict: false,
+                                keys: [],
+                                duplicates: Last,
                             },
                         },
                     ),
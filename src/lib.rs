@@ -2,32 +2,63 @@
 extern crate include_dir;
 
 pub mod alias;
+mod ansi;
+pub mod arrow;
+mod builder;
+pub mod columnar;
 pub mod data;
+mod decompress;
 mod errors;
+mod explain;
 mod filter;
+mod follow;
 mod funcs;
+pub mod grok;
+#[cfg(unix)]
+pub mod interactive;
 pub mod lang;
 pub mod operator;
+#[cfg(feature = "otlp")]
+pub mod otlp;
+pub mod parquet;
+pub mod plugin;
 mod printer;
 mod render;
+pub mod serve;
+pub mod sink;
+pub mod split;
+pub mod sqlite;
 mod typecheck;
 
+pub use builder::{PipelineBuilder, PipelineError, Rows};
+
 pub mod pipeline {
-    use crate::data::{DisplayConfig, Record, Row};
+    use crate::data::{DisplayConfig, Record, Row, Value};
+    use crate::errors::ErrorBuilder;
     pub use crate::errors::{ErrorReporter, QueryContainer, TermErrorReporter};
     use crate::filter;
+    use crate::follow::FollowFile;
     use crate::lang::*;
     use crate::operator;
-    use crate::operator::sort;
+    use crate::operator::{branch, fields, join, sort, top};
+    use crate::plugin;
     use crate::printer::{agg_printer, raw_printer};
+    pub use crate::render::{ColorChoice, Style, Theme, Threshold};
     use crate::render::{RenderConfig, Renderer, TerminalConfig};
+    use crate::serve::Snapshot;
     use crate::typecheck::{TypeCheck, TypeError};
     use anyhow::Error;
     use crossbeam_channel::{bounded, Receiver, RecvTimeoutError, Sender};
-    use std::collections::VecDeque;
-    use std::io::{BufRead, Write};
+    use itertools::Itertools;
+    use serde::Serialize;
+    use std::borrow::Cow;
+    use std::collections::{BTreeMap, HashSet, VecDeque};
+    use std::io::{self, BufRead, Write};
+    use std::path::{Path, PathBuf};
+    use std::str;
+    use std::sync::{Arc, Mutex};
     use std::thread;
-    use std::time::Duration;
+    use std::time::{Duration, Instant};
     use thiserror::Error;
 
     #[derive(Debug, Error)]
@@ -38,6 +69,14 @@ pub mod pipeline {
         #[error("Non aggregate operators can't follow aggregate operators")]
         NonAggregateAfterAggregate,
 
+        #[error("Only the results of an aggregate, like `count by status`, can be written as Prometheus metrics")]
+        PrometheusWithoutAggregate,
+
+        #[error(
+            "Only the results of an aggregate, like `count by status`, can be drawn as a chart"
+        )]
+        ChartWithoutAggregate,
+
         #[error("Unexpected failure: {}", message)]
         Unexpected { message: String },
     }
@@ -47,14 +86,910 @@ pub mod pipeline {
         Legacy,
         Logfmt,
         Format(String),
-        Json,
+        Json(JsonOptions),
+        /// The Prometheus text exposition format.  Only aggregates can be written this way.
+        Prometheus,
+        /// A GitHub-flavored markdown table.
+        Markdown,
+        /// A minimal HTML table.
+        Html,
+        /// A horizontal bar chart of a numeric column, or of the first numeric column that isn't
+        /// a key when none is given.  Only aggregates can be drawn this way.
+        Chart(Option<String>),
+        /// A sparkline of a numeric column for each group of an aggregate that's split into time
+        /// buckets, like `count by _timeslice, status`.  The column is picked like a chart's.
+        Sparkline(Option<String>),
+        #[cfg(feature = "otlp")]
+        Otlp(crate::otlp::OtlpConfig),
+        /// Rows inserted into a table of a SQLite database.
+        Sqlite(crate::sqlite::SqliteConfig),
+        /// The final rows written to a Parquet file.
+        Parquet(std::path::PathBuf),
+        /// Rows written to a file for each value of a field.
+        Split(crate::split::SplitConfig),
+        /// Rows handed to a callback instead of being written to the output.
+        Callback(crate::sink::Callback),
+    }
+
+    /// Options for `-o json`, given as a comma separated list, e.g. `-o json=array,pretty`.
+    #[derive(Clone, Debug, Default, PartialEq, Eq)]
+    pub struct JsonOptions {
+        /// Buffer every record and write them as a single JSON array once the input ends,
+        /// instead of writing one object per line as records arrive.
+        pub array: bool,
+        /// Indent the output over multiple lines.
+        pub pretty: bool,
+    }
+
+    /// Options for the layout of the `legacy` table.
+    #[derive(Clone, Debug, Default, PartialEq, Eq)]
+    pub struct TableOptions {
+        /// Only show these columns, in this order, instead of every column sorted by name for
+        /// records and in query order for aggregates.
+        pub columns: Option<Vec<String>>,
+        /// Cut values longer than this many characters short with an ellipsis.
+        pub max_column_width: Option<usize>,
+        /// Right-align the columns of an aggregate that only hold numbers.
+        pub right_align_numbers: bool,
+    }
+
+    /// Column used to rank the rows of a live-updating aggregate, `top`-style.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum Leaderboard {
+        /// Rank by the first aggregate function of the query, e.g. `_count` in
+        /// `count by host`.
+        PrimaryAggregate,
+        Column(String),
+    }
+
+    /// Options that control how the pipeline reads its input and renders its output.
+    #[derive(Clone, Debug, Default)]
+    pub struct PipelineOptions {
+        /// Keep aggregate rows sorted by the given column, largest first, and capped to the
+        /// height of the terminal.
+        pub leaderboard: Option<Leaderboard>,
+        /// Fail on the first line of input that isn't valid UTF-8 instead of replacing the
+        /// invalid bytes with `U+FFFD`.
+        pub strict_utf8: bool,
+        /// Fail on the first line that a parsing operator (`parse`, `json`, ...) doesn't match
+        /// instead of dropping it.  Rows that operators hold on to until the end of the input,
+        /// like `limit -N`, aren't checked since they no longer have a line number.
+        pub strict_parse: bool,
+        /// Cut lines longer than this many bytes down to size as they're read, so a single huge
+        /// line can't exhaust memory.  Unlimited by default.
+        pub max_line_length: Option<usize>,
+        /// Drop lines longer than `max_line_length` instead of truncating them.
+        pub skip_long_lines: bool,
+        /// Remove ANSI escape sequences, like terminal colors, from each line before it's
+        /// searched or parsed.
+        pub strip_ansi: bool,
+        /// Join lines that don't match this pattern onto the line before them, so a multiline
+        /// message like a stack trace becomes a single record.  Each line is its own record by
+        /// default.
+        pub multiline_start: Option<regex::Regex>,
+        /// The minimum time between redraws of a live aggregate. Updates in between are coalesced
+        /// into the next redraw. Defaults to `DEFAULT_REFRESH_INTERVAL`.
+        pub refresh_interval: Option<Duration>,
+        /// The layout of the `legacy` table.
+        pub table: TableOptions,
+        /// Whether to color the `legacy` table.
+        pub color: ColorChoice,
+        /// How the `legacy` table is colored, when it is.
+        pub theme: Theme,
+        /// Render aggregates once, when the input ends, even when the output is a terminal.  By
+        /// default they're redrawn as the input is read.
+        pub render_once: bool,
+        /// The number of threads that search and parse the input.  With more than one, the
+        /// records are processed in batches, so a slow stream of input is output a batch at a
+        /// time.  Defaults to one.
+        pub threads: usize,
+        /// The most memory, in bytes, that a `sort` uses for its rows, or an aggregate for its
+        /// groups, before writing rows or partial results to temporary files that are read back
+        /// when it's output.
+        /// Unlimited by default.
+        pub max_memory: Option<usize>,
+        /// Keep the latest rendering of aggregates here, so they can be served, instead of
+        /// writing them to the output.
+        pub snapshot: Option<Snapshot>,
+        /// The input has rows with typed fields, like a Parquet file, rather than lines that need
+        /// to be parsed, so fields can be used before any operator produces them.
+        pub typed_input: bool,
+        /// The input is more than one file, so each record gets a `__file__` field with the name
+        /// of its file.
+        pub multiple_files: bool,
+        /// The file that a `join` without a file of its own runs its operators over, instead of
+        /// the rows that reach the join.
+        pub file2: Option<PathBuf>,
+        /// The seed of the `sample` operators that don't have a `seed` of their own, so that they
+        /// pick the same rows on every run.
+        pub seed: Option<u64>,
+        /// Include the metadata fields, those starting with `_`, in the whole record keys of
+        /// `dedup` operators without keys of their own, and in the fields matched by the
+        /// patterns of `fields`.
+        pub include_meta: bool,
+    }
+
+    pub const DEFAULT_REFRESH_INTERVAL: Duration = Duration::from_millis(250);
+
+    #[derive(Debug, Error)]
+    pub enum InputError {
+        #[error("Invalid UTF-8 on line {}: {}", line, error)]
+        InvalidUtf8 {
+            line: u64,
+            error: std::str::Utf8Error,
+        },
+
+        #[error("Failed to parse line {}: {}", line, content)]
+        ParseFailed { line: u64, content: String },
+
+        #[error("Failed to read input: {}", .0)]
+        Io(#[from] std::io::Error),
+    }
+
+    /// Counts of the lines and rows that flowed through a pipeline.
+    #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+    pub struct PipelineStats {
+        /// Lines read from the input.
+        pub lines_read: u64,
+        /// Bytes read from the input, including newlines and the parts of long lines that were
+        /// cut off.
+        pub bytes_read: u64,
+        /// Lines, or records assembled from several lines with `multiline_start`, that matched
+        /// the search filters.
+        pub lines_matched: u64,
+        /// Rows dropped because a parsing operator (`parse`, `json`, ...) didn't match them.
+        pub dropped_by_parse: u64,
+        /// Rows dropped by a `where` clause.
+        pub dropped_by_where: u64,
+        /// Rows dropped because an operator failed to evaluate them.
+        pub errors: u64,
+        /// Rows that made it through the non-aggregate operators.
+        pub rows_output: u64,
+        /// Lines that weren't valid UTF-8 and had the invalid bytes replaced.
+        pub lines_with_invalid_utf8: u64,
+        /// Lines longer than the maximum line length, which were truncated or skipped.
+        pub long_lines: u64,
+        /// The counts for each operator before the first aggregate, in query order.
+        pub operators: Vec<OperatorStats>,
+        /// How long it took to process the input and render the output.
+        #[serde(rename = "elapsed_seconds", serialize_with = "serialize_seconds")]
+        pub elapsed: Duration,
+        /// The most memory the process had resident at once, where that can be found out.
+        #[serde(rename = "peak_memory_bytes")]
+        pub peak_memory: Option<u64>,
+    }
+
+    /// Counts of the rows a single operator dropped.
+    #[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+    pub struct OperatorStats {
+        /// The operator as it's written in the query, e.g. `where status == 500`.
+        pub operator: String,
+        /// Whether the operator parses its rows, like `parse` or `json`, so that the rows it
+        /// drops or fails on are ones it couldn't parse.
+        pub parser: bool,
+        /// Rows the operator didn't pass on, for parsing operators and `where`.
+        pub dropped: u64,
+        /// Rows the operator failed to evaluate.
+        pub errors: u64,
+    }
+
+    fn serialize_seconds<S: serde::Serializer>(
+        duration: &Duration,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_f64(duration.as_secs_f64())
+    }
+
+    impl PipelineStats {
+        fn merge(&mut self, other: &PipelineStats) {
+            self.lines_read += other.lines_read;
+            self.bytes_read += other.bytes_read;
+            self.lines_matched += other.lines_matched;
+            self.dropped_by_parse += other.dropped_by_parse;
+            self.dropped_by_where += other.dropped_by_where;
+            self.errors += other.errors;
+            self.rows_output += other.rows_output;
+            self.lines_with_invalid_utf8 += other.lines_with_invalid_utf8;
+            self.long_lines += other.long_lines;
+            for (stage, counts) in other.operators.iter().enumerate() {
+                let op = self.operator_mut(stage);
+                op.dropped += counts.dropped;
+                op.errors += counts.errors;
+            }
+            self.elapsed = self.elapsed.max(other.elapsed);
+            self.peak_memory = self.peak_memory.max(other.peak_memory);
+        }
+
+        /// The rows each parsing operator dropped or failed on.
+        pub fn parse_failures(&self) -> impl Iterator<Item = (&str, u64)> {
+            self.operators
+                .iter()
+                .filter(|op| op.parser)
+                .map(|op| (op.operator.as_str(), op.dropped + op.errors))
+        }
+
+        fn operator_mut(&mut self, stage: usize) -> &mut OperatorStats {
+            if self.operators.len() <= stage {
+                self.operators.resize(stage + 1, OperatorStats::default());
+            }
+            &mut self.operators[stage]
+        }
+    }
+
+    /// The peak resident memory of this process, from `/proc`.
+    #[cfg(target_os = "linux")]
+    fn peak_memory() -> Option<u64> {
+        let status = std::fs::read_to_string("/proc/self/status").ok()?;
+        let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+        let kb: u64 = line["VmHWM:".len()..]
+            .trim()
+            .trim_end_matches("kB")
+            .trim()
+            .parse()
+            .ok()?;
+        Some(kb * 1024)
+    }
+
+    #[cfg(not(target_os = "linux"))]
+    fn peak_memory() -> Option<u64> {
+        None
+    }
+
+    impl std::fmt::Display for PipelineStats {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            writeln!(
+                f,
+                "lines read: {}, bytes read: {}, matched: {}, dropped by parse: {}, \
+                 dropped by where: {}, errors: {}, rows output: {}, lines with invalid utf-8: {}, \
+                 long lines: {}",
+                self.lines_read,
+                self.bytes_read,
+                self.lines_matched,
+                self.dropped_by_parse,
+                self.dropped_by_where,
+                self.errors,
+                self.rows_output,
+                self.lines_with_invalid_utf8,
+                self.long_lines
+            )?;
+            for op in &self.operators {
+                writeln!(
+                    f,
+                    "  {}: dropped {}, errors {}",
+                    op.operator, op.dropped, op.errors
+                )?;
+            }
+            write!(f, "elapsed: {:.3}s", self.elapsed.as_secs_f64())?;
+            if let Some(peak_memory) = self.peak_memory {
+                write!(
+                    f,
+                    ", peak memory: {:.1} MiB",
+                    peak_memory as f64 / (1024.0 * 1024.0)
+                )?;
+            }
+            Ok(())
+        }
+    }
+
+    /// The counter a row dropped by a pre-aggregate operator is recorded under.  Operators like
+    /// `limit` hold on to rows and emit them later, so their drops aren't counted.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum DropKind {
+        Parse,
+        Where,
+        Other,
+    }
+
+    impl DropKind {
+        fn of(op: &InlineOperator) -> Self {
+            match op {
+                InlineOperator::Json { .. }
+                | InlineOperator::Logfmt { .. }
+                | InlineOperator::Csv { .. }
+                | InlineOperator::Syslog { .. }
+                | InlineOperator::AccessLog { .. }
+                | InlineOperator::UserAgent { .. }
+                | InlineOperator::Url { .. }
+                | InlineOperator::Parse { .. }
+                | InlineOperator::Split { .. } => DropKind::Parse,
+                InlineOperator::Where { .. } => DropKind::Where,
+                _ => DropKind::Other,
+            }
+        }
+    }
+
+    /// Where a pre-aggregate operator is in the query, which its counts are kept under, and what
+    /// the rows it drops are counted as.
+    #[derive(Clone, Copy, Debug)]
+    struct Stage {
+        index: usize,
+        drop_kind: DropKind,
+    }
+
+    type PreAggStage = (Stage, Box<dyn operator::UnaryPreAggOperator>);
+    type PreAggDef = (Stage, Box<dyn operator::OperatorBuilder>);
+
+    /// The number of records handed to a worker at a time by a parallel pipeline.
+    const BATCH_SIZE: usize = 1024;
+
+    /// Records read from one input, numbered in the order they were read so that they can be put
+    /// back in order once they've been processed.
+    #[derive(Default)]
+    struct Batch {
+        seq: u64,
+        file: Option<String>,
+        records: Vec<(u64, InputRecord<'static>)>,
+    }
+
+    /// The rows that made it through a worker's operators, along with the lines they started on.
+    #[derive(Default)]
+    struct BatchOutput {
+        seq: u64,
+        rows: Vec<(u64, Record)>,
+        stats: PipelineStats,
+        /// With `strict_parse`, the line that failed to parse.  The batch stops there.
+        error: Option<InputError>,
+    }
+
+    /// What a list of operators compiles to.
+    struct Stages {
+        /// Whether the operators end with an aggregate, or follow one.
+        in_agg: bool,
+        pre_agg: Vec<PreAggDef>,
+        stage_names: Vec<(String, bool)>,
+        post_agg: Vec<Box<dyn operator::AggregateOperator>>,
+        primary_aggregate: Option<String>,
+        key_columns: Vec<String>,
     }
 
     pub struct Pipeline {
         filter: filter::Filter,
-        pre_aggregates: Vec<Box<dyn operator::UnaryPreAggOperator>>,
+        pre_aggregates: Vec<PreAggDef>,
         aggregators: Vec<Box<dyn operator::AggregateOperator>>,
         renderer: Renderer,
+        reader: RecordReader,
+        strict_parse: bool,
+        threads: usize,
+        /// How each pre-aggregate operator is written in the query, and whether it's a parser,
+        /// for its stats.
+        stage_names: Vec<(String, bool)>,
+    }
+
+    /// An input to the pipeline: lines of text, or the rows of a columnar file like Parquet.
+    enum Input<T> {
+        Text(T),
+        Rows(crate::columnar::Rows),
+    }
+
+    /// A record as it's read: text that's yet to be parsed, or a row of a columnar file whose
+    /// fields are already typed.
+    enum InputRecord<'a> {
+        Text(Cow<'a, str>),
+        Parsed(Record),
+    }
+
+    impl InputRecord<'_> {
+        /// The text that search filters are matched against.
+        fn text(&self) -> &str {
+            match self {
+                InputRecord::Text(data) => data,
+                InputRecord::Parsed(rec) => &rec.raw,
+            }
+        }
+
+        fn into_owned(self) -> InputRecord<'static> {
+            match self {
+                InputRecord::Text(data) => InputRecord::Text(Cow::Owned(data.into_owned())),
+                InputRecord::Parsed(rec) => InputRecord::Parsed(rec),
+            }
+        }
+    }
+
+    /// Reads lines from the input and assembles them into records.
+    struct RecordReader {
+        strict_utf8: bool,
+        max_line_length: Option<usize>,
+        skip_long_lines: bool,
+        strip_ansi: bool,
+        multiline_start: Option<regex::Regex>,
+    }
+
+    impl RecordReader {
+        /// Read the records of each input in turn, passing each to `on_record` along with the
+        /// line it starts on and the name of its file.  The rows of a columnar input each count
+        /// as a line.  Stops early if `on_record` returns `Ok(false)` or an error.
+        fn read<T: BufRead>(
+            &self,
+            inputs: impl Iterator<Item = io::Result<(Option<String>, Input<T>)>>,
+            stats: &mut PipelineStats,
+            mut on_record: impl FnMut(
+                (u64, InputRecord),
+                &Option<String>,
+                &mut PipelineStats,
+            ) -> Result<bool, InputError>,
+        ) -> Result<(), InputError> {
+            // This is pretty slow in practice. We could move line splitting until after
+            // we find a match. Another option is moving the transformation to String until
+            // after we match (staying as Vec<u8> until then)
+            let mut line = Vec::with_capacity(1024);
+            // With `multiline_start`, the record being assembled and the line it started on.
+            let mut pending: Option<(u64, String)> = None;
+            for input in inputs {
+                let (file, mut buf) = match input? {
+                    (file, Input::Text(buf)) => (file, buf),
+                    (file, Input::Rows(rows)) => {
+                        stats.bytes_read += rows.bytes;
+                        for batch in rows.batches {
+                            for rec in batch? {
+                                stats.lines_read += 1;
+                                let record = (stats.lines_read, InputRecord::Parsed(rec));
+                                if !on_record(record, &file, stats)? {
+                                    return Ok(());
+                                }
+                            }
+                        }
+                        continue;
+                    }
+                };
+                loop {
+                    let (ct, truncated) = match self.max_line_length {
+                        Some(max) => read_line_capped(&mut buf, &mut line, max),
+                        None => buf.read_until(b'\n', &mut line).map(|ct| (ct, false)),
+                    }?;
+                    if ct == 0 {
+                        break;
+                    }
+                    stats.lines_read += 1;
+                    stats.bytes_read += ct as u64;
+                    if truncated {
+                        stats.long_lines += 1;
+                        if self.skip_long_lines {
+                            line.clear();
+                            continue;
+                        }
+                    }
+                    let data = match str::from_utf8(&line) {
+                        Ok(data) => Cow::Borrowed(data),
+                        Err(error) if self.strict_utf8 => {
+                            return Err(InputError::InvalidUtf8 {
+                                line: stats.lines_read,
+                                error,
+                            });
+                        }
+                        Err(_) => {
+                            stats.lines_with_invalid_utf8 += 1;
+                            String::from_utf8_lossy(&line)
+                        }
+                    };
+                    let data = if self.strip_ansi && data.contains('\x1b') {
+                        Cow::Owned(crate::ansi::strip_ansi(&data))
+                    } else {
+                        data
+                    };
+                    let record = match &self.multiline_start {
+                        None => Some((stats.lines_read, data)),
+                        Some(start) => match &mut pending {
+                            Some((_, record)) if !start.is_match(&data) => {
+                                record.push_str(&data);
+                                None
+                            }
+                            _ => pending
+                                .replace((stats.lines_read, data.into_owned()))
+                                .map(|(start, record)| (start, Cow::Owned(record))),
+                        },
+                    };
+                    if let Some((start, record)) = record {
+                        if !on_record((start, InputRecord::Text(record)), &file, stats)? {
+                            return Ok(());
+                        }
+                    }
+                    line.clear();
+                }
+                // A multiline record doesn't continue into the next file.
+                if let Some((start, record)) = pending.take() {
+                    let record = InputRecord::Text(Cow::Owned(record));
+                    if !on_record((start, record), &file, stats)? {
+                        return Ok(());
+                    }
+                }
+            }
+            Ok(())
+        }
+    }
+
+    /// Read a line into `line` like `read_until`, but keep at most `max` bytes of it, not counting
+    /// the newline.  The rest of the line is consumed without being buffered.  A line that's cut
+    /// short is cut at a character boundary so it stays valid UTF-8.  Returns the number of bytes
+    /// consumed and whether the line was cut short.
+    fn read_line_capped<T: BufRead>(
+        buf: &mut T,
+        line: &mut Vec<u8>,
+        max: usize,
+    ) -> io::Result<(usize, bool)> {
+        let mut consumed = 0;
+        let mut truncated = false;
+        let mut ended = false;
+        loop {
+            let available = match buf.fill_buf() {
+                Ok(available) => available,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if available.is_empty() {
+                break;
+            }
+            let newline = available.iter().position(|b| *b == b'\n');
+            let content = newline.unwrap_or(available.len());
+            let room = max.saturating_sub(line.len());
+            if content > room {
+                truncated = true;
+            }
+            line.extend_from_slice(&available[..content.min(room)]);
+            let used = newline.map_or(content, |pos| pos + 1);
+            buf.consume(used);
+            consumed += used;
+            if newline.is_some() {
+                ended = true;
+                break;
+            }
+        }
+        if truncated {
+            if let Err(e) = str::from_utf8(line) {
+                if e.error_len().is_none() {
+                    line.truncate(e.valid_up_to());
+                }
+            }
+        }
+        if ended {
+            line.push(b'\n');
+        }
+        Ok((consumed, truncated))
+    }
+
+    /// The fields that rows can have at a point in the pipeline, as far as can be told from the
+    /// query alone.  Operators like `json` produce fields that depend on the input, after which
+    /// any field could exist.
+    #[derive(Clone)]
+    pub(crate) enum KnownFields {
+        Any,
+        Only {
+            fields: HashSet<String>,
+            after_aggregate: bool,
+        },
+    }
+
+    impl KnownFields {
+        pub(crate) fn empty() -> Self {
+            KnownFields::Only {
+                fields: HashSet::new(),
+                after_aggregate: false,
+            }
+        }
+
+        fn add(&mut self, field: &str) {
+            if let KnownFields::Only { fields, .. } = self {
+                fields.insert(field.to_string());
+            }
+        }
+
+        /// Update the known fields with the ones the operator produces or drops.
+        pub(crate) fn apply(&mut self, op: &Operator) {
+            let inline_op = match op {
+                // Aliases are expanded from their own source text, so their operators aren't
+                // followed.  The fields a plugin's operator outputs are up to the plugin.
+                Operator::RenderedAlias { .. } | Operator::Plugin(_) | Operator::Error => {
+                    *self = KnownFields::Any;
+                    return;
+                }
+                Operator::MultiAggregate(agg_op) => {
+                    *self = KnownFields::Only {
+                        fields: agg_op
+                            .key_col_headers
+                            .iter()
+                            .chain(agg_op.aggregate_functions.iter().map(|(name, _)| name))
+                            .cloned()
+                            .collect(),
+                        after_aggregate: true,
+                    };
+                    return;
+                }
+                Operator::Top(top_op) => {
+                    *self = KnownFields::Only {
+                        fields: top_op
+                            .key_col_headers
+                            .iter()
+                            .cloned()
+                            .chain(["_count".to_string(), "_percent".to_string()])
+                            .collect(),
+                        after_aggregate: true,
+                    };
+                    return;
+                }
+                // The rows after the branches are those of all of them together, labeled with
+                // the branch they came out of.
+                Operator::Branch(branch_op) => {
+                    let mut merged = KnownFields::empty();
+                    for operators in &branch_op.branches {
+                        let mut known = self.clone();
+                        operators.iter().for_each(|op| known.apply(op));
+                        merged = match (merged, known) {
+                            (
+                                KnownFields::Only {
+                                    mut fields,
+                                    after_aggregate,
+                                },
+                                KnownFields::Only {
+                                    fields: branch_fields,
+                                    after_aggregate: branch_after_aggregate,
+                                },
+                            ) => {
+                                fields.extend(branch_fields);
+                                KnownFields::Only {
+                                    fields,
+                                    after_aggregate: after_aggregate || branch_after_aggregate,
+                                }
+                            }
+                            _ => KnownFields::Any,
+                        };
+                    }
+                    *self = merged;
+                    self.add(branch::BRANCH_COLUMN);
+                    return;
+                }
+                Operator::Sort(_) => return,
+                Operator::Inline(inline_op) => &inline_op.value,
+            };
+            match inline_op {
+                InlineOperator::Json {
+                    mode: JsonMode::Paths(paths),
+                    ..
+                } => paths.iter().for_each(|path| self.add(&path.name)),
+                InlineOperator::Logfmt { keys, .. } if !keys.is_empty() => {
+                    keys.iter().for_each(|key| self.add(key))
+                }
+                InlineOperator::Json { .. }
+                | InlineOperator::Logfmt { .. }
+                | InlineOperator::Join { .. }
+                | InlineOperator::Wasm { .. }
+                | InlineOperator::Script { .. }
+                | InlineOperator::Rename {
+                    pattern: RenamePattern::Regex(_),
+                    ..
+                } => *self = KnownFields::Any,
+                InlineOperator::Parse { fields, .. } => {
+                    fields.iter().for_each(|field| self.add(field))
+                }
+                InlineOperator::Lookup { fields, .. } => {
+                    fields.iter().for_each(|field| self.add(field))
+                }
+                InlineOperator::Csv { fields, .. } if fields.is_empty() => *self = KnownFields::Any,
+                InlineOperator::Csv { fields, .. } => {
+                    fields.iter().for_each(|field| self.add(field))
+                }
+                InlineOperator::AccessLog { .. } => operator::accesslog::FIELDS
+                    .iter()
+                    .for_each(|field| self.add(field)),
+                InlineOperator::Url { .. } => operator::url::FIELDS
+                    .iter()
+                    .for_each(|field| self.add(field)),
+                InlineOperator::GeoIp { .. } => operator::geoip::FIELDS
+                    .iter()
+                    .for_each(|field| self.add(field)),
+                InlineOperator::UserAgent { .. } => operator::useragent::FIELDS
+                    .iter()
+                    .for_each(|field| self.add(field)),
+                InlineOperator::Syslog { format, .. } => {
+                    let formats = match format {
+                        Some(SyslogFormat::Rfc3164) => &[operator::syslog::Format::Rfc3164][..],
+                        Some(SyslogFormat::Rfc5424) => &[operator::syslog::Format::Rfc5424][..],
+                        None => &[
+                            operator::syslog::Format::Rfc3164,
+                            operator::syslog::Format::Rfc5424,
+                        ][..],
+                    };
+                    formats
+                        .iter()
+                        .flat_map(|format| format.fields())
+                        .for_each(|field| self.add(field))
+                }
+                InlineOperator::Split { output_column, .. } => match output_column {
+                    Some(Expr::Column {
+                        head: DataAccessAtom::Key(key),
+                        ..
+                    }) => self.add(key),
+                    Some(_) => {}
+                    None => self.add("_split"),
+                },
+                InlineOperator::Explode {
+                    output_column:
+                        Expr::Column {
+                            head: DataAccessAtom::Key(key),
+                            ..
+                        },
+                    ..
+                } => self.add(key),
+                InlineOperator::Explode { .. } => {}
+                InlineOperator::Timeslice { output_column, .. } => {
+                    self.add(output_column.as_deref().unwrap_or("_timeslice"))
+                }
+                InlineOperator::Total { output_column, .. } => self.add(output_column),
+                InlineOperator::Transaction {
+                    key_col_headers, ..
+                } => {
+                    *self = KnownFields::Only {
+                        fields: key_col_headers
+                            .iter()
+                            .cloned()
+                            .chain(operator::transaction::FIELDS.iter().map(|f| f.to_string()))
+                            .collect(),
+                        after_aggregate: false,
+                    }
+                }
+                InlineOperator::FieldExpression { name, .. } => self.add(name),
+                InlineOperator::Rename {
+                    pattern: RenamePattern::Field(field),
+                    replacement,
+                } => {
+                    if let KnownFields::Only { fields, .. } = self {
+                        if fields.remove(field) {
+                            fields.insert(replacement.clone());
+                        }
+                    }
+                }
+                // Patterns could select any of the fields, when they aren't known.
+                InlineOperator::Fields {
+                    mode: FieldMode::Only,
+                    fields: selected,
+                    ..
+                } => match self {
+                    KnownFields::Any if selected.iter().any(|f| fields::is_pattern(f)) => {}
+                    KnownFields::Any => {
+                        *self = KnownFields::Only {
+                            fields: selected.iter().cloned().collect(),
+                            after_aggregate: false,
+                        }
+                    }
+                    KnownFields::Only { fields, .. } => {
+                        fields.retain(|f| selects_field(selected, f))
+                    }
+                },
+                InlineOperator::Fields {
+                    mode: FieldMode::Except,
+                    fields: dropped,
+                    ..
+                } => {
+                    if let KnownFields::Only { fields, .. } = self {
+                        fields.retain(|f| !selects_field(dropped, f))
+                    }
+                }
+                InlineOperator::Where { .. }
+                | InlineOperator::Limit { .. }
+                | InlineOperator::Sample { .. }
+                | InlineOperator::Dedup { .. } => {}
+            }
+        }
+    }
+
+    /// Whether `fields` given the names or patterns would select the field.  Metadata fields
+    /// are assumed to be left out, as they are by default.
+    fn selects_field(names: &[String], field: &str) -> bool {
+        names
+            .iter()
+            .any(|name| name == field || fields::matches(name, field, false))
+    }
+
+    /// Collect the top-level names of the fields referenced by an expression.
+    pub(crate) fn referenced_fields<'a>(expr: &'a Expr, out: &mut Vec<&'a str>) {
+        match expr {
+            Expr::Column {
+                head: DataAccessAtom::Key(key),
+                ..
+            } => out.push(key),
+            Expr::Column { .. } | Expr::Value(_) | Expr::Error => {}
+            Expr::Unary { operand, .. } => referenced_fields(operand, out),
+            Expr::Binary { left, right, .. } => {
+                referenced_fields(left, out);
+                referenced_fields(right, out);
+            }
+            Expr::FunctionCall { args, .. } => {
+                args.iter().for_each(|arg| referenced_fields(arg, out))
+            }
+            Expr::In { value, list } => {
+                referenced_fields(value, out);
+                list.iter().for_each(|item| referenced_fields(item, out))
+            }
+            Expr::Match { value, .. } => referenced_fields(value, out),
+            Expr::IfOp {
+                cond,
+                value_if_true,
+                value_if_false,
+            } => {
+                referenced_fields(cond, out);
+                referenced_fields(value_if_true, out);
+                referenced_fields(value_if_false, out);
+            }
+        }
+    }
+
+    /// Warn about `where` and `fields` operators that refer to a field that no earlier operator
+    /// could have produced, e.g. a `where` on a field that was dropped by an aggregate.  These are
+    /// only warnings since the query alone can't say for sure what fields the input will have.
+    /// With `typed_input`, the input's rows already have fields, so only the fields after an
+    /// aggregate are checked.  With `multiple_files`, every record starts with `__file__`.
+    fn check_field_references(
+        operators: &[Operator],
+        query: &QueryContainer,
+        options: &PipelineOptions,
+    ) {
+        let mut known = if options.typed_input {
+            KnownFields::Any
+        } else {
+            KnownFields::empty()
+        };
+        if options.multiple_files {
+            known.add("__file__");
+        }
+        check_fields_known(operators, query, known);
+    }
+
+    /// Warn about references to fields that aren't known to exist, starting from the fields
+    /// that are known before the operators.  Branches are each checked from the fields known
+    /// before them.
+    fn check_fields_known(operators: &[Operator], query: &QueryContainer, mut known: KnownFields) {
+        for op in operators {
+            if let Operator::Branch(branch_op) = op {
+                for branch in &branch_op.branches {
+                    check_fields_known(branch, query, known.clone());
+                }
+            }
+            if let (
+                Operator::Inline(inline_op),
+                KnownFields::Only {
+                    fields,
+                    after_aggregate,
+                },
+            ) = (op, &known)
+            {
+                let mut referenced = vec![];
+                let operator_name = match &inline_op.value {
+                    InlineOperator::Where { expr: Some(expr) } => {
+                        referenced_fields(&expr.value, &mut referenced);
+                        "where"
+                    }
+                    InlineOperator::Fields { fields, .. } => {
+                        referenced.extend(
+                            fields
+                                .iter()
+                                .map(String::as_str)
+                                .filter(|f| !fields::is_pattern(f)),
+                        );
+                        "fields"
+                    }
+                    _ => "",
+                };
+                for field in referenced.into_iter().filter(|f| !fields.contains(*f)) {
+                    let builder = query
+                        .report_warning_for(format!(
+                            "`{}` refers to `{}`, which no earlier operator produces",
+                            operator_name, field
+                        ))
+                        .with_code_pointer(inline_op, "");
+                    let builder = if *after_aggregate {
+                        builder.with_resolution(format!(
+                            "after an aggregate, only its columns are available: {}",
+                            fields.iter().sorted().join(", ")
+                        ))
+                    } else {
+                        builder.with_resolution(
+                            "fields are only available after an operator like `parse` or `json` \
+                             creates them, and until they're dropped",
+                        )
+                    };
+                    builder.send_report();
+                }
+            }
+            known.apply(op);
+        }
     }
 
     fn convert_filter(filter: Search) -> filter::Filter {
@@ -70,6 +1005,7 @@ pub mod pipeline {
         fn convert_sort(
             op: SortOperator,
             pipeline: &QueryContainer,
+            max_memory: Option<usize>,
         ) -> Result<Box<dyn operator::AggregateOperator>, TypeError> {
             let mode = match op.direction {
                 SortMode::Ascending => sort::SortDirection::Ascending,
@@ -80,12 +1016,36 @@ pub mod pipeline {
                 .into_iter()
                 .map(|expr| expr.type_check(pipeline))
                 .collect::<Result<Vec<_>, _>>()?;
-            Ok(Box::new(sort::Sorter::new(sort_cols, mode)))
+            Ok(Box::new(
+                sort::Sorter::new(sort_cols, mode).with_max_memory(max_memory),
+            ))
+        }
+
+        fn convert_top(
+            op: TopOperator,
+            pipeline: &QueryContainer,
+        ) -> Result<Box<dyn operator::AggregateOperator>, TypeError> {
+            let mode = match op.mode {
+                TopMode::Most => top::TopMode::Most,
+                TopMode::Least => top::TopMode::Least,
+            };
+            let key_cols: Vec<operator::Expr> = op
+                .key_cols
+                .into_iter()
+                .map(|expr| expr.type_check(pipeline))
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(Box::new(top::Top::new(
+                key_cols,
+                op.key_col_headers,
+                op.count,
+                mode,
+            )))
         }
 
         fn convert_multi_agg(
             op: MultiAggregateOperator,
             pipeline: &QueryContainer,
+            max_memory: Option<usize>,
         ) -> Result<Box<dyn operator::AggregateOperator>, TypeError> {
             let mut agg_functions = Vec::with_capacity(op.aggregate_functions.len());
 
@@ -98,11 +1058,10 @@ pub mod pipeline {
                 .into_iter()
                 .map(|expr| expr.type_check(pipeline))
                 .collect::<Result<Vec<_>, _>>()?;
-            Ok(Box::new(operator::MultiGrouper::new(
-                &(key_cols)[..],
-                op.key_col_headers,
-                agg_functions,
-            )))
+            Ok(Box::new(
+                operator::MultiGrouper::new(&(key_cols)[..], op.key_col_headers, agg_functions)
+                    .with_max_memory(max_memory),
+            ))
         }
 
         fn implicit_sort(multi_agg: &MultiAggregateOperator) -> SortOperator {
@@ -129,40 +1088,107 @@ pub mod pipeline {
             }
         }
 
-        pub fn new<W: 'static + Write + Send>(
+        /// Turn the operators into the stages that run them: the ones that run on each record as
+        /// it's read, and the ones that run on the output of the first aggregate.  When `in_agg`
+        /// is set, the operators follow an aggregate, so they all run on its output.
+        fn compile(
+            operators: Vec<Operator>,
             pipeline: &QueryContainer<'static>,
-            output: W,
-            output_mode: OutputMode,
-        ) -> Result<Self, Error> {
-            let query = pipeline.parse()?;
-            let filters = convert_filter(query.search);
-            let mut in_agg = false;
-            let mut pre_agg: Vec<Box<dyn operator::UnaryPreAggOperator>> = Vec::new();
+            options: &PipelineOptions,
+            in_agg: bool,
+        ) -> Result<Stages, Error> {
+            let max_memory = options.max_memory;
+            let mut in_agg = in_agg;
+            let mut pre_agg: Vec<PreAggDef> = Vec::new();
+            let mut stage_names = Vec::new();
             let mut post_agg: Vec<Box<dyn operator::AggregateOperator>> = Vec::new();
-            let mut op_deque = query.operators.into_iter().collect::<VecDeque<_>>();
+            let mut op_deque = operators.into_iter().collect::<VecDeque<_>>();
             let mut has_errors = false;
+            let mut primary_aggregate = None;
+            let mut key_columns = Vec::new();
             while let Some(op) = op_deque.pop_front() {
                 match op {
                     Operator::Error => {}
-                    Operator::RenderedAlias(rendered_alias) => {
-                        rendered_alias
+                    Operator::RenderedAlias { operators, .. } => {
+                        operators
                             .into_iter()
                             .rev()
                             .for_each(|op| op_deque.push_front(op));
                     }
                     Operator::Inline(inline_op) => {
-                        let op_builder = inline_op.type_check(pipeline)?;
+                        let drop_kind = DropKind::of(&inline_op.value);
+                        let name = inline_op.value.to_string();
+                        let op_builder = match inline_op.value {
+                            InlineOperator::Join {
+                                mode,
+                                path,
+                                operators,
+                                keys,
+                            } => {
+                                let keys = keys
+                                    .into_iter()
+                                    .map(|key| key.type_check(pipeline))
+                                    .collect::<Result<Vec<_>, _>>()?;
+                                let mode = match mode {
+                                    JoinMode::Inner => join::JoinMode::Inner,
+                                    JoinMode::Left => join::JoinMode::Left,
+                                };
+                                let path =
+                                    path.map(PathBuf::from).or_else(|| options.file2.clone());
+                                // Without a file, the operators run on the same rows as the join,
+                                // which are the output of an aggregate if it follows one.
+                                let right = Pipeline::compile(
+                                    operators,
+                                    pipeline,
+                                    options,
+                                    in_agg && path.is_none(),
+                                )?;
+                                let right = branch::AggregateBranch::new(
+                                    right.pre_agg.into_iter().map(|(_, def)| def).collect(),
+                                    right.post_agg,
+                                );
+                                match path {
+                                    Some(path) => Box::new(Pipeline::load_join(
+                                        &path,
+                                        keys,
+                                        mode,
+                                        right,
+                                        pipeline,
+                                        inline_op.range,
+                                    )?),
+                                    // The rows are all held until the end of the input, like
+                                    // an aggregate.
+                                    None => {
+                                        in_agg = true;
+                                        post_agg
+                                            .push(Box::new(join::SelfJoin::new(keys, mode, right)));
+                                        continue;
+                                    }
+                                }
+                            }
+                            _ => inline_op.type_check_with(options, pipeline)?,
+                        };
 
                         if !in_agg {
-                            pre_agg.push(op_builder.build());
+                            let stage = Stage {
+                                index: pre_agg.len(),
+                                drop_kind,
+                            };
+                            pre_agg.push((stage, op_builder));
+                            stage_names.push((name, drop_kind == DropKind::Parse));
                         } else {
                             post_agg.push(Box::new(operator::PreAggAdapter::new(op_builder)));
                         }
                     }
                     Operator::MultiAggregate(agg_op) => {
                         in_agg = true;
+                        primary_aggregate = agg_op
+                            .aggregate_functions
+                            .first()
+                            .map(|(name, _)| name.clone());
+                        key_columns = agg_op.key_col_headers.clone();
                         let sorter = Pipeline::implicit_sort(&agg_op);
-                        if let Ok(op) = Pipeline::convert_multi_agg(agg_op, pipeline) {
+                        if let Ok(op) = Pipeline::convert_multi_agg(agg_op, pipeline, max_memory) {
                             post_agg.push(op);
 
                             let needs_sort = matches!(
@@ -173,43 +1199,268 @@ pub mod pipeline {
                                 })) | None
                             );
                             if needs_sort {
-                                post_agg.push(Pipeline::convert_sort(sorter, pipeline)?);
+                                post_agg
+                                    .push(Pipeline::convert_sort(sorter, pipeline, max_memory)?);
                             }
                         } else {
                             has_errors = true;
                         }
                     }
                     Operator::Sort(sort_op) => {
-                        post_agg.push(Pipeline::convert_sort(sort_op, pipeline)?)
+                        // Everything after the sort runs on its output, so that a `head` or
+                        // `limit` after it keeps the first rows of the sorted output.
+                        in_agg = true;
+                        post_agg.push(Pipeline::convert_sort(sort_op, pipeline, max_memory)?)
+                    }
+                    Operator::Plugin(plugin_op) => {
+                        let name = plugin_op.value.to_string();
+                        match Pipeline::build_plugin(plugin_op, pipeline)? {
+                            plugin::PluginOperator::Inline(op_builder) if !in_agg => {
+                                let stage = Stage {
+                                    index: pre_agg.len(),
+                                    drop_kind: DropKind::Other,
+                                };
+                                pre_agg.push((stage, op_builder));
+                                stage_names.push((name, false));
+                            }
+                            plugin::PluginOperator::Inline(op_builder) => {
+                                post_agg.push(Box::new(operator::PreAggAdapter::new(op_builder)))
+                            }
+                            plugin::PluginOperator::Aggregate(op) => {
+                                in_agg = true;
+                                post_agg.push(op);
+                            }
+                        }
+                    }
+                    Operator::Top(top_op) => {
+                        in_agg = true;
+                        primary_aggregate = Some("_count".to_string());
+                        key_columns = top_op.key_col_headers.clone();
+                        post_agg.push(Pipeline::convert_top(top_op, pipeline)?);
+                    }
+                    Operator::Branch(branch_op) => {
+                        let name = branch_op.to_string();
+                        let branches = branch_op
+                            .branches
+                            .into_iter()
+                            .map(|operators| {
+                                Pipeline::compile(operators, pipeline, options, in_agg)
+                            })
+                            .collect::<Result<Vec<_>, _>>()?;
+                        // Branches that only have operators that run on each record can pass
+                        // on their records as they come, instead of waiting for the end of the
+                        // input.
+                        if branches.iter().all(|branch| branch.post_agg.is_empty()) {
+                            let stage = Stage {
+                                index: pre_agg.len(),
+                                drop_kind: DropKind::Other,
+                            };
+                            let branches = branches
+                                .into_iter()
+                                .map(|branch| {
+                                    branch.pre_agg.into_iter().map(|(_, def)| def).collect()
+                                })
+                                .collect();
+                            pre_agg.push((stage, Box::new(branch::BranchDef::new(branches))));
+                            stage_names.push((name, false));
+                        } else {
+                            in_agg = true;
+                            if let Some(first) = branches
+                                .iter()
+                                .find(|branch| branch.primary_aggregate.is_some())
+                            {
+                                primary_aggregate = first.primary_aggregate.clone();
+                                key_columns = first.key_columns.clone();
+                            }
+                            let branches = branches
+                                .into_iter()
+                                .map(|branch| {
+                                    branch::AggregateBranch::new(
+                                        branch.pre_agg.into_iter().map(|(_, def)| def).collect(),
+                                        branch.post_agg,
+                                    )
+                                })
+                                .collect();
+                            post_agg.push(Box::new(branch::AggregateBranches::new(branches)));
+                        }
                     }
                 }
             }
             if has_errors {
                 return Err(CompileError::Parse.into());
             }
+            Ok(Stages {
+                in_agg,
+                pre_agg,
+                stage_names,
+                post_agg,
+                primary_aggregate,
+                key_columns,
+            })
+        }
+
+        /// Build the operator of a plugin from the arguments it was given in the query.
+        fn build_plugin(
+            plugin_op: Positioned<PluginOperator>,
+            pipeline: &QueryContainer,
+        ) -> Result<plugin::PluginOperator, TypeError> {
+            let Positioned { range, value } = plugin_op;
+            let plugin = pipeline
+                .plugins
+                .get(&value.keyword)
+                .expect("only registered plugins are parsed");
+            plugin.build(&value.args.value).map_err(|error| {
+                let e = TypeError::PluginArgs {
+                    keyword: value.keyword,
+                    error,
+                };
+                // Without any arguments, the error is about the whole operator.
+                let range = if value.args.range.is_empty() {
+                    range
+                } else {
+                    value.args.range
+                };
+                pipeline
+                    .report_error_for(&e)
+                    .with_code_range(range, "")
+                    .send_report();
+                e
+            })
+        }
+
+        /// Read the records that a `join` matches rows against from a file.
+        fn load_join(
+            path: &Path,
+            keys: Vec<operator::Expr>,
+            mode: join::JoinMode,
+            right: branch::AggregateBranch,
+            pipeline: &QueryContainer,
+            range: QueryRange,
+        ) -> Result<join::Join, TypeError> {
+            let file = std::fs::File::open(path);
+            file.and_then(|f| join::Join::load(keys, mode, io::BufReader::new(f), right))
+                .map_err(|err| {
+                    let e = TypeError::JoinFile {
+                        path: path.display().to_string(),
+                        error: err.to_string(),
+                    };
+                    pipeline
+                        .report_error_for(&e)
+                        .with_code_range(range, "")
+                        .send_report();
+                    e
+                })
+        }
+
+        /// Render the query with its aliases expanded, followed by the plan of its stages.  The
+        /// query is type checked by building the pipeline, without reading any input.
+        pub fn explain(pipeline: &QueryContainer<'static>) -> Result<String, Error> {
+            let query = pipeline.parse()?;
+            Pipeline::from_query(
+                query.clone(),
+                pipeline,
+                io::sink(),
+                OutputMode::Legacy,
+                PipelineOptions::default(),
+            )?;
+            Ok(format!(
+                "{}\n\n{}",
+                crate::explain::explain(&query),
+                crate::explain::plan(&query).trim_end()
+            ))
+        }
+
+        pub fn new<W: 'static + Write + Send>(
+            pipeline: &QueryContainer<'static>,
+            output: W,
+            output_mode: OutputMode,
+        ) -> Result<Self, Error> {
+            Pipeline::new_with_options(pipeline, output, output_mode, PipelineOptions::default())
+        }
+
+        pub fn new_with_options<W: 'static + Write + Send>(
+            pipeline: &QueryContainer<'static>,
+            output: W,
+            output_mode: OutputMode,
+            options: PipelineOptions,
+        ) -> Result<Self, Error> {
+            let query = pipeline.parse()?;
+            Pipeline::from_query(query, pipeline, output, output_mode, options)
+        }
+
+        fn from_query<W: 'static + Write + Send>(
+            query: Query,
+            pipeline: &QueryContainer<'static>,
+            output: W,
+            output_mode: OutputMode,
+            options: PipelineOptions,
+        ) -> Result<Self, Error> {
+            let operators = query.operators.clone();
+            let filters = convert_filter(query.search);
+            let Stages {
+                in_agg,
+                pre_agg,
+                stage_names,
+                post_agg,
+                primary_aggregate,
+                key_columns,
+            } = Pipeline::compile(query.operators, pipeline, &options, false)?;
+            if !in_agg && output_mode == OutputMode::Prometheus {
+                return Err(CompileError::PrometheusWithoutAggregate.into());
+            }
+            if !in_agg && matches!(output_mode, OutputMode::Chart(_) | OutputMode::Sparkline(_)) {
+                return Err(CompileError::ChartWithoutAggregate.into());
+            }
+            check_field_references(&operators, pipeline, &options);
+            let color = match options.color {
+                ColorChoice::Auto => TerminalConfig::load().color_enabled,
+                ColorChoice::Always => true,
+                ColorChoice::Never => false,
+            };
             let render_config = RenderConfig {
                 display_config: DisplayConfig { floating_points: 2 },
                 min_buffer: 4,
                 max_buffer: 8,
+                table: options.table,
+                theme: if color { Some(options.theme) } else { None },
             };
             let raw_printer =
                 raw_printer(&output_mode, render_config.clone(), TerminalConfig::load())?;
-            let agg_printer = agg_printer(&output_mode, render_config, TerminalConfig::load())?;
+            let agg_printer = agg_printer(
+                &output_mode,
+                render_config.clone(),
+                TerminalConfig::load(),
+                &key_columns,
+            )?;
+            let leaderboard = match options.leaderboard {
+                Some(Leaderboard::PrimaryAggregate) => primary_aggregate,
+                Some(Leaderboard::Column(column)) => Some(column),
+                None => None,
+            };
             Ok(Pipeline {
                 filter: filters,
                 pre_aggregates: pre_agg,
                 aggregators: post_agg,
                 renderer: Renderer::new(
-                    RenderConfig {
-                        display_config: DisplayConfig { floating_points: 2 },
-                        min_buffer: 4,
-                        max_buffer: 8,
-                    },
-                    Duration::from_millis(50),
+                    render_config,
+                    options.refresh_interval.unwrap_or(DEFAULT_REFRESH_INTERVAL),
                     raw_printer,
                     agg_printer,
                     Box::new(output),
-                ),
+                    leaderboard,
+                    options.render_once,
+                )
+                .with_snapshot(options.snapshot),
+                reader: RecordReader {
+                    strict_utf8: options.strict_utf8,
+                    max_line_length: options.max_line_length,
+                    skip_long_lines: options.skip_long_lines,
+                    strip_ansi: options.strip_ansi,
+                    multiline_start: options.multiline_start,
+                },
+                strict_parse: options.strict_parse,
+                threads: options.threads,
+                stage_names,
             })
         }
 
@@ -229,6 +1480,9 @@ pub mod pipeline {
                     Err(RecvTimeoutError::Disconnected) => break,
                 }
             }
+            if let Err(e) = renderer.finish() {
+                eprintln!("error: {}", e);
+            }
         }
 
         fn render_aggregate(
@@ -246,8 +1500,8 @@ pub mod pipeline {
                 }
 
                 if renderer.should_print() {
-                    let result =
-                        renderer.render(&Pipeline::run_agg_pipeline(&*head, &mut rest), false);
+                    let result = renderer
+                        .render(&Pipeline::run_agg_pipeline(&*head, &mut rest, false), false);
 
                     if let Err(e) = result {
                         eprintln!("error: {}", e);
@@ -255,93 +1509,642 @@ pub mod pipeline {
                     }
                 }
             }
-            let result = renderer.render(&Pipeline::run_agg_pipeline(&*head, &mut rest), true);
+            head.finish();
+            let result =
+                renderer.render(&Pipeline::run_agg_pipeline(&*head, &mut rest, true), true);
 
             if let Err(e) = result {
                 eprintln!("error: {}", e);
             }
         }
 
-        pub fn process<T: BufRead>(self, mut buf: T) {
+        /// Run the lines of a file through the pipeline as they're written to it, like
+        /// `tail -F`.  Truncation and rotation of the file are handled by reopening it.  This
+        /// only returns if the file can't be read, so aggregates are only ever rendered live.
+        pub fn follow<P: Into<PathBuf>>(self, path: P) -> Result<PipelineStats, InputError> {
+            let file = FollowFile::open(path)?;
+            // New lines are processed as they're written instead of waiting for a full batch.
+            let pipeline = Pipeline { threads: 1, ..self };
+            pipeline.process(io::BufReader::new(file))
+        }
+
+        /// Run the input through the pipeline, returning counts of the lines and rows that were
+        /// processed once all of the output has been rendered.  Reading stops early if the input
+        /// can't be read, with `strict_utf8`, contains invalid UTF-8 or, with `strict_parse`, has a
+        /// line that a parsing operator doesn't match.
+        pub fn process<T: BufRead>(self, buf: T) -> Result<PipelineStats, InputError> {
+            self.process_inputs(std::iter::once(Ok((None, Input::Text(buf)))))
+        }
+
+        /// Run the lines of each file through the pipeline in turn, as if they were one input.
+        /// When there's more than one file, each record gets a `__file__` field holding the name
+        /// of the file it was read from.  Parquet and Arrow IPC files are read a row at a time
+        /// instead, with each row's columns as the fields of its record.
+        pub fn process_files<P: AsRef<Path>>(
+            self,
+            paths: &[P],
+        ) -> Result<PipelineStats, InputError> {
+            let tag = paths.len() > 1;
+            self.process_inputs(paths.iter().map(|path| {
+                let path = path.as_ref();
+                let input = match crate::columnar::detect(path)? {
+                    Some(format) => Input::Rows(crate::columnar::open(path, format)?),
+                    None => Input::Text(io::BufReader::new(crate::decompress::open(path)?)),
+                };
+                Ok((tag.then(|| path.display().to_string()), input))
+            }))
+        }
+
+        /// Run each input through the pipeline in turn.  Inputs that come with a file name have
+        /// it added to their records as `__file__`.
+        fn process_inputs<T: BufRead>(
+            self,
+            inputs: impl Iterator<Item = io::Result<(Option<String>, Input<T>)>>,
+        ) -> Result<PipelineStats, InputError> {
             let (tx, rx) = bounded(1000);
-            let mut aggregators = self.aggregators;
-            let mut preaggs = self.pre_aggregates;
-            let renderer = self.renderer;
-            let t = if !aggregators.is_empty() {
+            let Pipeline {
+                filter,
+                pre_aggregates,
+                aggregators,
+                renderer,
+                reader,
+                strict_parse,
+                threads,
+                stage_names,
+            } = self;
+            let start = Instant::now();
+            let mut stats = PipelineStats::default();
+            let t = Pipeline::spawn_renderer(aggregators, renderer, rx);
+
+            let result = if threads > 1 {
+                Pipeline::process_parallel(
+                    &reader,
+                    inputs,
+                    &filter,
+                    &pre_aggregates,
+                    threads,
+                    &tx,
+                    &mut stats,
+                    strict_parse,
+                )
+            } else {
+                let mut preaggs = Pipeline::build_preaggs(&pre_aggregates);
+                reader
+                    .read(inputs, &mut stats, |record, file, stats| {
+                        Pipeline::proc_record(
+                            record,
+                            file,
+                            &filter,
+                            &mut preaggs,
+                            stats,
+                            strict_parse,
+                            &mut |rec, stats| Pipeline::send(&tx, rec, stats),
+                        )
+                    })
+                    .map(|_| {
+                        Pipeline::drain_preaggs(preaggs, &mut stats, &mut |rec, stats| {
+                            Pipeline::send(&tx, rec, stats)
+                        })
+                    })
+            };
+
+            // Drop tx when causes the thread to exit.
+            drop(tx);
+            match t.join() {
+                Ok(_) => (),
+                Err(e) => println!("Error: {:?}", e),
+            }
+            Pipeline::finish_stats(&mut stats, stage_names, start);
+            result.map(|_| stats)
+        }
+
+        /// Start the thread that aggregates and renders the rows sent to it.
+        fn spawn_renderer(
+            mut aggregators: Vec<Box<dyn operator::AggregateOperator>>,
+            renderer: Renderer,
+            rx: Receiver<Row>,
+        ) -> thread::JoinHandle<()> {
+            if !aggregators.is_empty() {
                 let head = aggregators.remove(0);
                 thread::spawn(move || Pipeline::render_aggregate(head, aggregators, renderer, &rx))
             } else {
                 thread::spawn(move || Pipeline::render_noagg(renderer, &rx))
-            };
+            }
+        }
 
-            // This is pretty slow in practice. We could move line splitting until after
-            // we find a match. Another option is moving the transformation to String until
-            // after we match (staying as Vec<u8> until then)
-            let mut line = Vec::with_capacity(1024);
-            loop {
-                let ct = buf.read_until(b'\n', &mut line).unwrap();
-                if ct == 0 {
-                    break;
-                }
-                let data = String::from_utf8_lossy(&line[..ct]);
-                if self.filter.matches(data.as_ref())
-                    && !Pipeline::proc_preagg(Record::new(data), &mut preaggs, &tx)
-                {
-                    break;
-                }
-                line.clear();
+        /// Name the operators in the stats and record how long the run took.
+        fn finish_stats(
+            stats: &mut PipelineStats,
+            stage_names: Vec<(String, bool)>,
+            start: Instant,
+        ) {
+            for (stage, (name, parser)) in stage_names.into_iter().enumerate() {
+                let op = stats.operator_mut(stage);
+                op.operator = name;
+                op.parser = parser;
             }
+            stats.elapsed = start.elapsed();
+            stats.peak_memory = peak_memory();
+        }
+
+        fn build_preaggs(defs: &[PreAggDef]) -> Vec<PreAggStage> {
+            defs.iter()
+                .map(|(stage, builder)| (*stage, builder.build()))
+                .collect()
+        }
+
+        /// Send a row that made it through the pre-agg operators on to be aggregated or rendered.
+        /// Returns whether the receiver is still listening.
+        fn send(tx: &Sender<Row>, rec: Record, stats: &mut PipelineStats) -> bool {
+            stats.rows_output += 1;
+            tx.send(Row::Record(rec)).is_ok()
+        }
 
-            // Drain any remaining records from the operators.
+        /// Drain any remaining records from the operators, running them through the operators
+        /// that follow.
+        fn drain_preaggs(
+            mut preaggs: Vec<PreAggStage>,
+            stats: &mut PipelineStats,
+            emit: &mut impl FnMut(Record, &mut PipelineStats) -> bool,
+        ) {
             while !preaggs.is_empty() {
-                let preagg = preaggs.remove(0);
+                let (_, preagg) = preaggs.remove(0);
 
                 for rec in preagg.drain() {
-                    if !Pipeline::proc_preagg(rec, &mut preaggs, &tx) {
+                    if let Ok(false) = Pipeline::proc_preagg(rec, &mut preaggs, stats, false, emit)
+                    {
                         break;
                     }
                 }
             }
+        }
 
-            // Drop tx when causes the thread to exit.
-            drop(tx);
-            match t.join() {
-                Ok(_) => (),
-                Err(e) => println!("Error: {:?}", e),
+        /// Split the work of searching and parsing the input between `threads` workers.  The
+        /// records are read on this thread and handed out in batches.  Each worker has its own
+        /// copy of the pre-agg operators up to the first that needs to see every record in order,
+        /// like `limit`.  The batches that come back are put in the order they were read before
+        /// that operator and the rest are run, so the output is the same as with one thread.
+        #[allow(clippy::too_many_arguments)]
+        fn process_parallel<T: BufRead>(
+            reader: &RecordReader,
+            inputs: impl Iterator<Item = io::Result<(Option<String>, Input<T>)>>,
+            filter: &filter::Filter,
+            pre_aggregates: &[PreAggDef],
+            threads: usize,
+            tx: &Sender<Row>,
+            stats: &mut PipelineStats,
+            strict_parse: bool,
+        ) -> Result<(), InputError> {
+            let split = pre_aggregates
+                .iter()
+                .position(|(_, builder)| !builder.parallel())
+                .unwrap_or(pre_aggregates.len());
+            let (parallel, sequential) = pre_aggregates.split_at(split);
+            thread::scope(|scope| {
+                let (batch_tx, batch_rx) = bounded::<Batch>(threads * 2);
+                let (output_tx, output_rx) = bounded::<BatchOutput>(threads * 2);
+                for _ in 0..threads {
+                    let batch_rx = batch_rx.clone();
+                    let output_tx = output_tx.clone();
+                    let mut preaggs = Pipeline::build_preaggs(parallel);
+                    scope.spawn(move || {
+                        Pipeline::work(filter, &mut preaggs, &batch_rx, &output_tx, strict_parse)
+                    });
+                }
+                drop(batch_rx);
+                drop(output_tx);
+                let preaggs = Pipeline::build_preaggs(sequential);
+                let sequencer =
+                    scope.spawn(move || Pipeline::sequence(preaggs, &output_rx, tx, strict_parse));
+
+                let mut batch = Batch::default();
+                let read = reader.read(inputs, stats, |(line, data), file, _stats| {
+                    if batch.records.len() >= BATCH_SIZE || batch.file != *file {
+                        let next = Batch {
+                            seq: batch.seq + 1,
+                            file: file.clone(),
+                            records: Vec::with_capacity(BATCH_SIZE),
+                        };
+                        if batch_tx.send(std::mem::replace(&mut batch, next)).is_err() {
+                            return Ok(false);
+                        }
+                    }
+                    batch.records.push((line, data.into_owned()));
+                    Ok(true)
+                });
+                let _ = batch_tx.send(batch);
+                drop(batch_tx);
+                let (sequenced, result) = sequencer.join().expect("sequencer panicked");
+                stats.merge(&sequenced);
+                // An error from the sequencer came from a line before the one the reader stopped
+                // on, if it stopped early.
+                result.and(read)
+            })
+        }
+
+        /// Run each batch of records through the search filter and the pre-agg operators, sending
+        /// back the rows that make it through.
+        fn work(
+            filter: &filter::Filter,
+            preaggs: &mut [PreAggStage],
+            batches: &Receiver<Batch>,
+            outputs: &Sender<BatchOutput>,
+            strict_parse: bool,
+        ) {
+            for batch in batches {
+                let mut output = BatchOutput {
+                    seq: batch.seq,
+                    ..BatchOutput::default()
+                };
+                for (line, data) in batch.records {
+                    let mut rows = Vec::new();
+                    let result = Pipeline::proc_record(
+                        (line, data),
+                        &batch.file,
+                        filter,
+                        preaggs,
+                        &mut output.stats,
+                        strict_parse,
+                        &mut |rec, _stats| {
+                            rows.push(rec);
+                            true
+                        },
+                    );
+                    output.rows.extend(rows.into_iter().map(|rec| (line, rec)));
+                    if let Err(error) = result {
+                        output.error = Some(error);
+                        break;
+                    }
+                }
+                if outputs.send(output).is_err() {
+                    return;
+                }
             }
         }
 
-        /// Process a record using the pre-agg operators.  The output of the last operator will be
-        /// sent to `tx`.
-        fn proc_preagg(
-            mut rec: Record,
-            pre_aggs: &mut [Box<dyn operator::UnaryPreAggOperator>],
+        /// Put the batches from the workers back in order and run their rows through the rest of
+        /// the pre-agg operators.  Returns the counts from the workers along with those of the
+        /// operators run here.
+        fn sequence(
+            mut preaggs: Vec<PreAggStage>,
+            outputs: &Receiver<BatchOutput>,
             tx: &Sender<Row>,
-        ) -> bool {
-            for pre_agg in pre_aggs {
-                match (*pre_agg).process_mut(rec) {
-                    Ok(Some(next_rec)) => rec = next_rec,
-                    Ok(None) => return true,
-                    Err(err) => {
-                        eprintln!("error: {}", err);
-                        return true;
+            strict_parse: bool,
+        ) -> (PipelineStats, Result<(), InputError>) {
+            let mut stats = PipelineStats::default();
+            let mut send = |rec, stats: &mut PipelineStats| Pipeline::send(tx, rec, stats);
+            let mut pending = BTreeMap::new();
+            let mut next = 0;
+            'outputs: for output in outputs {
+                pending.insert(output.seq, output);
+                while let Some(output) = pending.remove(&next) {
+                    next += 1;
+                    stats.merge(&output.stats);
+                    for (line, rec) in output.rows {
+                        match Pipeline::proc_preagg(
+                            rec,
+                            &mut preaggs,
+                            &mut stats,
+                            strict_parse,
+                            &mut send,
+                        ) {
+                            Ok(true) => {}
+                            // The rest of the operators still need to be drained, e.g. after a
+                            // `head` has output all of its rows.
+                            Ok(false) => break 'outputs,
+                            Err(content) => {
+                                let error = InputError::ParseFailed {
+                                    line,
+                                    content: content.trim_end().to_string(),
+                                };
+                                return (stats, Err(error));
+                            }
+                        }
+                    }
+                    if let Some(error) = output.error {
+                        return (stats, Err(error));
                     }
                 }
             }
+            Pipeline::drain_preaggs(preaggs, &mut stats, &mut send);
+            (stats, Ok(()))
+        }
 
-            tx.send(Row::Record(rec)).is_ok()
+        /// Run a record of input, which starts on the given line, through the search filter and
+        /// the pre-agg operators, passing the row that comes out to `emit`.  Returns whether more
+        /// input is needed.
+        fn proc_record(
+            (line, data): (u64, InputRecord),
+            file: &Option<String>,
+            filter: &filter::Filter,
+            pre_aggs: &mut [PreAggStage],
+            stats: &mut PipelineStats,
+            strict_parse: bool,
+            emit: &mut impl FnMut(Record, &mut PipelineStats) -> bool,
+        ) -> Result<bool, InputError> {
+            if !filter.matches(data.text()) {
+                return Ok(true);
+            }
+            stats.lines_matched += 1;
+            let mut rec = match data {
+                InputRecord::Text(data) => Record::new(data),
+                InputRecord::Parsed(rec) => rec,
+            };
+            if let Some(file) = file {
+                rec.put_mut("__file__", Value::Str(file.clone()));
+            }
+            Pipeline::proc_preagg(rec, pre_aggs, stats, strict_parse, emit).map_err(|content| {
+                InputError::ParseFailed {
+                    line,
+                    content: content.trim_end().to_string(),
+                }
+            })
+        }
+
+        /// Process a record using the pre-agg operators.  The output of the last operator is
+        /// passed to `emit`, which returns whether more input is needed.  No more is needed once
+        /// an operator, like `head`, won't pass on any more records.  With `strict_parse`, a row
+        /// that a parsing operator drops or fails on is returned as an error with the raw line.
+        fn proc_preagg(
+            rec: Record,
+            pre_aggs: &mut [PreAggStage],
+            stats: &mut PipelineStats,
+            strict_parse: bool,
+            emit: &mut impl FnMut(Record, &mut PipelineStats) -> bool,
+        ) -> Result<bool, String> {
+            let more = Pipeline::run_preaggs(rec, pre_aggs, stats, strict_parse, emit)?;
+            Ok(more && !pre_aggs.iter().any(|(_, pre_agg)| pre_agg.exhausted()))
+        }
+
+        fn run_preaggs(
+            rec: Record,
+            pre_aggs: &mut [PreAggStage],
+            stats: &mut PipelineStats,
+            strict_parse: bool,
+            emit: &mut impl FnMut(Record, &mut PipelineStats) -> bool,
+        ) -> Result<bool, String> {
+            let raw = strict_parse.then(|| rec.raw.clone());
+            Pipeline::run_stages(rec, pre_aggs, stats, raw.as_deref(), emit)
+        }
+
+        /// Run a record through the remaining stages.  When a stage turns it into several
+        /// records, like `explode`, each of them goes through the stages after it in turn.
+        fn run_stages(
+            rec: Record,
+            pre_aggs: &mut [PreAggStage],
+            stats: &mut PipelineStats,
+            raw: Option<&str>,
+            emit: &mut impl FnMut(Record, &mut PipelineStats) -> bool,
+        ) -> Result<bool, String> {
+            let ((stage, pre_agg), rest) = match pre_aggs.split_first_mut() {
+                Some(split) => split,
+                None => return Ok(emit(rec, stats)),
+            };
+            let parse_failed = match (*pre_agg).process_mut(rec) {
+                Ok(Some(next_rec)) => {
+                    let mut more = Pipeline::run_stages(next_rec, rest, stats, raw, emit)?;
+                    while more {
+                        match pre_agg.pending() {
+                            Some(next_rec) => {
+                                more = Pipeline::run_stages(next_rec, rest, stats, raw, emit)?
+                            }
+                            None => break,
+                        }
+                    }
+                    return Ok(more);
+                }
+                Ok(None) if pre_agg.consumed_last() => return Ok(true),
+                Ok(None) => {
+                    match stage.drop_kind {
+                        DropKind::Parse => stats.dropped_by_parse += 1,
+                        DropKind::Where => stats.dropped_by_where += 1,
+                        DropKind::Other => {}
+                    }
+                    if stage.drop_kind != DropKind::Other {
+                        stats.operator_mut(stage.index).dropped += 1;
+                    }
+                    stage.drop_kind == DropKind::Parse
+                }
+                Err(err) => {
+                    stats.errors += 1;
+                    stats.operator_mut(stage.index).errors += 1;
+                    if stage.drop_kind != DropKind::Parse || raw.is_none() {
+                        eprintln!("error: {}", err);
+                    }
+                    stage.drop_kind == DropKind::Parse
+                }
+            };
+            match raw {
+                Some(raw) if parse_failed => Err(raw.to_string()),
+                _ => Ok(true),
+            }
         }
 
+        /// Run the aggregate through the operators after it.  Until the `last_row`, the operators
+        /// only show a live view of their rows.
         pub fn run_agg_pipeline(
             head: &dyn operator::AggregateOperator,
             rest: &mut [Box<dyn operator::AggregateOperator>],
+            last_row: bool,
         ) -> Row {
-            let mut row = Row::Aggregate((*head).emit());
+            let emit = |agg: &dyn operator::AggregateOperator| {
+                if last_row {
+                    agg.emit()
+                } else {
+                    agg.emit_live()
+                }
+            };
+            let mut row = Row::Aggregate(emit(head));
             for agg in (*rest).iter_mut() {
                 (*agg).process(row);
-                row = Row::Aggregate((*agg).emit());
+                row = Row::Aggregate(emit(&**agg));
             }
             row
         }
     }
+
+    /// Output that's held in memory, shared with the renderer that writes it.
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().expect("not poisoned").extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// A pipeline's share of a `MultiPipeline` run.
+    struct Branch {
+        filter: filter::Filter,
+        preaggs: Vec<PreAggStage>,
+        tx: Sender<Row>,
+        renderer: thread::JoinHandle<()>,
+        stats: PipelineStats,
+        stage_names: Vec<(String, bool)>,
+        strict_parse: bool,
+        /// Whether the pipeline needs more input.
+        more: bool,
+    }
+
+    /// Several queries that are run over a single pass of the input, with each record read
+    /// handed to every query in turn.  The output of each query is held until the input ends,
+    /// then written under a `==> query <==` heading, in the order the queries were given.
+    pub struct MultiPipeline {
+        pipelines: Vec<(String, Pipeline, SharedBuffer)>,
+        output: Box<dyn Write + Send>,
+    }
+
+    impl MultiPipeline {
+        /// Aggregates are rendered once each, when the input ends, and the input is read on a
+        /// single thread, whatever `options` say.
+        pub fn new_with_options<W: 'static + Write + Send>(
+            queries: &[QueryContainer<'static>],
+            output: W,
+            output_mode: OutputMode,
+            options: PipelineOptions,
+        ) -> Result<Self, Error> {
+            let pipelines = queries
+                .iter()
+                .map(|query| {
+                    let buffer = SharedBuffer::default();
+                    let options = PipelineOptions {
+                        render_once: true,
+                        ..options.clone()
+                    };
+                    let pipeline = Pipeline::new_with_options(
+                        query,
+                        buffer.clone(),
+                        output_mode.clone(),
+                        options,
+                    )?;
+                    Ok((query.query.clone(), pipeline, buffer))
+                })
+                .collect::<Result<_, Error>>()?;
+            Ok(MultiPipeline {
+                pipelines,
+                output: Box::new(output),
+            })
+        }
+
+        /// Run the input through every query, returning the counts for each, in order.
+        pub fn process<T: BufRead>(self, buf: T) -> Result<Vec<PipelineStats>, InputError> {
+            self.process_inputs(std::iter::once(Ok((None, Input::Text(buf)))))
+        }
+
+        /// Run the lines of each file through every query, as `Pipeline::process_files` does.
+        pub fn process_files<P: AsRef<Path>>(
+            self,
+            paths: &[P],
+        ) -> Result<Vec<PipelineStats>, InputError> {
+            let tag = paths.len() > 1;
+            self.process_inputs(paths.iter().map(|path| {
+                let path = path.as_ref();
+                let input = match crate::columnar::detect(path)? {
+                    Some(format) => Input::Rows(crate::columnar::open(path, format)?),
+                    None => Input::Text(io::BufReader::new(crate::decompress::open(path)?)),
+                };
+                Ok((tag.then(|| path.display().to_string()), input))
+            }))
+        }
+
+        fn process_inputs<T: BufRead>(
+            self,
+            inputs: impl Iterator<Item = io::Result<(Option<String>, Input<T>)>>,
+        ) -> Result<Vec<PipelineStats>, InputError> {
+            let start = Instant::now();
+            let MultiPipeline {
+                pipelines,
+                mut output,
+            } = self;
+            let mut reader = None;
+            let mut outputs = Vec::new();
+            let mut branches: Vec<Branch> = pipelines
+                .into_iter()
+                .map(|(query, pipeline, buffer)| {
+                    let (tx, rx) = bounded(1000);
+                    // Every pipeline reads its input the same way, since they share the options.
+                    reader.get_or_insert(pipeline.reader);
+                    outputs.push((query, buffer));
+                    Branch {
+                        filter: pipeline.filter,
+                        preaggs: Pipeline::build_preaggs(&pipeline.pre_aggregates),
+                        tx,
+                        renderer: Pipeline::spawn_renderer(
+                            pipeline.aggregators,
+                            pipeline.renderer,
+                            rx,
+                        ),
+                        stats: PipelineStats::default(),
+                        stage_names: pipeline.stage_names,
+                        strict_parse: pipeline.strict_parse,
+                        more: true,
+                    }
+                })
+                .collect();
+            let reader = match reader {
+                Some(reader) => reader,
+                None => return Ok(vec![]),
+            };
+
+            let mut read_stats = PipelineStats::default();
+            let result = reader.read(inputs, &mut read_stats, |(line, data), file, _stats| {
+                for branch in branches.iter_mut().filter(|branch| branch.more) {
+                    let data = match data {
+                        InputRecord::Text(ref text) => InputRecord::Text(Cow::Borrowed(text)),
+                        InputRecord::Parsed(ref rec) => InputRecord::Parsed(rec.clone()),
+                    };
+                    let tx = &branch.tx;
+                    branch.more = Pipeline::proc_record(
+                        (line, data),
+                        file,
+                        &branch.filter,
+                        &mut branch.preaggs,
+                        &mut branch.stats,
+                        branch.strict_parse,
+                        &mut |rec, stats| Pipeline::send(tx, rec, stats),
+                    )?;
+                }
+                Ok(branches.iter().any(|branch| branch.more))
+            });
+
+            let mut all_stats = Vec::new();
+            for branch in branches {
+                let Branch {
+                    preaggs,
+                    tx,
+                    renderer,
+                    mut stats,
+                    stage_names,
+                    ..
+                } = branch;
+                Pipeline::drain_preaggs(preaggs, &mut stats, &mut |rec, stats| {
+                    Pipeline::send(&tx, rec, stats)
+                });
+                drop(tx);
+                if let Err(e) = renderer.join() {
+                    println!("Error: {:?}", e);
+                }
+                stats.lines_read = read_stats.lines_read;
+                stats.bytes_read = read_stats.bytes_read;
+                stats.lines_with_invalid_utf8 = read_stats.lines_with_invalid_utf8;
+                stats.long_lines = read_stats.long_lines;
+                Pipeline::finish_stats(&mut stats, stage_names, start);
+                all_stats.push(stats);
+            }
+            for (i, (query, buffer)) in outputs.iter().enumerate() {
+                if i > 0 {
+                    writeln!(output)?;
+                }
+                writeln!(output, "==> {} <==", query.trim())?;
+                output.write_all(&buffer.0.lock().expect("not poisoned"))?;
+            }
+            output.flush()?;
+            result.map(|_| all_stats)
+        }
+    }
 }
@@ -2,8 +2,10 @@ use std::borrow::Cow;
 
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
 use std::iter;
 
+use itertools::Itertools;
 use thiserror::Error;
 
 pub(crate) use expr::Expr;
@@ -13,22 +15,48 @@ use crate::data::{Aggregate, Record, Row};
 
 type Data = HashMap<String, data::Value>;
 
+pub(crate) mod accesslog;
 pub(crate) mod average;
+pub(crate) mod branch;
 pub(crate) mod count;
 pub(crate) mod count_distinct;
+pub(crate) mod csv;
+pub(crate) mod dedup;
+pub(crate) mod explode;
 pub(crate) mod expr;
+pub(crate) mod extreme_by;
 pub(crate) mod fields;
+pub(crate) mod first_last;
+pub mod geoip;
+pub(crate) mod join;
 pub(crate) mod limit;
+pub(crate) mod logfmt;
+pub(crate) mod lookup;
 pub(crate) mod max;
 pub(crate) mod min;
+pub(crate) mod mode;
 // public for benchmarks
 pub mod parse;
 pub(crate) mod percentile;
+pub(crate) mod rename;
+pub(crate) mod sample;
+#[cfg(feature = "script")]
+pub(crate) mod script;
 pub(crate) mod sort;
+pub(crate) mod spill;
 pub(crate) mod split;
 pub(crate) mod sum;
+pub(crate) mod syslog;
 pub(crate) mod timeslice;
+pub(crate) mod top;
 pub(crate) mod total;
+pub(crate) mod transaction;
+pub(crate) mod url;
+pub(crate) mod useragent;
+pub(crate) mod values;
+pub(crate) mod variance;
+#[cfg(feature = "wasm")]
+pub(crate) mod wasm;
 pub(crate) mod where_op;
 
 #[derive(Debug, Error, PartialEq, Eq)]
@@ -48,6 +76,9 @@ pub enum EvalError {
     #[error("Expected JSON, found {}", found)]
     ExpectedJson { found: String },
 
+    #[error("Expected logfmt, found {} ({})", found, reason)]
+    ExpectedLogfmt { found: String, reason: String },
+
     #[error("Expected string, found {}", found)]
     ExpectedString { found: String },
 
@@ -60,6 +91,9 @@ pub enum EvalError {
     )]
     ExpectedDate { found: String },
 
+    #[error("Expected a date in the format \"{}\", found '{}'", format, found)]
+    ExpectedDateFormat { format: String, found: String },
+
     #[error("Expected positive number, found {}", found)]
     ExpectedPositiveNumber { found: String },
 
@@ -120,12 +154,33 @@ pub trait UnaryPreAggOperator: Send + Sync {
     fn drain(self: Box<Self>) -> Box<dyn Iterator<Item = Record>> {
         Box::new(iter::empty())
     }
+    /// Take the next of the extra records that the last call to `process_mut` produced, for
+    /// operators that turn one record into several, like `explode`.
+    fn pending(&mut self) -> Option<Record> {
+        None
+    }
+    /// Whether the last record that `process_mut` returned `None` for was consumed by the
+    /// operator, like a CSV header row, rather than dropped.
+    fn consumed_last(&self) -> bool {
+        false
+    }
+    /// Whether the operator won't pass on any more records, like a `head` that has output all
+    /// of its rows, so that the rest of the input doesn't need to be read.
+    fn exhausted(&self) -> bool {
+        false
+    }
 }
 
 /// Trait used to instantiate an operator from its definition.  If an operator does not maintain
 /// state, the operator definition value can be cloned and returned.
 pub trait OperatorBuilder: Send + Sync {
     fn build(&self) -> Box<dyn UnaryPreAggOperator>;
+    /// Whether the input can be split between several copies of the operator, each seeing some
+    /// of the records.  Operators whose output depends on the records that came before, like
+    /// `limit`, must see all of them in order.
+    fn parallel(&self) -> bool {
+        true
+    }
 }
 
 /// A trivial OperatorBuilder implementation for functional traits since they don't need to
@@ -186,7 +241,11 @@ impl AggregateOperator for PreAggAdapter {
                         data: vmap,
                         raw: "".to_string(),
                     })
-                    .flat_map(|rec| op.process_mut(rec).unwrap_or(None))
+                    .flat_map(|rec| {
+                        let first = op.process_mut(rec).unwrap_or(None);
+                        let rest: Vec<_> = iter::from_fn(|| op.pending()).collect();
+                        first.into_iter().chain(rest)
+                    })
                     .map(|rec| rec.data)
                     .collect();
                 processed_records.extend(op.drain().map(|rec| rec.data));
@@ -202,7 +261,7 @@ impl AggregateOperator for PreAggAdapter {
                     .iter()
                     .filter(|col| output_column_set.contains(*col));
                 columns.extend(filtered_previous_columns.cloned());
-                for column in output_column_set {
+                for column in output_column_set.into_iter().sorted() {
                     if !columns.contains(&column) {
                         columns.push(column);
                     }
@@ -220,12 +279,29 @@ impl AggregateOperator for PreAggAdapter {
 pub trait AggregateOperator: Send + Sync {
     fn emit(&self) -> data::Aggregate;
     fn process(&mut self, row: Row);
+
+    /// Called once at the end of the input, before the last `emit`, to pass on the records
+    /// that operators like `tail` are still holding back.
+    fn finish(&mut self) {}
+
+    /// The rows shown by a live refresh, before the input has ended.  Operators that move rows
+    /// to disk show the part of them that's still in memory instead of reading them all back.
+    fn emit_live(&self) -> data::Aggregate {
+        self.emit()
+    }
 }
 
 pub trait AggregateFunction: Send + Sync {
     fn process(&mut self, rec: &Data) -> Result<(), EvalError>;
     fn emit(&self) -> data::Value;
     fn empty_box(&self) -> Box<dyn AggregateFunction>;
+
+    /// The partial result so far, which can be written to disk and read back exactly.
+    fn state(&self) -> data::Value;
+
+    /// Combine this with the `state` of a function from the same `empty_box` that processed
+    /// the records after this one's.
+    fn merge(&mut self, state: data::Value);
 }
 
 impl<T: Copy + Send + Sync> Evaluate<T> for T {
@@ -234,12 +310,34 @@ impl<T: Copy + Send + Sync> Evaluate<T> for T {
     }
 }
 
+/// The number of files that the partial results of groups that don't fit in memory are split
+/// between.  Each file is merged on its own, so it only needs memory for its share of the groups.
+const PARTITIONS: usize = 16;
+
+/// A rough estimate of the memory used by each aggregate function of a group.
+const FUNCTION_SIZE: usize = 64;
+
+// key-column values -> (agg_columns -> builders)
+type Groups = HashMap<Vec<data::Value>, HashMap<String, Box<dyn AggregateFunction>>>;
+
 pub struct MultiGrouper {
     key_cols: Vec<Expr>,
     key_col_headers: Vec<String>,
     agg_col: Vec<(String, Box<dyn AggregateFunction>)>,
-    // key-column values -> (agg_columns -> builders)
-    state: HashMap<Vec<data::Value>, HashMap<String, Box<dyn AggregateFunction>>>,
+    state: Groups,
+    /// An estimate of the memory used by `state`, in bytes.
+    state_size: usize,
+    /// Once `state` uses more than half of this, records for groups that aren't in it are
+    /// aggregated in `overflow` instead, which is written to `partitions` whenever it uses more
+    /// than the other half.
+    max_memory: Option<usize>,
+    /// The groups that didn't fit in `state`, since `overflow` was last written to disk.
+    overflow: Groups,
+    /// An estimate of the memory used by `overflow`, in bytes.
+    overflow_size: usize,
+    /// The partial results of the groups in `overflow`, split by the hash of their key, which
+    /// are merged when the groups are emitted.
+    partitions: Vec<spill::SpillFile>,
 }
 
 impl MultiGrouper {
@@ -253,46 +351,215 @@ impl MultiGrouper {
             key_col_headers,
             agg_col: aggregators,
             state: HashMap::new(),
+            state_size: 0,
+            max_memory: None,
+            overflow: HashMap::new(),
+            overflow_size: 0,
+            partitions: Vec::new(),
         }
     }
-    fn process_map(&mut self, data: &Data) {
+
+    /// Move the partial results of new groups to disk once the groups take up more than
+    /// `max_memory` bytes.
+    pub fn with_max_memory(mut self, max_memory: Option<usize>) -> Self {
+        self.max_memory = max_memory;
+        self
+    }
+
+    fn key(&self, data: &Data) -> Vec<data::Value> {
         let key_values = self.key_cols.iter().map(|expr| expr.eval_value(data));
-        let key_columns: Vec<_> = key_values
+        key_values
             .map(|value_res| value_res.unwrap_or(Cow::Owned(data::Value::None)))
             .map(|v| v.into_owned())
-            .collect();
-        let agg_col = &self.agg_col;
-        let row = self.state.entry(key_columns).or_insert_with(|| {
-            agg_col
-                .iter()
-                .map(|(k, v)| (k.to_owned(), v.empty_box()))
-                .collect()
+            .collect()
+    }
+
+    /// The aggregate functions for a new group.
+    fn new_group(
+        agg_col: &[(String, Box<dyn AggregateFunction>)],
+    ) -> HashMap<String, Box<dyn AggregateFunction>> {
+        agg_col
+            .iter()
+            .map(|(k, v)| (k.to_owned(), v.empty_box()))
+            .collect()
+    }
+
+    /// Add a record to its group, returning whether the group is new.
+    fn add(
+        agg_col: &[(String, Box<dyn AggregateFunction>)],
+        groups: &mut Groups,
+        key_columns: Vec<data::Value>,
+        data: &Data,
+    ) -> bool {
+        let mut new = false;
+        let row = groups.entry(key_columns).or_insert_with(|| {
+            new = true;
+            MultiGrouper::new_group(agg_col)
         });
         for fun in row.values_mut() {
             // TODO: #25 capture erorrs here instead of ignoring
             let _ = fun.process(data);
         }
+        new
     }
-}
 
-impl AggregateOperator for MultiGrouper {
-    fn emit(&self) -> Aggregate {
+    fn process_map(&mut self, data: &Data) {
+        let key_columns = self.key(data);
+        let size = key_columns.iter().map(spill::value_size).sum::<usize>()
+            + self.agg_col.len() * FUNCTION_SIZE;
+        // Once groups have gone to `overflow`, `state` is full, so only the groups already in
+        // it are added to.
+        let in_state = self.state.contains_key(&key_columns)
+            || (self.overflow.is_empty()
+                && self.partitions.is_empty()
+                && !matches!(self.max_memory, Some(max) if self.state_size > max / 2));
+        if in_state {
+            if MultiGrouper::add(&self.agg_col, &mut self.state, key_columns, data) {
+                self.state_size += size;
+            }
+            return;
+        }
+        if MultiGrouper::add(&self.agg_col, &mut self.overflow, key_columns, data) {
+            self.overflow_size += size;
+        }
+        if matches!(self.max_memory, Some(max) if self.overflow_size > max / 2) {
+            if let Err(e) = self.spill() {
+                eprintln!("error: failed to write groups to disk: {}", e);
+                // Keep the groups in memory rather than retrying on every record.
+                self.max_memory = None;
+            }
+        }
+    }
+
+    /// The partition that a group's partial results are written to.
+    fn partition(key_columns: &[data::Value]) -> usize {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        key_columns.hash(&mut hasher);
+        (hasher.finish() % PARTITIONS as u64) as usize
+    }
+
+    /// Write the partial result of each group in `overflow` to the partition for its key, as
+    /// its key and the state of each of its functions.  A key always goes to the same
+    /// partition, so the partial results of a group can be merged one partition at a time.
+    fn spill(&mut self) -> std::io::Result<()> {
+        if self.partitions.is_empty() {
+            self.partitions = (0..PARTITIONS)
+                .map(|_| spill::SpillFile::create())
+                .collect::<Result<_, _>>()?;
+        }
+        for (key_columns, agg_map) in &self.overflow {
+            let states = agg_map
+                .iter()
+                .map(|(k, fun)| (k.clone(), fun.state()))
+                .collect();
+            let row: Data = vec![
+                ("key".to_string(), data::Value::Array(key_columns.clone())),
+                ("state".to_string(), data::Value::Obj(states)),
+            ]
+            .into_iter()
+            .collect();
+            self.partitions[MultiGrouper::partition(key_columns)].append(&row)?;
+        }
+        self.overflow.clear();
+        self.overflow_size = 0;
+        Ok(())
+    }
+
+    /// Merge the partial results written to a partition with the groups for it in `overflow`,
+    /// which came after them.
+    fn merge_partition(&self, index: usize) -> Groups {
+        let mut groups = Groups::new();
+        let mut merge = |key_columns: Vec<data::Value>,
+                         states: im::HashMap<String, data::Value>| {
+            let group = groups
+                .entry(key_columns)
+                .or_insert_with(|| MultiGrouper::new_group(&self.agg_col));
+            for (k, state) in states {
+                if let Some(fun) = group.get_mut(&k) {
+                    fun.merge(state);
+                }
+            }
+        };
+        match self.partitions.get(index).map(|partition| partition.read()) {
+            Some(Ok(rows)) => {
+                for mut row in rows {
+                    if let (Some(data::Value::Array(key_columns)), Some(data::Value::Obj(states))) =
+                        (row.remove("key"), row.remove("state"))
+                    {
+                        merge(key_columns, states);
+                    }
+                }
+            }
+            Some(Err(e)) => eprintln!("error: failed to read groups from disk: {}", e),
+            None => {}
+        }
+        for (key_columns, agg_map) in &self.overflow {
+            if MultiGrouper::partition(key_columns) == index {
+                let states = agg_map
+                    .iter()
+                    .map(|(k, fun)| (k.clone(), fun.state()))
+                    .collect();
+                merge(key_columns.clone(), states);
+            }
+        }
+        groups
+    }
+
+    fn row(
+        &self,
+        key_values: &[data::Value],
+        agg_map: &HashMap<String, Box<dyn AggregateFunction>>,
+    ) -> Data {
+        let key_values = key_values.iter().cloned();
+        let key_cols = self.key_col_headers.iter().map(|s| s.to_owned());
+        let mut res_map = HashMap::with_capacity(key_cols.len() + agg_map.len());
+        res_map.extend(itertools::zip_eq(key_cols, key_values));
+        for (k, v) in agg_map {
+            res_map.insert(k.to_string(), v.emit());
+        }
+        res_map
+    }
+
+    fn rows(&self, groups: &Groups) -> Vec<(Vec<data::Value>, Data)> {
+        groups
+            .iter()
+            .map(|(key_values, agg_map)| (key_values.clone(), self.row(key_values, agg_map)))
+            .collect()
+    }
+
+    fn aggregate(&self, mut rows: Vec<(Vec<data::Value>, Data)>) -> Aggregate {
         let mut columns = self.key_col_headers.to_vec();
         columns.extend(self.agg_col.iter().map(|(k, ..)| k.to_string()));
-        let data = self.state.iter().map(|(key_values, agg_map)| {
-            let key_values = key_values.iter().cloned();
-            let key_cols = self.key_col_headers.iter().map(|s| s.to_owned());
-            let mut res_map = HashMap::with_capacity(key_cols.len() + agg_map.len());
-            res_map.extend(itertools::zip_eq(key_cols, key_values));
-            for (k, v) in agg_map {
-                res_map.insert(k.to_string(), v.emit());
-            }
-            res_map
-        });
+        // Rows are ordered by their key so that the output doesn't depend on the hash order of
+        // the groups when no sort follows the aggregate.
+        rows.sort_by(|(l, _), (r, _)| l.cmp(r));
         Aggregate {
             columns,
-            data: data.collect(),
+            data: rows.into_iter().map(|(_, row)| row).collect(),
+        }
+    }
+}
+
+impl AggregateOperator for MultiGrouper {
+    fn emit(&self) -> Aggregate {
+        let mut rows = self.rows(&self.state);
+        if self.partitions.is_empty() {
+            rows.extend(self.rows(&self.overflow));
+        } else {
+            for index in 0..PARTITIONS {
+                rows.extend(self.rows(&self.merge_partition(index)));
+            }
         }
+        self.aggregate(rows)
+    }
+
+    /// Once groups have been written to disk, only the groups that are kept in memory are
+    /// shown, which are complete.
+    fn emit_live(&self) -> Aggregate {
+        if self.partitions.is_empty() {
+            return self.emit();
+        }
+        self.aggregate(self.rows(&self.state))
     }
 
     fn process(&mut self, row: Row) {
@@ -302,6 +569,10 @@ impl AggregateOperator for MultiGrouper {
             }
             Row::Aggregate(ag) => {
                 self.state.clear();
+                self.state_size = 0;
+                self.overflow.clear();
+                self.overflow_size = 0;
+                self.partitions.clear();
                 for row in ag.data {
                     self.process_map(&row);
                 }
@@ -317,16 +588,22 @@ mod tests {
 
     use crate::data::Value;
     use crate::lang;
+    use crate::operator::average::Average;
     use crate::operator::count::Count;
-    use crate::operator::count_distinct::CountDistinct;
+    use crate::operator::count_distinct::{CountDistinct, CountDistinctApprox};
     use crate::operator::expr::ValueRef;
+    use crate::operator::extreme_by::ExtremeBy;
     use crate::operator::fields::{FieldMode, Fields};
+    use crate::operator::first_last::FirstLast;
     use crate::operator::max::Max;
     use crate::operator::min::Min;
-    use crate::operator::parse::{Parse, ParseJson, ParseLogfmt, ParseOptions};
+    use crate::operator::mode::Mode;
+    use crate::operator::parse::{DuplicateKeys, Parse, ParseJson, ParseLogfmt, ParseOptions};
+    use crate::operator::percentile::Percentile;
     use crate::operator::sort::{SortDirection, Sorter};
     use crate::operator::sum::Sum;
     use crate::operator::total::TotalDef;
+    use crate::operator::values::Values;
     use crate::operator::where_op::Where;
 
     use super::*;
@@ -432,6 +709,31 @@ mod tests {
         );
     }
 
+    #[test]
+    fn logfmt_options() {
+        let rec = Record::new(r#"k1=5 k2=a k2=b k3"#.to_string() + "\n");
+        let parser = ParseLogfmt::new(None)
+            .with_keys(vec!["k2".to_string(), "k3".to_string(), "k4".to_string()])
+            .with_duplicates(DuplicateKeys::Array);
+        let rec = parser.process(rec).unwrap().unwrap();
+        assert_eq!(
+            rec.data,
+            hashmap! {
+                "k2".to_string() => Value::Array(vec![Value::Str("a".to_string()), Value::Str("b".to_string())]),
+                "k3".to_string() => Value::None,
+                "k4".to_string() => Value::None
+            }
+        );
+        let parser = ParseLogfmt::new(None).strict(true);
+        assert_eq!(
+            parser.process(Record::new("k1=5 k3")).err(),
+            Some(EvalError::ExpectedLogfmt {
+                found: "k1=5 k3".to_string(),
+                reason: "`k3` isn't a key=value pair".to_string()
+            })
+        );
+    }
+
     #[test]
     fn fields_only() {
         let rec = Record::new("");
@@ -468,6 +770,47 @@ mod tests {
         );
     }
 
+    #[test]
+    fn fields_patterns() {
+        let rec = Record::new("")
+            .put("req_id", Value::Int(1))
+            .put("req_path", Value::Str("/".to_string()))
+            .put("level", Value::Str("info".to_string()))
+            .put("_req_time", Value::Int(2));
+        let fields = Fields::new(&["req_*".to_string()], FieldMode::Only);
+        assert_eq!(
+            fields.process(rec.clone()).unwrap().unwrap().data,
+            hashmap! {
+                "req_id".to_string() => Value::Int(1),
+                "req_path".to_string() => Value::Str("/".to_string()),
+            }
+        );
+        let fields = Fields::new(&["*".to_string()], FieldMode::Except);
+        assert_eq!(
+            fields.process(rec.clone()).unwrap().unwrap().data,
+            hashmap! {"_req_time".to_string() => Value::Int(2)}
+        );
+        let fields = Fields::new(&["*_time".to_string()], FieldMode::Only).include_meta(true);
+        assert_eq!(
+            fields.process(rec).unwrap().unwrap().data,
+            hashmap! {"_req_time".to_string() => Value::Int(2)}
+        );
+    }
+
+    #[test]
+    fn field_pattern_matching() {
+        use crate::operator::fields::matches;
+        assert!(matches("*", "level", false));
+        assert!(!matches("*", "_raw", false));
+        assert!(matches("*", "_raw", true));
+        assert!(matches("_*", "_raw", false));
+        assert!(matches("a*c*e", "abcde", false));
+        assert!(matches("a*a", "aba", false));
+        assert!(!matches("a*a", "a", false));
+        assert!(!matches("req_*", "request", false));
+        assert!(matches("req.*.ms", "req.db.ms", false));
+    }
+
     #[test]
     fn parse() {
         let rec = Record::new(
@@ -634,6 +977,60 @@ mod tests {
         );
     }
 
+    #[test]
+    fn multi_grouper_spills_groups() {
+        let grouper = |max_memory| {
+            let ops: Vec<(String, Box<dyn AggregateFunction>)> = vec![
+                ("_count".to_string(), Box::new(Count::unconditional())),
+                ("_sum".to_string(), Box::new(Sum::empty("v1"))),
+                ("_average".to_string(), Box::new(Average::empty("v1"))),
+                ("_min".to_string(), Box::new(Min::empty("v1"))),
+                ("_max".to_string(), Box::new(Max::empty("v1"))),
+                (
+                    "_first".to_string(),
+                    Box::new(FirstLast::empty("v1", None, false)),
+                ),
+                (
+                    "_last".to_string(),
+                    Box::new(FirstLast::empty("v1", None, true)),
+                ),
+                (
+                    "_max_by".to_string(),
+                    Box::new(ExtremeBy::empty("m", "v1", true)),
+                ),
+                ("_mode".to_string(), Box::new(Mode::empty("m"))),
+                ("_values".to_string(), Box::new(Values::empty("m", 3))),
+                ("_p50".to_string(), Box::new(Percentile::empty("v1", 0.5))),
+                ("_distinct".to_string(), Box::new(CountDistinct::empty("m"))),
+                (
+                    "_approx".to_string(),
+                    Box::new(CountDistinctApprox::empty("v1")),
+                ),
+            ];
+            let mut grouper = MultiGrouper::new(&[Expr::column("k1")], vec!["k1".to_string()], ops)
+                .with_max_memory(max_memory);
+            (0..500).for_each(|n| {
+                let rec = Record::new(n.to_string());
+                let rec = rec.put("k1", data::Value::Int(n * 7 % 101));
+                let rec = rec.put("v1", data::Value::Int(n));
+                let rec = rec.put("m", data::Value::Int(n % 4));
+                grouper.process(Row::Record(rec));
+            });
+            grouper
+        };
+        let spilled = grouper(Some(20000));
+        assert!(!spilled.partitions.is_empty());
+        assert!(spilled.state.len() < 101);
+        let agg = spilled.emit();
+        assert_eq!(agg.data.len(), 101);
+        assert_eq!(agg, grouper(None).emit());
+
+        // Live refreshes only show the groups that stayed in memory, which are complete.
+        let live = spilled.emit_live();
+        assert_eq!(live.data.len(), spilled.state.len());
+        assert!(live.data.iter().all(|row| agg.data.contains(row)));
+    }
+
     #[test]
     fn multi_grouper() {
         let ops: Vec<(String, Box<dyn AggregateFunction>)> = vec![
@@ -0,0 +1,233 @@
+use crate::data::{Record, Value};
+use crate::operator::{EvalError, Expr, UnaryPreAggFunction};
+use crate::{data, operator};
+use chrono::DateTime;
+
+/// The fields an access log line is parsed into.
+pub const FIELDS: &[&str] = &[
+    "ip",
+    "user",
+    "timestamp",
+    "method",
+    "path",
+    "status",
+    "bytes",
+    "referer",
+    "user_agent",
+];
+
+/// Split off the next space-terminated token.
+fn next_token(s: &str) -> Option<(&str, &str)> {
+    let (token, rest) = s.split_once(' ')?;
+    if token.is_empty() {
+        None
+    } else {
+        Some((token, rest))
+    }
+}
+
+/// Split off a double-quoted string, unescaping `\"` and `\\` in it.
+fn quoted(s: &str) -> Option<(String, &str)> {
+    let s = s.strip_prefix('"')?;
+    let mut value = String::new();
+    let mut chars = s.char_indices();
+    loop {
+        match chars.next()? {
+            (_, '\\') => match chars.next()? {
+                (_, c @ ('"' | '\\')) => value.push(c),
+                (_, c) => {
+                    value.push('\\');
+                    value.push(c);
+                }
+            },
+            (i, '"') => return Some((value, &s[i + 1..])),
+            (_, c) => value.push(c),
+        }
+    }
+}
+
+/// A field that's `-` when it's missing.
+fn dash_or(value: &str) -> Value {
+    if value == "-" {
+        Value::None
+    } else {
+        Value::Str(value.to_string())
+    }
+}
+
+/// Parse a line in the Common or Combined Log Format written by Apache and nginx, e.g.
+/// `127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326`,
+/// optionally followed by the quoted referer and user agent.  Anything after those, like the
+/// `X-Forwarded-For` header that some configurations add, is ignored.
+pub fn parse_access_log(line: &str) -> Option<Vec<(&'static str, Value)>> {
+    let (ip, rest) = next_token(line)?;
+    let (_ident, rest) = next_token(rest)?;
+    let (user, rest) = next_token(rest)?;
+    let (timestamp, rest) = rest.strip_prefix('[')?.split_once("] ")?;
+    let timestamp = DateTime::parse_from_str(timestamp, "%d/%b/%Y:%H:%M:%S %z").ok()?;
+    let (request, rest) = quoted(rest)?;
+    let (status, rest) = next_token(rest.strip_prefix(' ')?)?;
+    let status: i64 = status.parse().ok()?;
+    let (bytes, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+    let bytes = match bytes {
+        "-" => 0,
+        bytes => bytes.parse().ok()?,
+    };
+    let (referer, user_agent) = if rest.is_empty() {
+        (Value::None, Value::None)
+    } else {
+        let (referer, rest) = quoted(rest)?;
+        let (user_agent, _) = quoted(rest.strip_prefix(' ')?)?;
+        (dash_or(&referer), dash_or(&user_agent))
+    };
+    // A request line that isn't `METHOD PATH [PROTOCOL]`, like the garbage sent by scanners, is
+    // kept out of the method and path.
+    let mut parts = request.split(' ');
+    let (method, path) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+        (Some(method), Some(path), _, None) if !method.is_empty() => {
+            (Value::Str(method.to_string()), Value::Str(path.to_string()))
+        }
+        _ => (Value::None, Value::None),
+    };
+    Some(vec![
+        ("ip", Value::Str(ip.to_string())),
+        ("user", dash_or(user)),
+        ("timestamp", Value::DateTime(timestamp.into())),
+        ("method", method),
+        ("path", path),
+        ("status", Value::Int(status)),
+        ("bytes", Value::Int(bytes)),
+        ("referer", referer),
+        ("user_agent", user_agent),
+    ])
+}
+
+#[derive(Clone)]
+pub struct ParseAccessLog {
+    input_column: Option<Expr>,
+    drop_nonmatching: bool,
+}
+
+impl ParseAccessLog {
+    pub fn new(input_column: Option<Expr>, drop_nonmatching: bool) -> Self {
+        ParseAccessLog {
+            input_column,
+            drop_nonmatching,
+        }
+    }
+}
+
+impl UnaryPreAggFunction for ParseAccessLog {
+    fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let fields = {
+            let inp = operator::get_input(&rec, &self.input_column)?;
+            parse_access_log(inp.trim_end_matches(['\r', '\n']))
+        };
+        match fields {
+            Some(fields) => {
+                let mut rec = rec;
+                for (field, value) in fields {
+                    rec.put_mut(field, value);
+                }
+                Ok(Some(rec))
+            }
+            None if self.drop_nonmatching => Ok(None),
+            None => {
+                let mut rec = rec;
+                for field in FIELDS {
+                    if !rec.data.contains_key(*field) {
+                        rec.put_mut(*field, data::Value::None);
+                    }
+                }
+                Ok(Some(rec))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(line: &str) -> Option<Vec<(&'static str, String)>> {
+        parse_access_log(line).map(|fields| {
+            fields
+                .into_iter()
+                .map(|(name, value)| (name, value.to_string()))
+                .collect()
+        })
+    }
+
+    fn fields(values: &[&str]) -> Option<Vec<(&'static str, String)>> {
+        Some(
+            FIELDS
+                .iter()
+                .zip(values)
+                .map(|(name, value)| (*name, value.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn parses_common_log_format() {
+        assert_eq!(
+            parse(
+                r#"127.0.0.1 - frank [10/Oct/2000:13:55:36 -0700] "GET /apache_pb.gif HTTP/1.0" 200 2326"#
+            ),
+            fields(&[
+                "127.0.0.1",
+                "frank",
+                "2000-10-10T20:55:36Z",
+                "GET",
+                "/apache_pb.gif",
+                "200",
+                "2326",
+                "None",
+                "None",
+            ])
+        );
+    }
+
+    #[test]
+    fn parses_combined_log_format() {
+        assert_eq!(
+            parse(
+                r#"::1 - - [10/Oct/2000:13:55:36 +0000] "POST /login HTTP/1.1" 302 - "https://example.com/" "curl/8.0 \"x\"" "10.0.0.1""#
+            ),
+            fields(&[
+                "::1",
+                "None",
+                "2000-10-10T13:55:36Z",
+                "POST",
+                "/login",
+                "302",
+                "0",
+                "https://example.com/",
+                r#"curl/8.0 "x""#,
+            ])
+        );
+    }
+
+    #[test]
+    fn keeps_malformed_requests_out_of_method_and_path() {
+        let fields =
+            parse(r#"1.2.3.4 - - [10/Oct/2000:13:55:36 +0000] "\x16\x03\x01" 400 0 "-" "-""#)
+                .unwrap();
+        assert_eq!(fields[3], ("method", "None".to_string()));
+        assert_eq!(fields[4], ("path", "None".to_string()));
+        assert_eq!(fields[7], ("referer", "None".to_string()));
+    }
+
+    #[test]
+    fn rejects_other_lines() {
+        assert_eq!(parse("not an access log"), None);
+        assert_eq!(
+            parse(r#"1.2.3.4 - - [yesterday] "GET / HTTP/1.1" 200 5"#),
+            None
+        );
+        assert_eq!(
+            parse(r#"1.2.3.4 - - [10/Oct/2000:13:55:36 +0000] "GET / HTTP/1.1" ok 5"#),
+            None
+        );
+    }
+}
@@ -1,5 +1,6 @@
 use crate::data;
 use crate::operator::{AggregateFunction, Data, EvalError, Evaluate, Expr};
+use ordered_float::OrderedFloat;
 
 pub struct Average {
     total: f64,
@@ -32,4 +33,20 @@ impl AggregateFunction for Average {
     fn empty_box(&self) -> Box<dyn AggregateFunction> {
         Box::new(Average::empty(self.column.clone()))
     }
+
+    fn state(&self) -> data::Value {
+        data::Value::Array(vec![
+            data::Value::Float(OrderedFloat(self.total)),
+            data::Value::Int(self.count),
+        ])
+    }
+
+    fn merge(&mut self, state: data::Value) {
+        if let data::Value::Array(state) = state {
+            if let [data::Value::Float(OrderedFloat(total)), data::Value::Int(count)] = state[..] {
+                self.total += total;
+                self.count += count;
+            }
+        }
+    }
 }
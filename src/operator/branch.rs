@@ -0,0 +1,324 @@
+use crate::data::{Aggregate, Record, Row, Value};
+use crate::operator::{AggregateOperator, EvalError, OperatorBuilder, UnaryPreAggOperator};
+use itertools::Itertools;
+use std::collections::{HashSet, VecDeque};
+use std::sync::Mutex;
+
+/// The column with the number of the branch that a row came out of, counting from 1.
+pub const BRANCH_COLUMN: &str = "_branch";
+
+/// Label the records that came out of the branch with its number.
+fn label(records: Vec<Record>, index: usize) -> impl Iterator<Item = Record> {
+    records
+        .into_iter()
+        .map(move |rec| rec.put(BRANCH_COLUMN, Value::Int(index as i64 + 1)))
+}
+
+/// Run a record through the operators of a branch, adding what comes out of the last one to
+/// `out`.  A record that an operator fails on is dropped from the branch.
+fn run_operators(
+    rec: Record,
+    operators: &mut [Box<dyn UnaryPreAggOperator>],
+    out: &mut Vec<Record>,
+) {
+    let (op, rest) = match operators.split_first_mut() {
+        Some(split) => split,
+        None => return out.push(rec),
+    };
+    match op.process_mut(rec) {
+        Ok(Some(next_rec)) => {
+            run_operators(next_rec, rest, out);
+            while let Some(next_rec) = op.pending() {
+                run_operators(next_rec, rest, out);
+            }
+        }
+        Ok(None) => {}
+        Err(err) => eprintln!("error: {}", err),
+    }
+}
+
+/// Drain the operators of a branch in order, running the records from each one through the
+/// operators after it.
+fn drain_operators(mut operators: Vec<Box<dyn UnaryPreAggOperator>>, out: &mut Vec<Record>) {
+    while !operators.is_empty() {
+        let op = operators.remove(0);
+        for rec in op.drain() {
+            run_operators(rec, &mut operators, out);
+        }
+    }
+}
+
+/// The definition of branches that don't aggregate.  Each record is run through every branch,
+/// and the records that come out of them are passed on in the order of the branches, labeled
+/// with the branch they came out of.
+pub struct BranchDef {
+    branches: Vec<Vec<Box<dyn OperatorBuilder>>>,
+}
+
+impl BranchDef {
+    pub fn new(branches: Vec<Vec<Box<dyn OperatorBuilder>>>) -> Self {
+        BranchDef { branches }
+    }
+}
+
+impl OperatorBuilder for BranchDef {
+    fn build(&self) -> Box<dyn UnaryPreAggOperator> {
+        Box::new(Branches {
+            branches: self
+                .branches
+                .iter()
+                .map(|builders| builders.iter().map(|builder| builder.build()).collect())
+                .collect(),
+            pending: VecDeque::new(),
+        })
+    }
+
+    fn parallel(&self) -> bool {
+        self.branches
+            .iter()
+            .flatten()
+            .all(|builder| builder.parallel())
+    }
+}
+
+pub struct Branches {
+    branches: Vec<Vec<Box<dyn UnaryPreAggOperator>>>,
+    /// The records from the last call to `process_mut` that haven't been passed on yet.
+    pending: VecDeque<Record>,
+}
+
+impl UnaryPreAggOperator for Branches {
+    fn process_mut(&mut self, rec: Record) -> Result<Option<Record>, EvalError> {
+        self.pending.clear();
+        for (index, operators) in self.branches.iter_mut().enumerate() {
+            let mut out = Vec::new();
+            run_operators(rec.clone(), operators, &mut out);
+            self.pending.extend(label(out, index));
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    fn drain(self: Box<Self>) -> Box<dyn Iterator<Item = Record>> {
+        let mut labeled = Vec::new();
+        for (index, operators) in self.branches.into_iter().enumerate() {
+            let mut out = Vec::new();
+            drain_operators(operators, &mut out);
+            labeled.extend(label(out, index));
+        }
+        Box::new(labeled.into_iter())
+    }
+
+    fn pending(&mut self) -> Option<Record> {
+        self.pending.pop_front()
+    }
+
+    fn exhausted(&self) -> bool {
+        self.branches
+            .iter()
+            .all(|operators| operators.iter().any(|op| op.exhausted()))
+    }
+}
+
+/// A branch with an aggregate, or one that follows an aggregate.
+pub struct AggregateBranch {
+    /// The operators that run on each record before the branch's aggregate.
+    operators: Vec<Box<dyn UnaryPreAggOperator>>,
+    aggregators: Vec<Box<dyn AggregateOperator>>,
+    /// The rows of a branch without an aggregate.
+    rows: Aggregate,
+}
+
+impl AggregateBranch {
+    pub fn new(
+        operators: Vec<Box<dyn OperatorBuilder>>,
+        aggregators: Vec<Box<dyn AggregateOperator>>,
+    ) -> Self {
+        AggregateBranch {
+            operators: operators.iter().map(|builder| builder.build()).collect(),
+            aggregators,
+            rows: Aggregate {
+                columns: Vec::new(),
+                data: Vec::new(),
+            },
+        }
+    }
+
+    pub fn process(&mut self, row: Row) {
+        match row {
+            Row::Record(rec) => {
+                let mut out = Vec::new();
+                run_operators(rec, &mut self.operators, &mut out);
+                out.into_iter().for_each(|rec| self.aggregate(rec));
+            }
+            Row::Aggregate(agg) => match self.aggregators.first_mut() {
+                Some(head) => head.process(Row::Aggregate(agg)),
+                None => self.rows = agg,
+            },
+        }
+    }
+
+    /// Drain the operators before the aggregate once there are no more records, passing on the
+    /// records they held on to.
+    pub fn finish(&mut self) {
+        let mut out = Vec::new();
+        drain_operators(std::mem::take(&mut self.operators), &mut out);
+        out.into_iter().for_each(|rec| self.aggregate(rec));
+        if let Some(head) = self.aggregators.first_mut() {
+            head.finish();
+        }
+    }
+
+    fn aggregate(&mut self, rec: Record) {
+        match self.aggregators.first_mut() {
+            Some(head) => head.process(Row::Record(rec)),
+            None => self.rows.data.push(rec.data),
+        }
+    }
+
+    pub fn emit(&mut self) -> Aggregate {
+        let (head, rest) = match self.aggregators.split_first_mut() {
+            Some(split) => split,
+            None if self.rows.columns.is_empty() => {
+                let columns = self
+                    .rows
+                    .data
+                    .iter()
+                    .flat_map(|data| data.keys())
+                    .unique()
+                    .sorted()
+                    .cloned()
+                    .collect();
+                return Aggregate {
+                    columns,
+                    data: self.rows.data.clone(),
+                };
+            }
+            None => return self.rows.clone(),
+        };
+        let mut agg = head.emit();
+        for op in rest {
+            op.process(Row::Aggregate(agg));
+            agg = op.emit();
+        }
+        agg
+    }
+}
+
+/// Branches with aggregates.  Their results are combined into one, with the rows of each branch
+/// in turn, labeled with the branch, and the columns of all of them.
+pub struct AggregateBranches {
+    branches: Vec<Mutex<AggregateBranch>>,
+}
+
+impl AggregateBranches {
+    pub fn new(branches: Vec<AggregateBranch>) -> Self {
+        AggregateBranches {
+            branches: branches.into_iter().map(Mutex::new).collect(),
+        }
+    }
+}
+
+impl AggregateOperator for AggregateBranches {
+    fn emit(&self) -> Aggregate {
+        let mut columns = vec![BRANCH_COLUMN.to_string()];
+        let mut seen: HashSet<String> = columns.iter().cloned().collect();
+        let mut data = Vec::new();
+        for (index, branch) in self.branches.iter().enumerate() {
+            let agg = branch.lock().expect("not poisoned").emit();
+            // A branch without any rows has nothing to show in its columns.
+            if agg.data.is_empty() {
+                continue;
+            }
+            for column in agg.columns {
+                if seen.insert(column.clone()) {
+                    columns.push(column);
+                }
+            }
+            data.extend(agg.data.into_iter().map(|mut row| {
+                row.insert(BRANCH_COLUMN.to_string(), Value::Int(index as i64 + 1));
+                row
+            }));
+        }
+        Aggregate { columns, data }
+    }
+
+    fn process(&mut self, row: Row) {
+        for branch in &mut self.branches {
+            branch.get_mut().expect("not poisoned").process(row.clone());
+        }
+    }
+
+    fn finish(&mut self) {
+        for branch in &mut self.branches {
+            branch.get_mut().expect("not poisoned").finish();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::operator::count::Count;
+    use crate::operator::expr::{BinaryExpr, BoolExpr};
+    use crate::operator::where_op::{Predicate, Where};
+    use crate::operator::{AggregateFunction, Expr, MultiGrouper};
+
+    fn rec(level: &str) -> Record {
+        Record::new(level).put("level", Value::Str(level.to_string()))
+    }
+
+    fn is_level(level: &str) -> Box<dyn OperatorBuilder> {
+        Box::new(Where::new(Predicate(Expr::Comparison(BinaryExpr {
+            operator: BoolExpr::Eq,
+            left: Box::new(Expr::column("level")),
+            right: Box::new(Expr::Value(Box::leak(Box::new(Value::Str(
+                level.to_string(),
+            ))))),
+        }))))
+    }
+
+    #[test]
+    fn passes_records_from_each_branch() {
+        let def = BranchDef::new(vec![vec![is_level("error")], vec![]]);
+        let mut op = def.build();
+        let first = op.process_mut(rec("error")).unwrap().unwrap();
+        assert_eq!(first.raw, "error");
+        assert_eq!(first.data[BRANCH_COLUMN], Value::Int(1));
+        let second = op.pending().unwrap();
+        assert_eq!(second.raw, "error");
+        assert_eq!(second.data[BRANCH_COLUMN], Value::Int(2));
+        assert!(op.pending().is_none());
+        let info = op.process_mut(rec("info")).unwrap().unwrap();
+        assert_eq!(info.raw, "info");
+        assert_eq!(info.data[BRANCH_COLUMN], Value::Int(2));
+        assert!(op.pending().is_none());
+    }
+
+    #[test]
+    fn combines_aggregates() {
+        let count = || -> Box<dyn AggregateOperator> {
+            let ops: Vec<(String, Box<dyn AggregateFunction>)> =
+                vec![("_count".to_string(), Box::new(Count::unconditional()))];
+            Box::new(MultiGrouper::new(&[], vec![], ops))
+        };
+        let mut op = AggregateBranches::new(vec![
+            AggregateBranch::new(vec![is_level("error")], vec![count()]),
+            AggregateBranch::new(vec![], vec![count()]),
+        ]);
+        for level in &["error", "info", "error"] {
+            op.process(Row::Record(rec(level)));
+        }
+        let agg = op.emit();
+        assert_eq!(agg.columns, vec!["_branch", "_count"]);
+        assert_eq!(
+            agg.data
+                .iter()
+                .map(|data| (data["_branch"].clone(), data["_count"].clone()))
+                .collect::<Vec<_>>(),
+            vec![
+                (Value::Int(1), Value::Int(2)),
+                (Value::Int(2), Value::Int(3))
+            ]
+        );
+    }
+}
@@ -35,6 +35,16 @@ impl<T: 'static + Send + Sync + Evaluate<bool>> AggregateFunction for Count<T> {
     fn empty_box(&self) -> Box<dyn AggregateFunction> {
         Box::new(Count::new(self.condition.clone()))
     }
+
+    fn state(&self) -> data::Value {
+        data::Value::Int(self.count)
+    }
+
+    fn merge(&mut self, state: data::Value) {
+        if let data::Value::Int(count) = state {
+            self.count += count;
+        }
+    }
 }
 
 #[cfg(test)]
@@ -1,7 +1,9 @@
 use crate::data;
 use crate::operator::expr::Expr;
 use crate::operator::{AggregateFunction, Data, EvalError};
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
 
 pub struct CountDistinct {
     state: HashSet<data::Value>,
@@ -31,4 +33,206 @@ impl AggregateFunction for CountDistinct {
     fn empty_box(&self) -> Box<dyn AggregateFunction> {
         Box::new(CountDistinct::empty(self.column.clone()))
     }
+
+    fn state(&self) -> data::Value {
+        data::Value::Array(self.state.iter().cloned().collect())
+    }
+
+    fn merge(&mut self, state: data::Value) {
+        if let data::Value::Array(values) = state {
+            self.state.extend(values);
+        }
+    }
+}
+
+/// The number of index bits of the HyperLogLog hash, giving `2^PRECISION` registers and a
+/// standard error of about `1.04 / sqrt(2^PRECISION)`, i.e. 0.8%.
+const PRECISION: u32 = 14;
+const REGISTERS: usize = 1 << PRECISION;
+
+/// The most distinct hashes that are kept before switching to the registers.  The registers
+/// take a byte each, so this is the point where they use less memory than the hashes.
+const MAX_EXACT: usize = REGISTERS / std::mem::size_of::<u64>();
+
+/// Counts distinct values in a fixed amount of memory with HyperLogLog.  Until there are
+/// enough values for the estimate to save memory, the hashes of the values are kept and the
+/// count is exact.
+pub struct CountDistinctApprox {
+    hashes: HashSet<u64>,
+    registers: Option<Vec<u8>>,
+    column: Expr,
+}
+
+impl CountDistinctApprox {
+    pub fn empty<T: Into<Expr>>(column: T) -> Self {
+        CountDistinctApprox {
+            hashes: HashSet::new(),
+            registers: None,
+            column: column.into(),
+        }
+    }
+
+    fn add_to_registers(registers: &mut [u8], hash: u64) {
+        let index = (hash >> (64 - PRECISION)) as usize;
+        // The position of the first set bit after the index bits.  The low bit is set so that
+        // it's found even if the rest are zero.
+        let rank = ((hash << PRECISION) | (1 << (PRECISION - 1))).leading_zeros() as u8 + 1;
+        registers[index] = registers[index].max(rank);
+    }
+
+    fn add_hash(&mut self, hash: u64) {
+        match &mut self.registers {
+            Some(registers) => CountDistinctApprox::add_to_registers(registers, hash),
+            None => {
+                self.hashes.insert(hash);
+                if self.hashes.len() > MAX_EXACT {
+                    self.switch_to_registers();
+                }
+            }
+        }
+    }
+
+    fn switch_to_registers(&mut self) -> &mut Vec<u8> {
+        if self.registers.is_none() {
+            let mut registers = vec![0; REGISTERS];
+            for hash in self.hashes.drain() {
+                CountDistinctApprox::add_to_registers(&mut registers, hash);
+            }
+            self.hashes.shrink_to_fit();
+            self.registers = Some(registers);
+        }
+        self.registers.as_mut().expect("just set")
+    }
+
+    fn estimate(registers: &[u8]) -> f64 {
+        let m = REGISTERS as f64;
+        let alpha = 0.7213 / (1.0 + 1.079 / m);
+        let sum: f64 = registers.iter().map(|r| 2f64.powi(-i32::from(*r))).sum();
+        let raw = alpha * m * m / sum;
+        let zeros = registers.iter().filter(|r| **r == 0).count();
+        if raw <= 2.5 * m && zeros > 0 {
+            // Linear counting is more accurate for small cardinalities.
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        }
+    }
+}
+
+impl AggregateFunction for CountDistinctApprox {
+    fn process(&mut self, rec: &Data) -> Result<(), EvalError> {
+        let value = self.column.eval_value(rec)?;
+        let mut hasher = DefaultHasher::new();
+        value.hash(&mut hasher);
+        self.add_hash(hasher.finish());
+        Ok(())
+    }
+
+    fn emit(&self) -> data::Value {
+        match &self.registers {
+            Some(registers) => {
+                data::Value::Int(CountDistinctApprox::estimate(registers).round() as i64)
+            }
+            None => data::Value::Int(self.hashes.len() as i64),
+        }
+    }
+
+    fn empty_box(&self) -> Box<dyn AggregateFunction> {
+        Box::new(CountDistinctApprox::empty(self.column.clone()))
+    }
+
+    /// The hashes while the count is exact, otherwise the registers, in an object so that the
+    /// two can be told apart.
+    fn state(&self) -> data::Value {
+        let (key, values): (_, Vec<i64>) = match &self.registers {
+            Some(registers) => (
+                "registers",
+                registers.iter().map(|r| i64::from(*r)).collect(),
+            ),
+            None => ("hashes", self.hashes.iter().map(|h| *h as i64).collect()),
+        };
+        let values = data::Value::Array(values.into_iter().map(data::Value::Int).collect());
+        data::Value::Obj(std::iter::once((key.to_string(), values)).collect())
+    }
+
+    fn merge(&mut self, state: data::Value) {
+        let state = match state {
+            data::Value::Obj(state) => state,
+            _ => return,
+        };
+        let ints = |values: &data::Value| match values {
+            data::Value::Array(values) => values
+                .iter()
+                .filter_map(|value| match value {
+                    data::Value::Int(i) => Some(*i),
+                    _ => None,
+                })
+                .collect(),
+            _ => vec![],
+        };
+        if let Some(hashes) = state.get("hashes") {
+            ints(hashes)
+                .into_iter()
+                .for_each(|hash| self.add_hash(hash as u64));
+        }
+        if let Some(other) = state.get("registers") {
+            let registers = self.switch_to_registers();
+            for (register, other) in registers.iter_mut().zip(ints(other)) {
+                *register = (*register).max(other as u8);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn process(values: impl Iterator<Item = i64>) -> CountDistinctApprox {
+        let mut counter = CountDistinctApprox::empty("x");
+        for value in values {
+            let mut rec = Data::new();
+            rec.insert("x".to_string(), data::Value::Int(value));
+            counter.process(&rec).unwrap();
+        }
+        counter
+    }
+
+    fn count(values: impl Iterator<Item = i64>) -> i64 {
+        match process(values).emit() {
+            data::Value::Int(count) => count,
+            other => panic!("unexpected count {:?}", other),
+        }
+    }
+
+    #[test]
+    fn exact_for_small_sets() {
+        assert_eq!(count(0..0), 0);
+        assert_eq!(count((0..1000).map(|i| i % 7)), 7);
+        assert_eq!(count(0..MAX_EXACT as i64), MAX_EXACT as i64);
+    }
+
+    #[test]
+    fn merges_partial_results() {
+        // The hashes of one with the registers of the other, both ways around.
+        let mut merged = process(0..3000);
+        merged.merge(process(2000..4000).state());
+        assert_eq!(merged.emit(), process(0..4000).emit());
+        let mut merged = process(2000..4000);
+        merged.merge(process(0..3000).state());
+        assert_eq!(merged.emit(), process(0..4000).emit());
+
+        let mut merged = process(0..100);
+        merged.merge(process(50..150).state());
+        assert_eq!(merged.emit(), data::Value::Int(150));
+    }
+
+    #[test]
+    fn estimates_large_sets() {
+        for n in [5_000, 50_000, 500_000] {
+            let estimate = count((0..n).chain(0..n));
+            let error = (estimate - n).abs() as f64 / n as f64;
+            assert!(error < 0.03, "estimated {} for {}", estimate, n);
+        }
+    }
 }
@@ -0,0 +1,212 @@
+use crate::data::{Record, Value, ValueType};
+use crate::operator::{EvalError, Expr, OperatorBuilder, UnaryPreAggOperator};
+use crate::{data, operator};
+
+/// Split a delimited line into its fields, following the quoting rules of RFC 4180: a field
+/// enclosed in double quotes can contain the delimiter, and a double quote inside it is written
+/// as two double quotes.  Returns `None` if a quoted field isn't terminated or is followed by
+/// something other than the delimiter.
+pub fn split_csv(line: &str, delimiter: char) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+    loop {
+        let mut field = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            loop {
+                match chars.next()? {
+                    '"' if chars.peek() == Some(&'"') => {
+                        chars.next();
+                        field.push('"');
+                    }
+                    '"' => break,
+                    c => field.push(c),
+                }
+            }
+            match chars.next() {
+                None => {
+                    fields.push(field);
+                    return Some(fields);
+                }
+                Some(c) if c == delimiter => fields.push(field),
+                Some(_) => return None,
+            }
+        } else {
+            loop {
+                match chars.next() {
+                    None => {
+                        fields.push(field);
+                        return Some(fields);
+                    }
+                    Some(c) if c == delimiter => break,
+                    Some(c) => field.push(c),
+                }
+            }
+            fields.push(field);
+        }
+    }
+}
+
+/// The definition for a `parse csv` operator.
+#[derive(Clone)]
+pub struct ParseCsvDef {
+    fields: Vec<String>,
+    /// The type each field is converted to, if one was given. Parallel to `fields`.
+    types: Vec<Option<ValueType>>,
+    input_column: Option<Expr>,
+    headers: bool,
+    delimiter: char,
+    drop_nonmatching: bool,
+}
+
+impl ParseCsvDef {
+    pub fn new(
+        fields: Vec<String>,
+        input_column: Option<Expr>,
+        headers: bool,
+        delimiter: char,
+        drop_nonmatching: bool,
+    ) -> Self {
+        ParseCsvDef {
+            types: vec![None; fields.len()],
+            fields,
+            input_column,
+            headers,
+            delimiter,
+            drop_nonmatching,
+        }
+    }
+
+    /// Convert the given fields to a type as they are parsed. Values that can't be converted
+    /// become `None`.
+    pub fn with_casts(mut self, casts: Vec<(String, ValueType)>) -> Self {
+        for (field, tpe) in casts {
+            if let Some(idx) = self.fields.iter().position(|f| *f == field) {
+                self.types[idx] = Some(tpe);
+            }
+        }
+        self
+    }
+}
+
+impl OperatorBuilder for ParseCsvDef {
+    fn build(&self) -> Box<dyn UnaryPreAggOperator> {
+        Box::new(ParseCsv {
+            def: self.clone(),
+            header: None,
+            consumed_last: false,
+        })
+    }
+
+    /// The header of each file is only seen by the copy that's given its first line.
+    fn parallel(&self) -> bool {
+        !self.headers
+    }
+}
+
+/// The state for a `parse csv` operator.  With `headers=true`, the first line of each file is
+/// taken as the header.
+pub struct ParseCsv {
+    def: ParseCsvDef,
+    /// The `__file__` of the header that was read, and the field names it gave, if they weren't
+    /// listed in the query.
+    header: Option<(Option<Value>, Vec<String>)>,
+    consumed_last: bool,
+}
+
+impl ParseCsv {
+    fn parse(&self, line: &str) -> Option<Vec<String>> {
+        split_csv(line.trim_end_matches(['\r', '\n']), self.def.delimiter)
+    }
+}
+
+impl UnaryPreAggOperator for ParseCsv {
+    fn process_mut(&mut self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let values = {
+            let inp = operator::get_input(&rec, &self.def.input_column)?;
+            self.parse(&inp)
+        };
+        self.consumed_last = false;
+        if self.def.headers {
+            let file = rec.data.get("__file__");
+            if self.header.as_ref().map(|(f, _)| f.as_ref()) != Some(file) {
+                self.header = Some((file.cloned(), values.unwrap_or_default()));
+                self.consumed_last = true;
+                return Ok(None);
+            }
+        }
+        let fields = match &self.header {
+            Some((_, names)) if self.def.fields.is_empty() => names,
+            _ => &self.def.fields,
+        };
+        match values {
+            Some(values) if values.len() == fields.len() => {
+                let mut rec = rec;
+                for ((field, value), tpe) in fields
+                    .iter()
+                    .zip(values)
+                    .zip(self.def.types.iter().chain(std::iter::repeat(&None)))
+                {
+                    let value = match tpe {
+                        Some(tpe) => data::Value::Str(value).coerce(*tpe),
+                        None => data::Value::from_string(&value),
+                    };
+                    rec.put_mut(field, value);
+                }
+                Ok(Some(rec))
+            }
+            _ if self.def.drop_nonmatching => Ok(None),
+            _ => {
+                let mut rec = rec;
+                for field in fields {
+                    if !rec.data.contains_key(field) {
+                        rec.put_mut(field, data::Value::None);
+                    }
+                }
+                Ok(Some(rec))
+            }
+        }
+    }
+
+    fn consumed_last(&self) -> bool {
+        self.consumed_last
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn split(line: &str) -> Option<Vec<String>> {
+        split_csv(line, ',')
+    }
+
+    fn fields(fields: &[&str]) -> Option<Vec<String>> {
+        Some(fields.iter().map(|f| f.to_string()).collect())
+    }
+
+    #[test]
+    fn splits_fields() {
+        assert_eq!(split("a,b,c"), fields(&["a", "b", "c"]));
+        assert_eq!(split("a,,c,"), fields(&["a", "", "c", ""]));
+        assert_eq!(split(""), fields(&[""]));
+        assert_eq!(split_csv("a\tb,c", '\t'), fields(&["a", "b,c"]));
+    }
+
+    #[test]
+    fn splits_quoted_fields() {
+        assert_eq!(
+            split(r#""a, b",c,"say ""hi""""#),
+            fields(&["a, b", "c", r#"say "hi""#])
+        );
+        assert_eq!(split(r#""",x"#), fields(&["", "x"]));
+        // A quote that doesn't start a field is taken literally.
+        assert_eq!(split(r#"5" disk,x"#), fields(&[r#"5" disk"#, "x"]));
+    }
+
+    #[test]
+    fn rejects_malformed_quotes() {
+        assert_eq!(split(r#""unterminated,x"#), None);
+        assert_eq!(split(r#""a"b,x"#), None);
+    }
+}
@@ -0,0 +1,263 @@
+use crate::data::{Record, Value};
+use crate::operator::timeslice::timestamp;
+use crate::operator::{EvalError, Expr, OperatorBuilder, UnaryPreAggOperator};
+use chrono::{DateTime, Duration, Utc};
+use itertools::Itertools;
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+type Key = Vec<Value>;
+
+#[derive(Clone)]
+pub struct DedupDef {
+    /// The values to deduplicate by, or the whole record if empty.
+    key_cols: Vec<Expr>,
+    /// Whether the whole record includes its metadata fields, those starting with `_`.
+    include_meta: bool,
+    last: Option<usize>,
+    ttl: Option<(Duration, Expr)>,
+}
+
+impl DedupDef {
+    pub fn new(
+        key_cols: Vec<Expr>,
+        include_meta: bool,
+        last: Option<usize>,
+        ttl: Option<(Duration, Expr)>,
+    ) -> Self {
+        DedupDef {
+            key_cols,
+            include_meta,
+            last,
+            ttl,
+        }
+    }
+
+    /// The key of the record: the values of the key columns, or the names and values of its
+    /// fields, in order of name.  Metadata fields like `_raw` or `_ingest_time` don't make a
+    /// record different from another unless they're included.  A record without any fields,
+    /// like an unparsed line, is keyed by its line.
+    fn key(&self, rec: &Record) -> Key {
+        if self.key_cols.is_empty() && rec.data.is_empty() {
+            return vec![Value::Str(rec.raw.clone())];
+        }
+        if self.key_cols.is_empty() {
+            return rec
+                .data
+                .iter()
+                .filter(|(name, _)| self.include_meta || !name.starts_with('_'))
+                .sorted_by(|(l, _), (r, _)| l.cmp(r))
+                .flat_map(|(name, value)| [Value::Str(name.clone()), value.clone()])
+                .collect();
+        }
+        self.key_cols
+            .iter()
+            .map(|expr| {
+                expr.eval_value(&rec.data)
+                    .unwrap_or(Cow::Owned(Value::None))
+                    .into_owned()
+            })
+            .collect()
+    }
+}
+
+impl OperatorBuilder for DedupDef {
+    fn build(&self) -> Box<dyn UnaryPreAggOperator> {
+        Box::new(Dedup {
+            def: self.clone(),
+            seen: HashMap::new(),
+            recent: BTreeMap::new(),
+            expiry: BTreeSet::new(),
+            next_seq: 0,
+        })
+    }
+
+    fn parallel(&self) -> bool {
+        false
+    }
+}
+
+/// When a combination of values was last seen, and when it was let through.
+struct Seen {
+    seq: u64,
+    passed: Option<DateTime<Utc>>,
+}
+
+/// The `dedup` operator, which drops records whose key matches one that's already been seen.
+/// Every combination is remembered unless the memory is bounded:
+///
+/// * With `last`, only the most recently seen combinations are remembered, and the one that
+///   was seen longest ago is forgotten to make room for a new one.
+/// * With `ttl`, a combination is forgotten once the timestamps have moved `ttl` past the record
+///   that was let through with it, so it's let through at most once per `ttl`.  The input is
+///   assumed to be in time order.
+pub struct Dedup {
+    def: DedupDef,
+    seen: HashMap<Key, Seen>,
+    /// The combinations in the order they were last seen, for `last`.
+    recent: BTreeMap<u64, Key>,
+    /// The combinations in the order they expire, for `ttl`.
+    expiry: BTreeSet<(DateTime<Utc>, Key)>,
+    next_seq: u64,
+}
+
+impl Dedup {
+    fn forget(&mut self, key: &Key) {
+        if let Some(seen) = self.seen.remove(key) {
+            self.recent.remove(&seen.seq);
+            if let (Some(passed), Some((ttl, _))) = (seen.passed, &self.def.ttl) {
+                self.expiry.remove(&(passed + *ttl, key.clone()));
+            }
+        }
+    }
+}
+
+impl UnaryPreAggOperator for Dedup {
+    fn process_mut(&mut self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let key = self.def.key(&rec);
+        let now = match &self.def.ttl {
+            Some((ttl, ts)) => Some((timestamp(ts.eval_value(&rec.data)?.as_ref(), None)?, *ttl)),
+            None => None,
+        };
+        if let Some((now, _)) = now {
+            while let Some((expires, expired)) = self.expiry.iter().next().cloned() {
+                if expires > now {
+                    break;
+                }
+                self.forget(&expired);
+            }
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        let duplicate = match self.seen.get_mut(&key) {
+            Some(seen) => {
+                self.recent.remove(&seen.seq);
+                seen.seq = seq;
+                true
+            }
+            None => {
+                if let Some((now, ttl)) = now {
+                    self.expiry.insert((now + ttl, key.clone()));
+                }
+                self.seen.insert(
+                    key.clone(),
+                    Seen {
+                        seq,
+                        passed: now.map(|(now, _)| now),
+                    },
+                );
+                false
+            }
+        };
+        self.recent.insert(seq, key);
+        if let Some(last) = self.def.last {
+            while self.seen.len() > last {
+                match self.recent.iter().next().map(|(_, key)| key.clone()) {
+                    Some(oldest) => self.forget(&oldest),
+                    None => break,
+                }
+            }
+        }
+
+        if duplicate {
+            Ok(None)
+        } else {
+            Ok(Some(rec))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(def: DedupDef, events: &[(&str, i64)]) -> Vec<String> {
+        let mut op = def.build();
+        events
+            .iter()
+            .filter_map(|(msg, minute)| {
+                let ts = DateTime::from_timestamp(minute * 60, 0).unwrap();
+                let rec = Record::new(format!("{} {}", msg, minute))
+                    .put("msg", Value::Str(msg.to_string()))
+                    .put("ts", Value::DateTime(ts));
+                op.process_mut(rec).unwrap()
+            })
+            .map(|rec| rec.raw)
+            .collect()
+    }
+
+    fn def(last: Option<usize>, ttl: Option<i64>) -> DedupDef {
+        DedupDef::new(
+            vec![Expr::column("msg")],
+            false,
+            last,
+            ttl.map(|ttl| (Duration::minutes(ttl), Expr::column("ts"))),
+        )
+    }
+
+    const EVENTS: &[(&str, i64)] = &[
+        ("a", 0),
+        ("b", 1),
+        ("a", 2),
+        ("c", 3),
+        ("a", 4),
+        ("b", 6),
+        ("a", 12),
+        ("b", 20),
+    ];
+
+    #[test]
+    fn drops_duplicates() {
+        assert_eq!(run(def(None, None), EVENTS), vec!["a 0", "b 1", "c 3"]);
+    }
+
+    #[test]
+    fn remembers_the_last_combinations() {
+        // `a` keeps being seen, so it's never forgotten, unlike `b` and `c`.
+        assert_eq!(
+            run(def(Some(2), None), EVENTS),
+            vec!["a 0", "b 1", "c 3", "b 6"]
+        );
+    }
+
+    #[test]
+    fn lets_combinations_through_after_the_ttl() {
+        assert_eq!(
+            run(def(None, Some(10)), EVENTS),
+            vec!["a 0", "b 1", "c 3", "a 12", "b 20"]
+        );
+    }
+
+    #[test]
+    fn deduplicates_whole_records() {
+        let records = [("a", 1, "x"), ("a", 1, "y"), ("a", 2, "y"), ("a", 1, "z")];
+        let run = |include_meta| {
+            let mut op = DedupDef::new(vec![], include_meta, None, None).build();
+            records
+                .iter()
+                .filter_map(|(msg, n, raw)| {
+                    let rec = Record::new(format!("{} {} {}", msg, n, raw))
+                        .put("msg", Value::Str(msg.to_string()))
+                        .put("n", Value::Int(*n))
+                        .put("_raw", Value::Str(raw.to_string()));
+                    op.process_mut(rec).unwrap()
+                })
+                .map(|rec| rec.raw)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(run(false), vec!["a 1 x", "a 2 y"]);
+        assert_eq!(run(true), vec!["a 1 x", "a 1 y", "a 2 y", "a 1 z"]);
+    }
+
+    #[test]
+    fn deduplicates_unparsed_lines() {
+        let mut op = DedupDef::new(vec![], false, None, None).build();
+        let lines: Vec<_> = ["error a", "error b", "error a"]
+            .iter()
+            .filter_map(|line| op.process_mut(Record::new(*line)).unwrap())
+            .map(|rec| rec.raw)
+            .collect();
+        assert_eq!(lines, vec!["error a", "error b"]);
+    }
+}
@@ -0,0 +1,95 @@
+use crate::data::{Record, Value};
+use crate::operator::{EvalError, Expr, OperatorBuilder, UnaryPreAggOperator};
+use std::collections::VecDeque;
+
+/// The definition for an explode operator, which emits a copy of each record for every element
+/// of an array.
+#[derive(Clone)]
+pub struct ExplodeDef {
+    input_column: Expr,
+    output_column: Expr,
+}
+
+impl ExplodeDef {
+    pub fn new(input_column: Expr, output_column: Expr) -> Self {
+        ExplodeDef {
+            input_column,
+            output_column,
+        }
+    }
+}
+
+impl OperatorBuilder for ExplodeDef {
+    fn build(&self) -> Box<dyn UnaryPreAggOperator> {
+        Box::new(Explode {
+            def: self.clone(),
+            pending: VecDeque::new(),
+        })
+    }
+}
+
+pub struct Explode {
+    def: ExplodeDef,
+    /// The copies of the last record that haven't been passed on yet.
+    pending: VecDeque<Record>,
+}
+
+impl UnaryPreAggOperator for Explode {
+    /// The first element is returned and the rest are left in `pending`.  An empty array drops
+    /// the record, while any other value is passed on as if it was the only element.
+    fn process_mut(&mut self, rec: Record) -> Result<Option<Record>, EvalError> {
+        self.pending.clear();
+        let elements = match self.def.input_column.eval_value(&rec.data)?.into_owned() {
+            Value::Array(elements) => elements,
+            value => vec![value],
+        };
+        for element in elements {
+            let copy = rec.clone().put_expr(&self.def.output_column, element)?;
+            self.pending.push_back(copy);
+        }
+        Ok(self.pending.pop_front())
+    }
+
+    fn pending(&mut self) -> Option<Record> {
+        self.pending.pop_front()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exploded(value: Value) -> Vec<String> {
+        let tags = Expr::NestedColumn {
+            head: "tags".to_string(),
+            rest: vec![],
+        };
+        let mut op = ExplodeDef::new(tags.clone(), tags).build();
+        let rec = Record::new("")
+            .put("tags", value)
+            .put("host", Value::from_string("a"));
+        op.process_mut(rec)
+            .unwrap()
+            .into_iter()
+            .chain(std::iter::from_fn(|| op.pending()))
+            .map(|rec| {
+                assert_eq!(rec.data["host"], Value::from_string("a"));
+                rec.data["tags"].to_string()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn emits_a_record_per_element() {
+        assert_eq!(
+            exploded(Value::Array(vec![
+                Value::from_string("x"),
+                Value::Int(1),
+                Value::Array(vec![])
+            ])),
+            vec!["x", "1", "[]"]
+        );
+        assert!(exploded(Value::Array(vec![])).is_empty());
+        assert_eq!(exploded(Value::from_string("y")), vec!["y"]);
+    }
+}
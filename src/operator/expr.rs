@@ -1,6 +1,7 @@
 use crate::data::DisplayConfig;
 use crate::operator::{Data, EvalError, Evaluate};
 use crate::{data, funcs};
+use regex::Regex;
 use std::borrow::Cow;
 use std::collections::HashMap;
 use std::convert::TryInto;
@@ -25,7 +26,23 @@ pub enum Expr {
         value_if_true: Box<Expr>,
         value_if_false: Box<Expr>,
     },
+    /// The first of the values that isn't missing or `None`.
+    Coalesce(Vec<Expr>),
+    In {
+        value: Box<Expr>,
+        list: Vec<Expr>,
+    },
+    Match {
+        value: Box<Expr>,
+        regex: Regex,
+    },
     Value(&'static data::Value),
+    /// A function exported by a WebAssembly module.
+    #[cfg(feature = "wasm")]
+    Wasm {
+        func: std::sync::Arc<crate::operator::wasm::WasmFunction>,
+        args: Vec<Expr>,
+    },
 }
 
 impl Expr {
@@ -133,6 +150,8 @@ impl Evaluate<f64> for Expr {
             data::Value::Int(i) => Ok(*i as f64),
             data::Value::Float(f) => Ok(f.into_inner()),
             data::Value::Str(s) => data::Value::aggressively_to_num(s),
+            // In seconds
+            duration @ data::Value::Duration(_) => duration.try_into(),
             other => Err(EvalError::ExpectedNumber {
                 found: format!("{}", other),
             }),
@@ -221,6 +240,20 @@ impl Expr {
 
                 func.eval_func(&evaluated_args?).map(Cow::Owned)
             }
+            #[cfg(feature = "wasm")]
+            Expr::Wasm { ref func, ref args } => {
+                let evaluated_args = args
+                    .iter()
+                    .map(|expr| expr.eval_value(record).map(|v| v.into_owned()))
+                    .collect::<Result<Vec<data::Value>, EvalError>>()?;
+                let input = serde_json::to_value(evaluated_args).map_err(|e| {
+                    EvalError::FunctionFailed {
+                        name: "wasm",
+                        msg: e.to_string(),
+                    }
+                })?;
+                func.call(&input).map(Cow::Owned)
+            }
             Expr::IfOp {
                 ref cond,
                 ref value_if_true,
@@ -234,6 +267,43 @@ impl Expr {
                     (*value_if_false).eval_value(record)
                 }
             }
+            Expr::Coalesce(ref args) => {
+                for arg in args {
+                    match arg.eval_value(record) {
+                        Ok(value) if value.as_ref() != &data::Value::None => return Ok(value),
+                        Ok(_)
+                        | Err(EvalError::NoValueForKey { .. })
+                        | Err(EvalError::IndexOutOfRange { .. }) => {}
+                        Err(err) => return Err(err),
+                    }
+                }
+                Ok(Cow::Owned(data::Value::None))
+            }
+            Expr::In {
+                ref value,
+                ref list,
+            } => {
+                let value = value.eval_value(record)?;
+                for item in list {
+                    if item.eval_value(record)? == value {
+                        return Ok(Cow::Owned(data::Value::Bool(true)));
+                    }
+                }
+                Ok(Cow::Owned(data::Value::Bool(false)))
+            }
+            Expr::Match {
+                ref value,
+                ref regex,
+            } => {
+                // Numbers and other values are matched by how they're displayed, so that
+                // `status =~ /^5/` works, while a missing value never matches.
+                let matched = match value.eval_value(record)?.as_ref() {
+                    data::Value::Str(s) => regex.is_match(s),
+                    data::Value::None => false,
+                    other => regex.is_match(&other.to_string()),
+                };
+                Ok(Cow::Owned(data::Value::from_bool(matched)))
+            }
             Expr::Value(v) => Ok(Cow::Borrowed(v)),
         }
     }
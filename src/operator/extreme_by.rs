@@ -0,0 +1,111 @@
+use crate::data;
+use crate::operator::{AggregateFunction, Data, EvalError, Evaluate, Expr};
+use ordered_float::OrderedFloat;
+use std::borrow::Cow;
+
+/// The `min_by` and `max_by` aggregates, which return the value of `output` from the row where
+/// `column` is the smallest or largest.  When several rows share that value, the first is used.
+pub struct ExtremeBy {
+    best: Option<(f64, data::Value)>,
+    column: Expr,
+    output: Expr,
+    max: bool,
+}
+
+impl ExtremeBy {
+    pub fn empty<T: Into<Expr>>(column: T, output: T, max: bool) -> Self {
+        ExtremeBy {
+            best: None,
+            column: column.into(),
+            output: output.into(),
+            max,
+        }
+    }
+
+    /// Whether a row with this value should replace the best row, which came before it.
+    fn better(&self, value: f64) -> bool {
+        match self.best {
+            None => true,
+            Some((best, _)) if self.max => value > best,
+            Some((best, _)) => value < best,
+        }
+    }
+}
+
+impl AggregateFunction for ExtremeBy {
+    fn process(&mut self, data: &Data) -> Result<(), EvalError> {
+        let value: f64 = self.column.eval(data)?;
+        if self.better(value) {
+            let output = self
+                .output
+                .eval_value(data)
+                .unwrap_or(Cow::Owned(data::Value::None))
+                .into_owned();
+            self.best = Some((value, output));
+        }
+        Ok(())
+    }
+
+    fn emit(&self) -> data::Value {
+        self.best
+            .as_ref()
+            .map(|(_, output)| output.clone())
+            .unwrap_or(data::Value::None)
+    }
+
+    fn empty_box(&self) -> Box<dyn AggregateFunction> {
+        Box::new(ExtremeBy::empty(
+            self.column.clone(),
+            self.output.clone(),
+            self.max,
+        ))
+    }
+
+    fn state(&self) -> data::Value {
+        match &self.best {
+            Some((value, output)) => data::Value::Array(vec![
+                data::Value::Float(OrderedFloat(*value)),
+                output.clone(),
+            ]),
+            None => data::Value::None,
+        }
+    }
+
+    fn merge(&mut self, state: data::Value) {
+        if let data::Value::Array(mut state) = state {
+            if let (Some(output), Some(data::Value::Float(OrderedFloat(value)))) =
+                (state.pop(), state.pop())
+            {
+                if self.better(value) {
+                    self.best = Some((value, output));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+
+    fn run(max: bool) -> Value {
+        let mut extreme = ExtremeBy::empty(Expr::column("latency"), Expr::column("url"), max);
+        for (latency, url) in [(3, "/a"), (9, "/b"), (1, "/c"), (9, "/d"), (1, "/e")].iter() {
+            let data = vec![
+                ("latency".to_string(), Value::Int(*latency)),
+                ("url".to_string(), Value::Str(url.to_string())),
+            ]
+            .into_iter()
+            .collect();
+            extreme.process(&data).unwrap();
+        }
+        extreme.emit()
+    }
+
+    #[test]
+    fn value_at_extreme() {
+        assert_eq!(run(true), Value::Str("/b".to_string()));
+        assert_eq!(run(false), Value::Str("/c".to_string()));
+    }
+}
@@ -1,6 +1,6 @@
-use crate::data::Record;
+use crate::data::{Record, Value, ValueType};
 use crate::operator::{EvalError, Expr, UnaryPreAggFunction};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::iter::FromIterator;
 
 #[derive(Clone)]
@@ -28,10 +28,26 @@ impl UnaryPreAggFunction for Fields {
         let mut rec = rec;
         match self.mode {
             FieldMode::Only => {
-                rec.data.retain(|k, _| self.columns.contains(k));
+                for (column, tpe) in &self.casts {
+                    if *tpe == ValueType::Json && !rec.data.contains_key(column) {
+                        let nested = Fields::gather(&rec, column);
+                        rec.data.insert(column.clone(), nested);
+                    }
+                }
+                rec.data.retain(|k, _| self.selects(k));
+                for (column, tpe) in &self.casts {
+                    if is_pattern(column) {
+                        rec.data
+                            .iter_mut()
+                            .filter(|(k, _)| matches(column, k, self.include_meta))
+                            .for_each(|(_, value)| *value = value.coerce(*tpe));
+                    } else if let Some(value) = rec.data.get_mut(column) {
+                        *value = value.coerce(*tpe);
+                    }
+                }
             }
             FieldMode::Except => {
-                rec.data.retain(|k, _| !self.columns.contains(k));
+                rec.data.retain(|k, _| !self.selects(k));
             }
         }
         if rec.data.is_empty() {
@@ -42,16 +58,94 @@ impl UnaryPreAggFunction for Fields {
     }
 }
 
+/// Whether a field name given to `fields` is a pattern, like `request_*`.
+pub fn is_pattern(name: &str) -> bool {
+    name.contains('*')
+}
+
+/// Whether the field matches the pattern, where `*` matches any run of characters.  Metadata
+/// fields, those starting with `_`, are only matched by a pattern that starts with `_` too,
+/// unless `include_meta`.
+pub fn matches(pattern: &str, field: &str, include_meta: bool) -> bool {
+    if field.starts_with('_') && !pattern.starts_with('_') && !include_meta {
+        return false;
+    }
+    let mut parts = pattern.split('*');
+    let mut rest = match field.strip_prefix(parts.next().unwrap_or_default()) {
+        Some(rest) => rest,
+        None => return false,
+    };
+    let parts: Vec<_> = parts.collect();
+    let (last, middle) = match parts.split_last() {
+        Some(split) => split,
+        None => return rest.is_empty(),
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
 #[derive(Clone)]
 pub struct Fields {
     columns: HashSet<String>,
+    /// The names with a wildcard, e.g. `request_*`.
+    patterns: Vec<String>,
     mode: FieldMode,
+    casts: Vec<(String, ValueType)>,
+    include_meta: bool,
 }
 
 impl Fields {
     pub fn new(columns: &[String], mode: FieldMode) -> Self {
-        let columns = HashSet::from_iter(columns.iter().cloned());
-        Fields { columns, mode }
+        let (patterns, columns): (Vec<_>, Vec<_>) =
+            columns.iter().cloned().partition(|name| is_pattern(name));
+        Fields {
+            columns: HashSet::from_iter(columns),
+            patterns,
+            mode,
+            casts: Vec::new(),
+            include_meta: false,
+        }
+    }
+
+    /// Let the patterns match metadata fields, those starting with `_`, too.
+    pub fn include_meta(mut self, include_meta: bool) -> Self {
+        self.include_meta = include_meta;
+        self
+    }
+
+    fn selects(&self, field: &str) -> bool {
+        self.columns.contains(field)
+            || self
+                .patterns
+                .iter()
+                .any(|pattern| matches(pattern, field, self.include_meta))
+    }
+
+    /// Collect the fields under `prefix`, e.g. `request.method` and `request.path` for
+    /// `request`, into an object.  If there aren't any, the result is `None`.
+    fn gather(rec: &Record, prefix: &str) -> Value {
+        let prefix = format!("{}.", prefix);
+        let nested: HashMap<String, Value> = rec
+            .data
+            .iter()
+            .filter_map(|(k, v)| k.strip_prefix(&prefix).map(|k| (k.to_string(), v.clone())))
+            .collect();
+        if nested.is_empty() {
+            Value::None
+        } else {
+            Value::Obj(nested.into())
+        }
+    }
+
+    /// Convert the given fields to a type as they are selected.
+    pub fn with_casts(mut self, casts: Vec<(String, ValueType)>) -> Self {
+        self.casts = casts;
+        self
     }
 }
 
@@ -0,0 +1,133 @@
+use crate::data;
+use crate::operator::timeslice::timestamp;
+use crate::operator::{AggregateFunction, Data, EvalError, Expr};
+
+/// The `first`, `last`, `earliest` and `latest` aggregates, which return the value of `column`
+/// from the first or last row, either in input order or ordered by a timestamp.  Rows where the
+/// value or the timestamp is missing are skipped.
+pub struct FirstLast {
+    /// The order of the picked row, if ordered by a timestamp, and its value.
+    picked: Option<(Option<data::Value>, data::Value)>,
+    column: Expr,
+    /// The timestamp to order the rows by, or `None` to use the input order.
+    by: Option<Expr>,
+    last: bool,
+}
+
+impl FirstLast {
+    pub fn empty<T: Into<Expr>>(column: T, by: Option<T>, last: bool) -> Self {
+        FirstLast {
+            picked: None,
+            column: column.into(),
+            by: by.map(|by| by.into()),
+            last,
+        }
+    }
+
+    /// Numbers are taken to be epoch timestamps and compared as-is, anything else has to be a
+    /// date.
+    fn order(&self, data: &Data) -> Result<Option<data::Value>, EvalError> {
+        let by = match &self.by {
+            Some(by) => by,
+            None => return Ok(None),
+        };
+        let value = by.eval_value(data)?;
+        match value.as_ref() {
+            data::Value::Int(_) | data::Value::Float(_) => Ok(Some(value.into_owned())),
+            other => Ok(Some(data::Value::DateTime(timestamp(other, None)?))),
+        }
+    }
+
+    /// Whether a row with this order should replace the picked row, which came before it.
+    fn better(&self, order: &Option<data::Value>) -> bool {
+        // Ties go to the later row for `last` and `latest`, like they would without an order.
+        match &self.picked {
+            None => true,
+            Some((best, _)) if self.last => order >= best,
+            Some((best, _)) => order < best,
+        }
+    }
+}
+
+impl AggregateFunction for FirstLast {
+    fn process(&mut self, data: &Data) -> Result<(), EvalError> {
+        let order = self.order(data)?;
+        if self.better(&order) {
+            let value = self.column.eval_value(data)?.into_owned();
+            self.picked = Some((order, value));
+        }
+        Ok(())
+    }
+
+    fn emit(&self) -> data::Value {
+        self.picked
+            .as_ref()
+            .map(|(_, value)| value.clone())
+            .unwrap_or(data::Value::None)
+    }
+
+    fn empty_box(&self) -> Box<dyn AggregateFunction> {
+        Box::new(FirstLast::empty(
+            self.column.clone(),
+            self.by.clone(),
+            self.last,
+        ))
+    }
+
+    fn state(&self) -> data::Value {
+        match &self.picked {
+            Some((order, value)) => data::Value::Array(vec![
+                order.clone().unwrap_or(data::Value::None),
+                value.clone(),
+            ]),
+            None => data::Value::None,
+        }
+    }
+
+    fn merge(&mut self, state: data::Value) {
+        if let data::Value::Array(mut state) = state {
+            if let (Some(value), Some(order)) = (state.pop(), state.pop()) {
+                let order = self.by.as_ref().map(|_| order);
+                if self.better(&order) {
+                    self.picked = Some((order, value));
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+
+    fn run(by: bool, last: bool) -> Value {
+        let by = if by { Some(Expr::column("ts")) } else { None };
+        let mut picker = FirstLast::empty(Expr::column("status"), by, last);
+        let rows = [
+            ("2024-01-01T10:00:05Z", Some("b")),
+            ("2024-01-01T09:59:00Z", Some("a")),
+            ("2024-01-01T10:01:00Z", None),
+            ("2024-01-01T10:00:30Z", Some("d")),
+            ("2024-01-01T09:58:00Z", Some("c")),
+        ];
+        for (ts, status) in rows.iter() {
+            let mut data: Data = vec![("ts".to_string(), Value::from_string(*ts))]
+                .into_iter()
+                .collect();
+            if let Some(status) = status {
+                data.insert("status".to_string(), Value::from_string(*status));
+            }
+            let _ = picker.process(&data);
+        }
+        picker.emit()
+    }
+
+    #[test]
+    fn picks_by_order() {
+        assert_eq!(run(false, false), Value::from_string("b"));
+        assert_eq!(run(false, true), Value::from_string("c"));
+        assert_eq!(run(true, false), Value::from_string("c"));
+        assert_eq!(run(true, true), Value::from_string("d"));
+    }
+}
@@ -0,0 +1,191 @@
+use crate::data::{Record, Value};
+use crate::operator::{EvalError, Expr, OperatorBuilder, UnaryPreAggOperator};
+use maxminddb::{geoip2, MaxMindDBError, Reader};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::path::Path;
+use std::sync::Arc;
+use thiserror::Error;
+
+/// The fields an IP address is looked up into.
+pub const FIELDS: &[&str] = &["country", "city", "asn", "asn_org"];
+
+/// The environment variable with the paths of the databases, separated like `PATH`.
+pub const DATABASE_ENV: &str = "AGRIND_GEOIP_DB";
+
+/// The most addresses that are remembered.  Logs tend to repeat a small set of addresses, so the
+/// cache is simply emptied when it fills up.
+const CACHE_SIZE: usize = 65536;
+
+#[derive(Debug, Error)]
+pub enum DatabaseError {
+    #[error("{0}")]
+    Io(#[from] std::io::Error),
+
+    #[error("not a MaxMind database: {0}")]
+    Invalid(MaxMindDBError),
+}
+
+/// A database in the [MaxMind DB](https://maxmind.github.io/MaxMind-DB/) format, like the
+/// GeoLite2 City, Country and ASN databases, read by the `maxminddb` crate.
+pub struct Database {
+    reader: Reader<Vec<u8>>,
+}
+
+impl Database {
+    pub fn open(path: &Path) -> Result<Database, DatabaseError> {
+        Database::from_bytes(std::fs::read(path)?)
+    }
+
+    pub fn from_bytes(buf: Vec<u8>) -> Result<Database, DatabaseError> {
+        let reader = Reader::from_source(buf).map_err(DatabaseError::Invalid)?;
+        Ok(Database { reader })
+    }
+
+    /// The fields of the network that contains `ip`, which are `None` when the database doesn't
+    /// have them.  Records that can't be decoded are treated like missing ones.
+    fn lookup(&self, ip: IpAddr) -> Vec<Value> {
+        let location = self.reader.lookup::<geoip2::City>(ip).ok();
+        let network = self.reader.lookup::<geoip2::Asn>(ip).ok();
+        let text = |s: Option<&str>| s.map_or(Value::None, |s| Value::Str(s.to_string()));
+        vec![
+            text(
+                location
+                    .as_ref()
+                    .and_then(|location| location.country.as_ref()?.iso_code),
+            ),
+            text(
+                location.as_ref().and_then(|location| {
+                    location.city.as_ref()?.names.as_ref()?.get("en").copied()
+                }),
+            ),
+            network
+                .as_ref()
+                .and_then(|network| network.autonomous_system_number)
+                .map_or(Value::None, |asn| Value::Int(asn.into())),
+            text(network.and_then(|network| network.autonomous_system_organization)),
+        ]
+    }
+}
+
+/// Look up an address in each of the databases, e.g. a City and an ASN database, taking each
+/// field from the first one that has it.
+fn lookup(databases: &[Database], ip: IpAddr) -> Vec<Value> {
+    let mut fields = vec![Value::None; FIELDS.len()];
+    for found in databases.iter().map(|db| db.lookup(ip)) {
+        for (field, found) in fields.iter_mut().zip(found) {
+            if let Value::None = field {
+                *field = found;
+            }
+        }
+    }
+    fields
+}
+
+#[derive(Clone)]
+pub struct GeoIpDef {
+    input_column: Expr,
+    databases: Arc<Vec<Database>>,
+}
+
+impl GeoIpDef {
+    pub fn new(input_column: Expr, databases: Vec<Database>) -> Self {
+        GeoIpDef {
+            input_column,
+            databases: Arc::new(databases),
+        }
+    }
+}
+
+impl OperatorBuilder for GeoIpDef {
+    fn build(&self) -> Box<dyn UnaryPreAggOperator> {
+        Box::new(GeoIp {
+            def: self.clone(),
+            cache: HashMap::new(),
+        })
+    }
+}
+
+/// The `geoip` operator, which adds the location and network of an IP address.  The lookups are
+/// remembered since the same addresses tend to show up over and over.
+pub struct GeoIp {
+    def: GeoIpDef,
+    cache: HashMap<IpAddr, Vec<Value>>,
+}
+
+impl UnaryPreAggOperator for GeoIp {
+    fn process_mut(&mut self, rec: Record) -> Result<Option<Record>, EvalError> {
+        // Addresses that are missing, like the `-` of an access log, or malformed aren't errors.
+        let ip = match self.def.input_column.eval_value(&rec.data)?.as_ref() {
+            Value::None => None,
+            input => input.to_string().trim().parse::<IpAddr>().ok(),
+        };
+        let fields = match ip {
+            None => vec![Value::None; FIELDS.len()],
+            Some(ip) => {
+                if self.cache.len() >= CACHE_SIZE && !self.cache.contains_key(&ip) {
+                    self.cache.clear();
+                }
+                let databases = &self.def.databases;
+                self.cache
+                    .entry(ip)
+                    .or_insert_with(|| lookup(databases, ip))
+                    .clone()
+            }
+        };
+        let mut rec = rec;
+        for (field, value) in FIELDS.iter().zip(fields) {
+            rec.put_mut(*field, value);
+        }
+        Ok(Some(rec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn open(name: &str) -> Database {
+        Database::open(Path::new("test_files").join(name).as_path()).unwrap()
+    }
+
+    fn find(databases: &[Database], ip: &str) -> Vec<String> {
+        lookup(databases, ip.parse().unwrap())
+            .iter()
+            .map(|value| value.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn looks_up_addresses() {
+        let databases = [open("geoip-city.mmdb"), open("geoip-asn.mmdb")];
+        assert_eq!(
+            find(&databases, "81.2.69.160"),
+            vec!["GB", "London", "20712", "Andrews & Arnold Ltd"]
+        );
+        assert_eq!(
+            find(&databases, "216.160.83.56"),
+            vec!["US", "Milton", "None", "None"]
+        );
+        assert_eq!(
+            find(&databases, "2001:db8::1"),
+            vec!["DE", "None", "64496", "Example Networks"]
+        );
+        assert_eq!(
+            find(&databases, "10.0.0.1"),
+            vec!["None", "None", "None", "None"]
+        );
+    }
+
+    #[test]
+    fn rejects_other_files() {
+        assert!(matches!(
+            Database::from_bytes(b"not a database".to_vec()),
+            Err(DatabaseError::Invalid(_))
+        ));
+        assert!(matches!(
+            Database::open(Path::new("test_files/missing.mmdb")),
+            Err(DatabaseError::Io(_))
+        ));
+    }
+}
@@ -0,0 +1,168 @@
+use crate::data::{Aggregate, Record, Row, VMap, Value};
+use crate::operator::branch::AggregateBranch;
+use crate::operator::{AggregateOperator, EvalError, Expr, UnaryPreAggFunction};
+use itertools::Itertools;
+use std::collections::HashMap;
+use std::io;
+use std::io::BufRead;
+use std::sync::{Arc, Mutex};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum JoinMode {
+    /// Drop rows without a matching record.
+    Inner,
+    /// Pass rows without a matching record through unchanged.
+    Left,
+}
+
+type Index = HashMap<Vec<Value>, VMap>;
+
+/// The values of the keys for a row, or `None` if any of them are missing.
+fn key_of(keys: &[Expr], data: &VMap) -> Option<Vec<Value>> {
+    keys.iter()
+        .map(|key| match key.eval_value(data) {
+            Ok(value) if *value != Value::None => Some(value.into_owned()),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Index the records to join against by their keys.  When several records share a key, the
+/// first is used.
+fn index(keys: &[Expr], records: Vec<VMap>) -> Index {
+    let mut index = HashMap::new();
+    for data in records {
+        if let Some(key) = key_of(keys, &data) {
+            index.entry(key).or_insert(data);
+        }
+    }
+    index
+}
+
+/// Add the fields of the matching record to the row, or return `None` if the row should be
+/// dropped.  Fields that are in both the row and the matching record keep the value from the
+/// row.
+fn join_row(keys: &[Expr], mode: JoinMode, right: &Index, mut data: VMap) -> Option<VMap> {
+    let matched = key_of(keys, &data).and_then(|key| right.get(&key));
+    match (matched, mode) {
+        (Some(right), _) => {
+            for (field, value) in right {
+                if !data.contains_key(field) {
+                    data.insert(field.clone(), value.clone());
+                }
+            }
+            Some(data)
+        }
+        (None, JoinMode::Left) => Some(data),
+        (None, JoinMode::Inner) => None,
+    }
+}
+
+/// Adds the fields of the record from another file that has the same keys as the row.  The
+/// records from the other file are all read up front and held in memory.
+#[derive(Clone)]
+pub struct Join {
+    keys: Vec<Expr>,
+    mode: JoinMode,
+    right: Arc<Index>,
+}
+
+impl Join {
+    /// Build the records to join against by running the lines of `input` through the operators
+    /// of `right`, e.g. `json`, or `json | count by id`.
+    pub fn load<R: BufRead>(
+        keys: Vec<Expr>,
+        mode: JoinMode,
+        mut input: R,
+        mut right: AggregateBranch,
+    ) -> io::Result<Self> {
+        let mut line = Vec::with_capacity(1024);
+        while input.read_until(b'\n', &mut line)? > 0 {
+            let rec = Record::new(String::from_utf8_lossy(&line));
+            right.process(Row::Record(rec));
+            line.clear();
+        }
+        right.finish();
+        let right = index(&keys, right.emit().data);
+        Ok(Join {
+            keys,
+            mode,
+            right: Arc::new(right),
+        })
+    }
+}
+
+impl UnaryPreAggFunction for Join {
+    fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let Record { data, raw } = rec;
+        Ok(join_row(&self.keys, self.mode, &self.right, data).map(|data| Record { data, raw }))
+    }
+}
+
+/// A join against the results of operators run over the same rows, e.g. the number of errors
+/// for each request.  Every row has to be seen before any can be matched, so the rows are held
+/// until the end of the input.
+pub struct SelfJoin {
+    keys: Vec<Expr>,
+    mode: JoinMode,
+    rows: Aggregate,
+    right: Mutex<AggregateBranch>,
+}
+
+impl SelfJoin {
+    pub fn new(keys: Vec<Expr>, mode: JoinMode, right: AggregateBranch) -> Self {
+        SelfJoin {
+            keys,
+            mode,
+            rows: Aggregate {
+                columns: Vec::new(),
+                data: Vec::new(),
+            },
+            right: Mutex::new(right),
+        }
+    }
+}
+
+impl AggregateOperator for SelfJoin {
+    fn emit(&self) -> Aggregate {
+        let right = self.right.lock().expect("not poisoned").emit();
+        let mut columns = if self.rows.columns.is_empty() {
+            self.rows
+                .data
+                .iter()
+                .flat_map(|data| data.keys())
+                .unique()
+                .sorted()
+                .cloned()
+                .collect()
+        } else {
+            self.rows.columns.clone()
+        };
+        let index = index(&self.keys, right.data);
+        let data: Vec<VMap> = self
+            .rows
+            .data
+            .iter()
+            .filter_map(|data| join_row(&self.keys, self.mode, &index, data.clone()))
+            .collect();
+        // Only the columns of the other rows that were matched are added.
+        for column in right.columns {
+            if !columns.contains(&column) && data.iter().any(|data| data.contains_key(&column)) {
+                columns.push(column);
+            }
+        }
+        Aggregate { columns, data }
+    }
+
+    fn process(&mut self, row: Row) {
+        match &row {
+            Row::Record(rec) => self.rows.data.push(rec.data.clone()),
+            Row::Aggregate(agg) => self.rows = agg.clone(),
+        }
+        self.right.get_mut().expect("not poisoned").process(row);
+    }
+
+    fn finish(&mut self) {
+        self.right.get_mut().expect("not poisoned").finish();
+    }
+}
@@ -55,6 +55,13 @@ impl UnaryPreAggOperator for Limit {
             Limit::Tail { queue, .. } => Box::new(queue.into_iter()),
         }
     }
+
+    fn exhausted(&self) -> bool {
+        match self {
+            Limit::Head { index, limit } => index >= limit,
+            Limit::Tail { .. } => false,
+        }
+    }
 }
 
 impl LimitDef {
@@ -80,9 +87,13 @@ impl OperatorBuilder for LimitDef {
             }
         } else {
             Limit::Tail {
-                queue: VecDeque::with_capacity(-self.limit as usize),
+                queue: VecDeque::new(),
                 limit: -self.limit as usize,
             }
         })
     }
+
+    fn parallel(&self) -> bool {
+        false
+    }
 }
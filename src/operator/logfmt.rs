@@ -0,0 +1,179 @@
+//! A parser for logfmt, lines of `key=value` pairs separated by whitespace, e.g.
+//! `level=info msg="request done" path=/users duration=12ms`.  Values may be quoted, with `\"`,
+//! `\\`, `\n`, `\t` and `\r` escapes inside the quotes.
+
+use std::borrow::Cow;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct Pair<'a> {
+    pub key: &'a str,
+    /// The value, which is missing for a bare word, e.g. `retry` in `level=warn retry`.
+    pub value: Option<Cow<'a, str>>,
+}
+
+/// Split the line into its pairs.  Unless `strict`, anything that isn't a pair is tolerated: a
+/// bare word becomes a pair without a value, a value without a key is skipped, and an
+/// unterminated quoted value runs to the end of the line.  In strict mode these are errors.
+pub fn parse(line: &str, strict: bool) -> Result<Vec<Pair<'_>>, String> {
+    let mut pairs = vec![];
+    let mut rest = line.trim_start();
+    while !rest.is_empty() {
+        let key_end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let (key, after_key) = rest.split_at(key_end);
+        match after_key.strip_prefix('=') {
+            Some(after_eq) => {
+                let (value, after_value, terminated) = value(after_eq);
+                if strict && key.is_empty() {
+                    return Err(format!("a value without a key, `={}`", value));
+                }
+                if strict && !terminated {
+                    return Err(format!("the quoted value of `{}` isn't terminated", key));
+                }
+                if !key.is_empty() {
+                    pairs.push(Pair {
+                        key,
+                        value: Some(value),
+                    });
+                }
+                rest = after_value;
+            }
+            None if strict => return Err(format!("`{}` isn't a key=value pair", key)),
+            None => {
+                pairs.push(Pair { key, value: None });
+                rest = after_key;
+            }
+        }
+        rest = rest.trim_start();
+    }
+    Ok(pairs)
+}
+
+/// Read the value at the start of the input, returning it, the rest of the input, and whether a
+/// quoted value was terminated.
+fn value(input: &str) -> (Cow<'_, str>, &str, bool) {
+    let quoted = match input.strip_prefix('"') {
+        Some(quoted) => quoted,
+        None => {
+            let end = input.find(char::is_whitespace).unwrap_or(input.len());
+            return (Cow::Borrowed(&input[..end]), &input[end..], true);
+        }
+    };
+    let mut value = String::new();
+    let mut chars = quoted.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return (Cow::Owned(value), &quoted[i + 1..], true),
+            '\\' => match chars.next() {
+                Some((_, 'n')) => value.push('\n'),
+                Some((_, 't')) => value.push('\t'),
+                Some((_, 'r')) => value.push('\r'),
+                Some((_, c @ ('"' | '\\'))) => value.push(c),
+                Some((_, c)) => {
+                    value.push('\\');
+                    value.push(c);
+                }
+                None => value.push('\\'),
+            },
+            c => value.push(c),
+        }
+    }
+    (Cow::Owned(value), "", false)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pairs(line: &str) -> Vec<(&str, Option<String>)> {
+        parse(line, false)
+            .unwrap()
+            .into_iter()
+            .map(|pair| (pair.key, pair.value.map(Cow::into_owned)))
+            .collect()
+    }
+
+    fn some(value: &str) -> Option<String> {
+        Some(value.to_string())
+    }
+
+    #[test]
+    fn parses_pairs() {
+        assert_eq!(
+            pairs("at=info\tmethod=GET path=\"/a b\" query=a=b empty= quoted=\"\" code=200"),
+            vec![
+                ("at", some("info")),
+                ("method", some("GET")),
+                ("path", some("/a b")),
+                ("query", some("a=b")),
+                ("empty", some("")),
+                ("quoted", some("")),
+                ("code", some("200")),
+            ]
+        );
+        assert_eq!(pairs("   "), vec![]);
+    }
+
+    #[test]
+    fn unescapes_quoted_values() {
+        assert_eq!(
+            pairs(r#"msg="say \"hi\"\n" dir="C:\\" x=1 re="a\d""#),
+            vec![
+                ("msg", some("say \"hi\"\n")),
+                ("dir", some("C:\\")),
+                ("x", some("1")),
+                ("re", some("a\\d")),
+            ]
+        );
+    }
+
+    #[test]
+    fn parses_edge_cases() {
+        assert_eq!(
+            pairs(r#"key="a \"b\"" next=1"#),
+            vec![("key", some(r#"a "b""#)), ("next", some("1"))]
+        );
+        assert_eq!(
+            pairs("debug level=info verbose"),
+            vec![("debug", None), ("level", some("info")), ("verbose", None)]
+        );
+        assert_eq!(pairs("a=1 b="), vec![("a", some("1")), ("b", some(""))]);
+        assert_eq!(
+            parse("b=", true).unwrap(),
+            vec![Pair {
+                key: "b",
+                value: Some(Cow::Borrowed("")),
+            }]
+        );
+    }
+
+    #[test]
+    fn tolerates_malformed_lines() {
+        assert_eq!(
+            pairs(r#"level=warn retry =orphan msg="unterminated x=1"#),
+            vec![
+                ("level", some("warn")),
+                ("retry", None),
+                ("msg", some("unterminated x=1")),
+            ]
+        );
+    }
+
+    #[test]
+    fn strict_rejects_malformed_lines() {
+        assert_eq!(
+            parse("level=warn retry", true),
+            Err("`retry` isn't a key=value pair".to_string())
+        );
+        assert_eq!(
+            parse("=orphan", true),
+            Err("a value without a key, `=orphan`".to_string())
+        );
+        assert_eq!(
+            parse(r#"msg="open"#, true),
+            Err("the quoted value of `msg` isn't terminated".to_string())
+        );
+        assert!(parse(r#"a=1 b="2 3""#, true).is_ok());
+    }
+}
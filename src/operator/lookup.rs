@@ -0,0 +1,155 @@
+use crate::data::{Record, Value};
+use crate::operator::csv::split_csv;
+use crate::operator::{EvalError, Expr, UnaryPreAggFunction};
+use std::collections::HashMap;
+use std::io;
+use std::io::BufRead;
+use std::sync::Arc;
+
+/// Adds columns from the row of a CSV or TSV table whose key column matches the key of the
+/// record.  The table is read up front and held in memory.
+#[derive(Clone)]
+pub struct Lookup {
+    key: Expr,
+    fields: Vec<String>,
+    /// The values of `fields` for each key of the table.
+    table: Arc<HashMap<String, Vec<Value>>>,
+}
+
+impl Lookup {
+    /// Build the table from `input`, whose first line is the header naming the columns.  When
+    /// several rows share a key, the first is used.
+    pub fn load<R: BufRead>(
+        key: Expr,
+        column: &str,
+        fields: Vec<String>,
+        delimiter: char,
+        input: R,
+    ) -> io::Result<Self> {
+        let invalid = |msg: String| io::Error::new(io::ErrorKind::InvalidData, msg);
+        let mut lines = input.lines();
+        let header = match lines.next() {
+            Some(line) => split_csv(line?.trim_end_matches('\r'), delimiter)
+                .ok_or_else(|| invalid("malformed header".to_string()))?,
+            None => return Err(invalid("the file is empty".to_string())),
+        };
+        let index_of = |name: &str| {
+            header
+                .iter()
+                .position(|col| col == name)
+                .ok_or_else(|| invalid(format!("there is no column named \"{}\"", name)))
+        };
+        let key_index = index_of(column)?;
+        let field_indexes = fields
+            .iter()
+            .map(|field| index_of(field))
+            .collect::<io::Result<Vec<_>>>()?;
+
+        let mut table = HashMap::new();
+        for (lineno, line) in lines.enumerate() {
+            let line = line?;
+            let line = line.trim_end_matches('\r');
+            if line.is_empty() {
+                continue;
+            }
+            let values = split_csv(line, delimiter)
+                .filter(|values| values.len() == header.len())
+                .ok_or_else(|| {
+                    invalid(format!(
+                        "line {} doesn't have the {} columns of the header",
+                        lineno + 2,
+                        header.len()
+                    ))
+                })?;
+            let row = field_indexes
+                .iter()
+                .map(|idx| Value::from_string(&values[*idx]))
+                .collect();
+            table.entry(values[key_index].clone()).or_insert(row);
+        }
+        Ok(Lookup {
+            key,
+            fields,
+            table: Arc::new(table),
+        })
+    }
+}
+
+impl UnaryPreAggFunction for Lookup {
+    /// The key is matched against the table as a string, so a numeric field matches a column
+    /// containing the same number.  The fields are set to `None` when there's no match.
+    fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let row = match self.key.eval_value(&rec.data) {
+            Ok(key) if *key != Value::None => self.table.get(&key.to_string()),
+            _ => None,
+        };
+        let mut rec = rec;
+        match row {
+            Some(row) => {
+                for (field, value) in self.fields.iter().zip(row) {
+                    rec.put_mut(field, value.clone());
+                }
+            }
+            None => {
+                for field in &self.fields {
+                    rec.put_mut(field, Value::None);
+                }
+            }
+        }
+        Ok(Some(rec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lookup(table: &str, fields: &[&str]) -> io::Result<Lookup> {
+        Lookup::load(
+            Expr::NestedColumn {
+                head: "status".to_string(),
+                rest: vec![],
+            },
+            "status",
+            fields.iter().map(|f| f.to_string()).collect(),
+            ',',
+            table.as_bytes(),
+        )
+    }
+
+    #[test]
+    fn enriches_records() {
+        let lookup = lookup(
+            "status,status_name,class\n200,OK,2xx\n404,\"Not Found\",4xx\n200,dup,2xx\n",
+            &["status_name"],
+        )
+        .unwrap();
+        let rec = lookup
+            .process(Record::new("").put("status", Value::Int(200)))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            rec.data.get("status_name"),
+            Some(&Value::Str("OK".to_string()))
+        );
+        let rec = lookup
+            .process(Record::new("").put("status", Value::Str("500".to_string())))
+            .unwrap()
+            .unwrap();
+        assert_eq!(rec.data.get("status_name"), Some(&Value::None));
+    }
+
+    #[test]
+    fn rejects_bad_tables() {
+        let err = |table, fields: &[&str]| lookup(table, fields).err().unwrap().to_string();
+        assert_eq!(err("", &[]), "the file is empty");
+        assert_eq!(
+            err("code,name\n", &["name"]),
+            "there is no column named \"status\""
+        );
+        assert_eq!(
+            err("status,name\n200,OK,extra\n", &["name"]),
+            "line 2 doesn't have the 2 columns of the header"
+        );
+    }
+}
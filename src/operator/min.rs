@@ -1,5 +1,6 @@
 use crate::data;
 use crate::operator::{AggregateFunction, Data, EvalError, Evaluate, Expr};
+use ordered_float::OrderedFloat;
 
 pub struct Min {
     min: f64,
@@ -35,4 +36,16 @@ impl AggregateFunction for Min {
     fn empty_box(&self) -> Box<dyn AggregateFunction> {
         Box::new(Min::empty(self.column.clone()))
     }
+
+    fn state(&self) -> data::Value {
+        data::Value::Float(OrderedFloat(self.min))
+    }
+
+    fn merge(&mut self, state: data::Value) {
+        if let data::Value::Float(OrderedFloat(value)) = state {
+            if value < self.min {
+                self.min = value;
+            }
+        }
+    }
 }
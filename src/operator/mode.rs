@@ -0,0 +1,83 @@
+use crate::data;
+use crate::operator::{AggregateFunction, Data, EvalError, Expr};
+use std::collections::HashMap;
+
+/// The most common value.  When several values are seen equally often, the smallest is used.
+pub struct Mode {
+    counts: HashMap<data::Value, u64>,
+    column: Expr,
+}
+
+impl Mode {
+    pub fn empty<T: Into<Expr>>(column: T) -> Self {
+        Mode {
+            counts: HashMap::new(),
+            column: column.into(),
+        }
+    }
+}
+
+impl AggregateFunction for Mode {
+    fn process(&mut self, rec: &Data) -> Result<(), EvalError> {
+        let value = self.column.eval_value(rec)?;
+        if *value != data::Value::None {
+            *self.counts.entry(value.into_owned()).or_insert(0) += 1;
+        }
+        Ok(())
+    }
+
+    fn emit(&self) -> data::Value {
+        self.counts
+            .iter()
+            .max_by(|(lv, lc), (rv, rc)| lc.cmp(rc).then(rv.cmp(lv)))
+            .map(|(value, _)| value.clone())
+            .unwrap_or(data::Value::None)
+    }
+
+    fn empty_box(&self) -> Box<dyn AggregateFunction> {
+        Box::new(Mode::empty(self.column.clone()))
+    }
+
+    fn state(&self) -> data::Value {
+        data::Value::Array(
+            self.counts
+                .iter()
+                .map(|(value, count)| {
+                    data::Value::Array(vec![value.clone(), data::Value::Int(*count as i64)])
+                })
+                .collect(),
+        )
+    }
+
+    fn merge(&mut self, state: data::Value) {
+        if let data::Value::Array(counts) = state {
+            for entry in counts {
+                if let data::Value::Array(mut entry) = entry {
+                    if let (Some(data::Value::Int(count)), Some(value)) = (entry.pop(), entry.pop())
+                    {
+                        *self.counts.entry(value).or_insert(0) += count as u64;
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+
+    #[test]
+    fn most_common_value() {
+        let mut mode = Mode::empty(Expr::column("x"));
+        assert_eq!(mode.emit(), Value::None);
+        for value in ["b", "a", "c", "b", "a"] {
+            let data = vec![("x".to_string(), Value::Str(value.to_string()))]
+                .into_iter()
+                .collect();
+            mode.process(&data).unwrap();
+        }
+        assert_eq!(mode.emit(), Value::Str("a".to_string()));
+    }
+}
@@ -1,14 +1,22 @@
-use crate::data::Record;
+use crate::data::{Record, ValueType};
 use crate::operator::expr::Expr;
-use crate::operator::{EvalError, UnaryPreAggFunction};
+use crate::operator::{logfmt, EvalError, UnaryPreAggFunction};
 use crate::{data, operator};
-use serde_json::Value as JsonValue;
-use std::collections::HashMap;
+use serde::de::{DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::cell::RefCell;
+use std::collections::hash_map::Entry;
+use std::collections::{HashMap, HashSet};
+use std::convert::TryFrom;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct Parse {
     regex: regex::Regex,
     fields: Vec<String>,
+    /// The type each capture is converted to, if one was given. Parallel to `fields`.
+    types: Vec<Option<ValueType>>,
     input_column: Option<Expr>,
     options: ParseOptions,
 }
@@ -22,26 +30,41 @@ impl Parse {
     ) -> Self {
         Parse {
             regex: pattern,
+            types: vec![None; fields.len()],
             fields,
             input_column,
             options,
         }
     }
 
+    /// Convert the given captures to a type as they are parsed. Values that can't be converted
+    /// become `None`.
+    pub fn with_casts(mut self, casts: Vec<(String, ValueType)>) -> Self {
+        for (field, tpe) in casts {
+            if let Some(idx) = self.fields.iter().position(|f| *f == field) {
+                self.types[idx] = Some(tpe);
+            }
+        }
+        self
+    }
+
     fn matches(&self, rec: &Record) -> Result<Option<Vec<data::Value>>, EvalError> {
         let inp = operator::get_input(rec, &self.input_column)?;
         match self.regex.captures_iter(inp.trim()).next() {
             None => Ok(None),
             Some(capture) => {
                 let mut values: Vec<data::Value> = Vec::with_capacity(self.fields.len());
-                for item in capture.iter().skip(1) {
-                    // the first capture is the entire string
+                // the first capture is the entire string
+                for (item, tpe) in capture.iter().skip(1).zip(self.types.iter()) {
                     match item {
                         None => values.push(data::Value::None),
                         Some(match_) => {
-                            let value = match self.options.no_conversion {
-                                true => data::Value::Str(match_.as_str().to_owned()),
-                                false => data::Value::from_string(match_.as_str()),
+                            let value = match (tpe, self.options.no_conversion) {
+                                (Some(tpe), _) => {
+                                    data::Value::Str(match_.as_str().to_owned()).coerce(*tpe)
+                                }
+                                (None, true) => data::Value::Str(match_.as_str().to_owned()),
+                                (None, false) => data::Value::from_string(match_.as_str()),
                             };
                             values.push(value)
                         }
@@ -88,56 +111,224 @@ pub struct ParseOptions {
     pub no_conversion: bool,
 }
 
+/// What to do when a JSON object has the same key more than once.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum DuplicateKeys {
+    /// Keep the value of the first occurrence.
+    First,
+    /// Keep the value of the last occurrence.
+    #[default]
+    Last,
+    /// Collect the values of every occurrence into an array, in order.
+    Array,
+}
+
+/// Which fields `json` adds to the record.
+#[derive(Clone, Default)]
+pub enum JsonMode {
+    /// Each top-level key of the object.
+    #[default]
+    Fields,
+    /// Each value nested in the object under its dotted path, e.g. `spans.0.duration`.
+    Flatten,
+    /// The value at each path, under the given name, or `None` if there's nothing there.
+    Paths(Vec<(Expr, String)>),
+}
+
 #[derive(Clone)]
 pub struct ParseJson {
     input_column: Option<Expr>,
+    mode: JsonMode,
+    duplicates: DuplicateKeys,
+    /// Set once a warning about duplicate keys has been written, if warnings were requested.
+    warned: Option<Arc<AtomicBool>>,
 }
 
 impl ParseJson {
     pub fn new(input_column: Option<Expr>) -> ParseJson {
-        ParseJson { input_column }
+        ParseJson {
+            input_column,
+            mode: JsonMode::default(),
+            duplicates: DuplicateKeys::default(),
+            warned: None,
+        }
+    }
+
+    pub fn with_mode(mut self, mode: JsonMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// Handle duplicate keys in objects with the given policy, optionally warning the first
+    /// time one is seen.
+    pub fn with_duplicates(mut self, duplicates: DuplicateKeys, warn: bool) -> Self {
+        self.duplicates = duplicates;
+        self.warned = warn.then(|| Arc::new(AtomicBool::new(false)));
+        self
     }
 }
 
-impl UnaryPreAggFunction for ParseJson {
-    fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
-        fn json_to_value(v: JsonValue) -> data::Value {
-            match v {
-                JsonValue::Number(num) => {
-                    if num.is_i64() {
-                        data::Value::Int(num.as_i64().unwrap())
-                    } else {
-                        data::Value::from_float(num.as_f64().unwrap())
+/// Converts JSON straight into a `data::Value`, since going through `serde_json::Value` would
+/// silently collapse duplicate keys.  The first duplicate key found is recorded in `duplicate`.
+#[derive(Clone, Copy)]
+struct JsonSeed<'a> {
+    duplicates: DuplicateKeys,
+    duplicate: &'a RefCell<Option<String>>,
+}
+
+impl<'de> DeserializeSeed<'de> for JsonSeed<'_> {
+    type Value = data::Value;
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<Self::Value, D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for JsonSeed<'_> {
+    type Value = data::Value;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str("a JSON value")
+    }
+
+    fn visit_bool<E>(self, v: bool) -> Result<Self::Value, E> {
+        Ok(data::Value::Bool(v))
+    }
+
+    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E> {
+        Ok(data::Value::Int(v))
+    }
+
+    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E> {
+        Ok(i64::try_from(v)
+            .map(data::Value::Int)
+            .unwrap_or_else(|_| data::Value::from_float(v as f64)))
+    }
+
+    fn visit_f64<E>(self, v: f64) -> Result<Self::Value, E> {
+        Ok(data::Value::from_float(v))
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E> {
+        Ok(data::Value::Str(v.to_owned()))
+    }
+
+    fn visit_string<E>(self, v: String) -> Result<Self::Value, E> {
+        Ok(data::Value::Str(v))
+    }
+
+    fn visit_unit<E>(self) -> Result<Self::Value, E> {
+        Ok(data::Value::None)
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut values = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(value) = seq.next_element_seed(self)? {
+            values.push(value);
+        }
+        Ok(data::Value::Array(values))
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut access: A) -> Result<Self::Value, A::Error> {
+        let mut map = HashMap::with_capacity(access.size_hint().unwrap_or(0));
+        // The keys whose values have already been collected into an array.
+        let mut collected = HashSet::new();
+        while let Some(key) = access.next_key::<String>()? {
+            let value = access.next_value_seed(self)?;
+            match map.entry(key) {
+                Entry::Vacant(entry) => {
+                    entry.insert(value);
+                }
+                Entry::Occupied(mut entry) => {
+                    self.duplicate
+                        .borrow_mut()
+                        .get_or_insert_with(|| entry.key().clone());
+                    match self.duplicates {
+                        DuplicateKeys::First => {}
+                        DuplicateKeys::Last => {
+                            entry.insert(value);
+                        }
+                        DuplicateKeys::Array => {
+                            if collected.insert(entry.key().clone()) {
+                                let first = entry.insert(data::Value::None);
+                                entry.insert(data::Value::Array(vec![first, value]));
+                            } else if let data::Value::Array(values) = entry.get_mut() {
+                                values.push(value);
+                            }
+                        }
                     }
                 }
-                JsonValue::String(s) => data::Value::Str(s),
-                JsonValue::Null => data::Value::None,
-                JsonValue::Bool(b) => data::Value::Bool(b),
-                JsonValue::Object(map) => data::Value::Obj(
-                    map.into_iter()
-                        .map(|(k, v)| (k, json_to_value(v)))
-                        .collect::<HashMap<String, data::Value>>()
-                        .into(),
-                ),
-                JsonValue::Array(vec) => data::Value::Array(
-                    vec.into_iter()
-                        .map(json_to_value)
-                        .collect::<Vec<data::Value>>(),
-                ),
             }
         }
-        let json: JsonValue = {
+        Ok(data::Value::Obj(map.into()))
+    }
+}
+
+/// Parses JSON, with simd-json when it's enabled.  Anything simd-json rejects is parsed again with
+/// serde_json, so that the fields found, and which lines are dropped, are the same either way.
+fn parse_json(inp: &str, seed: JsonSeed) -> Option<data::Value> {
+    #[cfg(feature = "simd-json")]
+    {
+        // simd-json parses in place, so it needs a copy of the input it can modify.
+        let mut bytes = inp.as_bytes().to_vec();
+        if let Ok(value) = simd_json::Deserializer::from_slice(&mut bytes)
+            .and_then(|mut deserializer| seed.deserialize(&mut deserializer))
+        {
+            return Some(value);
+        }
+        seed.duplicate.replace(None);
+    }
+    let mut deserializer = serde_json::Deserializer::from_str(inp);
+    seed.deserialize(&mut deserializer)
+        .and_then(|value| deserializer.end().map(|_| value))
+        .ok()
+}
+
+impl UnaryPreAggFunction for ParseJson {
+    fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let duplicate = RefCell::new(None);
+        let json = {
             let inp = operator::get_input(&rec, &self.input_column)?;
-            serde_json::from_str(&inp).map_err(|_| EvalError::ExpectedJson {
+            let seed = JsonSeed {
+                duplicates: self.duplicates,
+                duplicate: &duplicate,
+            };
+            parse_json(&inp, seed).ok_or_else(|| EvalError::ExpectedJson {
                 found: inp.trim_end().to_string(),
             })?
         };
-        let res = match json {
-            JsonValue::Object(map) => {
+        if let (Some(warned), Some(key)) = (&self.warned, duplicate.into_inner()) {
+            if !warned.swap(true, Ordering::Relaxed) {
+                eprintln!(
+                    "warning: found duplicate JSON key \"{}\", further duplicates will not be reported",
+                    key
+                );
+            }
+        }
+        let res = match (json, &self.mode) {
+            (data::Value::Obj(map), JsonMode::Fields) => {
                 let mut rec = rec;
                 rec.data.reserve(map.len());
                 for (k, v) in map {
-                    rec.put_mut(k, json_to_value(v));
+                    rec.put_mut(k, v);
+                }
+                rec
+            }
+            (data::Value::Obj(map), JsonMode::Flatten) => {
+                let mut rec = rec;
+                for (k, v) in map {
+                    flatten(k, v, &mut rec);
+                }
+                rec
+            }
+            (data::Value::Obj(map), JsonMode::Paths(paths)) => {
+                let map: HashMap<String, data::Value> = map.into_iter().collect();
+                let mut rec = rec;
+                for (path, name) in paths {
+                    let value = path
+                        .eval_value(&map)
+                        .map_or(data::Value::None, |value| value.into_owned());
+                    rec.put_mut(name, value);
                 }
                 rec
             }
@@ -148,31 +339,116 @@ impl UnaryPreAggFunction for ParseJson {
     }
 }
 
+/// Add a value to the record under its dotted path, or, for an object or array, each of the values
+/// nested in it.  Empty objects and arrays are added as they are.
+fn flatten(path: String, value: data::Value, rec: &mut Record) {
+    match value {
+        data::Value::Obj(map) if !map.is_empty() => {
+            for (k, v) in map {
+                flatten(format!("{}.{}", path, k), v, rec);
+            }
+        }
+        data::Value::Array(values) if !values.is_empty() => {
+            for (i, v) in values.into_iter().enumerate() {
+                flatten(format!("{}.{}", path, i), v, rec);
+            }
+        }
+        value => rec.put_mut(path, value),
+    }
+}
+
 #[derive(Clone)]
 pub struct ParseLogfmt {
     input_column: Option<Expr>,
+    strict: bool,
+    /// The keys to extract, or all of them if empty.
+    keys: Vec<String>,
+    duplicates: DuplicateKeys,
 }
 
 impl ParseLogfmt {
     pub fn new(input_column: Option<Expr>) -> ParseLogfmt {
-        ParseLogfmt { input_column }
+        ParseLogfmt {
+            input_column,
+            strict: false,
+            keys: vec![],
+            duplicates: DuplicateKeys::default(),
+        }
+    }
+
+    /// Fail on lines that aren't entirely `key=value` pairs, instead of making the best of them.
+    pub fn strict(mut self, strict: bool) -> Self {
+        self.strict = strict;
+        self
+    }
+
+    /// Only extract the given keys.  Those missing from a line are set to `None`.
+    pub fn with_keys(mut self, keys: Vec<String>) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    pub fn with_duplicates(mut self, duplicates: DuplicateKeys) -> Self {
+        self.duplicates = duplicates;
+        self
     }
 }
 
 impl UnaryPreAggFunction for ParseLogfmt {
     fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
-        let pairs = {
+        let mut values: Vec<(String, data::Value)> = vec![];
+        {
             let inp = operator::get_input(&rec, &self.input_column)?;
             // Record includes the trailing newline, while logfmt considers that part of the
             // message if present. Trim any trailing whitespace.
-            logfmt::parse(inp.trim_end())
-        };
-        let res = {
-            pairs.into_iter().fold(rec, |record, pair| match pair.val {
-                None => record.put(&pair.key, data::Value::None),
-                Some(val) => record.put(&pair.key, data::Value::from_string(val)),
-            })
-        };
-        Ok(Some(res))
+            let inp = inp.trim_end();
+            let pairs =
+                logfmt::parse(inp, self.strict).map_err(|reason| EvalError::ExpectedLogfmt {
+                    found: inp.to_string(),
+                    reason,
+                })?;
+            // The index in `values` of each key seen so far, and whether its values have been
+            // collected into an array.
+            let mut seen: HashMap<&str, (usize, bool)> = HashMap::new();
+            for pair in pairs {
+                if !self.keys.is_empty() && !self.keys.iter().any(|key| key == pair.key) {
+                    continue;
+                }
+                let value = match pair.value {
+                    Some(value) => data::Value::from_string(value),
+                    None => data::Value::None,
+                };
+                match (seen.get_mut(pair.key), self.duplicates) {
+                    (None, _) => {
+                        seen.insert(pair.key, (values.len(), false));
+                        values.push((pair.key.to_string(), value));
+                    }
+                    (Some(_), DuplicateKeys::First) => {}
+                    (Some((i, _)), DuplicateKeys::Last) => values[*i].1 = value,
+                    (Some((i, collected)), DuplicateKeys::Array) => {
+                        let previous = &mut values[*i].1;
+                        if *collected {
+                            if let data::Value::Array(array) = previous {
+                                array.push(value);
+                            }
+                        } else {
+                            let first = std::mem::replace(previous, data::Value::None);
+                            *previous = data::Value::Array(vec![first, value]);
+                            *collected = true;
+                        }
+                    }
+                }
+            }
+        }
+        let mut rec = rec;
+        for key in &self.keys {
+            if !values.iter().any(|(k, _)| k == key) {
+                values.push((key.clone(), data::Value::None));
+            }
+        }
+        for (key, value) in values {
+            rec.put_mut(key, value);
+        }
+        Ok(Some(rec))
     }
 }
@@ -1,6 +1,23 @@
 use crate::data;
 use crate::operator::{AggregateFunction, Data, EvalError, Evaluate, Expr};
-use quantiles::ckms::CKMS;
+use ordered_float::OrderedFloat;
+use std::collections::BTreeMap;
+
+/// The most values that are kept before switching to a sketch.  Up to this point the
+/// percentiles are exact.
+const MAX_EXACT: usize = 2048;
+
+/// The relative error of the percentiles from the sketch, i.e. the value returned is within 0.5%
+/// of a value with the right rank.
+const RELATIVE_ACCURACY: f64 = 0.005;
+
+/// The most buckets kept for each sign.  When there are more, the buckets closest to zero are
+/// merged, so only the smallest percentiles lose accuracy, and only when the values span many
+/// orders of magnitude.
+const MAX_BUCKETS: usize = 2048;
+
+/// Values closer to zero than this are counted as zero.
+const MIN_MAGNITUDE: f64 = 1e-9;
 
 impl Percentile {
     pub fn empty<T: Into<Expr>>(column: T, percentile: f64) -> Self {
@@ -9,34 +26,310 @@ impl Percentile {
         }
 
         Percentile {
-            ckms: CKMS::<f64>::new(0.001),
+            values: Vec::new(),
+            sketch: None,
             column: column.into(),
             percentile,
         }
     }
+
+    fn add(&mut self, value: f64) {
+        match self.sketch {
+            Some(ref mut sketch) => sketch.insert(value),
+            None if self.values.len() < MAX_EXACT => self.values.push(value),
+            None => self.switch_to_sketch().insert(value),
+        }
+    }
+
+    fn switch_to_sketch(&mut self) -> &mut Sketch {
+        if self.sketch.is_none() {
+            let mut sketch = Sketch::new();
+            for value in self.values.drain(..) {
+                sketch.insert(value);
+            }
+            self.sketch = Some(sketch);
+        }
+        self.sketch.as_mut().expect("just set")
+    }
 }
 
+/// Computes a percentile in a bounded amount of memory.  The first values are kept as-is and
+/// then moved into a DDSketch, which keeps a count per logarithmically sized bucket.
 pub struct Percentile {
-    ckms: CKMS<f64>,
+    values: Vec<f64>,
+    sketch: Option<Sketch>,
     column: Expr,
     percentile: f64,
 }
 
+/// The index of the value at the percentile, out of `count` values, using the nearest rank.
+fn rank(percentile: f64, count: u64) -> u64 {
+    ((percentile * count as f64).ceil() as u64).clamp(1, count) - 1
+}
+
 impl AggregateFunction for Percentile {
     fn process(&mut self, data: &Data) -> Result<(), EvalError> {
         let value: f64 = self.column.eval(data)?;
-        self.ckms.insert(value);
+        if !value.is_nan() {
+            self.add(value);
+        }
         Ok(())
     }
 
     fn emit(&self) -> data::Value {
-        let pct_opt = self.ckms.query(self.percentile);
+        let pct_opt = match self.sketch {
+            Some(ref sketch) => sketch.query(self.percentile),
+            None if self.values.is_empty() => None,
+            None => {
+                let mut sorted = self.values.clone();
+                sorted.sort_by(|l, r| l.partial_cmp(r).expect("NaNs are skipped"));
+                Some(sorted[rank(self.percentile, sorted.len() as u64) as usize])
+            }
+        };
         pct_opt
-            .map(|(_usize, pct_float)| data::Value::from_float(pct_float))
+            .map(data::Value::from_float)
             .unwrap_or(data::Value::None)
     }
 
     fn empty_box(&self) -> Box<dyn AggregateFunction> {
         Box::new(Percentile::empty(self.column.clone(), self.percentile))
     }
+
+    /// The values while the percentile is exact, otherwise the sketch's counts, in an object so
+    /// that the two can be told apart.
+    fn state(&self) -> data::Value {
+        let state = match &self.sketch {
+            Some(sketch) => ("sketch".to_string(), sketch.state()),
+            None => (
+                "values".to_string(),
+                data::Value::Array(
+                    self.values
+                        .iter()
+                        .map(|value| data::Value::Float(OrderedFloat(*value)))
+                        .collect(),
+                ),
+            ),
+        };
+        data::Value::Obj(std::iter::once(state).collect())
+    }
+
+    fn merge(&mut self, state: data::Value) {
+        let state = match state {
+            data::Value::Obj(state) => state,
+            _ => return,
+        };
+        if let Some(data::Value::Array(values)) = state.get("values") {
+            for value in values {
+                if let data::Value::Float(OrderedFloat(value)) = value {
+                    self.add(*value);
+                }
+            }
+        }
+        if let Some(other) = state.get("sketch") {
+            self.switch_to_sketch().merge(other);
+        }
+    }
+}
+
+/// A DDSketch, which counts the values in buckets whose bounds grow geometrically, so every
+/// value in a bucket is within `RELATIVE_ACCURACY` of the bucket's value.
+struct Sketch {
+    gamma_ln: f64,
+    /// The counts for positive values and for the magnitudes of negative values, by bucket.
+    positive: BTreeMap<i32, u64>,
+    negative: BTreeMap<i32, u64>,
+    zeros: u64,
+    count: u64,
+}
+
+impl Sketch {
+    fn new() -> Self {
+        let gamma = (1.0 + RELATIVE_ACCURACY) / (1.0 - RELATIVE_ACCURACY);
+        Sketch {
+            gamma_ln: gamma.ln(),
+            positive: BTreeMap::new(),
+            negative: BTreeMap::new(),
+            zeros: 0,
+            count: 0,
+        }
+    }
+
+    fn insert(&mut self, value: f64) {
+        self.count += 1;
+        if value.abs() < MIN_MAGNITUDE {
+            self.zeros += 1;
+            return;
+        }
+        let key = (value.abs().ln() / self.gamma_ln).ceil() as i32;
+        let buckets = if value > 0.0 {
+            &mut self.positive
+        } else {
+            &mut self.negative
+        };
+        *buckets.entry(key).or_insert(0) += 1;
+        if buckets.len() > MAX_BUCKETS {
+            let (_, lowest) = buckets.pop_first().expect("there are buckets");
+            *buckets.values_mut().next().expect("there are buckets") += lowest;
+        }
+    }
+
+    /// The counts, as `[zeros, count, positive, negative]` with the buckets as `[key, count]`
+    /// pairs.
+    fn state(&self) -> data::Value {
+        let buckets = |buckets: &BTreeMap<i32, u64>| {
+            data::Value::Array(
+                buckets
+                    .iter()
+                    .map(|(key, count)| {
+                        data::Value::Array(vec![
+                            data::Value::Int(i64::from(*key)),
+                            data::Value::Int(*count as i64),
+                        ])
+                    })
+                    .collect(),
+            )
+        };
+        data::Value::Array(vec![
+            data::Value::Int(self.zeros as i64),
+            data::Value::Int(self.count as i64),
+            buckets(&self.positive),
+            buckets(&self.negative),
+        ])
+    }
+
+    /// Add the counts of another sketch, from `state`.
+    fn merge(&mut self, state: &data::Value) {
+        let add = |buckets: &mut BTreeMap<i32, u64>, other: &data::Value| {
+            if let data::Value::Array(other) = other {
+                for bucket in other {
+                    if let data::Value::Array(bucket) = bucket {
+                        if let [data::Value::Int(key), data::Value::Int(count)] = bucket[..] {
+                            *buckets.entry(key as i32).or_insert(0) += count as u64;
+                        }
+                    }
+                }
+            }
+            while buckets.len() > MAX_BUCKETS {
+                let (_, lowest) = buckets.pop_first().expect("there are buckets");
+                *buckets.values_mut().next().expect("there are buckets") += lowest;
+            }
+        };
+        if let data::Value::Array(state) = state {
+            if let [data::Value::Int(zeros), data::Value::Int(count), ref positive, ref negative] =
+                state[..]
+            {
+                self.zeros += zeros as u64;
+                self.count += count as u64;
+                add(&mut self.positive, positive);
+                add(&mut self.negative, negative);
+            }
+        }
+    }
+
+    /// The value in the middle of a bucket, in terms of relative error.
+    fn bucket_value(&self, key: i32) -> f64 {
+        let gamma = self.gamma_ln.exp();
+        2.0 * (self.gamma_ln * key as f64).exp() / (gamma + 1.0)
+    }
+
+    fn query(&self, percentile: f64) -> Option<f64> {
+        if self.count == 0 {
+            return None;
+        }
+        let rank = rank(percentile, self.count);
+        // The buckets in order of their values: the negative ones from the largest magnitude,
+        // then zero, then the positive ones.
+        let buckets = self
+            .negative
+            .iter()
+            .rev()
+            .map(|(key, count)| (-self.bucket_value(*key), *count))
+            .chain(std::iter::once((0.0, self.zeros)))
+            .chain(
+                self.positive
+                    .iter()
+                    .map(|(key, count)| (self.bucket_value(*key), *count)),
+            );
+        let mut seen = 0;
+        for (value, count) in buckets {
+            seen += count;
+            if seen > rank {
+                return Some(value);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+
+    fn process(values: impl Iterator<Item = f64>, percentile: f64) -> Percentile {
+        let mut pct = Percentile::empty(Expr::column("x"), percentile);
+        for value in values {
+            let data = vec![("x".to_string(), Value::from_float(value))]
+                .into_iter()
+                .collect();
+            pct.process(&data).unwrap();
+        }
+        pct
+    }
+
+    fn run(values: impl Iterator<Item = f64>, percentile: f64) -> f64 {
+        match process(values, percentile).emit() {
+            Value::Float(f) => f.0,
+            Value::Int(i) => i as f64,
+            other => panic!("unexpected percentile {:?}", other),
+        }
+    }
+
+    #[test]
+    fn exact_percentiles() {
+        let values = || (1..=10).map(|i| i as f64);
+        assert_eq!(run(values(), 0.5), 5.0);
+        assert_eq!(run(values(), 0.99), 10.0);
+        assert_eq!(run(values(), 0.01), 1.0);
+    }
+
+    #[test]
+    fn merges_partial_results() {
+        let values = |range: std::ops::Range<i32>| range.map(|i| i as f64);
+        // Exact with exact, a sketch with exact values, and exact values with a sketch.
+        for (split, end) in [(400, 1000), (5000, 6000), (1000, 6000)] {
+            let mut merged = process(values(0..split), 0.9);
+            merged.merge(process(values(split..end), 0.9).state());
+            assert_eq!(merged.emit(), process(values(0..end), 0.9).emit());
+        }
+    }
+
+    #[test]
+    fn sketched_percentiles() {
+        let values = || (1..=100_000).map(|i| i as f64);
+        for (percentile, expected) in [(0.5, 50_000.0), (0.99, 99_000.0), (0.999, 99_900.0)] {
+            let found = run(values(), percentile);
+            assert!(
+                (found - expected).abs() <= expected * RELATIVE_ACCURACY,
+                "p{} was {}",
+                percentile,
+                found
+            );
+        }
+        let mixed = || (-50_000..50_000).map(|i| i as f64);
+        assert!((run(mixed(), 0.5) + 1.0).abs() <= RELATIVE_ACCURACY);
+        assert!((run(mixed(), 0.01) + 49_001.0).abs() <= 49_001.0 * RELATIVE_ACCURACY);
+    }
+
+    #[test]
+    fn sketch_memory_is_bounded() {
+        let mut sketch = Sketch::new();
+        for i in 0..100_000 {
+            sketch.insert(1.1f64.powi(i % 5000 - 2500));
+        }
+        assert!(sketch.positive.len() <= MAX_BUCKETS);
+        let p99 = sketch.query(0.99).unwrap();
+        let expected = 1.1f64.powi(2449);
+        assert!((p99 - expected).abs() <= expected * RELATIVE_ACCURACY * 2.0);
+    }
 }
@@ -0,0 +1,94 @@
+use crate::data::Record;
+use crate::operator::{EvalError, OperatorBuilder, UnaryPreAggOperator};
+use itertools::Itertools;
+use std::collections::HashSet;
+
+/// The fields a rename operator applies to.
+#[derive(Clone)]
+pub enum RenamePattern {
+    Field(String),
+    /// Every field whose name matches, renamed by substituting the match with the
+    /// replacement, which can refer to capture groups, e.g. `$1`.
+    Regex(regex::Regex),
+}
+
+#[derive(Clone)]
+pub struct RenameDef {
+    pattern: RenamePattern,
+    replacement: String,
+}
+
+impl RenameDef {
+    pub fn new(pattern: RenamePattern, replacement: String) -> Self {
+        RenameDef {
+            pattern,
+            replacement,
+        }
+    }
+}
+
+impl OperatorBuilder for RenameDef {
+    fn build(&self) -> Box<dyn UnaryPreAggOperator> {
+        Box::new(Rename {
+            def: self.clone(),
+            warned: HashSet::new(),
+        })
+    }
+}
+
+pub struct Rename {
+    def: RenameDef,
+    /// Names that have already been warned about colliding, so each is only reported once.
+    warned: HashSet<String>,
+}
+
+impl Rename {
+    fn new_name(&self, name: &str) -> Option<String> {
+        match &self.def.pattern {
+            RenamePattern::Field(field) if field == name => Some(self.def.replacement.clone()),
+            RenamePattern::Field(_) => None,
+            RenamePattern::Regex(regex) if regex.is_match(name) => Some(
+                regex
+                    .replace(name, self.def.replacement.as_str())
+                    .into_owned(),
+            ),
+            RenamePattern::Regex(_) => None,
+        }
+    }
+}
+
+impl UnaryPreAggOperator for Rename {
+    /// Fields are renamed in order of their names. When a new name is already taken, by a
+    /// field that wasn't renamed or by an earlier rename, the last value renamed to it wins.
+    fn process_mut(&mut self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let renames: Vec<(String, String)> = rec
+            .data
+            .keys()
+            .sorted()
+            .filter_map(|name| {
+                self.new_name(name)
+                    .filter(|new_name| new_name != name)
+                    .map(|new_name| (name.clone(), new_name))
+            })
+            .collect();
+        if renames.is_empty() {
+            return Ok(Some(rec));
+        }
+
+        let mut rec = rec;
+        let renamed: Vec<_> = renames
+            .into_iter()
+            .map(|(name, new_name)| (rec.data.remove(&name).unwrap(), name, new_name))
+            .collect();
+        for (value, name, new_name) in renamed {
+            if rec.data.contains_key(&new_name) && self.warned.insert(new_name.clone()) {
+                eprintln!(
+                    "warning: renaming `{}` to `{}` replaced an existing field of the same name",
+                    name, new_name
+                );
+            }
+            rec.data.insert(new_name, value);
+        }
+        Ok(Some(rec))
+    }
+}
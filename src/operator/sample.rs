@@ -0,0 +1,101 @@
+use crate::data::Record;
+use crate::operator::{EvalError, OperatorBuilder, UnaryPreAggOperator};
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+
+/// The definition for a sample operator, which passes each row through with the given
+/// probability.  Without a seed, different rows are picked each time.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SampleDef {
+    rate: f64,
+    seed: Option<u64>,
+}
+
+impl SampleDef {
+    pub fn new(rate: f64, seed: Option<u64>) -> Self {
+        SampleDef { rate, seed }
+    }
+}
+
+impl OperatorBuilder for SampleDef {
+    fn build(&self) -> Box<dyn UnaryPreAggOperator> {
+        let seed = self
+            .seed
+            .unwrap_or_else(|| RandomState::new().build_hasher().finish());
+        Box::new(Sample {
+            rate: self.rate,
+            rng: SplitMix64(seed),
+        })
+    }
+
+    // The rows picked for a seed depend on the order they're seen in.
+    fn parallel(&self) -> bool {
+        false
+    }
+}
+
+/// A small, fast generator that's plenty random enough to pick rows.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// A number in `[0, 1)`.
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+pub struct Sample {
+    rate: f64,
+    rng: SplitMix64,
+}
+
+impl UnaryPreAggOperator for Sample {
+    fn process_mut(&mut self, rec: Record) -> Result<Option<Record>, EvalError> {
+        if self.rng.next_f64() < self.rate {
+            Ok(Some(rec))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sampled(def: &SampleDef, rows: usize) -> Vec<usize> {
+        let mut op = def.build();
+        (0..rows)
+            .filter(|i| {
+                op.process_mut(Record::new(i.to_string()))
+                    .unwrap()
+                    .is_some()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn samples_at_the_rate() {
+        let kept = sampled(&SampleDef::new(0.1, None), 100_000).len();
+        assert!((9_000..11_000).contains(&kept), "kept {}", kept);
+        assert_eq!(sampled(&SampleDef::new(1.0, None), 100).len(), 100);
+    }
+
+    #[test]
+    fn seed_picks_the_same_rows() {
+        let def = SampleDef::new(0.5, Some(42));
+        assert_eq!(sampled(&def, 1000), sampled(&def, 1000));
+        assert_ne!(
+            sampled(&def, 1000),
+            sampled(&SampleDef::new(0.5, Some(43)), 1000)
+        );
+    }
+}
@@ -0,0 +1,134 @@
+//! Scripts run on each record.
+//!
+//! `script '<rhai>'` runs a [Rhai](https://rhai.rs) script for each record, with the record's
+//! fields in the object `rec` and the line in `line`.  The fields the script leaves in `rec` are
+//! the record's new fields, and setting `rec = ()` drops the record.  Scripts can't touch the
+//! filesystem or the network, and are stopped if they run too long on a record.
+use crate::data::{Record, Value};
+use crate::operator::{EvalError, UnaryPreAggFunction};
+use ordered_float::OrderedFloat;
+use rhai::{Dynamic, Engine, ParseError, Scope, AST};
+use std::sync::Arc;
+
+/// The number of operations a script can run for a record before it's stopped, so that a script
+/// that never ends doesn't hang the query.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+#[derive(Clone)]
+pub struct Script {
+    engine: Arc<Engine>,
+    ast: Arc<AST>,
+}
+
+impl Script {
+    pub fn new(source: &str) -> Result<Self, ParseError> {
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        // `print` and `debug` write to stderr so that they don't end up in the output.
+        engine.on_print(|s| eprintln!("{}", s));
+        engine.on_debug(|s, _, _| eprintln!("{}", s));
+        let ast = engine.compile(source)?;
+        Ok(Script {
+            engine: Arc::new(engine),
+            ast: Arc::new(ast),
+        })
+    }
+}
+
+fn to_dynamic(value: Value) -> Dynamic {
+    match value {
+        Value::Str(s) => s.into(),
+        Value::Int(i) => i.into(),
+        Value::Float(f) => f.0.into(),
+        Value::Bool(b) => b.into(),
+        Value::DateTime(_) | Value::Duration(_) => value.to_string().into(),
+        Value::Obj(o) => Dynamic::from_map(
+            o.into_iter()
+                .map(|(k, v)| (k.into(), to_dynamic(v)))
+                .collect(),
+        ),
+        Value::Array(a) => Dynamic::from_array(a.into_iter().map(to_dynamic).collect()),
+        Value::None => Dynamic::UNIT,
+    }
+}
+
+fn from_dynamic(value: Dynamic) -> Value {
+    let value = value.flatten();
+    if value.is_unit() {
+        Value::None
+    } else if let Ok(b) = value.as_bool() {
+        Value::Bool(b)
+    } else if let Ok(i) = value.as_int() {
+        Value::Int(i)
+    } else if let Ok(f) = value.as_float() {
+        Value::Float(OrderedFloat(f))
+    } else if value.is_array() {
+        Value::Array(
+            value
+                .cast::<rhai::Array>()
+                .into_iter()
+                .map(from_dynamic)
+                .collect(),
+        )
+    } else if value.is_map() {
+        Value::Obj(
+            value
+                .cast::<rhai::Map>()
+                .into_iter()
+                .map(|(k, v)| (k.to_string(), from_dynamic(v)))
+                .collect(),
+        )
+    } else {
+        Value::Str(value.to_string())
+    }
+}
+
+impl UnaryPreAggFunction for Script {
+    fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let mut scope = Scope::new();
+        scope.push(
+            "rec",
+            to_dynamic(Value::Obj(rec.data.into_iter().collect())),
+        );
+        scope.push_constant("line", rec.raw.clone());
+        self.engine
+            .run_ast_with_scope(&mut scope, &self.ast)
+            .map_err(|e| EvalError::FunctionFailed {
+                name: "script",
+                msg: e.to_string(),
+            })?;
+        match scope.remove::<Dynamic>("rec").map(from_dynamic) {
+            Some(Value::Obj(fields)) => Ok(Some(Record {
+                data: fields.into_iter().collect(),
+                raw: rec.raw,
+            })),
+            Some(Value::None) | None => Ok(None),
+            Some(other) => Err(EvalError::ExpectedXYZ {
+                expected: "rec to be an object of fields, or ()".to_string(),
+                found: other.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn updates_fields() {
+        let script = Script::new(r#"if rec.status >= 500 { rec.sev = "bad" }"#).unwrap();
+        let rec = Record::new("a").put("status", Value::Int(503));
+        let rec = script.process(rec).unwrap().unwrap();
+        assert_eq!(rec.data["sev"], Value::Str("bad".to_string()));
+        assert_eq!(rec.data["status"], Value::Int(503));
+        assert_eq!(rec.raw, "a");
+
+        let drop = Script::new("if line == \"b\" { rec = () }").unwrap();
+        assert!(drop.process(Record::new("b")).unwrap().is_none());
+        assert!(drop.process(Record::new("a")).unwrap().is_some());
+
+        let endless = Script::new("loop {}").unwrap();
+        assert!(endless.process(Record::new("a")).is_err());
+    }
+}
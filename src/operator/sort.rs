@@ -1,8 +1,13 @@
 use crate::data;
 use crate::data::{Aggregate, Record, Row};
-use crate::operator::{AggregateOperator, Data, EvalError, Expr};
+use crate::operator::spill::{data_size, SpillFile};
+use crate::operator::{AggregateOperator, Data, Expr};
 use std::cmp::Ordering;
-use std::collections::HashMap;
+use std::collections::{BinaryHeap, HashMap};
+use std::io;
+
+/// The number of rows that a live refresh shows once some of the rows have been moved to disk.
+const LIVE_ROWS: usize = 1000;
 
 #[derive(PartialEq, Eq)]
 pub enum SortDirection {
@@ -13,22 +18,38 @@ pub enum SortDirection {
 pub struct Sorter {
     columns: Vec<String>,
     state: Vec<Data>,
-    #[allow(clippy::type_complexity)]
-    ordering: Box<dyn Fn(&Data, &Data) -> Result<Ordering, EvalError> + Send + Sync>,
+    /// An estimate of the memory used by `state`, in bytes.
+    state_size: usize,
+    /// Once `state` uses more than this, it's sorted and written to a temporary file.
+    max_memory: Option<usize>,
+    /// The sorted rows that were moved out of memory, which are merged with `state` on emit.
+    runs: Vec<SpillFile>,
+    /// The first `LIVE_ROWS` rows of `runs`, so that live refreshes don't read them back.
+    runs_head: Vec<Data>,
+    exprs: Vec<Expr>,
     direction: SortDirection,
 }
 
 impl Sorter {
     pub fn new(exprs: Vec<Expr>, direction: SortDirection) -> Self {
-        let ordering = Box::new(Record::ordering(exprs));
         Sorter {
             state: Vec::new(),
+            state_size: 0,
+            max_memory: None,
+            runs: Vec::new(),
+            runs_head: Vec::new(),
             columns: Vec::new(),
             direction,
-            ordering,
+            exprs,
         }
     }
 
+    /// Spill the rows to disk when they take up more than `max_memory` bytes.
+    pub fn with_max_memory(mut self, max_memory: Option<usize>) -> Self {
+        self.max_memory = max_memory;
+        self
+    }
+
     fn new_columns(&self, data: &HashMap<String, data::Value>) -> Vec<String> {
         let mut new_keys: Vec<String> = data
             .keys()
@@ -38,49 +59,243 @@ impl Sorter {
         new_keys.sort();
         new_keys
     }
-}
 
-impl AggregateOperator for Sorter {
-    fn emit(&self) -> data::Aggregate {
-        let mut sorted_data = self.state.to_vec();
-        let order = &self.ordering;
+    fn compare(&self, l: &Data, r: &Data) -> Ordering {
         // To produce a deterministic sort, we should also sort by the non-key columns
         let second_ordering = Record::ordering_ref(&self.columns);
 
         // TODO: output errors here
-        if self.direction == SortDirection::Ascending {
-            sorted_data.sort_by(|l, r| {
-                ((order)(l, r))
-                    .unwrap_or(Ordering::Less)
-                    .then(second_ordering(l, r))
-            });
-        } else {
-            sorted_data.sort_by(|l, r| {
-                ((order)(r, l))
-                    .unwrap_or(Ordering::Less)
-                    .then(second_ordering(l, r))
-            });
+        for expr in &self.exprs {
+            // Rows where the value can't be evaluated go last, so that the order is consistent
+            // between the runs that are merged.
+            let ordering = match (expr.eval_value(l), expr.eval_value(r)) {
+                (Ok(l_val), Ok(r_val)) if self.direction == SortDirection::Ascending => {
+                    l_val.cmp(&r_val)
+                }
+                (Ok(l_val), Ok(r_val)) => r_val.cmp(&l_val),
+                (l_val, r_val) => l_val.is_err().cmp(&r_val.is_err()),
+            };
+            if ordering != Ordering::Equal {
+                return ordering;
+            }
+        }
+        second_ordering(l, r)
+    }
+
+    fn push(&mut self, data: Data) {
+        self.state_size += data_size(&data);
+        self.state.push(data);
+        if matches!(self.max_memory, Some(max) if self.state_size > max) {
+            if let Err(e) = self.spill() {
+                eprintln!("error: failed to write sorted rows to disk: {}", e);
+                // Keep the rows in memory rather than retrying on every row.
+                self.max_memory = None;
+            }
+        }
+    }
+
+    /// Sort the rows in memory and move them to a new run on disk.  Every row in the run has a
+    /// subset of the current columns, so the order of the run agrees with the final order,
+    /// even if more columns are found later.
+    fn spill(&mut self) -> io::Result<()> {
+        let mut state = std::mem::take(&mut self.state);
+        state.sort_by(|l, r| self.compare(l, r));
+        match SpillFile::with_rows(&state) {
+            Ok(run) => {
+                self.runs.push(run);
+                self.state_size = 0;
+                let mut runs_head = std::mem::take(&mut self.runs_head);
+                runs_head.extend(state.into_iter().take(LIVE_ROWS));
+                runs_head.sort_by(|l, r| self.compare(l, r));
+                runs_head.truncate(LIVE_ROWS);
+                self.runs_head = runs_head;
+                Ok(())
+            }
+            Err(e) => {
+                self.state = state;
+                Err(e)
+            }
+        }
+    }
+
+    /// Merge the runs on disk with the rows in memory, reading the runs a row at a time.
+    fn merged(&self) -> Merge<'_> {
+        let mut sources: Vec<Box<dyn Iterator<Item = Data>>> = Vec::new();
+        for run in &self.runs {
+            match run.read() {
+                Ok(rows) => sources.push(Box::new(rows)),
+                Err(e) => eprintln!("error: failed to read sorted rows from disk: {}", e),
+            }
         }
+        let mut in_memory = self.state.to_vec();
+        in_memory.sort_by(|l, r| self.compare(l, r));
+        sources.push(Box::new(in_memory.into_iter()));
+        Merge::new(self, sources)
+    }
+}
+
+impl AggregateOperator for Sorter {
+    fn emit(&self) -> data::Aggregate {
+        Aggregate {
+            data: self.merged().collect(),
+            columns: self.columns.clone(),
+        }
+    }
+
+    /// Once rows have been moved to disk, only the first `LIVE_ROWS` rows are shown, from the
+    /// rows in memory and the first rows of each run.
+    fn emit_live(&self) -> data::Aggregate {
+        if self.runs.is_empty() {
+            return self.emit();
+        }
+        let mut data: Vec<Data> = self.runs_head.iter().chain(&self.state).cloned().collect();
+        data.sort_by(|l, r| self.compare(l, r));
+        data.truncate(LIVE_ROWS);
         Aggregate {
-            data: sorted_data,
+            data,
             columns: self.columns.clone(),
         }
     }
 
     fn process(&mut self, row: Row) {
-        let order = &self.ordering;
         match row {
             Row::Aggregate(agg) => {
                 self.columns = agg.columns;
-                self.state = agg.data;
+                self.state.clear();
+                self.state_size = 0;
+                self.runs.clear();
+                self.runs_head.clear();
+                for data in agg.data {
+                    self.push(data);
+                }
             }
             Row::Record(rec) => {
                 let new_cols = self.new_columns(&rec.data);
-                self.state.push(rec.data);
-                self.state
-                    .sort_by(|l, r| ((order)(l, r)).unwrap_or(Ordering::Less));
                 self.columns.extend(new_cols);
+                self.push(rec.data);
             }
         }
     }
 }
+
+/// A k-way merge of sorted sources, which yields the rows in order as they're read.
+struct Merge<'a> {
+    sources: Vec<Box<dyn Iterator<Item = Data>>>,
+    heap: BinaryHeap<Head<'a>>,
+    sorter: &'a Sorter,
+}
+
+impl<'a> Merge<'a> {
+    fn new(sorter: &'a Sorter, mut sources: Vec<Box<dyn Iterator<Item = Data>>>) -> Self {
+        let mut heap = BinaryHeap::with_capacity(sources.len());
+        for (source, rows) in sources.iter_mut().enumerate() {
+            if let Some(data) = rows.next() {
+                heap.push(Head {
+                    data,
+                    source,
+                    sorter,
+                });
+            }
+        }
+        Merge {
+            sources,
+            heap,
+            sorter,
+        }
+    }
+}
+
+impl Iterator for Merge<'_> {
+    type Item = Data;
+
+    fn next(&mut self) -> Option<Data> {
+        let Head { data, source, .. } = self.heap.pop()?;
+        if let Some(next) = self.sources[source].next() {
+            self.heap.push(Head {
+                data: next,
+                source,
+                sorter: self.sorter,
+            });
+        }
+        Some(data)
+    }
+}
+
+/// The next row from one of the sources being merged, ordered so that the `BinaryHeap`, which
+/// pops the largest element, returns the row that comes first.  Ties go to the earlier source.
+struct Head<'a> {
+    data: Data,
+    source: usize,
+    sorter: &'a Sorter,
+}
+
+impl Ord for Head<'_> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.sorter
+            .compare(&self.data, &other.data)
+            .then(self.source.cmp(&other.source))
+            .reverse()
+    }
+}
+
+impl PartialOrd for Head<'_> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for Head<'_> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Head<'_> {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+
+    #[test]
+    fn spills_and_merges() {
+        let rows = |direction| {
+            let mut sorter =
+                Sorter::new(vec![Expr::column("n")], direction).with_max_memory(Some(300));
+            for i in 0..100 {
+                let rec = Record::new("")
+                    .put("n", Value::Int((i * 37) % 100))
+                    .put("i", Value::Int(i));
+                sorter.process(Row::Record(rec));
+            }
+            assert!(sorter.runs.len() > 1);
+            let sorted = sorter.emit().data;
+            sorted
+                .iter()
+                .map(|data| match data["n"] {
+                    Value::Int(n) => n,
+                    _ => panic!("expected an int"),
+                })
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(rows(SortDirection::Ascending), (0..100).collect::<Vec<_>>());
+        assert_eq!(
+            rows(SortDirection::Descending),
+            (0..100).rev().collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn live_rows_after_spilling() {
+        let mut sorter = Sorter::new(vec![Expr::column("n")], SortDirection::Ascending)
+            .with_max_memory(Some(3000));
+        for i in 0..3000 {
+            let rec = Record::new("").put("n", Value::Int((i * 37) % 3000));
+            sorter.process(Row::Record(rec));
+        }
+        assert!(sorter.runs.len() > 1);
+        let live = sorter.emit_live();
+        assert_eq!(live.data.len(), LIVE_ROWS);
+        assert_eq!(live.data, sorter.emit().data[..LIVE_ROWS]);
+    }
+}
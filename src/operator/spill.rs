@@ -0,0 +1,194 @@
+use crate::data;
+use crate::operator::Data;
+use chrono::{DateTime, Duration, Utc};
+use ordered_float::OrderedFloat;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+/// A temporary file that an operator moves rows to when they'd otherwise use more than
+/// `--max-memory`, with one JSON object per line.  Values are tagged with their type so
+/// that they're read back exactly as they were written.  The file is removed when it's dropped.
+pub struct SpillFile {
+    path: PathBuf,
+    out: Mutex<BufWriter<File>>,
+}
+
+impl SpillFile {
+    pub fn create() -> io::Result<Self> {
+        static FILES: AtomicUsize = AtomicUsize::new(0);
+        let path = std::env::temp_dir().join(format!(
+            "agrind-spill-{}-{}",
+            std::process::id(),
+            FILES.fetch_add(1, Ordering::Relaxed)
+        ));
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(SpillFile {
+            path,
+            out: Mutex::new(BufWriter::new(file)),
+        })
+    }
+
+    /// Write the rows to a new file.
+    pub fn with_rows<'a>(rows: impl IntoIterator<Item = &'a Data>) -> io::Result<Self> {
+        let mut file = SpillFile::create()?;
+        for data in rows {
+            file.append(data)?;
+        }
+        Ok(file)
+    }
+
+    pub fn append(&mut self, data: &Data) -> io::Result<()> {
+        let encoded: serde_json::Map<_, _> =
+            data.iter().map(|(k, v)| (k.clone(), encode(v))).collect();
+        let out = self.out.get_mut().expect("spill file lock poisoned");
+        serde_json::to_writer(&mut *out, &encoded)?;
+        out.write_all(b"\n")
+    }
+
+    /// Read back the rows written so far.  A row that can't be read ends the rows, with an
+    /// error printed.
+    pub fn read(&self) -> io::Result<impl Iterator<Item = Data>> {
+        self.out.lock().expect("spill file lock poisoned").flush()?;
+        let lines = BufReader::new(File::open(&self.path)?).lines();
+        Ok(lines.map_while(|line| {
+            let decoded = line
+                .map_err(|e| e.to_string())
+                .and_then(|line| serde_json::from_str(&line).map_err(|e| e.to_string()))
+                .and_then(|row: serde_json::Map<String, serde_json::Value>| {
+                    row.into_iter()
+                        .map(|(k, v)| Ok((k, decode(v)?)))
+                        .collect::<Result<Data, String>>()
+                });
+            decoded
+                .map_err(|e| eprintln!("error: failed to read rows back from disk: {}", e))
+                .ok()
+        }))
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// A rough estimate of the memory used by a row.
+pub fn data_size(data: &Data) -> usize {
+    data.iter()
+        .map(|(k, v)| std::mem::size_of::<String>() + k.len() + value_size(v))
+        .sum()
+}
+
+/// A rough estimate of the memory used by a value, including what it points to.
+pub fn value_size(value: &data::Value) -> usize {
+    let heap = match value {
+        data::Value::Str(s) => s.len(),
+        data::Value::Obj(o) => o
+            .iter()
+            .map(|(k, v)| std::mem::size_of::<String>() + k.len() + value_size(v))
+            .sum(),
+        data::Value::Array(a) => a.iter().map(value_size).sum(),
+        _ => 0,
+    };
+    std::mem::size_of::<data::Value>() + heap
+}
+
+fn encode(value: &data::Value) -> serde_json::Value {
+    use serde_json::json;
+    match value {
+        data::Value::Str(s) => json!({ "s": s }),
+        data::Value::Int(i) => json!({ "i": i }),
+        // The bits are written since JSON numbers can't be NaN or infinite.
+        data::Value::Float(f) => json!({ "f": f.0.to_bits() }),
+        data::Value::Bool(b) => json!({ "b": b }),
+        data::Value::DateTime(dt) => json!({ "t": dt.to_rfc3339() }),
+        data::Value::Duration(d) => {
+            let secs = d.num_seconds();
+            let nanos = (*d - Duration::seconds(secs))
+                .num_nanoseconds()
+                .unwrap_or(0);
+            json!({ "d": [secs, nanos] })
+        }
+        data::Value::Obj(o) => json!({
+            "o": o.iter().map(|(k, v)| (k.clone(), encode(v))).collect::<serde_json::Map<_, _>>()
+        }),
+        data::Value::Array(a) => json!({ "a": a.iter().map(encode).collect::<Vec<_>>() }),
+        data::Value::None => serde_json::Value::Null,
+    }
+}
+
+fn decode(value: serde_json::Value) -> Result<data::Value, String> {
+    let invalid = |v: &serde_json::Value| format!("invalid value: {}", v);
+    let (tag, inner) = match value {
+        serde_json::Value::Null => return Ok(data::Value::None),
+        serde_json::Value::Object(ref o) if o.len() == 1 => o.iter().next().unwrap(),
+        ref other => return Err(invalid(other)),
+    };
+    let decoded = match (tag.as_str(), inner) {
+        ("s", serde_json::Value::String(s)) => Some(data::Value::Str(s.clone())),
+        ("i", i) => i.as_i64().map(data::Value::Int),
+        ("f", f) => f
+            .as_u64()
+            .map(|bits| data::Value::Float(OrderedFloat(f64::from_bits(bits)))),
+        ("b", b) => b.as_bool().map(data::Value::Bool),
+        ("t", serde_json::Value::String(t)) => DateTime::parse_from_rfc3339(t)
+            .ok()
+            .map(|dt| data::Value::DateTime(dt.with_timezone(&Utc))),
+        ("d", serde_json::Value::Array(d)) => match d.as_slice() {
+            [secs, nanos] => secs.as_i64().zip(nanos.as_i64()).map(|(s, n)| {
+                data::Value::Duration(Duration::seconds(s) + Duration::nanoseconds(n))
+            }),
+            _ => None,
+        },
+        ("o", serde_json::Value::Object(o)) => Some(data::Value::Obj(
+            o.iter()
+                .map(|(k, v)| Ok((k.clone(), decode(v.clone())?)))
+                .collect::<Result<_, String>>()?,
+        )),
+        ("a", serde_json::Value::Array(a)) => Some(data::Value::Array(
+            a.iter()
+                .map(|v| decode(v.clone()))
+                .collect::<Result<_, _>>()?,
+        )),
+        _ => None,
+    };
+    decoded.ok_or_else(|| invalid(&value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+    use std::str::FromStr;
+
+    #[test]
+    fn reads_back_values_exactly() {
+        let values = vec![
+            Value::from_string("a"),
+            Value::Int(-3),
+            Value::Float(OrderedFloat(f64::NAN)),
+            Value::Float(OrderedFloat(0.1)),
+            Value::Bool(true),
+            Value::DateTime(DateTime::<Utc>::from_str("2021-08-11T01:02:03.456789Z").unwrap()),
+            Value::Duration(Duration::milliseconds(-1500)),
+            Value::Obj(im::hashmap! {"k".to_string() => Value::Array(vec![Value::None])}),
+            Value::None,
+        ];
+        let rows: Vec<Data> = values
+            .into_iter()
+            .enumerate()
+            .map(|(i, v)| vec![(i.to_string(), v)].into_iter().collect())
+            .collect();
+        let mut file = SpillFile::with_rows(&rows[..2]).unwrap();
+        for row in &rows[2..] {
+            file.append(row).unwrap();
+        }
+        assert_eq!(file.read().unwrap().collect::<Vec<_>>(), rows);
+    }
+}
@@ -54,16 +54,29 @@ fn split_once<'a>(s: &'a str, p: &'a str) -> (&'a str, &'a str) {
     (split_iter.next().unwrap(), split_iter.next().unwrap_or(""))
 }
 
-/// split function that respects delimiters and strips whitespace
+/// split function that respects delimiters and strips whitespace.  When `max_splits` is given,
+/// splitting stops after that many tokens and the remainder of the input, including any separators
+/// it contains, becomes the final token.
 pub fn split_with_delimiters<'a>(
     input: &'a str,
     separator: &'a str,
+    max_splits: Option<usize>,
     delimiters: &HashMap<&'static str, &'static str>,
 ) -> Vec<&'a str> {
     let mut wip = input;
     let mut ret: Vec<&'a str> = vec![];
 
     while !wip.is_empty() {
+        if max_splits == Some(ret.len()) {
+            // A quoted token leaves its trailing separator behind
+            let rest = wip.trim_start();
+            let rest = rest.strip_prefix(separator).unwrap_or(rest).trim();
+            if !rest.is_empty() {
+                ret.push(rest);
+            }
+            break;
+        }
+
         // Look for a leading quote
         let leading_delimiter = delimiters
             .iter()
@@ -117,21 +130,22 @@ mod tests {
     #[test]
     fn split_works() {
         assert_eq!(
-            split_with_delimiters("power hello", " ", &DEFAULT_DELIMITERS),
+            split_with_delimiters("power hello", " ", None, &DEFAULT_DELIMITERS),
             vec!["power", "hello"],
         );
         assert_eq!(
-            split_with_delimiters("morecomplicated", "ecomp", &DEFAULT_DELIMITERS),
+            split_with_delimiters("morecomplicated", "ecomp", None, &DEFAULT_DELIMITERS),
             vec!["mor", "licated"],
         );
         assert_eq!(
-            split_with_delimiters("owmmowmow", "ow", &DEFAULT_DELIMITERS),
+            split_with_delimiters("owmmowmow", "ow", None, &DEFAULT_DELIMITERS),
             vec!["mm", "m"],
         );
         assert_eq!(
             split_with_delimiters(
                 r#"Oct 09 20:22:21 web-001 influxd[188053]: 127.0.0.1 "POST /write \"escaped\" HTTP/1.0" 204"#,
                 " ",
+                None,
                 &DEFAULT_DELIMITERS
             ),
             vec![
@@ -150,19 +164,48 @@ mod tests {
     #[test]
     fn split_with_closures_works() {
         assert_eq!(
-            split_with_delimiters("power hello \"good bye\"", " ", &DEFAULT_DELIMITERS),
+            split_with_delimiters("power hello \"good bye\"", " ", None, &DEFAULT_DELIMITERS),
             vec!["power", "hello", "good bye"],
         );
         assert_eq!(
-            split_with_delimiters("more'ecomp'licated", "ecomp", &DEFAULT_DELIMITERS),
+            split_with_delimiters("more'ecomp'licated", "ecomp", None, &DEFAULT_DELIMITERS),
             vec!["more'", "'licated"],
         );
         assert_eq!(
-            split_with_delimiters("ow\"mm\"ow'\"mow\"'", "ow", &DEFAULT_DELIMITERS),
+            split_with_delimiters("ow\"mm\"ow'\"mow\"'", "ow", None, &DEFAULT_DELIMITERS),
             vec!["mm", "\"mow\""],
         );
     }
 
+    #[test]
+    fn split_with_max_splits() {
+        assert_eq!(
+            split_with_delimiters(
+                "2023-01-01 ERROR disk full: /dev/sda1 at 100%",
+                " ",
+                Some(2),
+                &DEFAULT_DELIMITERS
+            ),
+            vec!["2023-01-01", "ERROR", "disk full: /dev/sda1 at 100%"],
+        );
+        assert_eq!(
+            split_with_delimiters("a,b,c,d", ",", Some(1), &DEFAULT_DELIMITERS),
+            vec!["a", "b,c,d"],
+        );
+        assert_eq!(
+            split_with_delimiters("a,\"b,c\",d,e", ",", Some(2), &DEFAULT_DELIMITERS),
+            vec!["a", "b,c", "d,e"],
+        );
+        assert_eq!(
+            split_with_delimiters("a,b", ",", Some(5), &DEFAULT_DELIMITERS),
+            vec!["a", "b"],
+        );
+        assert_eq!(
+            split_with_delimiters("a,b", ",", Some(0), &DEFAULT_DELIMITERS),
+            vec!["a,b"],
+        );
+    }
+
     // see https://github.com/rcoh/angle-grinder/issues/138
     #[test]
     fn split_with_wide_characters() {
@@ -170,6 +213,7 @@ mod tests {
             split_with_delimiters(
                 r#""Bourgogne-Franche-Comté" hello"#,
                 " ",
+                None,
                 &DEFAULT_DELIMITERS
             ),
             vec!["Bourgogne-Franche-Comté", "hello"]
@@ -180,14 +224,21 @@ mod tests {
 #[derive(Clone)]
 pub struct Split {
     separator: String,
+    max_splits: Option<usize>,
     input_column: Option<Expr>,
     output_column: Option<Expr>,
 }
 
 impl Split {
-    pub fn new(separator: String, input_column: Option<Expr>, output_column: Option<Expr>) -> Self {
+    pub fn new(
+        separator: String,
+        max_splits: Option<usize>,
+        input_column: Option<Expr>,
+        output_column: Option<Expr>,
+    ) -> Self {
         Self {
             separator,
+            max_splits,
             input_column,
             output_column,
         }
@@ -197,10 +248,11 @@ impl Split {
 impl UnaryPreAggFunction for Split {
     fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
         let inp = operator::get_input(&rec, &self.input_column)?;
-        let array = split_with_delimiters(&inp, &self.separator, &DEFAULT_DELIMITERS)
-            .into_iter()
-            .map(data::Value::from_string)
-            .collect();
+        let array =
+            split_with_delimiters(&inp, &self.separator, self.max_splits, &DEFAULT_DELIMITERS)
+                .into_iter()
+                .map(data::Value::from_string)
+                .collect();
         let rec = if let Some(output_column) = &self.output_column {
             rec.put_expr(output_column, data::Value::Array(array))?
         } else {
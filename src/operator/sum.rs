@@ -1,23 +1,36 @@
 use crate::data;
 use crate::operator::expr::Expr;
 use crate::operator::{AggregateFunction, Data, EvalError, Evaluate};
+use ordered_float::OrderedFloat;
 
 pub struct Sum {
     total: f64,
     column: Expr,
+    /// Only the rows that meet the condition are added up, as in `sum_if(bytes, status == 200)`.
+    condition: Option<Expr>,
 }
 
 impl Sum {
     pub fn empty<T: Into<Expr>>(column: T) -> Self {
+        Sum::new(column.into(), None)
+    }
+
+    pub fn new(column: Expr, condition: Option<Expr>) -> Self {
         Sum {
             total: 0.0,
-            column: column.into(),
+            column,
+            condition,
         }
     }
 }
 
 impl AggregateFunction for Sum {
     fn process(&mut self, rec: &Data) -> Result<(), EvalError> {
+        if let Some(condition) = &self.condition {
+            if !condition.eval(rec)? {
+                return Ok(());
+            }
+        }
         let value: f64 = self.column.eval(rec)?;
         self.total += value;
         Ok(())
@@ -28,6 +41,16 @@ impl AggregateFunction for Sum {
     }
 
     fn empty_box(&self) -> Box<dyn AggregateFunction> {
-        Box::new(Sum::empty(self.column.clone()))
+        Box::new(Sum::new(self.column.clone(), self.condition.clone()))
+    }
+
+    fn state(&self) -> data::Value {
+        data::Value::Float(OrderedFloat(self.total))
+    }
+
+    fn merge(&mut self, state: data::Value) {
+        if let data::Value::Float(OrderedFloat(total)) = state {
+            self.total += total;
+        }
     }
 }
@@ -0,0 +1,409 @@
+use crate::data::{Record, Value};
+use crate::operator::{EvalError, Expr, UnaryPreAggFunction};
+use crate::{data, operator};
+use chrono::{DateTime, Datelike, Duration, NaiveDateTime, Utc};
+
+/// The framing of a syslog message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// The legacy BSD format, e.g. `<34>Oct 11 22:14:15 mymachine su[123]: 'su root' failed`.
+    Rfc3164,
+    /// The structured format, e.g.
+    /// `<165>1 2003-10-11T22:14:15.003Z mymachine evntslog - ID47 [exampleSDID@32473 iut="3"] msg`.
+    Rfc5424,
+}
+
+impl Format {
+    /// The fields a message in this format is parsed into.
+    pub fn fields(self) -> &'static [&'static str] {
+        match self {
+            Format::Rfc3164 => &[
+                "priority",
+                "facility",
+                "severity",
+                "timestamp",
+                "hostname",
+                "app_name",
+                "procid",
+                "message",
+            ],
+            Format::Rfc5424 => &[
+                "priority",
+                "facility",
+                "severity",
+                "version",
+                "timestamp",
+                "hostname",
+                "app_name",
+                "procid",
+                "msgid",
+                "structured_data",
+                "message",
+            ],
+        }
+    }
+}
+
+const FACILITIES: &[&str] = &[
+    "kern",
+    "user",
+    "mail",
+    "daemon",
+    "auth",
+    "syslog",
+    "lpr",
+    "news",
+    "uucp",
+    "cron",
+    "authpriv",
+    "ftp",
+    "ntp",
+    "security",
+    "console",
+    "solaris-cron",
+    "local0",
+    "local1",
+    "local2",
+    "local3",
+    "local4",
+    "local5",
+    "local6",
+    "local7",
+];
+
+const SEVERITIES: &[&str] = &[
+    "emerg", "alert", "crit", "err", "warning", "notice", "info", "debug",
+];
+
+type Fields = Vec<(&'static str, Value)>;
+
+/// Parse the `<PRI>` at the start of a message into the priority, facility, and severity.
+fn parse_priority(line: &str) -> Option<(Fields, &str)> {
+    let rest = line.strip_prefix('<')?;
+    let end = rest.find('>')?;
+    let digits = &rest[..end];
+    if digits.is_empty() || digits.len() > 3 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let priority: usize = digits.parse().ok()?;
+    let facility = FACILITIES.get(priority / 8)?;
+    let fields = vec![
+        ("priority", Value::Int(priority as i64)),
+        ("facility", Value::Str(facility.to_string())),
+        ("severity", Value::Str(SEVERITIES[priority % 8].to_string())),
+    ];
+    Some((fields, &rest[end + 1..]))
+}
+
+/// Split off the next space-terminated token.
+fn next_token(s: &str) -> Option<(&str, &str)> {
+    let end = s.find(' ')?;
+    if end == 0 {
+        return None;
+    }
+    Some((&s[..end], &s[end + 1..]))
+}
+
+/// The value of a header field, which is `-` when it's missing.
+fn nil_or(token: &str) -> Value {
+    if token == "-" {
+        Value::None
+    } else {
+        Value::from_string(token)
+    }
+}
+
+/// Parse a timestamp like `Oct 11 22:14:15`, which doesn't include a year or time zone.  It's
+/// taken to be UTC and in the most recent year that doesn't put it more than a day in the future.
+fn parse_bsd_timestamp(ts: &str, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+    let parse = |year: i32| {
+        NaiveDateTime::parse_from_str(&format!("{} {}", year, ts), "%Y %b %e %H:%M:%S")
+            .ok()
+            .map(|ts| ts.and_utc())
+    };
+    match parse(now.year())? {
+        ts if ts > now + Duration::days(1) => parse(now.year() - 1),
+        ts => Some(ts),
+    }
+}
+
+fn parse_rfc3164(line: &str, now: DateTime<Utc>) -> Option<Fields> {
+    // Messages read from log files usually don't include the priority.
+    let (mut fields, rest) = match parse_priority(line) {
+        Some((fields, rest)) => (fields, rest),
+        None => (
+            vec![
+                ("priority", Value::None),
+                ("facility", Value::None),
+                ("severity", Value::None),
+            ],
+            line,
+        ),
+    };
+    let timestamp = parse_bsd_timestamp(rest.get(..15)?, now)?;
+    let (hostname, rest) = next_token(rest[15..].strip_prefix(' ')?)?;
+    // The tag is the name of the program, optionally followed by its PID in brackets, then a
+    // colon.  Without the colon, there's no tag and the rest is all message.
+    let tag_end = rest.find([':', '[', ' ']).unwrap_or(rest.len());
+    let (app_name, procid, message) = match rest[tag_end..].chars().next() {
+        Some('[') => {
+            let (procid, after) = rest[tag_end + 1..].split_once(']')?;
+            let message = after.strip_prefix(':').unwrap_or(after);
+            (
+                Value::from_string(&rest[..tag_end]),
+                Value::from_string(procid),
+                message,
+            )
+        }
+        Some(':') if tag_end > 0 => (
+            Value::from_string(&rest[..tag_end]),
+            Value::None,
+            &rest[tag_end + 1..],
+        ),
+        _ => (Value::None, Value::None, rest),
+    };
+    let message = message.strip_prefix(' ').unwrap_or(message);
+    fields.extend([
+        ("timestamp", Value::DateTime(timestamp)),
+        ("hostname", Value::from_string(hostname)),
+        ("app_name", app_name),
+        ("procid", procid),
+        ("message", Value::Str(message.to_string())),
+    ]);
+    Some(fields)
+}
+
+/// Parse the structured data elements of an RFC 5424 message, e.g.
+/// `[exampleSDID@32473 iut="3" eventSource="Application"]`, into an object keyed by their IDs.
+fn parse_structured_data(s: &str) -> Option<(Value, &str)> {
+    if let Some(rest) = s.strip_prefix('-') {
+        return Some((Value::None, rest));
+    }
+    let mut elements = im::HashMap::new();
+    let mut rest = s;
+    while let Some(element) = rest.strip_prefix('[') {
+        let id_end = element.find([' ', ']'])?;
+        let id = &element[..id_end];
+        if id.is_empty() {
+            return None;
+        }
+        rest = &element[id_end..];
+        let mut params = im::HashMap::new();
+        loop {
+            if let Some(after) = rest.strip_prefix(']') {
+                rest = after;
+                break;
+            }
+            let (name, quoted) = rest.strip_prefix(' ')?.split_once('=')?;
+            let quoted = quoted.strip_prefix('"')?;
+            let mut value = String::new();
+            let mut chars = quoted.char_indices();
+            let end = loop {
+                match chars.next()? {
+                    (_, '\\') => match chars.next()? {
+                        (_, c @ ('"' | '\\' | ']')) => value.push(c),
+                        (_, c) => {
+                            value.push('\\');
+                            value.push(c);
+                        }
+                    },
+                    (i, '"') => break i,
+                    (_, c) => value.push(c),
+                }
+            };
+            params.insert(name.to_string(), Value::Str(value));
+            rest = &quoted[end + 1..];
+        }
+        elements.insert(id.to_string(), Value::Obj(params));
+    }
+    if elements.is_empty() {
+        None
+    } else {
+        Some((Value::Obj(elements), rest))
+    }
+}
+
+fn parse_rfc5424(line: &str) -> Option<Fields> {
+    let (mut fields, rest) = parse_priority(line)?;
+    let (version, rest) = next_token(rest)?;
+    let version: i64 = version.parse().ok().filter(|v| (1..=999).contains(v))?;
+    let (timestamp, rest) = next_token(rest)?;
+    let timestamp = match timestamp {
+        "-" => Value::None,
+        ts => Value::DateTime(DateTime::parse_from_rfc3339(ts).ok()?.into()),
+    };
+    let (hostname, rest) = next_token(rest)?;
+    let (app_name, rest) = next_token(rest)?;
+    let (procid, rest) = next_token(rest)?;
+    let (msgid, rest) = next_token(rest)?;
+    let (structured_data, rest) = parse_structured_data(rest)?;
+    let message = match rest.strip_prefix(' ') {
+        Some(message) => Value::Str(message.trim_start_matches('\u{feff}').to_string()),
+        None if rest.is_empty() => Value::None,
+        None => return None,
+    };
+    fields.extend([
+        ("version", Value::Int(version)),
+        ("timestamp", timestamp),
+        ("hostname", nil_or(hostname)),
+        ("app_name", nil_or(app_name)),
+        ("procid", nil_or(procid)),
+        ("msgid", nil_or(msgid)),
+        ("structured_data", structured_data),
+        ("message", message),
+    ]);
+    Some(fields)
+}
+
+/// Parse a syslog message.  Without a format, RFC 5424 is detected by the version number that
+/// follows the priority.
+pub fn parse_syslog(line: &str, format: Option<Format>, now: DateTime<Utc>) -> Option<Fields> {
+    let format = format.unwrap_or_else(|| {
+        let is_rfc5424 = parse_priority(line)
+            .and_then(|(_, rest)| next_token(rest))
+            .is_some_and(|(version, _)| version.bytes().all(|b| b.is_ascii_digit()));
+        if is_rfc5424 {
+            Format::Rfc5424
+        } else {
+            Format::Rfc3164
+        }
+    });
+    match format {
+        Format::Rfc3164 => parse_rfc3164(line, now),
+        Format::Rfc5424 => parse_rfc5424(line),
+    }
+}
+
+#[derive(Clone)]
+pub struct ParseSyslog {
+    input_column: Option<Expr>,
+    format: Option<Format>,
+    drop_nonmatching: bool,
+}
+
+impl ParseSyslog {
+    pub fn new(input_column: Option<Expr>, format: Option<Format>, drop_nonmatching: bool) -> Self {
+        ParseSyslog {
+            input_column,
+            format,
+            drop_nonmatching,
+        }
+    }
+}
+
+impl UnaryPreAggFunction for ParseSyslog {
+    fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let fields = {
+            let inp = operator::get_input(&rec, &self.input_column)?;
+            parse_syslog(inp.trim_end_matches(['\r', '\n']), self.format, Utc::now())
+        };
+        match fields {
+            Some(fields) => {
+                let mut rec = rec;
+                for (field, value) in fields {
+                    rec.put_mut(field, value);
+                }
+                Ok(Some(rec))
+            }
+            None if self.drop_nonmatching => Ok(None),
+            None => {
+                let mut rec = rec;
+                for field in self.format.unwrap_or(Format::Rfc3164).fields() {
+                    if !rec.data.contains_key(*field) {
+                        rec.put_mut(*field, data::Value::None);
+                    }
+                }
+                Ok(Some(rec))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn parse(line: &str) -> Option<Vec<(&'static str, String)>> {
+        let now = Utc.with_ymd_and_hms(2024, 1, 15, 12, 0, 0).unwrap();
+        parse_syslog(line, None, now).map(|fields| {
+            fields
+                .into_iter()
+                .map(|(name, value)| match value {
+                    Value::Obj(_) => (name, serde_json::to_string(&value).unwrap()),
+                    value => (name, value.to_string()),
+                })
+                .collect()
+        })
+    }
+
+    #[test]
+    fn parses_rfc3164() {
+        assert_eq!(
+            parse("<34>Jan 11 22:14:15 mymachine su[123]: 'su root' failed"),
+            Some(vec![
+                ("priority", "34".to_string()),
+                ("facility", "auth".to_string()),
+                ("severity", "crit".to_string()),
+                ("timestamp", "2024-01-11T22:14:15Z".to_string()),
+                ("hostname", "mymachine".to_string()),
+                ("app_name", "su".to_string()),
+                ("procid", "123".to_string()),
+                ("message", "'su root' failed".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn rfc3164_without_priority_or_tag() {
+        let fields = parse("Dec  3 01:02:03 host kernel: oops").unwrap();
+        assert_eq!(fields[0], ("priority", "None".to_string()));
+        // December is in the future, so the message must be from last year.
+        assert_eq!(fields[3], ("timestamp", "2023-12-03T01:02:03Z".to_string()));
+        assert_eq!(fields[5], ("app_name", "kernel".to_string()));
+        assert_eq!(fields[7], ("message", "oops".to_string()));
+
+        let fields = parse("Jan  3 01:02:03 host just a message").unwrap();
+        assert_eq!(fields[5], ("app_name", "None".to_string()));
+        assert_eq!(fields[7], ("message", "just a message".to_string()));
+    }
+
+    #[test]
+    fn parses_rfc5424() {
+        let fields = parse(
+            r#"<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut="3" eventSource="App \"x\""][other@1] An application event"#,
+        )
+        .unwrap();
+        let fields: Vec<_> = fields
+            .iter()
+            .map(|(name, value)| (*name, value.as_str()))
+            .collect();
+        assert_eq!(
+            fields,
+            vec![
+                ("priority", "165"),
+                ("facility", "local4"),
+                ("severity", "notice"),
+                ("version", "1"),
+                ("timestamp", "2003-10-11T22:14:15.003Z"),
+                ("hostname", "mymachine.example.com"),
+                ("app_name", "evntslog"),
+                ("procid", "None"),
+                ("msgid", "ID47"),
+                (
+                    "structured_data",
+                    r#"{"exampleSDID@32473":{"eventSource":"App \"x\"","iut":"3"},"other@1":{}}"#
+                ),
+                ("message", "An application event"),
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_malformed_messages() {
+        assert_eq!(parse("<999>Jan 11 22:14:15 host app: msg"), None);
+        assert_eq!(parse("not syslog at all"), None);
+        assert_eq!(parse("<13>1 not-a-date host app - - - msg"), None);
+        assert_eq!(parse("<13>1 - host app - - [unterminated"), None);
+    }
+}
@@ -1,11 +1,45 @@
 use crate::data;
 use crate::data::Record;
-use crate::operator::{EvalError, Expr, UnaryPreAggFunction};
-use chrono::DurationRound;
+use crate::funcs;
+use crate::operator::{EvalError, Expr, OperatorBuilder, UnaryPreAggFunction, UnaryPreAggOperator};
+use chrono::{DateTime, DurationRound, Utc};
+use itertools::{Itertools, MinMaxResult};
+use std::convert::TryFrom;
+use std::iter;
+
+/// Get the timestamp to slice from a value.  Strings are parsed with the given `strftime`
+/// format, or like `parseDate()` if there isn't one.
+pub(crate) fn timestamp(
+    value: &data::Value,
+    format: Option<&str>,
+) -> Result<DateTime<Utc>, EvalError> {
+    let parsed = match (value, format) {
+        (data::Value::DateTime(dt), _) => Some(*dt),
+        (data::Value::Str(s), Some(format)) => match funcs::parse_date_format(s, format) {
+            Ok(data::Value::DateTime(dt)) => Some(dt),
+            _ => None,
+        },
+        (data::Value::Str(s), None) => match funcs::parse_date(s) {
+            Ok(data::Value::DateTime(dt)) => Some(dt),
+            _ => None,
+        },
+        _ => None,
+    };
+    parsed.ok_or_else(|| match format {
+        Some(format) => EvalError::ExpectedDateFormat {
+            format: format.to_string(),
+            found: value.to_string(),
+        },
+        None => EvalError::ExpectedDate {
+            found: value.to_string(),
+        },
+    })
+}
 
 #[derive(Clone)]
 pub struct Timeslice {
     input_column: Expr,
+    format: Option<String>,
     duration: chrono::Duration,
     output_column: Option<String>,
 }
@@ -13,11 +47,13 @@ pub struct Timeslice {
 impl Timeslice {
     pub fn new(
         input_column: Expr,
+        format: Option<String>,
         duration: chrono::Duration,
         output_column: Option<String>,
     ) -> Self {
         Self {
             input_column,
+            format,
             duration,
             output_column,
         }
@@ -27,26 +63,217 @@ impl Timeslice {
 impl UnaryPreAggFunction for Timeslice {
     fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
         let inp = self.input_column.eval_value(&rec.data)?;
+        let dt = timestamp(inp.as_ref(), self.format.as_deref())?;
+        let rounded = dt
+            .duration_trunc(self.duration)
+            .map_err(|e| EvalError::InvalidDuration {
+                error: format!("{:?}", e),
+            })?;
+        let rec = rec.put(
+            self.output_column
+                .clone()
+                .unwrap_or_else(|| "_timeslice".to_string()),
+            data::Value::DateTime(rounded),
+        );
+
+        Ok(Some(rec))
+    }
+}
+
+/// Slice durations that an automatic timeslice can pick from, smallest first.
+const AUTO_DURATIONS: &[fn() -> chrono::Duration] = &[
+    || chrono::Duration::milliseconds(1),
+    || chrono::Duration::milliseconds(10),
+    || chrono::Duration::milliseconds(100),
+    || chrono::Duration::seconds(1),
+    || chrono::Duration::seconds(5),
+    || chrono::Duration::seconds(10),
+    || chrono::Duration::seconds(30),
+    || chrono::Duration::minutes(1),
+    || chrono::Duration::minutes(5),
+    || chrono::Duration::minutes(10),
+    || chrono::Duration::minutes(30),
+    || chrono::Duration::hours(1),
+    || chrono::Duration::hours(3),
+    || chrono::Duration::hours(6),
+    || chrono::Duration::hours(12),
+    || chrono::Duration::days(1),
+    || chrono::Duration::weeks(1),
+];
+
+/// Pick the smallest of the `AUTO_DURATIONS` that divides `span` into at most `buckets` slices.
+/// Spans too long for a week to be enough use a whole number of weeks.  More buckets than fit in
+/// an `i32` are as many as make no difference.
+pub fn auto_duration(span: chrono::Duration, buckets: usize) -> chrono::Duration {
+    let buckets = i32::try_from(buckets.max(1)).unwrap_or(i32::MAX);
+    AUTO_DURATIONS
+        .iter()
+        .map(|duration| duration())
+        // A product too long to represent is longer than any span.
+        .find(|duration| duration.checked_mul(buckets).is_none_or(|all| all > span))
+        .unwrap_or_else(|| chrono::Duration::weeks(span.num_weeks() / i64::from(buckets) + 1))
+}
+
+/// The definition for a timeslice whose duration is chosen from the span of the timestamps.
+pub struct AutoTimesliceDef {
+    input_column: Expr,
+    format: Option<String>,
+    buckets: usize,
+    output_column: Option<String>,
+}
 
-        match inp.as_ref() {
-            data::Value::DateTime(dt) => {
-                let rounded =
-                    dt.duration_trunc(self.duration)
-                        .map_err(|e| EvalError::InvalidDuration {
-                            error: format!("{:?}", e),
-                        })?;
-                let rec = rec.put(
-                    self.output_column
-                        .clone()
-                        .unwrap_or_else(|| "_timeslice".to_string()),
-                    data::Value::DateTime(rounded),
-                );
-
-                Ok(Some(rec))
-            }
-            _ => Err(EvalError::ExpectedDate {
-                found: inp.to_string(),
-            }),
+impl AutoTimesliceDef {
+    pub fn new(
+        input_column: Expr,
+        format: Option<String>,
+        buckets: usize,
+        output_column: Option<String>,
+    ) -> Self {
+        Self {
+            input_column,
+            format,
+            buckets,
+            output_column,
         }
     }
 }
+
+impl OperatorBuilder for AutoTimesliceDef {
+    fn build(&self) -> Box<dyn UnaryPreAggOperator> {
+        Box::new(AutoTimeslice {
+            input_column: self.input_column.clone(),
+            format: self.format.clone(),
+            buckets: self.buckets,
+            output_column: self.output_column.clone(),
+            records: Vec::new(),
+        })
+    }
+
+    fn parallel(&self) -> bool {
+        false
+    }
+}
+
+/// The state for an automatic timeslice.  The span of the timestamps isn't known until all of
+/// the input has been seen, so records are held until the operator is drained.
+pub struct AutoTimeslice {
+    input_column: Expr,
+    format: Option<String>,
+    buckets: usize,
+    output_column: Option<String>,
+    records: Vec<(Record, DateTime<Utc>)>,
+}
+
+impl UnaryPreAggOperator for AutoTimeslice {
+    fn process_mut(&mut self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let dt = {
+            let inp = self.input_column.eval_value(&rec.data)?;
+            timestamp(inp.as_ref(), self.format.as_deref())?
+        };
+        self.records.push((rec, dt));
+        Ok(None)
+    }
+
+    fn drain(self: Box<Self>) -> Box<dyn Iterator<Item = Record>> {
+        let span = match self.records.iter().map(|(_, dt)| dt).minmax() {
+            MinMaxResult::NoElements => return Box::new(iter::empty()),
+            MinMaxResult::OneElement(_) => chrono::Duration::zero(),
+            MinMaxResult::MinMax(min, max) => *max - *min,
+        };
+        let duration = auto_duration(span, self.buckets);
+        let output_column = self
+            .output_column
+            .unwrap_or_else(|| "_timeslice".to_string());
+        Box::new(self.records.into_iter().map(move |(rec, dt)| {
+            // The durations can always be rounded to since they're far shorter than the range
+            // of a timestamp.
+            let rounded = dt.duration_trunc(duration).unwrap_or(dt);
+            rec.put(&output_column, data::Value::DateTime(rounded))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_timestamps() {
+        let check = |value: &str, format: Option<&str>, expected: Option<&str>| {
+            assert_eq!(
+                timestamp(&data::Value::Str(value.to_string()), format)
+                    .ok()
+                    .map(|dt| dt.to_rfc3339()),
+                expected.map(|e| e.to_string()),
+                "{} {:?}",
+                value,
+                format
+            );
+        };
+        check(
+            "2024-01-11 22:14:15",
+            None,
+            Some("2024-01-11T22:14:15+00:00"),
+        );
+        check(
+            "10/Oct/2000:13:55:36 -0700",
+            Some("%d/%b/%Y:%H:%M:%S %z"),
+            Some("2000-10-10T20:55:36+00:00"),
+        );
+        check(
+            "11.01.2024 22:14",
+            Some("%d.%m.%Y %H:%M"),
+            Some("2024-01-11T22:14:00+00:00"),
+        );
+        check("2024-01-11", Some("%d.%m.%Y"), None);
+        check("not a date", None, None);
+    }
+
+    #[test]
+    fn auto_durations() {
+        let check = |span: chrono::Duration, buckets, expected: chrono::Duration| {
+            assert_eq!(auto_duration(span, buckets), expected, "span {}", span);
+        };
+        check(
+            chrono::Duration::zero(),
+            100,
+            chrono::Duration::milliseconds(1),
+        );
+        check(
+            chrono::Duration::seconds(90),
+            100,
+            chrono::Duration::seconds(1),
+        );
+        check(
+            chrono::Duration::hours(2),
+            100,
+            chrono::Duration::minutes(5),
+        );
+        check(
+            chrono::Duration::hours(2),
+            10,
+            chrono::Duration::minutes(30),
+        );
+        check(chrono::Duration::days(30), 100, chrono::Duration::hours(12));
+        check(
+            chrono::Duration::weeks(250),
+            100,
+            chrono::Duration::weeks(3),
+        );
+        check(
+            chrono::Duration::weeks(250),
+            4_294_967_296,
+            chrono::Duration::milliseconds(100),
+        );
+        check(
+            chrono::Duration::days(30),
+            3_000_000_000,
+            chrono::Duration::milliseconds(10),
+        );
+        check(
+            chrono::Duration::weeks(1_000_000),
+            usize::MAX,
+            chrono::Duration::minutes(5),
+        );
+    }
+}
@@ -0,0 +1,293 @@
+use crate::data::{Aggregate, Row, Value};
+use crate::operator::{AggregateOperator, Data, Expr};
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeSet, HashMap};
+use std::hash::{Hash, Hasher};
+
+/// Whether the most or the least frequent values are kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TopMode {
+    Most,
+    Least,
+}
+
+/// The number of distinct values that are counted for each row of output, and the fewest that
+/// are counted.  Counting more values than are output keeps the counts near the cutoff accurate.
+const TRACKED_PER_ROW: usize = 100;
+const MIN_TRACKED: usize = 1000;
+
+/// The size of the count-min sketch used to estimate how often values were seen by `rare`.
+const SKETCH_DEPTH: usize = 4;
+const SKETCH_WIDTH: usize = 1 << 16;
+
+type Key = Vec<Value>;
+
+/// A set of counted values ordered by their count.  Counts are exact until a value has been
+/// evicted to make room for another, since a value that's seen again after being evicted
+/// starts from an estimate.
+#[derive(Default)]
+struct Counts {
+    counts: HashMap<Key, u64>,
+    order: BTreeSet<(u64, Key)>,
+    evicted: bool,
+}
+
+impl Counts {
+    fn set(&mut self, key: Key, count: u64) {
+        if let Some(old) = self.counts.insert(key.clone(), count) {
+            self.order.remove(&(old, key.clone()));
+        }
+        self.order.insert((count, key));
+    }
+
+    fn evict(&mut self, entry: (u64, Key)) {
+        self.order.remove(&entry);
+        self.counts.remove(&entry.1);
+        self.evicted = true;
+    }
+}
+
+/// Estimates how often every value was seen in a fixed amount of memory.  Estimates are never
+/// too low, and conservative updates keep them from being much too high.
+struct Sketch {
+    counters: Vec<u64>,
+}
+
+impl Sketch {
+    fn new() -> Self {
+        Sketch {
+            counters: vec![0; SKETCH_DEPTH * SKETCH_WIDTH],
+        }
+    }
+
+    fn cells(key: &Key) -> impl Iterator<Item = usize> + '_ {
+        (0..SKETCH_DEPTH).map(move |row| {
+            let mut hasher = DefaultHasher::new();
+            row.hash(&mut hasher);
+            key.hash(&mut hasher);
+            row * SKETCH_WIDTH + (hasher.finish() as usize) % SKETCH_WIDTH
+        })
+    }
+
+    /// Count another occurrence of the key and return its estimated count.
+    fn add(&mut self, key: &Key) -> u64 {
+        let cells: Vec<_> = Sketch::cells(key).collect();
+        let estimate = cells.iter().map(|c| self.counters[*c]).min().unwrap_or(0) + 1;
+        for cell in cells {
+            self.counters[cell] = self.counters[cell].max(estimate);
+        }
+        estimate
+    }
+}
+
+/// The `top` and `rare` operators, which count how often each value is seen and output the
+/// most or least frequent ones along with their percentage of the total.  Memory is bounded,
+/// so the counts are estimates once there are more distinct values than can be tracked:
+///
+/// * `top` uses the Space-Saving algorithm: when a new value is seen and all of the slots are
+///   taken, it replaces the value with the lowest count and takes over that count.  Any value
+///   that makes up more than `1 / tracked` of the total is guaranteed to be counted.
+/// * `rare` keeps the values with the lowest counts as candidates, estimating the count of
+///   values that aren't candidates with a count-min sketch.
+pub struct Top {
+    key_cols: Vec<Expr>,
+    key_col_headers: Vec<String>,
+    rows: usize,
+    mode: TopMode,
+    tracked: usize,
+    total: u64,
+    counts: Counts,
+    sketch: Option<Sketch>,
+}
+
+impl Top {
+    pub fn new(
+        key_cols: Vec<Expr>,
+        key_col_headers: Vec<String>,
+        rows: usize,
+        mode: TopMode,
+    ) -> Self {
+        Top {
+            key_cols,
+            key_col_headers,
+            rows,
+            mode,
+            tracked: (rows * TRACKED_PER_ROW).max(MIN_TRACKED),
+            total: 0,
+            counts: Counts::default(),
+            sketch: None,
+        }
+    }
+
+    fn process_map(&mut self, data: &Data) {
+        let key: Key = self
+            .key_cols
+            .iter()
+            .map(|expr| {
+                expr.eval_value(data)
+                    .unwrap_or(Cow::Owned(Value::None))
+                    .into_owned()
+            })
+            .collect();
+        self.total += 1;
+        match self.mode {
+            TopMode::Most => self.count_most(key),
+            TopMode::Least => self.count_least(key),
+        }
+    }
+
+    fn count_most(&mut self, key: Key) {
+        let count = match self.counts.counts.get(&key) {
+            Some(count) => count + 1,
+            None if self.counts.counts.len() < self.tracked => 1,
+            None => {
+                let lowest = self.counts.order.iter().next().cloned();
+                match lowest {
+                    Some(lowest) => {
+                        let count = lowest.0 + 1;
+                        self.counts.evict(lowest);
+                        count
+                    }
+                    None => 1,
+                }
+            }
+        };
+        self.counts.set(key, count);
+    }
+
+    fn count_least(&mut self, key: Key) {
+        let estimate = self.sketch.get_or_insert_with(Sketch::new).add(&key);
+        let count = match self.counts.counts.get(&key) {
+            Some(count) => count + 1,
+            // Until something has been evicted, every value is a candidate, so one that isn't is
+            // being seen for the first time.
+            None if !self.counts.evicted => 1,
+            None => estimate,
+        };
+        if self.counts.counts.contains_key(&key) || self.counts.counts.len() < self.tracked {
+            self.counts.set(key, count);
+            return;
+        }
+        let highest = self.counts.order.iter().next_back().cloned();
+        if let Some(highest) = highest {
+            if count < highest.0 {
+                self.counts.evict(highest);
+                self.counts.set(key, count);
+            }
+        }
+    }
+}
+
+impl AggregateOperator for Top {
+    fn emit(&self) -> Aggregate {
+        let mut columns = self.key_col_headers.clone();
+        columns.push("_count".to_string());
+        columns.push("_percent".to_string());
+        let entries: Box<dyn Iterator<Item = &(u64, Key)>> = match self.mode {
+            // Ties are broken by the key, smallest first, in both directions.
+            TopMode::Most => {
+                let mut entries: Vec<_> = self.counts.order.iter().collect();
+                entries.sort_by(|(lc, lk), (rc, rk)| rc.cmp(lc).then(lk.cmp(rk)));
+                Box::new(entries.into_iter())
+            }
+            TopMode::Least => Box::new(self.counts.order.iter()),
+        };
+        let data = entries
+            .take(self.rows)
+            .map(|(count, key)| {
+                let mut row: Data = self
+                    .key_col_headers
+                    .iter()
+                    .cloned()
+                    .zip(key.iter().cloned())
+                    .collect();
+                row.insert("_count".to_string(), Value::Int(*count as i64));
+                row.insert(
+                    "_percent".to_string(),
+                    Value::from_float(*count as f64 * 100.0 / self.total as f64),
+                );
+                row
+            })
+            .collect();
+        Aggregate { columns, data }
+    }
+
+    fn process(&mut self, row: Row) {
+        match row {
+            Row::Record(rec) => self.process_map(&rec.data),
+            Row::Aggregate(agg) => {
+                self.total = 0;
+                self.counts = Counts::default();
+                self.sketch = None;
+                for row in agg.data {
+                    self.process_map(&row);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Record;
+
+    fn run(top: &mut Top, values: impl IntoIterator<Item = i64>) -> Vec<(i64, i64)> {
+        for value in values {
+            top.process(Row::Record(Record::new("").put("x", Value::Int(value))));
+        }
+        top.emit()
+            .data
+            .iter()
+            .map(|row| match (&row["x"], &row["_count"]) {
+                (Value::Int(x), Value::Int(count)) => (*x, *count),
+                other => panic!("unexpected row {:?}", other),
+            })
+            .collect()
+    }
+
+    fn top(rows: usize, mode: TopMode) -> Top {
+        Top::new(vec![Expr::column("x")], vec!["x".to_string()], rows, mode)
+    }
+
+    #[test]
+    fn counts_exactly_with_few_values() {
+        let values = [3, 1, 2, 3, 3, 2, 4];
+        assert_eq!(
+            run(&mut top(2, TopMode::Most), values),
+            vec![(3, 3), (2, 2)]
+        );
+        assert_eq!(
+            run(&mut top(3, TopMode::Least), values),
+            vec![(1, 1), (4, 1), (2, 2)]
+        );
+    }
+
+    #[test]
+    fn percentages() {
+        let mut top = top(1, TopMode::Most);
+        run(&mut top, [1, 1, 1, 2]);
+        assert_eq!(top.emit().data[0]["_percent"], Value::from_float(75.0));
+    }
+
+    #[test]
+    fn bounded_memory() {
+        // Every value is seen once except for a few frequent ones, spread through the input.
+        let values = (0..100_000).map(|i| if i % 10 == 0 { i % 30 } else { i });
+        let mut most = top(3, TopMode::Most);
+        assert_eq!(
+            run(&mut most, values.clone()),
+            vec![(0, 3334), (10, 3333), (20, 3333)]
+        );
+        assert!(most.counts.counts.len() <= MIN_TRACKED);
+
+        let mut least = top(2, TopMode::Least);
+        let rare = run(&mut least, values.chain([0, 0]));
+        assert_eq!(
+            rare.iter().map(|(_, count)| *count).collect::<Vec<_>>(),
+            [1, 1]
+        );
+        assert!(least.counts.counts.len() <= MIN_TRACKED);
+    }
+}
@@ -21,6 +21,10 @@ impl OperatorBuilder for TotalDef {
     fn build(&self) -> Box<dyn UnaryPreAggOperator> {
         Box::new(Total::new(self.column.clone(), self.output_column.clone()))
     }
+
+    fn parallel(&self) -> bool {
+        false
+    }
 }
 
 pub struct Total {
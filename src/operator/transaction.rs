@@ -0,0 +1,275 @@
+use crate::data::{Record, VMap, Value};
+use crate::operator::timeslice::timestamp;
+use crate::operator::{EvalError, Expr, OperatorBuilder, UnaryPreAggOperator};
+use chrono::{DateTime, Duration, Utc};
+use std::collections::{BTreeSet, HashMap, VecDeque};
+
+/// The fields a transaction is output with, besides its key columns.
+pub const FIELDS: &[&str] = &["_start", "_end", "_duration", "_count", "_first", "_last"];
+
+type Key = Vec<Value>;
+
+#[derive(Clone)]
+pub struct TransactionDef {
+    key_cols: Vec<Expr>,
+    key_col_headers: Vec<String>,
+    timestamp: Expr,
+    max_span: Option<Duration>,
+    max_pause: Option<Duration>,
+}
+
+impl TransactionDef {
+    pub fn new(
+        key_cols: Vec<Expr>,
+        key_col_headers: Vec<String>,
+        timestamp: Expr,
+        max_span: Option<Duration>,
+        max_pause: Option<Duration>,
+    ) -> Self {
+        TransactionDef {
+            key_cols,
+            key_col_headers,
+            timestamp,
+            max_span,
+            max_pause,
+        }
+    }
+
+    /// The time after which an event can no longer be added to a transaction, if there's a limit.
+    fn deadline(&self, start: DateTime<Utc>, end: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let span = self.max_span.map(|max_span| start + max_span);
+        let pause = self.max_pause.map(|max_pause| end + max_pause);
+        span.into_iter().chain(pause).min()
+    }
+}
+
+impl OperatorBuilder for TransactionDef {
+    fn build(&self) -> Box<dyn UnaryPreAggOperator> {
+        Box::new(Transaction {
+            def: self.clone(),
+            open: HashMap::new(),
+            deadlines: BTreeSet::new(),
+            closed: VecDeque::new(),
+            next_id: 0,
+        })
+    }
+
+    fn parallel(&self) -> bool {
+        false
+    }
+}
+
+/// A transaction that's still collecting events.
+struct Open {
+    /// The order the transactions were started in.
+    id: u64,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    deadline: Option<DateTime<Utc>>,
+    count: i64,
+    first: VMap,
+    last: VMap,
+    raw: String,
+}
+
+/// The `transaction` operator, which groups the events that share a key into one record.  A
+/// transaction is closed once an event for its key comes more than `max_span` after its first
+/// event or `max_pause` after its last, and then a new one is started.  Since the input is
+/// assumed to be in time order, a transaction is also closed once any event is past its
+/// deadline, so only the transactions that could still grow are held in memory.
+///
+/// Each input event is consumed, and a closed transaction is passed on in its place, so
+/// transactions can lag a few events behind.  Those still open at the end of the input are
+/// output in the order they were started.
+pub struct Transaction {
+    def: TransactionDef,
+    open: HashMap<Key, Open>,
+    deadlines: BTreeSet<(DateTime<Utc>, Key)>,
+    closed: VecDeque<Record>,
+    next_id: u64,
+}
+
+impl Transaction {
+    /// Close the transactions whose deadline was before `now`.
+    fn expire(&mut self, now: DateTime<Utc>) {
+        while let Some((deadline, key)) = self.deadlines.iter().next().cloned() {
+            if deadline >= now {
+                break;
+            }
+            self.close(&key);
+        }
+    }
+
+    fn close(&mut self, key: &Key) {
+        if let Some(open) = self.open.remove(key) {
+            if let Some(deadline) = open.deadline {
+                self.deadlines.remove(&(deadline, key.clone()));
+            }
+            let rec = self.output(key.clone(), open);
+            self.closed.push_back(rec);
+        }
+    }
+
+    fn output(&self, key: Key, open: Open) -> Record {
+        let mut rec = Record::new(open.raw);
+        for (header, value) in self.def.key_col_headers.iter().zip(key) {
+            rec.put_mut(header, value);
+        }
+        rec.put_mut("_start", Value::DateTime(open.start));
+        rec.put_mut("_end", Value::DateTime(open.end));
+        // In seconds, like other numbers, so that it can be aggregated.
+        let duration = open.end - open.start;
+        rec.put_mut(
+            "_duration",
+            Value::from_float(duration.num_milliseconds() as f64 / 1000.0),
+        );
+        rec.put_mut("_count", Value::Int(open.count));
+        rec.put_mut("_first", Value::Obj(open.first.into_iter().collect()));
+        rec.put_mut("_last", Value::Obj(open.last.into_iter().collect()));
+        rec
+    }
+}
+
+impl UnaryPreAggOperator for Transaction {
+    fn process_mut(&mut self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let key = self
+            .def
+            .key_cols
+            .iter()
+            .map(|expr| expr.eval_value(&rec.data).map(|value| value.into_owned()))
+            .collect::<Result<Key, _>>()?;
+        let ts = timestamp(self.def.timestamp.eval_value(&rec.data)?.as_ref(), None)?;
+
+        self.expire(ts);
+        if let Some(open) = self.open.get(&key) {
+            if open.deadline.is_some_and(|deadline| ts > deadline) {
+                self.close(&key);
+            }
+        }
+        match self.open.get_mut(&key) {
+            Some(open) => {
+                if let Some(deadline) = open.deadline {
+                    self.deadlines.remove(&(deadline, key.clone()));
+                }
+                open.start = open.start.min(ts);
+                open.end = open.end.max(ts);
+                open.deadline = self.def.deadline(open.start, open.end);
+                open.count += 1;
+                open.last = rec.data;
+                open.raw.push('\n');
+                open.raw.push_str(&rec.raw);
+                if let Some(deadline) = open.deadline {
+                    self.deadlines.insert((deadline, key));
+                }
+            }
+            None => {
+                let deadline = self.def.deadline(ts, ts);
+                if let Some(deadline) = deadline {
+                    self.deadlines.insert((deadline, key.clone()));
+                }
+                let open = Open {
+                    id: self.next_id,
+                    start: ts,
+                    end: ts,
+                    deadline,
+                    count: 1,
+                    first: rec.data.clone(),
+                    last: rec.data,
+                    raw: rec.raw,
+                };
+                self.next_id += 1;
+                self.open.insert(key, open);
+            }
+        }
+        Ok(self.closed.pop_front())
+    }
+
+    fn drain(mut self: Box<Self>) -> Box<dyn Iterator<Item = Record>> {
+        let mut open: Vec<_> = self.open.drain().collect();
+        open.sort_by_key(|(_, open)| open.id);
+        let closed = std::mem::take(&mut self.closed);
+        let rest: Vec<_> = open
+            .into_iter()
+            .map(|(key, open)| self.output(key, open))
+            .collect();
+        Box::new(closed.into_iter().chain(rest))
+    }
+
+    fn consumed_last(&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run(def: TransactionDef, events: &[(&str, i64)]) -> Vec<(String, i64, String)> {
+        let mut op = def.build();
+        let mut out = vec![];
+        for (sid, minute) in events {
+            let ts = DateTime::from_timestamp(minute * 60, 0).unwrap();
+            let rec = Record::new(format!("{} {}", sid, minute))
+                .put("sid", Value::Str(sid.to_string()))
+                .put("ts", Value::DateTime(ts));
+            out.extend(op.process_mut(rec).unwrap());
+        }
+        out.extend(op.drain());
+        out.into_iter()
+            .map(|rec| match (&rec.data["sid"], &rec.data["_count"]) {
+                (Value::Str(sid), Value::Int(count)) => (sid.clone(), *count, rec.raw),
+                other => panic!("unexpected transaction {:?}", other),
+            })
+            .collect()
+    }
+
+    fn def(max_span: Option<i64>, max_pause: Option<i64>) -> TransactionDef {
+        TransactionDef::new(
+            vec![Expr::column("sid")],
+            vec!["sid".to_string()],
+            Expr::column("ts"),
+            max_span.map(Duration::minutes),
+            max_pause.map(Duration::minutes),
+        )
+    }
+
+    #[test]
+    fn groups_by_key() {
+        let events = [("a", 0), ("b", 1), ("a", 2), ("a", 40)];
+        assert_eq!(
+            run(def(None, None), &events),
+            vec![
+                ("a".to_string(), 3, "a 0\na 2\na 40".to_string()),
+                ("b".to_string(), 1, "b 1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn splits_on_span_and_pause() {
+        let events = [
+            ("a", 0),
+            ("b", 1),
+            ("a", 10),
+            ("a", 20),
+            ("b", 30),
+            ("a", 31),
+        ];
+        let summary = |def| {
+            run(def, &events)
+                .into_iter()
+                .map(|(sid, count, _)| (sid, count))
+                .collect::<Vec<_>>()
+        };
+        let tx = |sid: &str, count| (sid.to_string(), count);
+        // Transactions are closed as soon as any later event is past their deadline.
+        assert_eq!(
+            summary(def(None, Some(10))),
+            vec![tx("b", 1), tx("a", 3), tx("b", 1), tx("a", 1)]
+        );
+        assert_eq!(
+            summary(def(Some(15), None)),
+            vec![tx("a", 2), tx("b", 1), tx("a", 2), tx("b", 1)]
+        );
+    }
+}
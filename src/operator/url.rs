@@ -0,0 +1,236 @@
+use crate::data::{Record, Value};
+use crate::operator::{EvalError, Expr, UnaryPreAggFunction};
+use crate::{data, operator};
+
+/// The fields a URL is parsed into.
+pub const FIELDS: &[&str] = &["scheme", "host", "port", "path", "query"];
+
+/// Decode the `%XX` escapes in part of a URL, and `+` as a space in a query string.  Invalid
+/// escapes are kept as they are, and invalid UTF-8 is replaced.
+fn percent_decode(s: &str, plus_as_space: bool) -> String {
+    let bytes = s.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' => {
+                let hex = s.get(i + 1..i + 3).filter(|hex| !hex.starts_with('+'));
+                match hex.and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 3;
+                        continue;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b'+' if plus_as_space => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// The parameters of a query string.  A parameter that's given more than once becomes an array
+/// of its values, and one without a value, like `?debug`, is an empty string.
+fn query_params(query: &str) -> Value {
+    let mut params = im::HashMap::new();
+    for param in query.split('&').filter(|param| !param.is_empty()) {
+        let (key, value) = param.split_once('=').unwrap_or((param, ""));
+        let value = Value::Str(percent_decode(value, true));
+        let entry = params
+            .entry(percent_decode(key, true))
+            .or_insert(Value::Array(vec![]));
+        match entry {
+            Value::Array(values) => values.push(value),
+            _ => unreachable!("query parameters are collected into arrays"),
+        }
+    }
+    Value::Obj(
+        params
+            .into_iter()
+            .map(|(key, values)| match values {
+                Value::Array(mut values) if values.len() == 1 => (key, values.remove(0)),
+                values => (key, values),
+            })
+            .collect(),
+    )
+}
+
+/// Split the authority of a URL, e.g. `user@example.com:8080`, into its host and port.  The
+/// brackets around an IPv6 address are removed.
+fn host_port(authority: &str) -> Option<(&str, Option<i64>)> {
+    let host_port = authority
+        .rsplit_once('@')
+        .map_or(authority, |(_, rest)| rest);
+    let (host, port) = match host_port.strip_prefix('[') {
+        Some(ipv6) => {
+            let (host, rest) = ipv6.split_once(']')?;
+            (host, rest.strip_prefix(':'))
+        }
+        None => match host_port.rsplit_once(':') {
+            Some((host, port)) => (host, Some(port)),
+            None => (host_port, None),
+        },
+    };
+    let port = match port {
+        None | Some("") => None,
+        Some(port) => Some(port.parse().ok()?),
+    };
+    Some((host, port))
+}
+
+/// Parse an absolute URL, like `https://example.com:8080/a%20b?q=1#top`, or one that's relative
+/// to the host, like the `/a%20b?q=1` in an access log.  The fields a URL doesn't have are `None`.
+pub fn parse_url(url: &str) -> Option<Vec<(&'static str, Value)>> {
+    let url = url.split_once('#').map_or(url, |(url, _fragment)| url);
+    let (scheme, rest) = match url.split_once("://") {
+        Some((scheme, rest))
+            if scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+                && scheme
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || "+-.".contains(c)) =>
+        {
+            (Some(scheme), Some(rest))
+        }
+        _ => (None, url.strip_prefix("//")),
+    };
+    let (host, port, path_query) = match rest {
+        Some(rest) => {
+            let split = rest.find(['/', '?']).unwrap_or(rest.len());
+            let (host, port) = host_port(&rest[..split])?;
+            if host.is_empty() {
+                return None;
+            }
+            (Some(host), port, &rest[split..])
+        }
+        None if url.starts_with('/') => (None, None, url),
+        None => return None,
+    };
+    let (path, query) = match path_query.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (path_query, None),
+    };
+    let str_or_none = |value: Option<&str>| value.map_or(Value::None, |v| Value::Str(v.into()));
+    Some(vec![
+        (
+            "scheme",
+            str_or_none(scheme.map(|s| s.to_ascii_lowercase()).as_deref()),
+        ),
+        (
+            "host",
+            str_or_none(host.map(|h| h.to_ascii_lowercase()).as_deref()),
+        ),
+        ("port", port.map_or(Value::None, Value::Int)),
+        ("path", Value::Str(percent_decode(path, false))),
+        ("query", query.map_or(Value::None, query_params)),
+    ])
+}
+
+#[derive(Clone)]
+pub struct ParseUrl {
+    input_column: Option<Expr>,
+    drop_nonmatching: bool,
+}
+
+impl ParseUrl {
+    pub fn new(input_column: Option<Expr>, drop_nonmatching: bool) -> Self {
+        ParseUrl {
+            input_column,
+            drop_nonmatching,
+        }
+    }
+}
+
+impl UnaryPreAggFunction for ParseUrl {
+    fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let fields = {
+            let inp = operator::get_input(&rec, &self.input_column)?;
+            parse_url(inp.trim())
+        };
+        match fields {
+            Some(fields) => {
+                let mut rec = rec;
+                for (field, value) in fields {
+                    rec.put_mut(field, value);
+                }
+                Ok(Some(rec))
+            }
+            None if self.drop_nonmatching => Ok(None),
+            None => {
+                let mut rec = rec;
+                for field in FIELDS {
+                    if !rec.data.contains_key(*field) {
+                        rec.put_mut(*field, data::Value::None);
+                    }
+                }
+                Ok(Some(rec))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(url: &str) -> Option<Vec<String>> {
+        parse_url(url).map(|fields| {
+            fields
+                .into_iter()
+                .map(|(_, value)| value.to_string())
+                .collect()
+        })
+    }
+
+    #[test]
+    fn parses_absolute_urls() {
+        assert_eq!(
+            parse("HTTPS://user:pw@Example.com:8443/a%20b/c+d?utm_source=news%26more&q=a+b&q=c&debug#top"),
+            Some(vec![
+                "https".to_string(),
+                "example.com".to_string(),
+                "8443".to_string(),
+                "/a b/c+d".to_string(),
+                r#"{"debug": Str(""), "q": Array([Str("a b"), Str("c")]), "utm_source": Str("news&more")}"#
+                    .to_string(),
+            ])
+        );
+        assert_eq!(
+            parse("http://[::1]:8080"),
+            Some(vec![
+                "http".to_string(),
+                "::1".to_string(),
+                "8080".to_string(),
+                "".to_string(),
+                "None".to_string(),
+            ])
+        );
+    }
+
+    #[test]
+    fn parses_relative_urls() {
+        assert_eq!(
+            parse("/search?q=%E2%98%83&bad=%zz"),
+            Some(vec![
+                "None".to_string(),
+                "None".to_string(),
+                "None".to_string(),
+                "/search".to_string(),
+                r#"{"bad": Str("%zz"), "q": Str("☃")}"#.to_string(),
+            ])
+        );
+        assert_eq!(
+            parse("//cdn.example.com/app.js").map(|fields| fields[1].clone()),
+            Some("cdn.example.com".to_string())
+        );
+    }
+
+    #[test]
+    fn rejects_other_strings() {
+        assert_eq!(parse("not a url"), None);
+        assert_eq!(parse("http://example.com:http/"), None);
+        assert_eq!(parse("http:///path"), None);
+    }
+}
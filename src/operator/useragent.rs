@@ -0,0 +1,198 @@
+use crate::data::{Record, Value};
+use crate::operator::{EvalError, Expr, UnaryPreAggFunction};
+use lazy_static::lazy_static;
+use regex::Regex;
+
+/// The fields a user agent is parsed into.
+pub const FIELDS: &[&str] = &["browser", "browser_version", "os", "device"];
+
+/// What a user agent is called when none of the patterns match it.
+const OTHER: &str = "Other";
+
+/// A pattern in the style of the [uap-core](https://github.com/ua-parser/uap-core) regexes.  The
+/// name is the replacement, which can refer to the groups as `$1`, or the first group if there's
+/// no replacement.  For browsers, the second to fourth groups are the version.
+struct Pattern {
+    regex: Regex,
+    replacement: Option<&'static str>,
+}
+
+fn patterns(patterns: &[(&str, Option<&'static str>)]) -> Vec<Pattern> {
+    patterns
+        .iter()
+        .map(|(regex, replacement)| Pattern {
+            regex: Regex::new(regex).expect("invalid user agent pattern"),
+            replacement: *replacement,
+        })
+        .collect()
+}
+
+lazy_static! {
+    // The first pattern that matches wins, so browsers built on another one, like Edge on
+    // Chrome, come before it.
+    static ref BROWSERS: Vec<Pattern> = patterns(&[
+        (
+            r"(Googlebot|bingbot|Baiduspider|YandexBot|DuckDuckBot|Slurp|facebookexternalhit|Twitterbot|AhrefsBot|SemrushBot)(?:/(\d+)\.(\d+)(?:\.(\d+))?)?",
+            None,
+        ),
+        (r"(curl)/(\d+)\.(\d+)(?:\.(\d+))?", None),
+        (r"(Wget)/(\d+)\.(\d+)(?:\.(\d+))?", None),
+        (r"(python-requests)/(\d+)\.(\d+)(?:\.(\d+))?", Some("Python Requests")),
+        (r"(Go-http-client)/(\d+)\.(\d+)", None),
+        (r"(Edge|Edg|EdgA|EdgiOS)/(\d+)\.(\d+)(?:\.(\d+))?", Some("Edge")),
+        (r"(OPR|OPiOS)/(\d+)\.(\d+)(?:\.(\d+))?", Some("Opera")),
+        (r"(Opera)/.+Version/(\d+)\.(\d+)", None),
+        (r"(SamsungBrowser)/(\d+)\.(\d+)", Some("Samsung Internet")),
+        (r"(YaBrowser)/(\d+)\.(\d+)(?:\.(\d+))?", Some("Yandex Browser")),
+        (r"(Vivaldi)/(\d+)\.(\d+)(?:\.(\d+))?", None),
+        (r"(FxiOS)/(\d+)\.(\d+)(?:\.(\d+))?", Some("Firefox iOS")),
+        (r"(CriOS)/(\d+)\.(\d+)(?:\.(\d+))?", Some("Chrome Mobile iOS")),
+        (r"(HeadlessChrome)/(\d+)\.(\d+)(?:\.(\d+))?", None),
+        (r"(Chromium)/(\d+)\.(\d+)(?:\.(\d+))?", None),
+        (r"(Chrome)/(\d+)\.(\d+)(?:\.(\d+))?[\d.]* Mobile", Some("Chrome Mobile")),
+        (r"(Chrome)/(\d+)\.(\d+)(?:\.(\d+))?", None),
+        (r"Mobile.*(Firefox)/(\d+)\.(\d+)(?:\.(\d+))?", Some("Firefox Mobile")),
+        (r"(Firefox)/(\d+)\.(\d+)(?:\.(\d+))?", None),
+        (r"(MSIE) (\d+)\.(\d+)", Some("IE")),
+        (r"(Trident)/.+rv:(\d+)\.(\d+)", Some("IE")),
+        (r"(iPhone|iPad|iPod).+Version/(\d+)\.(\d+)(?:\.(\d+))?.*Safari", Some("Mobile Safari")),
+        (r"(Android) .+Version/(\d+)\.(\d+)(?:\.(\d+))?.*Safari", Some("Android")),
+        (r"(Version)/(\d+)\.(\d+)(?:\.(\d+))?.*Safari/", Some("Safari")),
+    ]);
+    static ref OPERATING_SYSTEMS: Vec<Pattern> = patterns(&[
+        (r"Windows Phone", Some("Windows Phone")),
+        (r"Windows", Some("Windows")),
+        (r"(?:iPhone|iPad|iPod).*OS \d", Some("iOS")),
+        (r"Android", Some("Android")),
+        (r"CrOS", Some("Chrome OS")),
+        (r"Mac OS X|Macintosh", Some("Mac OS X")),
+        (r"(Ubuntu|Fedora|Debian)", None),
+        (r"Linux", Some("Linux")),
+        (r"(FreeBSD|OpenBSD|NetBSD)", None),
+    ]);
+    static ref DEVICES: Vec<Pattern> = patterns(&[
+        (
+            r"(?i)bot\b|crawler|spider|slurp|facebookexternalhit",
+            Some("Spider"),
+        ),
+        (r"(iPhone|iPad|iPod)", None),
+        (r"Android [\d.]+; (?:[a-zA-Z-]+; )?([^;)]+?)(?: Build/|\))", None),
+        (r"Android.+Mobile", Some("Generic Smartphone")),
+        (r"Android", Some("Generic Tablet")),
+        (r"Macintosh", Some("Mac")),
+    ]);
+}
+
+/// The name and, for browsers, the version given by the first pattern that matches.
+fn find(patterns: &[Pattern], user_agent: &str) -> Option<(String, Option<String>)> {
+    patterns.iter().find_map(|pattern| {
+        let captures = pattern.regex.captures(user_agent)?;
+        let name = match pattern.replacement {
+            Some(replacement) => {
+                let mut name = String::new();
+                captures.expand(replacement, &mut name);
+                name
+            }
+            None => captures.get(1)?.as_str().to_string(),
+        };
+        let version: Vec<&str> = (2..=4)
+            .map_while(|i| captures.get(i).map(|part| part.as_str()))
+            .collect();
+        let version = (!version.is_empty()).then(|| version.join("."));
+        Some((name, version))
+    })
+}
+
+/// Parse a user agent string into its browser, browser version, operating system and device.
+/// Those that aren't recognized are `Other`, and a browser without a version has `None`.
+pub fn parse_user_agent(user_agent: &str) -> Vec<(&'static str, Value)> {
+    let name = |found: Option<(String, Option<String>)>| {
+        Value::Str(found.map_or_else(|| OTHER.to_string(), |(name, _)| name))
+    };
+    let browser = find(&BROWSERS, user_agent);
+    let version = browser
+        .as_ref()
+        .and_then(|(_, version)| version.clone())
+        .map_or(Value::None, Value::Str);
+    vec![
+        ("browser", name(browser)),
+        ("browser_version", version),
+        ("os", name(find(&OPERATING_SYSTEMS, user_agent))),
+        ("device", name(find(&DEVICES, user_agent))),
+    ]
+}
+
+#[derive(Clone)]
+pub struct ParseUserAgent {
+    input_column: Option<Expr>,
+}
+
+impl ParseUserAgent {
+    pub fn new(input_column: Option<Expr>) -> Self {
+        ParseUserAgent { input_column }
+    }
+}
+
+impl UnaryPreAggFunction for ParseUserAgent {
+    fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let fields = match &self.input_column {
+            // A missing user agent, like one written as `-` in an access log, isn't an error.
+            Some(expr) => match expr.eval_value(&rec.data)?.as_ref() {
+                Value::None => FIELDS.iter().map(|field| (*field, Value::None)).collect(),
+                user_agent => parse_user_agent(&user_agent.to_string()),
+            },
+            None => parse_user_agent(rec.raw.trim_end_matches(['\r', '\n'])),
+        };
+        let mut rec = rec;
+        for (field, value) in fields {
+            rec.put_mut(field, value);
+        }
+        Ok(Some(rec))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(user_agent: &str) -> Vec<String> {
+        parse_user_agent(user_agent)
+            .into_iter()
+            .map(|(_, value)| value.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn parses_browsers() {
+        assert_eq!(
+            parse("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.6099.129 Safari/537.36"),
+            vec!["Chrome", "120.0.6099", "Windows", "Other"]
+        );
+        assert_eq!(
+            parse("Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36 Edg/120.0.2210.91"),
+            vec!["Edge", "120.0.2210", "Windows", "Other"]
+        );
+        assert_eq!(
+            parse("Mozilla/5.0 (iPhone; CPU iPhone OS 17_1 like Mac OS X) AppleWebKit/605.1.15 (KHTML, like Gecko) Version/17.1 Mobile/15E148 Safari/604.1"),
+            vec!["Mobile Safari", "17.1", "iOS", "iPhone"]
+        );
+        assert_eq!(
+            parse("Mozilla/5.0 (Linux; Android 13; SM-S908B) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/112.0.0.0 Mobile Safari/537.36"),
+            vec!["Chrome Mobile", "112.0.0", "Android", "SM-S908B"]
+        );
+        assert_eq!(
+            parse("Mozilla/5.0 (Macintosh; Intel Mac OS X 14.1; rv:121.0) Gecko/20100101 Firefox/121.0"),
+            vec!["Firefox", "121.0", "Mac OS X", "Mac"]
+        );
+    }
+
+    #[test]
+    fn parses_tools_and_bots() {
+        assert_eq!(parse("curl/8.4.0"), vec!["curl", "8.4.0", "Other", "Other"]);
+        assert_eq!(
+            parse("Mozilla/5.0 (compatible; Googlebot/2.1; +http://www.google.com/bot.html)"),
+            vec!["Googlebot", "2.1", "Other", "Spider"]
+        );
+        assert_eq!(parse(""), vec!["Other", "None", "Other", "Other"]);
+    }
+}
@@ -0,0 +1,88 @@
+use crate::data;
+use crate::operator::{AggregateFunction, Data, EvalError, Expr};
+use std::collections::HashSet;
+
+/// The number of distinct values that `values` keeps when no limit is given.
+pub const DEFAULT_LIMIT: usize = 100;
+
+/// The `values` aggregate, which collects the distinct values of `column` into an array, in the
+/// order they're first seen.  Values past the first `limit` are dropped.
+pub struct Values {
+    seen: HashSet<data::Value>,
+    values: Vec<data::Value>,
+    column: Expr,
+    limit: usize,
+}
+
+impl Values {
+    pub fn empty<T: Into<Expr>>(column: T, limit: usize) -> Self {
+        Values {
+            seen: HashSet::new(),
+            values: Vec::new(),
+            column: column.into(),
+            limit,
+        }
+    }
+
+    fn add(&mut self, value: data::Value) {
+        if self.values.len() < self.limit && self.seen.insert(value.clone()) {
+            self.values.push(value);
+        }
+    }
+}
+
+impl AggregateFunction for Values {
+    fn process(&mut self, data: &Data) -> Result<(), EvalError> {
+        if self.values.len() >= self.limit {
+            return Ok(());
+        }
+        let value = self.column.eval_value(data)?;
+        if !self.seen.contains(value.as_ref()) {
+            self.add(value.into_owned());
+        }
+        Ok(())
+    }
+
+    fn emit(&self) -> data::Value {
+        data::Value::Array(self.values.clone())
+    }
+
+    fn empty_box(&self) -> Box<dyn AggregateFunction> {
+        Box::new(Values::empty(self.column.clone(), self.limit))
+    }
+
+    fn state(&self) -> data::Value {
+        self.emit()
+    }
+
+    fn merge(&mut self, state: data::Value) {
+        if let data::Value::Array(values) = state {
+            values.into_iter().for_each(|value| self.add(value));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+
+    fn run(limit: usize) -> Value {
+        let mut values = Values::empty(Expr::column("url"), limit);
+        for url in ["/b", "/a", "/b", "/c", "/a"].iter() {
+            let data = vec![("url".to_string(), Value::from_string(*url))]
+                .into_iter()
+                .collect();
+            values.process(&data).unwrap();
+        }
+        values.emit()
+    }
+
+    #[test]
+    fn distinct_values_in_order() {
+        let urls =
+            |urls: &[&str]| Value::Array(urls.iter().map(|url| Value::from_string(*url)).collect());
+        assert_eq!(run(DEFAULT_LIMIT), urls(&["/b", "/a", "/c"]));
+        assert_eq!(run(2), urls(&["/b", "/a"]));
+    }
+}
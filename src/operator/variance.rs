@@ -0,0 +1,139 @@
+use crate::data;
+use crate::operator::{AggregateFunction, Data, EvalError, Evaluate, Expr};
+use ordered_float::OrderedFloat;
+
+/// The sample variance, or the standard deviation when `stddev` is set, computed with Welford's
+/// algorithm so that it's accurate even when the values are large compared to their spread.
+pub struct Variance {
+    count: i64,
+    mean: f64,
+    /// The sum of the squared differences from the mean.
+    m2: f64,
+    column: Expr,
+    stddev: bool,
+}
+
+impl Variance {
+    pub fn empty<T: Into<Expr>>(column: T, stddev: bool) -> Variance {
+        Variance {
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            column: column.into(),
+            stddev,
+        }
+    }
+}
+
+impl AggregateFunction for Variance {
+    fn process(&mut self, data: &Data) -> Result<(), EvalError> {
+        let value: f64 = self.column.eval(data)?;
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+        Ok(())
+    }
+
+    fn emit(&self) -> data::Value {
+        if self.count < 2 {
+            return data::Value::None;
+        }
+        let variance = self.m2 / (self.count - 1) as f64;
+        if self.stddev {
+            data::Value::from_float(variance.sqrt())
+        } else {
+            data::Value::from_float(variance)
+        }
+    }
+
+    fn empty_box(&self) -> Box<dyn AggregateFunction> {
+        Box::new(Variance::empty(self.column.clone(), self.stddev))
+    }
+
+    fn state(&self) -> data::Value {
+        data::Value::Array(vec![
+            data::Value::Int(self.count),
+            data::Value::Float(OrderedFloat(self.mean)),
+            data::Value::Float(OrderedFloat(self.m2)),
+        ])
+    }
+
+    /// Combines the means and squared differences with Chan et al.'s parallel algorithm.
+    fn merge(&mut self, state: data::Value) {
+        let state = match state {
+            data::Value::Array(state) => state,
+            _ => return,
+        };
+        if let [data::Value::Int(count), data::Value::Float(mean), data::Value::Float(m2)] =
+            state[..]
+        {
+            if count == 0 {
+                return;
+            }
+            let (mean, m2) = (mean.into_inner(), m2.into_inner());
+            let total = self.count + count;
+            let delta = mean - self.mean;
+            self.mean += delta * count as f64 / total as f64;
+            self.m2 += m2 + delta * delta * (self.count as f64 * count as f64) / total as f64;
+            self.count = total;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+
+    fn process(stddev: bool, values: &[f64]) -> Variance {
+        let mut variance = Variance::empty(Expr::column("x"), stddev);
+        for value in values {
+            let data = vec![("x".to_string(), Value::from_float(*value))]
+                .into_iter()
+                .collect();
+            variance.process(&data).unwrap();
+        }
+        variance
+    }
+
+    fn run(stddev: bool, values: &[f64]) -> Value {
+        process(stddev, values).emit()
+    }
+
+    #[test]
+    fn sample_variance() {
+        let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        assert_eq!(run(false, &values), Value::from_float(32.0 / 7.0));
+        assert_eq!(
+            run(true, &values),
+            Value::from_float((32.0f64 / 7.0).sqrt())
+        );
+        let shifted: Vec<_> = values.iter().map(|v| v + 1e9).collect();
+        match run(false, &shifted) {
+            Value::Float(variance) => assert!((variance.into_inner() - 32.0 / 7.0).abs() < 1e-6),
+            other => panic!("unexpected variance {:?}", other),
+        }
+    }
+
+    #[test]
+    fn merges_partial_results() {
+        let values = [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0];
+        for split in 0..=values.len() {
+            let mut merged = process(false, &values[..split]);
+            merged.merge(process(false, &values[split..]).state());
+            match merged.emit() {
+                Value::Float(variance) => {
+                    assert!((variance.into_inner() - 32.0 / 7.0).abs() < 1e-9)
+                }
+                other => panic!("unexpected variance {:?}", other),
+            }
+        }
+    }
+
+    #[test]
+    fn too_few_values() {
+        assert_eq!(run(false, &[]), Value::None);
+        assert_eq!(run(true, &[3.0]), Value::None);
+    }
+}
@@ -0,0 +1,244 @@
+//! Functions from WebAssembly modules.
+//!
+//! A module is loaded with wasmtime and runs sandboxed, with no imports.  Values are passed to
+//! and from it as JSON in its memory:
+//!
+//! - it exports its `memory`, and `alloc(len: i32) -> i32`, which returns a buffer of `len`
+//!   bytes for the input.  If it also exports `dealloc(ptr: i32, len: i32)`, the input and
+//!   output are handed back to it once they've been read.
+//! - a function takes the pointer and length of its input, and returns the pointer of its output
+//!   in the upper 32 bits of an `i64` and the length in the lower 32 bits.
+//!
+//! `wasm my_plugin.wasm` passes `{"line": ..., "fields": {...}}` for each record to the module's
+//! `map` function, which returns the record's new fields as an object, or `null` to drop it.
+//! `wasm("my_plugin.wasm", "name", args...)` in an expression passes the arguments as an array to
+//! the function `name`, which returns the value of the expression.
+use crate::data::{Record, Value};
+use crate::operator::{EvalError, UnaryPreAggFunction};
+use anyhow::anyhow;
+use lazy_static::lazy_static;
+use ordered_float::OrderedFloat;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use wasmtime::{Engine, Instance, Memory, Module, Store, TypedFunc};
+
+/// The function that `wasm` runs each record through, unless another is named.
+pub const DEFAULT_FUNCTION: &str = "map";
+
+lazy_static! {
+    static ref ENGINE: Engine = Engine::default();
+    /// The modules that have been compiled, so that a module used more than once in a query is
+    /// only compiled once.
+    static ref MODULES: Mutex<HashMap<PathBuf, Module>> = Mutex::new(HashMap::new());
+}
+
+/// An instance of a module, with the exports used to call one of its functions.
+struct Guest {
+    store: Store<()>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    dealloc: Option<TypedFunc<(i32, i32), ()>>,
+    func: TypedFunc<(i32, i32), i64>,
+}
+
+impl Guest {
+    fn call(&mut self, input: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let len = i32::try_from(input.len())?;
+        let ptr = self.alloc.call(&mut self.store, len)?;
+        self.memory
+            .write(&mut self.store, ptr as u32 as usize, input)?;
+        let packed = self.func.call(&mut self.store, (ptr, len))?;
+        let (out_ptr, out_len) = ((packed >> 32) as u32, packed as u32);
+        let mut output = vec![0; out_len as usize];
+        self.memory
+            .read(&self.store, out_ptr as usize, &mut output)?;
+        if let Some(dealloc) = &self.dealloc {
+            dealloc.call(&mut self.store, (ptr, len))?;
+            dealloc.call(&mut self.store, (out_ptr as i32, out_len as i32))?;
+        }
+        Ok(output)
+    }
+}
+
+/// A function exported by a module.  Its instance is shared by every copy of the operator or
+/// expression, so calls to it are made one at a time.
+pub struct WasmFunction {
+    path: PathBuf,
+    name: String,
+    guest: Mutex<Guest>,
+}
+
+impl WasmFunction {
+    pub fn load(path: &Path, name: &str) -> anyhow::Result<Self> {
+        let module = {
+            let mut modules = MODULES.lock().expect("not poisoned");
+            match modules.get(path) {
+                Some(module) => module.clone(),
+                None => {
+                    let module = Module::from_file(&ENGINE, path)?;
+                    modules.insert(path.to_path_buf(), module.clone());
+                    module
+                }
+            }
+        };
+        let mut store = Store::new(&ENGINE, ());
+        let instance = Instance::new(&mut store, &module, &[])?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| anyhow!("the module doesn't export its memory"))?;
+        let alloc = instance.get_typed_func(&mut store, "alloc")?;
+        let dealloc = instance.get_typed_func(&mut store, "dealloc").ok();
+        let func = instance
+            .get_typed_func(&mut store, name)
+            .map_err(|e| anyhow!("`{}`: {}", name, e))?;
+        Ok(WasmFunction {
+            path: path.to_path_buf(),
+            name: name.to_string(),
+            guest: Mutex::new(Guest {
+                store,
+                memory,
+                alloc,
+                dealloc,
+                func,
+            }),
+        })
+    }
+
+    /// Call the function with a JSON value, returning the JSON value it returns.
+    pub fn call(&self, input: &serde_json::Value) -> Result<Value, EvalError> {
+        let failed = |msg: String| EvalError::FunctionFailed { name: "wasm", msg };
+        let input = serde_json::to_vec(input).map_err(|e| failed(e.to_string()))?;
+        let output = self
+            .guest
+            .lock()
+            .expect("not poisoned")
+            .call(&input)
+            .map_err(|e| failed(format!("{} in {}: {}", self.name, self.path.display(), e)))?;
+        serde_json::from_slice(&output)
+            .map(from_json)
+            .map_err(|e| failed(format!("{} returned invalid JSON: {}", self.name, e)))
+    }
+}
+
+impl fmt::Debug for WasmFunction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.path.display(), self.name)
+    }
+}
+
+fn from_json(json: serde_json::Value) -> Value {
+    match json {
+        serde_json::Value::Null => Value::None,
+        serde_json::Value::Bool(b) => Value::Bool(b),
+        serde_json::Value::Number(n) => match n.as_i64() {
+            Some(i) => Value::Int(i),
+            None => Value::Float(OrderedFloat(n.as_f64().unwrap_or(f64::NAN))),
+        },
+        serde_json::Value::String(s) => Value::Str(s),
+        serde_json::Value::Array(a) => Value::Array(a.into_iter().map(from_json).collect()),
+        serde_json::Value::Object(o) => {
+            Value::Obj(o.into_iter().map(|(k, v)| (k, from_json(v))).collect())
+        }
+    }
+}
+
+/// Runs each record through a function of a module, replacing its fields with the ones the
+/// function returns.
+#[derive(Clone)]
+pub struct WasmMap {
+    func: Arc<WasmFunction>,
+}
+
+impl WasmMap {
+    pub fn new(func: WasmFunction) -> Self {
+        WasmMap {
+            func: Arc::new(func),
+        }
+    }
+}
+
+impl UnaryPreAggFunction for WasmMap {
+    fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
+        let input = serde_json::json!({ "line": rec.raw, "fields": rec.data });
+        match self.func.call(&input)? {
+            Value::Obj(fields) => Ok(Some(Record {
+                data: fields.into_iter().collect(),
+                raw: rec.raw,
+            })),
+            Value::None => Ok(None),
+            other => Err(EvalError::ExpectedXYZ {
+                expected: "an object of fields, or null".to_string(),
+                found: other.to_string(),
+            }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A module that echoes its input from `echo`, returns `{"n":1}` from `map`, and traps in
+    /// `trap`.  `alloc` hands out memory after the data segment, and never frees it.
+    const MODULE: &str = r#"
+        (module
+          (memory (export "memory") 1)
+          (global $next (mut i32) (i32.const 1024))
+          (data (i32.const 0) "{\"n\":1}")
+          (func (export "alloc") (param $len i32) (result i32)
+            (global.get $next)
+            (global.set $next (i32.add (global.get $next) (local.get $len))))
+          (func (export "echo") (param $ptr i32) (param $len i32) (result i64)
+            (i64.or
+              (i64.shl (i64.extend_i32_u (local.get $ptr)) (i64.const 32))
+              (i64.extend_i32_u (local.get $len))))
+          (func (export "map") (param i32 i32) (result i64)
+            (i64.const 7))
+          (func (export "trap") (param i32 i32) (result i64)
+            unreachable)
+          (func (export "wrong") (param i32) (result i32)
+            (local.get 0)))
+    "#;
+
+    fn module(name: &str) -> PathBuf {
+        let path =
+            std::env::temp_dir().join(format!("agrind-wasm-{}-{}.wat", name, std::process::id()));
+        std::fs::write(&path, MODULE).unwrap();
+        path
+    }
+
+    #[test]
+    fn calls_functions() {
+        let path = module("call");
+        let echo = WasmFunction::load(&path, "echo").unwrap();
+        assert_eq!(
+            echo.call(&serde_json::json!([1, "a"])).unwrap(),
+            Value::Array(vec![Value::Int(1), Value::Str("a".to_string())])
+        );
+
+        let map = WasmMap::new(WasmFunction::load(&path, DEFAULT_FUNCTION).unwrap());
+        let rec = map.process(Record::new("a")).unwrap().unwrap();
+        assert_eq!(rec.data["n"], Value::Int(1));
+        assert_eq!(rec.raw, "a");
+    }
+
+    #[test]
+    fn rejects_bad_exports() {
+        let path = module("exports");
+        assert!(WasmFunction::load(&path, "missing").is_err());
+        assert!(WasmFunction::load(&path, "wrong").is_err());
+        assert!(WasmFunction::load(&path.with_extension("missing"), "echo").is_err());
+    }
+
+    #[test]
+    fn reports_traps() {
+        let trap = WasmFunction::load(&module("trap"), "trap").unwrap();
+        assert!(matches!(
+            trap.call(&serde_json::Value::Null),
+            Err(EvalError::FunctionFailed { name: "wasm", .. })
+        ));
+    }
+}
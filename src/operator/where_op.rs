@@ -1,5 +1,6 @@
 use crate::data::Record;
-use crate::operator::{EvalError, Evaluate, UnaryPreAggFunction};
+use crate::operator::expr::{BinaryExpr, Expr, LogicalExpr};
+use crate::operator::{Data, EvalError, Evaluate, UnaryPreAggFunction};
 
 #[derive(Clone)]
 pub struct Where<T> {
@@ -21,3 +22,40 @@ impl<T: Evaluate<bool>> UnaryPreAggFunction for Where<T> {
         }
     }
 }
+
+/// The condition of a `where`.  A comparison whose operands are missing or can't be computed,
+/// like `a / b > 1` when `b` isn't a number, is false instead of an error.
+#[derive(Clone)]
+pub struct Predicate(pub Expr);
+
+impl Evaluate<bool> for Predicate {
+    fn eval(&self, record: &Data) -> Result<bool, EvalError> {
+        eval_predicate(&self.0, record)
+    }
+}
+
+fn eval_predicate(expr: &Expr, record: &Data) -> Result<bool, EvalError> {
+    match expr {
+        Expr::Comparison(comparison) => match comparison.eval(record) {
+            Err(
+                EvalError::NoValueForKey { .. }
+                | EvalError::IndexOutOfRange { .. }
+                | EvalError::ExpectedXYZ { .. }
+                | EvalError::UnexpectedNone { .. }
+                | EvalError::ExpectedNumber { .. }
+                | EvalError::ExpectedNumericOperands { .. },
+            ) => Ok(false),
+            other => other,
+        },
+        Expr::Logical(BinaryExpr {
+            operator,
+            left,
+            right,
+        }) => match (operator, eval_predicate(left, record)?) {
+            (LogicalExpr::And, false) => Ok(false),
+            (LogicalExpr::Or, true) => Ok(true),
+            _ => eval_predicate(right, record),
+        },
+        other => other.eval(record),
+    }
+}
@@ -0,0 +1,422 @@
+//! OpenTelemetry (OTLP/JSON) log output.
+//!
+//! Each output row is written as an OTLP `LogRecord` on its own line.  When an endpoint is given
+//! (`-o otlp=http://collector:4318`), the lines are batched into `ExportLogsServiceRequest`s and
+//! POSTed to the collector's `/v1/logs` route instead of being written to stdout.
+use std::io;
+use std::io::Write;
+
+use serde_json::{json, Map, Value as Json};
+
+use crate::data::{Aggregate, DisplayConfig, Value};
+use crate::printer::{AggregatePrinter, RowPrinter};
+
+pub const DEFAULT_SEVERITY_FIELD: &str = "level";
+pub const TIMESTAMP_FIELD: &str = "timestamp";
+
+/// Port used for endpoints that don't specify one, the OTLP/HTTP default.
+const DEFAULT_PORT: u16 = 4318;
+const DEFAULT_PATH: &str = "/v1/logs";
+/// Number of records sent to the collector in a single request.
+const BATCH_SIZE: usize = 512;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OtlpConfig {
+    /// Collector to POST the records to.  When absent, records are written to the output.
+    pub endpoint: Option<Endpoint>,
+    /// Field that the severity of a record is read from.
+    pub severity_field: String,
+}
+
+impl Default for OtlpConfig {
+    fn default() -> Self {
+        OtlpConfig {
+            endpoint: None,
+            severity_field: DEFAULT_SEVERITY_FIELD.to_owned(),
+        }
+    }
+}
+
+/// An `http://` collector address.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Endpoint {
+    host: String,
+    port: u16,
+    path: String,
+}
+
+impl Endpoint {
+    pub fn parse(url: &str) -> Result<Self, String> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or_else(|| format!("expected an http:// URL, found `{}`", url))?;
+        let (authority, path) = match rest.find('/') {
+            Some(idx) => rest.split_at(idx),
+            None => (rest, ""),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (
+                host,
+                port.parse::<u16>()
+                    .map_err(|_| format!("invalid port `{}`", port))?,
+            ),
+            None => (authority, DEFAULT_PORT),
+        };
+        if host.is_empty() {
+            return Err(format!("missing host in `{}`", url));
+        }
+        let path = match path {
+            "" | "/" => DEFAULT_PATH,
+            path => path,
+        };
+        Ok(Endpoint {
+            host: host.to_owned(),
+            port,
+            path: path.to_owned(),
+        })
+    }
+}
+
+/// Map a severity to its OTLP `SeverityNumber`.  Numbers are taken as-is, names are matched
+/// case-insensitively against the usual level names.
+fn severity_number(severity: &Value) -> Option<i64> {
+    match severity {
+        Value::Int(i) if (1..=24).contains(i) => Some(*i),
+        Value::Str(s) => match s.to_ascii_lowercase().as_str() {
+            "trace" => Some(1),
+            "debug" => Some(5),
+            "info" | "information" => Some(9),
+            "notice" => Some(10),
+            "warn" | "warning" => Some(13),
+            "error" | "err" => Some(17),
+            "fatal" | "critical" | "crit" | "panic" => Some(21),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Convert a value to an OTLP `AnyValue`.
+fn any_value(value: &Value, display_config: &DisplayConfig) -> Json {
+    match value {
+        Value::Str(s) => json!({ "stringValue": s }),
+        // int64s are encoded as strings in OTLP/JSON
+        Value::Int(i) => json!({ "intValue": i.to_string() }),
+        Value::Float(f) => json!({ "doubleValue": f.into_inner() }),
+        Value::Bool(b) => json!({ "boolValue": b }),
+        Value::Array(values) => json!({
+            "arrayValue": {
+                "values": values
+                    .iter()
+                    .map(|v| any_value(v, display_config))
+                    .collect::<Vec<_>>()
+            }
+        }),
+        Value::Obj(map) => {
+            let mut entries: Vec<_> = map.iter().collect();
+            entries.sort_by_key(|(k, _)| *k);
+            json!({ "kvlistValue": { "values": key_values(entries, display_config) } })
+        }
+        Value::None => json!({}),
+        other => json!({ "stringValue": other.render(display_config) }),
+    }
+}
+
+fn key_values<'a>(
+    entries: impl IntoIterator<Item = (&'a String, &'a Value)>,
+    display_config: &DisplayConfig,
+) -> Vec<Json> {
+    entries
+        .into_iter()
+        .map(|(k, v)| json!({ "key": k, "value": any_value(v, display_config) }))
+        .collect()
+}
+
+pub struct OtlpPrinter {
+    severity_field: String,
+}
+
+impl OtlpPrinter {
+    pub fn new(config: &OtlpConfig) -> Self {
+        OtlpPrinter {
+            severity_field: config.severity_field.clone(),
+        }
+    }
+
+    fn log_record(
+        &self,
+        display_config: &DisplayConfig,
+        raw: Option<&str>,
+        cols: &mut dyn Iterator<Item = (&String, &Value)>,
+    ) -> Json {
+        let mut record = Map::new();
+        let mut attributes: Vec<_> = vec![];
+        for (key, value) in cols {
+            if *key == self.severity_field {
+                if let Some(number) = severity_number(value) {
+                    record.insert("severityNumber".to_owned(), json!(number));
+                }
+                record.insert(
+                    "severityText".to_owned(),
+                    json!(value.render(display_config)),
+                );
+            } else if let (TIMESTAMP_FIELD, Value::DateTime(dt)) = (key.as_str(), value) {
+                if let Some(nanos) = dt.timestamp_nanos_opt() {
+                    record.insert("timeUnixNano".to_owned(), json!(nanos.to_string()));
+                }
+            } else {
+                attributes.push((key, value));
+            }
+        }
+        if let Some(raw) = raw {
+            record.insert("body".to_owned(), json!({ "stringValue": raw.trim_end() }));
+        }
+        attributes.sort_by_key(|(k, _)| *k);
+        record.insert(
+            "attributes".to_owned(),
+            Json::Array(key_values(attributes, display_config)),
+        );
+        Json::Object(record)
+    }
+}
+
+impl RowPrinter for OtlpPrinter {
+    fn print_row(
+        &mut self,
+        display_config: &DisplayConfig,
+        out: &mut dyn Write,
+        raw: Option<&str>,
+        cols: &mut dyn Iterator<Item = (&String, &Value)>,
+    ) -> io::Result<()> {
+        let record = self.log_record(display_config, raw, cols);
+        serde_json::to_writer(out, &record)?;
+        Ok(())
+    }
+}
+
+impl AggregatePrinter for OtlpPrinter {
+    /// Nothing is emitted until the aggregate is complete, so no partial results are exported.
+    fn print(&mut self, _row: &Aggregate, _display_config: &DisplayConfig) -> String {
+        String::new()
+    }
+
+    fn final_print(&mut self, row: &Aggregate, display_config: &DisplayConfig) -> String {
+        let mut out = vec![];
+        for data in &row.data {
+            let mut cols = row
+                .columns
+                .iter()
+                .map(|c| (c, data.get(c).unwrap_or(&Value::None)));
+            self.print_row(display_config, &mut out, None, &mut cols)
+                .expect("writing to string");
+            writeln!(&mut out).unwrap();
+        }
+        String::from_utf8(out).expect("invalid UTF-8 produced")
+    }
+}
+
+/// Output that POSTs the log records written to it, one per line, to an OTLP/HTTP collector.
+/// Records are sent in batches, the final batch when the exporter is flushed or dropped.
+pub struct OtlpExporter {
+    endpoint: Endpoint,
+    partial_line: Vec<u8>,
+    records: Vec<String>,
+}
+
+impl OtlpExporter {
+    pub fn new(endpoint: Endpoint) -> Self {
+        OtlpExporter {
+            endpoint,
+            partial_line: vec![],
+            records: vec![],
+        }
+    }
+
+    fn export(&mut self) -> io::Result<()> {
+        if self.records.is_empty() {
+            return Ok(());
+        }
+        let body = format!(
+            r#"{{"resourceLogs":[{{"resource":{{"attributes":[{{"key":"service.name","value":{{"stringValue":"agrind"}}}}]}},"scopeLogs":[{{"scope":{{"name":"agrind"}},"logRecords":[{}]}}]}}]}}"#,
+            self.records.join(",")
+        );
+        self.records.clear();
+
+        let Endpoint { host, port, path } = &self.endpoint;
+        let url = format!("http://{}:{}{}", host, port, path);
+        match ureq::post(&url)
+            .set("Content-Type", "application/json")
+            .send_string(&body)
+        {
+            Ok(_) => Ok(()),
+            Err(ureq::Error::Status(status, response)) => Err(io::Error::other(format!(
+                "collector at {} responded with `{} {}`",
+                url,
+                status,
+                response.status_text()
+            ))),
+            Err(ureq::Error::Transport(e)) => Err(io::Error::other(format!(
+                "could not reach the collector at {}: {}",
+                url, e
+            ))),
+        }
+    }
+}
+
+impl Write for OtlpExporter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.partial_line.extend_from_slice(buf);
+        while let Some(idx) = self.partial_line.iter().position(|b| *b == b'\n') {
+            let line: Vec<u8> = self.partial_line.drain(..=idx).collect();
+            let line = String::from_utf8_lossy(&line).trim().to_owned();
+            if !line.is_empty() {
+                self.records.push(line);
+            }
+        }
+        if self.records.len() >= BATCH_SIZE {
+            self.export()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.export()
+    }
+}
+
+impl Drop for OtlpExporter {
+    fn drop(&mut self) {
+        if let Err(e) = self.export() {
+            eprintln!("error: failed to export logs: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Record;
+    use std::io::Read;
+    use std::net::TcpListener;
+    use std::thread;
+
+    fn print(printer: &mut OtlpPrinter, rec: &Record) -> String {
+        let display_config = DisplayConfig { floating_points: 2 };
+        let mut out = vec![];
+        printer
+            .print_row(
+                &display_config,
+                &mut out,
+                Some(&rec.raw),
+                &mut rec.data.iter(),
+            )
+            .unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn parse_endpoint() {
+        assert_eq!(
+            Endpoint::parse("http://collector:4318"),
+            Ok(Endpoint {
+                host: "collector".to_owned(),
+                port: 4318,
+                path: "/v1/logs".to_owned()
+            })
+        );
+        assert_eq!(
+            Endpoint::parse("http://localhost/custom/logs"),
+            Ok(Endpoint {
+                host: "localhost".to_owned(),
+                port: 4318,
+                path: "/custom/logs".to_owned()
+            })
+        );
+        assert!(Endpoint::parse("https://collector:4318").is_err());
+        assert!(Endpoint::parse("http://collector:port").is_err());
+        assert!(Endpoint::parse("http://:4318").is_err());
+    }
+
+    #[test]
+    fn log_record() {
+        let rec = Record::new("WARN disk full\n")
+            .put("level", Value::Str("WARN".to_owned()))
+            .put("pct", Value::Int(100))
+            .put("host", Value::Str("web-1".to_owned()));
+        let mut printer = OtlpPrinter::new(&OtlpConfig::default());
+        assert_eq!(
+            print(&mut printer, &rec),
+            r#"{"attributes":[{"key":"host","value":{"stringValue":"web-1"}},{"key":"pct","value":{"intValue":"100"}}],"body":{"stringValue":"WARN disk full"},"severityNumber":13,"severityText":"WARN"}"#
+        );
+    }
+
+    #[test]
+    fn custom_severity_field() {
+        let rec = Record::new("x").put("sev", Value::Str("debug".to_owned()));
+        let mut printer = OtlpPrinter::new(&OtlpConfig {
+            severity_field: "sev".to_owned(),
+            ..OtlpConfig::default()
+        });
+        assert_eq!(
+            print(&mut printer, &rec),
+            r#"{"attributes":[],"body":{"stringValue":"x"},"severityNumber":5,"severityText":"debug"}"#
+        );
+    }
+
+    #[test]
+    fn export_to_collector() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buf = [0; 1024];
+            // Read until the end of the (single record) body
+            while !request.ends_with(b"]}]}]}") {
+                let n = conn.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            conn.write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+
+        let endpoint = Endpoint::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+        let mut exporter = OtlpExporter::new(endpoint);
+        exporter.write_all(b"{\"attributes\":[]}\n").unwrap();
+        exporter.flush().unwrap();
+
+        let request = server.join().unwrap();
+        assert!(request.starts_with("POST /v1/logs HTTP/1.1\r\n"));
+        assert!(request.ends_with(r#""logRecords":[{"attributes":[]}]}]}]}"#));
+    }
+
+    #[test]
+    fn export_rejected() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = thread::spawn(move || {
+            let (mut conn, _) = listener.accept().unwrap();
+            let mut request = vec![];
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"]}]}]}") {
+                let n = conn.read(&mut buf).unwrap();
+                request.extend_from_slice(&buf[..n]);
+            }
+            conn.write_all(b"HTTP/1.1 400 Bad Request\r\nContent-Length: 0\r\n\r\n")
+                .unwrap();
+        });
+
+        let endpoint = Endpoint::parse(&format!("http://127.0.0.1:{}", port)).unwrap();
+        let mut exporter = OtlpExporter::new(endpoint);
+        exporter.write_all(b"{\"attributes\":[]}\n").unwrap();
+        assert_eq!(
+            exporter.flush().unwrap_err().to_string(),
+            format!(
+                "collector at http://127.0.0.1:{}/v1/logs responded with `400 Bad Request`",
+                port
+            )
+        );
+        server.join().unwrap();
+    }
+}
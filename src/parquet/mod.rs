@@ -0,0 +1,13 @@
+//! Parquet files, which are written by `-o parquet` and can be read as input when agrind is built
+//! with the `parquet` feature.
+#[cfg(feature = "parquet")]
+mod read;
+#[cfg(feature = "parquet")]
+mod write;
+
+#[cfg(feature = "parquet")]
+pub use read::RecordBatches;
+#[cfg(feature = "parquet")]
+pub use write::{write, ParquetPrinter};
+
+pub(crate) const MAGIC: &[u8] = b"PAR1";
@@ -0,0 +1,90 @@
+//! Parquet input.
+//!
+//! Each row of a Parquet file becomes a record whose fields are its columns, with values typed
+//! from the file's schema, so they don't need to be parsed.  Files are read by the `parquet`
+//! crate's Arrow reader, so every encoding and compression codec it supports can be read, and
+//! nested columns become objects and arrays.
+use std::fs::File;
+
+use ::parquet::arrow::arrow_reader::{ParquetRecordBatchReader, ParquetRecordBatchReaderBuilder};
+
+use crate::columnar;
+use crate::data::Record;
+
+/// The rows of a Parquet file, read a batch at a time.
+pub struct RecordBatches {
+    reader: ParquetRecordBatchReader,
+}
+
+impl RecordBatches {
+    /// Read the schema and the row groups of the file from its footer.
+    pub fn new(file: File) -> Result<Self, String> {
+        let reader = ParquetRecordBatchReaderBuilder::try_new(file)
+            .and_then(|builder| builder.build())
+            .map_err(|e| e.to_string())?;
+        Ok(RecordBatches { reader })
+    }
+}
+
+impl Iterator for RecordBatches {
+    type Item = Result<Vec<Record>, String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let batch = self.reader.next()?;
+        Some(
+            batch
+                .map_err(|e| e.to_string())
+                .and_then(|batch| columnar::batch_records(&batch)),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+    use crate::parquet::write;
+    use chrono::{TimeZone, Utc};
+
+    fn read(path: &std::path::Path) -> Result<Vec<Record>, String> {
+        RecordBatches::new(File::open(path).unwrap())?
+            .collect::<Result<Vec<_>, _>>()
+            .map(|batches| batches.concat())
+    }
+
+    #[test]
+    fn reads_what_is_written() {
+        let path = std::env::temp_dir().join(format!("agrind-parquet-{}", std::process::id()));
+        let columns = vec!["n".to_string(), "s".to_string(), "t".to_string()];
+        let dt = Utc.timestamp_opt(1_700_000_000, 123_000).unwrap();
+        let rows = vec![
+            vec![
+                Value::Int(1),
+                Value::Str("a".to_string()),
+                Value::DateTime(dt),
+            ],
+            vec![Value::from_float(2.5), Value::None, Value::None],
+        ];
+        write(&path, &columns, &rows).unwrap();
+        let read = read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(read.len(), 2);
+        assert_eq!(read[0].data.get("n"), Some(&Value::from_float(1.0)));
+        assert_eq!(read[0].data.get("t"), Some(&Value::DateTime(dt)));
+        assert_eq!(read[1].data.get("n"), Some(&Value::from_float(2.5)));
+        assert_eq!(read[1].data.get("s"), Some(&Value::None));
+        assert_eq!(
+            read[0].raw,
+            r#"{"n":1,"s":"a","t":"2023-11-14T22:13:20.000123+00:00"}"#
+        );
+    }
+
+    #[test]
+    fn rejects_corrupt_files() {
+        let path = std::env::temp_dir().join(format!("agrind-corrupt-{}", std::process::id()));
+        std::fs::write(&path, b"PAR1PAR1").unwrap();
+        let read = read(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(read.is_err());
+    }
+}
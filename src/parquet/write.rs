@@ -0,0 +1,312 @@
+//! Parquet output.
+//!
+//! `-o parquet=results.parquet` writes the final rows to a Parquet file once the input ends, with
+//! a column type inferred from the values of each column: 64 bit integers, doubles, booleans,
+//! timestamps in microseconds since the epoch, or UTF-8 strings for everything else.  Every
+//! column is optional, so missing values are written as nulls.  The file is written by the
+//! `parquet` crate's Arrow writer.
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use ::parquet::arrow::ArrowWriter;
+use ::parquet::file::properties::WriterProperties;
+use arrow_array::{
+    ArrayRef, BooleanArray, Float64Array, Int64Array, RecordBatch, RecordBatchOptions, StringArray,
+    TimestampMicrosecondArray,
+};
+use arrow_schema::{DataType, Field, Schema, TimeUnit};
+use itertools::Itertools;
+
+use crate::data::{Aggregate, DisplayConfig, Record, Value};
+use crate::printer::{AggregatePrinter, RecordPrinter};
+
+/// The type of a column, inferred from its values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ColumnType {
+    Boolean,
+    Int64,
+    Double,
+    Timestamp,
+    String,
+    /// Only `None` has been seen, which is written as a column of null strings.
+    Any,
+}
+
+impl ColumnType {
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Bool(_) => ColumnType::Boolean,
+            Value::Int(_) => ColumnType::Int64,
+            Value::Float(_) => ColumnType::Double,
+            Value::DateTime(_) => ColumnType::Timestamp,
+            Value::None => ColumnType::Any,
+            _ => ColumnType::String,
+        }
+    }
+
+    fn merge(self, other: ColumnType) -> Self {
+        match (self, other) {
+            (ColumnType::Any, other) | (other, ColumnType::Any) => other,
+            (left, right) if left == right => left,
+            (ColumnType::Int64, ColumnType::Double) | (ColumnType::Double, ColumnType::Int64) => {
+                ColumnType::Double
+            }
+            _ => ColumnType::String,
+        }
+    }
+
+    fn data_type(self) -> DataType {
+        match self {
+            ColumnType::Boolean => DataType::Boolean,
+            ColumnType::Int64 => DataType::Int64,
+            ColumnType::Double => DataType::Float64,
+            ColumnType::Timestamp => DataType::Timestamp(TimeUnit::Microsecond, Some("UTC".into())),
+            ColumnType::String | ColumnType::Any => DataType::Utf8,
+        }
+    }
+
+    /// The column of the values, with nulls for `None`.
+    fn array(self, values: &[&Value]) -> ArrayRef {
+        match self {
+            ColumnType::Boolean => Arc::new(
+                values
+                    .iter()
+                    .map(|value| match value {
+                        Value::Bool(b) => Some(*b),
+                        _ => None,
+                    })
+                    .collect::<BooleanArray>(),
+            ),
+            ColumnType::Int64 => Arc::new(
+                values
+                    .iter()
+                    .map(|value| match value {
+                        Value::Int(i) => Some(*i),
+                        _ => None,
+                    })
+                    .collect::<Int64Array>(),
+            ),
+            ColumnType::Double => Arc::new(
+                values
+                    .iter()
+                    .map(|value| match value {
+                        Value::Float(f) => Some(f.into_inner()),
+                        Value::Int(i) => Some(*i as f64),
+                        _ => None,
+                    })
+                    .collect::<Float64Array>(),
+            ),
+            ColumnType::Timestamp => Arc::new(
+                values
+                    .iter()
+                    .map(|value| match value {
+                        Value::DateTime(dt) => Some(dt.timestamp_micros()),
+                        _ => None,
+                    })
+                    .collect::<TimestampMicrosecondArray>()
+                    .with_timezone("UTC"),
+            ),
+            ColumnType::String | ColumnType::Any => Arc::new(
+                values
+                    .iter()
+                    .map(|value| match value {
+                        Value::None => None,
+                        value => Some(text(value)),
+                    })
+                    .collect::<StringArray>(),
+            ),
+        }
+    }
+}
+
+/// The text written for a value in a string column.
+fn text(value: &Value) -> String {
+    match value {
+        Value::Str(s) => s.clone(),
+        Value::Int(i) => i.to_string(),
+        Value::Float(f) => f.to_string(),
+        Value::Bool(b) => b.to_string(),
+        // Dates, durations, objects and arrays are stored as they're written as JSON.
+        value => match serde_json::to_value(value) {
+            Ok(serde_json::Value::String(s)) => s,
+            Ok(json) => json.to_string(),
+            Err(_) => value.to_string(),
+        },
+    }
+}
+
+/// Write the rows to a Parquet file, with `rows[i][j]` the value of `columns[j]` in row `i`.
+/// Rows that are shorter than `columns` have nulls in the rest of the columns.
+pub fn write(path: &Path, columns: &[String], rows: &[Vec<Value>]) -> io::Result<()> {
+    let mut fields = Vec::with_capacity(columns.len());
+    let mut arrays = Vec::with_capacity(columns.len());
+    for (i, name) in columns.iter().enumerate() {
+        let values: Vec<&Value> = rows
+            .iter()
+            .map(|row| row.get(i).unwrap_or(&Value::None))
+            .collect();
+        let column_type = values
+            .iter()
+            .map(|value| ColumnType::of(value))
+            .fold(ColumnType::Any, ColumnType::merge);
+        fields.push(Field::new(name, column_type.data_type(), true));
+        arrays.push(column_type.array(&values));
+    }
+    let schema = Arc::new(Schema::new(fields));
+    let batch = RecordBatch::try_new_with_options(
+        schema.clone(),
+        arrays,
+        &RecordBatchOptions::new().with_row_count(Some(rows.len())),
+    )
+    .map_err(io::Error::other)?;
+    let properties = WriterProperties::builder()
+        .set_created_by(format!("agrind version {}", env!("CARGO_PKG_VERSION")))
+        .build();
+    let mut writer = ArrowWriter::try_new(File::create(path)?, schema, Some(properties))
+        .map_err(io::Error::other)?;
+    writer.write(&batch).map_err(io::Error::other)?;
+    writer.close().map_err(io::Error::other)?;
+    Ok(())
+}
+
+/// Holds the rows until the input ends, then writes them to the file.
+pub struct ParquetPrinter {
+    path: PathBuf,
+    columns: Vec<String>,
+    rows: Vec<Vec<Value>>,
+}
+
+impl ParquetPrinter {
+    pub fn new(path: &Path) -> Self {
+        ParquetPrinter {
+            path: path.to_path_buf(),
+            columns: Vec::new(),
+            rows: Vec::new(),
+        }
+    }
+
+    fn write(&self) -> io::Result<()> {
+        write(&self.path, &self.columns, &self.rows).map_err(|e| {
+            io::Error::new(
+                e.kind(),
+                format!("could not write {}: {}", self.path.display(), e),
+            )
+        })
+    }
+}
+
+impl RecordPrinter for ParquetPrinter {
+    fn print(
+        &mut self,
+        _out: &mut dyn Write,
+        row: &Record,
+        _display_config: &DisplayConfig,
+    ) -> io::Result<()> {
+        // Records don't have a column order, so the columns are in order of their names.  When
+        // nothing was parsed out of a record, the line itself is written instead.
+        let mut values = vec![];
+        let fields: Vec<(String, Value)> = if row.data.is_empty() {
+            let raw = row.raw.trim_end_matches(&['\r', '\n'][..]).to_string();
+            vec![("_raw".to_string(), Value::Str(raw))]
+        } else {
+            row.data
+                .iter()
+                .sorted_by(|(left, _), (right, _)| left.cmp(right))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect()
+        };
+        for (column, value) in fields {
+            let i = match self.columns.iter().position(|c| *c == column) {
+                Some(i) => i,
+                None => {
+                    self.columns.push(column);
+                    self.columns.len() - 1
+                }
+            };
+            if values.len() <= i {
+                values.resize(i + 1, Value::None);
+            }
+            values[i] = value;
+        }
+        self.rows.push(values);
+        Ok(())
+    }
+
+    fn is_buffered(&self) -> bool {
+        true
+    }
+
+    fn finish(&mut self, _out: &mut dyn Write) -> io::Result<()> {
+        self.write()
+    }
+}
+
+impl AggregatePrinter for ParquetPrinter {
+    fn print(&mut self, row: &Aggregate, _display_config: &DisplayConfig) -> String {
+        self.columns = row.columns.clone();
+        self.rows = row
+            .data
+            .iter()
+            .map(|data| {
+                row.columns
+                    .iter()
+                    .map(|column| data.get(column).cloned().unwrap_or(Value::None))
+                    .collect()
+            })
+            .collect();
+        // The file is written instead of the output, which has nothing to show.
+        if let Err(e) = self.write() {
+            eprintln!("error: {}", e);
+        }
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    #[test]
+    fn infers_column_types() {
+        let (one, half, none) = (Value::Int(1), Value::from_float(0.5), Value::None);
+        let infer = |values: &[&Value]| {
+            values
+                .iter()
+                .map(|value| ColumnType::of(value))
+                .fold(ColumnType::Any, ColumnType::merge)
+        };
+        assert_eq!(infer(&[&one, &none]), ColumnType::Int64);
+        assert_eq!(infer(&[&one, &half]), ColumnType::Double);
+        assert_eq!(
+            infer(&[&one, &Value::Str("a".to_string())]),
+            ColumnType::String
+        );
+        assert_eq!(infer(&[&none]), ColumnType::Any);
+        let at = Value::DateTime(Utc.timestamp_opt(1, 500).unwrap());
+        assert_eq!(infer(&[&at, &none]), ColumnType::Timestamp);
+    }
+
+    #[test]
+    fn writes_a_parquet_file() {
+        let path = std::env::temp_dir().join(format!("agrind-write-{}", std::process::id()));
+        let columns = vec!["name".to_string(), "n".to_string(), "at".to_string()];
+        let rows = vec![
+            vec![
+                Value::Str("a".to_string()),
+                Value::Int(1),
+                Value::DateTime(Utc.timestamp_opt(1, 500).unwrap()),
+            ],
+            vec![Value::None, Value::from_float(2.5)],
+        ];
+        write(&path, &columns, &rows).unwrap();
+        let file = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(&file[..4], crate::parquet::MAGIC);
+        assert_eq!(&file[file.len() - 4..], crate::parquet::MAGIC);
+        assert!(file.windows(4).any(|w| w == b"name"));
+    }
+}
@@ -0,0 +1,139 @@
+//! Operators defined outside of angle-grinder.
+//!
+//! A program using the library can add its own operators, like a parser for a proprietary log
+//! format, by implementing [`OperatorPlugin`] and registering it with [`Plugins::register`] before
+//! the query is parsed.  The plugin's keyword is then an operator in the query, e.g.
+//! `* | acme_log strict | count by service`.  Everything between the keyword and the next `|` is
+//! handed to the plugin as its arguments.
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::Arc;
+
+use thiserror::Error;
+
+use crate::lang;
+use crate::operator::{AggregateOperator, OperatorBuilder};
+
+/// An operator that can be used in a query by its keyword.
+pub trait OperatorPlugin: Send + Sync {
+    /// The keyword that starts the operator in a query.  It has to be an identifier, like
+    /// `acme_log`, that isn't one of the built-in operators.
+    fn keyword(&self) -> &str;
+
+    /// Build the operator for a use of it in a query.  `args` is the rest of the operator after
+    /// the keyword, with the surrounding whitespace trimmed.  The error is shown under the
+    /// arguments.
+    fn build(&self, args: &str) -> Result<PluginOperator, String>;
+}
+
+/// What a plugin builds for a use of its operator.
+pub enum PluginOperator {
+    /// An operator that runs on each row, like `parse` or `where`.
+    Inline(Box<dyn OperatorBuilder>),
+    /// An operator that sees every row before it outputs anything, like `count`.  The rows that
+    /// follow it are its output.
+    Aggregate(Box<dyn AggregateOperator>),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum PluginError {
+    #[error("`{}` isn't a valid operator name", keyword)]
+    InvalidKeyword { keyword: String },
+
+    #[error("`{}` is already an operator", keyword)]
+    DuplicateKeyword { keyword: String },
+}
+
+/// The plugins that can be used in a query, by their keywords.
+#[derive(Clone, Default)]
+pub struct Plugins {
+    plugins: HashMap<String, Arc<dyn OperatorPlugin>>,
+}
+
+impl Plugins {
+    pub fn register<P: 'static + OperatorPlugin>(&mut self, plugin: P) -> Result<(), PluginError> {
+        let keyword = plugin.keyword().to_string();
+        let mut chars = keyword.chars();
+        let is_ident = chars
+            .next()
+            .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+            && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !is_ident {
+            return Err(PluginError::InvalidKeyword { keyword });
+        }
+        if lang::VALID_OPERATORS.contains(&keyword.as_str()) || self.plugins.contains_key(&keyword)
+        {
+            return Err(PluginError::DuplicateKeyword { keyword });
+        }
+        self.plugins.insert(keyword, Arc::new(plugin));
+        Ok(())
+    }
+
+    pub fn get(&self, keyword: &str) -> Option<&dyn OperatorPlugin> {
+        self.plugins.get(keyword).map(|plugin| plugin.as_ref())
+    }
+
+    pub fn keywords(&self) -> impl Iterator<Item = &str> {
+        self.plugins.keys().map(String::as_str)
+    }
+}
+
+impl fmt::Debug for Plugins {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_set().entries(self.keywords()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Record;
+    use crate::operator::{EvalError, UnaryPreAggFunction};
+
+    #[derive(Clone)]
+    struct Upper;
+
+    impl UnaryPreAggFunction for Upper {
+        fn process(&self, rec: Record) -> Result<Option<Record>, EvalError> {
+            Ok(Some(Record::new(rec.raw.to_uppercase())))
+        }
+    }
+
+    struct UpperPlugin(&'static str);
+
+    impl OperatorPlugin for UpperPlugin {
+        fn keyword(&self) -> &str {
+            self.0
+        }
+
+        fn build(&self, _args: &str) -> Result<PluginOperator, String> {
+            Ok(PluginOperator::Inline(Box::new(Upper)))
+        }
+    }
+
+    #[test]
+    fn registers_keywords() {
+        let mut plugins = Plugins::default();
+        assert_eq!(plugins.register(UpperPlugin("upper")), Ok(()));
+        assert!(plugins.get("upper").is_some());
+        assert!(plugins.get("lower").is_none());
+        assert_eq!(
+            plugins.register(UpperPlugin("upper")),
+            Err(PluginError::DuplicateKeyword {
+                keyword: "upper".to_string()
+            })
+        );
+        assert_eq!(
+            plugins.register(UpperPlugin("json")),
+            Err(PluginError::DuplicateKeyword {
+                keyword: "json".to_string()
+            })
+        );
+        assert_eq!(
+            plugins.register(UpperPlugin("to-upper")),
+            Err(PluginError::InvalidKeyword {
+                keyword: "to-upper".to_string()
+            })
+        );
+    }
+}
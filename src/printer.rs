@@ -1,15 +1,27 @@
 use crate::data;
 use anyhow::Error;
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{BTreeSet, HashMap, HashSet};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use chrono::{DateTime, Utc};
 use std::io;
 use std::io::Write;
 
 use crate::data::{Aggregate, DisplayConfig, Record, Value, ValueDisplay};
-use crate::pipeline::OutputMode;
-use crate::render::{RenderConfig, TerminalConfig, TerminalSize};
+#[cfg(feature = "parquet")]
+use crate::parquet::ParquetPrinter;
+use crate::pipeline::{JsonOptions, OutputMode};
+use crate::render::{RenderConfig, Style, TerminalConfig, TerminalSize, RESET};
+use crate::sink::CallbackPrinter;
+use crate::split::SplitPrinter;
+use crate::sqlite::SqlitePrinter;
 use itertools::{intersperse, Itertools};
 use strfmt::{strfmt_map, FmtError, Formatter};
 
+#[cfg(feature = "otlp")]
+use crate::otlp::OtlpPrinter;
+
 pub trait AggregatePrinter {
     fn print(&mut self, row: &data::Aggregate, display_config: &DisplayConfig) -> String;
     fn final_print(&mut self, row: &data::Aggregate, display_config: &DisplayConfig) -> String {
@@ -25,6 +37,17 @@ pub trait RecordPrinter {
         display_config: &DisplayConfig,
     ) -> io::Result<()>;
 
+    /// Whether records are held until `finish` rather than written out one per line as they're
+    /// printed.
+    fn is_buffered(&self) -> bool {
+        false
+    }
+
+    /// Called once every record has been printed, to write out anything that was buffered.
+    fn finish(&mut self, _out: &mut dyn Write) -> io::Result<()> {
+        Ok(())
+    }
+
     #[cfg(test)]
     fn print_str(&mut self, row: &Record, display_config: &DisplayConfig) -> String {
         let mut out = vec![];
@@ -42,8 +65,13 @@ impl<T: RowPrinter> RecordPrinter for RecordFromRow<T> {
         row: &Record,
         display_config: &DisplayConfig,
     ) -> io::Result<()> {
-        self.0
-            .print_row(display_config, out, Some(&row.raw), &mut row.data.iter())
+        // Records don't have a column order, so they're written in order of their field names.
+        self.0.print_row(
+            display_config,
+            out,
+            Some(&row.raw),
+            &mut row.data.iter().sorted(),
+        )
     }
 }
 
@@ -90,7 +118,7 @@ impl RowPrinter for LogFmtPrinter {
         _raw: Option<&str>,
         cols: &mut dyn Iterator<Item = (&String, &Value)>,
     ) -> io::Result<()> {
-        let columns = intersperse(cols.sorted().map(Some), None);
+        let columns = intersperse(cols.map(Some), None);
 
         for col in columns {
             match col {
@@ -115,26 +143,76 @@ pub fn raw_printer(
     match mode {
         OutputMode::Logfmt => Ok(Box::new(RecordFromRow(LogFmtPrinter))),
         OutputMode::Legacy => Ok(Box::new(LegacyPrinter::new(render_config, terminal_config))),
-        OutputMode::Json => Ok(Box::new(JsonPrinter {})),
+        OutputMode::Json(options) => Ok(Box::new(JsonPrinter::new(options))),
         OutputMode::Format(format_str) => Ok(Box::new(FormatPrinter::new(format_str.to_owned())?)),
+        // The pipeline rejects queries without an aggregate for this mode, so no records are
+        // ever printed with it.
+        OutputMode::Prometheus => Ok(Box::new(RecordFromRow(LogFmtPrinter))),
+        OutputMode::Markdown => Ok(Box::new(TablePrinter::new(TableFormat::Markdown))),
+        OutputMode::Html => Ok(Box::new(TablePrinter::new(TableFormat::Html))),
+        // Like Prometheus metrics, charts are only drawn of aggregates.
+        OutputMode::Chart(_) | OutputMode::Sparkline(_) => {
+            Ok(Box::new(RecordFromRow(LogFmtPrinter)))
+        }
+        #[cfg(feature = "otlp")]
+        OutputMode::Otlp(config) => Ok(Box::new(RecordFromRow(OtlpPrinter::new(config)))),
+        OutputMode::Sqlite(config) => Ok(Box::new(SqlitePrinter::new(config))),
+        #[cfg(feature = "parquet")]
+        OutputMode::Parquet(path) => Ok(Box::new(ParquetPrinter::new(path))),
+        #[cfg(not(feature = "parquet"))]
+        OutputMode::Parquet(_) => Err(parquet_disabled()),
+        OutputMode::Split(config) => Ok(Box::new(SplitPrinter::new(config))),
+        OutputMode::Callback(callback) => Ok(Box::new(CallbackPrinter::new(callback))),
     }
 }
 
+/// `key_columns` are the columns that identify a group in the aggregate, e.g. `status` in
+/// `count by status`.
 pub fn agg_printer(
     mode: &OutputMode,
     render_config: RenderConfig,
     terminal_config: TerminalConfig,
+    key_columns: &[String],
 ) -> Result<Box<dyn AggregatePrinter + Send>, Error> {
     match mode {
         OutputMode::Logfmt => Ok(Box::new(PrintAggregateAsRows(LogFmtPrinter))),
         OutputMode::Format(format_str) => Ok(Box::new(PrintAggregateAsRows(FormatPrinter::new(
             format_str.to_owned(),
         )?))),
-        OutputMode::Json => Ok(Box::new(JsonPrinter {})),
+        OutputMode::Json(options) => Ok(Box::new(JsonPrinter::new(options))),
         OutputMode::Legacy => Ok(Box::new(LegacyPrinter::new(render_config, terminal_config))),
+        OutputMode::Prometheus => Ok(Box::new(PrometheusPrinter::new(key_columns))),
+        OutputMode::Markdown => Ok(Box::new(TablePrinter::new(TableFormat::Markdown))),
+        OutputMode::Html => Ok(Box::new(TablePrinter::new(TableFormat::Html))),
+        OutputMode::Chart(column) => Ok(Box::new(ChartPrinter::new(
+            column.clone(),
+            key_columns,
+            terminal_config.size.map(|size| size.width as usize),
+        ))),
+        OutputMode::Sparkline(column) => Ok(Box::new(SparklinePrinter::new(
+            column.clone(),
+            key_columns,
+            terminal_config.size.map(|size| size.width as usize),
+        ))),
+        #[cfg(feature = "otlp")]
+        OutputMode::Otlp(config) => Ok(Box::new(OtlpPrinter::new(config))),
+        OutputMode::Sqlite(config) => Ok(Box::new(SqlitePrinter::new(config))),
+        #[cfg(feature = "parquet")]
+        OutputMode::Parquet(path) => Ok(Box::new(ParquetPrinter::new(path))),
+        #[cfg(not(feature = "parquet"))]
+        OutputMode::Parquet(_) => Err(parquet_disabled()),
+        OutputMode::Split(config) => Ok(Box::new(SplitPrinter::new(config))),
+        OutputMode::Callback(callback) => Ok(Box::new(CallbackPrinter::new(callback))),
     }
 }
 
+#[cfg(not(feature = "parquet"))]
+fn parquet_disabled() -> Error {
+    anyhow::anyhow!(
+        "agrind was built without Parquet support. Rebuild it with `--features parquet` to write Parquet files"
+    )
+}
+
 struct LegacyPrinter {
     pretty_printer: PrettyPrinter,
 }
@@ -170,12 +248,40 @@ struct FormatPrinter {
 
 impl FormatPrinter {
     pub fn new(format_str: String) -> Result<Self, Error> {
+        let format_str = unescape_format_str(&format_str);
         let nop_formatter = |mut fmt: Formatter| fmt.str("");
         let _ = strfmt_map(&format_str, &nop_formatter)?;
         Ok(FormatPrinter { format_str })
     }
 }
 
+/// Expand the backslash escapes supported in `-o format=...` strings: `\t`, `\n`, `\\`, and
+/// `\{`/`\}` for literal braces.  Braces are rewritten to the `{{`/`}}` form understood by
+/// `strfmt`.  Any other backslash is left as-is.
+fn unescape_format_str(format_str: &str) -> String {
+    let mut out = String::with_capacity(format_str.len());
+    let mut chars = format_str.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.peek() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('\\') => out.push('\\'),
+            Some('{') => out.push_str("{{"),
+            Some('}') => out.push_str("}}"),
+            _ => {
+                out.push('\\');
+                continue;
+            }
+        }
+        chars.next();
+    }
+    out
+}
+
 pub fn strformat_record<'a>(
     fmtstr: &str,
     vars: impl Fn(&str) -> &'a Value,
@@ -237,7 +343,28 @@ impl RecordPrinter for FormatPrinter {
     }
 }
 
-struct JsonPrinter {}
+struct JsonPrinter {
+    options: JsonOptions,
+    /// Records waiting to be written as one array, when `options.array` is set.
+    records: Vec<serde_json::Value>,
+}
+
+impl JsonPrinter {
+    fn new(options: &JsonOptions) -> Self {
+        JsonPrinter {
+            options: options.clone(),
+            records: Vec::new(),
+        }
+    }
+
+    fn write<T: serde::Serialize + ?Sized>(&self, out: &mut dyn Write, value: &T) {
+        if self.options.pretty {
+            serde_json::to_writer_pretty(out, value).expect("failed to format");
+        } else {
+            serde_json::to_writer(out, value).expect("failed to format");
+        }
+    }
+}
 
 impl RecordPrinter for JsonPrinter {
     fn print(
@@ -246,15 +373,753 @@ impl RecordPrinter for JsonPrinter {
         row: &Record,
         _display_config: &DisplayConfig,
     ) -> io::Result<()> {
-        serde_json::to_writer(out, row).expect("failed to format");
+        if self.options.array {
+            self.records
+                .push(serde_json::to_value(row).expect("failed to format"));
+        } else {
+            self.write(out, row);
+        }
+        Ok(())
+    }
+
+    fn is_buffered(&self) -> bool {
+        self.options.array
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        if self.options.array {
+            self.write(out, &self.records);
+            writeln!(out)?;
+            self.records.clear();
+        }
         Ok(())
     }
 }
 
+/// Aggregates are always written as a single array, as they only have one row per group.
 impl AggregatePrinter for JsonPrinter {
     fn print(&mut self, row: &Aggregate, _display_config: &DisplayConfig) -> String {
-        let out = serde_json::to_string(row).expect("failed to format");
-        out + "\n"
+        let mut out = vec![];
+        self.write(&mut out, row);
+        writeln!(&mut out).unwrap();
+        String::from_utf8(out).expect("invalid UTF-8 produced")
+    }
+}
+
+/// Writes aggregates in the Prometheus text exposition format, e.g. for the node_exporter
+/// textfile collector.  Each aggregate column becomes a gauge named `agrind_<column>` with a
+/// sample per group, labelled with the group's key columns.
+struct PrometheusPrinter {
+    key_columns: Vec<String>,
+    /// Columns that had a non-numeric value, which is only reported once per column.
+    reported: HashSet<String>,
+}
+
+impl PrometheusPrinter {
+    fn new(key_columns: &[String]) -> Self {
+        PrometheusPrinter {
+            key_columns: key_columns.to_vec(),
+            reported: HashSet::new(),
+        }
+    }
+
+    /// Replace the characters that aren't allowed in a metric or label name with `_`.
+    fn name(name: &str) -> String {
+        let name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        if name.starts_with(|c: char| c.is_ascii_digit()) || name.is_empty() {
+            format!("_{}", name)
+        } else {
+            name
+        }
+    }
+
+    fn label_value(value: Option<&Value>) -> String {
+        let value = match value {
+            None | Some(Value::None) => String::new(),
+            Some(value) => value.to_string(),
+        };
+        let mut escaped = String::with_capacity(value.len());
+        for c in value.chars() {
+            match c {
+                '\\' => escaped.push_str("\\\\"),
+                '"' => escaped.push_str("\\\""),
+                '\n' => escaped.push_str("\\n"),
+                c => escaped.push(c),
+            }
+        }
+        escaped
+    }
+
+    fn sample_value(value: &Value) -> Option<String> {
+        let value = match value {
+            Value::Int(i) => return Some(i.to_string()),
+            Value::Float(f) => f.into_inner(),
+            _ => return None,
+        };
+        Some(if value.is_nan() {
+            "NaN".to_string()
+        } else if value.is_infinite() {
+            if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+        } else {
+            value.to_string()
+        })
+    }
+}
+
+impl AggregatePrinter for PrometheusPrinter {
+    fn print(&mut self, row: &Aggregate, _display_config: &DisplayConfig) -> String {
+        let (labels, metrics): (Vec<_>, Vec<_>) = row
+            .columns
+            .iter()
+            .partition(|column| self.key_columns.contains(column));
+        let mut out = String::new();
+        for column in metrics {
+            let metric = format!(
+                "agrind_{}",
+                PrometheusPrinter::name(column.trim_start_matches('_'))
+            );
+            out.push_str(&format!("# TYPE {} gauge\n", metric));
+            for data in &row.data {
+                let value = data.get(column).unwrap_or(&Value::None);
+                let value = match PrometheusPrinter::sample_value(value) {
+                    Some(value) => value,
+                    None => {
+                        if self.reported.insert(column.clone()) {
+                            eprintln!(
+                                "error: only numbers can be written as Prometheus samples, \
+                                 found {} in {}",
+                                value, column
+                            );
+                        }
+                        continue;
+                    }
+                };
+                out.push_str(&metric);
+                if !labels.is_empty() {
+                    let labels = labels
+                        .iter()
+                        .map(|label| {
+                            format!(
+                                "{}=\"{}\"",
+                                PrometheusPrinter::name(label),
+                                PrometheusPrinter::label_value(data.get(*label))
+                            )
+                        })
+                        .join(",");
+                    out.push_str(&format!("{{{}}}", labels));
+                }
+                out.push_str(&format!(" {}\n", value));
+            }
+        }
+        out
+    }
+}
+
+/// The blocks that bars are drawn with, from an eighth of a cell to a whole one.
+const BAR_BLOCKS: [char; 8] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉', '█'];
+
+/// Draws aggregates as a horizontal bar chart: a bar for each group, next to its key columns and
+/// its value, with a length proportional to the value.  The charted column is the one given or
+/// otherwise the first numeric column that isn't a key, e.g. `_count` in `count by status`.
+struct ChartPrinter {
+    column: Option<String>,
+    key_columns: Vec<String>,
+    /// The width of the whole chart, which is the terminal's when there is one.
+    width: usize,
+    /// Whether a missing column has been reported, which is only done once.
+    reported: bool,
+}
+
+impl ChartPrinter {
+    /// The width charts are drawn to when the output isn't a terminal.
+    const DEFAULT_WIDTH: usize = 80;
+    /// The least room left for the bars, however wide the labels are.
+    const MIN_BAR_WIDTH: usize = 10;
+
+    fn new(column: Option<String>, key_columns: &[String], width: Option<usize>) -> Self {
+        ChartPrinter {
+            column,
+            key_columns: key_columns.to_vec(),
+            width: width.unwrap_or(ChartPrinter::DEFAULT_WIDTH),
+            reported: false,
+        }
+    }
+
+    fn number(value: Option<&Value>) -> Option<f64> {
+        match value {
+            Some(Value::Int(i)) => Some(*i as f64),
+            Some(Value::Float(f)) => Some(f.into_inner()),
+            _ => None,
+        }
+    }
+
+    fn value_column<'a>(&mut self, agg: &'a Aggregate) -> Option<&'a String> {
+        match &self.column {
+            Some(column) => {
+                let found = agg.columns.iter().find(|c| *c == column);
+                if found.is_none() && !self.reported {
+                    self.reported = true;
+                    eprintln!(
+                        "error: can't chart `{}`, which isn't a column of the aggregate: {}",
+                        column,
+                        agg.columns.iter().join(", ")
+                    );
+                }
+                found
+            }
+            None => agg.columns.iter().find(|column| {
+                !self.key_columns.contains(column)
+                    && agg
+                        .data
+                        .iter()
+                        .any(|row| ChartPrinter::number(row.get(*column)).is_some())
+            }),
+        }
+    }
+
+    /// A bar that's `eighths` eighths of a cell long.
+    fn bar(eighths: usize) -> String {
+        let mut bar = BAR_BLOCKS[7].to_string().repeat(eighths / 8);
+        match eighths % 8 {
+            0 => {}
+            rest => bar.push(BAR_BLOCKS[rest - 1]),
+        }
+        bar
+    }
+}
+
+impl AggregatePrinter for ChartPrinter {
+    fn print(&mut self, agg: &Aggregate, display_config: &DisplayConfig) -> String {
+        let value_column = match self.value_column(agg) {
+            Some(column) => column,
+            None => return String::new(),
+        };
+        let labels: Vec<&String> = agg
+            .columns
+            .iter()
+            .filter(|column| self.key_columns.contains(column) && *column != value_column)
+            .collect();
+        let display = |value: Option<&Value>| match value {
+            None | Some(Value::None) => String::new(),
+            Some(value) => ValueDisplay::new(value, display_config).to_string(),
+        };
+        let rows: Vec<(Vec<String>, String, Option<f64>)> = agg
+            .data
+            .iter()
+            .map(|row| {
+                let value = row.get(value_column);
+                (
+                    labels
+                        .iter()
+                        .map(|label| display(row.get(*label)))
+                        .collect(),
+                    display(value),
+                    ChartPrinter::number(value),
+                )
+            })
+            .collect();
+        let label_widths: Vec<usize> = labels
+            .iter()
+            .enumerate()
+            .map(|(i, label)| {
+                rows.iter()
+                    .map(|(texts, _, _)| texts[i].chars().count())
+                    .chain(std::iter::once(label.chars().count()))
+                    .max()
+                    .unwrap_or(0)
+            })
+            .collect();
+        let value_width = rows
+            .iter()
+            .map(|(_, text, _)| text.chars().count())
+            .chain(std::iter::once(value_column.chars().count()))
+            .max()
+            .unwrap_or(0);
+        let used: usize = label_widths.iter().map(|width| width + 2).sum::<usize>() + value_width;
+        let bar_width = self
+            .width
+            .saturating_sub(used + 2)
+            .max(ChartPrinter::MIN_BAR_WIDTH);
+        let max = rows
+            .iter()
+            .filter_map(|(_, _, number)| *number)
+            .fold(0.0, f64::max);
+
+        let line = |texts: &[String], value: &str, bar: &str| {
+            let mut line = String::new();
+            for (text, width) in texts.iter().zip(&label_widths) {
+                line.push_str(text);
+                line.push_str(&" ".repeat(width + 2 - text.chars().count()));
+            }
+            line.push_str(&" ".repeat(value_width - value.chars().count()));
+            line.push_str(value);
+            line.push_str("  ");
+            line.push_str(bar);
+            line.trim_end().to_string() + "\n"
+        };
+        let header: Vec<String> = labels.iter().map(|label| label.to_string()).collect();
+        let mut out = line(&header, value_column, "");
+        for (texts, value, number) in &rows {
+            let eighths = match number {
+                Some(number) if *number > 0.0 && max.is_finite() => {
+                    (number / max * (bar_width * 8) as f64).round() as usize
+                }
+                _ => 0,
+            };
+            out.push_str(&line(texts, value, &ChartPrinter::bar(eighths)));
+        }
+        out
+    }
+}
+
+/// The blocks that sparklines are drawn with, from the lowest value to the highest.
+const SPARK_BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// The value of a sparkline's group in each time bucket.
+type Buckets<'a> = HashMap<DateTime<Utc>, &'a Value>;
+
+/// Draws aggregates that are split into time buckets, like `count by _timeslice, status`, as a
+/// sparkline for each group of the other key columns, with a block for each bucket.  Each
+/// sparkline is scaled between its group's smallest and largest values, which are shown next to
+/// it.  Buckets that a group has no row for are left blank, and only the latest buckets are drawn
+/// when there are more than fit.
+struct SparklinePrinter {
+    /// Picks the column that's drawn, like a bar chart does.
+    chart: ChartPrinter,
+    /// Whether a missing time column has been reported, which is only done once.
+    reported: bool,
+}
+
+impl SparklinePrinter {
+    /// The most buckets that gaps are filled in up to.  Past that, only the buckets that are in
+    /// the aggregate are drawn.
+    const MAX_BUCKETS: i64 = 10_000;
+
+    fn new(column: Option<String>, key_columns: &[String], width: Option<usize>) -> Self {
+        SparklinePrinter {
+            chart: ChartPrinter::new(column, key_columns, width),
+            reported: false,
+        }
+    }
+
+    /// The buckets, in order, with any that are missing between them filled in when they're
+    /// evenly spaced.
+    fn buckets(times: BTreeSet<DateTime<Utc>>) -> Vec<DateTime<Utc>> {
+        let times: Vec<_> = times.into_iter().collect();
+        let step = times.windows(2).map(|pair| pair[1] - pair[0]).min();
+        let (first, last, step) = match (times.first(), times.last(), step) {
+            (Some(first), Some(last), Some(step)) => (*first, *last, step),
+            _ => return times,
+        };
+        let regular = times.iter().all(|time| {
+            (*time - first)
+                .num_nanoseconds()
+                .zip(step.num_nanoseconds())
+                .is_some_and(|(offset, step)| offset % step == 0)
+        });
+        let count = (last - first).num_nanoseconds().zip(step.num_nanoseconds());
+        match count {
+            Some((span, step)) if regular && span / step < SparklinePrinter::MAX_BUCKETS => (0
+                ..=span / step)
+                .map(|i| first + chrono::Duration::nanoseconds(i * step))
+                .collect(),
+            _ => times,
+        }
+    }
+}
+
+impl AggregatePrinter for SparklinePrinter {
+    fn print(&mut self, agg: &Aggregate, display_config: &DisplayConfig) -> String {
+        let time_column = agg.columns.iter().find(|column| {
+            self.chart.key_columns.contains(column)
+                && agg
+                    .data
+                    .iter()
+                    .any(|row| matches!(row.get(*column), Some(Value::DateTime(_))))
+        });
+        let time_column = match time_column {
+            Some(column) => column,
+            None => {
+                if !self.reported {
+                    self.reported = true;
+                    eprintln!(
+                        "error: sparklines need the aggregate to be split into time buckets, \
+                         e.g. by `_timeslice` from `timeslice`"
+                    );
+                }
+                return String::new();
+            }
+        };
+        let value_column = match self.chart.value_column(agg) {
+            Some(column) => column,
+            None => return String::new(),
+        };
+        let labels: Vec<&String> = agg
+            .columns
+            .iter()
+            .filter(|column| {
+                self.chart.key_columns.contains(column)
+                    && *column != time_column
+                    && *column != value_column
+            })
+            .collect();
+        let display = |value: Option<&Value>| match value {
+            None | Some(Value::None) => String::new(),
+            Some(value) => ValueDisplay::new(value, display_config).to_string(),
+        };
+
+        // The value of each group in each bucket, with the groups in the order they're first seen.
+        let mut groups: Vec<(Vec<String>, Buckets)> = vec![];
+        let mut group_index: HashMap<Vec<String>, usize> = HashMap::new();
+        let mut times = BTreeSet::new();
+        for row in &agg.data {
+            let time = match row.get(time_column) {
+                Some(Value::DateTime(time)) => *time,
+                _ => continue,
+            };
+            times.insert(time);
+            let texts: Vec<String> = labels
+                .iter()
+                .map(|label| display(row.get(*label)))
+                .collect();
+            let index = *group_index.entry(texts.clone()).or_insert_with(|| {
+                groups.push((texts, HashMap::new()));
+                groups.len() - 1
+            });
+            if let Some(value) = row.get(value_column) {
+                groups[index].1.insert(time, value);
+            }
+        }
+
+        let stats: Vec<Option<(&Value, &Value)>> = groups
+            .iter()
+            .map(|(_, values)| {
+                let numbers = || {
+                    values
+                        .values()
+                        .filter_map(|value| ChartPrinter::number(Some(value)).map(|n| (n, *value)))
+                };
+                let min = numbers().min_by(|a, b| a.0.total_cmp(&b.0))?;
+                let max = numbers().max_by(|a, b| a.0.total_cmp(&b.0))?;
+                Some((min.1, max.1))
+            })
+            .collect();
+        let column_width = |header: &str, texts: &mut dyn Iterator<Item = String>| {
+            texts
+                .map(|text| text.chars().count())
+                .chain(std::iter::once(header.chars().count()))
+                .max()
+                .unwrap_or(0)
+        };
+        let label_widths: Vec<usize> = (0..labels.len())
+            .map(|i| {
+                column_width(
+                    labels[i],
+                    &mut groups.iter().map(|(texts, _)| texts[i].clone()),
+                )
+            })
+            .collect();
+        let min_width = column_width(
+            "min",
+            &mut stats.iter().map(|s| display(s.map(|(min, _)| min))),
+        );
+        let max_width = column_width(
+            "max",
+            &mut stats.iter().map(|s| display(s.map(|(_, max)| max))),
+        );
+        let used: usize =
+            label_widths.iter().map(|width| width + 2).sum::<usize>() + min_width + max_width + 4;
+        let room = self
+            .chart
+            .width
+            .saturating_sub(used)
+            .max(ChartPrinter::MIN_BAR_WIDTH);
+        let buckets = SparklinePrinter::buckets(times);
+        let buckets = &buckets[buckets.len().saturating_sub(room)..];
+
+        let line = |texts: &[String], min: &str, max: &str, spark: &str| {
+            let mut line = String::new();
+            for (text, width) in texts.iter().zip(&label_widths) {
+                line.push_str(text);
+                line.push_str(&" ".repeat(width + 2 - text.chars().count()));
+            }
+            line.push_str(&format!(
+                "{:>min_width$}  {:>max_width$}  {}",
+                min,
+                max,
+                spark,
+                min_width = min_width,
+                max_width = max_width
+            ));
+            line.trim_end().to_string() + "\n"
+        };
+        let header: Vec<String> = labels.iter().map(|label| label.to_string()).collect();
+        let mut out = line(&header, "min", "max", value_column);
+        for ((texts, values), stats) in groups.iter().zip(&stats) {
+            let (low, high) = match stats {
+                Some((min, max)) => (
+                    ChartPrinter::number(Some(min)).unwrap_or(0.0),
+                    ChartPrinter::number(Some(max)).unwrap_or(0.0),
+                ),
+                None => (0.0, 0.0),
+            };
+            let spark: String = buckets
+                .iter()
+                .map(
+                    |time| match ChartPrinter::number(values.get(time).copied()) {
+                        Some(n) if high > low => {
+                            SPARK_BLOCKS[((n - low) / (high - low) * 7.0).round() as usize]
+                        }
+                        Some(_) => SPARK_BLOCKS[0],
+                        None => ' ',
+                    },
+                )
+                .collect();
+            out.push_str(&line(
+                texts,
+                &display(stats.map(|(min, _)| min)),
+                &display(stats.map(|(_, max)| max)),
+                &spark,
+            ));
+        }
+        if let (Some(first), Some(last)) = (buckets.first(), buckets.last()) {
+            out.push_str(&format!(
+                "{} to {}\n",
+                display(Some(&Value::DateTime(*first))),
+                display(Some(&Value::DateTime(*last)))
+            ));
+        }
+        out
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TableFormat {
+    /// A GitHub-flavored markdown table.
+    Markdown,
+    /// A plain HTML `<table>`, without any styling.
+    Html,
+}
+
+/// A value as it's shown in a table cell.
+struct Cell {
+    text: String,
+    numeric: bool,
+}
+
+impl Cell {
+    fn new(value: &Value, display_config: &DisplayConfig) -> Self {
+        match value {
+            Value::None => Cell {
+                text: String::new(),
+                numeric: false,
+            },
+            value => Cell {
+                text: ValueDisplay::new(value, display_config).to_string(),
+                numeric: matches!(value, Value::Int(_) | Value::Float(_)),
+            },
+        }
+    }
+}
+
+/// Writes records and aggregates as a single markdown or HTML table.  Records are held until the
+/// end, since their columns are only known once every record has been seen.
+struct TablePrinter {
+    format: TableFormat,
+    rows: Vec<HashMap<String, Cell>>,
+    raw: Vec<String>,
+}
+
+impl TablePrinter {
+    fn new(format: TableFormat) -> Self {
+        TablePrinter {
+            format,
+            rows: Vec::new(),
+            raw: Vec::new(),
+        }
+    }
+
+    fn render(&self, columns: &[String], rows: &[HashMap<String, Cell>]) -> String {
+        match self.format {
+            TableFormat::Markdown => TablePrinter::markdown(columns, rows),
+            TableFormat::Html => TablePrinter::html(columns, rows),
+        }
+    }
+
+    fn markdown(columns: &[String], rows: &[HashMap<String, Cell>]) -> String {
+        fn escape(s: &str) -> String {
+            s.replace('|', "\\|")
+                .replace("\r\n", "<br>")
+                .replace('\n', "<br>")
+        }
+        let header: Vec<String> = columns.iter().map(|column| escape(column)).collect();
+        let body: Vec<Vec<Option<String>>> = rows
+            .iter()
+            .map(|row| {
+                columns
+                    .iter()
+                    .map(|column| row.get(column).map(|cell| escape(&cell.text)))
+                    .collect()
+            })
+            .collect();
+        // Numbers are right aligned, as long as every value in the column is one.
+        let numeric: Vec<bool> = columns
+            .iter()
+            .map(|column| {
+                let mut cells = rows
+                    .iter()
+                    .filter_map(|row| row.get(column))
+                    .filter(|cell| !cell.text.is_empty())
+                    .peekable();
+                cells.peek().is_some() && cells.all(|cell| cell.numeric)
+            })
+            .collect();
+        let widths: Vec<usize> = header
+            .iter()
+            .enumerate()
+            .map(|(i, name)| {
+                body.iter()
+                    .filter_map(|row| row[i].as_ref())
+                    .map(|text| text.chars().count())
+                    .chain(std::iter::once(name.chars().count()))
+                    .max()
+                    .unwrap_or(0)
+                    .max(3)
+            })
+            .collect();
+        let line = |cells: &mut dyn Iterator<Item = String>| format!("| {} |\n", cells.join(" | "));
+        let pad = |text: &str, i: usize| {
+            let fill = " ".repeat(widths[i] - text.chars().count());
+            if numeric[i] {
+                format!("{}{}", fill, text)
+            } else {
+                format!("{}{}", text, fill)
+            }
+        };
+
+        let mut out = line(&mut header.iter().enumerate().map(|(i, name)| pad(name, i)));
+        out.push_str(&line(&mut widths.iter().enumerate().map(|(i, width)| {
+            if numeric[i] {
+                format!("{}:", "-".repeat(width - 1))
+            } else {
+                "-".repeat(*width)
+            }
+        })));
+        for row in &body {
+            out.push_str(&line(
+                &mut row
+                    .iter()
+                    .enumerate()
+                    .map(|(i, text)| pad(text.as_deref().unwrap_or(""), i)),
+            ));
+        }
+        out
+    }
+
+    fn html(columns: &[String], rows: &[HashMap<String, Cell>]) -> String {
+        fn escape(s: &str) -> String {
+            let mut escaped = String::with_capacity(s.len());
+            for c in s.chars() {
+                match c {
+                    '&' => escaped.push_str("&amp;"),
+                    '<' => escaped.push_str("&lt;"),
+                    '>' => escaped.push_str("&gt;"),
+                    '"' => escaped.push_str("&quot;"),
+                    c => escaped.push(c),
+                }
+            }
+            escaped
+        }
+        let mut out = String::from("<table>\n<thead>\n<tr>");
+        for column in columns {
+            out.push_str(&format!("<th>{}</th>", escape(column)));
+        }
+        out.push_str("</tr>\n</thead>\n<tbody>\n");
+        for row in rows {
+            out.push_str("<tr>");
+            for column in columns {
+                let text = row.get(column).map(|cell| cell.text.as_str());
+                out.push_str(&format!("<td>{}</td>", escape(text.unwrap_or(""))));
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str("</tbody>\n</table>\n");
+        out
+    }
+}
+
+impl RecordPrinter for TablePrinter {
+    fn print(
+        &mut self,
+        _out: &mut dyn Write,
+        row: &Record,
+        display_config: &DisplayConfig,
+    ) -> io::Result<()> {
+        self.rows.push(
+            row.data
+                .iter()
+                .map(|(k, v)| (k.clone(), Cell::new(v, display_config)))
+                .collect(),
+        );
+        self.raw
+            .push(row.raw.trim_end_matches(&['\r', '\n'][..]).to_string());
+        Ok(())
+    }
+
+    fn is_buffered(&self) -> bool {
+        true
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> io::Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let columns: Vec<String> = self
+            .rows
+            .iter()
+            .flat_map(|row| row.keys())
+            .unique()
+            .sorted()
+            .cloned()
+            .collect();
+        let table = if columns.is_empty() {
+            // Nothing was parsed out of the records, so show the lines themselves.
+            let rows: Vec<_> = self
+                .raw
+                .iter()
+                .map(|raw| {
+                    let cell = Cell {
+                        text: raw.clone(),
+                        numeric: false,
+                    };
+                    std::iter::once(("_raw".to_string(), cell)).collect()
+                })
+                .collect();
+            self.render(&["_raw".to_string()], &rows)
+        } else {
+            self.render(&columns, &self.rows)
+        };
+        self.rows.clear();
+        self.raw.clear();
+        out.write_all(table.as_bytes())
+    }
+}
+
+impl AggregatePrinter for TablePrinter {
+    fn print(&mut self, row: &Aggregate, display_config: &DisplayConfig) -> String {
+        let rows: Vec<HashMap<String, Cell>> = row
+            .data
+            .iter()
+            .map(|data| {
+                data.iter()
+                    .map(|(k, v)| (k.clone(), Cell::new(v, display_config)))
+                    .collect()
+            })
+            .collect();
+        self.render(&row.columns, &rows)
     }
 }
 
@@ -269,17 +1134,60 @@ struct PrettyPrinter {
 // and display "..." instead
 const ELLIPSIS: &str = "…";
 
+/// The number of terminal columns the text takes up: wide characters, like CJK and most emoji,
+/// take up two, and combining characters none.
+fn display_width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+/// Pad the text with spaces on the right until it takes up `width` columns.
+fn pad_to_width(text: &str, width: usize) -> String {
+    format!(
+        "{}{}",
+        text,
+        " ".repeat(width.saturating_sub(display_width(text)))
+    )
+}
+
+/// The longest start of the text that fits in `width` columns, without splitting a character.
+fn truncate_to_width(text: &str, width: usize) -> &str {
+    let mut used = 0;
+    for (i, c) in text.char_indices() {
+        used += UnicodeWidthChar::width(c).unwrap_or(0);
+        if used > width {
+            return &text[..i];
+        }
+    }
+    text
+}
+
+/// Style the text of a cell, leaving its padding alone so the columns still line up, then switch
+/// back to the style of the row it's in.
+fn paint(cell: &str, style: &Style, row_style: &Style) -> String {
+    let text = cell.trim_matches(' ');
+    if style.is_plain() || text.is_empty() {
+        return cell.to_string();
+    }
+    let start = cell.len() - cell.trim_start_matches(' ').len();
+    format!(
+        "{}{}{}{}{}{}",
+        &cell[..start],
+        style.sgr(),
+        text,
+        RESET,
+        row_style.sgr(),
+        &cell[start + text.len()..]
+    )
+}
+
 fn format_with_ellipsis<S: Into<String>>(inp: S, limit: usize) -> String {
     let inp = inp.into();
-    if inp.chars().count() > limit {
-        format!(
-            "{str:.prelimit$}{ellipsis} ",
-            str = inp,
-            prelimit = limit - ELLIPSIS.chars().count() - 1,
-            ellipsis = ELLIPSIS
-        )
+    if display_width(&inp) > limit {
+        let prelimit = limit.saturating_sub(display_width(ELLIPSIS) + 1);
+        let cut = format!("{}{} ", truncate_to_width(&inp, prelimit), ELLIPSIS);
+        pad_to_width(&cut, limit)
     } else {
-        format!("{:limit$}", inp, limit = limit)
+        pad_to_width(&inp, limit)
     }
 }
 
@@ -293,15 +1201,50 @@ impl PrettyPrinter {
         }
     }
 
+    /// The value as it's shown in the table, cut short with an ellipsis if it's wider than the
+    /// maximum column width.
+    fn render_value(&self, value: &data::Value) -> String {
+        let rendered = value.render(&self.render_config.display_config);
+        match self.render_config.table.max_column_width {
+            Some(max) if display_width(&rendered) > max => {
+                let prelimit = max.saturating_sub(display_width(ELLIPSIS));
+                format!("{}{}", truncate_to_width(&rendered, prelimit), ELLIPSIS)
+            }
+            _ => rendered,
+        }
+    }
+
+    /// Drop the columns that weren't picked to be shown, if only some were.
+    fn select_columns<'a>(
+        &self,
+        data: &'a HashMap<String, data::Value>,
+    ) -> Cow<'a, HashMap<String, data::Value>> {
+        match self.render_config.table.columns {
+            Some(ref columns) => Cow::Owned(
+                data.iter()
+                    .filter(|(column, _)| columns.contains(column))
+                    .map(|(column, value)| (column.clone(), value.clone()))
+                    .collect(),
+            ),
+            None => Cow::Borrowed(data),
+        }
+    }
+
+    /// Sort columns into the order they were picked in, or by name if they weren't.
+    fn order_columns(&self, columns: &mut [String]) {
+        match self.render_config.table.columns {
+            Some(ref order) => columns.sort_by_key(|column| order.iter().position(|c| c == column)),
+            None => columns.sort(),
+        }
+    }
+
     fn compute_column_widths(&self, data: &HashMap<String, data::Value>) -> HashMap<String, usize> {
         data.iter()
             .map(|(column_name, value)| {
                 let current_width = *self.column_widths.get(column_name).unwrap_or(&0);
                 // 1. If the width would increase, set it to max_buffer
-                let value_length = value
-                    .render(&self.render_config.display_config)
-                    .len()
-                    .max(column_name.len());
+                let value_length =
+                    display_width(&self.render_value(value)).max(display_width(column_name));
                 let min_column_width = value_length + self.render_config.min_buffer;
                 let new_column_width = if min_column_width > current_width {
                     // if we're resizing, go to the max
@@ -320,7 +1263,7 @@ impl PrettyPrinter {
             .filter(|key| !self.column_order.contains(key))
             .cloned()
             .collect();
-        new_keys.sort();
+        self.order_columns(&mut new_keys);
         new_keys
     }
 
@@ -328,7 +1271,7 @@ impl PrettyPrinter {
         column_widths
             .iter()
             .map(&|(key, size): (&String, &usize)| {
-                let key_len: usize = key.len();
+                let key_len: usize = display_width(key);
                 size + key_len + 3
             })
             .sum()
@@ -343,42 +1286,52 @@ impl PrettyPrinter {
     }
 
     fn format_record_as_columns(&mut self, record: &data::Record) -> String {
-        let new_column_widths = self.compute_column_widths(&(record.data));
+        // A line read from one of several files that hasn't been parsed is written like `grep`
+        // does, prefixed by the name of its file.
+        if let (1, Some(file)) = (record.data.len(), record.data.get("__file__")) {
+            return format!("{}:{}", file, record.raw.trim_end());
+        }
+        let data = self.select_columns(&record.data);
+        let new_column_widths = self.compute_column_widths(&data);
         self.column_widths.extend(new_column_widths);
-        let new_columns = self.new_columns(&(record.data));
+        let new_columns = self.new_columns(&data);
         self.column_order.extend(new_columns);
+        if self.render_config.table.columns.is_some() {
+            let mut column_order = std::mem::take(&mut self.column_order);
+            self.order_columns(&mut column_order);
+            self.column_order = column_order;
+        }
         if self.column_order.is_empty() {
             return record.raw.trim_end().to_string();
         }
 
         let no_padding = if self.overflows_term() {
             self.column_widths = HashMap::new();
-            self.column_widths = self.compute_column_widths(&(record.data));
+            self.column_widths = self.compute_column_widths(&data);
             self.column_order = Vec::new();
-            self.column_order = self.new_columns(&(record.data));
+            self.column_order = self.new_columns(&data);
             self.overflows_term()
         } else {
             false
         };
         let mut strs = self.column_order.iter().map(|column_name| {
-            let value = record.data.get(column_name);
+            let value = data.get(column_name);
 
             let unpadded = match value {
-                Some(value) => format!(
-                    "[{}={}]",
-                    column_name,
-                    value.render(&self.render_config.display_config)
-                ),
+                Some(value) => format!("[{}={}]", column_name, self.render_value(value)),
                 None => "".to_string(),
             };
-            if no_padding {
+            let cell = if no_padding {
                 unpadded
             } else {
-                format!(
-                    "{:width$}",
-                    unpadded,
-                    width = column_name.len() + 3 + self.column_widths[column_name]
+                pad_to_width(
+                    &unpadded,
+                    display_width(column_name) + 3 + self.column_widths[column_name],
                 )
+            };
+            match value.and_then(|value| self.value_style(column_name, value)) {
+                Some(style) => paint(&cell, style, &Style::default()),
+                None => cell,
             }
         });
 
@@ -428,20 +1381,68 @@ impl PrettyPrinter {
         }
     }
 
+    /// Pad the text out to the column's width, on the left if it's right-aligned.  Right-aligned
+    /// text still leaves the smallest gap before the next column.
+    fn align(&self, text: String, width: usize, right: bool) -> String {
+        let gap = self.render_config.min_buffer;
+        let text_width = display_width(&text);
+        if right && text_width + gap <= width {
+            format!(
+                "{}{}{}",
+                " ".repeat(width - gap - text_width),
+                text,
+                " ".repeat(gap)
+            )
+        } else {
+            format_with_ellipsis(text, width)
+        }
+    }
+
+    /// The style of a value of the column, if the table is colored and the value passes one of
+    /// the theme's thresholds.
+    fn value_style(&self, column: &str, value: &data::Value) -> Option<&Style> {
+        self.render_config
+            .theme
+            .as_ref()?
+            .value_style(column, value)
+    }
+
+    /// Trim the padding from the ends of a line, keeping the padding that right-aligns the first
+    /// column.
+    fn trim<'a>(&self, line: &'a str) -> &'a str {
+        if self.render_config.table.right_align_numbers {
+            line.trim_end()
+        } else {
+            line.trim()
+        }
+    }
+
     fn format_aggregate_row(
         &self,
         columns: &[String],
+        numeric: &HashSet<&String>,
         row: &HashMap<String, data::Value>,
+        row_style: &Style,
     ) -> String {
         let mut row = columns.iter().map(|column_name| {
-            format_with_ellipsis(
-                row.get(column_name)
-                    .unwrap_or(&data::Value::None)
-                    .render(&self.render_config.display_config),
+            let value = row.get(column_name).unwrap_or(&data::Value::None);
+            let cell = self.align(
+                self.render_value(value),
                 self.column_widths[column_name],
-            )
+                numeric.contains(column_name),
+            );
+            match self.value_style(column_name, value) {
+                Some(style) => paint(&cell, style, row_style),
+                None => cell,
+            }
         });
-        row.join("").trim().to_string()
+        let line = row.join("");
+        if row_style.is_plain() {
+            self.trim(&line).to_string()
+        } else {
+            // Shaded rows keep their padding, so the shading spans the whole table.
+            format!("{}{}{}", row_style.sgr(), line, RESET)
+        }
     }
 
     pub fn format_aggregate(&mut self, aggregate: &data::Aggregate) -> String {
@@ -449,27 +1450,60 @@ impl PrettyPrinter {
             return "No data\n".to_string();
         }
 
+        let columns: Vec<String> = match self.render_config.table.columns {
+            Some(ref columns) => columns
+                .iter()
+                .filter(|column| aggregate.columns.contains(column))
+                .cloned()
+                .collect(),
+            None => aggregate.columns.clone(),
+        };
         aggregate.data.iter().for_each(|row| {
-            let new_widths = self.compute_column_widths(row);
+            let new_widths = self.compute_column_widths(&self.select_columns(row));
             self.column_widths.extend(new_widths);
         });
+        // Columns that only hold numbers, which are right-aligned if asked to be.
+        let numeric: HashSet<&String> = columns
+            .iter()
+            .filter(|_| self.render_config.table.right_align_numbers)
+            .filter(|column| {
+                let mut values = aggregate
+                    .data
+                    .iter()
+                    .filter_map(|row| row.get(*column))
+                    .filter(|value| **value != data::Value::None)
+                    .peekable();
+                values.peek().is_some()
+                    && values
+                        .all(|value| matches!(value, data::Value::Int(_) | data::Value::Float(_)))
+            })
+            .collect();
 
-        self.column_widths = self.resize_widths_to_fit(&self.column_widths, &aggregate.columns);
+        self.column_widths = self.resize_widths_to_fit(&self.column_widths, &columns);
         assert!(self.fits_within_term_agg(), "{:?}", self.column_widths);
-        let mut header = aggregate.columns.iter().map(|column_name| {
-            format!(
-                "{:width$}",
-                column_name,
-                width = self.column_widths[column_name]
+        let mut header = columns.iter().map(|column_name| {
+            self.align(
+                column_name.clone(),
+                self.column_widths[column_name],
+                numeric.contains(column_name),
             )
         });
         let header = header.join("");
-        let header_len = header.len();
-        let header = format!("{}\n{}", header.trim(), "-".repeat(header_len));
-        let mut body = aggregate
-            .data
-            .iter()
-            .map(|row| self.format_aggregate_row(&aggregate.columns, row));
+        let header_len = display_width(&header);
+        let plain = Style::default();
+        let (header_style, stripe) = match self.render_config.theme {
+            Some(ref theme) => (&theme.header, &theme.stripe),
+            None => (&plain, &plain),
+        };
+        let header = format!(
+            "{}\n{}",
+            paint(self.trim(&header), header_style, &plain),
+            "-".repeat(header_len)
+        );
+        let mut body = aggregate.data.iter().enumerate().map(|(i, row)| {
+            let row_style = if i % 2 == 1 { stripe } else { &plain };
+            self.format_aggregate_row(&columns, &numeric, row, row_style)
+        });
         let overlength_str = format!("{}\n{}\n", header, body.join("\n"));
         match self.term_size {
             Some(TerminalSize { height, .. }) => {
@@ -487,6 +1521,7 @@ mod tests {
     use crate::data::*;
     use crate::operator::parse::ParseJson;
     use crate::operator::*;
+    use crate::pipeline::TableOptions;
     use maplit::hashmap;
 
     impl LegacyPrinter {
@@ -502,6 +1537,8 @@ mod tests {
             display_config: DisplayConfig { floating_points: 2 },
             min_buffer: 1,
             max_buffer: 4,
+            table: TableOptions::default(),
+            theme: None,
         };
         let display_config = DisplayConfig { floating_points: 2 };
         let mut pp = LegacyPrinter::new(render_config, TerminalConfig::load());
@@ -518,6 +1555,8 @@ mod tests {
             display_config: DisplayConfig { floating_points: 2 },
             min_buffer: 1,
             max_buffer: 4,
+            table: TableOptions::default(),
+            theme: None,
         };
         let mut pp = LegacyPrinter::new(render_config, TerminalConfig::load());
         assert_eq!(
@@ -578,6 +1617,19 @@ mod tests {
         );
     }
 
+    #[test]
+    fn format_escapes() {
+        assert_eq!(unescape_format_str(r"{a}\t{b}\n"), "{a}\t{b}\n");
+        assert_eq!(unescape_format_str(r"\{{a}\}"), "{{{a}}}");
+        assert_eq!(unescape_format_str(r"c:\\dir\x\"), r"c:\dir\x\");
+
+        let rec = Record::new(r#"{"a": 1, "b": "two"}"#);
+        let rec = ParseJson::new(None).process(rec).unwrap().unwrap();
+        let display_config = DisplayConfig { floating_points: 2 };
+        let mut pp = FormatPrinter::new(r"{a}\t\{{b}\}".to_string()).unwrap();
+        assert_eq!(pp.print_str(&rec, &display_config), "1\t{two}");
+    }
+
     #[test]
     fn pretty_print_record_too_long() {
         let rec = Record::new(r#"{"k1": 5, "k2": 5.5000001, "k3": "str"}"#);
@@ -588,6 +1640,8 @@ mod tests {
             display_config: DisplayConfig { floating_points: 2 },
             min_buffer: 1,
             max_buffer: 4,
+            table: TableOptions::default(),
+            theme: None,
         };
         let mut pp = LegacyPrinter::new(
             render_config,
@@ -634,6 +1688,8 @@ mod tests {
                 display_config: DisplayConfig { floating_points: 2 },
                 min_buffer: 2,
                 max_buffer: 4,
+                table: TableOptions::default(),
+                theme: None,
             },
             Some(TerminalSize {
                 width: 100,
@@ -647,6 +1703,135 @@ mod tests {
         );
     }
 
+    #[test]
+    fn pretty_print_aggregate_table_options() {
+        let agg = Aggregate::new(
+            &["kc1".to_string(), "kc2".to_string()],
+            "count".to_string(),
+            &[
+                (
+                    hashmap! {
+                        "kc1".to_string() => "k1".to_string(),
+                        "kc2".to_string() => "k2".to_string()
+                    },
+                    Value::Int(100),
+                ),
+                (
+                    hashmap! {
+                        "kc1".to_string() => "k300".to_string(),
+                        "kc2".to_string() => "k40000".to_string()
+                    },
+                    Value::Int(500),
+                ),
+            ],
+        );
+        let mut pp = PrettyPrinter::new(
+            RenderConfig {
+                display_config: DisplayConfig { floating_points: 2 },
+                min_buffer: 2,
+                max_buffer: 4,
+                table: TableOptions {
+                    columns: Some(vec!["count".to_string(), "kc2".to_string()]),
+                    max_column_width: Some(4),
+                    right_align_numbers: true,
+                },
+                theme: None,
+            },
+            Some(TerminalSize {
+                width: 100,
+                height: 10,
+            }),
+        );
+        assert_eq!(
+            "  count  kc2\n----------------\n    100  k2\n    500  k40…\n",
+            pp.format_aggregate(&agg)
+        );
+    }
+
+    #[test]
+    fn wide_characters() {
+        // CJK takes up two columns and a combining accent none.
+        assert_eq!(display_width("日本"), 4);
+        assert_eq!(display_width("cafe\u{301}"), 4);
+        assert_eq!(pad_to_width("日本", 6), "日本  ");
+        assert_eq!(format_with_ellipsis("日本語テキスト", 6), "日本… ");
+        // A wide character that would only half fit is left out.
+        assert_eq!(format_with_ellipsis("日本語テキスト", 7), "日本…  ");
+        assert_eq!(truncate_to_width("🎉🎉", 3), "🎉");
+    }
+
+    #[test]
+    fn prometheus_aggregate() {
+        let agg = Aggregate::new(
+            &["status".to_string(), "path".to_string()],
+            "_count".to_string(),
+            &[
+                (
+                    hashmap! {
+                        "status".to_string() => "500".to_string(),
+                        "path".to_string() => "/a\"b\\c\nd".to_string()
+                    },
+                    Value::Int(42),
+                ),
+                (
+                    hashmap! {
+                        "status".to_string() => "200".to_string(),
+                        "path".to_string() => "/".to_string()
+                    },
+                    Value::from_float(1.5),
+                ),
+            ],
+        );
+        let display_config = DisplayConfig { floating_points: 2 };
+        let mut pp = PrometheusPrinter::new(&["status".to_string(), "path".to_string()]);
+        assert_eq!(
+            pp.print(&agg, &display_config),
+            "# TYPE agrind_count gauge\n\
+             agrind_count{status=\"500\",path=\"/a\\\"b\\\\c\\nd\"} 42\n\
+             agrind_count{status=\"200\",path=\"/\"} 1.5\n"
+        );
+        assert_eq!(PrometheusPrinter::name("p99 latency"), "p99_latency");
+        assert_eq!(PrometheusPrinter::name("5xx"), "_5xx");
+    }
+
+    #[test]
+    fn chart_aggregate() {
+        let agg = Aggregate::new(
+            &["status".to_string()],
+            "_count".to_string(),
+            &[
+                (
+                    hashmap! {"status".to_string() => "200".to_string()},
+                    Value::Int(20),
+                ),
+                (
+                    hashmap! {"status".to_string() => "500".to_string()},
+                    Value::Int(3),
+                ),
+                (
+                    hashmap! {"status".to_string() => "404".to_string()},
+                    Value::Int(0),
+                ),
+            ],
+        );
+        let display_config = DisplayConfig { floating_points: 2 };
+        let mut chart = ChartPrinter::new(None, &["status".to_string()], Some(30));
+        assert_eq!(
+            chart.print(&agg, &display_config),
+            "status  _count\n\
+             200         20  ██████████████\n\
+             500          3  ██▏\n\
+             404          0\n"
+        );
+        // There's always room for a bar, however narrow the terminal.
+        let mut chart = ChartPrinter::new(Some("_count".to_string()), &[], Some(5));
+        assert!(chart
+            .print(&agg, &display_config)
+            .contains("20  ██████████\n"));
+        let mut chart = ChartPrinter::new(Some("nope".to_string()), &[], None);
+        assert_eq!(chart.print(&agg, &display_config), "");
+    }
+
     #[test]
     fn pretty_print_aggregate_too_long() {
         let agg = Aggregate::new(
@@ -682,6 +1867,8 @@ mod tests {
                 display_config: DisplayConfig { floating_points: 2 },
                 min_buffer: 2,
                 max_buffer: 4,
+                table: TableOptions::default(),
+                theme: None,
             },
             Some(TerminalSize {
                 width: max_width as u16,
@@ -705,6 +1892,50 @@ mod tests {
         );
     }
 
+    #[test]
+    fn markdown_table() {
+        let display_config = DisplayConfig { floating_points: 2 };
+        let mut pp = TablePrinter::new(TableFormat::Markdown);
+        let mut out = vec![];
+        let records = [
+            Record::new("a")
+                .put("k", Value::Int(5))
+                .put("v", Value::None),
+            Record::new("b")
+                .put("k", Value::from_float(10.5))
+                .put("w", Value::from_string("x|y\nz")),
+        ];
+        for rec in &records {
+            RecordPrinter::print(&mut pp, &mut out, rec, &display_config).unwrap();
+        }
+        assert!(out.is_empty());
+        pp.finish(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "|     k | v   | w         |\n\
+             | ----: | --- | --------- |\n\
+             |     5 |     |           |\n\
+             | 10.50 |     | x\\|y<br>z |\n"
+        );
+    }
+
+    #[test]
+    fn html_table_of_raw_lines() {
+        let display_config = DisplayConfig { floating_points: 2 };
+        let mut pp = TablePrinter::new(TableFormat::Html);
+        let mut out = vec![];
+        pp.finish(&mut out).unwrap();
+        assert!(out.is_empty());
+        let rec = Record::new("<a> & \"b\"\n");
+        RecordPrinter::print(&mut pp, &mut out, &rec, &display_config).unwrap();
+        pp.finish(&mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "<table>\n<thead>\n<tr><th>_raw</th></tr>\n</thead>\n<tbody>\n\
+             <tr><td>&lt;a&gt; &amp; &quot;b&quot;</td></tr>\n</tbody>\n</table>\n"
+        );
+    }
+
     #[test]
     fn test_format_with_ellipsis() {
         assert_eq!(format_with_ellipsis("abcde", 4), "ab… ");
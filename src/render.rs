@@ -1,9 +1,13 @@
 use crate::data;
 use anyhow::Error;
+use serde::Deserialize;
+use std::convert::TryFrom;
 use std::io::Write;
 
 use crate::data::DisplayConfig;
+use crate::pipeline::TableOptions;
 use crate::printer::{AggregatePrinter, RecordPrinter};
+use crate::serve::Snapshot;
 use std::time::{Duration, Instant};
 use terminal_size::{terminal_size, Height, Width};
 
@@ -12,6 +16,9 @@ pub struct RenderConfig {
     pub display_config: DisplayConfig,
     pub min_buffer: usize,
     pub max_buffer: usize,
+    pub table: TableOptions,
+    /// How the legacy table is colored, or `None` to leave it plain.
+    pub theme: Option<Theme>,
 }
 
 impl Default for RenderConfig {
@@ -20,6 +27,8 @@ impl Default for RenderConfig {
             display_config: data::DisplayConfig { floating_points: 2 },
             min_buffer: 1,
             max_buffer: 4,
+            table: TableOptions::default(),
+            theme: None,
         }
     }
 }
@@ -35,14 +44,157 @@ impl TerminalConfig {
         let tsize_opt =
             terminal_size().map(|(Width(width), Height(height))| TerminalSize { width, height });
         let is_tty = tsize_opt.is_some();
+        // https://no-color.org
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        let dumb = std::env::var("TERM").is_ok_and(|term| term == "dumb");
         TerminalConfig {
             size: tsize_opt,
             is_tty,
-            color_enabled: is_tty,
+            color_enabled: is_tty && !no_color && !dumb,
         }
     }
 }
 
+/// Whether to color the output, from `--color`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ColorChoice {
+    /// Color the output when it's a terminal, unless `NO_COLOR` is set or it's `TERM=dumb`.
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+/// The escape sequence that turns off any style.
+pub const RESET: &str = "\x1b[0m";
+
+const COLORS: [&str; 8] = [
+    "black", "red", "green", "yellow", "blue", "magenta", "cyan", "white",
+];
+
+/// A style for text, written as space separated words in a theme, like `bold red` or
+/// `black on-bright-yellow`.  An empty style leaves the text as it is.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Style {
+    /// The SGR escape sequence that turns the style on.
+    sgr: String,
+}
+
+impl Style {
+    /// The SGR parameter for a word of a style: an attribute like `bold`, or a color like `red`,
+    /// optionally `bright-`, and with `on-` for the background.
+    fn code(word: &str) -> Option<u8> {
+        let attribute = match word {
+            "bold" => Some(1),
+            "dim" => Some(2),
+            "italic" => Some(3),
+            "underline" => Some(4),
+            "reverse" => Some(7),
+            _ => None,
+        };
+        let (word, background) = match word.strip_prefix("on-") {
+            Some(word) => (word, 10),
+            None => (word, 0),
+        };
+        let (word, bright) = match word.strip_prefix("bright-") {
+            Some(word) => (word, 60),
+            None => (word, 0),
+        };
+        let color = COLORS
+            .iter()
+            .position(|color| *color == word)
+            .map(|i| 30 + i as u8 + bright + background);
+        attribute.or(color)
+    }
+
+    pub fn is_plain(&self) -> bool {
+        self.sgr.is_empty()
+    }
+
+    /// The escape sequence that turns the style on.
+    pub fn sgr(&self) -> &str {
+        &self.sgr
+    }
+}
+
+impl TryFrom<String> for Style {
+    type Error = String;
+
+    fn try_from(words: String) -> Result<Self, String> {
+        let codes = words
+            .split_whitespace()
+            .map(|word| {
+                Style::code(word)
+                    .map(|code| code.to_string())
+                    .ok_or_else(|| format!("unknown style `{}`", word))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Style {
+            sgr: if codes.is_empty() {
+                String::new()
+            } else {
+                format!("\x1b[{}m", codes.join(";"))
+            },
+        })
+    }
+}
+
+/// Picks a style for the values of a column that are above and/or below a limit, like a `p99`
+/// that's over its SLO.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Threshold {
+    pub column: String,
+    pub above: Option<f64>,
+    pub below: Option<f64>,
+    pub style: Style,
+}
+
+/// How the legacy table is colored, read from the TOML file given to `--theme`.
+#[derive(Clone, Debug, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Theme {
+    /// The style of the header of an aggregate.
+    pub header: Style,
+    /// The style of every other row of an aggregate.
+    pub stripe: Style,
+    /// Styles for numeric values, by column.  The first threshold that a value passes picks its
+    /// style.
+    #[serde(rename = "threshold")]
+    pub thresholds: Vec<Threshold>,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            header: Style::try_from("bold".to_string()).expect("valid style"),
+            stripe: Style::default(),
+            thresholds: vec![],
+        }
+    }
+}
+
+impl Theme {
+    /// The style of a value of the column, if it passes one of the column's thresholds.
+    pub fn value_style(&self, column: &str, value: &data::Value) -> Option<&Style> {
+        let number = match value {
+            data::Value::Int(i) => *i as f64,
+            data::Value::Float(f) => f.into_inner(),
+            _ => return None,
+        };
+        self.thresholds
+            .iter()
+            .find(|threshold| {
+                threshold.column == column
+                    && (threshold.above.is_some() || threshold.below.is_some())
+                    && threshold.above.is_none_or(|above| number > above)
+                    && threshold.below.is_none_or(|below| number < below)
+            })
+            .map(|threshold| &threshold.style)
+    }
+}
+
 #[derive(PartialEq, Eq)]
 pub struct TerminalSize {
     pub height: u16,
@@ -58,7 +210,11 @@ pub struct Renderer {
 
     reset_sequence: String,
     is_tty: bool,
+    term_height: Option<u16>,
     last_print: Option<Instant>,
+    leaderboard: Option<String>,
+    /// Where aggregates are rendered instead of the output, if they're being served.
+    snapshot: Option<Snapshot>,
 }
 
 impl Renderer {
@@ -68,11 +224,16 @@ impl Renderer {
         raw_printer: Box<dyn RecordPrinter + Send>,
         agg_printer: Box<dyn AggregatePrinter + Send>,
         output: Box<dyn Write + Send>,
+        leaderboard: Option<String>,
+        render_once: bool,
     ) -> Self {
         let tsize_opt =
             terminal_size().map(|(Width(width), Height(height))| TerminalSize { width, height });
+        // A dumb terminal can't move the cursor back up to redraw the aggregate in place.
+        let dumb = std::env::var("TERM").is_ok_and(|term| term == "dumb");
         Renderer {
-            is_tty: tsize_opt.is_some(),
+            is_tty: tsize_opt.is_some() && !dumb && !render_once,
+            term_height: tsize_opt.map(|size| size.height),
             raw_printer,
             agg_printer,
             config,
@@ -80,13 +241,45 @@ impl Renderer {
             reset_sequence: "".to_string(),
             last_print: None,
             update_interval,
+            leaderboard,
+            snapshot: None,
         }
     }
 
+    /// Keep the latest rendering of the aggregate in the snapshot, updated at most every
+    /// `update_interval`, instead of writing it to the output.
+    pub fn with_snapshot(mut self, snapshot: Option<Snapshot>) -> Self {
+        self.snapshot = snapshot;
+        self
+    }
+
     pub fn render(&mut self, row: &data::Row, last_row: bool) -> Result<(), Error> {
         match *row {
             data::Row::Aggregate(ref aggregate) => {
-                if !self.is_tty {
+                let ranked;
+                // Only live output is ranked, piped output is left in the query's order.
+                let aggregate = match self.leaderboard {
+                    Some(ref column) if self.is_tty => {
+                        // Leave room for the header, the separator, and the prompt
+                        let max_rows = self.term_height.map(|h| (h as usize).saturating_sub(3));
+                        ranked = leaderboard(aggregate, column, max_rows)?;
+                        &ranked
+                    }
+                    Some(ref column) => {
+                        check_leaderboard_column(aggregate, column)?;
+                        aggregate
+                    }
+                    None => aggregate,
+                };
+                if let Some(snapshot) = &self.snapshot {
+                    if self.should_print() || last_row {
+                        snapshot.set(
+                            self.agg_printer
+                                .final_print(aggregate, &self.config.display_config),
+                        );
+                        self.last_print = Some(Instant::now());
+                    }
+                } else if !self.is_tty {
                     if last_row {
                         let output = self
                             .agg_printer
@@ -112,14 +305,24 @@ impl Renderer {
             data::Row::Record(ref record) => {
                 self.raw_printer
                     .print(&mut self.stdout, record, &self.config.display_config)?;
-                writeln!(&mut self.stdout)?;
+                if !self.raw_printer.is_buffered() {
+                    writeln!(&mut self.stdout)?;
+                }
                 Ok(())
             }
         }
     }
 
+    /// Write out any records the printer has buffered. Called once the last record has been
+    /// rendered.
+    pub fn finish(&mut self) -> Result<(), Error> {
+        self.raw_printer.finish(&mut self.stdout)?;
+        self.stdout.flush()?;
+        Ok(())
+    }
+
     pub fn should_print(&self) -> bool {
-        if !self.is_tty {
+        if !self.is_tty && self.snapshot.is_none() {
             return false;
         }
         self.last_print
@@ -127,3 +330,111 @@ impl Renderer {
             .unwrap_or(true)
     }
 }
+
+/// Sort the rows of an aggregate by the given column, largest first, keeping at most `max_rows`
+/// of them.  Rows with equal values keep their existing order so the display doesn't flicker.
+fn leaderboard(
+    aggregate: &data::Aggregate,
+    column: &str,
+    max_rows: Option<usize>,
+) -> Result<data::Aggregate, Error> {
+    check_leaderboard_column(aggregate, column)?;
+    let mut data = aggregate.data.clone();
+    data.sort_by(|l, r| r.get(column).cmp(&l.get(column)));
+    if let Some(max_rows) = max_rows {
+        data.truncate(max_rows);
+    }
+    Ok(data::Aggregate {
+        columns: aggregate.columns.clone(),
+        data,
+    })
+}
+
+/// It's an error to rank by a column the aggregate doesn't have, even when the output isn't
+/// ranked, so a typo doesn't go unnoticed until the query is run in a terminal.
+fn check_leaderboard_column(aggregate: &data::Aggregate, column: &str) -> Result<(), Error> {
+    if aggregate.columns.iter().any(|c| c == column) {
+        Ok(())
+    } else {
+        Err(anyhow::anyhow!(
+            "can't rank the leaderboard by `{}`, the aggregate only has the columns: {}",
+            column,
+            aggregate.columns.join(", ")
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::{Aggregate, Value};
+    use maplit::hashmap;
+
+    #[test]
+    fn leaderboard_sorts_and_truncates() {
+        let agg = Aggregate::new(
+            &["host".to_string()],
+            "_count".to_string(),
+            &[
+                (
+                    hashmap! {"host".to_string() => "a".to_string()},
+                    Value::Int(1),
+                ),
+                (
+                    hashmap! {"host".to_string() => "b".to_string()},
+                    Value::Int(30),
+                ),
+                (
+                    hashmap! {"host".to_string() => "c".to_string()},
+                    Value::Int(7),
+                ),
+            ],
+        );
+        let ranked = leaderboard(&agg, "_count", Some(2)).unwrap();
+        assert_eq!(ranked.columns, agg.columns);
+        assert_eq!(ranked.data, vec![agg.data[1].clone(), agg.data[2].clone()]);
+
+        let ranked = leaderboard(&agg, "_count", None).unwrap();
+        assert_eq!(ranked.data.len(), 3);
+        assert_eq!(ranked.data[2], agg.data[0]);
+
+        assert_eq!(
+            leaderboard(&agg, "bytes", None).unwrap_err().to_string(),
+            "can't rank the leaderboard by `bytes`, the aggregate only has the columns: host, _count"
+        );
+    }
+
+    #[test]
+    fn theme_styles() {
+        let style = |words: &str| Style::try_from(words.to_string());
+        assert_eq!(style("bold red").unwrap().sgr(), "\x1b[1;31m");
+        assert_eq!(
+            style("black on-bright-yellow").unwrap().sgr(),
+            "\x1b[30;103m"
+        );
+        assert!(style("").unwrap().is_plain());
+        assert_eq!(style("blinky").unwrap_err(), "unknown style `blinky`");
+
+        let theme: Theme = toml::from_str(
+            r#"
+            [[threshold]]
+            column = "p99"
+            above = 250
+            style = "red"
+
+            [[threshold]]
+            column = "p99"
+            above = 100
+            style = "yellow"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(theme.header, style("bold").unwrap());
+        let value_style = |value| theme.value_style("p99", &value).map(Style::sgr);
+        assert_eq!(value_style(Value::Int(300)), Some("\x1b[31m"));
+        assert_eq!(value_style(Value::from_float(120.5)), Some("\x1b[33m"));
+        assert_eq!(value_style(Value::Int(50)), None);
+        assert_eq!(value_style(Value::Str("300".to_string())), None);
+        assert_eq!(theme.value_style("p50", &Value::Int(300)), None);
+    }
+}
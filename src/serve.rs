@@ -0,0 +1,109 @@
+//! Serves the latest rendering of a live aggregate over HTTP, so that Prometheus can scrape the
+//! metrics that `--follow` keeps up to date.  Requests are parsed by `tiny_http`.
+
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use tiny_http::{Header, Method, Request, Response, Server};
+
+/// The latest rendering of an aggregate, shared between the thread that renders it and the
+/// thread that serves it.
+#[derive(Clone, Debug, Default)]
+pub struct Snapshot(Arc<Mutex<String>>);
+
+impl Snapshot {
+    pub fn set(&self, rendered: String) {
+        *self.0.lock().unwrap() = rendered;
+    }
+
+    pub fn get(&self) -> String {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+/// The content type of the Prometheus text exposition format.
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// Start serving the snapshot at `/metrics` on the given address, returning the address that
+/// was bound.  Requests are served one at a time on a background thread.
+pub fn serve_metrics<A: ToSocketAddrs>(addr: A, snapshot: Snapshot) -> io::Result<SocketAddr> {
+    let server = Server::http(addr).map_err(io::Error::other)?;
+    let addr = server
+        .server_addr()
+        .to_ip()
+        .ok_or_else(|| io::Error::other("not listening on an IP address"))?;
+    thread::spawn(move || {
+        for request in server.incoming_requests() {
+            // A client that goes away mid-request only affects that request.
+            let _ = respond(request, &snapshot);
+        }
+    });
+    Ok(addr)
+}
+
+fn respond(request: Request, snapshot: &Snapshot) -> io::Result<()> {
+    let path = request.url().split('?').next().unwrap_or_default();
+    let (status, body) = match (request.method(), path) {
+        (Method::Get, "/metrics") => (200, snapshot.get()),
+        (Method::Get, _) => (404, "Metrics are served at /metrics\n".to_string()),
+        _ => (405, String::new()),
+    };
+    let content_type = Header::from_bytes("Content-Type", CONTENT_TYPE)
+        .expect("the content type is a valid header");
+    request.respond(
+        Response::from_string(body)
+            .with_status_code(status)
+            .with_header(content_type),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    fn get(addr: SocketAddr, request: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.write_all(request.as_bytes()).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn serves_the_latest_snapshot() {
+        let snapshot = Snapshot::default();
+        let addr = serve_metrics("127.0.0.1:0", snapshot.clone()).unwrap();
+        snapshot.set("agrind_count 1\n".to_string());
+        snapshot.set("agrind_count 2\n".to_string());
+
+        let response = get(
+            addr,
+            "GET /metrics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n",
+        );
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.contains("Content-Length: 15\r\n"), "{}", response);
+        assert!(
+            response.ends_with("\r\n\r\nagrind_count 2\n"),
+            "{}",
+            response
+        );
+
+        let response = get(addr, "GET / HTTP/1.1\r\nConnection: close\r\n\r\n");
+        assert!(
+            response.starts_with("HTTP/1.1 404 Not Found\r\n"),
+            "{}",
+            response
+        );
+        let response = get(addr, "POST /metrics HTTP/1.1\r\nConnection: close\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 405"), "{}", response);
+        // Malformed requests are rejected without stopping the server.
+        let response = get(addr, "nonsense\r\n\r\n");
+        assert!(response.starts_with("HTTP/1.1 400"), "{}", response);
+        let response = get(addr, "GET /metrics HTTP/1.1\r\nConnection: close\r\n\r\n");
+        assert!(response.ends_with("agrind_count 2\n"), "{}", response);
+    }
+}
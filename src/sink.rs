@@ -0,0 +1,120 @@
+//! Callback output.
+//!
+//! `OutputMode::Callback` hands each output row to a [`RowSink`] as it's emitted, instead of
+//! rendering it, so programs built on the pipeline get the fields and their typed values rather
+//! than text to parse back out.  Records are passed on as they come out of the operators, and an
+//! aggregate each time it would be drawn; each aggregate replaces the one before it, and the last
+//! one is the result.  Nothing is written to the pipeline's output.
+use std::fmt;
+use std::io;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+use crate::data::{Aggregate, DisplayConfig, Record, Row};
+use crate::printer::{AggregatePrinter, RecordPrinter};
+
+/// Receives the rows output by a pipeline.
+pub trait RowSink: Send {
+    fn row(&mut self, row: Row);
+}
+
+impl<F: FnMut(Row) + Send> RowSink for F {
+    fn row(&mut self, row: Row) {
+        self(row)
+    }
+}
+
+/// A [`RowSink`] that can be given as an output mode.  Clones share the same sink.
+#[derive(Clone)]
+pub struct Callback(Arc<Mutex<dyn RowSink>>);
+
+impl Callback {
+    pub fn new<S: 'static + RowSink>(sink: S) -> Self {
+        Callback(Arc::new(Mutex::new(sink)))
+    }
+
+    fn send(&self, row: Row) {
+        self.0.lock().expect("not poisoned").row(row)
+    }
+}
+
+impl PartialEq for Callback {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl Eq for Callback {}
+
+impl fmt::Debug for Callback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("Callback")
+    }
+}
+
+pub struct CallbackPrinter(Callback);
+
+impl CallbackPrinter {
+    pub fn new(callback: &Callback) -> Self {
+        CallbackPrinter(callback.clone())
+    }
+}
+
+impl RecordPrinter for CallbackPrinter {
+    fn print(
+        &mut self,
+        _out: &mut dyn Write,
+        row: &Record,
+        _display_config: &DisplayConfig,
+    ) -> io::Result<()> {
+        self.0.send(Row::Record(row.clone()));
+        Ok(())
+    }
+
+    // Nothing is written for a record, not even the newline after it.
+    fn is_buffered(&self) -> bool {
+        true
+    }
+}
+
+impl AggregatePrinter for CallbackPrinter {
+    fn print(&mut self, row: &Aggregate, _display_config: &DisplayConfig) -> String {
+        self.0.send(Row::Aggregate(row.clone()));
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data::Value;
+
+    #[test]
+    fn passes_rows_to_the_sink() {
+        let rows = Arc::new(Mutex::new(Vec::new()));
+        let sink = rows.clone();
+        let mut printer = CallbackPrinter::new(&Callback::new(move |row: Row| {
+            sink.lock().unwrap().push(row);
+        }));
+        let rec = Record::new("a").put("status", Value::Int(200));
+        let mut out = vec![];
+        RecordPrinter::print(&mut printer, &mut out, &rec, &DisplayConfig::default()).unwrap();
+        let agg = Aggregate {
+            columns: vec!["_count".to_string()],
+            data: vec![std::iter::once(("_count".to_string(), Value::Int(1))).collect()],
+        };
+        assert_eq!(
+            AggregatePrinter::final_print(&mut printer, &agg, &DisplayConfig::default()),
+            ""
+        );
+        assert!(out.is_empty());
+        let rows = rows.lock().unwrap();
+        match &rows[..] {
+            [Row::Record(first), Row::Aggregate(second)] => {
+                assert_eq!(first, &rec);
+                assert_eq!(second, &agg);
+            }
+            _ => panic!("expected a record and an aggregate"),
+        }
+    }
+}
@@ -0,0 +1,224 @@
+//! Split output.
+//!
+//! `-o split=dir,by=service` writes each output row as a line of JSON to a file for the value of
+//! its `service` field, e.g. `dir/service=auth.jsonl`, so a mixed stream can be broken apart in
+//! one pass.  Rows without the field go to `dir/_other.jsonl`.  The files are replaced, rather
+//! than appended to.
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::path::PathBuf;
+
+use crate::data::{Aggregate, DisplayConfig, Record, Value, WrappedAggregateRow};
+use crate::printer::{AggregatePrinter, RecordPrinter};
+
+/// The file that rows without the field are written to.
+pub const OTHER_FILE: &str = "_other.jsonl";
+/// The most files that are kept open at once.  Past that, they're all closed and reopened as
+/// they're written to again.
+const MAX_OPEN_FILES: usize = 128;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SplitConfig {
+    pub dir: PathBuf,
+    pub by: String,
+}
+
+impl SplitConfig {
+    /// Parse `dir,by=field`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (dir, by) = match spec.split_once(',') {
+            Some((dir, option)) => match option.split_once('=') {
+                Some(("by", by)) => (dir, by),
+                _ => return Err(format!("unknown option `{}`", option)),
+            },
+            None => (spec, ""),
+        };
+        if dir.is_empty() {
+            return Err("missing the directory".to_string());
+        }
+        if by.is_empty() {
+            return Err("missing the field to split by".to_string());
+        }
+        Ok(SplitConfig {
+            dir: PathBuf::from(dir),
+            by: by.to_string(),
+        })
+    }
+}
+
+/// The name of the file for a value of the field.  Characters that can't be in a file name, or
+/// that would make it a path, are replaced by `_`.
+fn file_name(field: &str, value: &Value) -> String {
+    let value = match value {
+        Value::None => return OTHER_FILE.to_string(),
+        Value::Str(s) => s.clone(),
+        value => value.to_string(),
+    };
+    let name: String = format!("{}={}", field, value)
+        .chars()
+        .map(|c| match c {
+            '/' | '\\' | ':' | '*' | '?' | '"' | '<' | '>' | '|' => '_',
+            c if c.is_control() => '_',
+            c => c,
+        })
+        .collect();
+    format!("{}.jsonl", name)
+}
+
+/// Writes rows to a file for each value of the split field, instead of to the output.
+pub struct SplitPrinter {
+    config: SplitConfig,
+    open: HashMap<String, BufWriter<File>>,
+    /// The files that have been written to, which are appended to if they're opened again.
+    written: HashSet<String>,
+}
+
+impl SplitPrinter {
+    pub fn new(config: &SplitConfig) -> Self {
+        SplitPrinter {
+            config: config.clone(),
+            open: HashMap::new(),
+            written: HashSet::new(),
+        }
+    }
+
+    fn error(&self, name: &str, e: io::Error) -> io::Error {
+        io::Error::new(
+            e.kind(),
+            format!(
+                "could not write {}: {}",
+                self.config.dir.join(name).display(),
+                e
+            ),
+        )
+    }
+
+    /// The file for a row, opening it if it isn't already.
+    fn file(&mut self, name: String) -> io::Result<&mut BufWriter<File>> {
+        if !self.open.contains_key(&name) {
+            if self.open.len() >= MAX_OPEN_FILES {
+                self.flush()?;
+                self.open.clear();
+            }
+            if self.written.is_empty() {
+                fs::create_dir_all(&self.config.dir).map_err(|e| self.error(&name, e))?;
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .write(true)
+                .append(self.written.contains(&name))
+                .truncate(!self.written.contains(&name))
+                .open(self.config.dir.join(&name))
+                .map_err(|e| self.error(&name, e))?;
+            self.written.insert(name.clone());
+            self.open.insert(name.clone(), BufWriter::new(file));
+        }
+        Ok(self.open.get_mut(&name).expect("just opened"))
+    }
+
+    fn write_row<T: serde::Serialize>(&mut self, name: String, row: &T) -> io::Result<()> {
+        let file = self.file(name.clone())?;
+        let result = serde_json::to_writer(&mut *file, row)
+            .map_err(io::Error::from)
+            .and_then(|_| writeln!(file));
+        result.map_err(|e| self.error(&name, e))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        for file in self.open.values_mut() {
+            file.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl RecordPrinter for SplitPrinter {
+    fn print(
+        &mut self,
+        _out: &mut dyn Write,
+        row: &Record,
+        _display_config: &DisplayConfig,
+    ) -> io::Result<()> {
+        let name = file_name(
+            &self.config.by,
+            row.data.get(&self.config.by).unwrap_or(&Value::None),
+        );
+        // When nothing was parsed out of a record, the line itself is written instead.
+        if row.data.is_empty() {
+            let raw = row.raw.trim_end_matches(&['\r', '\n'][..]);
+            self.write_row(name, &serde_json::json!({ "_raw": raw }))
+        } else {
+            self.write_row(name, row)
+        }
+    }
+
+    fn is_buffered(&self) -> bool {
+        true
+    }
+
+    fn finish(&mut self, _out: &mut dyn Write) -> io::Result<()> {
+        self.flush()
+    }
+}
+
+impl AggregatePrinter for SplitPrinter {
+    fn print(&mut self, row: &Aggregate, _display_config: &DisplayConfig) -> String {
+        // The whole aggregate is written each time, so it replaces the files.
+        self.open.clear();
+        self.written.clear();
+        let result = row
+            .data
+            .iter()
+            .try_for_each(|data| {
+                let name = file_name(
+                    &self.config.by,
+                    data.get(&self.config.by).unwrap_or(&Value::None),
+                );
+                let wrapped = WrappedAggregateRow {
+                    columns: &row.columns,
+                    data,
+                };
+                self.write_row(name, &wrapped)
+            })
+            .and_then(|_| self.flush());
+        // The files are written instead of the output, which has nothing to show.
+        if let Err(e) = result {
+            eprintln!("error: {}", e);
+        }
+        String::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_config() {
+        assert_eq!(
+            SplitConfig::parse("out,by=service"),
+            Ok(SplitConfig {
+                dir: PathBuf::from("out"),
+                by: "service".to_string(),
+            })
+        );
+        assert!(SplitConfig::parse("out").is_err());
+        assert!(SplitConfig::parse(",by=service").is_err());
+        assert!(SplitConfig::parse("out,on=service").is_err());
+    }
+
+    #[test]
+    fn names_files() {
+        assert_eq!(
+            file_name("service", &Value::Str("auth".to_string())),
+            "service=auth.jsonl"
+        );
+        assert_eq!(file_name("status", &Value::Int(500)), "status=500.jsonl");
+        assert_eq!(
+            file_name("path", &Value::Str("/a/../b".to_string())),
+            "path=_a_.._b.jsonl"
+        );
+        assert_eq!(file_name("service", &Value::None), OTHER_FILE);
+    }
+}
@@ -0,0 +1,442 @@
+//! SQLite output.
+//!
+//! `-o sqlite=results.db[:table]` writes the output rows to a table, `results` by default, that
+//! is created from the columns of the rows, replacing any table of the same name.  The database
+//! is written with the bundled SQLite library, with the rows inserted by a prepared statement in
+//! transactions of `BATCH_SIZE` rows.
+use std::io;
+use std::io::Write;
+use std::path::PathBuf;
+
+use itertools::Itertools;
+use rusqlite::types::Value as SqlValue;
+use rusqlite::{params_from_iter, Connection};
+
+use crate::data::{Aggregate, DisplayConfig, Record, Value};
+use crate::printer::{AggregatePrinter, RecordPrinter};
+
+pub const DEFAULT_TABLE: &str = "results";
+/// Number of rows inserted in a single transaction.
+const BATCH_SIZE: usize = 1000;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SqliteConfig {
+    pub path: PathBuf,
+    pub table: String,
+}
+
+impl SqliteConfig {
+    /// Parse `path[:table]`.
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (path, table) = match spec.rsplit_once(':') {
+            // A colon followed by a path separator is part of the path, e.g. `C:\results.db`.
+            Some((path, table)) if !table.contains(['/', '\\']) => (path, table),
+            _ => (spec, DEFAULT_TABLE),
+        };
+        if path.is_empty() {
+            return Err("missing the path of the database".to_string());
+        }
+        if table.is_empty() {
+            return Err(format!("missing the table name after `{}:`", path));
+        }
+        Ok(SqliteConfig {
+            path: PathBuf::from(path),
+            table: table.to_string(),
+        })
+    }
+}
+
+/// The declared type of a column, inferred from its values.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum ColumnType {
+    Integer,
+    Real,
+    Text,
+    /// Only `None` has been seen, so the column is left untyped and takes values as they are.
+    Any,
+}
+
+impl ColumnType {
+    fn of(value: &Value) -> Self {
+        match value {
+            Value::Int(_) | Value::Bool(_) => ColumnType::Integer,
+            Value::Float(_) => ColumnType::Real,
+            Value::None => ColumnType::Any,
+            _ => ColumnType::Text,
+        }
+    }
+
+    fn merge(self, other: ColumnType) -> Self {
+        match (self, other) {
+            (ColumnType::Any, other) | (other, ColumnType::Any) => other,
+            (left, right) if left == right => left,
+            (ColumnType::Integer, ColumnType::Real) | (ColumnType::Real, ColumnType::Integer) => {
+                ColumnType::Real
+            }
+            _ => ColumnType::Text,
+        }
+    }
+
+    fn sql(self) -> &'static str {
+        match self {
+            ColumnType::Integer => " INTEGER",
+            ColumnType::Real => " REAL",
+            ColumnType::Text => " TEXT",
+            ColumnType::Any => "",
+        }
+    }
+}
+
+fn identifier(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// The value as it's bound to a statement, so it's never part of the SQL.
+fn sql_value(value: &Value) -> SqlValue {
+    match value {
+        Value::None => SqlValue::Null,
+        Value::Int(i) => SqlValue::Integer(*i),
+        Value::Bool(b) => SqlValue::Integer(*b as i64),
+        Value::Float(f) if f.is_finite() => SqlValue::Real(f.into_inner()),
+        Value::Float(_) => SqlValue::Null,
+        Value::Str(s) => SqlValue::Text(s.clone()),
+        // Dates, durations, objects and arrays are stored as they're written as JSON.
+        value => SqlValue::Text(match serde_json::to_value(value) {
+            Ok(serde_json::Value::String(s)) => s,
+            Ok(json) => json.to_string(),
+            Err(_) => value.to_string(),
+        }),
+    }
+}
+
+/// Writes rows to the table of the database, which is opened when the first rows are written.
+/// Records are held until a batch is full, and the table is created with the columns of the
+/// first batch.  Columns that only appear in a later batch are added to the table then.
+pub struct SqlitePrinter {
+    path: PathBuf,
+    table: String,
+    connection: Option<Connection>,
+    /// The columns of the table, once it's been created.
+    columns: Option<Vec<String>>,
+    rows: Vec<Vec<(String, Value)>>,
+}
+
+impl SqlitePrinter {
+    pub fn new(config: &SqliteConfig) -> Self {
+        SqlitePrinter {
+            path: config.path.clone(),
+            table: config.table.clone(),
+            connection: None,
+            columns: None,
+            rows: Vec::new(),
+        }
+    }
+
+    /// Insert the held rows in a transaction, with `columns` as the columns that the table has to
+    /// have even if none of the rows have them.
+    fn flush(&mut self, columns: &[String]) -> io::Result<()> {
+        self.write(columns).map_err(|e| {
+            io::Error::other(format!("could not write {}: {}", self.path.display(), e))
+        })
+    }
+
+    fn write(&mut self, columns: &[String]) -> rusqlite::Result<()> {
+        let rows = std::mem::take(&mut self.rows);
+        let table = identifier(&self.table);
+        let new_columns: Vec<(String, ColumnType)> = columns
+            .iter()
+            .chain(
+                rows.iter()
+                    .flat_map(|row| row.iter().map(|(column, _)| column)),
+            )
+            .unique()
+            .filter(|column| !self.columns.iter().flatten().contains(column))
+            .map(|column| {
+                let column_type = rows
+                    .iter()
+                    .flat_map(|row| row.iter().filter(|(c, _)| c == column))
+                    .map(|(_, value)| ColumnType::of(value))
+                    .fold(ColumnType::Any, ColumnType::merge);
+                (column.clone(), column_type)
+            })
+            .collect();
+        if rows.is_empty() && new_columns.is_empty() {
+            return Ok(());
+        }
+
+        let connection = match &mut self.connection {
+            Some(connection) => connection,
+            None => self.connection.insert(Connection::open(&self.path)?),
+        };
+        let transaction = connection.transaction()?;
+        match &mut self.columns {
+            Some(columns) => {
+                for (column, column_type) in new_columns {
+                    transaction.execute(
+                        &format!(
+                            "ALTER TABLE {} ADD COLUMN {}{}",
+                            table,
+                            identifier(&column),
+                            column_type.sql()
+                        ),
+                        [],
+                    )?;
+                    columns.push(column);
+                }
+            }
+            None => {
+                transaction.execute(&format!("DROP TABLE IF EXISTS {}", table), [])?;
+                transaction.execute(
+                    &format!(
+                        "CREATE TABLE {} ({})",
+                        table,
+                        new_columns
+                            .iter()
+                            .map(|(column, column_type)| {
+                                format!("{}{}", identifier(column), column_type.sql())
+                            })
+                            .join(", ")
+                    ),
+                    [],
+                )?;
+                self.columns = Some(new_columns.into_iter().map(|(column, _)| column).collect());
+            }
+        }
+        for row in rows.iter().filter(|row| !row.is_empty()) {
+            // Rows with the same columns share a statement.
+            let mut insert = transaction.prepare_cached(&format!(
+                "INSERT INTO {} ({}) VALUES ({})",
+                table,
+                row.iter().map(|(column, _)| identifier(column)).join(", "),
+                row.iter().map(|_| "?").join(", ")
+            ))?;
+            insert.execute(params_from_iter(
+                row.iter().map(|(_, value)| sql_value(value)),
+            ))?;
+        }
+        transaction.commit()
+    }
+}
+
+impl RecordPrinter for SqlitePrinter {
+    fn print(
+        &mut self,
+        _out: &mut dyn Write,
+        row: &Record,
+        _display_config: &DisplayConfig,
+    ) -> io::Result<()> {
+        // Records don't have a column order, so the columns are in order of their names.  When
+        // nothing was parsed out of a record, the line itself is written instead.
+        let columns = if row.data.is_empty() {
+            let raw = row.raw.trim_end_matches(&['\r', '\n'][..]).to_string();
+            vec![("_raw".to_string(), Value::Str(raw))]
+        } else {
+            row.data
+                .iter()
+                .sorted_by(|(left, _), (right, _)| left.cmp(right))
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect()
+        };
+        self.rows.push(columns);
+        if self.rows.len() >= BATCH_SIZE {
+            self.flush(&[])?;
+        }
+        Ok(())
+    }
+
+    fn is_buffered(&self) -> bool {
+        true
+    }
+
+    fn finish(&mut self, _out: &mut dyn Write) -> io::Result<()> {
+        self.flush(&[])
+    }
+}
+
+impl AggregatePrinter for SqlitePrinter {
+    fn print(&mut self, row: &Aggregate, _display_config: &DisplayConfig) -> String {
+        // The table is written instead of the output, which has nothing to show.
+        if let Err(e) = self.write_aggregate(row) {
+            eprintln!("error: {}", e);
+        }
+        String::new()
+    }
+}
+
+impl SqlitePrinter {
+    fn write_aggregate(&mut self, row: &Aggregate) -> io::Result<()> {
+        // The whole aggregate is written each time, so it replaces the table.
+        self.columns = None;
+        for chunk in row.data.chunks(BATCH_SIZE) {
+            self.rows = chunk
+                .iter()
+                .map(|data| {
+                    row.columns
+                        .iter()
+                        .map(|column| {
+                            let value = data.get(column).cloned().unwrap_or(Value::None);
+                            (column.clone(), value)
+                        })
+                        .collect()
+                })
+                .collect();
+            self.flush(&row.columns)?;
+        }
+        if self.columns.is_none() {
+            self.flush(&row.columns)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use maplit::hashmap;
+
+    fn config(table: &str) -> SqliteConfig {
+        SqliteConfig {
+            path: PathBuf::from("out.db"),
+            table: table.to_string(),
+        }
+    }
+
+    #[test]
+    fn parses_config() {
+        assert_eq!(SqliteConfig::parse("out.db"), Ok(config(DEFAULT_TABLE)));
+        assert_eq!(SqliteConfig::parse("out.db:errors"), Ok(config("errors")));
+        assert_eq!(
+            SqliteConfig::parse(r"C:\out.db").map(|config| config.table),
+            Ok(DEFAULT_TABLE.to_string())
+        );
+        assert!(SqliteConfig::parse("out.db:").is_err());
+        assert!(SqliteConfig::parse("").is_err());
+    }
+
+    fn database(name: &str) -> SqliteConfig {
+        let path =
+            std::env::temp_dir().join(format!("agrind-sqlite-{}-{}.db", name, std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        SqliteConfig {
+            path,
+            table: "t".to_string(),
+        }
+    }
+
+    /// The rows of the query, with their values separated by `|`.
+    fn query(config: &SqliteConfig, sql: &str) -> Vec<String> {
+        let connection = Connection::open(&config.path).unwrap();
+        let mut statement = connection.prepare(sql).unwrap();
+        let columns = statement.column_count();
+        let rows = statement
+            .query_map([], |row| {
+                (0..columns)
+                    .map(|i| {
+                        Ok(match row.get::<_, SqlValue>(i)? {
+                            SqlValue::Null => String::new(),
+                            SqlValue::Integer(i) => i.to_string(),
+                            SqlValue::Real(f) => f.to_string(),
+                            SqlValue::Text(s) => s,
+                            SqlValue::Blob(b) => format!("{:?}", b),
+                        })
+                    })
+                    .collect::<rusqlite::Result<Vec<_>>>()
+                    .map(|values| values.join("|"))
+            })
+            .unwrap()
+            .collect::<rusqlite::Result<Vec<_>>>()
+            .unwrap();
+        rows
+    }
+
+    #[test]
+    fn writes_records_in_batches() {
+        let display_config = DisplayConfig { floating_points: 2 };
+        let config = database("records");
+        let mut printer = SqlitePrinter::new(&config);
+        let mut out = vec![];
+        let records = [
+            Record::new("")
+                .put("a", Value::Int(1))
+                .put("b", Value::from_float(1.5)),
+            Record::new("")
+                .put("a", Value::Int(2))
+                .put("b", Value::Int(2)),
+            Record::new("").put("a", Value::Str("it's'); DROP TABLE t; --".to_string())),
+        ];
+        for record in &records {
+            RecordPrinter::print(&mut printer, &mut out, record, &display_config).unwrap();
+        }
+        printer.finish(&mut out).unwrap();
+        assert_eq!(
+            query(&config, "SELECT sql FROM sqlite_master"),
+            vec![r#"CREATE TABLE "t" ("a" TEXT, "b" REAL)"#]
+        );
+        let more = Record::new("")
+            .put("c\"", Value::Bool(true))
+            .put("d", Value::None);
+        RecordPrinter::print(&mut printer, &mut out, &more, &display_config).unwrap();
+        printer.finish(&mut out).unwrap();
+        drop(printer);
+        assert!(out.is_empty());
+        assert_eq!(
+            query(&config, "SELECT * FROM t"),
+            vec!["1|1.5||", "2|2||", "it's'); DROP TABLE t; --|||", "||1|"]
+        );
+        assert_eq!(
+            query(
+                &config,
+                "SELECT typeof(b), typeof(\"c\"\"\"), typeof(d) FROM t"
+            ),
+            vec![
+                "real|null|null",
+                "real|null|null",
+                "null|null|null",
+                "null|integer|null"
+            ]
+        );
+        std::fs::remove_file(&config.path).unwrap();
+    }
+
+    #[test]
+    fn writes_aggregates() {
+        let display_config = DisplayConfig { floating_points: 2 };
+        let config = database("aggregates");
+        let mut printer = SqlitePrinter::new(&config);
+        let aggregate = Aggregate::new(
+            &["status".to_string()],
+            "_count".to_string(),
+            &[(
+                hashmap! { "status".to_string() => "200".to_string() },
+                Value::Int(3),
+            )],
+        );
+        assert_eq!(printer.final_print(&aggregate, &display_config), "");
+        assert_eq!(query(&config, "SELECT * FROM t"), vec!["200|3"]);
+        let empty = Aggregate::new(&["status".to_string()], "_count".to_string(), &[]);
+        assert_eq!(printer.final_print(&empty, &display_config), "");
+        assert_eq!(
+            query(&config, "SELECT sql FROM sqlite_master"),
+            vec![r#"CREATE TABLE "t" ("status", "_count")"#]
+        );
+        assert!(query(&config, "SELECT * FROM t").is_empty());
+        drop(printer);
+        std::fs::remove_file(&config.path).unwrap();
+    }
+
+    #[test]
+    fn reports_errors() {
+        let config = SqliteConfig {
+            path: std::env::temp_dir()
+                .join("agrind-missing-dir")
+                .join("out.db"),
+            table: "t".to_string(),
+        };
+        let mut printer = SqlitePrinter::new(&config);
+        let record = Record::new("").put("a", Value::Int(1));
+        let display_config = DisplayConfig { floating_points: 2 };
+        RecordPrinter::print(&mut printer, &mut vec![], &record, &display_config).unwrap();
+        let error = printer.finish(&mut vec![]).unwrap_err().to_string();
+        assert!(error.starts_with("could not write "), "{}", error);
+    }
+}
@@ -1,10 +1,16 @@
 use crate::data::Value;
 use crate::errors::ErrorBuilder;
 use crate::lang;
+#[cfg(feature = "script")]
+use crate::operator::script;
+#[cfg(feature = "wasm")]
+use crate::operator::wasm;
 use crate::operator::{
-    average, count, count_distinct, expr, fields, limit, max, min, parse, percentile, split, sum,
-    timeslice, total, where_op,
+    accesslog, average, count, count_distinct, csv, dedup, explode, expr, extreme_by, fields,
+    first_last, geoip, limit, lookup, max, min, mode, parse, percentile, rename, sample, split,
+    sum, syslog, timeslice, total, transaction, url, useragent, values, variance, where_op,
 };
+use crate::pipeline::PipelineOptions;
 use crate::{funcs, operator};
 use thiserror::Error;
 
@@ -32,8 +38,76 @@ pub enum TypeError {
     #[error("Unknown function {}", name)]
     UnknownFunction { name: String },
 
+    #[error(
+        "The '{}' function expects {} arguments, found {}",
+        name,
+        expected,
+        found
+    )]
+    InvalidFunctionArguments {
+        name: &'static str,
+        expected: usize,
+        found: usize,
+    },
+
     #[error("Expected a duration for the timeslice (e.g. 1h)")]
     ExpectedDuration,
+
+    #[error("The number of buckets for an automatic timeslice must be greater than zero")]
+    InvalidSliceCount,
+
+    #[error("Types can't be given to fields that are being dropped")]
+    CastDroppedField,
+
+    #[error("The field names for `parse csv` weren't given")]
+    CsvWithoutFields,
+
+    #[error("Failed to read {} for the join: {}", path, error)]
+    JoinFile { path: String, error: String },
+
+    #[error("Invalid arguments for {}: {}", keyword, error)]
+    PluginArgs { keyword: String, error: String },
+
+    #[error("The column of the table to match the key of the lookup against wasn't given")]
+    LookupWithoutColumn,
+
+    #[error("Failed to read {} for the lookup: {}", path, error)]
+    LookupFile { path: String, error: String },
+
+    #[cfg(feature = "wasm")]
+    #[error("Failed to load the WebAssembly module {}: {}", path, error)]
+    WasmModule { path: String, error: String },
+
+    #[cfg(feature = "wasm")]
+    #[error("The wasm function expects the module and the name of the function as strings")]
+    WasmFunctionArguments,
+
+    #[cfg(not(feature = "wasm"))]
+    #[error("agrind was built without WebAssembly support")]
+    WasmDisabled,
+
+    #[cfg(feature = "script")]
+    #[error("Invalid script: {}", error)]
+    Script { error: String },
+
+    #[cfg(not(feature = "script"))]
+    #[error("agrind was built without scripting support")]
+    ScriptDisabled,
+
+    #[error("The number of values `dedup` remembers must be greater than zero")]
+    InvalidDedupWindow,
+
+    #[error(
+        "The fraction of rows to sample must be greater than 0 and at most 1, found {}",
+        rate
+    )]
+    InvalidSampleRate { rate: f64 },
+
+    #[error("No GeoIP database was given")]
+    GeoIpWithoutDatabase,
+
+    #[error("Failed to read {} for geoip: {}", path, error)]
+    GeoIpDatabase { path: String, error: String },
 }
 
 pub trait TypeCheck<O> {
@@ -137,11 +211,24 @@ impl TypeCheck<operator::Expr> for lang::Expr {
                     .into_iter()
                     .map(|arg| arg.type_check(error_builder))
                     .collect();
-                if let Some(func) = funcs::FUNC_MAP.get(name.as_str()) {
-                    Ok(operator::Expr::FunctionCall {
-                        func,
-                        args: converted_args?,
-                    })
+                // Missing values are skipped rather than reported, so the arguments can't be
+                // evaluated up front like a regular function's.
+                if name == "coalesce" {
+                    Ok(operator::Expr::Coalesce(converted_args?))
+                } else if name == "wasm" {
+                    wasm_function(converted_args?)
+                } else if let Some(func) = funcs::FUNC_MAP.get(name.as_str()) {
+                    let args = converted_args?;
+                    match func.arity() {
+                        Some(expected) if expected != args.len() => {
+                            Err(TypeError::InvalidFunctionArguments {
+                                name: func.name(),
+                                expected,
+                                found: args.len(),
+                            })
+                        }
+                        _ => Ok(operator::Expr::FunctionCall { func, args }),
+                    }
                 } else {
                     Err(TypeError::UnknownFunction { name })
                 }
@@ -155,6 +242,17 @@ impl TypeCheck<operator::Expr> for lang::Expr {
                 value_if_true: Box::new(value_if_true.type_check(error_builder)?),
                 value_if_false: Box::new(value_if_false.type_check(error_builder)?),
             }),
+            lang::Expr::In { value, list } => Ok(operator::Expr::In {
+                value: Box::new(value.type_check(error_builder)?),
+                list: list
+                    .into_iter()
+                    .map(|item| item.type_check(error_builder))
+                    .collect::<Result<_, _>>()?,
+            }),
+            lang::Expr::Match { value, regex } => Ok(operator::Expr::Match {
+                value: Box::new(value.type_check(error_builder)?),
+                regex: regex::Regex::new(&regex).unwrap(),
+            }),
             lang::Expr::Value(value) => {
                 let boxed = Box::new(value);
                 let static_value: &'static mut Value = Box::leak(boxed);
@@ -165,7 +263,42 @@ impl TypeCheck<operator::Expr> for lang::Expr {
     }
 }
 
-const DEFAULT_LIMIT: i64 = 10;
+/// `wasm("module.wasm", "function", args...)`, which calls a function exported by a module with
+/// the rest of the arguments.
+#[cfg(feature = "wasm")]
+fn wasm_function(args: Vec<operator::Expr>) -> Result<operator::Expr, TypeError> {
+    let mut args = args.into_iter();
+    let (path, name) = match (args.next(), args.next()) {
+        (
+            Some(operator::Expr::Value(Value::Str(path))),
+            Some(operator::Expr::Value(Value::Str(name))),
+        ) => (path, name),
+        _ => return Err(TypeError::WasmFunctionArguments),
+    };
+    let func = wasm::WasmFunction::load(std::path::Path::new(&path), name).map_err(|err| {
+        TypeError::WasmModule {
+            path: path.clone(),
+            error: err.to_string(),
+        }
+    })?;
+    Ok(operator::Expr::Wasm {
+        func: std::sync::Arc::new(func),
+        args: args.collect(),
+    })
+}
+
+#[cfg(not(feature = "wasm"))]
+fn wasm_function(_args: Vec<operator::Expr>) -> Result<operator::Expr, TypeError> {
+    Err(TypeError::WasmDisabled)
+}
+
+fn duplicate_keys(duplicates: lang::DuplicateKeys) -> parse::DuplicateKeys {
+    match duplicates {
+        lang::DuplicateKeys::First => parse::DuplicateKeys::First,
+        lang::DuplicateKeys::Last => parse::DuplicateKeys::Last,
+        lang::DuplicateKeys::Array => parse::DuplicateKeys::Array,
+    }
+}
 
 impl TypeCheck<Box<dyn operator::OperatorBuilder + Send + Sync>>
     for lang::Positioned<lang::InlineOperator>
@@ -175,24 +308,73 @@ impl TypeCheck<Box<dyn operator::OperatorBuilder + Send + Sync>>
     fn type_check<T: ErrorBuilder>(
         self,
         error_builder: &T,
+    ) -> Result<Box<dyn operator::OperatorBuilder + Send + Sync>, TypeError> {
+        self.type_check_with(&PipelineOptions::default(), error_builder)
+    }
+}
+
+impl lang::Positioned<lang::InlineOperator> {
+    /// Like `type_check`, with what the query leaves to the pipeline's options filled in: the
+    /// seed of a `sample` without one, and whether `dedup` and the patterns of `fields` include
+    /// the metadata fields.
+    pub(crate) fn type_check_with<T: ErrorBuilder>(
+        self,
+        options: &PipelineOptions,
+        error_builder: &T,
     ) -> Result<Box<dyn operator::OperatorBuilder + Send + Sync>, TypeError> {
         match self.value {
-            lang::InlineOperator::Json { input_column } => Ok(Box::new(parse::ParseJson::new(
-                input_column
-                    .map(|e| e.type_check(error_builder))
-                    .transpose()?,
-            ))),
-            lang::InlineOperator::Logfmt { input_column } => Ok(Box::new(parse::ParseLogfmt::new(
-                input_column
-                    .map(|e| e.type_check(error_builder))
-                    .transpose()?,
-            ))),
+            lang::InlineOperator::Json {
+                input_column,
+                mode,
+                duplicates,
+                warn_duplicates,
+            } => {
+                let mode = match mode {
+                    lang::JsonMode::Fields => parse::JsonMode::Fields,
+                    lang::JsonMode::Flatten => parse::JsonMode::Flatten,
+                    lang::JsonMode::Paths(paths) => parse::JsonMode::Paths(
+                        paths
+                            .into_iter()
+                            .map(|lang::JsonPath { column, name, .. }| {
+                                column
+                                    .type_check(error_builder)
+                                    .map(|column| (column, name))
+                            })
+                            .collect::<Result<_, _>>()?,
+                    ),
+                };
+                Ok(Box::new(
+                    parse::ParseJson::new(
+                        input_column
+                            .map(|e| e.type_check(error_builder))
+                            .transpose()?,
+                    )
+                    .with_mode(mode)
+                    .with_duplicates(duplicate_keys(duplicates), warn_duplicates),
+                ))
+            }
+            lang::InlineOperator::Logfmt {
+                input_column,
+                strict,
+                keys,
+                duplicates,
+            } => Ok(Box::new(
+                parse::ParseLogfmt::new(
+                    input_column
+                        .map(|e| e.type_check(error_builder))
+                        .transpose()?,
+                )
+                .strict(strict)
+                .with_keys(keys)
+                .with_duplicates(duplicate_keys(duplicates)),
+            )),
             lang::InlineOperator::Parse {
                 pattern,
                 fields,
                 input_column,
                 no_drop,
                 no_convert,
+                casts,
             } => {
                 let regex = pattern.to_regex();
 
@@ -217,25 +399,118 @@ impl TypeCheck<Box<dyn operator::OperatorBuilder + Send + Sync>>
                         extracted: fields.len(),
                     })
                 } else {
-                    Ok(Box::new(parse::Parse::new(
-                        regex,
+                    Ok(Box::new(
+                        parse::Parse::new(
+                            regex,
+                            fields,
+                            input_column
+                                .map(|e| e.type_check(error_builder))
+                                .transpose()?,
+                            parse::ParseOptions {
+                                drop_nonmatching: !no_drop,
+                                no_conversion: no_convert,
+                            },
+                        )
+                        .with_casts(casts),
+                    ))
+                }
+            }
+            lang::InlineOperator::Csv {
+                fields,
+                casts,
+                input_column,
+                headers,
+                delimiter,
+                no_drop,
+            } => {
+                if fields.is_empty() && !headers {
+                    let e = TypeError::CsvWithoutFields;
+                    error_builder
+                        .report_error_for(&e)
+                        .with_code_range(self.range.clone(), "")
+                        .with_resolution("List the fields with 'as', e.g. 'as date, user, status'")
+                        .with_resolution("Use 'headers=true' to take them from the first line")
+                        .send_report();
+                    return Err(e);
+                }
+                Ok(Box::new(
+                    csv::ParseCsvDef::new(
                         fields,
                         input_column
                             .map(|e| e.type_check(error_builder))
                             .transpose()?,
-                        parse::ParseOptions {
-                            drop_nonmatching: !no_drop,
-                            no_conversion: no_convert,
-                        },
-                    )))
-                }
+                        headers,
+                        delimiter,
+                        !no_drop,
+                    )
+                    .with_casts(casts),
+                ))
             }
-            lang::InlineOperator::Fields { fields, mode } => {
+            lang::InlineOperator::AccessLog {
+                input_column,
+                no_drop,
+            } => Ok(Box::new(accesslog::ParseAccessLog::new(
+                input_column
+                    .map(|e| e.type_check(error_builder))
+                    .transpose()?,
+                !no_drop,
+            ))),
+            lang::InlineOperator::Url {
+                input_column,
+                no_drop,
+            } => Ok(Box::new(url::ParseUrl::new(
+                input_column
+                    .map(|e| e.type_check(error_builder))
+                    .transpose()?,
+                !no_drop,
+            ))),
+            lang::InlineOperator::UserAgent { input_column } => {
+                Ok(Box::new(useragent::ParseUserAgent::new(
+                    input_column
+                        .map(|e| e.type_check(error_builder))
+                        .transpose()?,
+                )))
+            }
+            lang::InlineOperator::Syslog {
+                input_column,
+                format,
+                no_drop,
+            } => {
+                let format = format.map(|format| match format {
+                    lang::SyslogFormat::Rfc3164 => syslog::Format::Rfc3164,
+                    lang::SyslogFormat::Rfc5424 => syslog::Format::Rfc5424,
+                });
+                Ok(Box::new(syslog::ParseSyslog::new(
+                    input_column
+                        .map(|e| e.type_check(error_builder))
+                        .transpose()?,
+                    format,
+                    !no_drop,
+                )))
+            }
+            lang::InlineOperator::Fields {
+                fields,
+                mode,
+                casts,
+            } => {
                 let omode = match mode {
                     lang::FieldMode::Except => fields::FieldMode::Except,
                     lang::FieldMode::Only => fields::FieldMode::Only,
                 };
-                Ok(Box::new(fields::Fields::new(&fields, omode)))
+                if !casts.is_empty() && mode == lang::FieldMode::Except {
+                    let e = TypeError::CastDroppedField;
+                    error_builder
+                        .report_error_for(&e)
+                        .with_code_range(self.range.clone(), "")
+                        .with_resolution("Remove the types or select the fields to keep instead")
+                        .send_report();
+                    return Err(e);
+                }
+                Ok(Box::new(
+                    fields::Fields::new(&fields, omode)
+                        .with_casts(casts)
+                        .include_meta(options.include_meta),
+                ))
             }
             lang::InlineOperator::Where { expr: Some(expr) } => match expr
                 .value
@@ -259,7 +534,9 @@ impl TypeCheck<Box<dyn operator::OperatorBuilder + Send + Sync>>
                         Err(e)
                     }
                 }
-                generic_expr => Ok(Box::new(where_op::Where::new(generic_expr))),
+                generic_expr => Ok(Box::new(where_op::Where::new(where_op::Predicate(
+                    generic_expr,
+                )))),
             },
             lang::InlineOperator::Where { expr: None } => {
                 let e = TypeError::ExpectedExpr;
@@ -299,14 +576,29 @@ impl TypeCheck<Box<dyn operator::OperatorBuilder + Send + Sync>>
                 limit => Ok(Box::new(limit::LimitDef::new(limit as i64))),
             },
             lang::InlineOperator::Limit { count: None } => {
-                Ok(Box::new(limit::LimitDef::new(DEFAULT_LIMIT)))
+                Ok(Box::new(limit::LimitDef::new(lang::DEFAULT_LIMIT)))
+            }
+            lang::InlineOperator::Sample { rate, seed } => {
+                if rate.value > 0.0 && rate.value <= 1.0 {
+                    let seed = seed.or(options.seed);
+                    return Ok(Box::new(sample::SampleDef::new(rate.value, seed)));
+                }
+                let e = TypeError::InvalidSampleRate { rate: rate.value };
+                error_builder
+                    .report_error_for(e.to_string())
+                    .with_code_pointer(&rate, "")
+                    .with_resolution("eg. sample 0.01 to keep 1% of the rows")
+                    .send_report();
+                Err(e)
             }
             lang::InlineOperator::Split {
                 separator,
+                max_splits,
                 input_column,
                 output_column,
             } => Ok(Box::new(split::Split::new(
                 separator,
+                max_splits,
                 input_column
                     .map(|e| e.type_check(error_builder))
                     .transpose()?,
@@ -314,18 +606,42 @@ impl TypeCheck<Box<dyn operator::OperatorBuilder + Send + Sync>>
                     .map(|e| e.type_check(error_builder))
                     .transpose()?,
             ))),
+            lang::InlineOperator::Explode {
+                input_column,
+                output_column,
+            } => Ok(Box::new(explode::ExplodeDef::new(
+                input_column.type_check(error_builder)?,
+                output_column.type_check(error_builder)?,
+            ))),
             lang::InlineOperator::Timeslice { duration: None, .. } => {
                 Err(TypeError::ExpectedDuration)
             }
+            lang::InlineOperator::Timeslice {
+                duration: Some(lang::SliceDuration::Auto { buckets: 0 }),
+                ..
+            } => Err(TypeError::InvalidSliceCount),
             lang::InlineOperator::Timeslice {
                 input_column,
-                duration: Some(duration),
+                format,
+                duration: Some(lang::SliceDuration::Fixed(duration)),
                 output_column,
             } => Ok(Box::new(timeslice::Timeslice::new(
                 input_column.type_check(error_builder)?,
+                format,
                 duration,
                 output_column,
             ))),
+            lang::InlineOperator::Timeslice {
+                input_column,
+                format,
+                duration: Some(lang::SliceDuration::Auto { buckets }),
+                output_column,
+            } => Ok(Box::new(timeslice::AutoTimesliceDef::new(
+                input_column.type_check(error_builder)?,
+                format,
+                buckets,
+                output_column,
+            ))),
             lang::InlineOperator::Total {
                 input_column,
                 output_column,
@@ -333,9 +649,202 @@ impl TypeCheck<Box<dyn operator::OperatorBuilder + Send + Sync>>
                 input_column.type_check(error_builder)?,
                 output_column,
             ))),
+            lang::InlineOperator::Dedup { last: Some(0), .. } => Err(TypeError::InvalidDedupWindow),
+            lang::InlineOperator::Dedup {
+                key_cols,
+                last,
+                ttl,
+            } => Ok(Box::new(dedup::DedupDef::new(
+                key_cols
+                    .into_iter()
+                    .map(|expr| expr.type_check(error_builder))
+                    .collect::<Result<Vec<_>, _>>()?,
+                options.include_meta,
+                last,
+                ttl.map(|(ttl, timestamp)| {
+                    timestamp
+                        .type_check(error_builder)
+                        .map(|timestamp| (ttl, timestamp))
+                })
+                .transpose()?,
+            ))),
+            lang::InlineOperator::GeoIp { input_column } => {
+                let paths: Vec<_> = std::env::var_os(geoip::DATABASE_ENV)
+                    .map(|paths| std::env::split_paths(&paths).collect())
+                    .unwrap_or_default();
+                if paths.is_empty() {
+                    let e = TypeError::GeoIpWithoutDatabase;
+                    error_builder
+                        .report_error_for(&e)
+                        .with_code_range(self.range.clone(), "")
+                        .with_resolution(format!(
+                            "Give the path of a GeoLite2 database with --geoip-db or {}",
+                            geoip::DATABASE_ENV
+                        ))
+                        .send_report();
+                    return Err(e);
+                }
+                let range = self.range;
+                let databases = paths
+                    .iter()
+                    .map(|path| {
+                        geoip::Database::open(path).map_err(|error| {
+                            let e = TypeError::GeoIpDatabase {
+                                path: path.display().to_string(),
+                                error: error.to_string(),
+                            };
+                            error_builder
+                                .report_error_for(&e)
+                                .with_code_range(range.clone(), "")
+                                .send_report();
+                            e
+                        })
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+                Ok(Box::new(geoip::GeoIpDef::new(
+                    input_column.type_check(error_builder)?,
+                    databases,
+                )))
+            }
+            lang::InlineOperator::Transaction {
+                key_cols,
+                key_col_headers,
+                max_span,
+                max_pause,
+                timestamp,
+            } => Ok(Box::new(transaction::TransactionDef::new(
+                key_cols
+                    .into_iter()
+                    .map(|expr| expr.type_check(error_builder))
+                    .collect::<Result<Vec<_>, _>>()?,
+                key_col_headers,
+                timestamp.type_check(error_builder)?,
+                max_span,
+                max_pause,
+            ))),
+            // Joins run operators of their own, so they're built along with the pipeline.
+            lang::InlineOperator::Join { .. } => unreachable!(),
+            lang::InlineOperator::Lookup {
+                fields,
+                path,
+                key,
+                column,
+            } => {
+                let column = match (column, &key) {
+                    (Some(column), _) => column,
+                    (
+                        None,
+                        lang::Expr::Column {
+                            head: lang::DataAccessAtom::Key(name),
+                            rest,
+                        },
+                    ) if rest.is_empty() => name.clone(),
+                    (None, _) => {
+                        let e = TypeError::LookupWithoutColumn;
+                        error_builder
+                            .report_error_for(&e)
+                            .with_code_range(self.range, "")
+                            .with_resolution(
+                                "Name the column after the key, e.g. `on toLowerCase(host) = host`",
+                            )
+                            .send_report();
+                        return Err(e);
+                    }
+                };
+                let key = key.type_check(error_builder)?;
+                // A `.tsv` file is split on tabs instead of commas.
+                let delimiter = if path.ends_with(".tsv") { '\t' } else { ',' };
+                let loaded = std::fs::File::open(&path).and_then(|f| {
+                    lookup::Lookup::load(
+                        key,
+                        &column,
+                        fields,
+                        delimiter,
+                        std::io::BufReader::new(f),
+                    )
+                });
+                match loaded {
+                    Ok(lookup) => Ok(Box::new(lookup)),
+                    Err(err) => {
+                        let e = TypeError::LookupFile {
+                            path,
+                            error: err.to_string(),
+                        };
+                        error_builder
+                            .report_error_for(&e)
+                            .with_code_range(self.range, "")
+                            .send_report();
+                        Err(e)
+                    }
+                }
+            }
+            #[cfg(feature = "wasm")]
+            lang::InlineOperator::Wasm { path, function } => {
+                let function = function.as_deref().unwrap_or(wasm::DEFAULT_FUNCTION);
+                match wasm::WasmFunction::load(std::path::Path::new(&path), function) {
+                    Ok(func) => Ok(Box::new(wasm::WasmMap::new(func))),
+                    Err(err) => {
+                        let e = TypeError::WasmModule {
+                            path,
+                            error: err.to_string(),
+                        };
+                        error_builder
+                            .report_error_for(&e)
+                            .with_code_range(self.range, "")
+                            .send_report();
+                        Err(e)
+                    }
+                }
+            }
+            #[cfg(not(feature = "wasm"))]
+            lang::InlineOperator::Wasm { .. } => {
+                let e = TypeError::WasmDisabled;
+                error_builder
+                    .report_error_for(&e)
+                    .with_code_range(self.range, "")
+                    .with_resolution("rebuild it with `--features wasm`")
+                    .send_report();
+                Err(e)
+            }
+            #[cfg(feature = "script")]
+            lang::InlineOperator::Script { source } => match script::Script::new(&source) {
+                Ok(script) => Ok(Box::new(script)),
+                Err(err) => {
+                    let e = TypeError::Script {
+                        error: err.to_string(),
+                    };
+                    error_builder
+                        .report_error_for(&e)
+                        .with_code_range(self.range, "")
+                        .send_report();
+                    Err(e)
+                }
+            },
+            #[cfg(not(feature = "script"))]
+            lang::InlineOperator::Script { .. } => {
+                let e = TypeError::ScriptDisabled;
+                error_builder
+                    .report_error_for(&e)
+                    .with_code_range(self.range, "")
+                    .with_resolution("rebuild it with `--features script`")
+                    .send_report();
+                Err(e)
+            }
             lang::InlineOperator::FieldExpression { value, name } => Ok(Box::new(
                 fields::FieldExpressionDef::new(value.type_check(error_builder)?, name),
             )),
+            lang::InlineOperator::Rename {
+                pattern,
+                replacement,
+            } => {
+                let pattern = match pattern {
+                    lang::RenamePattern::Field(field) => rename::RenamePattern::Field(field),
+                    lang::RenamePattern::Regex(re) => {
+                        rename::RenamePattern::Regex(regex::Regex::new(&re).unwrap())
+                    }
+                };
+                Ok(Box::new(rename::RenameDef::new(pattern, replacement)))
+            }
         }
     }
 }
@@ -362,35 +871,72 @@ impl TypeCheck<Box<dyn operator::AggregateFunction>> for lang::Positioned<lang::
             lang::AggregateFunction::Sum { column } => {
                 Ok(Box::new(sum::Sum::empty(column.type_check(error_builder)?)))
             }
+            lang::AggregateFunction::SumIf { ref args } => {
+                if let Some([column, condition]) = args.as_ref().map(|pos| pos.value.as_slice()) {
+                    return Ok(Box::new(sum::Sum::new(
+                        column.clone().type_check(error_builder)?,
+                        Some(condition.clone().type_check(error_builder)?),
+                    )));
+                }
+                let builder = error_builder.report_error_for(
+                    "Expecting the value to add up and the condition for adding it",
+                );
+                let builder = match args {
+                    Some(pos) => builder.with_code_pointer(pos, "2 arguments are needed"),
+                    None => builder.with_code_pointer(&self, "No arguments given"),
+                };
+                builder
+                    .with_resolution("example: sum_if(bytes, status == 200)")
+                    .send_report();
+
+                Err(TypeError::ExpectedExpr)
+            }
             lang::AggregateFunction::Percentile {
                 column, percentile, ..
             } => Ok(Box::new(percentile::Percentile::empty(
                 column.type_check(error_builder)?,
                 percentile,
             ))),
-            lang::AggregateFunction::CountDistinct { column: Some(pos) } => {
-                match pos.value.as_slice() {
-                    [column] => Ok(Box::new(count_distinct::CountDistinct::empty(
-                        column.clone().type_check(error_builder)?,
-                    ))),
-                    _ => {
-                        error_builder
-                            .report_error_for("Expecting a single expression to count")
-                            .with_code_pointer(
-                                &pos,
-                                match pos.value.len() {
-                                    0 => "No expression given",
-                                    _ => "Only a single expression can be given",
-                                },
-                            )
-                            .with_resolution("example: count_distinct(field_to_count)")
-                            .send_report();
+            lang::AggregateFunction::StdDev { column } => Ok(Box::new(variance::Variance::empty(
+                column.type_check(error_builder)?,
+                true,
+            ))),
+            lang::AggregateFunction::Variance { column } => Ok(Box::new(
+                variance::Variance::empty(column.type_check(error_builder)?, false),
+            )),
+            lang::AggregateFunction::Median { column } => Ok(Box::new(
+                percentile::Percentile::empty(column.type_check(error_builder)?, 0.5),
+            )),
+            lang::AggregateFunction::Mode { column } => Ok(Box::new(mode::Mode::empty(
+                column.type_check(error_builder)?,
+            ))),
+            lang::AggregateFunction::CountDistinct {
+                column: Some(pos),
+                approx,
+            } => match pos.value.as_slice() {
+                [column] if approx => Ok(Box::new(count_distinct::CountDistinctApprox::empty(
+                    column.clone().type_check(error_builder)?,
+                ))),
+                [column] => Ok(Box::new(count_distinct::CountDistinct::empty(
+                    column.clone().type_check(error_builder)?,
+                ))),
+                _ => {
+                    error_builder
+                        .report_error_for("Expecting a single expression to count")
+                        .with_code_pointer(
+                            &pos,
+                            match pos.value.len() {
+                                0 => "No expression given",
+                                _ => "Only a single expression can be given",
+                            },
+                        )
+                        .with_resolution("example: count_distinct(field_to_count)")
+                        .send_report();
 
-                        Err(TypeError::ExpectedExpr)
-                    }
+                    Err(TypeError::ExpectedExpr)
                 }
-            }
-            lang::AggregateFunction::CountDistinct { column: None } => {
+            },
+            lang::AggregateFunction::CountDistinct { column: None, .. } => {
                 error_builder
                     .report_error_for("Expecting an expression to count")
                     .with_code_pointer(&self, "No field argument given")
@@ -399,6 +945,87 @@ impl TypeCheck<Box<dyn operator::AggregateFunction>> for lang::Positioned<lang::
 
                 Err(TypeError::ExpectedExpr)
             }
+            lang::AggregateFunction::MinBy { ref args }
+            | lang::AggregateFunction::MaxBy { ref args } => {
+                let (name, max) = match self.value {
+                    lang::AggregateFunction::MaxBy { .. } => ("max_by", true),
+                    _ => ("min_by", false),
+                };
+                if let Some([column, output]) = args.as_ref().map(|pos| pos.value.as_slice()) {
+                    return Ok(Box::new(extreme_by::ExtremeBy::empty(
+                        column.clone().type_check(error_builder)?,
+                        output.clone().type_check(error_builder)?,
+                        max,
+                    )));
+                }
+                let builder = error_builder.report_error_for(format!(
+                    "Expecting the value to {} and the value to return",
+                    if max { "maximize" } else { "minimize" }
+                ));
+                let builder = match args {
+                    Some(pos) => builder.with_code_pointer(pos, "2 arguments are needed"),
+                    None => builder.with_code_pointer(&self, "No arguments given"),
+                };
+                builder
+                    .with_resolution(format!("example: {}(latency, url)", name))
+                    .send_report();
+
+                Err(TypeError::ExpectedExpr)
+            }
+            lang::AggregateFunction::First { column } => Ok(Box::new(
+                first_last::FirstLast::empty(column.type_check(error_builder)?, None, false),
+            )),
+            lang::AggregateFunction::Last { column } => Ok(Box::new(first_last::FirstLast::empty(
+                column.type_check(error_builder)?,
+                None,
+                true,
+            ))),
+            lang::AggregateFunction::Earliest { column, by } => {
+                Ok(Box::new(first_last::FirstLast::empty(
+                    column.type_check(error_builder)?,
+                    Some(by.type_check(error_builder)?),
+                    false,
+                )))
+            }
+            lang::AggregateFunction::Latest { column, by } => {
+                Ok(Box::new(first_last::FirstLast::empty(
+                    column.type_check(error_builder)?,
+                    Some(by.type_check(error_builder)?),
+                    true,
+                )))
+            }
+            lang::AggregateFunction::Values { ref args } => {
+                match args.as_ref().map(|pos| pos.value.as_slice()) {
+                    Some([column]) => {
+                        return Ok(Box::new(values::Values::empty(
+                            column.clone().type_check(error_builder)?,
+                            values::DEFAULT_LIMIT,
+                        )));
+                    }
+                    Some([column, lang::Expr::Value(Value::Int(limit))]) if *limit > 0 => {
+                        return Ok(Box::new(values::Values::empty(
+                            column.clone().type_check(error_builder)?,
+                            *limit as usize,
+                        )));
+                    }
+                    _ => (),
+                }
+                let builder = error_builder.report_error_for(
+                    "Expecting the value to collect and, optionally, the most values to keep",
+                );
+                let builder = match args {
+                    Some(pos) if pos.value.len() == 2 => {
+                        builder.with_code_pointer(pos, "The limit must be a positive integer")
+                    }
+                    Some(pos) => builder.with_code_pointer(pos, "1 or 2 arguments are needed"),
+                    None => builder.with_code_pointer(&self, "No arguments given"),
+                };
+                builder
+                    .with_resolution("example: values(url, 10)")
+                    .send_report();
+
+                Err(TypeError::ExpectedExpr)
+            }
             lang::AggregateFunction::Error => unreachable!(),
         }
     }
@@ -0,0 +1,458 @@
+#!/usr/bin/env python3
+# Writes the small Parquet and Arrow IPC files used to test reading columnar input, covering the
+# encodings and compression codecs that are read.  Run it from the test_files directory; the
+# `zstd` program needs to be installed.
+import gzip
+import struct
+import subprocess
+
+# The rows of every file: timestamps in milliseconds, then level, status, latency and ok.
+ROWS = [
+    (1704067200000, 'info', 200, 0.25, True),
+    (1704067201000, 'error', 500, 1.5, False),
+    (1704067202000, 'info', 200, None, True),
+    (1704067203000, 'warn', 404, 0.75, True),
+    (1704067204000, 'error', 503, 2.0, False),
+    (1704067205000, None, 200, 0.5, True),
+]
+TAGS = [['a'], [], ['a', 'b'], ['c'], [], ['b']]
+USERS = [('ana', 1), ('bo', 2), ('ana', 1), ('cy', 3), ('bo', 2), ('dee', 4)]
+
+
+def varint(n):
+    out = bytearray()
+    while n >= 0x80:
+        out.append(n & 0x7f | 0x80)
+        n >>= 7
+    out.append(n)
+    return bytes(out)
+
+
+def zigzag(n):
+    return varint((n << 1) ^ (n >> 63))
+
+
+# Thrift's compact protocol, for Parquet's metadata.  A struct is a list of (id, type, value),
+# with the types below, or 'list' with a value of (element type, elements).
+I32, I64, BINARY, LIST, STRUCT = 5, 6, 8, 9, 12
+
+
+def thrift(fields):
+    out = bytearray()
+    last = 0
+    for field_id, kind, value in fields:
+        if kind == 'bool':
+            field_type = 1 if value else 2
+        else:
+            field_type = {'i32': I32, 'i64': I64, 'binary': BINARY, 'list': LIST,
+                          'struct': STRUCT}[kind]
+        out.append((field_id - last) << 4 | field_type)
+        last = field_id
+        if kind in ('i32', 'i64'):
+            out += zigzag(value)
+        elif kind == 'binary':
+            value = value.encode() if isinstance(value, str) else value
+            out += varint(len(value)) + value
+        elif kind == 'struct':
+            out += thrift(value)
+        elif kind == 'list':
+            element_type, elements = value
+            out.append(len(elements) << 4 | {'i32': I32, 'binary': BINARY,
+                                             'struct': STRUCT}[element_type])
+            for element in elements:
+                if element_type == 'i32':
+                    out += zigzag(element)
+                elif element_type == 'binary':
+                    out += varint(len(element)) + element.encode()
+                else:
+                    out += thrift(element)
+    out.append(0)
+    return bytes(out)
+
+
+def bit_pack(values, width):
+    bits = 0
+    for i, value in enumerate(values):
+        bits |= value << (i * width)
+    return bits.to_bytes((len(values) * width + 7) // 8, 'little')
+
+
+def hybrid_packed(values, width):
+    """The RLE/bit-packing hybrid encoding, as a single bit-packed run."""
+    groups = (len(values) + 7) // 8
+    padded = list(values) + [0] * (groups * 8 - len(values))
+    return varint(groups << 1 | 1) + bit_pack(padded, width)
+
+
+def hybrid_rle(values, width):
+    """The RLE/bit-packing hybrid encoding, as a run for each repeated value."""
+    out = bytearray()
+    i = 0
+    while i < len(values):
+        run = 1
+        while i + run < len(values) and values[i + run] == values[i]:
+            run += 1
+        out += varint(run << 1) + values[i].to_bytes((width + 7) // 8, 'little')
+        i += run
+    return bytes(out)
+
+
+def delta_binary_packed(values):
+    """DELTA_BINARY_PACKED, in blocks of 128 values made of four miniblocks."""
+    out = bytearray(varint(128) + varint(4) + varint(len(values)) + zigzag(values[0]))
+    deltas = [b - a for a, b in zip(values, values[1:])]
+    for block_start in range(0, len(deltas), 128):
+        block = deltas[block_start:block_start + 128]
+        min_delta = min(block)
+        out += zigzag(min_delta)
+        miniblocks = [[d - min_delta for d in block[i:i + 32]] for i in range(0, 128, 32)]
+        widths = [max(m).bit_length() if m else 0 for m in miniblocks]
+        out += bytes(widths)
+        for miniblock, width in zip(miniblocks, widths):
+            if miniblock:
+                out += bit_pack(miniblock + [0] * (32 - len(miniblock)), width)
+    return bytes(out)
+
+
+def snappy(data):
+    """Snappy compression, with a copy for each repeat of at least four bytes."""
+    out = bytearray(varint(len(data)))
+    literal = bytearray()
+
+    def flush():
+        # Literals of up to 60 bytes have their length in the tag.
+        for i in range(0, len(literal), 60):
+            chunk = literal[i:i + 60]
+            out.extend(bytes([(len(chunk) - 1) << 2]) + chunk)
+        literal.clear()
+
+    i = 0
+    while i < len(data):
+        best_len, best_offset = 0, 0
+        for start in range(max(0, i - 2047), i):
+            length = 0
+            while (i + length < len(data) and length < 11
+                   and data[start + length] == data[i + length]):
+                length += 1
+            if length > best_len:
+                best_len, best_offset = length, i - start
+        if best_len >= 4:
+            flush()
+            out += bytes([(best_offset >> 8) << 5 | (best_len - 4) << 2 | 1, best_offset & 0xff])
+            i += best_len
+        else:
+            literal.append(data[i])
+            i += 1
+    flush()
+    return bytes(out)
+
+
+def zstd(data):
+    return subprocess.run(['zstd', '-q', '-c'], input=data, stdout=subprocess.PIPE,
+                          check=True).stdout
+
+
+CODECS = {'none': (0, lambda data: data), 'snappy': (1, snappy),
+          'gzip': (2, lambda data: gzip.compress(data, mtime=0)), 'zstd': (6, zstd)}
+
+
+def page(page_type, header_id, header, body, uncompressed_len):
+    return thrift([(1, 'i32', page_type), (2, 'i32', uncompressed_len),
+                   (3, 'i32', len(body)), (header_id, 'struct', header)]) + body
+
+
+def data_page(values, encoding, body, codec, levels=b''):
+    """A version 1 data page, whose definition levels are compressed along with its values."""
+    data = (struct.pack('<I', len(levels)) + levels if levels else b'') + body
+    return page(0, 5, [(1, 'i32', len(values)), (2, 'i32', encoding), (3, 'i32', 3),
+                       (4, 'i32', 3)], CODECS[codec][1](data), len(data))
+
+
+def data_page_v2(values, encoding, body, codec, levels=b''):
+    compressed = CODECS[codec][1](body)
+    nulls = sum(value is None for value in values)
+    return page(3, 8, [(1, 'i32', len(values)), (2, 'i32', nulls), (3, 'i32', len(values)),
+                       (4, 'i32', encoding), (5, 'i32', len(levels)), (6, 'i32', 0)],
+                levels + compressed, len(levels) + len(body))
+
+
+def levels(values):
+    return [0 if value is None else 1 for value in values]
+
+
+def ts_pages(values):
+    body = b''.join(struct.pack('<q', value) for value in values)
+    return [data_page(values, 0, body, 'snappy')]
+
+
+def level_pages(values):
+    dictionary = ['info', 'error', 'warn']
+    plain = b''.join(struct.pack('<I', len(s)) + s.encode() for s in dictionary)
+    dictionary_page = page(2, 7, [(1, 'i32', len(dictionary)), (2, 'i32', 0)],
+                           CODECS['gzip'][1](plain), len(plain))
+    indices = [dictionary.index(value) for value in values if value is not None]
+    body = bytes([2]) + hybrid_packed(indices, 2)
+    return [dictionary_page,
+            data_page(values, 8, body, 'gzip', levels=hybrid_packed(levels(values), 1))]
+
+
+def status_pages(values):
+    return [data_page_v2(values, 5, delta_binary_packed(values), 'none')]
+
+
+def latency_pages(values):
+    defined = [struct.pack('<d', value) for value in values if value is not None]
+    body = bytes(value[i] for i in range(8) for value in defined)
+    return [data_page_v2(values, 9, body, 'zstd', levels=hybrid_rle(levels(values), 1))]
+
+
+def ok_pages(values):
+    return [data_page(values, 0, bit_pack([int(value) for value in values], 1), 'none')]
+
+
+# The columns: name, physical type, whether it's optional, its logical type and converted type,
+# the function that writes its pages, and its codec.
+COLUMNS = [
+    ('ts', 2, False, [(8, 'struct', [(1, 'bool', True), (2, 'struct', [(1, 'struct', [])])])],
+     9, ts_pages, 'snappy'),
+    ('level', 6, True, [(1, 'struct', [])], 0, level_pages, 'gzip'),
+    ('status', 1, False, None, None, status_pages, 'none'),
+    ('latency', 5, True, None, None, latency_pages, 'zstd'),
+    ('ok', 0, False, None, None, ok_pages, 'none'),
+]
+
+
+def parquet(path, row_groups):
+    out = bytearray(b'PAR1')
+    groups = []
+    for rows in row_groups:
+        chunks = []
+        for i, (name, physical_type, _, _, _, pages, codec) in enumerate(COLUMNS):
+            start = len(out)
+            written = pages([row[i] for row in rows])
+            out += b''.join(written)
+            dictionary = len(written) > 1
+            meta = [(1, 'i32', physical_type), (2, 'list', ('i32', [0, 3, 8])),
+                    (3, 'list', ('binary', [name])), (4, 'i32', CODECS[codec][0]),
+                    (5, 'i64', len(rows)), (6, 'i64', len(out) - start),
+                    (7, 'i64', len(out) - start),
+                    (9, 'i64', start + len(written[0]) if dictionary else start)]
+            if dictionary:
+                meta.append((11, 'i64', start))
+            chunks.append([(2, 'i64', start), (3, 'struct', meta)])
+        groups.append([(1, 'list', ('struct', chunks)), (2, 'i64', 0),
+                       (3, 'i64', len(rows))])
+    schema = [[(4, 'binary', 'schema'), (5, 'i32', len(COLUMNS))]]
+    for name, physical_type, optional, logical, converted, _, _ in COLUMNS:
+        element = [(1, 'i32', physical_type), (3, 'i32', int(optional)), (4, 'binary', name)]
+        if converted is not None:
+            element.append((6, 'i32', converted))
+        if logical is not None:
+            element.append((10, 'struct', logical))
+        schema.append(element)
+    footer = thrift([(1, 'i32', 1), (2, 'list', ('struct', schema)),
+                     (3, 'i64', sum(len(rows) for rows in row_groups)),
+                     (4, 'list', ('struct', groups)),
+                     (6, 'binary', 'gen_columnar.py')])
+    out += footer + struct.pack('<I', len(footer)) + b'PAR1'
+    with open(path, 'wb') as f:
+        f.write(out)
+
+
+# Flatbuffers, for Arrow's metadata.  A table is a dict of field index to a scalar, as a
+# (struct format, value) pair, or to an object that it refers to: a table, a string, a vector of
+# tables, or a vector of structs as (struct format, values).  The objects that a table refers to
+# are written after it, so every offset points forward.
+class Vector(list):
+    pass
+
+
+class Structs:
+    def __init__(self, fmt, values):
+        self.fmt, self.values = fmt, values
+
+
+def align(out, n):
+    out += bytes(-len(out) % n)
+
+
+def place(obj, out):
+    """Write an object to the end of `out`, returning where it is."""
+    if isinstance(obj, str):
+        align(out, 4)
+        pos = len(out)
+        out += struct.pack('<I', len(obj.encode())) + obj.encode() + b'\0'
+        return pos
+    if isinstance(obj, Structs):
+        # The structs themselves are aligned to 8 bytes, after the length.
+        out += bytes((4 - len(out)) % 8)
+        pos = len(out)
+        out += struct.pack('<I', len(obj.values))
+        for value in obj.values:
+            out += struct.pack(obj.fmt, *value)
+        return pos
+    if isinstance(obj, Vector):
+        align(out, 4)
+        pos = len(out)
+        out += struct.pack('<I', len(obj)) + bytes(4 * len(obj))
+        for i, element in enumerate(obj):
+            at = pos + 4 + 4 * i
+            out[at:at + 4] = struct.pack('<I', place(element, out) - at)
+        return pos
+    # A table: its vtable, then the table, with the biggest fields first.
+    fields = sorted(obj.items(), key=lambda item: -struct.calcsize(
+        item[1][0] if isinstance(item[1], tuple) else '<I'))
+    layout, size = {}, 4
+    for index, value in fields:
+        width = struct.calcsize(value[0] if isinstance(value, tuple) else '<I')
+        size += -size % width
+        layout[index] = size
+        size += width
+    vtable = [4 + 2 * (max(obj, default=-1) + 1), size]
+    vtable += [layout.get(i, 0) for i in range(max(obj, default=-1) + 1)]
+    align(out, 2)
+    vtable_pos = len(out)
+    out += struct.pack('<%dH' % len(vtable), *vtable)
+    align(out, 8)
+    pos = len(out)
+    out += struct.pack('<i', pos - vtable_pos) + bytes(size - 4)
+    for index, value in obj.items():
+        at = pos + layout[index]
+        if isinstance(value, tuple):
+            out[at:at + struct.calcsize(value[0])] = struct.pack(*value)
+    for index, value in obj.items():
+        if not isinstance(value, tuple):
+            at = pos + layout[index]
+            out[at:at + 4] = struct.pack('<I', place(value, out) - at)
+    return pos
+
+
+def flatbuffer(root):
+    out = bytearray(4)
+    out[0:4] = struct.pack('<I', place(root, out))
+    align(out, 8)
+    return bytes(out)
+
+
+UTF8, INT, FLOAT, BOOL, TIMESTAMP, LIST_TYPE, STRUCT_TYPE = 5, 2, 3, 6, 10, 12, 13
+
+
+def field(name, type_type, type_table=None, nullable=True, children=(), dictionary=None):
+    table = {0: name, 1: ('<B', nullable), 2: ('<B', type_type), 3: type_table or {},
+             5: Vector(children)}
+    if dictionary is not None:
+        table[4] = {0: ('<q', dictionary), 1: {0: ('<i', 32), 1: ('<B', True)}}
+    return table
+
+
+SCHEMA = {0: ('<h', 0), 1: Vector([
+    field('ts', TIMESTAMP, {0: ('<h', 1), 1: 'UTC'}, nullable=False),
+    field('level', UTF8, dictionary=0),
+    field('status', INT, {0: ('<i', 32), 1: ('<B', True)}, nullable=False),
+    field('latency', FLOAT, {0: ('<h', 2)}),
+    field('ok', BOOL, nullable=False),
+    field('tags', LIST_TYPE, children=[field('item', UTF8)]),
+    field('user', STRUCT_TYPE, children=[
+        field('name', UTF8), field('id', INT, {0: ('<i', 64), 1: ('<B', True)})]),
+])}
+DICTIONARY = ['info', 'error', 'warn']
+
+
+class Body:
+    """The buffers of a record batch, and the length and null count of each column."""
+
+    def __init__(self):
+        self.data, self.buffers, self.nodes = bytearray(), [], []
+
+    def buffer(self, data):
+        self.buffers.append((len(self.data), len(data)))
+        self.data += data
+        align(self.data, 8)
+
+    def validity(self, values):
+        nulls = sum(value is None for value in values)
+        self.nodes.append((len(values), nulls))
+        self.buffer(bit_pack([int(value is not None) for value in values], 1) if nulls else b'')
+
+    def strings(self, values):
+        self.validity(values)
+        values = [(value or '').encode() for value in values]
+        offsets = [0]
+        for value in values:
+            offsets.append(offsets[-1] + len(value))
+        self.buffer(struct.pack('<%di' % len(offsets), *offsets))
+        self.buffer(b''.join(values))
+
+    def fixed(self, values, fmt, default=0):
+        self.validity(values)
+        self.buffer(b''.join(struct.pack(fmt, default if value is None else value)
+                             for value in values))
+
+
+def message(header_type, header, body):
+    metadata = flatbuffer({0: ('<h', 4), 1: ('<B', header_type), 2: header,
+                           3: ('<q', len(body))})
+    return struct.pack('<Ii', 0xffffffff, len(metadata)) + metadata + bytes(body)
+
+
+def record_batch(body, length):
+    return {0: ('<q', length), 1: Structs('<qq', body.nodes),
+            2: Structs('<qq', body.buffers)}
+
+
+def arrow_messages(batches):
+    """The schema, the dictionary of `level`, and a record batch of each batch of rows."""
+    messages = [(1, message(1, SCHEMA, b''))]
+    body = Body()
+    body.strings(DICTIONARY)
+    dictionary = {0: ('<q', 0), 1: record_batch(body, len(DICTIONARY))}
+    messages.append((2, message(2, dictionary, body.data)))
+    start = 0
+    for rows in batches:
+        indices = range(start, start + len(rows))
+        start += len(rows)
+        body = Body()
+        body.fixed([row[0] for row in rows], '<q')
+        body.fixed([row[1] and DICTIONARY.index(row[1]) for row in rows], '<i')
+        body.fixed([row[2] for row in rows], '<i')
+        body.fixed([row[3] for row in rows], '<d')
+        body.validity([row[4] for row in rows])
+        body.buffer(bit_pack([int(row[4]) for row in rows], 1))
+        body.validity(rows)
+        offsets = [0]
+        for i in indices:
+            offsets.append(offsets[-1] + len(TAGS[i]))
+        body.buffer(struct.pack('<%di' % len(offsets), *offsets))
+        body.strings([tag for i in indices for tag in TAGS[i]])
+        body.validity(rows)
+        body.strings([USERS[i][0] for i in indices])
+        body.fixed([USERS[i][1] for i in indices], '<q')
+        messages.append((3, message(3, record_batch(body, len(rows)), body.data)))
+    return messages
+
+
+def arrow_stream(path, batches):
+    out = b''.join(data for _, data in arrow_messages(batches)) + struct.pack('<Ii', 0xffffffff, 0)
+    with open(path, 'wb') as f:
+        f.write(out)
+
+
+def arrow_file(path, batches):
+    out = bytearray(b'ARROW1\0\0')
+    blocks = {2: [], 3: []}
+    for header_type, data in arrow_messages(batches):
+        metadata_len = 8 + struct.unpack('<i', data[4:8])[0]
+        if header_type in blocks:
+            blocks[header_type].append((len(out), metadata_len, 0, len(data) - metadata_len))
+        out += data
+    out += struct.pack('<Ii', 0xffffffff, 0)
+    footer = flatbuffer({0: ('<h', 4), 1: SCHEMA, 2: Structs('<qiiq', blocks[2]),
+                         3: Structs('<qiiq', blocks[3])})
+    out += footer + struct.pack('<i', len(footer)) + b'ARROW1'
+    with open(path, 'wb') as f:
+        f.write(out)
+
+
+parquet('requests.parquet', [ROWS[:4], ROWS[4:]])
+arrow_file('requests.arrow', [ROWS[:4], ROWS[4:]])
+arrow_stream('requests.arrows', [ROWS])
@@ -0,0 +1,116 @@
+#!/usr/bin/env python3
+# Writes the small MaxMind DB files used to test the geoip operator, since the GeoLite2 databases
+# can't be redistributed.  Run it from the test_files directory.
+import ipaddress
+import struct
+
+TYPES = {'pointer': 1, 'string': 2, 'double': 3, 'uint16': 5, 'uint32': 6, 'map': 7,
+         'uint64': 9, 'array': 11, 'boolean': 14}
+
+
+def control(kind, size):
+    kind = TYPES[kind]
+    if size < 29:
+        prefix, extra = size, b''
+    elif size < 285:
+        prefix, extra = 29, bytes([size - 29])
+    elif size < 65821:
+        prefix, extra = 30, struct.pack('>H', size - 285)
+    else:
+        prefix, extra = 31, struct.pack('>I', size - 65821)[1:]
+    if kind <= 7:
+        return bytes([kind << 5 | prefix]) + extra
+    return bytes([prefix, kind - 7]) + extra
+
+
+def encode(value):
+    if isinstance(value, bool):
+        return control('boolean', int(value))
+    if isinstance(value, float):
+        return control('double', 8) + struct.pack('>d', value)
+    if isinstance(value, str):
+        data = value.encode()
+        return control('string', len(data)) + data
+    if isinstance(value, int):
+        data = value.to_bytes((value.bit_length() + 7) // 8, 'big')
+        return control('uint32', len(data)) + data
+    if isinstance(value, dict):
+        return control('map', len(value)) + b''.join(
+            encode(k) + encode(v) for k, v in value.items())
+    if isinstance(value, list):
+        return control('array', len(value)) + b''.join(encode(v) for v in value)
+    raise TypeError(value)
+
+
+def write(path, database_type, networks):
+    # A binary tree of the network prefixes, whose leaves are offsets into the data section.
+    nodes = [[None, None]]
+    data = b''
+    for network, record in networks:
+        network = ipaddress.ip_network(network)
+        if network.version == 4:
+            network = ipaddress.ip_network('::%s/%d' % (network.network_address, 96 + network.prefixlen))
+        bits = bin(int(network.network_address))[2:].zfill(128)[:network.prefixlen]
+        node = 0
+        for bit in bits[:-1]:
+            bit = int(bit)
+            if nodes[node][bit] is None:
+                nodes.append([None, None])
+                nodes[node][bit] = ('node', len(nodes) - 1)
+            node = nodes[node][bit][1]
+        nodes[node][int(bits[-1])] = ('data', len(data))
+        data += encode(record)
+
+    def record(value):
+        if value is None:
+            return len(nodes)
+        kind, n = value
+        return n if kind == 'node' else len(nodes) + 16 + n
+
+    tree = b''.join(
+        struct.pack('>I', record(left))[1:] + struct.pack('>I', record(right))[1:]
+        for left, right in nodes)
+    metadata = {
+        'node_count': len(nodes),
+        'record_size': 24,
+        'ip_version': 6,
+        'database_type': database_type,
+        'languages': ['en'],
+        'binary_format_major_version': 2,
+        'binary_format_minor_version': 0,
+        'build_epoch': 1700000000,
+        'description': {'en': 'Test data for angle-grinder'},
+    }
+    with open(path, 'wb') as f:
+        f.write(tree + bytes(16) + data + b'\xab\xcd\xefMaxMind.com' + encode(metadata))
+
+
+def main():
+    write('geoip-city.mmdb', 'GeoLite2-City', [
+        ('81.2.69.0/24', {
+            'country': {'iso_code': 'GB', 'names': {'en': 'United Kingdom'}},
+            'city': {'names': {'en': 'London'}},
+            'location': {'latitude': 51.5142, 'longitude': -0.0931},
+        }),
+        ('216.160.83.0/24', {
+            'country': {'iso_code': 'US', 'names': {'en': 'United States'}},
+            'city': {'names': {'en': 'Milton'}},
+        }),
+        ('2001:db8::/32', {
+            'country': {'iso_code': 'DE', 'names': {'en': 'Germany'}},
+        }),
+    ])
+    write('geoip-asn.mmdb', 'GeoLite2-ASN', [
+        ('81.2.64.0/19', {
+            'autonomous_system_number': 20712,
+            'autonomous_system_organization': 'Andrews & Arnold Ltd',
+        }),
+        ('2001:db8::/32', {
+            'autonomous_system_number': 64496,
+            'autonomous_system_organization': 'Example Networks',
+        }),
+    ])
+
+
+if __name__ == '__main__':
+    main()
@@ -16,6 +16,17 @@ struct TestDefinition {
     enabled: Option<bool>,
     #[serde(default)]
     flags: Vec<String>,
+    /// Cargo features the test needs; it's skipped unless they're all enabled.
+    #[serde(default)]
+    features: Vec<String>,
+}
+
+/// Whether a cargo feature is enabled in this build.
+fn has_feature(feature: &str) -> bool {
+    match feature {
+        "simd-json" => cfg!(feature = "simd-json"),
+        _ => false,
+    }
 }
 
 #[cfg(test)]
@@ -24,7 +35,7 @@ mod integration {
     use ag::alias::AliasCollection;
     use ag::pipeline::{ErrorReporter, OutputMode, Pipeline, QueryContainer};
     use assert_cmd::Command;
-    use predicates::prelude::predicate;
+    use predicates::prelude::{predicate, PredicateBooleanExt};
 
     use std::fs;
     use std::io::stdout;
@@ -54,7 +65,7 @@ mod integration {
         let conf: TestDefinition = toml::from_str(&contents).unwrap();
         let err = conf.error.unwrap_or("".to_string());
 
-        if !conf.enabled.unwrap_or(true) {
+        if !conf.enabled.unwrap_or(true) || !conf.features.iter().all(|f| has_feature(f)) {
             return;
         }
 
@@ -130,6 +141,451 @@ mod integration {
             .stdout("_count\n--------------\n3\n");
     }
 
+    #[test]
+    fn count_by_multiple_keys_is_deterministic() {
+        let input = "h=a s=1 z=x\nh=b s=2 z=y\nh=c s=1 z=x\nh=d s=3 z=y\nh=e s=2 z=x\n";
+        for output in ["legacy", "logfmt", "json"] {
+            let outputs: Vec<_> = (0..5)
+                .map(|_| {
+                    run()
+                        .write_stdin(input)
+                        .args(["* | logfmt | count by z, s, h | where _count > 0"])
+                        .args(["-o", output, "--no-alias"])
+                        .output()
+                        .unwrap()
+                        .stdout
+                })
+                .collect();
+            assert!(
+                outputs.iter().all(|o| o == &outputs[0]),
+                "output for -o {} changed between runs",
+                output
+            );
+        }
+    }
+
+    #[test]
+    fn refresh_interval() {
+        run()
+            .write_stdin("1\n2\n3\n")
+            .args(["* | count", "--refresh-interval", "1000"])
+            .assert()
+            .stdout("_count\n--------------\n3\n");
+        run()
+            .args(["* | count", "--refresh-interval", "soon"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("invalid value 'soon'"));
+        run()
+            .write_stdin("1\n2\n3\n")
+            .args(["* | count", "--render-interval", "500ms", "--no-live"])
+            .assert()
+            .stdout("_count\n--------------\n3\n");
+    }
+
+    #[test]
+    fn table_options() {
+        run()
+            .write_stdin(
+                "{\"level\": \"info\", \"message\": \"hello there\", \"n\": 5}\n{\"n\": 123}\n",
+            )
+            .args([
+                "--columns",
+                "n,message",
+                "--max-column-width",
+                "6",
+                "* | json",
+            ])
+            .assert()
+            .stdout("[n=5]        [message=hello…]\n[n=123]\n");
+    }
+
+    #[test]
+    fn color() {
+        run()
+            .write_stdin("{\"n\": 5}\n{\"n\": 500}\n")
+            .args(["--color", "always", "* | json | count by n"])
+            .assert()
+            .stdout(
+                "\x1b[1mn        _count\x1b[0m\n-----------------------\n5        1\n500      1\n",
+            );
+        run()
+            .write_stdin("{\"n\": 5}\n")
+            .args([
+                "--color",
+                "always",
+                "--theme",
+                "tests/nope.toml",
+                "* | json",
+            ])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("InvalidTheme"));
+    }
+
+    #[test]
+    fn multi_query() {
+        run()
+            .write_stdin("{\"level\": \"info\"}\n{\"level\": \"error\"}\n{\"level\": \"info\"}\n")
+            .args(["--multi", "* | json | count by level", "\"error\" | json"])
+            .assert()
+            .success()
+            .stdout(
+                "==> * | json | count by level <==\n\
+                 level        _count\n\
+                 ---------------------------\n\
+                 info         2\n\
+                 error        1\n\
+                 \n\
+                 ==> \"error\" | json <==\n\
+                 [level=error]\n",
+            );
+    }
+
+    #[test]
+    fn branches() {
+        let input = "{\"level\": \"info\"}\n{\"level\": \"error\"}\n{\"level\": \"info\"}\n";
+        run()
+            .write_stdin(input)
+            .arg("* | json | [where level == \"error\" | count as errors; count as total]")
+            .assert()
+            .success()
+            .stdout(
+                "_branch        errors        total\n\
+                 ------------------------------------------\n\
+                 1              1             None\n\
+                 2              None          3\n",
+            );
+        run()
+            .write_stdin(input)
+            .arg("* | json | [where level == \"error\"; where level == \"info\" | limit 1]")
+            .assert()
+            .success()
+            .stdout(
+                "[_branch=2]              [level=info]\n\
+                 [_branch=1]              [level=error]\n",
+            );
+        // The records `tail` holds back are counted once the input ends.
+        run()
+            .write_stdin(input)
+            .arg("* | json | [tail 2 | count; count]")
+            .assert()
+            .success()
+            .stdout(
+                "_branch        _count\n\
+                 -----------------------------\n\
+                 1              2\n\
+                 2              3\n",
+            );
+    }
+
+    #[test]
+    fn expand_env() {
+        run()
+            .env("AGRIND_TEST_HOST", "web-1")
+            .write_stdin("host=web-1 cost=$5\nhost=web-2 cost=$7\n")
+            .args([
+                r#"* | parse "host=* cost=$$*" as host, cost | where host == "${AGRIND_TEST_HOST}" | fields cost"#,
+                "--expand-env",
+            ])
+            .assert()
+            .success()
+            .stdout("[cost=5]\n");
+        run()
+            .env_remove("AGRIND_TEST_UNSET")
+            .args(["* | where host == $AGRIND_TEST_UNSET", "--expand-env"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "UnsetEnvVar { name: \"AGRIND_TEST_UNSET\" }",
+            ));
+        run()
+            .args(["* | where host == ${HOST", "--expand-env"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("UnterminatedEnvVar"));
+    }
+
+    #[test]
+    fn repl() {
+        run()
+            .write_stdin("* | parse 'k=*' as k\n* | pasres\n\n* | parse 'k=*' as k | count\nexit\n")
+            .args([
+                "--repl",
+                "--file",
+                "test_files/binary_data.bin",
+                "--no-alias",
+            ])
+            .assert()
+            .success()
+            .stdout("[k=v2]\n[k=v]\n_count\n--------------\n2\n")
+            .stderr(predicate::str::contains("pasres is not a valid operator"));
+        run()
+            .args(["--repl"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("ReplWithoutFile"));
+    }
+
+    #[test]
+    fn follow() {
+        let dir = std::env::temp_dir().join(format!("agrind-follow-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        fs::write(&path, "k=1\n").unwrap();
+        let appender = {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                std::thread::sleep(std::time::Duration::from_millis(500));
+                let mut file = fs::OpenOptions::new().append(true).open(path).unwrap();
+                std::io::Write::write_all(&mut file, b"k=2\n").unwrap();
+            })
+        };
+        // The process never exits on its own, so it's stopped by the timeout.
+        run()
+            .args(["* | parse 'k=*' as k", "--follow", "--file"])
+            .arg(&path)
+            .timeout(std::time::Duration::from_millis(1500))
+            .assert()
+            .stdout("[k=1]\n[k=2]\n");
+        appender.join().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn serve_metrics() {
+        let dir = std::env::temp_dir().join(format!("agrind-metrics-cli-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("app.log");
+        fs::write(&path, "status=200\nstatus=500\nstatus=200\n").unwrap();
+        // The server binds a free port and says which one, and the process never exits on its own.
+        let mut agrind = std::process::Command::new(assert_cmd::cargo::cargo_bin("agrind"))
+            .args(["* | logfmt | count by status", "--follow", "--file"])
+            .arg(&path)
+            .args(["--serve-metrics", "127.0.0.1:0"])
+            .args(["--refresh-interval", "50"])
+            .stdout(std::process::Stdio::null())
+            .stderr(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        let mut serving = String::new();
+        std::io::BufRead::read_line(
+            &mut std::io::BufReader::new(agrind.stderr.take().unwrap()),
+            &mut serving,
+        )
+        .unwrap();
+        let addr = serving
+            .strip_prefix("serving metrics at http://")
+            .and_then(|url| url.strip_suffix("/metrics\n"))
+            .unwrap_or_else(|| panic!("unexpected output: {}", serving))
+            .to_string();
+        let scrape = || {
+            let mut stream = std::net::TcpStream::connect(&addr).unwrap();
+            std::io::Write::write_all(
+                &mut stream,
+                b"GET /metrics HTTP/1.1\r\nConnection: close\r\n\r\n",
+            )
+            .unwrap();
+            let mut response = String::new();
+            std::io::Read::read_to_string(&mut stream, &mut response).unwrap();
+            response
+        };
+        let expected = "# TYPE agrind_count gauge\n\
+                        agrind_count{status=\"200\"} 2\n\
+                        agrind_count{status=\"500\"} 1\n";
+        // The metrics are served as soon as the port is bound, before the file has been read.
+        let mut response = scrape();
+        for _ in 0..50 {
+            if response.ends_with(expected) {
+                break;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(100));
+            response = scrape();
+        }
+        agrind.kill().unwrap();
+        agrind.wait().unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.ends_with(expected), "{}", response);
+        fs::remove_dir_all(&dir).unwrap();
+
+        run()
+            .args([
+                "* | count",
+                "--follow",
+                "--file",
+                "test_files/test_json.log",
+            ])
+            .args(["--serve-metrics", "127.0.0.1:0", "-o", "json"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("ServeMetricsOutput"));
+    }
+
+    #[test]
+    fn multiple_files() {
+        run()
+            .args([
+                "--no-alias",
+                "* | json | count by __file__",
+                "-f",
+                "test_files/test_json.log",
+                "-f",
+                "test_files/join_users.log",
+            ])
+            .assert()
+            .success()
+            .stdout(
+                "__file__                        _count\n\
+                 ----------------------------------------------\n\
+                 test_files/test_json.log        6\n\
+                 test_files/join_users.log       3\n",
+            );
+        // Every record has the field, so referring to it isn't worth a warning.
+        run()
+            .args([
+                "--no-alias",
+                "* | where __file__ == \"test_files/join_users.log\" | count",
+                "-f",
+                "test_files/test_json.log",
+                "-f",
+                "test_files/join_users.log",
+            ])
+            .assert()
+            .success()
+            .stdout("_count\n--------------\n3\n")
+            .stderr("");
+        run()
+            .args([
+                "alice",
+                "-f",
+                "test_files/test_json.log",
+                "-f",
+                "test_files/join_users.log",
+            ])
+            .assert()
+            .success()
+            .stdout(
+                "test_files/join_users.log:{\"id\": 1, \"name\": \"alice\", \"team\": \"core\"}\n",
+            );
+        run()
+            .args(["--repl", "-f", "a.log", "-f", "b.log"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("SingleFileOnly"));
+    }
+
+    #[test]
+    fn file_patterns() {
+        run()
+            .args(["--no-alias", "* | limit 1", "-f", "test_files/test_*json.log"])
+            .assert()
+            .success()
+            .stdout("test_files/test_json.log:{\"level\": \"info\", \"message\": \"A thing happened\", \"num_things\": 1102}\n");
+        run()
+            .args([
+                "--no-alias",
+                "* | limit -1",
+                "-f",
+                "test_files/test_*json.log",
+            ])
+            .assert()
+            .success()
+            .stdout("test_files/test_partial_json.log:WARN {\"level\": null}\n");
+        run()
+            .args(["*", "-f", "test_files/*.nope"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("NoMatchingFiles"));
+    }
+
+    #[test]
+    fn compressed_files() {
+        run()
+            .args([
+                "--no-alias",
+                "* | json | count by __file__",
+                "-f",
+                "test_files/test_json.log.gz",
+                "-f",
+                "test_files/test_json.log.zst",
+                "-f",
+                "test_files/test_json.log.bz2",
+                "-f",
+                "test_files/test_json.log",
+            ])
+            .assert()
+            .success()
+            .stdout(
+                "__file__                        _count
+----------------------------------------------
+test_files/test_json.log        6
+test_files/test_json.log.bz2    6
+test_files/test_json.log.gz     6
+test_files/test_json.log.zst    6
+",
+            );
+    }
+
+    #[test]
+    fn explain() {
+        run()
+            .args(["* | apache | count by status", "--explain"])
+            .assert()
+            .success()
+            .stdout(
+                r#"*
+| parse "* - * [*] \"* * *\" * *" as ip, name, timestamp, method, url, protocol, status, contentlength
+| count by status
+
+1. parse "* - * [*] \"* * *\" * *" as ip, name, timestamp, method, url, protocol, status, contentlength
+   from alias `apache`, streaming
+   reads: (line)
+   writes: contentlength, ip, method, name, protocol, status, timestamp, url
+2. count by status
+   blocking, waits for the end of the input
+   reads: status
+   outputs only: _count, status
+"#,
+            );
+        run()
+            .args(["* | json | nosuchfn(x) as y", "--explain"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Unknown function nosuchfn"));
+        run()
+            .args(["* | pasres", "--explain"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Failed to parse query"));
+    }
+
+    #[test]
+    fn check() {
+        run()
+            .args(["* | apache | count by status", "--check"])
+            .write_stdin("this isn't read")
+            .assert()
+            .success()
+            .stdout("");
+        run()
+            .args(["* | pasres", "--check"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Failed to parse query"));
+        run()
+            .args(["* | json | nosuchfn(x) as y", "--check"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Unknown function nosuchfn"));
+        run()
+            .args(["* | json | count | where x > 1", "--check"])
+            .assert()
+            .success()
+            .stderr(predicate::str::contains(
+                "which no earlier operator produces",
+            ));
+    }
+
     #[test]
     fn file_input() {
         run()
@@ -149,17 +605,877 @@ None         1\n",
     }
 
     #[test]
-    fn binary_input() {
+    fn stats_summary() {
+        run()
+            .write_stdin("k=1\nk=2\nother\nk=3\nskip\n")
+            .args(["k | parse 'k=*' as k | where k > 1", "--stats"])
+            .assert()
+            .stdout("[k=2]\n[k=3]\n")
+            .stderr(
+                predicate::str::starts_with(
+                    "lines read: 5, bytes read: 23, matched: 4, dropped by parse: 1, \
+                     dropped by where: 1, errors: 0, rows output: 2, \
+                     lines with invalid utf-8: 0, long lines: 0\n  \
+                     parse \"k=*\" as k: dropped 1, errors 0\n  \
+                     where k > 1: dropped 1, errors 0\n\
+                     elapsed: ",
+                )
+                .and(predicate::str::ends_with(" MiB\n")),
+            );
+    }
+
+    #[test]
+    fn stats_json() {
+        let output = run()
+            .write_stdin("k=1\nk=2\nother\nk=3\nskip\n")
+            .args(["k | parse 'k=*' as k | where k > 1 | count", "--stats=json"])
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stats: serde_json::Value = serde_json::from_slice(&output.stderr).unwrap();
+        assert_eq!(stats["lines_read"], 5);
+        assert_eq!(stats["bytes_read"], 23);
+        assert_eq!(stats["rows_output"], 2);
+        assert_eq!(
+            stats["operators"],
+            serde_json::json!([
+                {"operator": "parse \"k=*\" as k", "parser": true, "dropped": 1, "errors": 0},
+                {"operator": "where k > 1", "parser": false, "dropped": 1, "errors": 0},
+            ])
+        );
+        assert!(stats["elapsed_seconds"].is_f64());
+    }
+
+    #[test]
+    fn head_stops_reading() {
+        let input: String = (0..5000).map(|i| format!("k={}\n", i)).collect();
+        run()
+            .write_stdin(input.clone())
+            .args(["* | head 2 | tail 1", "--stats"])
+            .assert()
+            .stdout("k=1\n")
+            .stderr(predicate::str::starts_with(
+                "lines read: 2, bytes read: 8, matched: 2, dropped by parse: 0, \
+                 dropped by where: 0, errors: 0, rows output: 1, \
+                 lines with invalid utf-8: 0, long lines: 0\n",
+            ));
+        run()
+            .write_stdin(input)
+            .args(["* | head 2 | tail 1", "--threads", "4"])
+            .assert()
+            .success()
+            .stdout("k=1\n");
+    }
+
+    #[test]
+    fn threads() {
+        // Enough lines for several batches, so the rows come back from the workers out of order.
+        let input: String = (0..5000)
+            .map(|i| match i % 1500 {
+                1499 => "skip\n".to_string(),
+                _ => format!("k={}\n", i),
+            })
+            .collect();
+        for query in [
+            "k | parse 'k=*' as k | where k > 2500",
+            "k | parse 'k=*' as k | total(k) | limit -3",
+            "k | parse 'k=*' as k | count by k > 3000",
+        ] {
+            // The time taken and memory used are left out, as they change between runs.
+            let counts = |stderr: Vec<u8>| {
+                String::from_utf8(stderr)
+                    .unwrap()
+                    .lines()
+                    .filter(|line| !line.starts_with("elapsed: "))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            };
+            let single = run()
+                .args([query, "--stats"])
+                .write_stdin(input.clone())
+                .output()
+                .unwrap();
+            let parallel = run()
+                .args([query, "--stats", "--threads", "4"])
+                .write_stdin(input.clone())
+                .output()
+                .unwrap();
+            assert!(parallel.status.success());
+            assert_eq!(parallel.stdout, single.stdout);
+            assert_eq!(counts(parallel.stderr), counts(single.stderr));
+        }
+        run()
+            .args(["* | parse 'k=*' as k", "--strict-parse", "--threads", "4"])
+            .write_stdin(input)
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                r#"ParseFailed { line: 1500, content: "skip" }"#,
+            ));
+    }
+
+    #[test]
+    fn strict_parse_failure_threshold() {
+        let input = "k=1\nk=2\nother\nk=3\nskip\n";
+        let query = "* | parse 'k=*' as k | where k > 1";
+        run()
+            .write_stdin(input)
+            .args([query, "--strict"])
+            .assert()
+            .failure()
+            .stdout("[k=2]\n[k=3]\n")
+            .stderr(predicate::str::contains("  parse \"k=*\" as k: 2\n").and(
+                predicate::str::contains(
+                    r#"TooManyParseFailures { failures: 2, rows: 5, allowed: "0" }"#,
+                ),
+            ));
+        for allowed in ["--strict=2", "--strict=40%"] {
+            run()
+                .write_stdin(input)
+                .args([query, allowed])
+                .assert()
+                .success()
+                .stderr("");
+        }
+        run()
+            .write_stdin(input)
+            .args([query, "--strict=39%"])
+            .assert()
+            .failure();
+    }
+
+    #[test]
+    fn csv_header_is_not_a_parse_failure() {
+        run()
+            .write_stdin("user,status\nalice,200\nbob\n")
+            .args(["* | parse csv headers=true", "--stats"])
+            .assert()
+            .stdout("[status=200]           [user=alice]\n")
+            .stderr(predicate::str::contains("dropped by parse: 1,"));
+        run()
+            .write_stdin("user,status\nalice,200\n")
+            .args(["* | parse csv headers=true", "--strict-parse"])
+            .assert()
+            .success();
+    }
+
+    #[test]
+    fn multiline_parse_failure_names_first_line() {
+        run()
+            .args([
+                "* | parse 'ok *' as rest",
+                "--multiline-start",
+                "^(ok|bad) ",
+                "--strict-parse",
+            ])
+            .write_stdin("ok one\n  more\nbad two\n  more\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                r#"ParseFailed { line: 3, content: "bad two\n  more" }"#,
+            ));
+    }
+
+    #[test]
+    fn strip_ansi() {
+        run()
+            .write_stdin("\x1b[1;31mERROR\x1b[0m status=500\nINFO status=200\n")
+            .args([
+                "--strip-ansi",
+                "ERROR | parse '* status=*' as level, status",
+            ])
+            .assert()
+            .stdout("[level=ERROR]        [status=500]\n");
+    }
+
+    #[test]
+    fn max_line_length() {
         run()
+            .write_stdin("short\n0123456789\nh\u{e9}\u{e9}\u{e9}\u{e9}\n")
+            .args(["*", "--max-line-length", "4", "--stats"])
+            .assert()
+            .stdout("shor\n0123\nh\u{e9}\n")
+            .stderr(predicate::str::contains("long lines: 3"));
+        run()
+            .write_stdin("k=1\nk=0123456789\nk=2\n")
             .args([
                 "* | parse 'k=*' as k",
-                "--file",
-                "test_files/binary_data.bin",
+                "--max-line-length",
+                "4",
+                "--skip-long-lines",
+            ])
+            .assert()
+            .stdout("[k=1]\n[k=2]\n");
+    }
+
+    #[test]
+    fn local_alias_shadows_builtin() {
+        run()
+            .write_stdin("1.2.3.4 - - [x] \"GET / HTTP/1.1\" 200 5\n")
+            .args([
+                "* | apache | fields first",
+                "-a",
+                "test_files/shadowing_aliases",
+            ])
+            .assert()
+            .stdout("[first=1.2.3.4]\n")
+            .stderr(predicate::str::contains(
+                "alias `apache` shadows a built-in alias of the same name",
+            ));
+    }
+
+    #[test]
+    fn parameterized_aliases() {
+        run()
+            .args([
+                "* | level_is(error) | count",
+                "-f",
+                "test_files/test_json.log",
+                "-a",
+                "test_files/param_aliases",
+            ])
+            .assert()
+            .stdout("_count\n--------------\n2\n");
+        run()
+            .args([
+                "* | level_is('info') | slowest(num_things, 1) | fields num_things",
+                "-f",
+                "test_files/test_json.log",
+                "-a",
+                "test_files/param_aliases",
+            ])
+            .assert()
+            .stdout("num_things\n------------------\n1102\n");
+    }
+
+    #[test]
+    fn parameterized_alias_wrong_arguments() {
+        run()
+            .args([
+                "* | slowest(num_things) | count",
+                "-f",
+                "test_files/test_json.log",
+                "-a",
+                "test_files/param_aliases",
+            ])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "the `slowest` alias expects 2 arguments: field, count",
+            ));
+        run()
+            .args([
+                "* | level_is | count",
+                "-f",
+                "test_files/test_json.log",
+                "-a",
+                "test_files/param_aliases",
+            ])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "the `level_is` alias expects 1 argument: level",
+            ));
+    }
+
+    #[test]
+    fn alias_list() {
+        run()
+            .args(["alias", "list", "-a", "test_files/shadowing_aliases"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "apache             built-in (shadowed)\n",
+            ))
+            .stdout(predicate::str::contains(
+                "apache             test_files/shadowing_aliases/apache.toml\n",
+            ));
+        run()
+            .args(["alias", "list", "-a", "test_files/param_aliases"])
+            .assert()
+            .success()
+            .stdout(predicate::str::contains(
+                "slowest(field, count)  test_files/param_aliases/slowest.toml\n",
+            ));
+    }
+
+    #[test]
+    fn alias_show() {
+        run()
+            .args([
+                "alias",
+                "show",
+                "level_is(warn)",
+                "-a",
+                "test_files/param_aliases",
+            ])
+            .assert()
+            .success()
+            .stdout(
+                "source: test_files/param_aliases/level.toml\n\
+                 params: level\n\
+                 template:\n\
+                 json | where level == \"{level}\"\n\
+                 expands to:\n\
+                 | json\n\
+                 | where level == \"warn\"\n",
+            );
+        run()
+            .args(["alias", "show", "nope", "--no-alias"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("UnknownAlias"));
+    }
+
+    #[test]
+    fn alias_check() {
+        run()
+            .args(["alias", "check", "-a", "test_files/param_aliases"])
+            .assert()
+            .success()
+            .stdout(predicate::str::ends_with("aliases are valid\n"));
+        run()
+            .args(["alias", "check", "-a", "test_files/invalid_aliases"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "invalid alias: Failed to parse query",
+            ))
+            .stderr(predicate::str::contains(
+                "alias `errors` has a parameter `count` that isn't used in its template",
+            ))
+            .stderr(predicate::str::contains("InvalidAliases { invalid: 1"));
+    }
+
+    #[test]
+    fn alias_sync() {
+        let dir = std::env::temp_dir().join(format!("agrind-alias-sync-{}", std::process::id()));
+        let alias_dir = dir.join(".agrind-aliases");
+        fs::create_dir_all(&alias_dir).unwrap();
+        let tarball = fs::canonicalize("test_files/alias_pack.tar.gz").unwrap();
+        fs::write(
+            alias_dir.join("packs.toml"),
+            format!(
+                "[[pack]]\nname = \"shared\"\nurl = \"file://{}\"\n",
+                tarball.display()
+            ),
+        )
+        .unwrap();
+        let log = fs::canonicalize("test_files/test_json.log").unwrap();
+        let query = || {
+            let mut cmd = run();
+            cmd.current_dir(&dir)
+                .args(["* | level_of(error) | count", "--file"])
+                .arg(&log);
+            cmd
+        };
+
+        query().assert().failure().stderr(predicate::str::contains(
+            "alias pack `shared` hasn't been fetched, run `agrind alias sync`",
+        ));
+        run()
+            .current_dir(&dir)
+            .args(["alias", "sync"])
+            .assert()
+            .success()
+            .stdout(predicate::str::starts_with("fetched `shared` from file://"));
+        // Only the `.toml` files in the pack are loaded, so its README isn't an invalid alias.
+        query()
+            .assert()
+            .success()
+            .stdout("_count\n--------------\n2\n")
+            .stderr("");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prefer_builtin_alias() {
+        run()
+            .write_stdin("1.2.3.4 - - [x] \"GET / HTTP/1.1\" 200 5\n")
+            .args([
+                "* | apache | fields status",
+                "-a",
+                "test_files/shadowing_aliases",
+                "--prefer-builtin",
+            ])
+            .assert()
+            .stdout("[status=200]\n")
+            .stderr(predicate::str::contains(
+                "alias `apache` is shadowed by a built-in alias of the same name",
+            ));
+    }
+
+    #[test]
+    fn quiet_suppresses_warnings() {
+        run()
+            .write_stdin("1.2.3.4 - - [x] \"GET / HTTP/1.1\" 200 5\n")
+            .args([
+                "* | apache | fields first",
+                "-a",
+                "test_files/shadowing_aliases",
+                "--format",
+                "{first}",
+                "--quiet",
+            ])
+            .assert()
+            .stdout("1.2.3.4\n")
+            .stderr("");
+        run()
+            .args(["* | pasres", "-Q"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("Failed to parse query"));
+    }
+
+    #[test]
+    fn query_from_stdin() {
+        run()
+            .write_stdin("* | json\n| count by level\n")
+            .args(["--query-stdin", "--file", "test_files/test_json.log"])
+            .assert()
+            .stdout(
+                "level        _count
+---------------------------
+info         3
+error        2
+None         1\n",
+            );
+        run()
+            .write_stdin("* | count")
+            .args(["--query-stdin"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("QueryStdinWithoutFile"));
+        run()
+            .args([
+                "* | count",
+                "--query-stdin",
+                "--file",
+                "test_files/test_json.log",
+            ])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("cannot be used with"));
+    }
+
+    #[test]
+    fn query_from_file() {
+        run()
+            .args([
+                "--query-file",
+                "test_files/queries/levels.agrind",
+                "--file",
+                "test_files/test_json.log",
+            ])
+            .assert()
+            .stdout(
+                "level        _count
+---------------------------
+info         3
+error        2
+None         1\n",
+            );
+        run()
+            .args(["--query-file", "test_files/queries/missing.agrind"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("UnreadableQueryFile"));
+        run()
+            .args([
+                "* | count",
+                "--query-file",
+                "test_files/queries/levels.agrind",
+            ])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("cannot be used with"));
+    }
+
+    #[test]
+    fn query_after_double_dash() {
+        run()
+            .write_stdin("-x here\nother\n")
+            .args(["--", "-x"])
+            .assert()
+            .stdout("-x here\n");
+        run()
+            .write_stdin("-x here\nother\n")
+            .args(["--", "-x", "|", "count"])
+            .assert()
+            .stdout("_count\n--------------\n1\n");
+        run()
+            .args(["* | count", "--", "-x"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("cannot be used with"));
+    }
+
+    #[test]
+    fn binary_input() {
+        run()
+            .args([
+                "* | parse 'k=*' as k",
+                "--file",
+                "test_files/binary_data.bin",
             ])
             .assert()
             .stdout("[k=v2]\n[k=v]\n");
     }
 
+    #[test]
+    fn invalid_utf8_input() {
+        run()
+            .args([
+                "* | parse 'k=*' as k",
+                "--file",
+                "test_files/invalid_utf8.log",
+                "--stats",
+            ])
+            .assert()
+            .stdout("[k=1]\n[k=\u{FFFD}\u{FFFD}2]\n[k=3]\n")
+            .stderr(predicate::str::contains("lines with invalid utf-8: 1"));
+        run()
+            .args([
+                "* | parse 'k=*' as k",
+                "--file",
+                "test_files/invalid_utf8.log",
+                "--strict-utf8",
+            ])
+            .assert()
+            .failure()
+            .stdout("[k=1]\n")
+            .stderr(predicate::str::contains("InvalidUtf8 { line: 2"));
+    }
+
+    #[test]
+    fn sqlite_output() {
+        let dir = std::env::temp_dir().join(format!("agrind-sqlite-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let db = dir.join("results.db");
+        let sqlite = |sql: &str| {
+            use rusqlite::types::Value;
+            let connection = rusqlite::Connection::open(&db).unwrap();
+            let mut statement = connection.prepare(sql).unwrap();
+            let columns = statement.column_count();
+            let mut rows = statement.query([]).unwrap();
+            let mut out = String::new();
+            while let Some(row) = rows.next().unwrap() {
+                let values = (0..columns).map(|i| match row.get::<_, Value>(i).unwrap() {
+                    Value::Null => String::new(),
+                    Value::Integer(i) => i.to_string(),
+                    Value::Real(f) => f.to_string(),
+                    Value::Text(s) => s,
+                    Value::Blob(b) => format!("{:?}", b),
+                });
+                out.push_str(&values.collect::<Vec<_>>().join("|"));
+                out.push('\n');
+            }
+            out
+        };
+        run()
+            .args(["* | json", "--file", "test_files/test_json.log", "-o"])
+            .arg(format!("sqlite={}:logs", db.display()))
+            .assert()
+            .success()
+            .stdout("");
+        run()
+            .args([
+                "* | json | count by level",
+                "--file",
+                "test_files/test_json.log",
+            ])
+            .arg("-o")
+            .arg(format!("sqlite={}", db.display()))
+            .assert()
+            .success()
+            .stdout("");
+        assert_eq!(
+            sqlite("SELECT level, sum(num_things), max(event_duration) FROM logs GROUP BY level"),
+            "||\nerror||\ninfo|1114|1002.5\n"
+        );
+        assert_eq!(
+            sqlite("SELECT typeof(num_things), typeof(event_duration) FROM logs LIMIT 1"),
+            "integer|null\n"
+        );
+        assert_eq!(
+            sqlite("SELECT * FROM results ORDER BY _count DESC"),
+            "info|3\nerror|2\n|1\n"
+        );
+        // Writing the table again replaces it.
+        run()
+            .args(["* | json | count", "--file", "test_files/test_json.log"])
+            .arg("-o")
+            .arg(format!("sqlite={}", db.display()))
+            .assert()
+            .success();
+        assert_eq!(sqlite("SELECT * FROM results"), "6\n");
+        fs::remove_dir_all(&dir).unwrap();
+
+        run()
+            .args(["* | json", "-o", "sqlite="])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("missing the path of the database"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "arrow"))]
+    fn arrow_disabled() {
+        run()
+            .args(["* | count", "--file", "test_files/requests.arrow"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--features arrow"));
+    }
+
+    #[test]
+    #[cfg(feature = "parquet")]
+    fn parquet_output() {
+        let dir = std::env::temp_dir().join(format!("agrind-parquet-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("results.parquet");
+        run()
+            .args([
+                "* | json | count by level",
+                "--file",
+                "test_files/test_json.log",
+            ])
+            .arg("-o")
+            .arg(format!("parquet={}", path.display()))
+            .assert()
+            .success()
+            .stdout("");
+        let file = fs::read(&path).unwrap();
+        assert_eq!(&file[..4], b"PAR1");
+        assert_eq!(&file[file.len() - 4..], b"PAR1");
+        assert!(file.windows(6).any(|w| w == b"_count"));
+        fs::remove_dir_all(&dir).unwrap();
+
+        run()
+            .args(["* | json", "-o", "parquet="])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("InvalidParquetOutput"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "parquet"))]
+    fn parquet_disabled() {
+        run()
+            .args(["* | json", "-o", "parquet=results.parquet"])
+            .write_stdin("{\"a\": 1}\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--features parquet"));
+        run()
+            .args(["* | count", "--file", "test_files/requests.parquet"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("--features parquet"));
+    }
+
+    #[test]
+    fn split_output() {
+        let dir = std::env::temp_dir().join(format!("agrind-split-{}", std::process::id()));
+        run()
+            .args(["* | json"])
+            .arg("-o")
+            .arg(format!("split={},by=service", dir.display()))
+            .write_stdin(
+                "{\"service\": \"auth\", \"n\": 1}\n\
+                 {\"service\": \"api/v2\", \"n\": 2}\n\
+                 {\"n\": 3}\n\
+                 {\"service\": \"auth\", \"n\": 4}\n",
+            )
+            .assert()
+            .success()
+            .stdout("");
+        let read = |name: &str| fs::read_to_string(dir.join(name)).unwrap();
+        assert_eq!(
+            read("service=auth.jsonl"),
+            "{\"n\":1,\"service\":\"auth\"}\n{\"n\":4,\"service\":\"auth\"}\n"
+        );
+        assert_eq!(
+            read("service=api_v2.jsonl"),
+            "{\"n\":2,\"service\":\"api/v2\"}\n"
+        );
+        assert_eq!(read("_other.jsonl"), "{\"n\":3}\n");
+        fs::remove_dir_all(&dir).unwrap();
+
+        run()
+            .args(["* | json", "-o", "split=logs"])
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("InvalidSplitOutput"));
+    }
+
+    #[test]
+    #[cfg(feature = "arrow")]
+    fn arrow_input() {
+        for file in ["test_files/requests.arrow", "test_files/requests.arrows"] {
+            run()
+                .args([
+                    "* | where status >= 400 | sum(latency) by level",
+                    "--file",
+                    file,
+                ])
+                .assert()
+                .success()
+                .stdout("level        _sum\n-------------------------\nerror        3.50\nwarn         0.75\n")
+                .stderr("");
+        }
+        run()
+            .args([
+                "* | fields user, tags",
+                "-o",
+                "json",
+                "--file",
+                "test_files/requests.arrow",
+            ])
+            .assert()
+            .success()
+            .stdout(predicate::str::starts_with(
+                r#"{"tags":["a"],"user":{"id":1,"name":"ana"}}"#,
+            ));
+    }
+
+    #[test]
+    #[cfg(feature = "parquet")]
+    fn parquet_input() {
+        run()
+            .args([
+                "* | where status >= 400 | sum(latency) by level",
+                "--file",
+                "test_files/requests.parquet",
+            ])
+            .assert()
+            .success()
+            .stdout("level        _sum\n-------------------------\nerror        3.50\nwarn         0.75\n")
+            .stderr("");
+        // Rows are searched as JSON objects.
+        run()
+            .args([
+                r#""level":"error" | count"#,
+                "--file",
+                "test_files/requests.parquet",
+            ])
+            .assert()
+            .success()
+            .stdout("_count\n--------------\n2\n");
+
+        // What `-o parquet` writes can be read back.
+        let dir = std::env::temp_dir().join(format!("agrind-columnar-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("levels.parquet");
+        run()
+            .args([
+                "* | json | count by level",
+                "--file",
+                "test_files/test_json.log",
+            ])
+            .arg("-o")
+            .arg(format!("parquet={}", path.display()))
+            .assert()
+            .success();
+        run()
+            .args(["* | sort by level", "-o", "logfmt", "--file"])
+            .arg(&path)
+            .assert()
+            .success()
+            .stdout("_count=1 level=None\n_count=2 level=error\n_count=3 level=info\n");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn prometheus_without_aggregate() {
+        run()
+            .args(["-o", "prometheus", "* | json"])
+            .write_stdin("{\"a\": 1}\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                "can be written as Prometheus metrics",
+            ));
+    }
+
+    #[test]
+    fn chart_output() {
+        run()
+            .args(["-o", "chart", "* | json | count by level"])
+            .write_stdin("{\"level\": \"info\"}\n{\"level\": \"info\"}\n{\"level\": \"error\"}\n")
+            .assert()
+            .success()
+            .stdout(format!(
+                "level  _count\ninfo        2  {}\nerror       1  {}▌\n",
+                "█".repeat(65),
+                "█".repeat(32)
+            ));
+        run()
+            .args(["-o", "chart", "* | json"])
+            .write_stdin("{\"a\": 1}\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains("can be drawn as a chart"));
+    }
+
+    #[test]
+    fn sparkline_output() {
+        run()
+            .args([
+                "-o",
+                "sparkline",
+                "* | json | timeslice(parseDate(ts)) 1m | count by _timeslice, status",
+            ])
+            .write_stdin(
+                "{\"ts\": \"2020-06-01T10:00:00Z\", \"status\": 200}\n\
+                 {\"ts\": \"2020-06-01T10:00:30Z\", \"status\": 200}\n\
+                 {\"ts\": \"2020-06-01T10:01:10Z\", \"status\": 500}\n\
+                 {\"ts\": \"2020-06-01T10:03:10Z\", \"status\": 200}\n",
+            )
+            .assert()
+            .success()
+            .stdout(
+                "status  min  max  _count\n\
+                 200       1    2  █  ▁\n\
+                 500       1    1   ▁\n\
+                 2020-06-01 10:00:00 UTC to 2020-06-01 10:03:00 UTC\n",
+            );
+        run()
+            .args(["-o", "sparkline", "* | json | count by status"])
+            .write_stdin("{\"status\": 200}\n")
+            .assert()
+            .success()
+            .stderr(predicate::str::contains("split into time buckets"));
+    }
+
+    #[test]
+    fn strict_parse() {
+        run()
+            .args(["* | parse 'k=*' as k", "--strict-parse"])
+            .write_stdin("k=1\nk=2\nnope\nk=4\n")
+            .assert()
+            .failure()
+            .stdout("[k=1]\n[k=2]\n")
+            .stderr(predicate::str::contains(
+                r#"ParseFailed { line: 3, content: "nope" }"#,
+            ));
+        run()
+            .args(["* | json", "--strict-parse"])
+            .write_stdin("{\"a\": 1}\nnot json\n")
+            .assert()
+            .failure()
+            .stderr(predicate::str::contains(
+                r#"ParseFailed { line: 2, content: "not json" }"#,
+            ))
+            .stderr(predicate::str::contains("Expected JSON").not());
+        run()
+            .args(["* | parse 'k=*' as k", "--strict-parse"])
+            .write_stdin("k=1\nk=2\n")
+            .assert()
+            .success()
+            .stdout("[k=1]\n[k=2]\n");
+    }
+
     #[test]
     fn filter_wildcard() {
         run()